@@ -0,0 +1,239 @@
+//! Permission changes over a filtered selection (`smv attr ... PERM:+x`)
+//!
+//! `PERM:` takes what chmod takes: an octal mode (`PERM:755`) or symbolic
+//! clauses (`PERM:+x`, `PERM:g-w`, `PERM:u+rw,go-r`, `PERM:a=r`). A clause
+//! without a class applies to user, group and others alike, and `X` adds
+//! execute only to directories and files someone may already execute.
+//! Symlinks are left alone, since changing one changes what it points to.
+//!
+//! Directories smv creates on the way to a destination get `--dir-mode`, the
+//! same kind of value, applied to what the umask leaves of `rwxrwxrwx`; without
+//! it they keep that umask default.
+
+use once_cell::sync::OnceCell;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+static DIR_MODE: OnceCell<PermChange> = OnceCell::new();
+
+/// The umask of the process, read once
+#[cfg(unix)]
+static UMASK: once_cell::sync::Lazy<u32> = once_cell::sync::Lazy::new(|| {
+    // umask can only be read by setting it, so set it straight back
+    let mask = unsafe { libc::umask(0o022) };
+    unsafe { libc::umask(mask) };
+    mask as u32 & 0o777
+});
+
+/// Permission bits of each class, in `ugo` order
+const CLASSES: [(char, u32); 3] = [('u', 0o700), ('g', 0o070), ('o', 0o007)];
+
+/// A `PERM:` value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PermChange {
+    /// Replace the mode (`PERM:644`)
+    Absolute(u32),
+    /// Adjust it (`PERM:go-w,+X`)
+    Symbolic(Vec<Clause>),
+}
+
+/// One comma-separated part of a symbolic change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clause {
+    /// Bits of the classes the clause applies to
+    who: u32,
+    op: char,
+    /// `rwx` bits for every class, masked by `who` when applied
+    perms: u32,
+    /// `X`: execute only for directories and files already executable
+    conditional_execute: bool,
+}
+
+impl PermChange {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || {
+            format!("Invalid PERM:{text} (expected an octal mode like 755 or clauses like g-w,+x)")
+        };
+        if !text.is_empty() && text.chars().all(|c| c.is_ascii_digit()) {
+            let mode = u32::from_str_radix(text, 8).map_err(|_| invalid())?;
+            return if mode <= 0o7777 {
+                Ok(PermChange::Absolute(mode))
+            } else {
+                Err(invalid())
+            };
+        }
+
+        let mut clauses = Vec::new();
+        for part in text.split(',') {
+            let Some(at) = part.find(['+', '-', '=']) else {
+                return Err(invalid());
+            };
+            let (classes, rest) = part.split_at(at);
+            let mut who = 0;
+            for class in classes.chars() {
+                who |= match class {
+                    'a' => 0o777,
+                    _ => CLASSES
+                        .iter()
+                        .find(|(name, _)| *name == class)
+                        .map(|(_, bits)| *bits)
+                        .ok_or_else(invalid)?,
+                };
+            }
+            let mut chars = rest.chars();
+            let op = chars.next().ok_or_else(invalid)?;
+            let (mut perms, mut conditional_execute) = (0, false);
+            for perm in chars {
+                match perm {
+                    'r' => perms |= 0o444,
+                    'w' => perms |= 0o222,
+                    'x' => perms |= 0o111,
+                    'X' => conditional_execute = true,
+                    _ => return Err(invalid()),
+                }
+            }
+            clauses.push(Clause {
+                who: if who == 0 { 0o777 } else { who },
+                op,
+                perms,
+                conditional_execute,
+            });
+        }
+        Ok(PermChange::Symbolic(clauses))
+    }
+
+    /// `mode` after the change, for a directory when `is_dir`
+    pub fn apply(&self, mode: u32, is_dir: bool) -> u32 {
+        let clauses = match self {
+            PermChange::Absolute(new) => return (mode & !0o7777) | new,
+            PermChange::Symbolic(clauses) => clauses,
+        };
+        let mut mode = mode;
+        for clause in clauses {
+            let mut perms = clause.perms;
+            if clause.conditional_execute && (is_dir || mode & 0o111 != 0) {
+                perms |= 0o111;
+            }
+            let bits = perms & clause.who;
+            mode = match clause.op {
+                '+' => mode | bits,
+                '-' => mode & !bits,
+                _ => (mode & !clause.who) | bits,
+            };
+        }
+        mode
+    }
+}
+
+/// `rwxr-xr-x (755)`
+pub fn format_mode(mode: u32) -> String {
+    let mut text = String::with_capacity(16);
+    for (_, bits) in CLASSES {
+        for (flag, bit) in [('r', 0o444), ('w', 0o222), ('x', 0o111)] {
+            text.push(if mode & bits & bit != 0 { flag } else { '-' });
+        }
+    }
+    format!("{} ({:03o})", text, mode & 0o7777)
+}
+
+/// The permission bits of `path`, not following a symlink
+#[cfg(unix)]
+pub fn mode_of(path: &Path) -> io::Result<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(path.symlink_metadata()?.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+pub fn mode_of(_path: &Path) -> io::Result<u32> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "permission modes exist only on Unix",
+    ))
+}
+
+#[cfg(unix)]
+pub fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+pub fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "permission modes exist only on Unix",
+    ))
+}
+
+/// Permission bits the umask takes away from new files and directories
+#[cfg(unix)]
+pub fn umask() -> u32 {
+    *UMASK
+}
+
+#[cfg(not(unix))]
+pub fn umask() -> u32 {
+    0
+}
+
+/// Give the directories created from here on `change` (`--dir-mode`)
+pub fn set_dir_mode(text: &str) -> Result<(), String> {
+    let change = PermChange::parse(text).map_err(|_| {
+        format!("Invalid --dir-mode {text} (expected an octal mode like 2775 or clauses like g+w)")
+    })?;
+    let _ = DIR_MODE.set(change);
+    Ok(())
+}
+
+/// The mode `--dir-mode` gives new directories, if it was set
+pub fn dir_mode() -> Option<u32> {
+    DIR_MODE
+        .get()
+        .map(|change| change.apply(0o777 & !umask(), true))
+}
+
+/// `fs::create_dir_all`, giving the directories it makes `--dir-mode`
+///
+/// Without `--dir-mode` they get what the umask leaves, as before.
+pub fn create_dir_all(path: &Path) -> io::Result<()> {
+    let Some(mode) = dir_mode().filter(|_| cfg!(unix)) else {
+        return fs::create_dir_all(path);
+    };
+    let missing: Vec<&Path> = path
+        .ancestors()
+        .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+        .collect();
+    fs::create_dir_all(path)?;
+    // Outermost first; chmod rather than the umask decides, so group bits stay
+    for dir in missing.iter().rev() {
+        set_mode(dir, mode)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perm_changes() {
+        let change = |text: &str, mode: u32| PermChange::parse(text).unwrap().apply(mode, false);
+        assert_eq!(change("+x", 0o644), 0o755);
+        assert_eq!(change("g-w", 0o664), 0o644);
+        assert_eq!(change("u+rw,go-r", 0o444), 0o600);
+        assert_eq!(change("a=r", 0o755), 0o444);
+        assert_eq!(change("go=", 0o755), 0o700);
+        assert_eq!(change("640", 0o4755), 0o640);
+        // X only for directories and what is executable already
+        let x = PermChange::parse("+X").unwrap();
+        assert_eq!(x.apply(0o644, false), 0o644);
+        assert_eq!(x.apply(0o744, false), 0o755);
+        assert_eq!(x.apply(0o644, true), 0o755);
+
+        for bad in ["", "+q", "z+x", "999", "x", "17777"] {
+            assert!(PermChange::parse(bad).is_err(), "{bad}");
+        }
+        assert_eq!(format_mode(0o754), "rwxr-xr-- (754)");
+    }
+}
@@ -0,0 +1,169 @@
+//! Brace and sequence expansion for batch moves
+//!
+//! `report_{1..12}.pdf` expands to `report_1.pdf` … `report_12.pdf`, `{01..12}` keeps
+//! the zero padding, `{a..e}` counts letters, `{1..9..2}` steps, and `{jpg,png}` lists
+//! alternatives. Groups combine like in the shell. An empty `{}` is not expanded: in
+//! `mv` destinations it stands for each source's stem.
+
+use std::path::Path;
+
+/// Whether `pattern` contains a group [`expand`] would expand
+pub fn has_braces(pattern: &str) -> bool {
+    let expanded = expand(pattern);
+    expanded.len() != 1 || expanded[0] != pattern
+}
+
+/// Every string `pattern` expands to, in order; a pattern without groups is returned as is
+pub fn expand(pattern: &str) -> Vec<String> {
+    for (open, _) in pattern.match_indices('{') {
+        let Some(close) = matching_close(pattern, open) else {
+            continue;
+        };
+        let Some(alternatives) = alternatives(&pattern[open + 1..close]) else {
+            continue;
+        };
+        let (prefix, suffix) = (&pattern[..open], &pattern[close + 1..]);
+        return alternatives
+            .iter()
+            .flat_map(|alternative| expand(&format!("{prefix}{alternative}{suffix}")))
+            .collect();
+    }
+    vec![pattern.to_string()]
+}
+
+/// Replace each `{}` in `pattern` with the file stem of `source`
+pub fn substitute_stem(pattern: &str, source: &Path) -> String {
+    let stem = source
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    pattern.replace("{}", &stem)
+}
+
+fn matching_close(pattern: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (index, ch) in pattern[open..].char_indices() {
+        match ch {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + index);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The alternatives of a group body: a sequence, or a comma list at the top level
+fn alternatives(body: &str) -> Option<Vec<String>> {
+    if let Some(sequence) = sequence(body) {
+        return Some(sequence);
+    }
+
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (index, ch) in body.char_indices() {
+        match ch {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(body[start..index].to_string());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    parts.push(body[start..].to_string());
+    Some(parts)
+}
+
+/// `1..12`, `01..12`, `12..1`, `1..9..2` or `a..e`
+fn sequence(body: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = body.split("..").collect();
+    let (start, end, step) = match parts[..] {
+        [start, end] => (start, end, 1),
+        [start, end, step] => (start, end, step.parse::<i64>().ok()?.unsigned_abs().max(1)),
+        _ => return None,
+    };
+
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let padded = |text: &str| {
+            let digits = text.trim_start_matches('-');
+            digits.len() > 1 && digits.starts_with('0')
+        };
+        let width = if padded(start) || padded(end) {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+        return Some(
+            stepped(first, last, step)
+                .map(|value| format!("{value:0width$}"))
+                .collect(),
+        );
+    }
+
+    let (mut first, mut last) = (start.chars(), end.chars());
+    match (first.next(), first.next(), last.next(), last.next()) {
+        (Some(first), None, Some(last), None)
+            if first.is_ascii_alphabetic() && last.is_ascii_alphabetic() =>
+        {
+            Some(
+                stepped(first as i64, last as i64, step)
+                    .map(|value| (value as u8 as char).to_string())
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+fn stepped(first: i64, last: i64, step: u64) -> impl Iterator<Item = i64> {
+    let count = first.abs_diff(last) / step + 1;
+    let step = if first <= last {
+        step as i64
+    } else {
+        -(step as i64)
+    };
+    (0..count as i64).map(move |index| first + index * step)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(expand("a_{1..3}.pdf"), ["a_1.pdf", "a_2.pdf", "a_3.pdf"]);
+        assert_eq!(expand("r/{08..10}/"), ["r/08/", "r/09/", "r/10/"]);
+        assert_eq!(expand("{3..1}"), ["3", "2", "1"]);
+        assert_eq!(expand("{1..7..3}"), ["1", "4", "7"]);
+        assert_eq!(expand("{a..c}"), ["a", "b", "c"]);
+        assert_eq!(expand("{x,y}{1..2}"), ["x1", "x2", "y1", "y2"]);
+        assert_eq!(
+            expand("img.{jpg,{png,gif}}"),
+            ["img.jpg", "img.png", "img.gif"]
+        );
+
+        // Groups that are not lists or sequences stay literal
+        assert_eq!(expand("logs/{}.log"), ["logs/{}.log"]);
+        assert_eq!(expand("{single}"), ["{single}"]);
+        assert!(!has_braces("logs/{}.log"));
+        assert!(has_braces("{1..2}"));
+    }
+
+    #[test]
+    fn test_substitute_stem() {
+        assert_eq!(
+            substitute_stem("logs/{}.archived.log", Path::new("var/app.log")),
+            "logs/app.archived.log"
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! Several steps in one command, joined by `THEN`
+//!
+//! `smv clean . EXT:jpg THEN sort date THEN mv TO-DIR:~/Photos -r` selects files
+//! once, with the path, filters and flags of the first stage, and passes them
+//! through every stage in turn. No file moves between stages: each works out
+//! where the files would be after the one before, and only the end result is
+//! carried out, as one batch that `-p` previews and `smv -u` undoes as a whole.
+//!
+//! A stage is a transform (`snake`, `clean`, `CHANGE a INTO b`, ...), `sort
+//! date`, `sort alpha`, `bucket RULE`, or `mv` with a destination directory.
+//! Stages keep each file's place below the search root, so `mv` carries the
+//! folders an earlier `sort` made along.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::config::expand_home;
+use crate::sort::{self, BucketRule, DateLayout};
+use crate::transformers::{self, TransformType};
+
+/// The word between stages
+pub const KEYWORD: &str = "THEN";
+
+/// What one stage does with each file
+#[derive(Debug, Clone)]
+pub enum Stage {
+    Rename(TransformType),
+    /// `sort date` and `bucket RULE`: into a folder below the root
+    Bucket(BucketRule),
+    /// `sort alpha`: into `a/ab/` shards below the root
+    Shard(usize),
+    /// `mv DEST`: into a directory, keeping paths below the root
+    Move(PathBuf),
+}
+
+impl Stage {
+    /// Read the stage the words start with; returns it and how many words it took
+    ///
+    /// `layout` and `levels` are `--layout` and `--levels`, for the sort stages.
+    pub fn parse(
+        words: &[String],
+        layout: Option<&str>,
+        levels: usize,
+    ) -> Result<Option<(Self, usize)>, Box<dyn Error>> {
+        let word = |index: usize| words.get(index).map(String::as_str);
+        let Some(first) = word(0) else {
+            return Ok(None);
+        };
+        if let Some(transform_type) = TransformType::from_str(first) {
+            return Ok(Some((Stage::Rename(transform_type), 1)));
+        }
+        match first.to_lowercase().as_str() {
+            kind @ ("change" | "regex") => {
+                let (Some(from), Some("INTO"), Some(to)) = (word(1), word(2), word(3)) else {
+                    return Err(format!("{first} needs: {first} \"old\" INTO \"new\"").into());
+                };
+                let transform_type = if kind == "change" {
+                    TransformType::replace(from, to)
+                } else {
+                    regex::Regex::new(from)
+                        .map_err(|e| format!("Invalid regex pattern '{from}': {e}"))?;
+                    TransformType::replace_regex(from, to)
+                };
+                Ok(Some((Stage::Rename(transform_type), 4)))
+            }
+            "sort" => match word(1) {
+                Some("date") => {
+                    let layout = DateLayout::parse(layout.unwrap_or(sort::DEFAULT_DATE_LAYOUT))?;
+                    Ok(Some((Stage::Bucket(BucketRule::Modified(layout)), 2)))
+                }
+                Some("alpha") => Ok(Some((Stage::Shard(levels.max(1)), 2))),
+                _ => Err("A sort stage needs a method: sort date or sort alpha".into()),
+            },
+            "bucket" => {
+                let rule = word(1).ok_or("bucket needs a rule, e.g. bucket LETTER")?;
+                let rule = BucketRule::parse_with_layout(rule, layout)?;
+                Ok(Some((Stage::Bucket(rule), 2)))
+            }
+            "mv" => {
+                let destination = match word(1) {
+                    Some(dest) => dest
+                        .strip_prefix("TO-DIR:")
+                        .or_else(|| dest.strip_prefix("INTO-DIR:"))
+                        .unwrap_or(dest),
+                    None => "",
+                };
+                if destination.is_empty() || destination.starts_with('-') {
+                    return Err("An mv stage needs a directory: mv TO-DIR:dir".into());
+                }
+                Ok(Some((Stage::Move(expand_home(destination)), 2)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Stage::Rename(transform_type) => transform_type.as_str(),
+            Stage::Bucket(BucketRule::Modified(_)) => String::from("sort date"),
+            Stage::Bucket(_) => String::from("bucket"),
+            Stage::Shard(levels) => format!("sort alpha ({levels} levels)"),
+            Stage::Move(dir) => format!("mv into {}", dir.display()),
+        }
+    }
+
+    /// Where the file first found at `source`, by now at `current`, ends up
+    ///
+    /// The result is `current` when the stage leaves the file be.
+    fn place(&self, root: &Path, source: &Path, current: &Path) -> PathBuf {
+        let Some(file_name) = current.file_name() else {
+            return current.to_path_buf();
+        };
+        let name = file_name.to_string_lossy();
+        let below_root = current.strip_prefix(root).unwrap_or(Path::new(file_name));
+        match self {
+            Stage::Rename(transform_type) => {
+                // Names that are not UTF-8 are renamed only when transliterated
+                let Some(text) = transformers::name_text(file_name) else {
+                    return current.to_path_buf();
+                };
+                let renamed = transformers::transform(&text, transform_type);
+                if transformers::check_name(&text, &renamed).is_some() {
+                    return current.to_path_buf();
+                }
+                current.with_file_name(renamed)
+            }
+            // Dates come from the file itself, which has not moved yet
+            Stage::Bucket(rule @ BucketRule::Modified(_)) => {
+                rule.bucket_for_file(source).map_or_else(
+                    || current.to_path_buf(),
+                    |dir| root.join(dir).join(file_name),
+                )
+            }
+            Stage::Bucket(rule) => rule.bucket_for(&name).map_or_else(
+                || current.to_path_buf(),
+                |dir| root.join(dir).join(file_name),
+            ),
+            Stage::Shard(levels) => root.join(sort::alpha_shard(&name, *levels)).join(file_name),
+            Stage::Move(dir) => dir.join(below_root),
+        }
+    }
+}
+
+/// Split a command line at every `THEN`
+pub fn split(words: &[String]) -> Vec<&[String]> {
+    words.split(|word| word == KEYWORD).collect()
+}
+
+/// A selected file on its way through the stages
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub source: PathBuf,
+    pub current: PathBuf,
+}
+
+/// Pass `sources` through `stages`, returning where each ends up and how many
+/// files every stage changed
+pub fn run(stages: &[Stage], root: &Path, sources: Vec<PathBuf>) -> (Vec<Entry>, Vec<usize>) {
+    let mut entries: Vec<Entry> = sources
+        .into_iter()
+        .map(|source| Entry {
+            current: source.clone(),
+            source,
+        })
+        .collect();
+    let mut changed = Vec::with_capacity(stages.len());
+    for stage in stages {
+        let mut count = 0;
+        for entry in &mut entries {
+            let next = stage.place(root, &entry.source, &entry.current);
+            if next != entry.current {
+                entry.current = next;
+                count += 1;
+            }
+        }
+        changed.push(count);
+    }
+    (entries, changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_stages_compose() {
+        let line = words("snake . EXT:jpg THEN bucket LETTER THEN mv TO-DIR:/photos");
+        let parts = split(&line);
+        assert_eq!(parts.len(), 3);
+
+        let mut stages = Vec::new();
+        for part in &parts {
+            let (stage, taken) = Stage::parse(part, None, 2).unwrap().unwrap();
+            assert!(taken <= part.len());
+            stages.push(stage);
+        }
+        let root = Path::new("/in");
+        let (entries, changed) = run(
+            &stages,
+            root,
+            vec![
+                PathBuf::from("/in/My Trip.jpg"),
+                PathBuf::from("/in/sub/b.jpg"),
+            ],
+        );
+        assert_eq!(entries[0].current, PathBuf::from("/photos/m/my_trip.jpg"));
+        assert_eq!(entries[1].current, PathBuf::from("/photos/b/b.jpg"));
+        assert_eq!(changed, [1, 2, 2]);
+
+        assert!(
+            Stage::parse(&words("frobnicate"), None, 2)
+                .unwrap()
+                .is_none()
+        );
+        assert!(Stage::parse(&words("mv"), None, 2).is_err());
+        assert!(Stage::parse(&words("sort size"), None, 2).is_err());
+    }
+}
@@ -0,0 +1,136 @@
+//! Copying the paths an operation produced to the system clipboard
+//!
+//! `--copy-names` copies the new paths of the files a run renamed, one per
+//! line, so a renamed file can be pasted straight into another program.
+//! Transforms report their renames as they finish; for other commands the
+//! batch the run recorded for undo says where the files went. The REPL's
+//! `copy` command and the TUI's `Y` key copy the paths of the last batch.
+//! The text goes through whichever clipboard tool is installed: `pbcopy` on
+//! macOS, `clip` on Windows, and `wl-copy`, `xclip` or `xsel` elsewhere.
+
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Local};
+
+use crate::history::HistoryManager;
+
+/// New paths of the renames this run made, in order
+static RECORDED: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+#[cfg(target_os = "macos")]
+const TOOLS: &[&[&str]] = &[&["pbcopy"]];
+#[cfg(windows)]
+const TOOLS: &[&[&str]] = &[&["clip"]];
+#[cfg(not(any(target_os = "macos", windows)))]
+const TOOLS: &[&[&str]] = &[
+    &["wl-copy"],
+    &["xclip", "-selection", "clipboard"],
+    &["xsel", "--clipboard", "--input"],
+];
+
+/// Put `text` on the clipboard with the first tool that runs
+///
+/// Returns the name of the tool that took it.
+pub fn copy(text: &str) -> io::Result<&'static str> {
+    for tool in TOOLS {
+        // wl-copy only works inside a Wayland session
+        if tool[0] == "wl-copy" && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+            continue;
+        }
+        let child = Command::new(tool[0])
+            .args(&tool[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        if child.wait()?.success() {
+            return Ok(tool[0]);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "no clipboard tool found (install wl-clipboard, xclip or xsel)",
+    ))
+}
+
+/// Copy `paths`, one per line
+pub fn copy_paths(paths: &[PathBuf]) -> io::Result<&'static str> {
+    let lines: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+    copy(&lines.join("\n"))
+}
+
+/// Remember where renamed files ended up (`(old, new)` pairs)
+pub fn record_renames(renames: &[(PathBuf, PathBuf)]) {
+    if let Ok(mut recorded) = RECORDED.lock() {
+        recorded.extend(renames.iter().map(|(_, new)| new.clone()));
+    }
+}
+
+/// The paths remembered by [`record_renames`], emptying the list
+pub fn take_recorded() -> Vec<PathBuf> {
+    RECORDED
+        .lock()
+        .map(|mut recorded| std::mem::take(&mut *recorded))
+        .unwrap_or_default()
+}
+
+/// Where the files of the last recorded batch ended up
+///
+/// With `since`, a batch recorded before then gives nothing, so a run that
+/// changed nothing does not copy an older result. Removals give nothing either,
+/// since their files only exist in the trash.
+pub fn new_paths(history: &HistoryManager, since: Option<DateTime<Local>>) -> Vec<PathBuf> {
+    let batches = history.batches();
+    let Some(batch) = batches.last() else {
+        return Vec::new();
+    };
+    if since.is_some_and(|since| batch[0].timestamp < since)
+        || batch.iter().any(|op| op.trash.is_some())
+    {
+        return Vec::new();
+    }
+    batch
+        .iter()
+        .flat_map(|op| history.files_of(op).unwrap_or_default())
+        .map(|moved| moved.destination)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_paths_of_last_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = HistoryManager::new(10, &dir.path().join("backups"));
+        assert!(new_paths(&history, None).is_empty());
+
+        let moves = [
+            (dir.path().join("A.txt"), dir.path().join("a.txt")),
+            (dir.path().join("B.txt"), dir.path().join("b.txt")),
+        ];
+        history
+            .record_mapping(dir.path().to_path_buf(), &moves)
+            .unwrap();
+        assert_eq!(
+            new_paths(&history, None),
+            [dir.path().join("a.txt"), dir.path().join("b.txt")]
+        );
+
+        // A batch from before the run started is not this run's result
+        let later = Local::now() + chrono::Duration::seconds(1);
+        assert!(new_paths(&history, Some(later)).is_empty());
+    }
+}
@@ -1,6 +1,8 @@
 use std::error::Error;
 use std::fmt;
 
+use crate::suggest;
+
 /// CNP Grammar Parser for SMV
 /// Implements the full CNP grammar specification with filters, routes, and semantic groups
 
@@ -12,7 +14,28 @@ pub struct CnpCommand {
     pub flags: String,
     pub transform_command: Option<TransformCommand>,
     pub remove_command: Option<RemoveCommand>,
+    /// `tier`: move matches to the `TO-DIR:` destination
+    pub tier: bool,
+    /// `attr`: change the permissions of matches as `perm` says
+    pub attr: bool,
+    /// `PERM:` value, such as `+x`, `g-w` or `644`
+    pub perm: Option<String>,
+    /// `cp`/`mv` with filters: copy or move matches into `destination`
+    pub transfer: Option<Transfer>,
+    /// Second path of a `cp`/`mv` command
+    pub destination: Option<String>,
     pub case_insensitive: bool,
+    /// `LIMIT:`, `FIRST:` or `SAMPLE:` cap on the matched files
+    pub limit: Option<Limit>,
+    /// `BY:` order files are processed and previewed in (and `FIRST:` ranks by)
+    pub sort: Option<SortOrder>,
+}
+
+/// What `cp`/`mv` with filters does with each match
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transfer {
+    Copy,
+    Move,
 }
 
 #[derive(Debug, Clone)]
@@ -31,6 +54,8 @@ pub struct RemoveCommand {
 #[derive(Debug, Clone, PartialEq)]
 pub enum Filter {
     Name(String),
+    /// `NAME~:` or `REGEX:` - regular expression searched in the file name
+    NameRegex(String),
     Type(FileType),
     Extension(String),
     SizeGreater(String),
@@ -41,12 +66,82 @@ pub enum Filter {
     ModifiedBefore(String),
     AccessedAfter(String),
     AccessedBefore(String),
+    ResolutionGreater(u32),
+    ResolutionLess(u32),
     Tag(String),
+    /// `HASH:[ALGO:]HEX` - files whose content hash starts with HEX
     Hash(String),
-    Where(Vec<Filter>),
+    /// `DUPES:` - every copy of a file but the first by path (`DUPES:all`
+    /// for every copy)
+    Duplicates {
+        all: bool,
+    },
+    /// `EMPTY:true` - zero-byte files and empty directories (`EMPTY:false` for the rest)
+    Empty(bool),
+    Where(Box<FilterExpr>),
     For(SemanticGroup),
 }
 
+/// Boolean combination of filters built by `WHERE ( ... )`
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterExpr {
+    Filter(Filter),
+    Not(Box<FilterExpr>),
+    And(Vec<FilterExpr>),
+    Or(Vec<FilterExpr>),
+}
+
+impl FilterExpr {
+    /// Evaluate the expression, deciding single filters with `check`
+    pub fn evaluate<E>(
+        &self,
+        check: &mut dyn FnMut(&Filter) -> Result<bool, E>,
+    ) -> Result<bool, E> {
+        match self {
+            FilterExpr::Filter(filter) => check(filter),
+            FilterExpr::Not(inner) => Ok(!inner.evaluate(check)?),
+            FilterExpr::And(items) => {
+                for item in items {
+                    if !item.evaluate(check)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            FilterExpr::Or(items) => {
+                for item in items {
+                    if item.evaluate(check)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+        }
+    }
+
+    /// The single filters the expression is made of
+    pub fn filters(&self) -> Vec<&Filter> {
+        match self {
+            FilterExpr::Filter(filter) => vec![filter],
+            FilterExpr::Not(inner) => inner.filters(),
+            FilterExpr::And(items) | FilterExpr::Or(items) => {
+                items.iter().flat_map(FilterExpr::filters).collect()
+            }
+        }
+    }
+}
+
+/// Tokens of a `WHERE` clause
+#[derive(Debug, Clone, PartialEq)]
+enum WhereToken {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Filter(Filter),
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum FileType {
     File,
@@ -69,6 +164,102 @@ pub enum Route {
     To { tool: String, args: Vec<String> }, // TO:tool[:arg1,arg2] - delegate to another CNP tool with optional args
     Into(String),                           // INTO:file - write output to file
     Format(OutputFormat),                   // FORMAT:type - change output format
+    ToDir(String),                          // TO-DIR:path - move matches into a directory
+    IntoDir(String),                        // INTO-DIR:path - transform and move into a directory
+}
+
+/// How many of the matched files an operation touches
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    /// `LIMIT:N` - the first N matches in walk order
+    Count(usize),
+    /// `FIRST:N` - the top N matches by the `BY:` key (newest by default)
+    First(usize),
+    /// `SAMPLE:N` or `SAMPLE:N%` - a random subset
+    Sample(SampleSize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleSize {
+    Count(usize),
+    Percent(f64),
+}
+
+/// Key for `BY:`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    Modified,
+    Size,
+}
+
+impl SortKey {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Modified => "mtime",
+            SortKey::Size => "size",
+        }
+    }
+}
+
+/// `BY:key [ASC|DESC]`
+///
+/// Without a direction, names run A to Z while dates and sizes run newest and
+/// largest first, like `ls -t` and `ls -S`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortOrder {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl SortOrder {
+    pub fn new(key: SortKey) -> Self {
+        Self {
+            key,
+            descending: key != SortKey::Name,
+        }
+    }
+
+    /// Parse `BY:key` at `args[*i]` plus an optional `ASC`/`DESC` after it
+    pub fn parse(args: &[String], i: &mut usize) -> Result<Option<Self>, Box<dyn Error>> {
+        let Some(value) = args.get(*i).and_then(|arg| arg.strip_prefix("BY:")) else {
+            return Ok(None);
+        };
+        let key = match value.to_lowercase().as_str() {
+            "name" => SortKey::Name,
+            "mtime" | "modified" | "date" => SortKey::Modified,
+            "size" => SortKey::Size,
+            _ => {
+                return Err(Box::new(GrammarParseError {
+                    message: format!("Invalid sort key: {value} (use name, mtime or size)"),
+                }));
+            }
+        };
+        *i += 1;
+
+        let mut order = Self::new(key);
+        match args.get(*i).map(String::as_str) {
+            Some("DESC") => order.descending = true,
+            Some("ASC") => order.descending = false,
+            _ => return Ok(Some(order)),
+        }
+        *i += 1;
+        Ok(Some(order))
+    }
+
+    /// "size, largest first"
+    pub fn describe(&self) -> String {
+        let direction = match (self.key, self.descending) {
+            (SortKey::Name, false) => "A to Z",
+            (SortKey::Name, true) => "Z to A",
+            (SortKey::Modified, false) => "oldest first",
+            (SortKey::Modified, true) => "newest first",
+            (SortKey::Size, false) => "smallest first",
+            (SortKey::Size, true) => "largest first",
+        };
+        format!("{}, {}", self.key.as_str(), direction)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -79,6 +270,125 @@ pub enum OutputFormat {
     Yaml,
 }
 
+/// A flag the CNP grammar understands
+#[derive(Debug, Clone, Copy)]
+pub struct FlagSpec {
+    /// Spelling after `-` (`r`, or `ic` for the CNP ignore-case group)
+    pub short: &'static str,
+    /// Spelling after `--`
+    pub long: &'static str,
+    /// Character recorded in [`CnpCommand::flags`]
+    pub normalized: char,
+    pub help: &'static str,
+}
+
+/// Every flag accepted in CNP commands; multi-character spellings come first
+pub const FLAG_REGISTRY: &[FlagSpec] = &[
+    FlagSpec {
+        short: "ic",
+        long: "ignore-case",
+        normalized: 'i',
+        help: "case-insensitive matching (CNP standard)",
+    },
+    FlagSpec {
+        short: "a",
+        long: "hidden",
+        normalized: 'a',
+        help: "include hidden files",
+    },
+    FlagSpec {
+        short: "e",
+        long: "everything",
+        normalized: 'e',
+        help: "include directories",
+    },
+    FlagSpec {
+        short: "f",
+        long: "force",
+        normalized: 'f',
+        help: "force, skip confirmations",
+    },
+    FlagSpec {
+        short: "F",
+        long: "",
+        normalized: 'f',
+        help: "force (SMV spelling)",
+    },
+    FlagSpec {
+        short: "i",
+        long: "",
+        normalized: 'i',
+        help: "case-insensitive matching",
+    },
+    FlagSpec {
+        short: "I",
+        long: "interactive",
+        normalized: 'I',
+        help: "interactive REPL",
+    },
+    FlagSpec {
+        short: "L",
+        long: "dereference",
+        normalized: 'L',
+        help: "dereference symbolic links",
+    },
+    FlagSpec {
+        short: "n",
+        long: "no-clobber",
+        normalized: 'n',
+        help: "never overwrite existing files",
+    },
+    FlagSpec {
+        short: "p",
+        long: "preview",
+        normalized: 'p',
+        help: "preview without applying",
+    },
+    FlagSpec {
+        short: "P",
+        long: "no-dereference",
+        normalized: 'P',
+        help: "do not follow symbolic links",
+    },
+    FlagSpec {
+        short: "r",
+        long: "recursive",
+        normalized: 'r',
+        help: "recurse into subdirectories",
+    },
+    FlagSpec {
+        short: "T",
+        long: "tui",
+        normalized: 'T',
+        help: "terminal UI",
+    },
+    FlagSpec {
+        short: "u",
+        long: "undo",
+        normalized: 'u',
+        help: "undo the last operation",
+    },
+    FlagSpec {
+        short: "v",
+        long: "verbose",
+        normalized: 'v',
+        help: "verbose output",
+    },
+    FlagSpec {
+        short: "x",
+        long: "one-file-system",
+        normalized: 'x',
+        help: "do not descend into other filesystems",
+    },
+];
+
+/// Flags that cannot be combined, by normalized character
+const FLAG_CONFLICTS: &[(char, char, &str)] = &[
+    ('f', 'n', "force overwrites files that no-clobber protects"),
+    ('L', 'P', "links cannot be both dereferenced and left alone"),
+    ('I', 'T', "only one interface can be launched"),
+];
+
 #[derive(Debug)]
 pub struct GrammarParseError {
     pub message: String,
@@ -103,13 +413,46 @@ impl CnpGrammarParser {
             flags: String::new(),
             transform_command: None,
             remove_command: None,
+            tier: false,
+            attr: false,
+            perm: None,
+            transfer: None,
+            destination: None,
             case_insensitive: false,
+            limit: None,
+            sort: None,
         };
 
         let mut i = 0;
         while i < args.len() {
             let arg = &args[i];
 
+            // WHERE ( ... ) groups filters with AND/OR/NOT
+            if arg == "WHERE" {
+                let filter = Self::parse_where(args, &mut i)?;
+                command.filters.push(filter);
+                continue;
+            }
+
+            // BY:key [ASC|DESC] sets the processing order
+            if let Some(order) = SortOrder::parse(args, &mut i)? {
+                command.sort = Some(order);
+                continue;
+            }
+
+            // LIMIT:/FIRST:/SAMPLE: shape the selection rather than filter it
+            if Self::parse_selection(arg, &mut command)? {
+                i += 1;
+                continue;
+            }
+
+            // PERM: is what `attr` does to the matches, not a filter
+            if let Some(value) = arg.strip_prefix("PERM:") {
+                command.perm = Some(value.to_string());
+                i += 1;
+                continue;
+            }
+
             // Parse CNP filters (UPPERCASE keywords)
             if let Some(filter) = Self::parse_filter(arg)? {
                 command.filters.push(filter);
@@ -124,6 +467,27 @@ impl CnpGrammarParser {
                 continue;
             }
 
+            // `tier`, `attr`, `cp` and `mv` only count as the command word, not as a path later on
+            if i == 0 && arg.eq_ignore_ascii_case("tier") {
+                command.tier = true;
+                i += 1;
+                continue;
+            }
+            if i == 0 && arg == "attr" {
+                command.attr = true;
+                i += 1;
+                continue;
+            }
+            if i == 0 && matches!(arg.as_str(), "cp" | "mv") {
+                command.transfer = Some(if arg == "cp" {
+                    Transfer::Copy
+                } else {
+                    Transfer::Move
+                });
+                i += 1;
+                continue;
+            }
+
             // Parse SMV remove commands FIRST (before transform commands)
             if let Some(remove) = Self::parse_remove_command(args, &mut i)? {
                 command.remove_command = Some(remove);
@@ -137,12 +501,14 @@ impl CnpGrammarParser {
             }
 
             // Parse flags (starting with -)
-            if let Some(flags) = arg.strip_prefix('-') {
-                command.flags.push_str(flags);
-                // Check for case-insensitive flag (both CNP standard 'ic' and SMV-specific 'i')
-                if flags.contains("ic") || flags.contains('i') {
-                    command.case_insensitive = true;
+            if arg.starts_with('-') && arg.len() > 1 {
+                for flag in Self::parse_flag_group(arg)? {
+                    if !command.flags.contains(flag) {
+                        command.flags.push(flag);
+                    }
                 }
+                // Both the CNP standard 'ic' and SMV-specific 'i' normalize to 'i'
+                command.case_insensitive = command.flags.contains('i');
                 i += 1;
                 continue;
             }
@@ -163,12 +529,237 @@ impl CnpGrammarParser {
                 continue;
             }
 
+            // cp/mv take a destination after the path
+            if command.transfer.is_some() && command.destination.is_none() && !arg.contains(':') {
+                command.destination = Some(arg.clone());
+                i += 1;
+                continue;
+            }
+
+            // A misspelled keyword would otherwise be dropped without a word
+            if suggest::looks_like_keyword(arg) {
+                return Err(Box::new(GrammarParseError {
+                    message: suggest::unknown_keyword(arg),
+                }));
+            }
+
             i += 1;
         }
 
+        Self::check_flag_conflicts(&command.flags)?;
+
         Ok(command)
     }
 
+    /// Parse `WHERE <expr>` starting at the `WHERE` keyword
+    ///
+    /// The clause runs until the first argument that is not a filter, operator or
+    /// parenthesis. `NOT` binds tighter than `AND`, which binds tighter than `OR`;
+    /// filters next to each other are ANDed. Parentheses may stand alone or stick
+    /// to filters (`(EXT:log`, `EXT:tmp)`), and a quoted clause is split on spaces.
+    fn parse_where(args: &[String], i: &mut usize) -> Result<Filter, Box<dyn Error>> {
+        *i += 1;
+        let mut tokens = Vec::new();
+        let mut depth = 0usize;
+
+        'args: while *i < args.len() {
+            let words: Vec<&str> = args[*i].split_whitespace().collect();
+            let mut arg_tokens = Vec::new();
+            for (index, word) in words.iter().enumerate() {
+                match Self::tokenize_where_word(word)? {
+                    Some(word_tokens) => arg_tokens.extend(word_tokens),
+                    // A plain argument ends the clause, unless it is inside a quoted clause
+                    None if index == 0 && depth == 0 => break 'args,
+                    None => {
+                        return Err(Box::new(GrammarParseError {
+                            message: format!("Unexpected '{word}' in WHERE clause"),
+                        }));
+                    }
+                }
+            }
+            for token in &arg_tokens {
+                match token {
+                    WhereToken::Open => depth += 1,
+                    WhereToken::Close => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+            }
+            tokens.extend(arg_tokens);
+            *i += 1;
+        }
+
+        if tokens.is_empty() {
+            return Err(Box::new(GrammarParseError {
+                message: "WHERE needs at least one filter".to_string(),
+            }));
+        }
+
+        let mut pos = 0;
+        let expr = Self::parse_or(&tokens, &mut pos)?;
+        if pos < tokens.len() {
+            return Err(Box::new(GrammarParseError {
+                message: "Unbalanced ')' in WHERE clause".to_string(),
+            }));
+        }
+        Ok(Filter::Where(Box::new(expr)))
+    }
+
+    /// Split one word into WHERE tokens, or `None` if it does not belong in a clause
+    fn tokenize_where_word(word: &str) -> Result<Option<Vec<WhereToken>>, Box<dyn Error>> {
+        let mut tokens = Vec::new();
+        let mut core = word;
+        while let Some(rest) = core.strip_prefix('(') {
+            tokens.push(WhereToken::Open);
+            core = rest;
+        }
+
+        // Only peel closing parentheses that are not part of the filter value
+        let mut closing = 0;
+        while core.ends_with(')') && core.matches(')').count() > core.matches('(').count() {
+            core = &core[..core.len() - 1];
+            closing += 1;
+        }
+
+        match core {
+            "" => {}
+            "AND" => tokens.push(WhereToken::And),
+            "OR" => tokens.push(WhereToken::Or),
+            "NOT" => tokens.push(WhereToken::Not),
+            _ => {
+                let filter = match Self::parse_filter(core)? {
+                    Some(filter) => filter,
+                    None if Self::is_glob_pattern(core) => {
+                        match Self::convert_glob_to_filter(core)? {
+                            Some(filter) => filter,
+                            None => return Ok(None),
+                        }
+                    }
+                    None => return Ok(None),
+                };
+                tokens.push(WhereToken::Filter(filter));
+            }
+        }
+
+        tokens.extend(std::iter::repeat_n(WhereToken::Close, closing));
+        Ok(Some(tokens))
+    }
+
+    fn parse_or(tokens: &[WhereToken], pos: &mut usize) -> Result<FilterExpr, Box<dyn Error>> {
+        let mut items = vec![Self::parse_and(tokens, pos)?];
+        while tokens.get(*pos) == Some(&WhereToken::Or) {
+            *pos += 1;
+            items.push(Self::parse_and(tokens, pos)?);
+        }
+        Ok(if items.len() == 1 {
+            items.remove(0)
+        } else {
+            FilterExpr::Or(items)
+        })
+    }
+
+    fn parse_and(tokens: &[WhereToken], pos: &mut usize) -> Result<FilterExpr, Box<dyn Error>> {
+        let mut items = vec![Self::parse_not(tokens, pos)?];
+        loop {
+            match tokens.get(*pos) {
+                Some(WhereToken::And) => *pos += 1,
+                // Adjacent filters are ANDed
+                Some(WhereToken::Filter(_) | WhereToken::Not | WhereToken::Open) => {}
+                _ => break,
+            }
+            items.push(Self::parse_not(tokens, pos)?);
+        }
+        Ok(if items.len() == 1 {
+            items.remove(0)
+        } else {
+            FilterExpr::And(items)
+        })
+    }
+
+    fn parse_not(tokens: &[WhereToken], pos: &mut usize) -> Result<FilterExpr, Box<dyn Error>> {
+        let token = tokens.get(*pos).cloned();
+        *pos += 1;
+        match token {
+            Some(WhereToken::Not) => Ok(FilterExpr::Not(Box::new(Self::parse_not(tokens, pos)?))),
+            Some(WhereToken::Filter(filter)) => Ok(FilterExpr::Filter(filter)),
+            Some(WhereToken::Open) => {
+                let expr = Self::parse_or(tokens, pos)?;
+                if tokens.get(*pos) != Some(&WhereToken::Close) {
+                    return Err(Box::new(GrammarParseError {
+                        message: "Missing ')' in WHERE clause".to_string(),
+                    }));
+                }
+                *pos += 1;
+                Ok(expr)
+            }
+            Some(other) => Err(Box::new(GrammarParseError {
+                message: format!("Expected a filter in WHERE clause, found {other:?}"),
+            })),
+            None => Err(Box::new(GrammarParseError {
+                message: "WHERE clause ends where a filter was expected".to_string(),
+            })),
+        }
+    }
+
+    /// Split `-rp`, `-ric` or `--recursive` into normalized flag characters
+    ///
+    /// Unknown and repeated flags are errors, so typos such as `-rrp` or `-z`
+    /// are reported instead of silently ignored.
+    pub fn parse_flag_group(arg: &str) -> Result<Vec<char>, Box<dyn Error>> {
+        if let Some(long) = arg.strip_prefix("--") {
+            return FLAG_REGISTRY
+                .iter()
+                .find(|spec| !spec.long.is_empty() && spec.long == long)
+                .map(|spec| vec![spec.normalized])
+                .ok_or_else(|| {
+                    Box::new(GrammarParseError {
+                        message: format!("Unknown flag: {arg}\n{}", Self::flag_help()),
+                    }) as Box<dyn Error>
+                });
+        }
+
+        let mut rest = &arg[1..];
+        let mut flags = Vec::new();
+        while !rest.is_empty() {
+            let spec = FLAG_REGISTRY
+                .iter()
+                .find(|spec| rest.starts_with(spec.short))
+                .ok_or_else(|| GrammarParseError {
+                    message: format!(
+                        "Unknown flag -{} in '{arg}'\n{}",
+                        rest.chars().next().unwrap_or_default(),
+                        Self::flag_help()
+                    ),
+                })?;
+            if flags.contains(&spec.normalized) {
+                return Err(Box::new(GrammarParseError {
+                    message: format!("Flag -{} is repeated in '{arg}'", spec.short),
+                }));
+            }
+            flags.push(spec.normalized);
+            rest = &rest[spec.short.len()..];
+        }
+        Ok(flags)
+    }
+
+    fn check_flag_conflicts(flags: &str) -> Result<(), Box<dyn Error>> {
+        for (a, b, reason) in FLAG_CONFLICTS {
+            if flags.contains(*a) && flags.contains(*b) {
+                return Err(Box::new(GrammarParseError {
+                    message: format!("Flags -{a} and -{b} conflict: {reason}"),
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    fn flag_help() -> String {
+        let flags: Vec<String> = FLAG_REGISTRY
+            .iter()
+            .map(|spec| format!("  -{:<3} {}", spec.short, spec.help))
+            .collect();
+        format!("Valid flags:\n{}", flags.join("\n"))
+    }
+
     /// Check if an argument is a glob pattern
     fn is_glob_pattern(arg: &str) -> bool {
         arg.contains('*') || arg.contains('?') || arg.contains('[') || arg.contains('{')
@@ -212,6 +803,7 @@ impl CnpGrammarParser {
             && !arg.starts_with("DEPTH")
             && !arg.starts_with("MODIFIED")
             && !arg.starts_with("ACCESSED")
+            && !arg.starts_with("RES")
         {
             return Ok(None);
         }
@@ -238,6 +830,23 @@ impl CnpGrammarParser {
             return Ok(Some(Filter::DepthLess(value)));
         }
 
+        // Handle video resolution comparisons (RES>1080p, RES<4k)
+        for (prefix, greater) in [("RES>", true), ("RES<", false)] {
+            if let Some(stripped) = arg.strip_prefix(prefix) {
+                let height =
+                    crate::template::video::parse_resolution(stripped).ok_or_else(|| {
+                        GrammarParseError {
+                            message: format!("Invalid resolution value: {stripped}"),
+                        }
+                    })?;
+                return Ok(Some(if greater {
+                    Filter::ResolutionGreater(height)
+                } else {
+                    Filter::ResolutionLess(height)
+                }));
+            }
+        }
+
         // Handle timestamp comparisons
         if let Some(stripped) = arg.strip_prefix("MODIFIED>") {
             return Ok(Some(Filter::ModifiedAfter(stripped.to_string())));
@@ -259,6 +868,12 @@ impl CnpGrammarParser {
 
             match key {
                 "NAME" => Ok(Some(Filter::Name(value.to_string()))),
+                "NAME~" | "REGEX" => {
+                    regex::Regex::new(value).map_err(|e| GrammarParseError {
+                        message: format!("Invalid regex in {key}: {e}"),
+                    })?;
+                    Ok(Some(Filter::NameRegex(value.to_string())))
+                }
                 "TYPE" => {
                     let file_type = match value.to_lowercase().as_str() {
                         "file" => FileType::File,
@@ -273,9 +888,53 @@ impl CnpGrammarParser {
                     };
                     Ok(Some(Filter::Type(file_type)))
                 }
-                "EXT" => Ok(Some(Filter::Extension(value.to_string()))),
+                "EXT" => Self::extension_filter(value).map(Some),
                 "TAG" => Ok(Some(Filter::Tag(value.to_string()))),
-                "HASH" => Ok(Some(Filter::Hash(value.to_string()))),
+                "EMPTY" => {
+                    let empty = match value.to_lowercase().as_str() {
+                        "" | "true" | "yes" => true,
+                        "false" | "no" => false,
+                        _ => {
+                            return Err(Box::new(GrammarParseError {
+                                message: format!(
+                                    "Invalid EMPTY: value: {value} (use true or false)"
+                                ),
+                            }));
+                        }
+                    };
+                    Ok(Some(Filter::Empty(empty)))
+                }
+                "HASH" => {
+                    let hex = match value.split_once(':') {
+                        Some((algorithm, hex)) => {
+                            crate::hash::Algorithm::parse(algorithm).map_err(|message| {
+                                Box::new(GrammarParseError { message }) as Box<dyn Error>
+                            })?;
+                            hex
+                        }
+                        None => value,
+                    };
+                    if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return Err(Box::new(GrammarParseError {
+                            message: format!("HASH: needs hex digits, got '{value}'"),
+                        }));
+                    }
+                    Ok(Some(Filter::Hash(value.to_lowercase())))
+                }
+                "DUPES" => {
+                    let all = match value.to_lowercase().as_str() {
+                        "" | "extra" | "true" | "yes" => false,
+                        "all" => true,
+                        _ => {
+                            return Err(Box::new(GrammarParseError {
+                                message: format!(
+                                    "Invalid DUPES: value: {value} (use extra or all)"
+                                ),
+                            }));
+                        }
+                    };
+                    Ok(Some(Filter::Duplicates { all }))
+                }
                 "FOR" => {
                     let semantic_group = match value.to_lowercase().as_str() {
                         "notes" => SemanticGroup::Notes,
@@ -298,6 +957,51 @@ impl CnpGrammarParser {
         }
     }
 
+    /// Parse `LIMIT:`, `FIRST:` and `SAMPLE:` into `command`
+    fn parse_selection(arg: &str, command: &mut CnpCommand) -> Result<bool, Box<dyn Error>> {
+        let Some((key, value)) = arg.split_once(':') else {
+            return Ok(false);
+        };
+        let count = |value: &str| {
+            value
+                .parse::<usize>()
+                .ok()
+                .filter(|count| *count > 0)
+                .ok_or_else(|| GrammarParseError {
+                    message: format!("{key}: needs a positive count, got '{value}'"),
+                })
+        };
+
+        let limit = match key {
+            "LIMIT" => Limit::Count(count(value)?),
+            "FIRST" => Limit::First(count(value)?),
+            "SAMPLE" => match value.strip_suffix('%') {
+                Some(percent) => {
+                    let percent = percent
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|percent| *percent > 0.0 && *percent <= 100.0)
+                        .ok_or_else(|| GrammarParseError {
+                            message: format!(
+                                "SAMPLE: percentage must be in (0, 100], got '{value}'"
+                            ),
+                        })?;
+                    Limit::Sample(SampleSize::Percent(percent))
+                }
+                None => Limit::Sample(SampleSize::Count(count(value)?)),
+            },
+            _ => return Ok(false),
+        };
+
+        if command.limit.is_some() {
+            return Err(Box::new(GrammarParseError {
+                message: "Only one of LIMIT:, FIRST: and SAMPLE: can be given".to_string(),
+            }));
+        }
+        command.limit = Some(limit);
+        Ok(true)
+    }
+
     fn parse_route(arg: &str) -> Result<Option<Route>, Box<dyn Error>> {
         if !arg.contains(':') {
             return Ok(None);
@@ -329,6 +1033,22 @@ impl CnpGrammarParser {
                     }
                 }
                 "INTO" => Ok(Some(Route::Into(value.to_string()))),
+                "TO-DIR" => {
+                    if value.is_empty() {
+                        return Err(Box::new(GrammarParseError {
+                            message: "TO-DIR: needs a destination directory".to_string(),
+                        }));
+                    }
+                    Ok(Some(Route::ToDir(value.to_string())))
+                }
+                "INTO-DIR" => {
+                    if value.is_empty() {
+                        return Err(Box::new(GrammarParseError {
+                            message: "INTO-DIR: needs a destination directory".to_string(),
+                        }));
+                    }
+                    Ok(Some(Route::IntoDir(value.to_string())))
+                }
                 "FORMAT" => {
                     let format = match value.to_lowercase().as_str() {
                         "json" => OutputFormat::Json,
@@ -406,11 +1126,96 @@ impl CnpGrammarParser {
         Ok(None)
     }
 
+    /// Parse the value of `EXT:`: `jpg`, `jpg,png,gif` (any of them) or `!tmp` (not tmp)
+    fn extension_filter(value: &str) -> Result<Filter, Box<dyn Error>> {
+        let mut include = Vec::new();
+        let mut exclude = Vec::new();
+        for item in value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            match item.strip_prefix('!') {
+                Some(negated) => exclude.push(negated.trim_start_matches('.')),
+                None => include.push(item.trim_start_matches('.')),
+            }
+        }
+
+        if include.len() == 1 && exclude.is_empty() {
+            return Ok(Filter::Extension(include[0].to_string()));
+        }
+
+        let mut parts = Vec::new();
+        match include.len() {
+            0 => {}
+            1 => parts.push(FilterExpr::Filter(Filter::Extension(
+                include[0].to_string(),
+            ))),
+            _ => parts.push(FilterExpr::Or(
+                include
+                    .iter()
+                    .map(|ext| FilterExpr::Filter(Filter::Extension(ext.to_string())))
+                    .collect(),
+            )),
+        }
+        parts.extend(exclude.iter().map(|ext| {
+            FilterExpr::Not(Box::new(FilterExpr::Filter(Filter::Extension(
+                ext.to_string(),
+            ))))
+        }));
+
+        match parts.len() {
+            0 => Err(Box::new(GrammarParseError {
+                message: "EXT: needs at least one extension".to_string(),
+            })),
+            1 => Ok(Filter::Where(Box::new(parts.remove(0)))),
+            _ => Ok(Filter::Where(Box::new(FilterExpr::And(parts)))),
+        }
+    }
+
+    /// The extensions a filter accepts when it is only a list of alternatives
+    fn listed_extensions(filter: &Filter) -> Option<Vec<&str>> {
+        match filter {
+            Filter::Extension(ext) => Some(vec![ext.as_str()]),
+            Filter::Where(expr) => match expr.as_ref() {
+                FilterExpr::Or(items) => items
+                    .iter()
+                    .map(|item| match item {
+                        FilterExpr::Filter(Filter::Extension(ext)) => Some(ext.as_str()),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     /// Expand semantic groups into concrete filters
+    ///
+    /// Groups with several extensions or names match any one of them, and so do
+    /// several `EXT:` filters (or `*.ext` globs) given side by side.
     pub fn expand_semantic_groups(filters: &[Filter]) -> Vec<Filter> {
         let mut expanded = Vec::new();
 
+        let extensions: Vec<&str> = filters
+            .iter()
+            .filter_map(Self::listed_extensions)
+            .flatten()
+            .collect();
+        let mut extensions_added = false;
+
         for filter in filters {
+            if Self::listed_extensions(filter).is_some() {
+                if !extensions_added {
+                    expanded.push(match extensions.as_slice() {
+                        [ext] => Filter::Extension(ext.to_string()),
+                        _ => Self::any_extension(&extensions),
+                    });
+                    extensions_added = true;
+                }
+                continue;
+            }
             match filter {
                 Filter::For(group) => match group {
                     SemanticGroup::Notes => {
@@ -418,32 +1223,36 @@ impl CnpGrammarParser {
                         expanded.push(Filter::Type(FileType::File));
                     }
                     SemanticGroup::Media => {
-                        for ext in ["jpg", "png", "gif", "webm", "mp4", "jpeg", "webp", "svg"] {
-                            expanded.push(Filter::Extension(ext.to_string()));
-                        }
+                        expanded.push(Self::any_extension(&[
+                            "jpg", "png", "gif", "webm", "mp4", "jpeg", "webp", "svg",
+                        ]));
                         expanded.push(Filter::Type(FileType::File));
                     }
                     SemanticGroup::Scripts => {
-                        for ext in ["sh", "py", "rb", "pl", "rs", "js", "ts", "bash", "zsh"] {
-                            expanded.push(Filter::Extension(ext.to_string()));
-                        }
+                        expanded.push(Self::any_extension(&[
+                            "sh", "py", "rb", "pl", "rs", "js", "ts", "bash", "zsh",
+                        ]));
                         expanded.push(Filter::Type(FileType::File));
                     }
                     SemanticGroup::Projects => {
                         expanded.push(Filter::Type(FileType::Folder));
-                        for name in ["src", "build", "docs", "target", "dist", "bin"] {
-                            expanded.push(Filter::Name(name.to_string()));
-                        }
+                        expanded.push(Filter::Where(Box::new(FilterExpr::Or(
+                            ["src", "build", "docs", "target", "dist", "bin"]
+                                .iter()
+                                .map(|name| FilterExpr::Filter(Filter::Name(name.to_string())))
+                                .collect(),
+                        ))));
                     }
                     SemanticGroup::Configs => {
-                        for ext in [
+                        expanded.push(Self::any_extension(&[
                             "conf", "ini", "yaml", "yml", "toml", "json", "config", "cfg",
-                        ] {
-                            expanded.push(Filter::Extension(ext.to_string()));
-                        }
+                        ]));
                         expanded.push(Filter::Type(FileType::File));
                     }
                 },
+                Filter::Where(expr) => {
+                    expanded.push(Filter::Where(Box::new(Self::expand_expr(expr))));
+                }
                 _ => expanded.push(filter.clone()),
             }
         }
@@ -451,6 +1260,34 @@ impl CnpGrammarParser {
         expanded
     }
 
+    fn any_extension(extensions: &[&str]) -> Filter {
+        Filter::Where(Box::new(FilterExpr::Or(
+            extensions
+                .iter()
+                .map(|ext| FilterExpr::Filter(Filter::Extension(ext.to_string())))
+                .collect(),
+        )))
+    }
+
+    /// Expand semantic groups nested inside a WHERE expression
+    fn expand_expr(expr: &FilterExpr) -> FilterExpr {
+        match expr {
+            FilterExpr::Filter(filter) => {
+                let mut expanded = Self::expand_semantic_groups(std::slice::from_ref(filter));
+                if expanded.len() == 1 {
+                    FilterExpr::Filter(expanded.remove(0))
+                } else {
+                    FilterExpr::And(expanded.into_iter().map(FilterExpr::Filter).collect())
+                }
+            }
+            FilterExpr::Not(inner) => FilterExpr::Not(Box::new(Self::expand_expr(inner))),
+            FilterExpr::And(items) => {
+                FilterExpr::And(items.iter().map(Self::expand_expr).collect())
+            }
+            FilterExpr::Or(items) => FilterExpr::Or(items.iter().map(Self::expand_expr).collect()),
+        }
+    }
+
     fn parse_remove_command(
         args: &[String],
         i: &mut usize,
@@ -635,4 +1472,330 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_resolution_filters() -> Result<(), Box<dyn std::error::Error>> {
+        let args = vec![
+            "snake".to_string(),
+            "RES>1080p".to_string(),
+            "RES<4k".to_string(),
+        ];
+        let result = CnpGrammarParser::parse(&args)?;
+        assert_eq!(
+            result.filters,
+            vec![
+                Filter::ResolutionGreater(1080),
+                Filter::ResolutionLess(2160)
+            ]
+        );
+
+        assert!(CnpGrammarParser::parse(&["RES>huge".to_string()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tier_command() -> Result<(), Box<dyn std::error::Error>> {
+        let args: Vec<String> = [
+            "tier",
+            ".",
+            "SIZE>1GB",
+            "ACCESSED<2023-01-01",
+            "TO-DIR:/mnt/cold",
+            "-r",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let result = CnpGrammarParser::parse(&args)?;
+        assert!(result.tier);
+        assert_eq!(result.path, ".");
+        assert_eq!(result.filters.len(), 2);
+        assert!(matches!(&result.routes[0], Route::ToDir(dir) if dir == "/mnt/cold"));
+
+        assert!(CnpGrammarParser::parse(&["tier".to_string(), "TO-DIR:".to_string()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_attr_command() -> Result<(), Box<dyn std::error::Error>> {
+        let args: Vec<String> = ["attr", "scripts", "EXT:sh", "PERM:+x", "-r"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let result = CnpGrammarParser::parse(&args)?;
+        assert!(result.attr);
+        assert_eq!(result.path, "scripts");
+        assert_eq!(result.filters.len(), 1);
+        assert_eq!(result.perm.as_deref(), Some("+x"));
+        assert!(result.flags.contains('r'));
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_dir_route() -> Result<(), Box<dyn std::error::Error>> {
+        let args: Vec<String> = ["snake", "inbox/", "INTO-DIR:archive/", "-r"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let result = CnpGrammarParser::parse(&args)?;
+        assert_eq!(result.path, "inbox/");
+        assert!(result.transform_command.is_some());
+        assert!(matches!(&result.routes[0], Route::IntoDir(dir) if dir == "archive/"));
+
+        assert!(CnpGrammarParser::parse(&["snake".to_string(), "INTO-DIR:".to_string()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_selection_modifiers() -> Result<(), Box<dyn std::error::Error>> {
+        let parse = |items: &[&str]| {
+            CnpGrammarParser::parse(&items.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+        };
+
+        let command = parse(&["snake", "photos/", "FIRST:10", "BY:mtime", "-r"])?;
+        assert_eq!(command.path, "photos/");
+        assert_eq!(command.limit, Some(Limit::First(10)));
+        assert_eq!(command.sort, Some(SortOrder::new(SortKey::Modified)));
+        assert!(command.sort.unwrap().descending);
+
+        assert_eq!(
+            parse(&["snake", "LIMIT:100"])?.limit,
+            Some(Limit::Count(100))
+        );
+        assert_eq!(
+            parse(&["snake", "SAMPLE:5%"])?.limit,
+            Some(Limit::Sample(SampleSize::Percent(5.0)))
+        );
+        assert!(parse(&["snake", "LIMIT:0"]).is_err());
+        assert!(parse(&["snake", "SAMPLE:150%"]).is_err());
+        assert!(parse(&["snake", "LIMIT:5", "SAMPLE:2"]).is_err());
+        assert!(parse(&["snake", "BY:colour"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_extension_lists() -> Result<(), Box<dyn std::error::Error>> {
+        let any = |exts: &[&str]| {
+            FilterExpr::Or(
+                exts.iter()
+                    .map(|ext| FilterExpr::Filter(Filter::Extension(ext.to_string())))
+                    .collect(),
+            )
+        };
+        let not = |ext: &str| {
+            FilterExpr::Not(Box::new(FilterExpr::Filter(Filter::Extension(
+                ext.to_string(),
+            ))))
+        };
+
+        assert_eq!(
+            CnpGrammarParser::parse_filter("EXT:jpg,png,gif")?,
+            Some(Filter::Where(Box::new(any(&["jpg", "png", "gif"]))))
+        );
+        assert_eq!(
+            CnpGrammarParser::parse_filter("EXT:!tmp")?,
+            Some(Filter::Where(Box::new(not("tmp"))))
+        );
+        assert_eq!(
+            CnpGrammarParser::parse_filter("EXT:md,txt,!bak")?,
+            Some(Filter::Where(Box::new(FilterExpr::And(vec![
+                any(&["md", "txt"]),
+                not("bak")
+            ]))))
+        );
+        assert!(CnpGrammarParser::parse_filter("EXT:,").is_err());
+
+        // Side-by-side extension filters are alternatives, negations still apply
+        let filters = vec![
+            Filter::Extension("jpg".to_string()),
+            Filter::Type(FileType::File),
+            Filter::Where(Box::new(any(&["png", "gif"]))),
+            Filter::Where(Box::new(not("tmp"))),
+        ];
+        assert_eq!(
+            CnpGrammarParser::expand_semantic_groups(&filters),
+            vec![
+                Filter::Where(Box::new(any(&["jpg", "png", "gif"]))),
+                Filter::Type(FileType::File),
+                Filter::Where(Box::new(not("tmp"))),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_name_regex_filter() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(
+            CnpGrammarParser::parse_filter(r"NAME~:^IMG_\d{4}\.jpe?g$")?,
+            Some(Filter::NameRegex(r"^IMG_\d{4}\.jpe?g$".to_string()))
+        );
+        assert_eq!(
+            CnpGrammarParser::parse_filter("REGEX:draft|wip")?,
+            Some(Filter::NameRegex("draft|wip".to_string()))
+        );
+        assert!(CnpGrammarParser::parse_filter("NAME~:(unclosed").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_filter() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(
+            CnpGrammarParser::parse_filter("EMPTY:true")?,
+            Some(Filter::Empty(true))
+        );
+        assert_eq!(
+            CnpGrammarParser::parse_filter("EMPTY:false")?,
+            Some(Filter::Empty(false))
+        );
+        assert!(CnpGrammarParser::parse_filter("EMPTY:maybe").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_filters() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(
+            CnpGrammarParser::parse_filter("HASH:sha256:BA7816")?,
+            Some(Filter::Hash("sha256:ba7816".to_string()))
+        );
+        assert!(CnpGrammarParser::parse_filter("HASH:md5:ba7816").is_err());
+        assert!(CnpGrammarParser::parse_filter("HASH:xyz").is_err());
+        assert_eq!(
+            CnpGrammarParser::parse_filter("DUPES:")?,
+            Some(Filter::Duplicates { all: false })
+        );
+        assert_eq!(
+            CnpGrammarParser::parse_filter("DUPES:all")?,
+            Some(Filter::Duplicates { all: true })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_filtered_transfer() -> Result<(), Box<dyn std::error::Error>> {
+        let parse = |items: &[&str]| {
+            CnpGrammarParser::parse(&items.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+        };
+
+        let command = parse(&["cp", "photos", "EXT:raw", "-r", "/mnt/backup"])?;
+        assert_eq!(command.transfer, Some(Transfer::Copy));
+        assert_eq!(command.path, "photos");
+        assert_eq!(command.destination.as_deref(), Some("/mnt/backup"));
+
+        let command = parse(&["mv", ".", "EXT:log", "TO-DIR:old/"])?;
+        assert_eq!(command.transfer, Some(Transfer::Move));
+        assert!(command.destination.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_order() -> Result<(), Box<dyn std::error::Error>> {
+        let parse = |items: &[&str]| {
+            CnpGrammarParser::parse(&items.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+        };
+
+        let command = parse(&["snake", "BY:size", "ASC", "docs/", "-p"])?;
+        assert_eq!(command.path, "docs/");
+        assert_eq!(
+            command.sort,
+            Some(SortOrder {
+                key: SortKey::Size,
+                descending: false,
+            })
+        );
+
+        let command = parse(&["snake", "docs/", "BY:name", "DESC"])?;
+        assert!(command.sort.unwrap().descending);
+        assert_eq!(command.sort.unwrap().describe(), "name, Z to A");
+        assert!(!parse(&["snake", "BY:name"])?.sort.unwrap().descending);
+        Ok(())
+    }
+
+    #[test]
+    fn test_flag_validation() -> Result<(), Box<dyn std::error::Error>> {
+        let parse = |items: &[&str]| {
+            CnpGrammarParser::parse(&items.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+        };
+
+        let command = parse(&["rm", ".", "EXT:log", "-ric", "--preview"])?;
+        assert_eq!(command.flags, "rip");
+        assert!(command.case_insensitive);
+
+        // -ic no longer leaks a stray 'c'; -F and -f both mean force
+        assert_eq!(parse(&[".", "-ic"])?.flags, "i");
+        assert_eq!(parse(&[".", "-F"])?.flags, "f");
+        assert_eq!(parse(&[".", "-rx"])?.flags, "rx");
+        assert_eq!(parse(&[".", "--one-file-system"])?.flags, "x");
+
+        assert!(parse(&[".", "-rrp"]).is_err());
+        assert!(parse(&[".", "-z"]).is_err());
+        assert!(parse(&[".", "--bogus"]).is_err());
+        assert!(parse(&[".", "-f", "-n"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_where_grouping() -> Result<(), Box<dyn std::error::Error>> {
+        let parse = |items: &[&str]| {
+            CnpGrammarParser::parse(&items.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+        };
+        let ext = |e: &str| FilterExpr::Filter(Filter::Extension(e.to_string()));
+
+        // AND binds tighter than OR; parentheses may stick to filters
+        let command = parse(&[
+            "rm",
+            ".",
+            "WHERE",
+            "(EXT:log",
+            "OR",
+            "EXT:tmp)",
+            "AND",
+            "NOT",
+            "NAME:keep",
+            "-r",
+        ])?;
+        assert_eq!(command.path, ".");
+        assert_eq!(command.flags, "r");
+        assert_eq!(
+            command.filters,
+            vec![Filter::Where(Box::new(FilterExpr::And(vec![
+                FilterExpr::Or(vec![ext("log"), ext("tmp")]),
+                FilterExpr::Not(Box::new(FilterExpr::Filter(Filter::Name("keep".into())))),
+            ])))]
+        );
+
+        // A quoted clause, nested groups and implicit AND
+        let command = parse(&["rm", "WHERE", "EXT:md OR ((EXT:txt SIZE>1KB))", "notes"])?;
+        assert_eq!(command.path, "notes");
+        assert_eq!(
+            command.filters,
+            vec![Filter::Where(Box::new(FilterExpr::Or(vec![
+                ext("md"),
+                FilterExpr::And(vec![
+                    ext("txt"),
+                    FilterExpr::Filter(Filter::SizeGreater("1KB".into())),
+                ]),
+            ])))]
+        );
+
+        assert!(parse(&["rm", "WHERE", "(EXT:log"]).is_err());
+        assert!(parse(&["rm", "WHERE", "EXT:log", "OR"]).is_err());
+        assert!(parse(&["rm", "WHERE", "-r"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_where_evaluation() {
+        let expr = FilterExpr::Or(vec![
+            FilterExpr::Filter(Filter::Extension("log".into())),
+            FilterExpr::Not(Box::new(FilterExpr::Filter(Filter::Extension("md".into())))),
+        ]);
+        let matches = |ext: &str| {
+            expr.evaluate(&mut |filter| {
+                Ok::<_, ()>(matches!(filter, Filter::Extension(e) if e == ext))
+            })
+            .unwrap()
+        };
+        assert!(matches("log"));
+        assert!(!matches("md"));
+    }
 }
@@ -0,0 +1,478 @@
+//! The user's configuration file
+//!
+//! Read from `$SMV_CONFIG` when set, then `$SMV_HOME/config.toml`, then
+//! `$XDG_CONFIG_HOME/smv/config.toml` (`~/.config/smv/config.toml`). A missing
+//! file is an empty configuration.
+//!
+//! Per-directory rules guard folders that should only ever hold certain kinds
+//! of files:
+//!
+//! ```toml
+//! [dir."~/Pictures"]
+//! allow_ext = ["jpg", "png", "dng"]
+//! ```
+//!
+//! A run that would touch any other type of file under `~/Pictures`, as a
+//! source or as a destination, is refused before anything changes unless
+//! `--any-ext` is given. The most specific matching directory's rule applies.
+//!
+//! The `[tools]` table controls the programs smv hands work to (`TO:tool`,
+//! and `dsc` for glob patterns):
+//!
+//! ```toml
+//! [tools]
+//! delegation = true        # false refuses to start any of them
+//! env = ["SSH_AUTH_SOCK"]  # variables to pass on beyond the usual few
+//!
+//! [tools.paths]
+//! dsc = "/usr/local/bin/dsc"
+//! ```
+//!
+//! A tool with a configured path is started from that path only, so a `dsc`
+//! placed earlier on someone's `PATH` cannot stand in for it. Every tool runs
+//! with a cleared environment: the locale, home, user, terminal and temporary
+//! directory variables, the names in `env`, and `PATH` set to the system
+//! directories unless `env` lists it.
+//!
+//! The `[tui]` table overrides what `smv tui` detects about the terminal
+//! (`colors = "none"|"16"|"256"|"truecolor"`, `unicode = false` for ASCII
+//! borders and markers).
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use walkdir::WalkDir;
+
+use crate::ui::terminal::TuiConfig;
+
+/// Refused files listed before "and N more"
+const SHOWN_REFUSED: usize = 5;
+
+/// Environment variables every delegated tool gets, when they are set
+const KEPT_ENV: &[&str] = &[
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "LANG",
+    "LC_ALL",
+    "LC_CTYPE",
+    "LC_MESSAGES",
+    "TERM",
+    "TMPDIR",
+    "TZ",
+    // Windows needs these to start anything at all
+    "SystemRoot",
+    "TEMP",
+    "USERPROFILE",
+];
+
+/// `PATH` for delegated tools unless `[tools] env` passes the user's on
+const SAFE_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Rules keyed by directory; `~` stands for the home directory
+    #[serde(default)]
+    pub dir: BTreeMap<String, DirRules>,
+    #[serde(default)]
+    pub tools: Tools,
+    /// Overrides for the colors and glyphs the TUI detects
+    #[serde(default)]
+    pub tui: TuiConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DirRules {
+    /// Extensions (without the dot) the directory may hold; empty allows all
+    #[serde(default)]
+    pub allow_ext: Vec<String>,
+}
+
+/// How external tools are found and started
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tools {
+    /// Whether smv starts other programs at all
+    #[serde(default = "enabled")]
+    pub delegation: bool,
+    /// Absolute paths to use instead of a `PATH` lookup
+    #[serde(default)]
+    pub paths: BTreeMap<String, PathBuf>,
+    /// Environment variables passed on besides the usual few
+    #[serde(default)]
+    pub env: Vec<String>,
+}
+
+impl Default for Tools {
+    fn default() -> Self {
+        Self {
+            delegation: true,
+            paths: BTreeMap::new(),
+            env: Vec::new(),
+        }
+    }
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Tools {
+    /// The program to start for `tool`, checked before anything is spawned
+    pub fn resolve(&self, tool: &str) -> Result<PathBuf, Box<dyn Error>> {
+        if !self.delegation {
+            return Err(format!(
+                "Not starting {tool}: delegation is disabled ([tools] delegation = false in the config)"
+            )
+            .into());
+        }
+        if let Some(path) = self.paths.get(tool) {
+            if !path.is_absolute() {
+                return Err(format!(
+                    "[tools.paths] {tool} = \"{}\" must be an absolute path",
+                    path.display()
+                )
+                .into());
+            }
+            if !is_executable(path) {
+                return Err(format!(
+                    "{tool} is configured as {}, which is not an executable file",
+                    path.display()
+                )
+                .into());
+            }
+            return Ok(path.clone());
+        }
+        if tool.contains(std::path::MAIN_SEPARATOR) || tool.contains('/') {
+            let path = PathBuf::from(tool);
+            return if is_executable(&path) {
+                Ok(path)
+            } else {
+                Err(format!("{tool} is not an executable file").into())
+            };
+        }
+        find_in_path(tool).ok_or_else(|| {
+            format!(
+                "{tool} was not found on PATH; install it or set [tools.paths] {tool} = \"/absolute/path\" in the config"
+            )
+            .into()
+        })
+    }
+
+    /// A command for `tool` with the restricted environment
+    pub fn command(&self, tool: &str) -> Result<Command, Box<dyn Error>> {
+        let mut command = Command::new(self.resolve(tool)?);
+        command.env_clear();
+        let kept = KEPT_ENV
+            .iter()
+            .copied()
+            .chain(self.env.iter().map(String::as_str));
+        for name in kept {
+            if let Some(value) = std::env::var_os(name) {
+                command.env(name, value);
+            }
+        }
+        if !self.env.iter().any(|name| name == "PATH") {
+            match std::env::var_os("PATH") {
+                Some(path) if cfg!(windows) => command.env("PATH", path),
+                _ => command.env("PATH", SAFE_PATH),
+            };
+        }
+        Ok(command)
+    }
+}
+
+/// The first executable called `name` on `PATH`
+pub fn find_in_path(name: &str) -> Option<PathBuf> {
+    find_in(&std::env::var_os("PATH")?, name)
+}
+
+/// Relative entries (`.`, or an empty one) would pick up whatever sits in
+/// the current directory, so only absolute directories are searched
+fn find_in(path: &std::ffi::OsStr, name: &str) -> Option<PathBuf> {
+    std::env::split_paths(path)
+        .filter(|dir| dir.is_absolute())
+        .flat_map(|dir| {
+            let plain = dir.join(name);
+            let exe = dir.join(format!("{name}.exe"));
+            [plain, exe]
+        })
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Where the configuration file is read from
+pub fn config_path() -> Option<PathBuf> {
+    let env = |name: &str| {
+        std::env::var_os(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    if let Some(path) = env("SMV_CONFIG") {
+        return Some(path);
+    }
+    if let Some(smv_home) = env("SMV_HOME") {
+        return Some(smv_home.join("config.toml"));
+    }
+    env("XDG_CONFIG_HOME")
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))
+        .map(|base| base.join("smv").join("config.toml"))
+}
+
+impl Config {
+    /// Read the configuration file, if there is one
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        match config_path() {
+            Some(path) if path.is_file() => {
+                let text = fs::read_to_string(&path)?;
+                Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e).into())
+            }
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// The rule for `path`, from the deepest configured directory holding it
+    pub fn rule_for(&self, path: &Path) -> Option<(PathBuf, &DirRules)> {
+        let path = absolute(path);
+        self.dir
+            .iter()
+            .filter(|(_, rules)| !rules.allow_ext.is_empty())
+            .map(|(dir, rules)| (absolute(&expand_home(dir)), rules))
+            .filter(|(dir, _)| path.starts_with(dir))
+            .max_by_key(|(dir, _)| dir.components().count())
+    }
+
+    /// Refuse `paths` when any file among them (or below them) has a type
+    /// its directory does not allow
+    pub fn check(&self, paths: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+        if self.dir.is_empty() {
+            return Ok(());
+        }
+        let mut refused: BTreeMap<PathBuf, (Vec<String>, Vec<String>)> = BTreeMap::new();
+        for path in paths {
+            let files: Vec<PathBuf> = if path.is_dir() {
+                WalkDir::new(path)
+                    .into_iter()
+                    .filter_map(Result::ok)
+                    .filter(|entry| !entry.file_type().is_dir())
+                    .map(|entry| entry.into_path())
+                    .collect()
+            } else {
+                vec![path.clone()]
+            };
+            for file in files {
+                let Some((dir, rules)) = self.rule_for(&file) else {
+                    continue;
+                };
+                if !allowed(&file, &rules.allow_ext) {
+                    refused
+                        .entry(dir)
+                        .or_insert_with(|| (rules.allow_ext.clone(), Vec::new()))
+                        .1
+                        .push(file.display().to_string());
+                }
+            }
+        }
+
+        let Some((dir, (allowed, files))) = refused.into_iter().next() else {
+            return Ok(());
+        };
+        let mut shown: Vec<String> = files.iter().take(SHOWN_REFUSED).cloned().collect();
+        if files.len() > SHOWN_REFUSED {
+            shown.push(format!("and {} more", files.len() - SHOWN_REFUSED));
+        }
+        Err(format!(
+            "{} only allows {} files; refusing to touch {}. Use --any-ext to go ahead anyway",
+            dir.display(),
+            allowed.join(", "),
+            shown.join(", ")
+        )
+        .into())
+    }
+
+    /// [`Config::check`] for moving or copying `sources` to `destination`,
+    /// covering both where the files are and where they would land
+    pub fn check_transfer(
+        &self,
+        sources: &[PathBuf],
+        destination: &Path,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.dir.is_empty() {
+            return Ok(());
+        }
+        self.check(sources)?;
+        let into_dir = destination.is_dir() || sources.len() > 1;
+        let mut landed = Vec::new();
+        for source in sources {
+            let target = match source.file_name() {
+                Some(name) if into_dir => destination.join(name),
+                _ => destination.to_path_buf(),
+            };
+            if source.is_dir() {
+                landed.extend(
+                    WalkDir::new(source)
+                        .into_iter()
+                        .filter_map(Result::ok)
+                        .filter(|entry| !entry.file_type().is_dir())
+                        .filter_map(|entry| {
+                            let relative = entry.path().strip_prefix(source).ok()?;
+                            Some(target.join(relative))
+                        }),
+                );
+            } else {
+                landed.push(target);
+            }
+        }
+        self.check(&landed)
+    }
+}
+
+fn allowed(file: &Path, allow_ext: &[String]) -> bool {
+    file.extension().is_some_and(|ext| {
+        let ext = ext.to_string_lossy();
+        allow_ext
+            .iter()
+            .any(|allowed| allowed.trim_start_matches('.').eq_ignore_ascii_case(&ext))
+    })
+}
+
+pub(crate) fn expand_home(dir: &str) -> PathBuf {
+    match (dir.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(dir),
+    }
+}
+
+/// `path` made absolute, through symlinks where it exists
+fn absolute(path: &Path) -> PathBuf {
+    if let Ok(canonical) = path.canonicalize() {
+        return canonical;
+    }
+    // A destination that does not exist yet: resolve what does
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => absolute(parent).join(name),
+        _ => std::env::current_dir()
+            .map(|cwd| cwd.join(path))
+            .unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directory_rules() {
+        let temp = tempfile::tempdir().unwrap();
+        let pictures = temp.path().join("Pictures");
+        fs::create_dir_all(pictures.join("raw")).unwrap();
+        for name in ["a.JPG", "raw/b.dng", "notes.txt"] {
+            fs::write(pictures.join(name), "").unwrap();
+        }
+        let config = Config::parse(&format!(
+            "[dir.\"{}\"]\nallow_ext = [\"jpg\", \"png\", \"dng\"]\n",
+            pictures.display()
+        ))
+        .unwrap();
+
+        assert!(config.check(&[pictures.join("a.JPG")]).is_ok());
+        assert!(config.check(&[temp.path().join("elsewhere.exe")]).is_ok());
+        // Moving a stray type in is refused like touching one already there
+        assert!(config.check(&[pictures.join("setup.exe")]).is_err());
+        let error = config
+            .check(std::slice::from_ref(&pictures))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("notes.txt"), "{error}");
+        assert!(!error.contains("b.dng"), "{error}");
+
+        assert!(Config::parse("[dir.x]\nallow = []\n").is_err());
+        assert!(Config::parse("").unwrap().dir.is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_path_search_skips_relative_entries() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Created under the working directory so it can be named relatively
+        let temp = tempfile::tempdir_in(".").unwrap();
+        let relative = PathBuf::from(".").join(temp.path().file_name().unwrap());
+        fs::write(relative.join("tool"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(relative.join("tool"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        let search = std::env::join_paths([relative.as_path(), Path::new("")]).unwrap();
+        assert_eq!(find_in(&search, "tool"), None);
+        let absolute = std::env::join_paths([temp.path()]).unwrap();
+        assert!(find_in(&absolute, "tool").is_some());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_tool_resolution() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let tool = temp.path().join("dsc");
+        fs::write(&tool, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+        let config = Config::parse(&format!(
+            "[tools]\nenv = [\"SMV_TEST_VAR\"]\n[tools.paths]\ndsc = \"{}\"\nsay = \"bin/say\"\ndff = \"{}\"\n",
+            tool.display(),
+            temp.path().join("missing").display()
+        ))
+        .unwrap();
+        let tools = &config.tools;
+        assert!(tools.delegation);
+        assert_eq!(tools.resolve("dsc").unwrap(), tool);
+        assert!(
+            tools
+                .resolve("say")
+                .unwrap_err()
+                .to_string()
+                .contains("absolute")
+        );
+        assert!(tools.resolve("dff").is_err());
+
+        let command = tools.command("dsc").unwrap();
+        let envs: BTreeMap<_, _> = command.get_envs().collect();
+        assert_eq!(
+            envs.get(std::ffi::OsStr::new("PATH")).copied().flatten(),
+            Some(std::ffi::OsStr::new(SAFE_PATH))
+        );
+
+        let search = std::env::join_paths([temp.path()]).unwrap();
+        assert_eq!(find_in(&search, "dsc"), Some(tool.clone()));
+
+        let disabled = Config::parse("[tools]\ndelegation = false\n").unwrap();
+        assert!(
+            disabled
+                .tools
+                .resolve("dsc")
+                .unwrap_err()
+                .to_string()
+                .contains("disabled")
+        );
+    }
+}
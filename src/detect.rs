@@ -0,0 +1,313 @@
+//! Naming-convention report (`smv detect`)
+//!
+//! Walks a tree and sorts every file name in each directory into a naming
+//! style. The most common style is taken as the directory's convention, the
+//! names a transform to that style would change are its outliers, and the
+//! report ends with the `smv` commands that would rename them. Directories are
+//! judged on their own, since `src/` and `Photos/` rarely agree.
+
+use colored::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::transformers::{TransformType, transform};
+use crate::wizard::display_command;
+
+/// Outlier names listed per directory before "and N more"
+const SHOWN_OUTLIERS: usize = 5;
+
+/// How a file name (without its extension) is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Style {
+    Snake,
+    Kebab,
+    Camel,
+    Pascal,
+    Spaces,
+    /// One lowercase word, which fits snake, kebab and camel alike
+    Word,
+    /// Mixed separators or casing the other styles do not cover
+    Mixed,
+}
+
+impl Style {
+    /// Classify a file name by its stem
+    pub fn of(name: &str) -> Self {
+        let name = name.trim_start_matches('.');
+        let stem = match name.rfind('.') {
+            Some(dot) if dot > 0 => &name[..dot],
+            _ => name,
+        };
+        let has = |c: char| stem.contains(c);
+        let upper = stem.chars().any(char::is_uppercase);
+        let lower = stem.chars().any(char::is_lowercase);
+        let separators = [' ', '_', '-'].into_iter().filter(|&c| has(c)).count();
+
+        match separators {
+            0 => match stem.chars().find(|c| c.is_alphabetic()) {
+                _ if !upper => Style::Word,
+                Some(first) if first.is_lowercase() => Style::Camel,
+                Some(_) if lower => Style::Pascal,
+                _ => Style::Mixed,
+            },
+            1 if has(' ') => Style::Spaces,
+            1 if has('_') && !upper => Style::Snake,
+            1 if has('-') && !upper => Style::Kebab,
+            _ => Style::Mixed,
+        }
+    }
+
+    /// The transforms that produce this style, preferred first
+    fn transforms(self) -> &'static [TransformType] {
+        match self {
+            Style::Snake => &[TransformType::Snake],
+            Style::Kebab => &[TransformType::Kebab],
+            Style::Camel => &[TransformType::Camel],
+            Style::Pascal => &[TransformType::Pascal],
+            // title and sentence join the words, so only these keep the spaces
+            Style::Spaces => &[TransformType::Lower, TransformType::Start],
+            Style::Word | Style::Mixed => &[],
+        }
+    }
+}
+
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Style::Snake => "snake",
+            Style::Kebab => "kebab",
+            Style::Camel => "camel",
+            Style::Pascal => "pascal",
+            Style::Spaces => "spaces",
+            Style::Word => "word",
+            Style::Mixed => "mixed",
+        })
+    }
+}
+
+/// The naming styles of the files directly in one directory
+#[derive(Debug, Clone, Serialize)]
+pub struct DirectoryStyles {
+    pub directory: PathBuf,
+    pub files: usize,
+    pub counts: BTreeMap<Style, usize>,
+    /// The most common style that a transform can produce
+    pub dominant: Option<Style>,
+    /// The transform that renames the fewest files into the dominant style
+    #[serde(serialize_with = "transform_name")]
+    pub transform: Option<TransformType>,
+    /// Names the transform would change
+    pub outliers: Vec<String>,
+}
+
+fn transform_name<S: serde::Serializer>(
+    transform: &Option<TransformType>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match transform {
+        Some(transform) => serializer.serialize_some(&transform.as_str()),
+        None => serializer.serialize_none(),
+    }
+}
+
+impl DirectoryStyles {
+    pub fn from_names(directory: PathBuf, names: &[String]) -> Self {
+        let mut counts = BTreeMap::new();
+        for name in names {
+            *counts.entry(Style::of(name)).or_insert(0) += 1;
+        }
+        // Ties go to the style listed first
+        let dominant = counts
+            .iter()
+            .filter(|(style, _)| !style.transforms().is_empty())
+            .rev()
+            .max_by_key(|(_, count)| **count)
+            .map(|(style, _)| *style);
+
+        let changed = |transform_type: &TransformType| -> Vec<String> {
+            names
+                .iter()
+                .filter(|name| transform(name, transform_type) != **name)
+                .cloned()
+                .collect()
+        };
+        let (transform, outliers) = dominant
+            .and_then(|style| {
+                style
+                    .transforms()
+                    .iter()
+                    .map(|transform_type| (transform_type.clone(), changed(transform_type)))
+                    .min_by_key(|(_, outliers)| outliers.len())
+            })
+            .map_or((None, Vec::new()), |(t, outliers)| (Some(t), outliers));
+
+        Self {
+            directory,
+            files: names.len(),
+            counts,
+            dominant,
+            transform,
+            outliers,
+        }
+    }
+
+    /// The command that renames the outliers, when there are any
+    pub fn command(&self) -> Option<String> {
+        let transform = self
+            .transform
+            .as_ref()
+            .filter(|_| !self.outliers.is_empty())?;
+        Some(display_command(&[
+            transform.as_str(),
+            self.directory.display().to_string(),
+        ]))
+    }
+}
+
+/// Group the files under `root` by directory and classify their names
+///
+/// Hidden files and directories are left out unless `include_hidden` is set.
+pub fn detect(root: &Path, include_hidden: bool) -> Result<Vec<DirectoryStyles>, Box<dyn Error>> {
+    if !root.is_dir() {
+        return Err(format!("Not a directory: {}", root.display()).into());
+    }
+    let mut names: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+    let walker = WalkDir::new(root).into_iter().filter_entry(|entry| {
+        include_hidden
+            || entry.depth() == 0
+            || !entry.file_name().to_string_lossy().starts_with('.')
+    });
+    for entry in walker.filter_map(Result::ok) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let directory = entry.path().parent().unwrap_or(root).to_path_buf();
+        names
+            .entry(directory)
+            .or_default()
+            .push(entry.file_name().to_string_lossy().into_owned());
+    }
+    Ok(names
+        .into_iter()
+        .map(|(directory, names)| DirectoryStyles::from_names(directory, &names))
+        .collect())
+}
+
+/// Print the report with the commands at the end
+pub fn print(root: &Path, directories: &[DirectoryStyles]) {
+    let files: usize = directories.iter().map(|d| d.files).sum();
+    println!(
+        "{} {} ({} directories, {} files)",
+        "Naming styles in".bold(),
+        root.display(),
+        directories.len(),
+        files
+    );
+
+    for directory in directories {
+        println!(
+            "\n{} ({} files)",
+            directory.directory.display().to_string().cyan(),
+            directory.files
+        );
+        let mut counts: Vec<_> = directory.counts.iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (style, count) in counts {
+            println!(
+                "  {:<7} {:>5}  {:>3.0}%",
+                style.to_string(),
+                count,
+                *count as f64 * 100.0 / directory.files as f64
+            );
+        }
+        match (&directory.dominant, &directory.transform) {
+            (Some(style), Some(_)) if directory.outliers.is_empty() => {
+                println!("  {} every name is {style}", "Consistent:".green());
+            }
+            (Some(style), Some(_)) => {
+                let mut shown: Vec<String> = directory
+                    .outliers
+                    .iter()
+                    .take(SHOWN_OUTLIERS)
+                    .map(|name| format!("\"{name}\""))
+                    .collect();
+                if directory.outliers.len() > SHOWN_OUTLIERS {
+                    shown.push(format!(
+                        "and {} more",
+                        directory.outliers.len() - SHOWN_OUTLIERS
+                    ));
+                }
+                println!(
+                    "  {} {style}, except {}: {}",
+                    "Dominant:".yellow(),
+                    directory.outliers.len(),
+                    shown.join(", ")
+                );
+            }
+            _ => println!("  No dominant convention"),
+        }
+    }
+
+    let commands: Vec<String> = directories.iter().filter_map(|d| d.command()).collect();
+    if commands.is_empty() {
+        println!("\nNothing to normalize.");
+    } else {
+        println!("\n{} (add -p to preview)", "Normalize the outliers:".bold());
+        for command in commands {
+            println!("  {command}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_styles_of_names() {
+        assert_eq!(Style::of("my_notes.txt"), Style::Snake);
+        assert_eq!(Style::of("my-notes.md"), Style::Kebab);
+        assert_eq!(Style::of("myNotes.md"), Style::Camel);
+        assert_eq!(Style::of("MyNotes"), Style::Pascal);
+        assert_eq!(Style::of("My Notes.md"), Style::Spaces);
+        assert_eq!(Style::of("notes.tar.gz"), Style::Word);
+        assert_eq!(Style::of(".env"), Style::Word);
+        assert_eq!(Style::of("My_Notes-v2.md"), Style::Mixed);
+        assert_eq!(Style::of("README.md"), Style::Mixed);
+    }
+
+    #[test]
+    fn test_dominant_style_and_outliers() {
+        let dir = DirectoryStyles::from_names(
+            PathBuf::from("docs"),
+            &names(&["a-b.md", "c-d.md", "notes.md", "My File.md", "e_f.md"]),
+        );
+        assert_eq!(dir.dominant, Some(Style::Kebab));
+        assert_eq!(dir.transform, Some(TransformType::Kebab));
+        assert_eq!(dir.outliers, names(&["My File.md", "e_f.md"]));
+        assert_eq!(dir.command().as_deref(), Some("smv kebab docs"));
+
+        // Spaced names keep the casing most of them already have
+        let dir = DirectoryStyles::from_names(
+            PathBuf::from("My Books"),
+            &names(&["Dune Messiah.epub", "Old Man.epub", "the_road.epub"]),
+        );
+        assert_eq!(dir.dominant, Some(Style::Spaces));
+        assert_eq!(dir.transform, Some(TransformType::Start));
+        assert_eq!(dir.outliers, names(&["the_road.epub"]));
+        assert_eq!(dir.command().as_deref(), Some("smv start \"My Books\""));
+
+        let dir = DirectoryStyles::from_names(PathBuf::from("x"), &names(&["README", "a.txt"]));
+        assert_eq!(dir.dominant, None);
+        assert_eq!(dir.command(), None);
+    }
+}
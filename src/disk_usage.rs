@@ -0,0 +1,422 @@
+//! Disk-usage summaries for previews (`--du`)
+//!
+//! Given the moves an operation would make, shows how many bytes each source and
+//! destination directory holds before and after, and whether every destination
+//! filesystem has room for the data that has to be copied onto it. Before an
+//! `rm`, [`RemovalSummary`] shows how much is about to go and where it sits.
+
+use colored::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Format a byte count for humans ("1.5 GB")
+pub fn format_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{bytes} B")
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else if bytes < 1024 * 1024 * 1024 {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{:.1} GB", bytes as f64 / (1024.0 * 1024.0 * 1024.0))
+    }
+}
+
+fn format_delta(before: u64, after: u64) -> String {
+    match after.cmp(&before) {
+        std::cmp::Ordering::Greater => format!("+{}", format_size(after - before)),
+        std::cmp::Ordering::Less => format!("-{}", format_size(before - after)),
+        std::cmp::Ordering::Equal => "0 B".to_string(),
+    }
+}
+
+/// Total size of a file, or of everything below a directory
+pub fn path_size(path: &Path) -> u64 {
+    if path.is_dir() {
+        WalkDir::new(path)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| entry.metadata().ok())
+            .map(|metadata| metadata.len())
+            .sum()
+    } else {
+        fs::symlink_metadata(path).map(|m| m.len()).unwrap_or(0)
+    }
+}
+
+/// Bytes held by a directory before and after the planned moves
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectoryUsage {
+    pub path: PathBuf,
+    pub before: u64,
+    pub after: u64,
+}
+
+/// Data that has to be copied onto another filesystem
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilesystemUsage {
+    /// A destination directory on that filesystem
+    pub example: PathBuf,
+    pub incoming: u64,
+    pub available: Option<u64>,
+}
+
+impl FilesystemUsage {
+    pub fn fits(&self) -> bool {
+        self.available
+            .is_none_or(|available| self.incoming <= available)
+    }
+}
+
+/// The moves of one operation, with their sizes measured up front
+pub struct UsagePlan {
+    moves: Vec<(PathBuf, PathBuf, u64)>,
+}
+
+impl UsagePlan {
+    /// Measure `(source, destination)` pairs; call before anything is moved
+    pub fn new(moves: &[(PathBuf, PathBuf)]) -> Self {
+        Self {
+            moves: moves
+                .iter()
+                .map(|(source, destination)| {
+                    (source.clone(), destination.clone(), path_size(source))
+                })
+                .collect(),
+        }
+    }
+
+    /// Before/after totals for every directory a move leaves or enters
+    ///
+    /// Totals are recursive (like `du -s`), so moving a file between two
+    /// subdirectories leaves their common parent unchanged.
+    pub fn directory_changes(&self) -> Vec<DirectoryUsage> {
+        let directories: BTreeSet<PathBuf> = self
+            .moves
+            .iter()
+            .flat_map(|(source, destination, _)| [source.parent(), destination.parent()])
+            .flatten()
+            .map(Path::to_path_buf)
+            .collect();
+
+        directories
+            .into_iter()
+            .map(|dir| {
+                let before = if dir.exists() { path_size(&dir) } else { 0 };
+                let (mut incoming, mut outgoing) = (0, 0);
+                for (source, destination, bytes) in &self.moves {
+                    if destination.starts_with(&dir) {
+                        incoming += bytes;
+                    }
+                    if source.starts_with(&dir) {
+                        outgoing += bytes;
+                    }
+                }
+                DirectoryUsage {
+                    path: dir,
+                    before,
+                    after: (before + incoming).saturating_sub(outgoing),
+                }
+            })
+            .filter(|usage| usage.before != usage.after)
+            .collect()
+    }
+
+    /// Bytes that will be copied onto each destination filesystem
+    ///
+    /// Moves within one filesystem are renames and need no extra space.
+    pub fn filesystem_checks(&self) -> Vec<FilesystemUsage> {
+        let mut by_device: BTreeMap<u64, FilesystemUsage> = BTreeMap::new();
+        let mut device_cache: HashMap<PathBuf, Option<u64>> = HashMap::new();
+
+        for (source, destination, bytes) in &self.moves {
+            let Some(dest_dir) = destination.parent() else {
+                continue;
+            };
+            let dest_device = *device_cache
+                .entry(dest_dir.to_path_buf())
+                .or_insert_with(|| device_of(dest_dir));
+            let (Some(dest_device), Some(source_device)) = (dest_device, device_of(source)) else {
+                continue;
+            };
+            if dest_device == source_device {
+                continue;
+            }
+
+            by_device
+                .entry(dest_device)
+                .or_insert_with(|| FilesystemUsage {
+                    example: dest_dir.to_path_buf(),
+                    incoming: 0,
+                    available: available_bytes(dest_dir),
+                })
+                .incoming += bytes;
+        }
+
+        by_device.into_values().collect()
+    }
+
+    /// Print the `--du` summary
+    pub fn print(&self) {
+        println!("\n{}", "Disk usage after this operation:".bold());
+
+        let changes = self.directory_changes();
+        if changes.is_empty() {
+            println!("  No directory changes size.");
+        } else {
+            println!(
+                "  {:<40} {:>10} {:>10} {:>11}",
+                "Directory", "Before", "After", "Change"
+            );
+            for usage in &changes {
+                let delta = format_delta(usage.before, usage.after);
+                let delta = if usage.after > usage.before {
+                    delta.yellow()
+                } else {
+                    delta.green()
+                };
+                println!(
+                    "  {:<40} {:>10} {:>10} {:>11}",
+                    usage.path.display().to_string(),
+                    format_size(usage.before),
+                    format_size(usage.after),
+                    delta
+                );
+            }
+        }
+
+        for filesystem in self.filesystem_checks() {
+            let free = filesystem
+                .available
+                .map(format_size)
+                .unwrap_or_else(|| "unknown".to_string());
+            let line = format!(
+                "  Filesystem of {}: needs {}, {} free",
+                filesystem.example.display(),
+                format_size(filesystem.incoming),
+                free
+            );
+            if filesystem.fits() {
+                println!("{line}");
+            } else {
+                println!("{} {}", line.red(), "(NOT ENOUGH SPACE)".red().bold());
+            }
+        }
+    }
+}
+
+/// Extension groups listed before the rest are folded into one line
+const REMOVAL_EXTENSION_LINES: usize = 6;
+
+/// What a removal deletes: total size, size per extension, and the largest files
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemovalSummary {
+    pub files: usize,
+    pub bytes: u64,
+    /// Extension (empty for none), file count and bytes, largest first
+    pub extensions: Vec<(String, usize, u64)>,
+    /// The `largest` biggest files, biggest first
+    pub largest: Vec<(PathBuf, u64)>,
+}
+
+impl RemovalSummary {
+    /// Summarize `targets`, counting every file below the directories among them
+    pub fn new(targets: &[PathBuf], largest: usize) -> Self {
+        let mut sizes = Vec::new();
+        for target in targets {
+            for entry in WalkDir::new(target).into_iter().filter_map(Result::ok) {
+                if entry.file_type().is_dir() {
+                    continue;
+                }
+                let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                sizes.push((entry.into_path(), size));
+            }
+        }
+
+        let mut by_extension: HashMap<String, (usize, u64)> = HashMap::new();
+        for (path, size) in &sizes {
+            let extension = path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let group = by_extension.entry(extension).or_default();
+            group.0 += 1;
+            group.1 += size;
+        }
+        let mut extensions: Vec<(String, usize, u64)> = by_extension
+            .into_iter()
+            .map(|(extension, (count, bytes))| (extension, count, bytes))
+            .collect();
+        extensions.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+
+        sizes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let files = sizes.len();
+        let bytes = sizes.iter().map(|(_, size)| size).sum();
+        sizes.truncate(largest);
+
+        Self {
+            files,
+            bytes,
+            extensions,
+            largest: sizes,
+        }
+    }
+
+    /// Print the summary shown above the rm confirmation
+    pub fn print(&self) {
+        println!(
+            "Total: {} in {} files",
+            format_size(self.bytes).yellow().bold(),
+            self.files
+        );
+        if self.files == 0 {
+            return;
+        }
+
+        println!("By type:");
+        for (extension, count, bytes) in self.extensions.iter().take(REMOVAL_EXTENSION_LINES) {
+            let label = if extension.is_empty() {
+                "(none)".to_string()
+            } else {
+                format!(".{extension}")
+            };
+            println!(
+                "  {:<12} {:>10}  {} files",
+                label,
+                format_size(*bytes),
+                count
+            );
+        }
+        let rest = &self.extensions[self.extensions.len().min(REMOVAL_EXTENSION_LINES)..];
+        if !rest.is_empty() {
+            let count: usize = rest.iter().map(|(_, count, _)| count).sum();
+            let bytes: u64 = rest.iter().map(|(_, _, bytes)| bytes).sum();
+            println!(
+                "  {:<12} {:>10}  {} files",
+                format!("{} more", rest.len()),
+                format_size(bytes),
+                count
+            );
+        }
+
+        if !self.largest.is_empty() {
+            println!("Largest:");
+            for (path, size) in &self.largest {
+                println!("  {:>10}  {}", format_size(*size), path.display());
+            }
+        }
+    }
+}
+
+/// Device id of a path, or of its nearest existing ancestor
+#[cfg(unix)]
+pub fn device_of(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    path.ancestors()
+        .find_map(|ancestor| fs::metadata(ancestor).ok())
+        .map(|metadata| metadata.dev())
+}
+
+#[cfg(not(unix))]
+pub fn device_of(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Free space available to unprivileged users on the filesystem holding `path`
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)] // field widths differ between platforms
+fn available_bytes(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let existing = path.ancestors().find(|ancestor| ancestor.exists())?;
+    let c_path = std::ffi::CString::new(existing.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn available_bytes(_path: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directory_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::write(root.join("a").join("one.bin"), vec![0u8; 100]).unwrap();
+        fs::write(root.join("a").join("two.bin"), vec![0u8; 50]).unwrap();
+
+        let plan = UsagePlan::new(&[(
+            root.join("a").join("one.bin"),
+            root.join("b").join("one.bin"),
+        )]);
+        let changes = plan.directory_changes();
+
+        assert_eq!(
+            changes,
+            vec![
+                DirectoryUsage {
+                    path: root.join("a"),
+                    before: 150,
+                    after: 50,
+                },
+                DirectoryUsage {
+                    path: root.join("b"),
+                    before: 0,
+                    after: 100,
+                },
+            ]
+        );
+        // Same filesystem: nothing to copy
+        assert!(plan.filesystem_checks().is_empty());
+    }
+
+    #[test]
+    fn test_removal_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("logs")).unwrap();
+        fs::write(root.join("logs/a.log"), vec![0u8; 300]).unwrap();
+        fs::write(root.join("logs/b.LOG"), vec![0u8; 100]).unwrap();
+        fs::write(root.join("big.iso"), vec![0u8; 1000]).unwrap();
+        fs::write(root.join("README"), vec![0u8; 10]).unwrap();
+
+        let summary = RemovalSummary::new(
+            &[root.join("logs"), root.join("big.iso"), root.join("README")],
+            2,
+        );
+        assert_eq!(summary.files, 4);
+        assert_eq!(summary.bytes, 1410);
+        assert_eq!(
+            summary.extensions,
+            [
+                ("iso".to_string(), 1, 1000),
+                ("log".to_string(), 2, 400),
+                (String::new(), 1, 10),
+            ]
+        );
+        assert_eq!(
+            summary.largest,
+            [(root.join("big.iso"), 1000), (root.join("logs/a.log"), 300)]
+        );
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_delta(100, 40), "-60 B");
+    }
+}
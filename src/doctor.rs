@@ -0,0 +1,274 @@
+//! Environment diagnostics (`smv doctor --env`)
+//!
+//! Many problems that look like bugs come from the machine smv runs on: a
+//! state directory it cannot write, a peer tool missing from `PATH`, a
+//! terminal without color, or a filesystem that folds case or limits name
+//! length. Each check gives a finding and, when something is off, what to do
+//! about it. Probes of the target directory create a file or two there and
+//! remove them again.
+
+use colored::*;
+use serde::Serialize;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::Path;
+
+use crate::config;
+use crate::paths;
+
+/// Tools smv hands work to, and what for
+const PEERS: &[(&str, &str)] = &[
+    ("dsc", "glob patterns in transforms and TO:dsc"),
+    ("say", "TO:say delegation"),
+    ("dff", "TO:dff duplicate finding"),
+    ("fzf", "--pick selection"),
+    ("ffprobe", "video metadata ({width}, {codec}, RES>1080p)"),
+];
+
+/// The longest path Windows accepts without long-path support
+const MAX_PATH: usize = 260;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    /// Works, with less than smv can do
+    Warn,
+    /// Something smv needs is broken
+    Fail,
+}
+
+/// The outcome of one check
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub check: String,
+    pub status: Status,
+    pub detail: String,
+    /// What to do about it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+}
+
+impl Finding {
+    fn new(check: impl Into<String>, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            check: check.into(),
+            status,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn fix(mut self, fix: impl Into<String>) -> Self {
+        self.fix = Some(fix.into());
+        self
+    }
+}
+
+/// Run every check, probing `target` for filesystem behavior
+pub fn check_environment(target: &Path) -> Vec<Finding> {
+    let mut findings = vec![check_state_dir(&paths::state_dir()), check_config()];
+    let tools = config::Config::load().unwrap_or_default().tools;
+    findings.extend(
+        PEERS
+            .iter()
+            .map(|(tool, used_for)| check_peer(&tools, tool, used_for)),
+    );
+    findings.push(check_terminal());
+    findings.push(check_case_sensitivity(target));
+    findings.push(check_long_paths(target));
+    findings
+}
+
+/// Whether the undo history can be written
+pub fn check_state_dir(dir: &Path) -> Finding {
+    let check = "state directory";
+    let probe = dir.join(".smv-doctor");
+    let result = fs::create_dir_all(dir)
+        .and_then(|()| fs::write(&probe, b"probe"))
+        .and_then(|()| fs::remove_file(&probe));
+    match result {
+        Ok(()) => Finding::new(check, Status::Ok, format!("{} is writable", dir.display())),
+        Err(e) => Finding::new(
+            check,
+            Status::Fail,
+            format!("cannot write {}: {}", dir.display(), e),
+        )
+        .fix("point --state-dir or SMV_STATE_DIR at a writable directory; without it nothing can be undone"),
+    }
+}
+
+fn check_config() -> Finding {
+    let check = "config file";
+    let Some(path) = config::config_path().filter(|path| path.is_file()) else {
+        return Finding::new(check, Status::Ok, "none (defaults apply)");
+    };
+    match config::Config::load() {
+        Ok(config) => Finding::new(
+            check,
+            Status::Ok,
+            format!("{} ({} directory rules)", path.display(), config.dir.len()),
+        ),
+        Err(e) => Finding::new(check, Status::Fail, e.to_string())
+            .fix("fix the file; every command that reads it stops on this error"),
+    }
+}
+
+fn check_peer(tools: &config::Tools, tool: &str, used_for: &str) -> Finding {
+    let check = format!("peer: {tool}");
+    match tools.resolve(tool) {
+        Ok(path) if tools.paths.contains_key(tool) => Finding::new(
+            check,
+            Status::Ok,
+            format!("{} (from the config)", path.display()),
+        ),
+        Ok(path) => Finding::new(check, Status::Ok, path.display().to_string()),
+        Err(e) => Finding::new(check, Status::Warn, format!("{e}; needed for {used_for}")).fix(
+            format!("install {tool}, or set its path under [tools.paths], if you use it"),
+        ),
+    }
+}
+
+fn check_terminal() -> Finding {
+    let check = "terminal";
+    let term = std::env::var("TERM").unwrap_or_default();
+    if !std::io::stdout().is_terminal() {
+        return Finding::new(
+            check,
+            Status::Ok,
+            "output is not a terminal; colors and the TUI are off",
+        );
+    }
+    let size = crossterm::terminal::size()
+        .map(|(columns, rows)| format!("{columns}x{rows}"))
+        .unwrap_or_else(|_| String::from("unknown size"));
+    if term.is_empty() || term == "dumb" {
+        return Finding::new(
+            check,
+            Status::Warn,
+            format!("TERM is {:?} ({size}); the TUI may not draw", term),
+        )
+        .fix("set TERM to your terminal's type, e.g. xterm-256color");
+    }
+    let color = if std::env::var_os("NO_COLOR").is_some() {
+        "colors off (NO_COLOR)"
+    } else if std::env::var("COLORTERM").is_ok_and(|c| c == "truecolor" || c == "24bit") {
+        "true color"
+    } else {
+        "colors on"
+    };
+    Finding::new(check, Status::Ok, format!("{term}, {size}, {color}"))
+}
+
+/// Whether names differing only in case are different files under `target`
+pub fn check_case_sensitivity(target: &Path) -> Finding {
+    let check = "case sensitivity";
+    let lower = target.join(".smv-doctor-case");
+    let upper = target.join(".SMV-DOCTOR-CASE");
+    if let Err(e) = fs::write(&lower, b"probe") {
+        return Finding::new(
+            check,
+            Status::Warn,
+            format!("cannot write to {}: {}", target.display(), e),
+        )
+        .fix("run doctor on a directory you can write to");
+    }
+    let folds = upper.exists();
+    let _ = fs::remove_file(&lower);
+    if folds {
+        Finding::new(
+            check,
+            Status::Warn,
+            format!(
+                "{} ignores case: File.txt and file.txt are one file",
+                target.display()
+            ),
+        )
+        .fix("names that differ only in case collide here; smv reports them as conflicts")
+    } else {
+        Finding::new(
+            check,
+            Status::Ok,
+            format!("{} is case-sensitive", target.display()),
+        )
+    }
+}
+
+/// Whether `target` takes 255-byte names and paths past Windows' 260 characters
+pub fn check_long_paths(target: &Path) -> Finding {
+    let check = "long paths";
+    let root = target.join(".smv-doctor-long");
+    let name = "n".repeat(255);
+    let mut deep = root.clone();
+    while deep.as_os_str().len() <= MAX_PATH {
+        deep.push("d".repeat(60));
+    }
+    let result = fs::create_dir_all(&root)
+        .and_then(|()| fs::write(root.join(&name), b"probe"))
+        .map_err(|e| ("255-byte file names", e))
+        .and_then(|()| fs::create_dir_all(&deep).map_err(|e| ("paths over 260 characters", e)));
+    let _ = fs::remove_dir_all(&root);
+    match result {
+        Ok(()) => Finding::new(
+            check,
+            Status::Ok,
+            "255-byte names and paths over 260 characters work",
+        ),
+        Err((what, e)) => Finding::new(
+            check,
+            Status::Warn,
+            format!("{} fail under {}: {}", what, target.display(), e),
+        )
+        .fix(if cfg!(windows) {
+            "enable long paths (LongPathsEnabled in the registry) or keep trees shallow"
+        } else {
+            "keep names short here; transforms that lengthen names will fail"
+        }),
+    }
+}
+
+/// Print the findings, problems with their fixes
+pub fn print(findings: &[Finding]) {
+    println!("{}", "CNP Smart Move - Environment".bold());
+    for finding in findings {
+        let mark = match finding.status {
+            Status::Ok => "ok  ".green(),
+            Status::Warn => "warn".yellow(),
+            Status::Fail => "FAIL".red().bold(),
+        };
+        println!("  {} {:<18} {}", mark, finding.check, finding.detail);
+        if let Some(fix) = &finding.fix {
+            println!("       {:<18} {} {}", "", "→".cyan(), fix);
+        }
+    }
+    let count = |status| findings.iter().filter(|f| f.status == status).count();
+    println!(
+        "\n{} problems, {} warnings",
+        count(Status::Fail),
+        count(Status::Warn)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filesystem_probes_clean_up() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            check_state_dir(&dir.path().join("state")).status,
+            Status::Ok
+        );
+        let blocked = dir.path().join("file");
+        fs::write(&blocked, "").unwrap();
+        let finding = check_state_dir(&blocked.join("state"));
+        assert_eq!(finding.status, Status::Fail);
+        assert!(finding.fix.is_some());
+
+        check_case_sensitivity(dir.path());
+        check_long_paths(dir.path());
+        let left: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(left.len(), 2, "probes left files behind: {left:?}");
+    }
+}
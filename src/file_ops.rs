@@ -1,12 +1,21 @@
+use std::cell::RefCell;
 use std::error::Error;
 use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::time::SystemTime;
 
 use colored::*;
 use walkdir::WalkDir;
 
+use crate::attr;
+use crate::history::Trash;
+use crate::output::{self, Op};
+use crate::progress::Progress;
+use crate::protect::Protection;
+use crate::vfs::Vfs;
+
 #[derive(Debug, Clone, Default)]
 pub struct FileOpConfig {
     pub recursive: bool,
@@ -17,6 +26,245 @@ pub struct FileOpConfig {
     pub dereference_symlinks: bool,
     pub follow_symlinks: bool,
     pub verbose: bool,
+    pub layout: DestinationLayout,
+    /// Heartbeats and checkpoints for `--heartbeat` / `--output json`
+    pub progress: Option<Rc<Progress>>,
+    /// Where `rm` puts removed items so undo can restore them; `None` deletes for good
+    pub trash: Option<Rc<RefCell<Trash>>>,
+    /// `-x`: leave directories mounted from other filesystems alone when recursing
+    pub one_file_system: bool,
+    /// What a batch does when one of its items fails
+    pub on_error: OnError,
+}
+
+/// What a batch does when one of its items fails (`--on-error`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnError {
+    /// Report the error and go on with the rest
+    #[default]
+    Continue,
+    /// Stop at the first error and keep what was done
+    Abort,
+    /// Stop at the first error and reverse what the batch did
+    Rollback,
+    /// Ask which of the above to do
+    Prompt,
+}
+
+impl OnError {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "continue" => Ok(OnError::Continue),
+            "abort" => Ok(OnError::Abort),
+            "rollback" => Ok(OnError::Rollback),
+            "prompt" => Ok(OnError::Prompt),
+            _ => Err(format!(
+                "Unknown --on-error policy '{value}' (expected continue, abort, rollback or prompt)"
+            )),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            OnError::Continue => "continue",
+            OnError::Abort => "abort",
+            OnError::Rollback => "rollback",
+            OnError::Prompt => "prompt",
+        }
+    }
+}
+
+/// One completed step of a [`Batch`]
+#[derive(Debug, Clone)]
+enum Step {
+    Moved {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Copied(PathBuf),
+    /// Deleted for good; nothing can bring it back
+    Deleted(PathBuf),
+}
+
+/// Applies `--on-error` to a batch of moves, copies or removals
+///
+/// Commands note each completed step and ask [`Batch::keep_going`] after each
+/// failure. A rollback reverses the completed steps newest first: moves go
+/// back, copies are deleted and removed items come back out of the trash.
+#[derive(Debug)]
+pub struct Batch {
+    on_error: OnError,
+    steps: Vec<Step>,
+    trash: Option<Rc<RefCell<Trash>>>,
+    rolled_back: bool,
+}
+
+impl Batch {
+    pub fn new(on_error: OnError) -> Self {
+        Self {
+            on_error,
+            steps: Vec::new(),
+            trash: None,
+            rolled_back: false,
+        }
+    }
+
+    /// Bring removals back out of `trash` on a rollback
+    pub fn with_trash(mut self, trash: Option<Rc<RefCell<Trash>>>) -> Self {
+        self.trash = trash;
+        self
+    }
+
+    pub fn moved(&mut self, from: &Path, to: &Path) {
+        self.steps.push(Step::Moved {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        });
+    }
+
+    /// A copy made at `to`; copies that `replaced` a file are kept on a
+    /// rollback, since the file they replaced is gone either way
+    pub fn copied(&mut self, to: &Path, replaced: bool) {
+        if !replaced {
+            self.steps.push(Step::Copied(to.to_path_buf()));
+        }
+    }
+
+    /// A removal that bypassed the trash
+    pub fn deleted(&mut self, path: &Path) {
+        self.steps.push(Step::Deleted(path.to_path_buf()));
+    }
+
+    /// Whether to go on after an item failed (and was reported)
+    ///
+    /// Rolls the batch back through `fs` first when that is the policy or
+    /// the answer to the prompt.
+    pub fn keep_going(&mut self, fs: &mut dyn Vfs) -> bool {
+        let decision = match self.on_error {
+            OnError::Prompt => prompt_on_error(),
+            policy => policy,
+        };
+        match decision {
+            OnError::Continue | OnError::Prompt => return true,
+            OnError::Abort => eprintln!(
+                "{}: stopped at the first error (--on-error {}); what was done is kept",
+                "Note".yellow(),
+                self.on_error.as_str()
+            ),
+            OnError::Rollback => self.roll_back(fs),
+        }
+        false
+    }
+
+    /// Whether the batch was rolled back, leaving nothing to record
+    pub fn rolled_back(&self) -> bool {
+        self.rolled_back
+    }
+
+    fn roll_back(&mut self, fs: &mut dyn Vfs) {
+        self.rolled_back = true;
+        let mut reversed = 0;
+        let mut lost = Vec::new();
+        for step in self.steps.drain(..).rev() {
+            let (path, result) = match &step {
+                Step::Moved { from, to } => (
+                    to,
+                    from.parent()
+                        .map_or(Ok(()), |parent| fs.create_dir_all(parent))
+                        .and_then(|()| fs.rename(to, from)),
+                ),
+                Step::Copied(to) => (to, fs.remove(to)),
+                Step::Deleted(path) => {
+                    lost.push(path.display().to_string());
+                    continue;
+                }
+            };
+            match result {
+                Ok(()) => reversed += 1,
+                Err(e) => eprintln!(
+                    "{}: could not roll back '{}': {}",
+                    "Error".red(),
+                    path.display(),
+                    e
+                ),
+            }
+        }
+        if let Some(trash) = &self.trash {
+            match trash.borrow_mut().restore_all() {
+                Ok(restored) => reversed += restored,
+                Err(e) => eprintln!(
+                    "{}: could not restore every removed item ({}); the rest stay in the trash for smv -u",
+                    "Error".red(),
+                    e
+                ),
+            }
+        }
+        eprintln!(
+            "{}: rolled back {} completed item(s) (--on-error {})",
+            "Note".yellow(),
+            reversed,
+            self.on_error.as_str()
+        );
+        if !lost.is_empty() {
+            eprintln!(
+                "{}: removed with --permanent, so not brought back: {}",
+                "Warning".yellow(),
+                lost.join(", ")
+            );
+        }
+    }
+}
+
+/// Where a source lands when the destination is a directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DestinationLayout {
+    /// `dest/name`
+    #[default]
+    Flat,
+    /// The source path as given below `dest`, minus its first `strip` components
+    ///
+    /// `--parents` keeps every component (like `cp --parents`); `--strip-components N`
+    /// drops N like tar. The file name itself is never stripped.
+    Parents { strip: usize },
+}
+
+/// The path `source` is moved or copied to for `destination`
+pub fn destination_for(
+    source: &Path,
+    destination: &Path,
+    dest_is_dir: bool,
+    layout: DestinationLayout,
+) -> PathBuf {
+    match layout {
+        DestinationLayout::Flat if dest_is_dir => {
+            destination.join(source.file_name().unwrap_or_default())
+        }
+        DestinationLayout::Flat => destination.to_path_buf(),
+        DestinationLayout::Parents { strip } => {
+            // Only plain names count: "./", "../" and the root never reach the destination
+            let components: Vec<_> = source
+                .components()
+                .filter_map(|component| match component {
+                    std::path::Component::Normal(name) => Some(name),
+                    _ => None,
+                })
+                .collect();
+            let keep_from = strip.min(components.len().saturating_sub(1));
+            destination.join(components[keep_from..].iter().collect::<PathBuf>())
+        }
+    }
+}
+
+/// The directories `dir` needs created, outermost first
+pub fn missing_directories(dir: &Path) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = dir
+        .ancestors()
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .take_while(|ancestor| ancestor.symlink_metadata().is_err())
+        .map(Path::to_path_buf)
+        .collect();
+    missing.reverse();
+    missing
 }
 
 #[derive(Debug, Clone, Default)]
@@ -28,6 +276,18 @@ pub struct FileOpStats {
     pub skipped: u32,
 }
 
+impl FileOpStats {
+    /// The `--json` totals; `done` counts what was moved, copied or created
+    pub fn totals(&self) -> output::Totals {
+        output::Totals {
+            processed: self.processed,
+            done: self.moved + self.copied,
+            skipped: self.skipped,
+            errors: self.errors,
+        }
+    }
+}
+
 pub fn move_files(
     sources: &[PathBuf],
     destination: &Path,
@@ -35,16 +295,28 @@ pub fn move_files(
 ) -> Result<FileOpStats, Box<dyn Error>> {
     let mut stats = FileOpStats::default();
     let dest_is_dir = destination.is_dir();
+    let mut batch = Batch::new(config.on_error);
+    let mut protection = Protection::new();
 
     for source in sources {
         stats.processed += 1;
 
-        let dest_path = if dest_is_dir {
-            destination.join(source.file_name().unwrap_or_default())
-        } else {
-            destination.to_path_buf()
-        };
+        let dest_path = destination_for(source, destination, dest_is_dir, config.layout);
 
+        if let Some(reason) = protection.reason(source) {
+            if output::json() {
+                output::skipped(Op::Move, source, &format!("protected: {reason}"));
+            } else {
+                println!(
+                    "{} {} (protected: {})",
+                    "Skipping".yellow(),
+                    source.display(),
+                    reason
+                );
+            }
+            stats.skipped += 1;
+            continue;
+        }
         if let Err(e) = move_single_item(source, &dest_path, config) {
             eprintln!(
                 "{}: Failed to move {}: {}",
@@ -52,11 +324,33 @@ pub fn move_files(
                 source.display(),
                 e
             );
+            output::failed(Op::Move, Some(source), Some(&dest_path), &e.to_string());
+            if let Some(progress) = &config.progress {
+                progress.file_failed();
+            }
             stats.errors += 1;
+            if !batch.keep_going(&mut DiskFs::default()) {
+                break;
+            }
         } else {
             stats.moved += 1;
+            // Skipped items (-n, a declined overwrite) are still at the source
+            if source.symlink_metadata().is_err() {
+                batch.moved(source, &dest_path);
+                output::done(Op::Move, Some(source), Some(&dest_path));
+            } else {
+                output::conflict(
+                    Op::Move,
+                    source,
+                    &dest_path,
+                    crate::plan::Conflict::DestinationExists,
+                );
+            }
         }
     }
+    if batch.rolled_back() {
+        stats.moved = 0;
+    }
 
     Ok(stats)
 }
@@ -68,15 +362,13 @@ pub fn copy_files(
 ) -> Result<FileOpStats, Box<dyn Error>> {
     let mut stats = FileOpStats::default();
     let dest_is_dir = destination.is_dir();
+    let mut batch = Batch::new(config.on_error);
 
     for source in sources {
         stats.processed += 1;
 
-        let dest_path = if dest_is_dir {
-            destination.join(source.file_name().unwrap_or_default())
-        } else {
-            destination.to_path_buf()
-        };
+        let dest_path = destination_for(source, destination, dest_is_dir, config.layout);
+        let replaced = dest_path.symlink_metadata().is_ok();
 
         match copy_single_item(source, &dest_path, config) {
             Ok(item_stats) => {
@@ -84,6 +376,10 @@ pub fn copy_files(
                 stats.processed += item_stats.processed - 1; // -1 because we already counted this in the outer loop
                 stats.errors += item_stats.errors;
                 stats.skipped += item_stats.skipped;
+                if item_stats.copied > 0 {
+                    batch.copied(&dest_path, replaced);
+                    output::done(Op::Copy, Some(source), Some(&dest_path));
+                }
             }
             Err(e) => {
                 eprintln!(
@@ -92,10 +388,20 @@ pub fn copy_files(
                     source.display(),
                     e
                 );
+                output::failed(Op::Copy, Some(source), Some(&dest_path), &e.to_string());
+                if let Some(progress) = &config.progress {
+                    progress.file_failed();
+                }
                 stats.errors += 1;
+                if !batch.keep_going(&mut DiskFs::default()) {
+                    break;
+                }
             }
         }
     }
+    if batch.rolled_back() {
+        stats.copied = 0;
+    }
 
     Ok(stats)
 }
@@ -121,7 +427,7 @@ fn move_single_item(
 
     if source.is_dir() {
         if config.recursive {
-            move_directory_recursive(source, destination, config)?;
+            move_directory_recursive(source, destination, destination, config)?;
         } else {
             return Err(format!(
                 "Source is a directory, use -r flag for recursive move: {}",
@@ -131,8 +437,10 @@ fn move_single_item(
         }
     } else if source.is_file() {
         move_file(source, destination, config)?;
+        file_done(config);
     } else if source.is_symlink() {
         move_symlink(source, destination, config)?;
+        file_done(config);
     } else {
         return Err(format!("Unsupported file type: {}", source.display()).into());
     }
@@ -169,7 +477,8 @@ fn copy_single_item(
 
     if source.is_dir() {
         if config.recursive {
-            let recursive_stats = copy_directory_recursive(source, destination, config)?;
+            let recursive_stats =
+                copy_directory_recursive(source, destination, destination, config)?;
             return Ok(recursive_stats);
         } else {
             return Err(format!(
@@ -180,8 +489,10 @@ fn copy_single_item(
         }
     } else if source.is_file() {
         copy_file(source, destination, config)?;
+        file_done(config);
     } else if source.is_symlink() {
         copy_symlink(source, destination, config)?;
+        file_done(config);
     } else {
         return Err(format!("Unsupported file type: {}", source.display()).into());
     }
@@ -199,7 +510,7 @@ fn move_file(
     config: &FileOpConfig,
 ) -> Result<(), Box<dyn Error>> {
     if let Some(parent) = destination.parent() {
-        fs::create_dir_all(parent)?;
+        attr::create_dir_all(parent)?;
     }
 
     fs::rename(source, destination)?;
@@ -207,28 +518,183 @@ fn move_file(
     if config.preserve_metadata {
         preserve_metadata(source, destination)?;
     }
+    log_moved(config, source, destination);
 
     Ok(())
 }
 
+/// How [`move_file_verified`] got a file to its destination
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MoveKind {
+    /// Same filesystem: a plain rename
+    Renamed,
+    /// Different filesystem: copied, verified and the source removed
+    Copied,
+}
+
+/// Move a file, falling back to copy + verify + delete when the destination is on another filesystem
+///
+/// The source is only removed once the copy reads back byte-for-byte identical.
+pub fn move_file_verified(source: &Path, destination: &Path) -> Result<MoveKind, Box<dyn Error>> {
+    if let Some(parent) = destination.parent() {
+        attr::create_dir_all(parent)?;
+    }
+
+    match fs::rename(source, destination) {
+        Ok(()) => return Ok(MoveKind::Renamed),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    fs::copy(source, destination)?;
+    // Keep timestamps so age-based filters still see the original times
+    preserve_metadata(source, destination)?;
+
+    if !files_identical(source, destination)? {
+        let _ = fs::remove_file(destination);
+        return Err(format!(
+            "Verification failed, kept source: {}",
+            destination.display()
+        )
+        .into());
+    }
+
+    fs::remove_file(source)?;
+    Ok(MoveKind::Copied)
+}
+
+/// Compare two files byte-for-byte
+fn files_identical(a: &Path, b: &Path) -> Result<bool, Box<dyn Error>> {
+    use std::io::Read;
+
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+
+    let mut reader_a = io::BufReader::new(fs::File::open(a)?);
+    let mut reader_b = io::BufReader::new(fs::File::open(b)?);
+    let mut buf_a = vec![0u8; 64 * 1024];
+    let mut buf_b = vec![0u8; 64 * 1024];
+
+    loop {
+        let read = reader_a.read(&mut buf_a)?;
+        if read == 0 {
+            return Ok(true);
+        }
+        reader_b.read_exact(&mut buf_b[..read])?;
+        if buf_a[..read] != buf_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
 fn copy_file(
     source: &Path,
     destination: &Path,
     config: &FileOpConfig,
 ) -> Result<(), Box<dyn Error>> {
     if let Some(parent) = destination.parent() {
-        fs::create_dir_all(parent)?;
+        attr::create_dir_all(parent)?;
     }
 
     fs::copy(source, destination)?;
 
     if config.preserve_metadata {
         preserve_metadata(source, destination)?;
+    } else {
+        apply_umask(destination)?;
     }
+    log_copied(config, source, destination);
+
+    Ok(())
+}
 
+/// Move or copy one planned file with the mv/cp options
+///
+/// Moves fall back to copy + verify across filesystems; copies keep metadata only
+/// with `--preserve`, as `cp` does.
+pub fn transfer_file(
+    source: &Path,
+    destination: &Path,
+    copy: bool,
+    config: &FileOpConfig,
+) -> Result<(), Box<dyn Error>> {
+    if copy {
+        return copy_file(source, destination, config);
+    }
+    move_file_verified(source, destination)?;
+    log_moved(config, source, destination);
     Ok(())
 }
 
+fn file_done(config: &FileOpConfig) {
+    if let Some(progress) = &config.progress {
+        progress.file_done();
+    }
+}
+
+/// `-v` output for moves and renames, in the style of `mv -v`
+fn log_moved(config: &FileOpConfig, source: &Path, destination: &Path) {
+    if config.verbose {
+        eprintln!(
+            "renamed '{}' -> '{}'",
+            source.display(),
+            destination.display()
+        );
+    }
+}
+
+/// `-v` output for copies, in the style of `cp -v`
+fn log_copied(config: &FileOpConfig, source: &Path, destination: &Path) {
+    if config.verbose {
+        eprintln!("'{}' -> '{}'", source.display(), destination.display());
+    }
+}
+
+/// The disk as a [`Vfs`], so transforms rename and copy with the mv/cp options
+#[derive(Debug, Clone, Default)]
+pub struct DiskFs {
+    config: FileOpConfig,
+}
+
+impl DiskFs {
+    pub fn new(config: FileOpConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl Vfs for DiskFs {
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)?;
+        log_moved(&self.config, from, to);
+        Ok(())
+    }
+
+    fn copy(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        copy_file(from, to, &self.config).map_err(|e| io::Error::other(e.to_string()))
+    }
+
+    fn create_dir_all(&mut self, path: &Path) -> io::Result<()> {
+        attr::create_dir_all(path)
+    }
+
+    fn remove(&mut self, path: &Path) -> io::Result<()> {
+        if path.is_dir() && !path.is_symlink() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        }
+    }
+}
+
 fn move_symlink(
     source: &Path,
     destination: &Path,
@@ -245,12 +711,12 @@ fn move_symlink(
         if resolved_target.is_file() {
             move_file(&resolved_target, destination, config)?;
         } else if resolved_target.is_dir() && config.recursive {
-            move_directory_recursive(&resolved_target, destination, config)?;
+            move_directory_recursive(&resolved_target, destination, destination, config)?;
         }
     } else {
         let target = fs::read_link(source)?;
         if let Some(parent) = destination.parent() {
-            fs::create_dir_all(parent)?;
+            attr::create_dir_all(parent)?;
         }
 
         #[cfg(unix)]
@@ -285,12 +751,12 @@ fn copy_symlink(
         if resolved_target.is_file() {
             copy_file(&resolved_target, destination, config)?;
         } else if resolved_target.is_dir() && config.recursive {
-            copy_directory_recursive(&resolved_target, destination, config)?;
+            copy_directory_recursive(&resolved_target, destination, destination, config)?;
         }
     } else {
         let target = fs::read_link(source)?;
         if let Some(parent) = destination.parent() {
-            fs::create_dir_all(parent)?;
+            attr::create_dir_all(parent)?;
         }
 
         #[cfg(unix)]
@@ -307,12 +773,23 @@ fn copy_symlink(
     Ok(())
 }
 
+/// Move a directory entry by entry; false when part of it had to stay in place
+///
+/// `output` is the top-level destination. When it lies inside the source it is
+/// skipped, so the walk never moves the tree into itself.
 fn move_directory_recursive(
     source: &Path,
     destination: &Path,
+    output: &Path,
     config: &FileOpConfig,
-) -> Result<(), Box<dyn Error>> {
-    fs::create_dir_all(destination)?;
+) -> Result<bool, Box<dyn Error>> {
+    attr::create_dir_all(destination)?;
+    if let Some(progress) = &config.progress {
+        progress.enter_dir(source);
+    }
+    let device = walk_device(source, config);
+    // Mounts left behind with -x keep their parents from being removed
+    let mut kept = false;
 
     for entry in WalkDir::new(source).min_depth(1).max_depth(1) {
         let entry = entry?;
@@ -320,36 +797,59 @@ fn move_directory_recursive(
         let dest_path = destination.join(entry_path.file_name().unwrap_or_default());
 
         if entry_path.is_dir() {
-            move_directory_recursive(entry_path, &dest_path, config)?;
+            if is_output(entry_path, output) {
+                kept = true;
+            } else if on_other_filesystem(entry_path, device) {
+                report_other_filesystem(entry_path);
+                kept = true;
+            } else if !move_directory_recursive(entry_path, &dest_path, output, config)? {
+                kept = true;
+            }
         } else {
             move_single_item(entry_path, &dest_path, config)?;
         }
     }
 
-    fs::remove_dir(source)?;
+    if !kept {
+        fs::remove_dir(source)?;
+    }
 
     if config.preserve_metadata {
         preserve_metadata(source, destination)?;
     }
+    if let Some(progress) = &config.progress {
+        progress.leave_dir(source);
+    }
 
-    Ok(())
+    Ok(!kept)
 }
 
+/// Copy a directory entry by entry, skipping `output` (the top-level destination) inside it
 fn copy_directory_recursive(
     source: &Path,
     destination: &Path,
+    output: &Path,
     config: &FileOpConfig,
 ) -> Result<FileOpStats, Box<dyn Error>> {
-    fs::create_dir_all(destination)?;
+    attr::create_dir_all(destination)?;
     let mut total_stats = FileOpStats::default();
+    if let Some(progress) = &config.progress {
+        progress.enter_dir(source);
+    }
+    let device = walk_device(source, config);
 
     for entry in WalkDir::new(source).min_depth(1).max_depth(1) {
         let entry = entry?;
         let entry_path = entry.path();
         let dest_path = destination.join(entry_path.file_name().unwrap_or_default());
 
-        if entry_path.is_dir() {
-            let dir_stats = copy_directory_recursive(entry_path, &dest_path, config)?;
+        if entry_path.is_dir() && is_output(entry_path, output) {
+            continue;
+        } else if entry_path.is_dir() && on_other_filesystem(entry_path, device) {
+            report_other_filesystem(entry_path);
+            total_stats.skipped += 1;
+        } else if entry_path.is_dir() {
+            let dir_stats = copy_directory_recursive(entry_path, &dest_path, output, config)?;
             total_stats.processed += dir_stats.processed;
             total_stats.copied += dir_stats.copied;
             total_stats.errors += dir_stats.errors;
@@ -367,6 +867,10 @@ fn copy_directory_recursive(
         preserve_metadata(source, destination)?;
     }
 
+    if let Some(progress) = &config.progress {
+        progress.leave_dir(source);
+    }
+
     // Count the directory itself
     total_stats.processed += 1;
     total_stats.copied += 1;
@@ -374,6 +878,21 @@ fn copy_directory_recursive(
     Ok(total_stats)
 }
 
+/// Take the umask off the mode `fs::copy` carried over, as `cp` without `-p` does
+fn apply_umask(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let mode = attr::mode_of(path)?;
+        let masked = mode & !attr::umask();
+        if masked != mode {
+            attr::set_mode(path, masked)?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
 fn preserve_metadata(source: &Path, destination: &Path) -> Result<(), Box<dyn Error>> {
     let metadata = fs::metadata(source)?;
 
@@ -465,6 +984,21 @@ fn systemtime_to_filetime(
     })
 }
 
+/// Ask what to do about a failed item; anything but continue or roll back aborts
+fn prompt_on_error() -> OnError {
+    print!("Continue, abort or roll back? [c/a/r] ");
+    let _ = io::stdout().flush();
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input).is_err() {
+        return OnError::Abort;
+    }
+    match input.trim().to_lowercase().as_str() {
+        "c" | "continue" => OnError::Continue,
+        "r" | "rollback" | "roll back" => OnError::Rollback,
+        _ => OnError::Abort,
+    }
+}
+
 fn prompt_overwrite(source: &Path, destination: &Path) -> Result<bool, Box<dyn Error>> {
     print!("{} overwrite '{}'? ", "mv:".yellow(), destination.display());
     io::stdout().flush()?;
@@ -481,6 +1015,7 @@ pub fn remove_files(
     config: &FileOpConfig,
 ) -> Result<FileOpStats, Box<dyn Error>> {
     let mut stats = FileOpStats::default();
+    let mut batch = Batch::new(config.on_error).with_trash(config.trash.clone());
 
     for target in targets {
         stats.processed += 1;
@@ -492,11 +1027,22 @@ pub fn remove_files(
                 target.display(),
                 e
             );
+            output::failed(Op::Remove, Some(target), None, &e.to_string());
             stats.errors += 1;
+            if !batch.keep_going(&mut DiskFs::default()) {
+                break;
+            }
         } else {
             stats.moved += 1; // Use moved count for removed items
+            output::done(Op::Remove, Some(target), None);
+            if config.trash.is_none() && target.symlink_metadata().is_err() {
+                batch.deleted(target);
+            }
         }
     }
+    if batch.rolled_back() {
+        stats.moved = 0;
+    }
 
     Ok(stats)
 }
@@ -515,16 +1061,23 @@ fn remove_single_item(target: &Path, config: &FileOpConfig) -> Result<(), Box<dy
         return Ok(());
     }
 
-    if target.is_dir() {
-        if config.recursive {
-            remove_directory_recursive(target, config)?;
-        } else {
-            return Err(format!(
-                "Is a directory: {} (use -r to remove directories)",
-                target.display()
-            )
-            .into());
+    if target.is_dir() && !config.recursive {
+        return Err(format!(
+            "Is a directory: {} (use -r to remove directories)",
+            target.display()
+        )
+        .into());
+    }
+
+    if let Some(trash) = &config.trash
+        && !holds_other_filesystems(target, config)
+    {
+        trash.borrow_mut().put(target)?;
+        if config.verbose {
+            eprintln!("removed '{}'", target.display());
         }
+    } else if target.is_dir() {
+        remove_directory_recursive(target, config)?;
     } else {
         fs::remove_file(target)?;
         if config.verbose {
@@ -535,13 +1088,32 @@ fn remove_single_item(target: &Path, config: &FileOpConfig) -> Result<(), Box<dy
     Ok(())
 }
 
+/// Remove a directory bottom-up; with a trash, files are trashed one by one
+///
+/// That only happens with `-x` when mounts inside have to stay, so the
+/// directories holding them are kept as well.
 fn remove_directory_recursive(target: &Path, config: &FileOpConfig) -> Result<(), Box<dyn Error>> {
-    for entry in WalkDir::new(target).contents_first(true) {
+    let device = walk_device(target, config);
+    let mut kept: Vec<PathBuf> = Vec::new();
+    let walker = WalkDir::new(target)
+        .contents_first(true)
+        .same_file_system(config.one_file_system);
+    for entry in walker {
         let entry = entry?;
         let entry_path = entry.path();
 
         if entry_path.is_dir() {
+            if on_other_filesystem(entry_path, device) {
+                report_other_filesystem(entry_path);
+                kept.push(entry_path.to_path_buf());
+                continue;
+            }
+            if kept.iter().any(|mount| mount.starts_with(entry_path)) {
+                continue;
+            }
             fs::remove_dir(entry_path)?;
+        } else if let Some(trash) = &config.trash {
+            trash.borrow_mut().put(entry_path)?;
         } else {
             fs::remove_file(entry_path)?;
         }
@@ -554,6 +1126,56 @@ fn remove_directory_recursive(target: &Path, config: &FileOpConfig) -> Result<()
     Ok(())
 }
 
+/// Whether `dir` is the destination of the running move or copy
+///
+/// Compared by device and inode, so `backup`, `./backup` and a symlinked
+/// spelling of it all count.
+fn is_output(dir: &Path, output: &Path) -> bool {
+    let same = crate::plan::same_file(dir, output);
+    if same {
+        eprintln!(
+            "{}: skipping '{}': it is the destination",
+            "Note".yellow(),
+            dir.display()
+        );
+    }
+    same
+}
+
+/// The device a `-x` walk from `root` stays on; `None` without `-x`
+fn walk_device(root: &Path, config: &FileOpConfig) -> Option<u64> {
+    if config.one_file_system {
+        crate::disk_usage::device_of(root)
+    } else {
+        None
+    }
+}
+
+fn on_other_filesystem(dir: &Path, device: Option<u64>) -> bool {
+    device.is_some() && crate::disk_usage::device_of(dir) != device
+}
+
+fn report_other_filesystem(dir: &Path) {
+    eprintln!(
+        "{}: skipping '{}': on a different file system",
+        "Warning".yellow(),
+        dir.display()
+    );
+}
+
+/// With `-x`, whether a mount from another filesystem sits below `dir`
+fn holds_other_filesystems(dir: &Path, config: &FileOpConfig) -> bool {
+    let device = walk_device(dir, config);
+    device.is_some()
+        && dir.is_dir()
+        && WalkDir::new(dir)
+            .min_depth(1)
+            .same_file_system(true)
+            .into_iter()
+            .filter_map(Result::ok)
+            .any(|entry| entry.file_type().is_dir() && on_other_filesystem(entry.path(), device))
+}
+
 fn prompt_remove(target: &Path) -> Result<bool, Box<dyn Error>> {
     print!("remove {}? ", target.display());
     io::stdout().flush()?;
@@ -598,7 +1220,21 @@ pub fn expand_glob_patterns(patterns: &[String]) -> Result<Vec<PathBuf>, Box<dyn
         return Err("No files matched the given patterns".into());
     }
 
-    Ok(expanded)
+    Ok(dedupe_reported(expanded))
+}
+
+/// Drop sources named twice (`a.txt ./a.txt`, or by a glob and by name), saying so
+pub fn dedupe_reported(sources: Vec<PathBuf>) -> Vec<PathBuf> {
+    let given = sources.len();
+    let sources = crate::plan::dedupe_sources(sources);
+    if sources.len() < given {
+        eprintln!(
+            "{}: ignoring {} duplicate source(s)",
+            "Note".yellow(),
+            given - sources.len()
+        );
+    }
+    sources
 }
 
 pub fn create_files(
@@ -617,13 +1253,14 @@ pub fn create_files(
         // Create parent directories if they don't exist
         if let Some(parent) = file_path.parent() {
             if !parent.exists() {
-                if let Err(e) = fs::create_dir_all(parent) {
+                if let Err(e) = attr::create_dir_all(parent) {
                     eprintln!(
                         "{}: Failed to create parent directory for '{}': {}",
                         "Error".red(),
                         file_path.display(),
                         e
                     );
+                    output::failed(Op::Touch, None, Some(&file_path), &e.to_string());
                     stats.errors += 1;
                     continue;
                 }
@@ -660,6 +1297,7 @@ pub fn create_files(
                         eprintln!("created '{}'", file_path.display());
                     }
                 }
+                output::done(Op::Touch, None, Some(&file_path));
                 stats.moved += 1; // Using moved count for created/touched files
             }
             Err(e) => {
@@ -669,6 +1307,7 @@ pub fn create_files(
                     file_path.display(),
                     e
                 );
+                output::failed(Op::Touch, None, Some(&file_path), &e.to_string());
                 stats.errors += 1;
             }
         }
@@ -695,6 +1334,7 @@ pub fn create_directories(
                 if verbose {
                     eprintln!("directory '{}' already exists", dir_path.display());
                 }
+                output::skipped(Op::Mkdir, &dir_path, "already exists");
                 stats.skipped += 1;
             } else {
                 eprintln!(
@@ -702,6 +1342,12 @@ pub fn create_directories(
                     "Error".red(),
                     dir_path.display()
                 );
+                output::failed(
+                    Op::Mkdir,
+                    None,
+                    Some(&dir_path),
+                    "exists but is not a directory",
+                );
                 stats.errors += 1;
             }
             continue;
@@ -730,6 +1376,7 @@ pub fn create_directories(
                 if verbose {
                     eprintln!("created directory '{}'", dir_path.display());
                 }
+                output::done(Op::Mkdir, None, Some(&dir_path));
                 stats.moved += 1; // Using moved count for created directories
             }
             Err(e) => {
@@ -739,6 +1386,7 @@ pub fn create_directories(
                     dir_path.display(),
                     e
                 );
+                output::failed(Op::Mkdir, None, Some(&dir_path), &e.to_string());
                 stats.errors += 1;
             }
         }
@@ -767,3 +1415,45 @@ fn set_directory_mode(path: &Path, mode: u32) -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+
+    #[test]
+    fn test_rollback_reverses_completed_steps() {
+        assert_eq!(OnError::parse("Rollback"), Ok(OnError::Rollback));
+        assert!(OnError::parse("retry").is_err());
+
+        let mut memory = MemoryFs::new();
+        memory.add_dir(Path::new("in"));
+        memory.add_dir(Path::new("out"));
+        memory.add_file(Path::new("in/a.txt"), 1);
+        memory.add_file(Path::new("in/b.txt"), 2);
+        memory.add_file(Path::new("out/old.txt"), 3);
+
+        let mut batch = Batch::new(OnError::Rollback);
+        memory
+            .rename(Path::new("in/a.txt"), Path::new("out/a.txt"))
+            .unwrap();
+        batch.moved(Path::new("in/a.txt"), Path::new("out/a.txt"));
+        memory
+            .copy(Path::new("in/b.txt"), Path::new("out/b.txt"))
+            .unwrap();
+        batch.copied(Path::new("out/b.txt"), false);
+        batch.copied(Path::new("out/old.txt"), true);
+
+        assert!(!batch.keep_going(&mut memory));
+        assert!(batch.rolled_back());
+        assert_eq!(memory.size(Path::new("in/a.txt")), Some(1));
+        assert!(!memory.exists(Path::new("out/a.txt")));
+        assert!(!memory.exists(Path::new("out/b.txt")));
+        // A copy that replaced a file stays, since the original is gone anyway
+        assert!(memory.exists(Path::new("out/old.txt")));
+
+        let mut batch = Batch::new(OnError::Continue);
+        assert!(batch.keep_going(&mut memory));
+        assert!(!batch.rolled_back());
+    }
+}
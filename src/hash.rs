@@ -0,0 +1,300 @@
+//! Content hashes of files, with a choice of algorithm (`--hash-algo`)
+//!
+//! Hashes tell undo whether a file changed since smv moved it, fill `smv index
+//! build --hash`, and answer the `HASH:` and `DUPES:` filters. `xxh3` is the
+//! default and fast enough for whole media libraries; `blake3` and `sha256`
+//! are there for hashes that must hold up against deliberate collisions. Hashes
+//! are written as `ALGO:HEX`, so ones saved with another algorithm, or by older
+//! versions (plain FNV-1a hex), are still checked with the algorithm they were
+//! made with.
+//!
+//! A partial hash covers the size and the first and last 64 KiB of a file. It
+//! reads a fixed amount however large the file is, which makes it a cheap first
+//! pass: files whose partial hashes differ cannot be the same.
+
+use once_cell::sync::OnceCell;
+use sha2::Digest as _;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Bytes a partial hash reads from each end of a file
+pub const PARTIAL_BYTES: u64 = 64 * 1024;
+
+static ALGORITHM: OnceCell<Algorithm> = OnceCell::new();
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    /// 64-bit FNV-1a, what older versions fingerprinted with
+    Fnv,
+    #[default]
+    Xxh3,
+    Blake3,
+    Sha256,
+}
+
+impl Algorithm {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "fnv" => Ok(Algorithm::Fnv),
+            "xxh3" => Ok(Algorithm::Xxh3),
+            "blake3" => Ok(Algorithm::Blake3),
+            "sha256" => Ok(Algorithm::Sha256),
+            _ => Err(format!(
+                "Unknown hash algorithm: {name} (expected xxh3, blake3 or sha256)"
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Fnv => "fnv",
+            Algorithm::Xxh3 => "xxh3",
+            Algorithm::Blake3 => "blake3",
+            Algorithm::Sha256 => "sha256",
+        }
+    }
+}
+
+/// Hash with `algorithm` from here on
+pub fn set_algorithm(algorithm: Algorithm) {
+    let _ = ALGORITHM.set(algorithm);
+}
+
+/// The algorithm of this run
+pub fn algorithm() -> Algorithm {
+    ALGORITHM.get().copied().unwrap_or_default()
+}
+
+/// A hash and the algorithm that made it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Digest {
+    pub algorithm: Algorithm,
+    pub hex: String,
+}
+
+impl Digest {
+    /// Read `ALGO:HEX`; a bare hex string is an FNV-1a hash from an older version
+    pub fn parse(text: &str) -> Option<Self> {
+        let (algorithm, hex) = match text.split_once(':') {
+            Some((name, hex)) => (Algorithm::parse(name).ok()?, hex),
+            None => (Algorithm::Fnv, text),
+        };
+        Some(Self {
+            algorithm,
+            hex: hex.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm.name(), self.hex)
+    }
+}
+
+/// One of the algorithms, fed piece by piece
+enum Hasher {
+    Fnv(u64),
+    Xxh3(Box<xxhash_rust::xxh3::Xxh3>),
+    Blake3(Box<blake3::Hasher>),
+    Sha256(sha2::Sha256),
+}
+
+impl Hasher {
+    fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Fnv => Hasher::Fnv(0xcbf2_9ce4_8422_2325),
+            Algorithm::Xxh3 => Hasher::Xxh3(Box::default()),
+            Algorithm::Blake3 => Hasher::Blake3(Box::default()),
+            Algorithm::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Fnv(hash) => {
+                for byte in bytes {
+                    *hash ^= u64::from(*byte);
+                    *hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+                }
+            }
+            Hasher::Xxh3(hasher) => hasher.update(bytes),
+            Hasher::Blake3(hasher) => {
+                hasher.update(bytes);
+            }
+            Hasher::Sha256(hasher) => hasher.update(bytes),
+        }
+    }
+
+    fn finish(self) -> String {
+        match self {
+            Hasher::Fnv(hash) => format!("{hash:016x}"),
+            Hasher::Xxh3(hasher) => format!("{:032x}", hasher.digest128()),
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            Hasher::Sha256(hasher) => hasher
+                .finalize()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+        }
+    }
+}
+
+fn feed(hasher: &mut Hasher, reader: &mut impl Read, mut limit: u64) -> io::Result<()> {
+    let mut buffer = vec![0u8; 64 * 1024];
+    while limit > 0 {
+        let want = buffer
+            .len()
+            .min(usize::try_from(limit).unwrap_or(usize::MAX));
+        let read = reader.read(&mut buffer[..want])?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        limit -= read as u64;
+    }
+    Ok(())
+}
+
+/// Hash all of a file
+pub fn file(path: &Path, algorithm: Algorithm) -> io::Result<Digest> {
+    let mut hasher = Hasher::new(algorithm);
+    feed(&mut hasher, &mut fs::File::open(path)?, u64::MAX)?;
+    Ok(Digest {
+        algorithm,
+        hex: hasher.finish(),
+    })
+}
+
+/// Hash the size and both ends of a file
+///
+/// Files up to twice [`PARTIAL_BYTES`] are read whole, so their partial hash
+/// still tells every change apart.
+pub fn partial(path: &Path, algorithm: Algorithm) -> io::Result<Digest> {
+    let mut file = fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut hasher = Hasher::new(algorithm);
+    hasher.update(&size.to_le_bytes());
+    feed(&mut hasher, &mut file, PARTIAL_BYTES)?;
+    if size > PARTIAL_BYTES {
+        file.seek(SeekFrom::Start(
+            size.saturating_sub(PARTIAL_BYTES).max(PARTIAL_BYTES),
+        ))?;
+        feed(&mut hasher, &mut file, PARTIAL_BYTES)?;
+    }
+    Ok(Digest {
+        algorithm,
+        hex: hasher.finish(),
+    })
+}
+
+/// Files with the same content, in groups of two or more
+///
+/// Sizes sort out most files without reading them, partial hashes most of the
+/// rest; only files alike in both are hashed in full. Each group, and the list
+/// of groups, is ordered by path. Unreadable files are left out.
+pub fn duplicates(files: &[PathBuf], algorithm: Algorithm) -> Vec<Vec<PathBuf>> {
+    let mut by_size: HashMap<u64, Vec<&PathBuf>> = HashMap::new();
+    for path in files {
+        if let Ok(metadata) = fs::metadata(path)
+            && metadata.is_file()
+        {
+            by_size.entry(metadata.len()).or_default().push(path);
+        }
+    }
+
+    let mut groups = Vec::new();
+    for same_size in by_size.into_values().filter(|paths| paths.len() > 1) {
+        let mut by_partial: HashMap<Digest, Vec<&PathBuf>> = HashMap::new();
+        for path in same_size {
+            if let Ok(digest) = partial(path, algorithm) {
+                by_partial.entry(digest).or_default().push(path);
+            }
+        }
+        for alike in by_partial.into_values().filter(|paths| paths.len() > 1) {
+            let mut by_content: HashMap<Digest, Vec<PathBuf>> = HashMap::new();
+            for path in alike {
+                if let Ok(digest) = file(path, algorithm) {
+                    by_content.entry(digest).or_default().push(path.clone());
+                }
+            }
+            groups.extend(by_content.into_values().filter(|paths| paths.len() > 1));
+        }
+    }
+    for group in &mut groups {
+        group.sort();
+    }
+    groups.sort();
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_algorithms_and_digests() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abc.txt");
+        fs::write(&path, "abc").unwrap();
+
+        assert_eq!(
+            file(&path, Algorithm::Sha256).unwrap().hex,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            file(&path, Algorithm::Blake3).unwrap().hex,
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        assert_eq!(file(&path, Algorithm::Fnv).unwrap().hex, "e71fa2190541574b");
+        assert_eq!(file(&path, Algorithm::Xxh3).unwrap().hex.len(), 32);
+
+        let digest = file(&path, Algorithm::Xxh3).unwrap();
+        assert_eq!(Digest::parse(&digest.to_string()), Some(digest));
+        assert_eq!(
+            Digest::parse("e71fa2190541574b").unwrap().algorithm,
+            Algorithm::Fnv
+        );
+        assert!(Algorithm::parse("md5").is_err());
+    }
+
+    #[test]
+    fn test_duplicates_and_partial_hashes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let big = |fill: u8, last: u8| {
+            let mut content = vec![fill; 3 * PARTIAL_BYTES as usize];
+            *content.last_mut().unwrap() = last;
+            content
+        };
+        fs::write(root.join("a.bin"), big(1, 0)).unwrap();
+        fs::write(root.join("b.bin"), big(1, 0)).unwrap();
+        // Alike at both ends, different in the middle
+        let mut middle = big(1, 0);
+        middle[PARTIAL_BYTES as usize + 10] = 9;
+        fs::write(root.join("c.bin"), &middle).unwrap();
+        fs::write(root.join("d.bin"), big(1, 7)).unwrap();
+        fs::write(root.join("e.txt"), "x").unwrap();
+
+        let files: Vec<PathBuf> = ["a.bin", "b.bin", "c.bin", "d.bin", "e.txt"]
+            .iter()
+            .map(|name| root.join(name))
+            .collect();
+        let algorithm = Algorithm::Xxh3;
+        assert_eq!(
+            partial(&files[0], algorithm).unwrap(),
+            partial(&files[2], algorithm).unwrap()
+        );
+        assert_ne!(
+            partial(&files[0], algorithm).unwrap(),
+            partial(&files[3], algorithm).unwrap()
+        );
+        assert_eq!(
+            duplicates(&files, algorithm),
+            [vec![root.join("a.bin"), root.join("b.bin")]]
+        );
+    }
+}
@@ -1,8 +1,177 @@
 use chrono::{DateTime, Local};
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::attr;
+use crate::disk_usage;
+use crate::hash;
+use crate::journal::{self, JournalKey};
+use crate::report::{Quiet, Reporter};
+
+/// Journal file inside the backup directory (`history.json.age` when encrypted)
+const HISTORY_FILE: &str = "history.json";
+
+/// Directory inside the backup directory holding what `smv rm` removed, per batch
+const TRASH_DIR: &str = "trash";
+
+/// When undos happened, for `smv stats` (`undos.json.age` when encrypted)
+const UNDO_LOG: &str = "undos.json";
+
+/// Undo times kept in the log
+const UNDO_LOG_LIMIT: usize = 10_000;
+
+/// Files up to this size get a full content hash in their fingerprint, larger
+/// ones a partial hash
+const FINGERPRINT_HASH_LIMIT: u64 = 8 * 1024 * 1024;
+
+/// The command line operations of this run are recorded under
+static COMMAND: OnceCell<String> = OnceCell::new();
+
+/// Record later operations as made by `line` (the arguments after `smv`)
+pub fn set_command(line: String) {
+    let _ = COMMAND.set(line);
+}
+
+/// What a file looked like right after an operation, to tell whether undoing it is safe
+///
+/// Renames keep size and modification time, so a fingerprint taken from the source
+/// before the rename describes the destination afterwards.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    /// `ALGO:HEX` of the content, for files up to 8 MiB (plain FNV-1a hex
+    /// from older versions)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// `ALGO:HEX` of the size and both ends, for larger files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial: Option<String>,
+}
+
+impl Fingerprint {
+    /// Fingerprint a regular file; directories and missing paths have none
+    pub fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+        let algorithm = hash::algorithm();
+        let (hash, partial) = if metadata.len() <= FINGERPRINT_HASH_LIMIT {
+            (hash::file(path, algorithm).ok(), None)
+        } else {
+            (None, hash::partial(path, algorithm).ok())
+        };
+        Some(Self {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            hash: hash.map(|digest| digest.to_string()),
+            partial: partial.map(|digest| digest.to_string()),
+        })
+    }
+
+    /// Explain how `path` differs from this fingerprint, if it does
+    pub fn difference(&self, path: &Path) -> Option<String> {
+        let metadata = fs::metadata(path).ok().filter(fs::Metadata::is_file);
+        let Some(metadata) = metadata else {
+            return Some("it is no longer a regular file".to_string());
+        };
+        if metadata.len() != self.size {
+            return Some(format!(
+                "size changed from {} to {} bytes",
+                self.size,
+                metadata.len()
+            ));
+        }
+        // Hashed again with the algorithm the fingerprint was made with
+        let content_changed = |saved: &Option<String>,
+                               hash_with: fn(
+            &Path,
+            hash::Algorithm,
+        ) -> io::Result<hash::Digest>| {
+            saved
+                .as_deref()
+                .and_then(hash::Digest::parse)
+                .is_some_and(|then| hash_with(path, then.algorithm).is_ok_and(|now| now != then))
+        };
+        if content_changed(&self.hash, hash::file) || content_changed(&self.partial, hash::partial)
+        {
+            return Some("content changed".to_string());
+        }
+        if self.modified.is_some() && metadata.modified().ok() != self.modified {
+            return Some("modification time changed".to_string());
+        }
+        None
+    }
+}
+
+/// One file of a bulk move recorded with [`HistoryManager::record_mapping`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MappedMove {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<Fingerprint>,
+    /// Index of the move in the same batch that renamed the nearest directory
+    /// above this one, so undo can find the file after only part of the batch
+    /// was reverted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<usize>,
+}
+
+/// What undoing one file would do, as shown by `smv undo -p`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UndoAction {
+    /// Rename or move back to where it was
+    MoveBack,
+    /// Out of the trash to where it was removed from
+    Restore,
+    /// Put back the permissions an attribute change replaced
+    RestoreMode,
+    /// Delete a created file
+    Delete,
+    /// Remove a created directory, if it is empty by then
+    RemoveDir,
+    /// Copy the backup taken before the operation back
+    FromBackup,
+}
+
+/// One file of a planned undo
+#[derive(Debug, Clone, Serialize)]
+pub struct UndoStep {
+    pub action: UndoAction,
+    /// Where the file is once the steps before it have run
+    pub path: PathBuf,
+    /// Where the undo would put it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<PathBuf>,
+    /// Why the undo would leave this file alone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub problem: Option<String>,
+}
+
+impl UndoStep {
+    fn new(action: UndoAction, path: PathBuf, to: Option<PathBuf>) -> Self {
+        Self {
+            action,
+            path,
+            to,
+            problem: None,
+        }
+    }
+
+    fn problem(mut self, problem: Option<String>) -> Self {
+        self.problem = problem;
+        self
+    }
+}
 
 /// Represents a single file operation that can be undone
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -10,6 +179,106 @@ pub struct Operation {
     pub source: PathBuf,
     pub destination: PathBuf,
     pub timestamp: DateTime<Local>,
+    /// File with the `(old, new)` paths of a bulk move, undone as one operation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mapping: Option<PathBuf>,
+    /// The destination as left by the operation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<Fingerprint>,
+    /// Operations recorded by one command share a batch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<String>,
+    /// Copy of the source taken before the operation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<PathBuf>,
+    /// Trash directory holding what a removal took away; `mapping` lists its contents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trash: Option<PathBuf>,
+    /// File with the permissions an attribute change replaced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modes: Option<PathBuf>,
+    /// The smv command line that made the operation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+}
+
+impl Operation {
+    /// Whether this keeps a file another operation of the batch overwrote
+    pub fn is_overwrite(&self) -> bool {
+        self.destination.as_os_str().is_empty() && !self.source.as_os_str().is_empty()
+    }
+
+    /// Backup, mapping, trash and mode paths that only this operation needs
+    fn owned_files(&self) -> impl Iterator<Item = &PathBuf> {
+        self.backup
+            .iter()
+            .chain(self.mapping.iter())
+            .chain(self.trash.iter())
+            .chain(self.modes.iter())
+    }
+}
+
+/// Where `smv rm` puts what it removes, so undo can bring it back
+///
+/// Items are moved (not copied) into the trash, each in its own numbered
+/// directory so equal names from different places do not clash. Record the
+/// trash with [`HistoryManager::record_trash`] once the removal is done.
+#[derive(Debug)]
+pub struct Trash {
+    dir: PathBuf,
+    items: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Trash {
+    /// Move `path` (a file, link or whole directory) into the trash
+    pub fn put(&mut self, path: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let name = path.file_name().ok_or("Invalid file path")?;
+        let slot = self.dir.join(self.items.len().to_string());
+        fs::create_dir_all(&slot)?;
+        let trashed = slot.join(name);
+        move_path(path, &trashed)?;
+        let original = std::path::absolute(path)?;
+        self.items.push((original, trashed.clone()));
+        Ok(trashed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Move everything back out of the trash, newest first
+    ///
+    /// Returns how many items came back. On an error the rest stay in the
+    /// trash, where recording it keeps them restorable.
+    pub fn restore_all(&mut self) -> Result<usize, Box<dyn Error>> {
+        let mut restored = 0;
+        while let Some((original, trashed)) = self.items.last() {
+            if let Some(parent) = original.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            move_path(trashed, original)?;
+            self.items.pop();
+            restored += 1;
+        }
+        Ok(restored)
+    }
+}
+
+/// How much history to keep; `None` means no limit
+#[derive(Debug, Clone, Default)]
+pub struct Retention {
+    pub max_entries: Option<usize>,
+    pub max_age: Option<chrono::Duration>,
+    pub max_bytes: Option<u64>,
+}
+
+/// What `history gc` removed (or would remove)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GcReport {
+    pub batches: usize,
+    pub entries: usize,
+    pub files: usize,
+    pub bytes: u64,
 }
 
 impl Operation {
@@ -18,10 +287,25 @@ impl Operation {
             source,
             destination,
             timestamp: Local::now(),
+            mapping: None,
+            fingerprint: None,
+            batch: None,
+            backup: None,
+            trash: None,
+            modes: None,
+            command: COMMAND.get().cloned(),
         }
     }
 }
 
+/// One file of an attribute change recorded with [`HistoryManager::record_modes`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModeChange {
+    pub path: PathBuf,
+    pub before: u32,
+    pub after: u32,
+}
+
 /// History manager for tracking file operations
 #[derive(Debug)]
 pub struct HistoryManager {
@@ -29,37 +313,170 @@ pub struct HistoryManager {
     max_history_size: usize,
     backup_directory: PathBuf,
     history_file: PathBuf,
+    batch: String,
+    /// Encrypts the journal, mappings and backups when set
+    key: Option<JournalKey>,
+    /// Hears what undo skipped and restored; quiet unless set
+    reporter: Box<dyn Reporter>,
+}
+
+/// Whether `name` is a backup (`NAME_YYYYMMDD_HHMMSS`) or a mapping or mode
+/// listing (`mapping_*.json`, `modes_*.json`), encrypted or not
+fn is_history_record(name: &str) -> bool {
+    let suffix = format!(".{}", journal::ENCRYPTED_EXTENSION);
+    let name = name.strip_suffix(&suffix).unwrap_or(name);
+    if (name.starts_with("mapping_") || name.starts_with("modes_")) && name.ends_with(".json") {
+        return true;
+    }
+    // `_` + date + `_` + time, after a name of at least one character
+    let bytes = name.as_bytes();
+    bytes.len() > 16 && {
+        let stamp = &bytes[bytes.len() - 16..];
+        stamp[0] == b'_'
+            && stamp[9] == b'_'
+            && stamp[1..9].iter().all(u8::is_ascii_digit)
+            && stamp[10..].iter().all(u8::is_ascii_digit)
+    }
+}
+
+fn new_batch_id() -> String {
+    format!(
+        "{}-{}",
+        Local::now().format("%Y%m%d_%H%M%S%.f"),
+        std::process::id()
+    )
 }
 
 impl HistoryManager {
+    #[allow(dead_code)]
     pub fn new(max_history_size: usize, backup_directory: &Path) -> Self {
-        let history_file = backup_directory.join("history.json");
+        let mut manager = Self::with_key(max_history_size, backup_directory, None);
+        // Load existing history from file
+        let _ = manager.load_history();
+        manager
+    }
+
+    /// Open the history, encrypted to the key named by `SMV_HISTORY_KEY` if set
+    ///
+    /// Unlike [`HistoryManager::new`] this fails when the journal cannot be read,
+    /// rather than starting over without it.
+    pub fn open(max_history_size: usize, backup_directory: &Path) -> Result<Self, Box<dyn Error>> {
+        let key = JournalKey::from_env()?;
+        let encrypted = journal::encrypted_path(&backup_directory.join(HISTORY_FILE));
+        if key.is_none() && encrypted.exists() {
+            return Err(format!(
+                "History in '{}' is encrypted; set {} to its key file",
+                backup_directory.display(),
+                journal::KEY_ENV
+            )
+            .into());
+        }
+
+        let encrypt = key.is_some();
+        let mut manager = Self::with_key(max_history_size, backup_directory, key);
+        if encrypt {
+            manager.load_history()?;
+        } else {
+            let _ = manager.load_history();
+        }
+        Ok(manager)
+    }
+
+    fn with_key(max_history_size: usize, backup_directory: &Path, key: Option<JournalKey>) -> Self {
         let mut manager = Self {
-            operations: Vec::with_capacity(max_history_size),
+            operations: Vec::with_capacity(max_history_size.min(1024)),
             max_history_size,
             backup_directory: backup_directory.to_path_buf(),
-            history_file,
+            history_file: backup_directory.join(HISTORY_FILE),
+            batch: new_batch_id(),
+            key,
+            reporter: Box::new(Quiet),
         };
-        // Load existing history from file
-        let _ = manager.load_history();
+        manager.history_file = manager.state_path(manager.history_file.clone());
         manager
     }
 
+    /// Send what undo skips and restores to `reporter` (nothing is reported by default)
+    pub fn set_reporter(&mut self, reporter: Box<dyn Reporter>) {
+        self.reporter = reporter;
+    }
+
+    /// Where a state file goes: with the encrypted extension when there is a key
+    fn state_path(&self, path: PathBuf) -> PathBuf {
+        if self.key.is_some() {
+            journal::encrypted_path(&path)
+        } else {
+            path
+        }
+    }
+
+    /// Write a journal or mapping file, encrypted when there is a key
+    fn write_state(&self, path: &Path, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        match self.key {
+            Some(ref key) => fs::write(path, key.encrypt(data)?)?,
+            None => fs::write(path, data)?,
+        }
+        Ok(())
+    }
+
+    /// Read a journal or mapping file, decrypting it if it was written encrypted
+    fn read_state(&self, path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+        let data = fs::read(path)?;
+        if !is_encrypted(path) {
+            return Ok(data);
+        }
+        match self.key {
+            Some(ref key) => key.decrypt(&data),
+            None => Err(format!(
+                "'{}' is encrypted; set {} to its key file",
+                path.display(),
+                journal::KEY_ENV
+            )
+            .into()),
+        }
+    }
+
+    /// Copy a backup back to `target`, decrypting it if needed
+    fn copy_backup(&self, backup: &Path, target: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if !is_encrypted(backup) {
+            fs::copy(backup, target)?;
+            return Ok(());
+        }
+        match self.key {
+            Some(ref key) => key.decrypt_file(backup, target),
+            None => Err(format!(
+                "Backup '{}' is encrypted; set {} to its key file",
+                backup.display(),
+                journal::KEY_ENV
+            )
+            .into()),
+        }
+    }
+
     /// Record a new operation
     pub fn record(&mut self, source: PathBuf, destination: PathBuf) -> Result<(), Box<dyn Error>> {
         // Create backup if a source file exists
-        if source.exists() {
-            self.create_backup(&source)?;
-        }
+        let backup = if source.exists() {
+            Some(self.create_backup(&source)?)
+        } else {
+            None
+        };
+
+        // Callers record either just before a rename or right after a create
+        let fingerprint = Fingerprint::of(&destination).or_else(|| Fingerprint::of(&source));
 
         // Add operation to history
-        let operation = Operation::new(source, destination);
+        let mut operation = Operation::new(source, destination);
+        operation.fingerprint = fingerprint;
+        operation.batch = Some(self.batch.clone());
+        operation.backup = backup;
         self.operations.push(operation);
 
         // Trim history if needed
-        if self.operations.len() > self.max_history_size {
-            self.operations.remove(0);
-        }
+        self.trim();
 
         // Save history to file
         self.save_history()?;
@@ -67,58 +484,948 @@ impl HistoryManager {
         Ok(())
     }
 
+    /// Back up a file that is about to be overwritten, so undo puts it back
+    ///
+    /// Record this before the operation that overwrites `path`: undo goes
+    /// newest first, so the file returns once what replaced it has moved away.
+    pub fn record_overwrite(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut operation = Operation::new(path.to_path_buf(), PathBuf::new());
+        operation.backup = Some(self.create_backup(path)?);
+        operation.batch = Some(self.batch.clone());
+        self.operations.push(operation);
+        self.trim();
+        self.save_history()?;
+        Ok(())
+    }
+
+    /// Drop the oldest operations beyond the size limit, with their files
+    fn trim(&mut self) {
+        while self.operations.len() > self.max_history_size {
+            let dropped = self.operations.remove(0);
+            for file in dropped.owned_files() {
+                let _ = remove_owned(file);
+            }
+        }
+    }
+
+    /// A trash for this batch's removals
+    ///
+    /// Trashed items are kept as they are, so with an encrypted history the
+    /// trash is refused rather than leaving plain copies next to it.
+    pub fn trash(&self) -> Result<Trash, Box<dyn Error>> {
+        if self.key.is_some() {
+            return Err(format!(
+                "Removed items would be kept unencrypted in the trash while {} encrypts the history; use --permanent to delete them outright",
+                journal::KEY_ENV
+            )
+            .into());
+        }
+        Ok(Trash {
+            dir: self.backup_directory.join(TRASH_DIR).join(&self.batch),
+            items: Vec::new(),
+        })
+    }
+
+    /// Record the removals put into `trash` as one undoable operation
+    pub fn record_trash(&mut self, trash: &Trash) -> Result<(), Box<dyn Error>> {
+        if trash.is_empty() {
+            return Ok(());
+        }
+        let listing = self.state_path(self.backup_directory.join(format!(
+            "removed_{}.json",
+            Local::now().format("%Y%m%d_%H%M%S%.f")
+        )));
+        self.write_trash_listing(&listing, &trash.items)?;
+
+        let mut operation = Operation::new(PathBuf::new(), trash.dir.clone());
+        operation.mapping = Some(listing);
+        operation.trash = Some(trash.dir.clone());
+        operation.batch = Some(self.batch.clone());
+        self.operations.push(operation);
+
+        self.trim();
+        self.save_history()
+    }
+
+    fn write_trash_listing(
+        &self,
+        listing: &Path,
+        items: &[(PathBuf, PathBuf)],
+    ) -> Result<(), Box<dyn Error>> {
+        let mapped: Vec<MappedMove> = items
+            .iter()
+            .map(|(original, trashed)| MappedMove {
+                source: original.clone(),
+                destination: trashed.clone(),
+                fingerprint: None,
+                parent: None,
+            })
+            .collect();
+        self.write_state(listing, serde_json::to_string(&mapped)?.as_bytes())
+    }
+
+    /// Put removed items back where they were, recreating missing parent directories
+    ///
+    /// Returns the items that could not be restored; they stay in the trash and in
+    /// the listing, so a later undo can try again.
+    fn restore_trash(
+        &mut self,
+        trash: &Path,
+        listing: &Path,
+    ) -> Result<Vec<(PathBuf, PathBuf)>, Box<dyn Error>> {
+        let items: Vec<MappedMove> = serde_json::from_slice(&self.read_state(listing)?)?;
+
+        let mut restored = 0;
+        let mut failed = Vec::new();
+        for item in items.iter().rev() {
+            let problem = if item.source.symlink_metadata().is_ok() {
+                Some("something else is there now".to_string())
+            } else if item.destination.symlink_metadata().is_err() {
+                Some(format!("missing from the trash at '{}'", trash.display()))
+            } else {
+                item.source
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|()| move_path(&item.destination, &item.source))
+                    .err()
+                    .map(|e| e.to_string())
+            };
+            match problem {
+                None => restored += 1,
+                Some(problem) => {
+                    self.reporter.warn(&format!(
+                        "Could not restore '{}': {}",
+                        item.source.display(),
+                        problem
+                    ));
+                    failed.push((item.source.clone(), item.destination.clone()));
+                }
+            }
+        }
+
+        self.reporter.info(&format!(
+            "Undone: Restored {} of {} removed items",
+            restored,
+            items.len()
+        ));
+        if failed.is_empty() {
+            let _ = fs::remove_dir_all(trash);
+            let _ = fs::remove_file(listing);
+        } else {
+            failed.reverse();
+            self.write_trash_listing(listing, &failed)?;
+        }
+        Ok(failed)
+    }
+
+    /// Start a new batch; later records are undone separately from earlier ones
+    ///
+    /// Each manager starts with its own batch, so only long-lived sessions (the REPL)
+    /// need to call this between commands.
+    pub fn start_batch(&mut self) {
+        self.batch = new_batch_id();
+    }
+
+    /// Record a bulk move under `root` as a single undoable operation
+    ///
+    /// Renames lose no data, so instead of backups the `(old, new)` mapping is
+    /// written next to the history file.
+    pub fn record_mapping(
+        &mut self,
+        root: PathBuf,
+        moves: &[(PathBuf, PathBuf)],
+    ) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&self.backup_directory)?;
+        let timestamp = Local::now().format("%Y%m%d_%H%M%S%.f").to_string();
+        let mapping_file = self.state_path(
+            self.backup_directory
+                .join(format!("mapping_{timestamp}.json")),
+        );
+        let mut mapped: Vec<MappedMove> = moves
+            .iter()
+            // The mapping is JSON, which holds only UTF-8 paths
+            .filter(|(source, destination)| {
+                let representable = source.to_str().is_some() && destination.to_str().is_some();
+                if !representable {
+                    self.reporter.warn(&format!(
+                        "Not recorded for undo: '{}' (path is not valid UTF-8)",
+                        destination.display()
+                    ));
+                }
+                representable
+            })
+            .map(|(source, destination)| MappedMove {
+                source: source.clone(),
+                destination: destination.clone(),
+                fingerprint: Fingerprint::of(destination),
+                parent: None,
+            })
+            .collect();
+        link_parents(&mut mapped);
+        self.write_state(&mapping_file, serde_json::to_string(&mapped)?.as_bytes())?;
+
+        let mut operation = Operation::new(root.clone(), root);
+        operation.mapping = Some(mapping_file);
+        operation.batch = Some(self.batch.clone());
+        self.operations.push(operation);
+
+        self.trim();
+
+        self.save_history()?;
+
+        Ok(())
+    }
+
+    /// Record the permission changes of `smv attr` under `root` as one undoable operation
+    pub fn record_modes(
+        &mut self,
+        root: PathBuf,
+        changes: &[ModeChange],
+    ) -> Result<(), Box<dyn Error>> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.backup_directory)?;
+        let listing = self.state_path(self.backup_directory.join(format!(
+            "modes_{}.json",
+            Local::now().format("%Y%m%d_%H%M%S%.f")
+        )));
+        self.write_state(&listing, serde_json::to_string(changes)?.as_bytes())?;
+
+        let mut operation = Operation::new(root.clone(), root);
+        operation.modes = Some(listing);
+        operation.batch = Some(self.batch.clone());
+        self.operations.push(operation);
+
+        self.trim();
+        self.save_history()
+    }
+
+    fn read_modes(&self, listing: &Path) -> Result<Vec<ModeChange>, Box<dyn Error>> {
+        Ok(serde_json::from_slice(&self.read_state(listing)?)?)
+    }
+
+    /// Put back the permissions a recorded attribute change replaced
+    ///
+    /// Files whose permissions changed again since are left alone unless `force` is set.
+    fn undo_modes(&mut self, listing: &Path, force: bool) -> Result<(), Box<dyn Error>> {
+        let changes = self.read_modes(listing)?;
+        let mut restored = 0;
+        // Directories were changed after their contents, so they go back first
+        for change in changes.iter().rev() {
+            if let Some(problem) = mode_problem(change, force) {
+                self.reporter.warn(&format!(
+                    "Skipping '{}' ({})",
+                    change.path.display(),
+                    problem
+                ));
+                continue;
+            }
+            attr::set_mode(&change.path, change.before)?;
+            restored += 1;
+        }
+        let _ = fs::remove_file(listing);
+        self.reporter.info(&format!(
+            "Undone: Restored the permissions of {} of {} files",
+            restored,
+            changes.len()
+        ));
+        Ok(())
+    }
+
+    /// Move every file of a recorded mapping back and prune the emptied directories
+    ///
+    /// Files modified since the move are left in place unless `force` is set.
+    /// Skipped files stay in the mapping, and the undo fails so the entry is
+    /// kept for another try.
+    fn undo_mapping(
+        &mut self,
+        root: &Path,
+        mapping_file: &Path,
+        force: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let moves: Vec<MappedMove> = serde_json::from_slice(&self.read_state(mapping_file)?)?;
+
+        // Moved back so far, as (from, to), for a rollback when a later one fails
+        let mut restored: Vec<(PathBuf, PathBuf)> = Vec::new();
+        let mut skipped = Vec::new();
+        for index in (0..moves.len()).rev() {
+            let (source, destination) = locate(&moves, index);
+            let fingerprint = &moves[index].fingerprint;
+            if !destination.exists() || source.exists() {
+                self.reporter.warn(&format!(
+                    "Skipping '{}' (moved or replaced since)",
+                    destination.display()
+                ));
+                skipped.push(index);
+                continue;
+            }
+            if let Some(reason) = fingerprint
+                .as_ref()
+                .and_then(|f| f.difference(&destination))
+            {
+                if !force {
+                    self.reporter.warn(&format!(
+                        "Skipping '{}' (modified since the move: {}; use -F to undo anyway)",
+                        destination.display(),
+                        reason
+                    ));
+                    skipped.push(index);
+                    continue;
+                }
+                self.reporter.warn(&format!(
+                    "Warning: '{}' was modified since the move ({})",
+                    destination.display(),
+                    reason
+                ));
+            }
+            if let Err(e) = move_back(&destination, &source) {
+                return Err(self.roll_forward(&destination, &e, &restored).into());
+            }
+            restored.push((destination.clone(), source));
+
+            // Remove shard directories that are now empty, stopping at the root
+            let mut dir = destination.parent();
+            while let Some(current) = dir {
+                if current == root || fs::remove_dir(current).is_err() {
+                    break;
+                }
+                dir = current.parent();
+            }
+        }
+
+        self.reporter.info(&format!(
+            "Undone: Moved {} of {} files back into '{}'",
+            restored.len(),
+            moves.len(),
+            root.display()
+        ));
+        if skipped.is_empty() {
+            let _ = fs::remove_file(mapping_file);
+            return Ok(());
+        }
+
+        // What is left is written where it is now, under parents that stay moved
+        skipped.reverse();
+        let left: Vec<MappedMove> = skipped
+            .iter()
+            .map(|&index| {
+                let (source, destination) = locate(&moves, index);
+                MappedMove {
+                    source,
+                    destination,
+                    fingerprint: moves[index].fingerprint.clone(),
+                    parent: moves[index]
+                        .parent
+                        .and_then(|parent| skipped.iter().position(|&kept| kept == parent)),
+                }
+            })
+            .collect();
+        self.write_state(mapping_file, serde_json::to_string(&left)?.as_bytes())?;
+        Err(format!(
+            "{} of {} files were not moved back and stay in the history; undo again once they are in place (-F undoes modified files)",
+            left.len(),
+            moves.len()
+        )
+        .into())
+    }
+
+    /// Move the files a failed undo already moved back forward again
+    ///
+    /// Returns the error to report: where the undo stopped, and whether the
+    /// batch is as it was before, so the history entry can simply be kept.
+    fn roll_forward(
+        &mut self,
+        failed: &Path,
+        error: &io::Error,
+        restored: &[(PathBuf, PathBuf)],
+    ) -> String {
+        let mut stranded = Vec::new();
+        for (destination, source) in restored.iter().rev() {
+            if move_back(source, destination).is_err() {
+                stranded.push(source);
+            }
+        }
+        let mut message = format!(
+            "Undo stopped at '{}' ({}); moved the {} files already restored forward again",
+            failed.display(),
+            error,
+            restored.len() - stranded.len()
+        );
+        if stranded.is_empty() {
+            message.push_str(", so the batch is unchanged and still in the history");
+        } else {
+            message.push_str(&format!(
+                ", but {} stay restored and the rest of the batch is still in the history:",
+                stranded.len()
+            ));
+            for path in stranded {
+                message.push_str(&format!("\n  {}", path.display()));
+            }
+        }
+        message
+    }
+
     /// Undo the last operation
     pub fn undo(&mut self) -> Result<(), Box<dyn Error>> {
+        self.undo_checked(false)
+    }
+
+    /// Undo the last operation, refusing if its result was modified afterwards
+    ///
+    /// With `force` the check only warns.
+    pub fn undo_checked(&mut self, force: bool) -> Result<(), Box<dyn Error>> {
+        if let Some(operation) = self.operations.last()
+            && let Some(ref fingerprint) = operation.fingerprint
+            && operation.destination.exists()
+            && let Some(reason) = fingerprint.difference(&operation.destination)
+        {
+            if !force {
+                return Err(format!(
+                    "'{}' was modified after the operation ({}); refusing to undo. Use -F to undo anyway",
+                    operation.destination.display(),
+                    reason
+                )
+                .into());
+            }
+            self.reporter.warn(&format!(
+                "Warning: '{}' was modified after the operation ({})",
+                operation.destination.display(),
+                reason
+            ));
+        }
+
         if let Some(operation) = self.operations.pop() {
+            // Removals come back out of the trash; what cannot stays there for another try
+            if let (Some(trash), Some(listing)) = (&operation.trash, &operation.mapping) {
+                let failed = self.restore_trash(trash, listing)?;
+                if !failed.is_empty() {
+                    self.operations.push(operation.clone());
+                    self.save_history()?;
+                    return Err(format!(
+                        "{} removed items could not be restored; they are kept in '{}'",
+                        failed.len(),
+                        trash.display()
+                    )
+                    .into());
+                }
+            }
+            // Attribute changes put the old permissions back
+            else if let Some(ref modes) = operation.modes {
+                self.undo_modes(modes, force)?;
+            }
+            // Bulk moves (sort alpha, transforms) carry their own mapping file
+            // and go back as a whole: a move that fails puts the earlier ones forward again
+            else if let Some(ref mapping) = operation.mapping {
+                if let Err(e) = self.undo_mapping(&operation.source, mapping, force) {
+                    self.operations.push(operation.clone());
+                    self.save_history()?;
+                    return Err(e);
+                }
+            }
             // Check if this was a file creation operation (source is empty)
-            if operation.source.as_os_str().is_empty() {
+            else if operation.source.as_os_str().is_empty() {
                 // This was a file creation - delete the created file
-                if operation.destination.exists() {
+                if operation.destination.is_dir() {
+                    // Directories made for a copy go only once they are empty again
+                    match fs::remove_dir(&operation.destination) {
+                        Ok(()) => self.reporter.info(&format!(
+                            "Undone: Removed created directory '{}'",
+                            operation.destination.display()
+                        )),
+                        Err(_) => self.reporter.warn(&format!(
+                            "Kept created directory '{}' (not empty)",
+                            operation.destination.display()
+                        )),
+                    }
+                } else if operation.destination.exists() {
                     fs::remove_file(&operation.destination)?;
-                    println!(
+                    self.reporter.info(&format!(
                         "Undone: Deleted created file '{}'",
                         operation.destination.display()
-                    );
+                    ));
                 } else {
-                    println!(
+                    self.reporter.warn(&format!(
                         "File '{}' was already deleted or doesn't exist",
                         operation.destination.display()
-                    );
+                    ));
                 }
             }
             // If the destination exists, move it back to source
             else if operation.destination.exists() {
                 fs::rename(&operation.destination, &operation.source)?;
-                println!(
+                self.reporter.info(&format!(
                     "Undone: Moved '{}' back to '{}'",
                     operation.destination.display(),
                     operation.source.display()
-                );
+                ));
             }
-            // If source doesn't exist but we have a backup, restore it
-            else if !operation.source.exists() {
-                self.restore_backup(&operation.source)?;
-                println!(
+            // If source doesn't exist but we have a backup, restore it; files
+            // edited in place get their old content back over the new
+            else if !operation.source.exists() || operation.is_overwrite() {
+                match operation.backup {
+                    Some(ref backup) if backup.exists() => {
+                        self.copy_backup(backup, &operation.source)?;
+                    }
+                    _ => self.restore_backup(&operation.source)?,
+                }
+                self.reporter.info(&format!(
                     "Undone: Restored '{}' from backup",
                     operation.source.display()
-                );
+                ));
             }
             // Save updated history to file
             self.save_history()?;
+            self.log_undo()?;
             Ok(())
         } else {
             Err("No operations to undo".into())
         }
     }
 
+    /// What [`undo_batch`](Self::undo_batch) would do, without doing it
+    pub fn plan_undo_batch(
+        &self,
+        number: usize,
+        force: bool,
+    ) -> Result<Vec<UndoStep>, Box<dyn Error>> {
+        let range = self.batch_range(number)?;
+        let mut steps = Vec::new();
+        for operation in self.operations[range].iter().rev() {
+            steps.extend(self.plan_undo_of(operation, force)?);
+        }
+        Ok(steps)
+    }
+
+    /// What [`undo_checked`](Self::undo_checked) would do to `operation`
+    fn plan_undo_of(
+        &self,
+        operation: &Operation,
+        force: bool,
+    ) -> Result<Vec<UndoStep>, Box<dyn Error>> {
+        if let (Some(trash), Some(listing)) = (&operation.trash, &operation.mapping) {
+            let items: Vec<MappedMove> = serde_json::from_slice(&self.read_state(listing)?)?;
+            return Ok(items
+                .into_iter()
+                .rev()
+                .map(|item| {
+                    let problem = if item.source.symlink_metadata().is_ok() {
+                        Some("something else is there now".to_string())
+                    } else if item.destination.symlink_metadata().is_err() {
+                        Some(format!("missing from the trash at '{}'", trash.display()))
+                    } else {
+                        None
+                    };
+                    UndoStep::new(UndoAction::Restore, item.destination, Some(item.source))
+                        .problem(problem)
+                })
+                .collect());
+        }
+        if let Some(ref modes) = operation.modes {
+            return Ok(self
+                .read_modes(modes)?
+                .into_iter()
+                .rev()
+                .map(|change| {
+                    let problem = mode_problem(&change, force);
+                    UndoStep::new(UndoAction::RestoreMode, change.path, None).problem(problem)
+                })
+                .collect());
+        }
+        if operation.mapping.is_some() {
+            let moves = self.files_of(operation)?;
+            return Ok(plan_moves_back(&moves, (0..moves.len()).rev(), force));
+        }
+
+        let destination = operation.destination.clone();
+        let refused = operation
+            .fingerprint
+            .as_ref()
+            .filter(|_| !force && destination.exists())
+            .and_then(|fingerprint| fingerprint.difference(&destination))
+            .map(|reason| {
+                format!("modified after the operation ({reason}); use -F to undo anyway")
+            });
+        let step = if operation.source.as_os_str().is_empty() {
+            if destination.is_dir() {
+                let problem = fs::read_dir(&destination)?
+                    .next()
+                    .map(|_| "not empty; it would be kept".to_string());
+                UndoStep::new(UndoAction::RemoveDir, destination, None).problem(problem)
+            } else {
+                let problem = (!destination.exists()).then(|| "already deleted".to_string());
+                UndoStep::new(UndoAction::Delete, destination, None).problem(problem)
+            }
+        } else if destination.exists() {
+            UndoStep::new(
+                UndoAction::MoveBack,
+                destination,
+                Some(operation.source.clone()),
+            )
+        } else if !operation.source.exists() || operation.is_overwrite() {
+            // Without its own backup the newest one of that name is used
+            let backup = operation
+                .backup
+                .clone()
+                .filter(|backup| backup.exists())
+                .unwrap_or_else(|| self.backup_directory.clone());
+            UndoStep::new(
+                UndoAction::FromBackup,
+                backup,
+                Some(operation.source.clone()),
+            )
+        } else {
+            UndoStep::new(
+                UndoAction::MoveBack,
+                destination,
+                Some(operation.source.clone()),
+            )
+            .problem(Some("moved or replaced since".to_string()))
+        };
+        Ok(vec![match refused {
+            Some(reason) => step.problem(Some(reason)),
+            None => step,
+        }])
+    }
+
+    /// What [`undo_selected`](Self::undo_selected) would do, without doing it
+    pub fn plan_undo_selected(
+        &self,
+        batch: &[MappedMove],
+        selected: &[usize],
+        force: bool,
+    ) -> Vec<UndoStep> {
+        plan_moves_back(batch, selected.iter().rev().copied(), force)
+    }
+
+    /// Note that an undo happened now
+    fn log_undo(&self) -> Result<(), Box<dyn Error>> {
+        let mut undos = self.undo_times()?;
+        undos.push(Local::now());
+        let excess = undos.len().saturating_sub(UNDO_LOG_LIMIT);
+        undos.drain(..excess);
+        let log = self.state_path(self.backup_directory.join(UNDO_LOG));
+        self.write_state(&log, serde_json::to_string(&undos)?.as_bytes())
+    }
+
+    /// When past undos happened, oldest first
+    pub fn undo_times(&self) -> Result<Vec<DateTime<Local>>, Box<dyn Error>> {
+        let log = self.state_path(self.backup_directory.join(UNDO_LOG));
+        if !log.exists() {
+            return Ok(Vec::new());
+        }
+        Ok(serde_json::from_slice(&self.read_state(&log)?)?)
+    }
+
+    /// The files an operation touched: its mapping, or the operation itself
+    ///
+    /// Files of an attribute change stay where they are: each is its own
+    /// source and destination.
+    pub fn files_of(&self, operation: &Operation) -> Result<Vec<MappedMove>, Box<dyn Error>> {
+        if let Some(ref modes) = operation.modes {
+            return Ok(self
+                .read_modes(modes)?
+                .into_iter()
+                .map(|change| MappedMove {
+                    source: change.path.clone(),
+                    destination: change.path,
+                    fingerprint: None,
+                    parent: None,
+                })
+                .collect());
+        }
+        if let Some(ref mapping) = operation.mapping {
+            return Ok(serde_json::from_slice(&self.read_state(mapping)?)?);
+        }
+        Ok(vec![MappedMove {
+            source: operation.source.clone(),
+            destination: operation.destination.clone(),
+            fingerprint: operation.fingerprint.clone(),
+            parent: None,
+        }])
+    }
+
+    /// The moves of the most recent batch, in the order they were made
+    ///
+    /// File creations have nothing to move back and are left out.
+    pub fn last_batch(&self) -> Result<Vec<MappedMove>, Box<dyn Error>> {
+        let Some(last) = self.operations.last() else {
+            return Err("No operations to undo".into());
+        };
+        if last.modes.is_some() {
+            return Err(
+                "The last operation changed permissions, not names; undo it with smv -u".into(),
+            );
+        }
+
+        if let Some(ref mapping) = last.mapping {
+            return Ok(serde_json::from_slice(&self.read_state(mapping)?)?);
+        }
+
+        let batch_size = match last.batch {
+            Some(ref batch) => self
+                .operations
+                .iter()
+                .rev()
+                .take_while(|op| op.mapping.is_none() && op.batch.as_ref() == Some(batch))
+                .count(),
+            None => 1,
+        };
+
+        let mut moves: Vec<MappedMove> = self.operations[self.operations.len() - batch_size..]
+            .iter()
+            .filter(|op| !op.source.as_os_str().is_empty())
+            .map(|op| MappedMove {
+                source: op.source.clone(),
+                destination: op.destination.clone(),
+                fingerprint: op.fingerprint.clone(),
+                parent: None,
+            })
+            .collect();
+        link_parents(&mut moves);
+        Ok(moves)
+    }
+
+    /// Move the `selected` files of `batch` back, recording that as a new operation
+    ///
+    /// The batch itself stays in the history; undoing the new entry moves the
+    /// selected files forward again. Files below a directory the batch renamed
+    /// are found wherever that directory is now, and go back into it if it
+    /// keeps its new name. Returns how many files were moved back.
+    pub fn undo_selected(
+        &mut self,
+        batch: &[MappedMove],
+        selected: &[usize],
+        force: bool,
+    ) -> Result<usize, Box<dyn Error>> {
+        let mut reverted = Vec::new();
+        for &index in selected.iter().rev() {
+            let (source, destination) = locate(batch, index);
+            let fingerprint = &batch[index].fingerprint;
+            if !destination.exists() || source.exists() {
+                self.reporter.warn(&format!(
+                    "Skipping '{}' (moved or replaced since)",
+                    destination.display()
+                ));
+                continue;
+            }
+            if let Some(reason) = fingerprint
+                .as_ref()
+                .and_then(|f| f.difference(&destination))
+            {
+                if !force {
+                    self.reporter.warn(&format!(
+                        "Skipping '{}' (modified since the move: {}; use -F to undo anyway)",
+                        destination.display(),
+                        reason
+                    ));
+                    continue;
+                }
+                self.reporter.warn(&format!(
+                    "Warning: '{}' was modified since the move ({})",
+                    destination.display(),
+                    reason
+                ));
+            }
+            if let Some(parent) = source.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(&destination, &source)?;
+            self.reporter.info(&format!(
+                "Undone: Moved '{}' back to '{}'",
+                destination.display(),
+                source.display()
+            ));
+            reverted.push((destination, source));
+        }
+
+        if !reverted.is_empty() {
+            reverted.reverse();
+            let root = common_ancestor(reverted.iter().flat_map(|(a, b)| [a, b]));
+            self.start_batch();
+            self.record_mapping(root, &reverted)?;
+            self.log_undo()?;
+        }
+        Ok(reverted.len())
+    }
+
     /// Get a list of recorded operations
-    #[allow(dead_code)]
     pub fn list_operations(&self) -> &[Operation] {
         &self.operations
     }
 
+    /// Recorded operations grouped by the command that made them, oldest first
+    pub fn batches(&self) -> Vec<&[Operation]> {
+        self.batch_ranges()
+            .into_iter()
+            .map(|range| &self.operations[range])
+            .collect()
+    }
+
+    /// Prune the oldest batches until the history fits `retention`
+    ///
+    /// Batches are removed whole, together with their backups and mapping files.
+    /// Backups no remaining entry refers to are removed too. With `dry_run` only
+    /// the report is computed.
+    pub fn gc(&mut self, retention: &Retention, dry_run: bool) -> Result<GcReport, Box<dyn Error>> {
+        let batches = self.batch_ranges();
+        let file_size = |path: &PathBuf| owned_size(path);
+        let batch_bytes: Vec<u64> = batches
+            .iter()
+            .map(|range| {
+                self.operations[range.clone()]
+                    .iter()
+                    .flat_map(Operation::owned_files)
+                    .map(file_size)
+                    .sum()
+            })
+            .collect();
+
+        let cutoff = retention.max_age.map(|age| Local::now() - age);
+        let mut first_kept = 0;
+        let mut kept_entries = self.operations.len();
+        let mut kept_bytes: u64 = batch_bytes.iter().sum();
+        while first_kept < batches.len() {
+            let range = &batches[first_kept];
+            let too_old =
+                cutoff.is_some_and(|cutoff| self.operations[range.end - 1].timestamp < cutoff);
+            let too_many = retention.max_entries.is_some_and(|max| kept_entries > max);
+            let too_big = retention.max_bytes.is_some_and(|max| kept_bytes > max);
+            if !(too_old || too_many || too_big) {
+                break;
+            }
+            kept_entries -= range.len();
+            kept_bytes -= batch_bytes[first_kept];
+            first_kept += 1;
+        }
+
+        let split = batches
+            .get(first_kept)
+            .map_or(self.operations.len(), |range| range.start);
+        let mut report = GcReport {
+            batches: first_kept,
+            entries: split,
+            ..GcReport::default()
+        };
+
+        let mut doomed: Vec<PathBuf> = self.operations[..split]
+            .iter()
+            .flat_map(Operation::owned_files)
+            .filter(|path| path.exists())
+            .cloned()
+            .collect();
+        doomed.extend(self.orphaned_files(split)?);
+
+        for path in &doomed {
+            report.bytes += file_size(path);
+            report.files += 1;
+            if !dry_run {
+                remove_owned(path)?;
+            }
+        }
+
+        if !dry_run && split > 0 {
+            self.operations.drain(..split);
+            self.save_history()?;
+        }
+        Ok(report)
+    }
+
+    /// Undo every operation of batch `number`, counting [`batches`](Self::batches)
+    /// from the newest (1); returns how many operations that was
+    ///
+    /// Later batches stay recorded and untouched. The chosen one is moved to the
+    /// end of the history and undone from there, newest operation first, with
+    /// the checks of [`undo_checked`](Self::undo_checked); what an error stops
+    /// at stays recorded for another try.
+    pub fn undo_batch(&mut self, number: usize, force: bool) -> Result<usize, Box<dyn Error>> {
+        let range = self.batch_range(number)?;
+        let count = range.len();
+        let batch: Vec<Operation> = self.operations.drain(range).collect();
+        self.operations.extend(batch);
+        for _ in 0..count {
+            self.undo_checked(force)?;
+        }
+        Ok(count)
+    }
+
+    /// Where batch `number` (1 is the newest) sits in the history
+    fn batch_range(&self, number: usize) -> Result<std::ops::Range<usize>, Box<dyn Error>> {
+        let ranges = self.batch_ranges();
+        if ranges.is_empty() {
+            return Err("No operations to undo".into());
+        }
+        number
+            .checked_sub(1)
+            .and_then(|index| ranges.iter().rev().nth(index))
+            .cloned()
+            .ok_or_else(|| {
+                format!(
+                    "No batch {number} in the history (smv history lists {})",
+                    ranges.len()
+                )
+                .into()
+            })
+    }
+
+    /// Index ranges of consecutive operations sharing a batch
+    fn batch_ranges(&self) -> Vec<std::ops::Range<usize>> {
+        let mut ranges: Vec<std::ops::Range<usize>> = Vec::new();
+        for (index, operation) in self.operations.iter().enumerate() {
+            match ranges.last_mut() {
+                Some(range)
+                    if operation.batch.is_some()
+                        && operation.batch == self.operations[range.end - 1].batch =>
+                {
+                    range.end = index + 1;
+                }
+                _ => ranges.push(index..index + 1),
+            }
+        }
+        ranges
+    }
+
+    /// Files in the backup directory that no operation from `first_kept` on needs
+    ///
+    /// Older entries found their backups by name, so only files older than the
+    /// oldest kept entry (with a minute of slack) count as orphaned.
+    fn orphaned_files(&self, first_kept: usize) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        if !self.backup_directory.is_dir() {
+            return Ok(Vec::new());
+        }
+        let kept = &self.operations[first_kept..];
+        let referenced: std::collections::HashSet<&PathBuf> = self
+            .operations
+            .iter()
+            .flat_map(Operation::owned_files)
+            .collect();
+        let oldest_kept: Option<SystemTime> = kept
+            .first()
+            .map(|op| SystemTime::from(op.timestamp) - std::time::Duration::from_secs(60));
+
+        let mut orphans = Vec::new();
+        for entry in fs::read_dir(&self.backup_directory)? {
+            let path = entry?.path();
+            // The state dir holds more than history: sessions, bookmarks, indexes
+            let is_record = path
+                .file_name()
+                .is_some_and(|name| is_history_record(&name.to_string_lossy()));
+            if !is_record || !path.is_file() || referenced.contains(&path) {
+                continue;
+            }
+            let modified = fs::metadata(&path)?.modified()?;
+            if oldest_kept.is_none_or(|oldest| modified < oldest) {
+                orphans.push(path);
+            }
+        }
+        Ok(orphans)
+    }
+
     /// Create a backup of a file
-    fn create_backup(&self, file_path: &Path) -> Result<(), Box<dyn Error>> {
+    ///
+    /// Backups are named `NAME_YYYYMMDD_HHMMSS`, which is how `gc` tells them
+    /// from the other files in the state dir.
+    fn create_backup(&self, file_path: &Path) -> Result<PathBuf, Box<dyn Error>> {
         // Ensure backup directory exists
         fs::create_dir_all(&self.backup_directory)?;
 
@@ -129,12 +1436,17 @@ impl HistoryManager {
             .to_string_lossy();
         let timestamp = Local::now().format("%Y%m%d_%H%M%S").to_string();
         let backup_name = format!("{filename}_{timestamp}");
-        let backup_path = self.backup_directory.join(backup_name);
+        let backup_path = self.state_path(self.backup_directory.join(backup_name));
 
         // Copy the file to backup
-        fs::copy(file_path, &backup_path)?;
+        match self.key {
+            Some(ref key) => key.encrypt_file(file_path, &backup_path)?,
+            None => {
+                fs::copy(file_path, &backup_path)?;
+            }
+        }
 
-        Ok(())
+        Ok(backup_path)
     }
 
     /// Restore a file from backup
@@ -172,13 +1484,7 @@ impl HistoryManager {
 
         // Restore the most recent backup if found
         if let Some(backup_path) = backups.first() {
-            // Ensure parent directory exists
-            if let Some(parent) = file_path.parent() {
-                fs::create_dir_all(parent)?;
-            }
-
-            fs::copy(backup_path, file_path)?;
-            Ok(())
+            self.copy_backup(backup_path, file_path)
         } else {
             Err("No backup found for this file".into())
         }
@@ -193,16 +1499,28 @@ impl HistoryManager {
 
         // Serialize operations to JSON
         let json = serde_json::to_string_pretty(&self.operations)?;
-        fs::write(&self.history_file, json)?;
+        self.write_state(&self.history_file, json.as_bytes())?;
+
+        // A plaintext journal left from before encryption was enabled
+        let plain = self.backup_directory.join(HISTORY_FILE);
+        if self.key.is_some() && plain.exists() {
+            fs::remove_file(plain)?;
+        }
 
         Ok(())
     }
 
     /// Load history from disk
     fn load_history(&mut self) -> Result<(), Box<dyn Error>> {
-        if self.history_file.exists() {
-            let json = fs::read_to_string(&self.history_file)?;
-            let operations: Vec<Operation> = serde_json::from_str(&json)?;
+        // Fall back to a plaintext journal when encryption was just enabled
+        let plain = self.backup_directory.join(HISTORY_FILE);
+        let journal = if self.history_file.exists() {
+            &self.history_file
+        } else {
+            &plain
+        };
+        if journal.exists() {
+            let operations: Vec<Operation> = serde_json::from_slice(&self.read_state(journal)?)?;
 
             // Only keep operations up to max_history_size
             let start_index = if operations.len() > self.max_history_size {
@@ -216,3 +1534,708 @@ impl HistoryManager {
         Ok(())
     }
 }
+
+/// Move a file or directory, copying it when `to` is on another filesystem
+fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let moves = [(from.to_path_buf(), to.to_path_buf())];
+            if let Some(full) = disk_usage::UsagePlan::new(&moves)
+                .filesystem_checks()
+                .into_iter()
+                .find(|usage| !usage.fits())
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::StorageFull,
+                    format!(
+                        "'{}' needs {} but only {} are free on the filesystem of '{}'",
+                        from.display(),
+                        disk_usage::format_size(full.incoming),
+                        disk_usage::format_size(full.available.unwrap_or(0)),
+                        full.example.display()
+                    ),
+                ));
+            }
+            copy_tree(from, to)?;
+            if from.is_dir() && !from.is_symlink() {
+                fs::remove_dir_all(from)
+            } else {
+                fs::remove_file(from)
+            }
+        }
+        result => result,
+    }
+}
+
+pub(crate) fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+    for entry in walkdir::WalkDir::new(from) {
+        let entry = entry.map_err(io::Error::other)?;
+        let relative = entry.path().strip_prefix(from).map_err(io::Error::other)?;
+        let target = to.join(relative);
+        if entry.path_is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &target)?;
+            #[cfg(not(unix))]
+            fs::copy(entry.path(), &target)?;
+        } else if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove a backup or mapping file, or a whole trash directory
+fn remove_owned(path: &Path) -> io::Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Bytes a backup, mapping file or trash directory takes up
+fn owned_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+fn is_encrypted(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == journal::ENCRYPTED_EXTENSION)
+}
+
+/// Parse a selection such as `1,3-5` or `all` into zero-based indexes below `count`
+pub fn parse_selection(input: &str, count: usize) -> Result<Vec<usize>, String> {
+    let input = input.trim();
+    if input.eq_ignore_ascii_case("all") {
+        return Ok((0..count).collect());
+    }
+
+    let number = |text: &str| -> Result<usize, String> {
+        let value: usize = text
+            .trim()
+            .parse()
+            .map_err(|_| format!("'{}' is not a file number", text.trim()))?;
+        if value == 0 || value > count {
+            return Err(format!("{value} is out of range (1-{count})"));
+        }
+        Ok(value - 1)
+    };
+
+    let mut indexes = Vec::new();
+    for part in input
+        .split([',', ' '])
+        .filter(|part| !part.trim().is_empty())
+    {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (number(start)?, number(end)?);
+                if start > end {
+                    return Err(format!("range '{part}' runs backwards"));
+                }
+                indexes.extend(start..=end);
+            }
+            None => indexes.push(number(part)?),
+        }
+    }
+    indexes.sort_unstable();
+    indexes.dedup();
+    Ok(indexes)
+}
+
+/// Why undo would leave the permissions of `change` as they are, if it would
+fn mode_problem(change: &ModeChange, force: bool) -> Option<String> {
+    match attr::mode_of(&change.path) {
+        Err(_) => Some("no longer there".to_string()),
+        Ok(mode) if mode != change.after && !force => Some(format!(
+            "permissions changed since to {}; use -F to undo anyway",
+            attr::format_mode(mode)
+        )),
+        Ok(_) => None,
+    }
+}
+
+/// The moves back of the `indexes` of a batch, in the order undo makes them
+///
+/// Files are skipped for the same reasons [`HistoryManager::undo_selected`]
+/// and mapping undos skip them. Paths are shown where they will be by their turn,
+/// inside the directories earlier steps have moved back.
+fn plan_moves_back(
+    moves: &[MappedMove],
+    indexes: impl Iterator<Item = usize>,
+    force: bool,
+) -> Vec<UndoStep> {
+    // Directories moved back so far: where each is now, and where it goes
+    let mut moved_dirs: Vec<(PathBuf, PathBuf)> = Vec::new();
+    indexes
+        .map(|index| {
+            let (source, destination) = locate(moves, index);
+            let by_then = |path: &Path| {
+                moved_dirs
+                    .iter()
+                    .filter_map(|(now, back)| Some((now, back.join(path.strip_prefix(now).ok()?))))
+                    .max_by_key(|(now, _)| now.components().count())
+                    .map_or_else(|| path.to_path_buf(), |(_, path)| path)
+            };
+            let (shown_source, shown_destination) = (by_then(&source), by_then(&destination));
+            let problem = if !destination.exists() || source.exists() {
+                Some("moved or replaced since".to_string())
+            } else if force {
+                None
+            } else {
+                moves[index]
+                    .fingerprint
+                    .as_ref()
+                    .and_then(|f| f.difference(&destination))
+                    .map(|reason| {
+                        format!("modified since the move: {reason}; use -F to undo anyway")
+                    })
+            };
+            if problem.is_none() && destination.is_dir() {
+                moved_dirs.push((destination, shown_source.clone()));
+            }
+            UndoStep::new(UndoAction::MoveBack, shown_destination, Some(shown_source))
+                .problem(problem)
+        })
+        .collect()
+}
+
+/// Point each move at the move that renamed its nearest ancestor directory
+///
+/// Only paths some move of the batch starts or ends at can be such a
+/// directory, so every ancestor is looked up instead of comparing all pairs.
+fn link_parents(moves: &mut [MappedMove]) {
+    let mut renamed: HashMap<&Path, usize> = HashMap::new();
+    for (index, entry) in moves.iter().enumerate() {
+        renamed.entry(&entry.source).or_insert(index);
+        renamed.entry(&entry.destination).or_insert(index);
+    }
+    let parents: Vec<Option<usize>> = moves
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            [&entry.source, &entry.destination]
+                .into_iter()
+                .filter_map(|path| {
+                    path.ancestors().skip(1).find_map(|dir| {
+                        let j = *renamed.get(dir)?;
+                        (j != index).then(|| (j, dir.components().count()))
+                    })
+                })
+                // The deeper of the two ancestors is the nearer one
+                .max_by_key(|&(_, depth)| depth)
+                .map(|(j, _)| j)
+        })
+        .collect();
+    for (entry, parent) in moves.iter_mut().zip(parents) {
+        entry.parent = parent;
+    }
+}
+
+/// Rename `from` to `to`, making the directories `to` needs
+fn move_back(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(from, to)
+}
+
+/// Where move `index` of a batch goes back from and to right now
+///
+/// A recorded path below a directory the batch renamed is rebased onto
+/// wherever that directory is now: its new name, or its old one if that
+/// rename was undone on its own.
+fn locate(moves: &[MappedMove], index: usize) -> (PathBuf, PathBuf) {
+    locate_within(moves, index, moves.len())
+}
+
+/// [`locate`] following at most `depth` parents, so odd batches whose moves
+/// are each other's parents cannot loop
+fn locate_within(moves: &[MappedMove], index: usize, depth: usize) -> (PathBuf, PathBuf) {
+    let entry = &moves[index];
+    let Some(parent) = entry.parent.filter(|&p| depth > 0 && p < moves.len()) else {
+        return (entry.source.clone(), entry.destination.clone());
+    };
+    let (old_dir, new_dir) = locate_within(moves, parent, depth - 1);
+    let dir = if new_dir.exists() || !old_dir.exists() {
+        new_dir
+    } else {
+        old_dir
+    };
+    let recorded = &moves[parent];
+    let rebase = |path: &Path| {
+        path.strip_prefix(&recorded.destination)
+            .or_else(|_| path.strip_prefix(&recorded.source))
+            .map_or_else(|_| path.to_path_buf(), |rest| dir.join(rest))
+    };
+    (rebase(&entry.source), rebase(&entry.destination))
+}
+
+/// Deepest directory containing every path
+fn common_ancestor<'a>(paths: impl Iterator<Item = &'a PathBuf>) -> PathBuf {
+    let mut common: Option<PathBuf> = None;
+    for path in paths {
+        let parent = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        common = Some(match common {
+            None => parent,
+            Some(current) => current
+                .ancestors()
+                .find(|ancestor| parent.starts_with(ancestor))
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+        });
+    }
+    common.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_detects_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "hello").unwrap();
+
+        let fingerprint = Fingerprint::of(&file).unwrap();
+        assert_eq!(fingerprint.size, 5);
+        assert!(fingerprint.hash.is_some());
+        assert_eq!(fingerprint.difference(&file), None);
+
+        // Same size, different content
+        fs::write(&file, "jello").unwrap();
+        assert!(fingerprint.difference(&file).is_some());
+
+        assert!(Fingerprint::of(dir.path()).is_none());
+    }
+
+    #[test]
+    fn test_undo_refuses_modified_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("old.txt");
+        let destination = dir.path().join("new.txt");
+        fs::write(&source, "data").unwrap();
+
+        let mut history = HistoryManager::new(10, &dir.path().join("backups"));
+        history.record(source.clone(), destination.clone()).unwrap();
+        fs::rename(&source, &destination).unwrap();
+        fs::write(&destination, "changed later").unwrap();
+
+        assert!(history.undo().is_err());
+        assert!(destination.exists());
+
+        history.undo_checked(true).unwrap();
+        assert!(source.exists());
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn test_encrypted_history_refuses_the_trash() {
+        use age::secrecy::ExposeSecret;
+
+        let dir = tempfile::tempdir().unwrap();
+        let key_file = dir.path().join("key.txt");
+        let identity = age::x25519::Identity::generate();
+        fs::write(&key_file, identity.to_string().expose_secret()).unwrap();
+        let key = JournalKey::load(&key_file).unwrap();
+
+        let history = HistoryManager::with_key(10, &dir.path().join("backups"), Some(key));
+        let error = history.trash().unwrap_err().to_string();
+        assert!(error.contains("--permanent"), "{error}");
+    }
+
+    #[test]
+    fn test_undo_restores_trashed_items() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        fs::create_dir_all(project.join("src/nested")).unwrap();
+        fs::write(project.join("src/nested/main.rs"), "fn main() {}").unwrap();
+        let notes = dir.path().join("notes.txt");
+        let todo = dir.path().join("todo.txt");
+        fs::write(&notes, "notes").unwrap();
+        fs::write(&todo, "todo").unwrap();
+
+        let mut history = HistoryManager::new(10, &dir.path().join("backups"));
+        let mut trash = history.trash().unwrap();
+        for path in [&project, &notes, &todo] {
+            trash.put(path).unwrap();
+        }
+        history.record_trash(&trash).unwrap();
+        assert!(!project.exists() && !notes.exists());
+
+        // Something new took one name; everything else comes back
+        fs::write(&todo, "new").unwrap();
+        assert!(history.undo().is_err());
+        assert_eq!(
+            fs::read_to_string(project.join("src/nested/main.rs")).unwrap(),
+            "fn main() {}"
+        );
+        assert_eq!(fs::read_to_string(&notes).unwrap(), "notes");
+
+        // The one left behind is restored once its place is free again
+        fs::remove_file(&todo).unwrap();
+        history.undo().unwrap();
+        assert_eq!(fs::read_to_string(&todo).unwrap(), "todo");
+        assert!(history.list_operations().is_empty());
+        assert!(
+            !dir.path()
+                .join("backups")
+                .join(TRASH_DIR)
+                .join(&history.batch)
+                .exists()
+        );
+    }
+
+    #[test]
+    fn test_parse_selection() {
+        assert_eq!(parse_selection("1,3-5", 6), Ok(vec![0, 2, 3, 4]));
+        assert_eq!(parse_selection("2 2 1", 3), Ok(vec![0, 1]));
+        assert_eq!(parse_selection("all", 2), Ok(vec![0, 1]));
+        assert!(parse_selection("0", 3).is_err());
+        assert!(parse_selection("4", 3).is_err());
+        assert!(parse_selection("3-1", 3).is_err());
+    }
+
+    #[test]
+    fn test_failed_mapping_undo_rolls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("B.txt"), "b").unwrap();
+        let moves = [
+            (root.join("one/a.txt"), root.join("a.txt")),
+            (root.join("b.txt"), root.join("B.txt")),
+        ];
+        let mut history = HistoryManager::new(10, &root.join("backups"));
+        history.record_mapping(root.to_path_buf(), &moves).unwrap();
+
+        // b.txt goes back first, then a file in the way stops one/a.txt
+        fs::write(root.join("one"), "in the way").unwrap();
+        let error = history.undo().unwrap_err().to_string();
+        assert!(error.contains("Undo stopped at"), "{error}");
+        assert!(error.contains("batch is unchanged"), "{error}");
+        assert!(root.join("B.txt").exists() && !root.join("b.txt").exists());
+        assert_eq!(history.list_operations().len(), 1);
+
+        fs::remove_file(root.join("one")).unwrap();
+        history.undo().unwrap();
+        assert!(root.join("one/a.txt").exists() && root.join("b.txt").exists());
+        assert!(history.list_operations().is_empty());
+    }
+
+    #[test]
+    fn test_undo_selected_records_new_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let names = ["a.txt", "b.txt", "c.txt"];
+        let mut moves = Vec::new();
+        fs::create_dir(dir.path().join("out")).unwrap();
+        for name in names {
+            let from = dir.path().join(name);
+            let to = dir.path().join("out").join(name);
+            fs::write(&from, name).unwrap();
+            fs::rename(&from, &to).unwrap();
+            moves.push((from, to));
+        }
+
+        let mut history = HistoryManager::new(10, &dir.path().join("backups"));
+        history
+            .record_mapping(dir.path().to_path_buf(), &moves)
+            .unwrap();
+
+        let batch = history.last_batch().unwrap();
+        assert_eq!(batch.len(), 3);
+        assert_eq!(history.undo_selected(&batch, &[0, 2], false).unwrap(), 2);
+        assert!(dir.path().join("a.txt").exists());
+        assert!(dir.path().join("out").join("b.txt").exists());
+        assert!(dir.path().join("c.txt").exists());
+        assert_eq!(history.list_operations().len(), 2);
+        let batches = history.batches();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(history.files_of(&batches[1][0]).unwrap().len(), 2);
+
+        // Undoing the partial undo moves the files forward again
+        history.undo().unwrap();
+        assert!(dir.path().join("out").join("a.txt").exists());
+        assert!(dir.path().join("out").join("c.txt").exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_undo_restores_modes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("run.sh");
+        let notes = dir.path().join("notes.txt");
+        for (file, mode) in [(&script, 0o755), (&notes, 0o600)] {
+            fs::write(file, "").unwrap();
+            fs::set_permissions(file, fs::Permissions::from_mode(mode)).unwrap();
+        }
+        let mut history = HistoryManager::new(10, &dir.path().join("backups"));
+        history
+            .record_modes(
+                dir.path().to_path_buf(),
+                &[
+                    ModeChange {
+                        path: script.clone(),
+                        before: 0o644,
+                        after: 0o755,
+                    },
+                    // Changed again after the attribute change
+                    ModeChange {
+                        path: notes.clone(),
+                        before: 0o664,
+                        after: 0o644,
+                    },
+                ],
+            )
+            .unwrap();
+        assert_eq!(
+            history
+                .files_of(&history.list_operations()[0])
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(history.last_batch().is_err());
+        let problems: Vec<bool> = history
+            .plan_undo_batch(1, false)
+            .unwrap()
+            .iter()
+            .map(|step| step.problem.is_some())
+            .collect();
+        assert_eq!(problems, [true, false]);
+
+        history.undo().unwrap();
+        let mode = |file: &Path| fs::metadata(file).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&script), 0o644);
+        assert_eq!(mode(&notes), 0o600);
+        assert!(history.list_operations().is_empty());
+    }
+
+    #[test]
+    fn test_plan_undo_changes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut moves = Vec::new();
+        fs::create_dir(dir.path().join("out")).unwrap();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            let from = dir.path().join(name);
+            let to = dir.path().join("out").join(name);
+            fs::write(&to, name).unwrap();
+            moves.push((from, to));
+        }
+        let mut history = HistoryManager::new(10, &dir.path().join("backups"));
+        history
+            .record_mapping(dir.path().to_path_buf(), &moves)
+            .unwrap();
+        fs::write(dir.path().join("out/b.txt"), "edited since").unwrap();
+        fs::remove_file(dir.path().join("out/c.txt")).unwrap();
+
+        let steps = history.plan_undo_batch(1, false).unwrap();
+        assert_eq!(steps.len(), 3);
+        assert!(steps.iter().all(|s| s.action == UndoAction::MoveBack));
+        let problems: Vec<bool> = steps.iter().map(|s| s.problem.is_some()).collect();
+        assert_eq!(problems, [true, true, false]);
+        assert!(steps[1].problem.as_ref().unwrap().contains("modified"));
+        assert!(
+            history.plan_undo_batch(1, true).unwrap()[1]
+                .problem
+                .is_none()
+        );
+        assert!(!dir.path().join("a.txt").exists());
+        assert_eq!(history.list_operations().len(), 1);
+
+        let batch = history.last_batch().unwrap();
+        let steps = history.plan_undo_selected(&batch, &[0], false);
+        assert_eq!(
+            steps[0].to.as_deref(),
+            Some(dir.path().join("a.txt").as_path())
+        );
+    }
+
+    #[test]
+    fn test_plan_undo_follows_directories_moved_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("dir/sub")).unwrap();
+        fs::write(root.join("dir/sub/file.txt"), "x").unwrap();
+        // Renamed bottom-up, so each move is recorded under the old parent names
+        let moves = [
+            (root.join("Dir/Sub/File.txt"), root.join("Dir/Sub/file.txt")),
+            (root.join("Dir/Sub"), root.join("Dir/sub")),
+            (root.join("Dir"), root.join("dir")),
+        ];
+        let mut history = HistoryManager::new(10, &root.join("backups"));
+        history.record_mapping(root.to_path_buf(), &moves).unwrap();
+
+        let steps = history.plan_undo_batch(1, false).unwrap();
+        let shown: Vec<(PathBuf, PathBuf)> = steps
+            .iter()
+            .map(|step| (step.path.clone(), step.to.clone().unwrap()))
+            .collect();
+        assert_eq!(
+            shown,
+            [
+                (root.join("dir"), root.join("Dir")),
+                (root.join("Dir/sub"), root.join("Dir/Sub")),
+                (root.join("Dir/Sub/file.txt"), root.join("Dir/Sub/File.txt")),
+            ]
+        );
+        assert!(steps.iter().all(|step| step.problem.is_none()));
+
+        history.undo().unwrap();
+        assert!(root.join("Dir/Sub/File.txt").exists());
+    }
+
+    #[test]
+    fn test_undo_batch_reverts_an_older_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let mut history = HistoryManager::new(10, &root.join("backups"));
+        for name in ["a", "b", "c"] {
+            let (from, to) = (root.join(name), root.join(name.to_uppercase()));
+            fs::write(&to, name).unwrap();
+            history.start_batch();
+            history
+                .record_mapping(root.to_path_buf(), &[(from, to)])
+                .unwrap();
+        }
+
+        assert!(history.undo_batch(4, false).is_err());
+        assert_eq!(history.undo_batch(2, false).unwrap(), 1);
+        assert!(root.join("b").exists() && !root.join("B").exists());
+        assert!(root.join("A").exists() && root.join("C").exists());
+        assert_eq!(history.batches().len(), 2);
+
+        // The newest batch is still the one plain undo takes back
+        history.undo().unwrap();
+        assert!(root.join("c").exists() && root.join("A").exists());
+    }
+
+    #[test]
+    fn test_skipped_mapping_moves_stay_undoable() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut moves = Vec::new();
+        for name in ["a", "b"] {
+            let (from, to) = (
+                dir.path().join(format!("{name}.txt")),
+                dir.path().join(name),
+            );
+            fs::write(&to, name).unwrap();
+            moves.push((from, to));
+        }
+        let mut history = HistoryManager::new(10, &dir.path().join("backups"));
+        history
+            .record_mapping(dir.path().to_path_buf(), &moves)
+            .unwrap();
+        fs::write(dir.path().join("b"), "edited since").unwrap();
+
+        // The untouched file goes back; the edited one keeps the entry alive
+        assert!(history.undo().is_err());
+        assert!(dir.path().join("a.txt").exists());
+        assert!(dir.path().join("b").exists());
+        assert_eq!(history.list_operations().len(), 1);
+        assert_eq!(history.last_batch().unwrap().len(), 1);
+
+        history.undo_checked(true).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("b.txt")).unwrap(),
+            "edited since"
+        );
+        assert!(history.list_operations().is_empty());
+    }
+
+    #[test]
+    fn test_partial_undo_follows_renamed_parents() {
+        let dir = tempfile::tempdir().unwrap();
+        let (old_dir, new_dir) = (dir.path().join("Dir A"), dir.path().join("dir-a"));
+        fs::create_dir(&old_dir).unwrap();
+        fs::write(old_dir.join("X.txt"), "x").unwrap();
+        fs::rename(&old_dir, &new_dir).unwrap();
+
+        // The directory was renamed after the file inside it
+        let mut history = HistoryManager::new(10, &dir.path().join("backups"));
+        let moves = [
+            (old_dir.join("x.txt"), old_dir.join("X.txt")),
+            (old_dir.clone(), new_dir.clone()),
+        ];
+        history
+            .record_mapping(dir.path().to_path_buf(), &moves)
+            .unwrap();
+        let batch = history.last_batch().unwrap();
+        assert_eq!(batch[0].parent, Some(1));
+
+        // Undoing only the file renames it inside the directory's new name
+        assert_eq!(history.undo_selected(&batch, &[0], false).unwrap(), 1);
+        assert!(new_dir.join("x.txt").exists());
+
+        // Undoing the directory on its own first, then the file, finds it under the old name
+        fs::rename(new_dir.join("x.txt"), new_dir.join("X.txt")).unwrap();
+        assert_eq!(history.undo_selected(&batch, &[1], false).unwrap(), 1);
+        assert!(old_dir.join("X.txt").exists());
+        assert_eq!(history.undo_selected(&batch, &[0], false).unwrap(), 1);
+        assert!(old_dir.join("x.txt").exists());
+    }
+
+    #[test]
+    fn test_gc_prunes_oldest_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let backups = dir.path().join("backups");
+        let mut history = HistoryManager::new(100, &backups);
+        for name in ["one", "two", "three"] {
+            let file = dir.path().join(name);
+            fs::write(&file, "0123456789").unwrap();
+            history.start_batch();
+            history
+                .record(file.clone(), dir.path().join("moved"))
+                .unwrap();
+        }
+        // An old backup nothing refers to any more, and other state that is no backup
+        let stray = backups.join("stray.txt_20240101_120000");
+        let session = backups.join("repl-session.json");
+        let bookmarks = backups.join("tui_bookmarks.json");
+        let old = SystemTime::now() - std::time::Duration::from_secs(3600);
+        for path in [&stray, &session, &bookmarks] {
+            fs::write(path, "x").unwrap();
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(old)
+                .unwrap();
+        }
+
+        let retention = Retention {
+            max_entries: Some(1),
+            ..Retention::default()
+        };
+        let preview = history.gc(&retention, true).unwrap();
+        assert_eq!(preview.entries, 2);
+        assert_eq!(history.list_operations().len(), 3);
+
+        let report = history.gc(&retention, false).unwrap();
+        assert_eq!(report, preview);
+        assert_eq!(report.files, 3);
+        assert_eq!(report.bytes, 21);
+        assert_eq!(history.list_operations().len(), 1);
+        assert!(!stray.exists());
+        assert!(session.exists() && bookmarks.exists());
+        assert!(is_history_record("mapping_20240101_120000.123.json.age"));
+        assert!(!is_history_record("_20240101_120000"));
+        assert!(
+            history.list_operations()[0]
+                .backup
+                .as_ref()
+                .unwrap()
+                .exists()
+        );
+    }
+}
@@ -0,0 +1,292 @@
+//! Saved listings of large trees (`smv index build|update|clear DIR`)
+//!
+//! Walking a huge archive that barely changes costs the same every time. `smv
+//! index build photos` records every path below `photos/` with its type, size
+//! and modification time (and, with `--hash`, a content hash of each file) in
+//! the state directory. CNP commands on exactly that directory then filter the
+//! saved listing instead of walking it again.
+//!
+//! An index is only used while it is fresh: every directory in it still has the
+//! modification time it was indexed with, so nothing was added, removed or
+//! renamed since. A stale index is passed over with a note until `smv index
+//! update` brings it up to date. Changes to the contents of files do not touch
+//! their directory, so sizes and times may lag behind until then.
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::hash;
+use crate::journal::{self, JournalKey};
+use crate::paths;
+
+/// Directory inside the state directory holding one index per root
+const INDEX_DIR: &str = "index";
+
+/// What an indexed path is, following symlinks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    File,
+    Dir,
+    /// Anything else, and symlinks that point nowhere
+    Other,
+}
+
+/// One path below the root
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Relative to the root
+    pub path: PathBuf,
+    pub kind: Kind,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub link: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Nanoseconds since the epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    /// `ALGO:HEX` of the content, with `--hash`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl Entry {
+    pub fn digest(&self) -> Option<hash::Digest> {
+        self.hash.as_deref().and_then(hash::Digest::parse)
+    }
+
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+            .map(|nanos| UNIX_EPOCH + std::time::Duration::from_nanos(nanos))
+    }
+}
+
+/// The saved listing of one directory tree
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Index {
+    /// Canonical path of the indexed directory
+    pub root: PathBuf,
+    pub built: DateTime<Local>,
+    /// Whether files carry a content hash
+    #[serde(default)]
+    pub hashes: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root_modified: Option<u64>,
+    /// In the order the walk found them
+    pub entries: Vec<Entry>,
+}
+
+/// What `smv index update` changed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Changes {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+}
+
+impl Index {
+    /// Walk `root` and record everything below it
+    ///
+    /// Hashes of files whose size and time match `previous` are taken from it
+    /// rather than read again.
+    pub fn build(
+        root: &Path,
+        hashes: bool,
+        previous: Option<&Index>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let root = root.canonicalize()?;
+        if !root.is_dir() {
+            return Err(format!("Not a directory: {}", root.display()).into());
+        }
+        let known: HashMap<&Path, &Entry> = previous
+            .map(|index| {
+                index
+                    .entries
+                    .iter()
+                    .map(|entry| (entry.path.as_path(), entry))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut entries = Vec::new();
+        for dir_entry in walkdir::WalkDir::new(&root)
+            .min_depth(1)
+            .into_iter()
+            .filter_map(Result::ok)
+        {
+            let path = dir_entry.path();
+            let Ok(relative) = path.strip_prefix(&root) else {
+                continue;
+            };
+            let link = dir_entry.path_is_symlink();
+            let metadata = if link {
+                path.metadata().ok()
+            } else {
+                dir_entry.metadata().ok()
+            };
+            let kind = match &metadata {
+                Some(metadata) if metadata.is_file() => Kind::File,
+                Some(metadata) if metadata.is_dir() => Kind::Dir,
+                _ => Kind::Other,
+            };
+            let mut entry = Entry {
+                path: relative.to_path_buf(),
+                kind,
+                link,
+                size: metadata.as_ref().map(fs::Metadata::len),
+                modified: metadata
+                    .as_ref()
+                    .and_then(|metadata| nanos(metadata.modified().ok()?)),
+                hash: None,
+            };
+            if hashes && kind == Kind::File {
+                let algorithm = hash::algorithm();
+                entry.hash = match known.get(relative) {
+                    Some(old)
+                        if old.size == entry.size
+                            && old.modified == entry.modified
+                            && old.digest().is_some_and(|old| old.algorithm == algorithm) =>
+                    {
+                        old.hash.clone()
+                    }
+                    _ => hash::file(path, algorithm)
+                        .ok()
+                        .map(|digest| digest.to_string()),
+                };
+            }
+            entries.push(entry);
+        }
+
+        Ok(Self {
+            root_modified: modified_nanos(&root),
+            root,
+            built: Local::now(),
+            hashes,
+            entries,
+        })
+    }
+
+    /// The saved index of `root`, if there is one
+    pub fn load(root: &Path) -> Option<Self> {
+        let root = root.canonicalize().ok()?;
+        let key = JournalKey::from_env().ok()?;
+        let content = journal::read_state_file(key.as_ref(), &file_for(&root)).ok()??;
+        let index: Index = serde_json::from_slice(&content).ok()?;
+        (index.root == root).then_some(index)
+    }
+
+    pub fn save(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let file = file_for(&self.root);
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        journal::write_state_file(
+            JournalKey::from_env()?.as_ref(),
+            &file,
+            &serde_json::to_vec(self)?,
+        )
+    }
+
+    /// Remove the saved index of `root`; returns whether there was one
+    pub fn clear(root: &Path) -> Result<bool, Box<dyn Error>> {
+        journal::remove_state_file(&file_for(&root.canonicalize()?))
+    }
+
+    /// Why the index no longer matches the tree, if it does not
+    pub fn stale(&self) -> Option<String> {
+        if modified_nanos(&self.root) != self.root_modified {
+            return Some(format!("{} changed", self.root.display()));
+        }
+        self.entries
+            .iter()
+            .filter(|entry| entry.kind == Kind::Dir && !entry.link)
+            .find(|entry| modified_nanos(&self.root.join(&entry.path)) != entry.modified)
+            .map(|entry| format!("{} changed", self.root.join(&entry.path).display()))
+    }
+
+    /// What differs between this index and `newer`
+    pub fn changes(&self, newer: &Index) -> Changes {
+        let old: HashMap<&Path, &Entry> = self
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_path(), entry))
+            .collect();
+        let mut changes = Changes::default();
+        for entry in &newer.entries {
+            match old.get(entry.path.as_path()) {
+                None => changes.added += 1,
+                Some(old) if *old != entry => changes.changed += 1,
+                Some(_) => {}
+            }
+        }
+        changes.removed = self.entries.len() + changes.added - newer.entries.len();
+        changes
+    }
+}
+
+/// Where the index of the canonical directory `root` is kept
+fn file_for(root: &Path) -> PathBuf {
+    // FNV-1a of the path keeps file names short and free of separators
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in root.to_string_lossy().bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    paths::state_dir()
+        .join(INDEX_DIR)
+        .join(format!("{hash:016x}.json"))
+}
+
+fn nanos(time: SystemTime) -> Option<u64> {
+    u64::try_from(time.duration_since(UNIX_EPOCH).ok()?.as_nanos()).ok()
+}
+
+fn modified_nanos(path: &Path) -> Option<u64> {
+    nanos(fs::metadata(path).ok()?.modified().ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_stale_and_update() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("2023/raw")).unwrap();
+        fs::write(root.join("2023/raw/a.jpg"), "aaaa").unwrap();
+        fs::write(root.join("b.txt"), "b").unwrap();
+
+        let index = Index::build(root, true, None).unwrap();
+        assert_eq!(index.entries.len(), 4);
+        let a = index
+            .entries
+            .iter()
+            .find(|entry| entry.path == Path::new("2023/raw/a.jpg"))
+            .unwrap();
+        assert_eq!((a.kind, a.size), (Kind::File, Some(4)));
+        assert!(a.hash.is_some());
+        assert!(index.stale().is_none());
+
+        // A new file changes its directory, deep down too
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs::write(root.join("2023/raw/c.jpg"), "c").unwrap();
+        assert!(index.stale().unwrap().contains("raw"));
+
+        let updated = Index::build(root, true, Some(&index)).unwrap();
+        assert!(updated.stale().is_none());
+        assert_eq!(
+            index.changes(&updated),
+            Changes {
+                added: 1,
+                removed: 0,
+                changed: 1
+            }
+        );
+    }
+}
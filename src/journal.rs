@@ -0,0 +1,281 @@
+//! Encryption of the history journal and backups at rest
+//!
+//! History records full paths and backups hold whole files, which can leak data on
+//! shared machines. Pointing `SMV_HISTORY_KEY` at an age identity file (as written by
+//! `age-keygen -o key.txt`) makes SMV encrypt everything it writes to the state
+//! directory to that key. Encryption needs a build with the `encryption` feature.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Environment variable naming the identity file
+pub const KEY_ENV: &str = "SMV_HISTORY_KEY";
+
+/// Extension of encrypted journal, mapping and backup files
+pub const ENCRYPTED_EXTENSION: &str = "age";
+
+/// Key used to encrypt and decrypt state files
+#[derive(Clone)]
+pub struct JournalKey {
+    path: PathBuf,
+    #[cfg(feature = "encryption")]
+    identity: age::x25519::Identity,
+    #[cfg(not(feature = "encryption"))]
+    never: std::convert::Infallible,
+}
+
+impl std::fmt::Debug for JournalKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JournalKey")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl JournalKey {
+    /// The key named by `SMV_HISTORY_KEY`, if set
+    pub fn from_env() -> Result<Option<Self>, Box<dyn Error>> {
+        match std::env::var_os(KEY_ENV) {
+            Some(path) if !path.is_empty() => Self::load(Path::new(&path)).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Read the first `AGE-SECRET-KEY-` line of an identity file
+    #[cfg(feature = "encryption")]
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read history key '{}': {}", path.display(), e))?;
+        let line = contents
+            .lines()
+            .map(str::trim)
+            .find(|line| line.starts_with("AGE-SECRET-KEY-"))
+            .ok_or_else(|| format!("No age secret key found in '{}'", path.display()))?;
+        let identity = line
+            .parse()
+            .map_err(|e| format!("Invalid age secret key in '{}': {}", path.display(), e))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            identity,
+        })
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Err(format!(
+            "{} is set to '{}' but this smv was built without the 'encryption' feature",
+            KEY_ENV,
+            path.display()
+        )
+        .into())
+    }
+
+    /// Encrypt `plaintext` to this key
+    #[cfg(feature = "encryption")]
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut ciphertext = Vec::new();
+        self.encrypt_stream(&mut &plaintext[..], &mut ciphertext)?;
+        Ok(ciphertext)
+    }
+
+    /// Decrypt data written by [`JournalKey::encrypt`]
+    #[cfg(feature = "encryption")]
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut plaintext = Vec::new();
+        self.decrypt_stream(ciphertext, &mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    /// Encrypt the file at `from` into `to` without loading it whole
+    #[cfg(feature = "encryption")]
+    pub fn encrypt_file(&self, from: &Path, to: &Path) -> Result<(), Box<dyn Error>> {
+        let mut input = std::fs::File::open(from)?;
+        let output = std::fs::File::create(to)?;
+        self.encrypt_stream(&mut input, output)
+    }
+
+    /// Decrypt the file at `from` into `to` without loading it whole
+    #[cfg(feature = "encryption")]
+    pub fn decrypt_file(&self, from: &Path, to: &Path) -> Result<(), Box<dyn Error>> {
+        let input = std::io::BufReader::new(std::fs::File::open(from)?);
+        let output = std::fs::File::create(to)?;
+        self.decrypt_stream(input, output)
+    }
+
+    #[cfg(feature = "encryption")]
+    fn encrypt_stream(
+        &self,
+        input: &mut dyn std::io::Read,
+        output: impl std::io::Write,
+    ) -> Result<(), Box<dyn Error>> {
+        let recipient = self.identity.to_public();
+        let encryptor =
+            age::Encryptor::with_recipients(std::iter::once(&recipient as &dyn age::Recipient))?;
+        let mut writer = encryptor.wrap_output(output)?;
+        std::io::copy(input, &mut writer)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    #[cfg(feature = "encryption")]
+    fn decrypt_stream(
+        &self,
+        input: impl std::io::BufRead,
+        mut output: impl std::io::Write,
+    ) -> Result<(), Box<dyn Error>> {
+        let decryptor = age::Decryptor::new_buffered(input)?;
+        let mut reader =
+            decryptor.decrypt(std::iter::once(&self.identity as &dyn age::Identity))?;
+        std::io::copy(&mut reader, &mut output)?;
+        Ok(())
+    }
+
+    // Without the feature no key can be constructed, so these cannot be called
+    #[cfg(not(feature = "encryption"))]
+    pub fn encrypt(&self, _plaintext: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        match self.never {}
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub fn decrypt(&self, _ciphertext: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        match self.never {}
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub fn encrypt_file(&self, _from: &Path, _to: &Path) -> Result<(), Box<dyn Error>> {
+        match self.never {}
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub fn decrypt_file(&self, _from: &Path, _to: &Path) -> Result<(), Box<dyn Error>> {
+        match self.never {}
+    }
+}
+
+/// `path` with the encrypted-file extension appended
+pub fn encrypted_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(ENCRYPTED_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Write a state file, encrypted to `key` if there is one
+///
+/// Encrypted data goes to [`encrypted_path`] and a plaintext copy from before the key
+/// was set is removed. The file is replaced whole, so a reader never sees half of it.
+/// Returns the path written.
+pub fn write_state_file(
+    key: Option<&JournalKey>,
+    path: &Path,
+    data: &[u8],
+) -> Result<PathBuf, Box<dyn Error>> {
+    let (target, data) = match key {
+        Some(key) => (encrypted_path(path), key.encrypt(data)?),
+        None => (path.to_path_buf(), data.to_vec()),
+    };
+    let mut partial = target.clone().into_os_string();
+    partial.push(".partial");
+    std::fs::write(&partial, data)?;
+    std::fs::rename(&partial, &target)?;
+    if key.is_some() {
+        remove_if_present(path)?;
+    }
+    Ok(target)
+}
+
+/// Read a state file written by [`write_state_file`], `None` if there is none
+pub fn read_state_file(
+    key: Option<&JournalKey>,
+    path: &Path,
+) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    let encrypted = encrypted_path(path);
+    if encrypted.exists() {
+        let Some(key) = key else {
+            return Err(format!(
+                "'{}' is encrypted; set {} to its key file",
+                encrypted.display(),
+                KEY_ENV
+            )
+            .into());
+        };
+        return key.decrypt(&std::fs::read(&encrypted)?).map(Some);
+    }
+    match std::fs::read(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Whether a state file exists, encrypted or not
+pub fn state_file_exists(path: &Path) -> bool {
+    path.exists() || encrypted_path(path).exists()
+}
+
+/// Remove a state file in both forms; returns whether there was one
+pub fn remove_state_file(path: &Path) -> Result<bool, Box<dyn Error>> {
+    let plain = remove_if_present(path)?;
+    let encrypted = remove_if_present(&encrypted_path(path))?;
+    Ok(plain || encrypted)
+}
+
+fn remove_if_present(path: &Path) -> std::io::Result<bool> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(all(test, feature = "encryption"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_file = dir.path().join("key.txt");
+        let identity = age::x25519::Identity::generate();
+        use age::secrecy::ExposeSecret;
+        std::fs::write(
+            &key_file,
+            format!("# test key\n{}\n", identity.to_string().expose_secret()),
+        )
+        .unwrap();
+
+        let key = JournalKey::load(&key_file).unwrap();
+        let ciphertext = key.encrypt(b"/home/me/secret-plans.txt").unwrap();
+        assert!(!String::from_utf8_lossy(&ciphertext).contains("secret-plans"));
+        assert_eq!(
+            key.decrypt(&ciphertext).unwrap(),
+            b"/home/me/secret-plans.txt"
+        );
+    }
+
+    #[test]
+    fn test_state_file_encrypted_with_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_file = dir.path().join("key.txt");
+        let identity = age::x25519::Identity::generate();
+        use age::secrecy::ExposeSecret;
+        std::fs::write(&key_file, identity.to_string().expose_secret()).unwrap();
+        let key = JournalKey::load(&key_file).unwrap();
+
+        let file = dir.path().join("repl-session.json");
+        write_state_file(None, &file, b"/home/me/plain").unwrap();
+        write_state_file(Some(&key), &file, b"/home/me/secret-plans.txt").unwrap();
+        assert!(!file.exists());
+        let stored = std::fs::read(encrypted_path(&file)).unwrap();
+        assert!(!String::from_utf8_lossy(&stored).contains("secret-plans"));
+
+        assert_eq!(
+            read_state_file(Some(&key), &file).unwrap().unwrap(),
+            b"/home/me/secret-plans.txt"
+        );
+        assert!(read_state_file(None, &file).is_err());
+        assert!(remove_state_file(&file).unwrap());
+        assert!(!state_file_exists(&file));
+        assert_eq!(read_state_file(Some(&key), &file).unwrap(), None);
+    }
+}
@@ -0,0 +1,261 @@
+//! Fitting output to the terminal: `ls` columns, preview lines and tables
+//!
+//! Output only adapts when it goes to a terminal, or when `COLUMNS` says how
+//! wide to be. Piped output keeps one whole record per line, however long, so
+//! scripts reading it see every path in full.
+
+use std::io::IsTerminal;
+use unicode_width::UnicodeWidthStr;
+
+/// Columns between the cells of a row
+const GAP: usize = 2;
+
+/// Indent of the second line of a wrapped preview line
+const WRAP_INDENT: &str = "    ";
+
+/// How wide output may be, or `None` when it need not fit anything
+pub fn terminal_width() -> Option<usize> {
+    if let Some(columns) = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|columns| *columns > 0)
+    {
+        return Some(columns);
+    }
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    crossterm::terminal::size()
+        .ok()
+        .map(|(columns, _)| columns as usize)
+        .filter(|columns| *columns > 0)
+}
+
+/// Columns `text` takes on screen, not counting color codes
+pub fn width(text: &str) -> usize {
+    let mut visible = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // `ESC [ ... m` and other CSI sequences end at a letter
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            visible.push(c);
+        }
+    }
+    visible.width()
+}
+
+/// `text` cut to `max` columns with `…` in the middle, so the start and the
+/// extension of a long name both stay visible
+pub fn truncate(text: &str, max: usize) -> String {
+    if width(text) <= max {
+        return text.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    let keep = max - 1;
+    let mut head = String::new();
+    let mut head_width = 0;
+    for c in text.chars() {
+        let w = c.to_string().width();
+        if head_width + w > keep.div_ceil(2) {
+            break;
+        }
+        head.push(c);
+        head_width += w;
+    }
+    let mut tail = Vec::new();
+    let mut tail_width = 0;
+    for c in text.chars().rev() {
+        let w = c.to_string().width();
+        if head_width + tail_width + w > keep {
+            break;
+        }
+        tail.push(c);
+        tail_width += w;
+    }
+    head.push('…');
+    head.extend(tail.into_iter().rev());
+    head
+}
+
+/// `text` followed by spaces up to `columns`
+pub fn pad(text: &str, columns: usize) -> String {
+    format!(
+        "{}{}",
+        text,
+        " ".repeat(columns.saturating_sub(width(text)))
+    )
+}
+
+/// Lay `items` out in columns, down then across like `ls`, within `max` columns
+///
+/// Each returned line is `indent` followed by the padded cells. Items wider than
+/// a whole line are truncated.
+pub fn columns(items: &[String], max: usize, indent: &str) -> Vec<String> {
+    if items.is_empty() {
+        return Vec::new();
+    }
+    let room = max.saturating_sub(width(indent)).max(1);
+    let items: Vec<String> = items.iter().map(|item| truncate(item, room)).collect();
+    // The most columns whose widest cells still fit
+    let mut layout = (
+        1,
+        vec![items.iter().map(|item| width(item)).max().unwrap_or(0)],
+    );
+    for count in 2..=items.len() {
+        let rows = items.len().div_ceil(count);
+        let widths: Vec<usize> = items
+            .chunks(rows)
+            .map(|column| column.iter().map(|item| width(item)).max().unwrap_or(0))
+            .collect();
+        if widths.iter().sum::<usize>() + GAP * (widths.len() - 1) > room {
+            break;
+        }
+        layout = (count, widths);
+    }
+
+    let (count, widths) = layout;
+    let rows = items.len().div_ceil(count);
+    (0..rows)
+        .map(|row| {
+            let cells: Vec<&String> = (0..widths.len())
+                .filter_map(|column| items.get(column * rows + row))
+                .collect();
+            let mut line = indent.to_string();
+            for (index, cell) in cells.iter().enumerate() {
+                if index + 1 == cells.len() {
+                    line.push_str(cell);
+                } else {
+                    line.push_str(&pad(cell, widths[index] + GAP));
+                }
+            }
+            line
+        })
+        .collect()
+}
+
+/// `head "from" → "to"`, on two lines when one would not fit in `max` columns
+pub fn arrow(head: &str, from: &str, to: &str, max: Option<usize>) -> String {
+    let line = format!("{head}\"{from}\" → \"{to}\"");
+    let Some(max) = max else {
+        return line;
+    };
+    if width(&line) <= max {
+        return line;
+    }
+    let room = max.saturating_sub(width(head) + 2).max(8);
+    let second = max.saturating_sub(WRAP_INDENT.len() + 4).max(8);
+    format!(
+        "{}\"{}\"\n{}→ \"{}\"",
+        head,
+        truncate(from, room),
+        WRAP_INDENT,
+        truncate(to, second)
+    )
+}
+
+/// Rows of cells with aligned columns; the last column shrinks to fit
+#[derive(Debug, Default)]
+pub struct Table {
+    rows: Vec<Vec<String>>,
+    /// Columns aligned to the right, such as counts
+    right: Vec<usize>,
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Align `column` to the right
+    pub fn right(mut self, column: usize) -> Self {
+        self.right.push(column);
+        self
+    }
+
+    pub fn row(&mut self, cells: Vec<String>) {
+        self.rows.push(cells);
+    }
+
+    /// The lines of the table, each starting with `indent`, within `max` columns
+    pub fn render(&self, indent: &str, max: Option<usize>) -> Vec<String> {
+        let columns = self.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let mut widths = vec![0; columns];
+        for row in &self.rows {
+            for (index, cell) in row.iter().enumerate() {
+                widths[index] = widths[index].max(width(cell));
+            }
+        }
+        if let (Some(max), Some(last)) = (max, widths.len().checked_sub(1)) {
+            let fixed: usize = widths[..last].iter().map(|w| w + GAP).sum();
+            widths[last] = widths[last].min(max.saturating_sub(width(indent) + fixed).max(8));
+        }
+
+        self.rows
+            .iter()
+            .map(|row| {
+                let mut line = indent.to_string();
+                for (index, cell) in row.iter().enumerate() {
+                    let cell = truncate(cell, widths[index]);
+                    let last = index + 1 == row.len();
+                    if self.right.contains(&index) {
+                        line.push_str(&" ".repeat(widths[index] - width(&cell)));
+                        line.push_str(&cell);
+                    } else if last {
+                        line.push_str(&cell);
+                    } else {
+                        line.push_str(&pad(&cell, widths[index]));
+                    }
+                    if !last {
+                        line.push_str(&" ".repeat(GAP));
+                    }
+                }
+                line
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_helpers() {
+        assert_eq!(width("\x1b[1;34mdocs/\x1b[0m"), 5);
+        assert_eq!(width("日本"), 4);
+        assert_eq!(truncate("holiday-photo-2024.jpg", 11), "holid…4.jpg");
+        assert_eq!(truncate("short", 10), "short");
+
+        let items: Vec<String> = ["a", "bb", "ccc", "dddd", "e"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(columns(&items, 12, ""), ["a   ccc   e", "bb  dddd"]);
+        assert_eq!(columns(&items, 9, ""), ["a    dddd", "bb   e", "ccc"]);
+        assert_eq!(columns(&items, 3, "").len(), 5);
+
+        assert_eq!(arrow("", "a", "b", Some(80)), "\"a\" → \"b\"");
+        let wrapped = arrow("Rename: ", &"x".repeat(50), &"y".repeat(50), Some(40));
+        assert!(wrapped.lines().all(|line| width(line) <= 40), "{wrapped}");
+        assert_eq!(arrow("", &"x".repeat(50), "y", None).lines().count(), 1);
+
+        let mut table = Table::new().right(0);
+        table.row(vec![
+            "7".into(),
+            "files".into(),
+            "/very/long/path/to/dir".into(),
+        ]);
+        table.row(vec!["120".into(), "files".into(), ".".into()]);
+        let lines = table.render("  ", Some(24));
+        assert_eq!(lines[0], "    7  files  /very…/dir");
+        assert_eq!(lines[1], "  120  files  .");
+    }
+}
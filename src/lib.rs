@@ -1,8 +1,57 @@
+//! Smart Move as a library
+//!
+//! The items re-exported at the crate root are the stable API and follow
+//! semantic versioning: transforming names, checking a rename plan for
+//! conflicts, recording and undoing operations, and reorganizing directories.
+//! None of them print or exit the process; operations with something to say
+//! while they run take a [`Reporter`]. The modules themselves are public for
+//! the binary and the tests and may change in any release.
+
+pub use history::{HistoryManager, MappedMove, Operation};
+pub use plan::{Conflict, ConflictChecker, OpKind, PlannedOp, execute_renames};
+pub use report::{Collected, Console, Json, Level, Outcome, Quiet, Reporter};
+pub use sort::{BucketRule, bucket_files, fan_out_alpha, group_by_basename};
+pub use transformers::{TransformType, transform};
+pub use unsort::{flatten_directory, remove_empty_dirs};
+
 // Re-export modules for testing and library usage
+pub mod attr;
+pub mod brace;
+pub mod chain;
+pub mod clipboard;
 pub mod cnp_grammar;
+pub mod config;
+pub mod detect;
+pub mod disk_usage;
+pub mod doctor;
+pub mod hash;
 pub mod history;
+pub mod index;
+pub mod journal;
+pub mod layout;
+pub mod links;
+pub mod notes;
+pub mod notify;
+pub mod output;
+pub mod paths;
+pub mod pick;
+pub mod plan;
+pub mod preset;
+pub mod preview;
+pub mod progress;
+pub mod protect;
 pub mod repl;
+pub mod report;
+pub mod selection;
+pub mod shell_init;
 pub mod sort;
+pub mod stage;
+pub mod stats;
+pub mod suggest;
+pub mod template;
+pub mod trace;
 pub mod transformers;
 pub mod ui;
 pub mod unsort;
+pub mod vfs;
+pub mod wizard;
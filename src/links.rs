@@ -0,0 +1,500 @@
+//! Link rewriting for note collections
+//! Keeps markdown links, wiki-links and org file links valid after items are renamed
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+
+/// File types scanned for links when `--link-ext` is not given
+pub const DEFAULT_LINK_EXTENSIONS: &[&str] = &["md", "markdown", "org"];
+
+// [text](target) and [text](target "title")
+static MARKDOWN_LINK: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(\]\()(<[^>\n]+>|[^)\s]+)((?:\s+"[^"\n]*")?\))"#).unwrap());
+
+// [id]: target
+static MARKDOWN_REFERENCE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?m)^(\s{0,3}\[[^\]\n]+\]:\s*)(\S+)").unwrap());
+
+// [[Target]], [[Target|alias]], [[Target#heading]], ![[image.png]]
+static WIKI_LINK: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(\[\[)([^\]|#\n]+)((?:[#|][^\]\n]*)?\]\])").unwrap());
+
+// [[file:target]] and [[file:target][description]]
+static ORG_FILE_LINK: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(\[\[file:)([^\]\n:]+)((?:::[^\]\n]*)?\])").unwrap());
+
+/// A single line changed by a link update
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineChange {
+    pub line_number: usize,
+    pub before: String,
+    pub after: String,
+}
+
+/// Pending content edit for one file
+#[derive(Debug, Clone)]
+pub struct LinkEdit {
+    pub path: PathBuf,
+    pub updated_content: String,
+    pub changes: Vec<LineChange>,
+}
+
+/// Resolves link targets against a set of completed (or planned) renames
+#[derive(Debug, Default)]
+pub struct LinkRewriter {
+    // old absolute path -> new file name
+    forward: HashMap<PathBuf, String>,
+    // new absolute path (in the old parent directory) -> old absolute path
+    reverse: HashMap<PathBuf, PathBuf>,
+    // old file name -> new file name, for wiki-links that only name the note
+    by_name: HashMap<String, Option<String>>,
+}
+
+impl LinkRewriter {
+    /// Create a rewriter from `(old, new)` path pairs
+    pub fn new(renames: &[(PathBuf, PathBuf)]) -> Self {
+        let mut rewriter = Self::default();
+        for (old, new) in renames {
+            let old_abs = normalize(old);
+            let new_abs = normalize(new);
+            let Some(new_name) = new_abs.file_name().map(|n| n.to_string_lossy().to_string())
+            else {
+                continue;
+            };
+            if let Some(old_name) = old_abs.file_name().map(|n| n.to_string_lossy().to_string()) {
+                // Two different notes sharing a name make bare wiki-links ambiguous
+                rewriter
+                    .by_name
+                    .entry(old_name)
+                    .and_modify(|existing| {
+                        if existing.as_deref() != Some(new_name.as_str()) {
+                            *existing = None;
+                        }
+                    })
+                    .or_insert_with(|| Some(new_name.clone()));
+            }
+            rewriter.reverse.insert(new_abs, old_abs.clone());
+            rewriter.forward.insert(old_abs, new_name);
+        }
+        rewriter
+    }
+
+    /// Returns true when there is nothing to rewrite
+    pub fn is_empty(&self) -> bool {
+        self.forward.is_empty()
+    }
+
+    /// Map a path as it exists now back to where it lived before the renames
+    pub fn original_path(&self, current: &Path) -> PathBuf {
+        let current = normalize(current);
+        let mut original = PathBuf::new();
+        for component in current.components() {
+            let candidate = original.join(component.as_os_str());
+            match self.reverse.get(&candidate) {
+                Some(old) => original = old.clone(),
+                None => original = candidate,
+            }
+        }
+        original
+    }
+
+    /// Rewrite a relative link target resolved from `base_dir` (in pre-rename terms)
+    ///
+    /// `implicit_ext` allows a final component to match with an extension appended,
+    /// which is how wiki-links usually refer to notes.
+    fn rewrite_relative(
+        &self,
+        base_dir: &Path,
+        target: &str,
+        implicit_ext: Option<&str>,
+    ) -> Option<String> {
+        if target.is_empty() || target.starts_with('/') || has_scheme(target) {
+            return None;
+        }
+
+        let segments: Vec<&str> = target.split('/').collect();
+        let mut cursor = base_dir.to_path_buf();
+        let mut rewritten = Vec::with_capacity(segments.len());
+        let mut changed = false;
+
+        for (index, segment) in segments.iter().enumerate() {
+            let decoded = percent_decode(segment);
+            match decoded.as_str() {
+                "" | "." => {
+                    rewritten.push(segment.to_string());
+                    continue;
+                }
+                ".." => {
+                    cursor.pop();
+                    rewritten.push(segment.to_string());
+                    continue;
+                }
+                _ => {}
+            }
+
+            cursor.push(&decoded);
+            let is_last = index == segments.len() - 1;
+
+            if let Some(new_name) = self.forward.get(&cursor) {
+                rewritten.push(encode_like(segment, new_name));
+                changed = true;
+                continue;
+            }
+
+            if let (true, Some(ext)) = (is_last, implicit_ext) {
+                let with_ext = cursor.with_file_name(format!("{decoded}.{ext}"));
+                if let Some(new_name) = self.forward.get(&with_ext) {
+                    let new_stem = strip_extension(new_name, ext);
+                    rewritten.push(encode_like(segment, &new_stem));
+                    changed = true;
+                    continue;
+                }
+            }
+
+            rewritten.push(segment.to_string());
+        }
+
+        changed.then(|| rewritten.join("/"))
+    }
+
+    /// Rewrite a wiki-link target that names a note without a path
+    fn rewrite_wiki_name(&self, target: &str, implicit_ext: &str) -> Option<String> {
+        if let Some(Some(new_name)) = self.by_name.get(target) {
+            return Some(new_name.clone());
+        }
+        let with_ext = format!("{target}.{implicit_ext}");
+        if let Some(Some(new_name)) = self.by_name.get(&with_ext) {
+            return Some(strip_extension(new_name, implicit_ext));
+        }
+        None
+    }
+
+    /// Rewrite every recognised link in `content`
+    ///
+    /// `file_path` is the pre-rename location of the file the content came from;
+    /// `root` is the directory wiki-links with folders are resolved against.
+    pub fn rewrite_content(&self, content: &str, file_path: &Path, root: &Path) -> String {
+        let base_dir = file_path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let root = normalize(root);
+        let is_org = file_path
+            .extension()
+            .map(|e| e.eq_ignore_ascii_case("org"))
+            .unwrap_or(false);
+
+        if is_org {
+            return ORG_FILE_LINK
+                .replace_all(content, |caps: &Captures| {
+                    self.replace_capture(caps, |target| {
+                        self.rewrite_relative(&base_dir, target, None)
+                    })
+                })
+                .into_owned();
+        }
+
+        let content = MARKDOWN_LINK.replace_all(content, |caps: &Captures| {
+            self.replace_capture(caps, |target| {
+                let (inner, wrapped) = match target.strip_prefix('<') {
+                    Some(rest) => (rest.trim_end_matches('>'), true),
+                    None => (target, false),
+                };
+                let (path_part, suffix) = split_fragment(inner);
+                let new_path = self.rewrite_relative(&base_dir, path_part, None)?;
+                Some(if wrapped {
+                    format!("<{new_path}{suffix}>")
+                } else {
+                    format!("{new_path}{suffix}")
+                })
+            })
+        });
+
+        let content = MARKDOWN_REFERENCE.replace_all(&content, |caps: &Captures| {
+            self.replace_capture(caps, |target| {
+                let (path_part, suffix) = split_fragment(target);
+                let new_path = self.rewrite_relative(&base_dir, path_part, None)?;
+                Some(format!("{new_path}{suffix}"))
+            })
+        });
+
+        WIKI_LINK
+            .replace_all(&content, |caps: &Captures| {
+                self.replace_capture(caps, |target| {
+                    let trimmed = target.trim();
+                    if trimmed.contains('/') {
+                        self.rewrite_relative(&root, trimmed, Some("md"))
+                    } else {
+                        self.rewrite_wiki_name(trimmed, "md")
+                    }
+                })
+            })
+            .into_owned()
+    }
+
+    fn replace_capture<F>(&self, caps: &Captures, rewrite: F) -> String
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let target = &caps[2];
+        let new_target = rewrite(target).unwrap_or_else(|| target.to_string());
+        format!("{}{}{}", &caps[1], new_target, &caps[3])
+    }
+}
+
+/// Scan `root` for link-bearing files and plan the edits needed after `renames`
+///
+/// Works both before the renames are applied (preview) and after them: files
+/// are read at their current location but links are resolved relative to
+/// where each file lived before the renames.
+pub fn plan_link_updates(
+    root: &Path,
+    renames: &[(PathBuf, PathBuf)],
+    extensions: &[String],
+) -> Result<Vec<LinkEdit>, Box<dyn Error>> {
+    let rewriter = LinkRewriter::new(renames);
+    if rewriter.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let original_root = rewriter.original_path(root);
+    let mut edits = Vec::new();
+
+    let walker = WalkDir::new(root).into_iter().filter_entry(|entry| {
+        // Skip hidden directories such as .git or .obsidian
+        entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.')
+    });
+
+    for entry in walker.filter_map(Result::ok) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.path();
+        let matches_ext = path
+            .extension()
+            .map(|ext| {
+                let ext = ext.to_string_lossy();
+                extensions.iter().any(|e| e.eq_ignore_ascii_case(&ext))
+            })
+            .unwrap_or(false);
+        if !matches_ext {
+            continue;
+        }
+
+        // Non UTF-8 content cannot contain links we know how to rewrite
+        let Ok(content) = fs::read_to_string(path) else {
+            continue;
+        };
+
+        let original_path = rewriter.original_path(path);
+        let updated = rewriter.rewrite_content(&content, &original_path, &original_root);
+        if updated == content {
+            continue;
+        }
+
+        edits.push(LinkEdit {
+            path: path.to_path_buf(),
+            changes: diff_lines(&content, &updated),
+            updated_content: updated,
+        });
+    }
+
+    Ok(edits)
+}
+
+/// Write planned link edits back to disk
+pub fn apply_link_updates(edits: &[LinkEdit]) -> Result<(), Box<dyn Error>> {
+    for edit in edits {
+        fs::write(&edit.path, &edit.updated_content)
+            .map_err(|e| format!("Failed to update links in {}: {e}", edit.path.display()))?;
+    }
+    Ok(())
+}
+
+/// Parse a comma-separated `--link-ext` value, falling back to the defaults
+pub fn parse_link_extensions(value: Option<&str>) -> Vec<String> {
+    match value {
+        Some(list) => list
+            .split(',')
+            .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+            .filter(|ext| !ext.is_empty())
+            .collect(),
+        None => DEFAULT_LINK_EXTENSIONS
+            .iter()
+            .map(|ext| ext.to_string())
+            .collect(),
+    }
+}
+
+fn diff_lines(before: &str, after: &str) -> Vec<LineChange> {
+    // Link rewrites never add or remove lines, so a line-by-line comparison is enough
+    before
+        .lines()
+        .zip(after.lines())
+        .enumerate()
+        .filter(|(_, (old, new))| old != new)
+        .map(|(index, (old, new))| LineChange {
+            line_number: index + 1,
+            before: old.to_string(),
+            after: new.to_string(),
+        })
+        .collect()
+}
+
+/// Absolute, lexically normalised form of a path (does not touch the filesystem)
+fn normalize(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut normalized = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+fn has_scheme(target: &str) -> bool {
+    target.starts_with('#')
+        || target
+            .split_once(':')
+            .map(|(scheme, _)| {
+                !scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_alphanumeric())
+            })
+            .unwrap_or(false)
+}
+
+fn split_fragment(target: &str) -> (&str, &str) {
+    match target.find(['#', '?']) {
+        Some(pos) => target.split_at(pos),
+        None => (target, ""),
+    }
+}
+
+fn strip_extension(name: &str, ext: &str) -> String {
+    name.strip_suffix(&format!(".{ext}"))
+        .unwrap_or(name)
+        .to_string()
+}
+
+fn percent_decode(segment: &str) -> String {
+    if !segment.contains('%') {
+        return segment.to_string();
+    }
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Keep the original link's encoding style when substituting a new name
+fn encode_like(original: &str, replacement: &str) -> String {
+    if original.contains("%20") {
+        replacement.replace(' ', "%20")
+    } else {
+        replacement.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewriter(pairs: &[(&str, &str)]) -> LinkRewriter {
+        let renames: Vec<(PathBuf, PathBuf)> = pairs
+            .iter()
+            .map(|(old, new)| (PathBuf::from(old), PathBuf::from(new)))
+            .collect();
+        LinkRewriter::new(&renames)
+    }
+
+    #[test]
+    fn test_markdown_relative_links() {
+        let r = rewriter(&[("/vault/notes/Old Note.md", "/vault/notes/old-note.md")]);
+        let content = "See [old](notes/Old%20Note.md#intro) and [x](<notes/Old Note.md>).";
+        let updated = r.rewrite_content(content, Path::new("/vault/index.md"), Path::new("/vault"));
+        assert_eq!(
+            updated,
+            "See [old](notes/old-note.md#intro) and [x](<notes/old-note.md>)."
+        );
+    }
+
+    #[test]
+    fn test_parent_relative_and_external_links_untouched() {
+        let r = rewriter(&[("/vault/a.md", "/vault/b.md")]);
+        let content = "[up](../a.md) [web](https://example.com/a.md) [same](a.md)";
+        let updated = r.rewrite_content(content, Path::new("/vault/sub/n.md"), Path::new("/vault"));
+        assert_eq!(
+            updated,
+            "[up](../b.md) [web](https://example.com/a.md) [same](a.md)"
+        );
+    }
+
+    #[test]
+    fn test_wiki_links() {
+        let r = rewriter(&[
+            ("/vault/Old Note.md", "/vault/old-note.md"),
+            ("/vault/img/Photo 1.png", "/vault/img/photo_1.png"),
+        ]);
+        let content = "[[Old Note]] [[Old Note|alias]] [[Old Note#Heading]] ![[Photo 1.png]]";
+        let updated = r.rewrite_content(content, Path::new("/vault/x.md"), Path::new("/vault"));
+        assert_eq!(
+            updated,
+            "[[old-note]] [[old-note|alias]] [[old-note#Heading]] ![[photo_1.png]]"
+        );
+    }
+
+    #[test]
+    fn test_renamed_directory_components() {
+        let r = rewriter(&[("/vault/My Folder", "/vault/my_folder")]);
+        let content = "[n](My%20Folder/note.md) [[My Folder/note]]";
+        let updated = r.rewrite_content(content, Path::new("/vault/x.md"), Path::new("/vault"));
+        assert_eq!(updated, "[n](my_folder/note.md) [[my_folder/note]]");
+    }
+
+    #[test]
+    fn test_org_file_links() {
+        let r = rewriter(&[("/notes/Todo List.org", "/notes/todo_list.org")]);
+        let content = "[[file:Todo List.org][todo]] [[file:Todo List.org::*Tasks]]";
+        let updated = r.rewrite_content(content, Path::new("/notes/main.org"), Path::new("/notes"));
+        assert_eq!(
+            updated,
+            "[[file:todo_list.org][todo]] [[file:todo_list.org::*Tasks]]"
+        );
+    }
+
+    #[test]
+    fn test_original_path_follows_renamed_parents() {
+        let r = rewriter(&[("/vault/Old Dir", "/vault/old_dir")]);
+        assert_eq!(
+            r.original_path(Path::new("/vault/old_dir/note.md")),
+            PathBuf::from("/vault/Old Dir/note.md")
+        );
+    }
+
+    #[test]
+    fn test_parse_link_extensions() {
+        assert_eq!(parse_link_extensions(Some(".MD, org")), vec!["md", "org"]);
+        assert_eq!(
+            parse_link_extensions(None).len(),
+            DEFAULT_LINK_EXTENSIONS.len()
+        );
+    }
+}
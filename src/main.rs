@@ -1,27 +1,68 @@
+mod attr;
+mod brace;
+mod chain;
+mod clipboard;
 mod cnp_grammar;
+mod config;
+mod detect;
+mod disk_usage;
+mod doctor;
 mod file_ops;
+mod hash;
 mod history;
+mod index;
+mod journal;
+mod layout;
+mod links;
+mod notes;
+mod notify;
+mod output;
+mod paths;
+mod pick;
+mod plan;
+mod preset;
+mod preview;
+mod progress;
+mod protect;
 mod repl;
+mod report;
+mod selection;
+mod shell_init;
 mod sort;
+mod stage;
+mod stats;
+mod suggest;
+mod template;
+mod trace;
 mod transformers;
 mod ui;
 mod unsort;
+mod vfs;
+mod wizard;
 
+use std::cell::{OnceCell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
+use std::ffi::OsStr;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process;
+use std::rc::Rc;
 
 use clap::{ArgAction, Parser};
 use colored::*;
-use dirs::home_dir;
 
 use cnp_grammar::{CnpCommand, CnpGrammarParser};
-use file_ops::{FileOpConfig, copy_files, expand_glob_patterns, move_files, remove_files};
+use file_ops::{
+    Batch, DestinationLayout, FileOpConfig, OnError, copy_files, expand_glob_patterns, move_files,
+    remove_files,
+};
 use history::HistoryManager;
+use output::Op;
 use repl::InteractiveSession;
-use transformers::{TransformType, transform};
+use transformers::{NameFallback, NonUtf8, TransformType, transform};
 use ui::UserInterface;
+use vfs::Vfs;
 
 #[derive(Parser, Debug, Clone)]
 #[command(
@@ -40,19 +81,55 @@ COMMANDS:
   split TRANSFORMATION [target]                      Split camelCase/PascalCase then transform
   transformation file.txt                             Transform specific file
   CHANGE \"old\" INTO \"new\" [target]                  Replace substring in filenames
+  note [target]                                       Vault names: date-prefixed kebab-case, links kept valid
+  sort alpha [target] --levels N                      Shard a huge flat directory into a/ab/abcdef.txt (undoable)
+  sort date [target] --layout T                       Move files into folders by modification date
+  flatten [target]                                    Move every file up into target (over 1,000 only with -F; mount points skipped)
+  tier [path] FILTERS... TO-DIR:dir                   Move matching files to other storage, verified across filesystems
+  attr [path] FILTERS... PERM:MODE                    chmod the matches: PERM:+x, PERM:g-w, PERM:u+rw,go-r or PERM:644
+                                                      (-e includes directories; smv -u restores the old modes)
+  cp|mv [path] FILTERS... dest                        Copy or move matching files into dest (flat, or --preserve-structure)
+  transformation [path] INTO-DIR:dir                  Rename and move matches into dir in one undoable batch
+  STAGE [path] FILTERS... THEN STAGE THEN ...         Chain transforms, sort date|alpha, bucket RULE and mv TO-DIR:dir
+                                                      over one selection, run and undone as one batch
+  undo --select [LIST]                                Revert only some files of the last batch (e.g. 1,3-5)
+  undo -p                                             Show what undo would move back and what it cannot, without undoing
+  history [--full]                                    List past batches, newest first: when, the command, counts and files
+  history undo N                                      Revert batch N of that list, leaving later batches as they are
+  history gc                                          Prune old history and backups past the retention limits
+  stats [--since AGE]                                 Batches and files per day, busiest directories, bytes moved, undos
+  detect [dir]                                        Naming styles per directory, the dominant one, and commands to fix outliers
+  doctor --env [dir]                                  Check the state dir, peer tools, terminal, and dir's case and long-path handling
+  stage DIR                                           Copy DIR to DIR.smv-staging/ to reorganize it there undisturbed
+  commit-stage DIR                                    Swap the staged tree in atomically, keeping the original as DIR.smv-previous/
+  apply-plan FILE                                     Run a plan saved with --plan once every source is there and every destination free (-F: run what can)
+  batch FILE                                          Run an operations CSV (operation,source,destination), JSON or YAML plan
+                                                      headless: every op is checked first, and one problem stops them all
+  index build|update|clear [dir]                      Save a listing of dir that CNP filters on dir use instead of walking it
+  where-did-it-go NAME                                Trace files once or now named like NAME through the history to where they are now
+  bucket RULE [target]                                Move files into folders named by LETTER, PREFIX:n, DATE[:month], MTIME, REGEX:(...)
+  template \"PATTERN\" [target]                         Rename from a template: {name}, {ext}, {parent}, {n:02}, {fm:title|kebab}
   mv source destination                               Move files/directories  
   cp source destination                               Copy files/directories
   rm targets...                                       Remove files/directories
   interactive, tui                                    Launch interactive modes
+  wizard                                              Answer a few questions and get (and run) the matching command
+  preset save NAME [--description TEXT] COMMAND...    Save a whole command line under NAME
+  preset run NAME [OPTIONS]                           Run a saved command; OPTIONS are added and win over the saved ones
+  preset list | preset rm NAME                        List saved presets with their descriptions, or delete one
+  shell-init zsh|bash                                 Ctrl-X s widget: rename the file name before the cursor
 
 FLAGS: (alphabetical)
   -a, --hidden        Include hidden files (default: excluded)
   -c                  Create (combine with -d for directories, -f for files)  
   -d                  Directory creation (use with -c)
   -e, --everything    Process everything (files and directories)
+  --apply-to KIND     What transforms rename: files (default), dirs or both;
+                      -r with both renames every folder on the way, too
   -f                  File creation (use with -c)
   -F                  Force (skip confirmations/overwrite files)
   -i                  Case-insensitive pattern matching
+  -ic, --ignore-case  Case-insensitive matching (CNP standard spelling of -i)
   -I                  Interactive mode
   -L                  Dereference symbolic links
   -n                  No-clobber (do not overwrite existing files)
@@ -60,17 +137,108 @@ FLAGS: (alphabetical)
   -P                  Do not follow symbolic links  
   -r                  Recursive (process subdirectories)
   -T                  Terminal UI mode
-  -u                  Undo last operation
+  -x, --one-file-system  Stay on one filesystem: recursion skips other mounts (like rsync -x, du -x)
+  -u                  Undo the last command (its whole batch)
   -v, --verbose       Verbose output
 
 ARGUMENTS:
   [TARGET]            Target directory or file pattern (default: current directory)
-  [FILTERS]           CNP filters: NAME:pattern, EXT:ext, TYPE:file/dir, SIZE>1MB
+  [FILTERS]           CNP filters: NAME:pattern, EXT:ext, TYPE:file/dir, SIZE>1MB, RES>1080p
+                      EXT:jpg,png matches any listed extension, EXT:!tmp excludes one
+                      NAME~:regex (or REGEX:regex) searches file names with a regex; -i applies
+                      EMPTY:true matches zero-byte files and empty directories
+                      HASH:[ALGO:]HEX matches files whose content hash starts with HEX
+                      DUPES: matches every copy of a file but the first by path (DUPES:all
+                      every copy); sizes and partial hashes narrow it down before full hashes
+                      WHERE ( ... ) groups filters with AND, OR and NOT (NOT > AND > OR)
+                      LIMIT:N, FIRST:N or SAMPLE:N / SAMPLE:N% cap how many matches are
+                      touched (FIRST: takes the newest N by default)
+                      BY:name|mtime|size [ASC|DESC] sets the processing and preview order
+                      (names A-Z, dates and sizes newest/largest first unless told otherwise)
 
 OPTIONS:
   --preserve          Preserve file attributes (mode, ownership, timestamps)
   --exclude PATTERNS  Comma-separated patterns to exclude (e.g., \"*.tmp,test_*\")
   --max-history-size  Maximum operations to keep in history (default: 50)
+  --state-dir DIR     Directory for the undo history and backups (overrides SMV_STATE_DIR)
+  --max-history-age   With history gc: drop batches older than DAYS
+  --max-history-bytes With history gc: keep backups under SIZE (e.g. 500MB)
+  --since AGE         With stats: only activity within AGE (30d) or since DATE
+  --description TEXT  With preset save: what the preset is for, shown by preset list
+  --update-links      Rewrite markdown/wiki/org links that point at renamed files
+  --link-ext EXTS     File types scanned by --update-links (default: md,markdown,org)
+  --levels N          Shard depth for sort alpha (default: 2)
+  --layout TEMPLATE   Date folder names for sort date and DATE/MTIME buckets (default for sort
+                      date: {Y}/{Y}-{m}); {Y} {y} {m} {d} {W} week, {month_name} {mon} in the
+                      LC_TIME language, {relative} today/yesterday/this-week/last-week/...
+  --stdin-ops         Execute a plan from stdin: \"src<TAB>dst\" lines, JSON {\"op\",\"from\",\"to\"}, a YAML list
+                      or CSV with an operation,source,destination header;
+                      stops first when a directory it changes is read-only or immutable
+  --plan FILE         Preview only and save the planned renames/moves to FILE (JSON, or YAML for
+                      .yaml/.yml) with absolute paths, to review and edit before smv apply-plan FILE
+  --hash              With index build/update: also record a content hash of every file
+  --hash-algo ALGO    xxh3 (default), blake3 or sha256, for HASH:, DUPES:, index hashes and the
+                      fingerprints undo checks files against
+  --dir-mode MODE     Mode of directories mv, cp, sort and transforms create on the way (2775,
+                      g+w, ...); default: what the umask leaves. Copies without --preserve get
+                      the source's mode minus the umask, as with cp
+  --du                With -p: bytes per source/destination directory and free space per filesystem
+  --copy              With transforms: create renamed copies, keep the originals
+  --select [LIST]     With undo: pick files of the last batch to revert (prompts when LIST is omitted)
+  --parents           mv/cp: recreate each source path under the destination (like cp --parents)
+  --strip-components N  mv/cp: like --parents, minus the first N path components (like tar)
+  --preserve-structure  mv/cp with filters: keep each match's path below the search root
+  --mkpath            mv/cp: create a missing destination directory (asked on a terminal otherwise)
+  --on-error POLICY   When an item fails: continue (default), abort (keep what was done),
+                      rollback (reverse the batch so far) or prompt; for transforms, mv, cp, rm
+  --any-ext           Touch file types a directory's allow_ext config would refuse
+
+PROTECTED FILES:
+  A .smvignore (gitignore syntax) in any directory lists files below it that are never
+  renamed or moved; so does an xattr user.smv.norename or a NORENAME tag on a single file
+  --simulate          Run a transform on an in-memory copy of the target and report what happens
+  --full              With -p: list every rename; previews of more than 1,000 items otherwise show
+                      a random sample and the count per rename pattern (IMG_#### → ####)
+  --show N            rm: list the N largest files (default 5) with the total size and the
+                      size per extension before asking to confirm
+  --pick              Choose which matches to act on in a fuzzy finder (fzf if installed, else
+                      built in; Tab marks, Enter runs) before the plan is built
+  --copy-names        After the run, copy the new paths of renamed, moved or created files to
+                      the clipboard, one per line (wl-copy, xclip, xsel or pbcopy)
+  --script FILE       With -I: run REPL commands from FILE (one per line, # comments) and exit;
+                      -F answers its confirmations, otherwise they are read from stdin
+  --resume            With -I: carry on with the last session: its directory and directory stack,
+                      the last preview and a rename that was waiting for confirmation
+  --name-fallback F   When a transform would leave an empty, dot-only or hidden name: keep (default)
+                      the name, or placeholder (\"***.png\" → untitled.png)
+  --non-utf8 P        Names that are not valid UTF-8: skip (default) leaves them and says so,
+                      transliterate reads the stray bytes as Latin-1 (Caf\\xe9 → Café) and renames
+  --notify-after SECS Desktop notification with the summary when a run takes SECS or longer
+                      (default 60); --notify always notifies, --no-notify never does (it wins
+                      over --notify, so an alias with --notify can be silenced)
+  --permanent         rm: delete for good; by default removed items go to the trash in the
+                      state directory and smv -u restores them (history gc empties it);
+                      required while SMV_HISTORY_KEY encrypts the history
+  --heartbeat SECS    mv/cp: print files done/left, errors and the current directory every SECS
+  --output json       The records of --json, plus for mv/cp progress as NDJSON events on stderr
+                      (heartbeats every 5s unless --heartbeat says otherwise, plus one per
+                      finished directory)
+                      With -u: what the undo restored and skipped, one JSON record per line
+                      With -u -p: one JSON record per file the undo would touch
+                      With detect: one JSON record per directory
+                      With where-did-it-go: one JSON record per traced file
+  --json              Transforms, mv, cp, rm, mkdir, -cf and plans: no prose, one JSON record per
+                      line on stdout: planned, done, conflict, skipped and error per file, stats
+                      last; conflicts carry a code (TARGET_EXISTS, SOURCE_MISSING,
+                      CASE_INSENSITIVE_COLLISION, NAME_TOO_LONG, ...), and planned ops that cross
+                      filesystems list CROSS_DEVICE under warnings
+                      (FORMAT:json does the same on a CNP command that changes files)
+  --newer-than AGE    Only files modified within AGE (7d, 12h) or after DATE (same as MODIFIED>)
+  --older-than AGE    Only files modified more than AGE ago or before DATE (same as MODIFIED<)
+  --larger-than SIZE  Only files larger than SIZE, e.g. 10MB (same as SIZE>)
+  --smaller-than SIZE Only files smaller than SIZE (same as SIZE<)
+  --name PATTERN      Only names matching PATTERN (same as NAME:)
+  --ext EXTS          Only files with these extensions, e.g. jpg,png or !tmp (same as EXT:)
 
 EXAMPLES:
   smv snake .                          # Transform files to snake_case  
@@ -79,14 +247,84 @@ EXAMPLES:
   smv split snake .                    # Split camelCase/PascalCase then apply snake_case
   smv split kebab featureList.md -p    # Preview: featureList.md → feature-list.md
   smv snake . -e                       # Transform files AND directories
+  smv snake docs/ -r --apply-to dirs   # Only the folders, not the files in them
+  smv snake . -re --simulate           # Dry run that renames exactly what a real run would
   smv CHANGE \"IMG_\" INTO \"\" . -rp      # Preview remove IMG_ prefix recursively
   smv mv file.txt newname.txt          # Rename file
+  smv -I --script tidy.smv -F          # Run a saved REPL cleanup routine without prompts
+  smv -I --resume                      # Pick up an interrupted REPL cleanup where it stopped
   smv rm . EXT:log -p                  # Preview delete all .log files
+  smv rm . WHERE \"(EXT:log OR EXT:tmp) AND NOT NAME:keep\" -rp   # Grouped filters
+  other-tool | smv --stdin-ops -p       # Preview a move plan produced by another tool
+  smv batch moves.csv -p && smv batch moves.csv   # Check, then run, a move plan from an inventory system
+  smv snake . -r --plan plan.json && $EDITOR plan.json && smv apply-plan plan.json
+  smv index build /archive --hash && smv /archive EXT:iso SIZE>4GB FORMAT:json  # Query a saved listing
+  smv flatten . -p --du                # Preview flatten with before/after directory sizes
+  smv flatten archive -F               # Flatten more than 1,000 files; mount points stay put
+  smv snake inbox/ INTO-DIR:archive/ -r  # Rename to snake_case and file into archive/ in one step
+  smv clean data/ INTO-DIR:export/ -r --copy  # Cleaned export, source tree untouched
+  smv snake photos/ -r SAMPLE:5% -p  # Try a transform on a random 5% of matches first
+  smv rm downloads/ EXT:zip --pick     # Fuzzy-pick which of the matching archives to remove
+  smv kebab downloads/ --newer-than 7d --ext pdf -p  # Filter options instead of CNP keywords
+  smv snake photos/ -r 'NAME~:^IMG_[0-9]{4}' -p  # Only camera-named files
+  smv rm . EMPTY:true -r -p            # Preview removing empty files and directories
+  smv rm photos/ DUPES: -r -p          # Preview removing extra copies, keeping one of each
+  smv rm old/ -r && smv -u             # Removed by mistake? Undo restores the whole tree
+  smv cp --parents src/a/b.txt backup/   # Copy to backup/src/a/b.txt
+  smv mv --strip-components 1 shoots/*/raw/*.cr2 raw/ -p  # Keep <shoot>/raw/<file> under raw/
+  smv cp . EXT:raw -r /mnt/backup --preserve-structure  # Back up raws, tree intact
+  smv mv notes.txt archive/2024/ --mkpath  # Create archive/2024 first; undo removes it
+  smv mv 'report_{1..12}.pdf' 'reports/{01..12}/' -p  # report_3.pdf → reports/03/
+  smv mv *.log 'logs/{}.archived.log'  # app.log → logs/app.archived.log
+  smv cp -r photos/ /mnt/backup/ --heartbeat 60  # Checkpoint every minute on long copies
+  smv undo --select 2,4-6              # Move back only some files of the last batch
+  smv undo -p                          # What would undo restore, and what changed since?
+  smv history gc --max-history-age 30 -p   # Preview pruning history older than a month
+  smv stats --since 30d                # What smv did this month, and how often it was undone
+  smv detect ~/notes                   # Which naming style each folder uses, and how to fix stragglers
+  smv where-did-it-go report           # Every file once or now named *report*, and where it is now
+  smv clean . EXT:jpg THEN sort date THEN mv TO-DIR:~/Photos -r
+                                       # Clean names, file by date and move, as one batch
+  smv stage photos && smv snake photos.smv-staging -r && smv commit-stage photos
+                                       # Reorganize a copy, then swap it in at once
+  eval \"$(smv shell-init zsh)\"        # In ~/.zshrc: Ctrl-X s renames the word before the cursor
+  smv sort alpha photos/ --levels 2     # photos/abcdef.jpg → photos/a/ab/abcdef.jpg
+  smv tier . SIZE>1GB ACCESSED<2023-01-01 TO-DIR:/mnt/cold -r   # Move cold data to slow storage
+  smv attr . EXT:sh PERM:+x -r         # Make every shell script executable
+  smv attr shared/ PERM:g-w -r -e -p   # Preview taking group write off a whole tree
+  smv bucket 'REGEX:^(\\d{4})' . -r     # Move files into year folders from their names
+  smv sort date scans/ --layout '{Y}/{Y}-{m}-{month_name}'  # scans/2024/2024-06-June/
+  smv bucket MTIME . --layout '{relative}'  # today/, last-week/, 2024-03/ ...
   smv -cd newdir                       # Create directory
   smv -cf newfile.txt                  # Create file
+  smv snake . -p --json | jq -r 'select(.event==\"planned\") | .to'   # Script around a preview
   smv tui                              # Launch file explorer UI
-
-Use 'smv --help' for complete documentation."
+  smv tui -F                           # TUI that runs the queue without reviewing it
+  smv kebab notes/ -r --update-links   # Rename notes and fix links pointing at them
+  smv note vault/ -rp                  # Preview: My Idea.md → 2024-03-01-my-idea.md
+  smv template \"{fm:date}-{fm:title|kebab}.md\" notes/ -r   # Rename notes from front matter
+  smv template \"{pdf:date}_{pdf:title|snake}.pdf\" scans/  # Rename PDFs from their metadata
+  smv template \"{doc:modified}_{doc:title|kebab}.{ext}\" shared/   # Rename docx/xlsx from properties
+  smv template \"{name}_{width}x{height}_{codec}.{ext}\" videos/  # Tag footage with its quality
+  smv template \"{parent}_{n:02}.{ext}\" music/ -r     # Number tracks after their album folder
+  smv template \"shot_{n:03}.{ext}\" photos/ BY:mtime ASC -p  # Number photos oldest first
+
+ENVIRONMENT:
+  SMV_STATE_DIR       Directory for the undo history and backups
+                      (default: $XDG_STATE_HOME/smv or ~/.local/state/smv; an existing ~/.config/smv/backups is kept)
+  SMV_HOME            Base directory for SMV data; history goes to $SMV_HOME/state
+                      and the config to $SMV_HOME/config.toml
+  SMV_CONFIG          Config file (default: ~/.config/smv/config.toml); [dir.\"~/Pictures\"]
+                      allow_ext = [\"jpg\", \"png\"] keeps other file types out of a directory;
+                      [tools.paths] dsc = \"/usr/local/bin/dsc\" pins a delegated tool, and
+                      [tools] delegation = false refuses to start any
+  SMV_HISTORY_KEY     age identity file (age-keygen -o key.txt); encrypts history and backups
+                      (needs a build with --features encryption)
+  SMV_WIDGET_STYLE    Style the shell-init widget suggests (default: snake)
+
+Use 'smv --help' for complete documentation.",
+    // Preset runs add options that may repeat the saved ones; the last wins
+    args_override_self = true
 )]
 struct Args {
     // === XFD COMMAND SYNTAX ===
@@ -137,7 +375,7 @@ struct Args {
     #[arg(short = 'T', action = ArgAction::SetTrue, help = "TUI - launch terminal UI file explorer")]
     tui: bool,
 
-    #[arg(short = 'u', action = ArgAction::SetTrue, help = "Undo - reverse last operation")]
+    #[arg(short = 'u', action = ArgAction::SetTrue, help = "Undo - reverse the last command")]
     undo: bool,
 
     #[arg(short = 'c', action = ArgAction::SetTrue, help = "Create - must be combined with -d (directories) or -F (files)")]
@@ -174,6 +412,31 @@ struct Args {
     #[arg(short = 'e', long = "everything", action = ArgAction::SetTrue, help = "Process everything (files and directories)")]
     everything: bool,
 
+    #[arg(short = 'x', long = "one-file-system", action = ArgAction::SetTrue, help = "Stay on one filesystem - do not descend into other mounts")]
+    one_file_system: bool,
+
+    #[arg(
+        long = "apply-to",
+        value_name = "KIND",
+        value_parser = ApplyTo::parse,
+        help = "What transforms rename: files (default), dirs or both"
+    )]
+    apply_to: Option<ApplyTo>,
+
+    #[arg(
+        long = "on-error",
+        value_name = "POLICY",
+        value_parser = OnError::parse,
+        help = "When an item fails: continue (default), abort, rollback or prompt"
+    )]
+    on_error: Option<OnError>,
+
+    #[arg(long = "env", action = ArgAction::SetTrue, help = "With doctor: check the runtime environment")]
+    env: bool,
+
+    #[arg(long = "any-ext", action = ArgAction::SetTrue, help = "Touch any type of file, even in directories whose config allows only some")]
+    any_ext: bool,
+
     #[arg(
         short = 'm',
         long = "mode",
@@ -190,6 +453,192 @@ struct Args {
     /// Maximum number of operations to keep in history
     #[arg(long, value_name = "SIZE", default_value = "50")]
     max_history_size: usize,
+
+    /// Directory for the undo history and backups
+    #[arg(long = "state-dir", value_name = "DIR")]
+    state_dir: Option<PathBuf>,
+
+    /// Days of history `history gc` keeps
+    #[arg(long, value_name = "DAYS")]
+    max_history_age: Option<u32>,
+
+    /// Space the history backups may use before `history gc` prunes them (e.g. 500MB)
+    #[arg(long, value_name = "SIZE")]
+    max_history_bytes: Option<String>,
+
+    /// With stats: only activity within AGE (30d, 12h) or since a YYYY-MM-DD date
+    #[arg(long, value_name = "AGE|DATE")]
+    since: Option<String>,
+
+    /// With preset save: what the preset is for, shown by preset list
+    #[arg(long, value_name = "TEXT")]
+    description: Option<String>,
+
+    // === LINK MAINTENANCE ===
+    /// Rewrite links inside notes that point at renamed files
+    #[arg(long = "update-links", action = ArgAction::SetTrue)]
+    update_links: bool,
+
+    /// Comma-separated file types scanned by --update-links (default: md,markdown,org)
+    #[arg(long = "link-ext", value_name = "EXTS")]
+    link_ext: Option<String>,
+
+    /// Show bytes per source/destination directory in previews
+    #[arg(long = "du", action = ArgAction::SetTrue)]
+    du: bool,
+
+    /// Execute a move/rename plan read from stdin (TAB or " -> " pairs, or JSON ops)
+    #[arg(long = "stdin-ops", action = ArgAction::SetTrue)]
+    stdin_ops: bool,
+
+    /// Preview only and save the planned renames to FILE for `smv apply-plan`
+    #[arg(long = "plan", value_name = "FILE")]
+    plan: Option<PathBuf>,
+
+    /// With index build/update: also record a content hash of every file
+    #[arg(long = "hash", action = ArgAction::SetTrue)]
+    hash: bool,
+
+    /// Hash algorithm for HASH:, DUPES:, index hashes and undo fingerprints
+    #[arg(long = "hash-algo", value_name = "ALGO")]
+    hash_algo: Option<String>,
+
+    /// Mode of the directories created on the way to a destination
+    #[arg(long = "dir-mode", value_name = "MODE")]
+    dir_mode: Option<String>,
+
+    /// Date bucket names for `sort date` and DATE/MTIME buckets, e.g. "{Y}/{m}-{month_name}"
+    #[arg(long = "layout", value_name = "TEMPLATE")]
+    layout: Option<String>,
+
+    /// Shard depth for `sort alpha` (2 gives a/ab/abcdef.txt)
+    #[arg(long, value_name = "N", default_value = "2")]
+    levels: usize,
+
+    /// Transforms write renamed copies and leave the originals untouched
+    #[arg(long = "copy", action = ArgAction::SetTrue)]
+    copy: bool,
+
+    /// With undo: revert only the listed files of the last batch (prompts without a list)
+    #[arg(long, value_name = "LIST", num_args = 0..=1, default_missing_value = "")]
+    select: Option<String>,
+
+    /// mv/cp: recreate each source path under the destination (like cp --parents)
+    #[arg(long = "parents", action = ArgAction::SetTrue)]
+    parents: bool,
+
+    /// mv/cp with filters: keep each match's path relative to the search root
+    #[arg(long = "preserve-structure", action = ArgAction::SetTrue)]
+    preserve_structure: bool,
+
+    /// mv/cp: recreate source paths minus their first N components (like tar)
+    #[arg(
+        long = "strip-components",
+        value_name = "N",
+        conflicts_with = "parents"
+    )]
+    strip_components: Option<usize>,
+
+    /// What to do when a transform yields an empty, dot-only or hidden name: keep or placeholder
+    #[arg(
+        long = "name-fallback",
+        value_name = "keep|placeholder",
+        default_value = "keep"
+    )]
+    name_fallback: String,
+
+    /// Names that are not valid UTF-8: skip them (and say so) or transliterate them
+    #[arg(
+        long = "non-utf8",
+        value_name = "skip|transliterate",
+        default_value = "skip"
+    )]
+    non_utf8: String,
+
+    /// With -p: list every rename, however many there are
+    #[arg(long = "full", action = ArgAction::SetTrue)]
+    full: bool,
+
+    /// Run transforms against an in-memory copy of the target; nothing on disk changes
+    #[arg(long = "simulate", action = ArgAction::SetTrue)]
+    simulate: bool,
+
+    /// Choose which matches to operate on in a fuzzy finder (fzf, or the built-in one)
+    #[arg(long = "pick", action = ArgAction::SetTrue)]
+    pick: bool,
+
+    /// Copy the new paths of the files the run renamed, moved or created to the clipboard
+    #[arg(long = "copy-names", action = ArgAction::SetTrue)]
+    copy_names: bool,
+
+    /// With -I: run the REPL commands in FILE instead of prompting
+    #[arg(long = "script", value_name = "FILE")]
+    script: Option<PathBuf>,
+
+    /// With -I: restore the working set the last REPL session left
+    #[arg(long = "resume", action = ArgAction::SetTrue)]
+    resume: bool,
+
+    /// rm: how many of the largest files the confirmation lists
+    #[arg(long = "show", value_name = "N", default_value_t = DEFAULT_SHOW_LARGEST)]
+    show: usize,
+
+    /// mv/cp: create a missing destination directory (undo removes it again)
+    #[arg(long = "mkpath", action = ArgAction::SetTrue)]
+    mkpath: bool,
+
+    /// mv/cp: report progress on stderr every SECS seconds
+    #[arg(long = "heartbeat", value_name = "SECS")]
+    heartbeat: Option<u64>,
+
+    /// Always send a desktop notification when the run finishes
+    #[arg(long = "notify", action = ArgAction::SetTrue)]
+    notify: bool,
+
+    /// Never send a desktop notification, even with --notify (e.g. from an alias)
+    #[arg(long = "no-notify", action = ArgAction::SetTrue)]
+    no_notify: bool,
+
+    /// Notify when a run takes at least SECS seconds (default: 60)
+    #[arg(long = "notify-after", value_name = "SECS", default_value_t = notify::DEFAULT_NOTIFY_AFTER)]
+    notify_after: u64,
+
+    /// rm: delete for good instead of keeping removed items for undo
+    #[arg(long = "permanent", action = ArgAction::SetTrue)]
+    permanent: bool,
+
+    /// Format of reports: text, or json (NDJSON, the records of --json)
+    #[arg(long = "output", value_name = "text|json", default_value = "text")]
+    output: String,
+
+    /// Report planned and performed operations as JSON lines instead of prose
+    #[arg(long = "json", action = ArgAction::SetTrue)]
+    json: bool,
+
+    // === FILTER OPTIONS (same as the CNP filters) ===
+    /// Only files modified within AGE (7d, 12h) or after a YYYY-MM-DD date (MODIFIED>)
+    #[arg(long = "newer-than", value_name = "AGE|DATE")]
+    newer_than: Option<String>,
+
+    /// Only files last modified more than AGE ago or before a date (MODIFIED<)
+    #[arg(long = "older-than", value_name = "AGE|DATE")]
+    older_than: Option<String>,
+
+    /// Only files larger than SIZE, e.g. 10MB (SIZE>)
+    #[arg(long = "larger-than", value_name = "SIZE")]
+    larger_than: Option<String>,
+
+    /// Only files smaller than SIZE (SIZE<)
+    #[arg(long = "smaller-than", value_name = "SIZE")]
+    smaller_than: Option<String>,
+
+    /// Only names matching PATTERN, a substring or glob (NAME:)
+    #[arg(long = "name", value_name = "PATTERN")]
+    name: Option<String>,
+
+    /// Only files with these extensions: jpg,png or !tmp (EXT:)
+    #[arg(long = "ext", value_name = "EXTS")]
+    ext: Option<String>,
 }
 
 #[derive(Debug, Default)]
@@ -198,10 +647,284 @@ struct Stats {
     renamed: u32,
     errors: u32,
     skipped: u32,
+    /// Skipped and filtered-out items by reason
+    reasons: BTreeMap<SkipReason, u32>,
+    /// Set for previews too large to list: renames are sampled instead of printed
+    sample: Option<preview::Sample>,
+    /// Renames by the kind of edit they make (`prefix IMG_ removed`)
+    changes: HashMap<String, u32>,
+    /// The `.smvignore` files read so far
+    protection: protect::Protection,
+}
+
+impl Stats {
+    /// Whether `path` is protected from renaming, reporting and counting it when it is
+    fn protected(&mut self, path: &Path, preview_only: bool) -> bool {
+        let Some(reason) = self.protection.reason(path) else {
+            return false;
+        };
+        if output::json() {
+            output::skipped(Op::Rename, path, &format!("protected: {reason}"));
+        } else {
+            println!(
+                "{}Protected: \"{}\" ({})",
+                if preview_only { "[PREVIEW] " } else { "" },
+                path.display(),
+                reason
+            );
+        }
+        self.skip(SkipReason::Protected);
+        true
+    }
+
+    /// Count a rename of `old` to `new` under the kind of edit it makes
+    fn rename(&mut self, old: &str, new: &str) {
+        self.renamed += 1;
+        *self
+            .changes
+            .entry(report::edit_class(old, new))
+            .or_default() += 1;
+    }
+
+    /// Kinds of edits by how many renames made them, most first
+    fn changes(&self) -> Vec<(&str, u32)> {
+        let mut changes: Vec<(&str, u32)> = self
+            .changes
+            .iter()
+            .map(|(class, count)| (class.as_str(), *count))
+            .collect();
+        changes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        changes
+    }
+
+    /// Count an item left alone; filtered-out items are not part of `skipped`
+    fn skip(&mut self, reason: SkipReason) {
+        if !reason.is_filter() {
+            self.skipped += 1;
+        }
+        *self.reasons.entry(reason).or_default() += 1;
+    }
+
+    fn add_filtered(&mut self, filtered: Filtered) {
+        for (reason, count) in filtered {
+            *self.reasons.entry(reason).or_default() += count;
+        }
+    }
+
+    fn totals(&self) -> output::Totals {
+        output::Totals {
+            processed: self.processed,
+            done: self.renamed,
+            skipped: self.skipped,
+            errors: self.errors,
+        }
+    }
+
+    /// Why items were skipped or left out, and the kinds of renames, for the
+    /// closing `stats` record
+    fn details(&self) -> serde_json::Map<String, serde_json::Value> {
+        let mut details = serde_json::Map::new();
+        details.insert("skip_reasons".into(), self.reason_counts(false).into());
+        details.insert("filtered".into(), self.reason_counts(true).into());
+        if !self.changes.is_empty() {
+            details.insert("changes".into(), serde_json::json!(self.changes));
+        }
+        details
+    }
+
+    /// `code: count` for the reasons that are (or are not) filters
+    fn reason_counts(&self, filters: bool) -> serde_json::Map<String, serde_json::Value> {
+        self.reasons
+            .iter()
+            .filter(|(reason, _)| reason.is_filter() == filters)
+            .map(|(reason, count)| (reason.code().to_string(), (*count).into()))
+            .collect()
+    }
+}
+
+/// The kinds of entries a transform renames (`--apply-to`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ApplyTo {
+    Files,
+    Dirs,
+    Both,
+}
+
+impl ApplyTo {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "files" | "file" => Ok(ApplyTo::Files),
+            "dirs" | "dir" | "directories" => Ok(ApplyTo::Dirs),
+            "both" | "all" => Ok(ApplyTo::Both),
+            _ => Err(format!(
+                "Unknown --apply-to '{value}' (expected files, dirs or both)"
+            )),
+        }
+    }
+
+    /// `--apply-to`, or files only unless `-e` asks for everything
+    fn of(args: &Args) -> Self {
+        args.apply_to.unwrap_or(if args.everything {
+            ApplyTo::Both
+        } else {
+            ApplyTo::Files
+        })
+    }
+
+    fn admits(self, path: &Path) -> bool {
+        match self {
+            ApplyTo::Files => !path.is_dir(),
+            ApplyTo::Dirs => path.is_dir(),
+            ApplyTo::Both => true,
+        }
+    }
+}
+
+/// Why an item was left alone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum SkipReason {
+    Hidden,
+    Excluded,
+    ExtensionMismatch,
+    Permission,
+    NoChange,
+    Conflict,
+    UnusableName,
+    NotUtf8,
+    TemplateError,
+    Protected,
+}
+
+impl SkipReason {
+    fn code(self) -> &'static str {
+        match self {
+            SkipReason::Hidden => "hidden",
+            SkipReason::Excluded => "excluded",
+            SkipReason::ExtensionMismatch => "extension_mismatch",
+            SkipReason::Permission => "permission",
+            SkipReason::NoChange => "no_change",
+            SkipReason::Conflict => "conflict",
+            SkipReason::UnusableName => "unusable_name",
+            SkipReason::NotUtf8 => "not_utf8",
+            SkipReason::TemplateError => "template_error",
+            SkipReason::Protected => "protected",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SkipReason::Hidden => "hidden (use -a)",
+            SkipReason::Excluded => "matched --exclude",
+            SkipReason::ExtensionMismatch => "other extension",
+            SkipReason::Permission => "permission denied",
+            SkipReason::NoChange => "name already right",
+            SkipReason::Conflict => "conflict",
+            SkipReason::UnusableName => "unusable new name",
+            SkipReason::NotUtf8 => "name not UTF-8 (see --non-utf8)",
+            SkipReason::TemplateError => "template failed",
+            SkipReason::Protected => "protected (.smvignore or NORENAME)",
+        }
+    }
+
+    /// Left out while collecting items, before any of them was looked at
+    fn is_filter(self) -> bool {
+        matches!(
+            self,
+            SkipReason::Hidden
+                | SkipReason::Excluded
+                | SkipReason::ExtensionMismatch
+                | SkipReason::Permission
+        )
+    }
 }
 
+/// Items the file walk left out, by reason
+type Filtered = BTreeMap<SkipReason, u32>;
+
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
+    let notify_mode = notify::Mode::from_flags(args.notify, args.no_notify, args.notify_after);
+    let started = std::time::Instant::now();
+    let started_at = chrono::Local::now();
+    let (copy_names, max_history_size) = (args.copy_names, args.max_history_size);
+
+    let result = run(args);
+    if copy_names && result.is_ok() {
+        copy_new_names(max_history_size, started_at);
+    }
+    let failure = result.as_ref().err().map(|e| e.to_string());
+    notify::finish(notify_mode, started.elapsed(), failure.as_deref());
+    result
+}
+
+/// `--copy-names`: put the paths this run produced on the clipboard
+///
+/// The run already succeeded, so a missing clipboard tool is only a warning.
+fn copy_new_names(max_history_size: usize, since: chrono::DateTime<chrono::Local>) {
+    let mut names = clipboard::take_recorded();
+    if names.is_empty() {
+        names = HistoryManager::open(max_history_size, &paths::state_dir())
+            .map(|history| clipboard::new_paths(&history, Some(since)))
+            .unwrap_or_default();
+    }
+    if names.is_empty() {
+        eprintln!(
+            "{}",
+            "--copy-names: no files were renamed, nothing copied".yellow()
+        );
+        return;
+    }
+    match clipboard::copy_paths(&names) {
+        Ok(tool) => println!("Copied {} path(s) to the clipboard ({tool})", names.len()),
+        Err(e) => eprintln!("{}: --copy-names: {e}", "Warning".yellow()),
+    }
+}
+
+fn run(mut args: Args) -> Result<(), Box<dyn Error>> {
+    if let Some(ref dir) = args.state_dir {
+        paths::set_state_dir(dir.clone());
+    }
+    // A plan is only written by a run that changes nothing
+    if args.plan.is_some() {
+        args.preview = true;
+    }
+    // --output json is the same report as --json
+    if args.json || progress::OutputFormat::parse(&args.output)? == progress::OutputFormat::Json {
+        output::enable();
+    }
+    if let Some(ref name) = args.hash_algo {
+        hash::set_algorithm(hash::Algorithm::parse(name)?);
+    }
+    if let Some(ref mode) = args.dir_mode {
+        attr::set_dir_mode(mode)?;
+    }
+    NonUtf8::parse(&args.non_utf8)?.set();
+    history::set_command(
+        std::env::args_os()
+            .skip(1)
+            .map(|arg| wizard::quote_arg(&arg.to_string_lossy()))
+            .collect::<Vec<_>>()
+            .join(" "),
+    );
+
+    if args.stdin_ops {
+        return run_stdin_ops(&args);
+    }
+
+    // Templates and bucket rules are full of braces and colons, so keep them away from the CNP parser
+    match args.command.as_deref() {
+        Some("template") => return run_template_command(&args),
+        Some("bucket") => {
+            let rule = args
+                .arg1
+                .as_deref()
+                .ok_or("Missing bucket rule. Usage: smv bucket RULE [target]")?;
+            let rule = sort::BucketRule::parse_with_layout(rule, args.layout.as_deref())?;
+            let method = SortMethod::Bucket(rule);
+            return run_sort_command(&args, method);
+        }
+        _ => {}
+    }
 
     // Check if we should use CNP grammar parsing
     if should_use_cnp_grammar(&args) {
@@ -228,14 +951,41 @@ fn main() -> Result<(), Box<dyn Error>> {
             pattern,
             replacement,
         } => {
+            regex::Regex::new(&pattern)
+                .map_err(|e| format!("Invalid regex pattern '{pattern}': {e}"))?;
             let transform_type = TransformType::replace_regex(&pattern, &replacement);
             run_transform_command(&args, transform_type)?
         }
         XfdCommand::Transform(transform_type) => run_transform_command(&args, transform_type)?,
+        XfdCommand::Note => run_note_command(&args)?,
         XfdCommand::Sort { method } => run_sort_command(&args, method)?,
-        XfdCommand::Interactive => run_interactive_mode(args.max_history_size)?,
-        XfdCommand::Tui => run_tui_mode()?,
-        XfdCommand::Undo => run_undo_mode(args.max_history_size)?,
+        XfdCommand::Interactive => run_interactive_mode(
+            args.max_history_size,
+            args.script.as_deref(),
+            args.resume,
+            args.force,
+        )?,
+        XfdCommand::Tui => run_tui_mode(args.max_history_size, args.force)?,
+        XfdCommand::Wizard => run_wizard()?,
+        XfdCommand::Preset(command) => run_preset_command(command)?,
+        XfdCommand::Undo => run_undo_mode(&args, args.force)?,
+        XfdCommand::HistoryGc => run_history_gc(&args)?,
+        XfdCommand::HistoryList => run_history_list(&args)?,
+        XfdCommand::HistoryUndo { number } => run_history_undo(&args, number)?,
+        XfdCommand::Stats => run_stats(&args)?,
+        XfdCommand::Detect => run_detect(&args)?,
+        XfdCommand::Doctor => run_doctor(&args)?,
+        XfdCommand::WhereDidItGo { name } => run_where_did_it_go(&args, &name)?,
+        XfdCommand::Stage { dir } => run_stage_command(&args, &dir)?,
+        XfdCommand::CommitStage { dir } => run_commit_stage(&args, &dir)?,
+        XfdCommand::ApplyPlan { file } => run_apply_plan(&args, &file)?,
+        XfdCommand::Batch { file } => run_batch(&args, &file)?,
+        XfdCommand::Index { action, dir } => run_index_command(&args, action, &dir)?,
+        XfdCommand::ShellInit { shell } => print!("{}", shell.script()),
+        XfdCommand::ShellName {
+            transform_type,
+            word,
+        } => println!("{}", shell_init::renamed_word(&word, &transform_type)),
         XfdCommand::Move {
             sources,
             destination,
@@ -263,12 +1013,51 @@ enum XfdCommand {
         replacement: String,
     },
     Transform(TransformType),
+    Note,
     Sort {
         method: SortMethod,
     },
     Interactive,
     Tui,
+    Wizard,
+    Preset(preset::PresetCommand),
     Undo,
+    HistoryGc,
+    HistoryList,
+    /// `smv history undo N`: N counts batches from the newest, as listed
+    HistoryUndo {
+        number: usize,
+    },
+    Stats,
+    Detect,
+    Doctor,
+    WhereDidItGo {
+        name: String,
+    },
+    Stage {
+        dir: String,
+    },
+    CommitStage {
+        dir: String,
+    },
+    ApplyPlan {
+        file: String,
+    },
+    Batch {
+        file: String,
+    },
+    Index {
+        action: IndexAction,
+        dir: String,
+    },
+    ShellInit {
+        shell: shell_init::Shell,
+    },
+    /// The renamed word the shell widget puts on the command line
+    ShellName {
+        transform_type: TransformType,
+        word: String,
+    },
     Move {
         sources: Vec<String>,
         destination: String,
@@ -288,6 +1077,13 @@ enum XfdCommand {
     },
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IndexAction {
+    Build,
+    Update,
+    Clear,
+}
+
 #[derive(Debug, Clone)]
 enum SortMethod {
     Group,
@@ -295,6 +1091,8 @@ enum SortMethod {
     ByType,
     ByDate,
     BySize,
+    Bucket(sort::BucketRule),
+    Alpha,
 }
 
 fn parse_xfd_command(args: &Args) -> Result<XfdCommand, Box<dyn Error>> {
@@ -492,12 +1290,21 @@ fn parse_xfd_command(args: &Args) -> Result<XfdCommand, Box<dyn Error>> {
                 "studly" => Ok(XfdCommand::Transform(TransformType::SplitStudly)),
                 "lower" => Ok(XfdCommand::Transform(TransformType::SplitLower)),
                 "upper" => Ok(XfdCommand::Transform(TransformType::SplitUpper)),
-                _ => Err(format!("Unknown split transformation: {transform_type}").into()),
+                _ => Err(suggest::unknown_split(transform_type).into()),
             }
         }
-        Some("sort") => Ok(XfdCommand::Sort {
-            method: SortMethod::Group,
-        }), // Default sort method
+        Some("note") => Ok(XfdCommand::Note),
+        Some("sort") => match args.arg1.as_deref() {
+            Some("alpha") => Ok(XfdCommand::Sort {
+                method: SortMethod::Alpha,
+            }),
+            Some("date") => Ok(XfdCommand::Sort {
+                method: SortMethod::ByDate,
+            }),
+            _ => Ok(XfdCommand::Sort {
+                method: SortMethod::Group,
+            }), // Default sort method
+        },
         Some("group") => Ok(XfdCommand::Sort {
             method: SortMethod::Group,
         }),
@@ -506,12 +1313,79 @@ fn parse_xfd_command(args: &Args) -> Result<XfdCommand, Box<dyn Error>> {
         }),
         Some("interactive") => Ok(XfdCommand::Interactive),
         Some("tui") => Ok(XfdCommand::Tui),
+        Some("wizard") => Ok(XfdCommand::Wizard),
+        Some("preset") => {
+            let command_line: Vec<String> = std::env::args_os()
+                .skip(1)
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect();
+            Ok(XfdCommand::Preset(preset::PresetCommand::parse(
+                &command_line,
+            )?))
+        }
         Some("undo") => Ok(XfdCommand::Undo),
+        Some("history") => match args.arg1.as_deref() {
+            Some("gc") => Ok(XfdCommand::HistoryGc),
+            Some("list") | None => Ok(XfdCommand::HistoryList),
+            Some("undo") => {
+                let number = args
+                    .into_keyword
+                    .as_deref()
+                    .ok_or("Missing batch number (usage: smv history undo N)")?;
+                let number = number
+                    .parse()
+                    .map_err(|_| format!("Not a batch number: {number} (see smv history)"))?;
+                Ok(XfdCommand::HistoryUndo { number })
+            }
+            Some(other) => {
+                Err(format!("Unknown history command: {other} (expected: list, undo or gc)").into())
+            }
+        },
+        Some("stats") => Ok(XfdCommand::Stats),
+        Some("detect") => Ok(XfdCommand::Detect),
+        Some("doctor") => Ok(XfdCommand::Doctor),
+        Some("where-did-it-go") => match args.arg1.clone() {
+            Some(name) => Ok(XfdCommand::WhereDidItGo { name }),
+            None => Err("Missing file name (usage: smv where-did-it-go <name>)".into()),
+        },
+        Some("stage") => match args.arg1.clone() {
+            Some(dir) => Ok(XfdCommand::Stage { dir }),
+            None => Err("Missing directory (usage: smv stage <dir>)".into()),
+        },
+        Some("commit-stage") => match args.arg1.clone() {
+            Some(dir) => Ok(XfdCommand::CommitStage { dir }),
+            None => Err("Missing directory (usage: smv commit-stage <dir>)".into()),
+        },
+        Some("apply-plan") => match args.arg1.clone() {
+            Some(file) => Ok(XfdCommand::ApplyPlan { file }),
+            None => Err("Missing plan file (usage: smv apply-plan <plan.json>)".into()),
+        },
+        Some("batch") => match args.arg1.clone() {
+            Some(file) => Ok(XfdCommand::Batch { file }),
+            None => Err("Missing operations file (usage: smv batch <ops.csv>, - for stdin)".into()),
+        },
+        Some("index") => {
+            let action = match args.arg1.as_deref() {
+                Some("build") => IndexAction::Build,
+                Some("update") => IndexAction::Update,
+                Some("clear") => IndexAction::Clear,
+                Some(other) => return Err(format!("Unknown index command: {other}").into()),
+                None => {
+                    return Err("Missing index command (expected: build, update or clear)".into());
+                }
+            };
+            let dir = args
+                .into_keyword
+                .clone()
+                .unwrap_or_else(|| String::from("."));
+            Ok(XfdCommand::Index { action, dir })
+        }
+        Some("shell-init") => parse_shell_init_command(args),
         Some("cp") => parse_copy_command(args),
         Some("mv") => parse_move_command(args),
         Some("rm") => parse_remove_command(args),
         Some("mkdir") => parse_mkdir_command(args),
-        Some(unknown) => Err(format!("Unknown command: {unknown}").into()),
+        Some(unknown) => Err(suggest::unknown_command(unknown).into()),
         None => {
             // Check if this looks like a basic file operation (source(s) and destination)
             if let Some(ref command) = args.command {
@@ -794,61 +1668,143 @@ fn run_move_command(
     sources: &[String],
     destination: &str,
 ) -> Result<(), Box<dyn Error>> {
+    if brace::has_braces(destination) || destination.contains("{}") {
+        return run_patterned_move(args, sources, destination);
+    }
+
     let config = build_file_op_config(args);
 
-    println!("\n{}", "CNP Smart Move - Move Operation".bold());
-    println!("Sources: {}", sources.join(", ").cyan());
-    println!("Destination: {}", destination.cyan());
-    println!(
-        "Recursive: {}",
-        if config.recursive {
-            "Yes".green()
-        } else {
-            "No".yellow()
-        }
-    );
-    println!(
-        "Force: {}",
-        if config.force {
-            "Yes".red()
-        } else {
-            "No".green()
-        }
-    );
-    println!(
-        "No-clobber: {}",
-        if config.no_clobber {
-            "Yes".green()
-        } else {
-            "No".yellow()
-        }
-    );
-    println!(
-        "Interactive: {}",
-        if config.interactive {
-            "Yes".cyan()
-        } else {
-            "No".yellow()
-        }
-    );
-    println!(
-        "Preserve metadata: {}",
-        if config.preserve_metadata {
-            "Yes".green()
-        } else {
-            "No".yellow()
-        }
-    );
-    println!();
+    if !output::json() {
+        println!("\n{}", "CNP Smart Move - Move Operation".bold());
+        println!("Sources: {}", sources.join(", ").cyan());
+        println!("Destination: {}", destination.cyan());
+        println!(
+            "Recursive: {}",
+            if config.recursive {
+                "Yes".green()
+            } else {
+                "No".yellow()
+            }
+        );
+        println!(
+            "Force: {}",
+            if config.force {
+                "Yes".red()
+            } else {
+                "No".green()
+            }
+        );
+        println!(
+            "No-clobber: {}",
+            if config.no_clobber {
+                "Yes".green()
+            } else {
+                "No".yellow()
+            }
+        );
+        println!(
+            "Interactive: {}",
+            if config.interactive {
+                "Yes".cyan()
+            } else {
+                "No".yellow()
+            }
+        );
+        println!(
+            "Preserve metadata: {}",
+            if config.preserve_metadata {
+                "Yes".green()
+            } else {
+                "No".yellow()
+            }
+        );
+        println!();
+    }
 
     // Expand glob patterns
     let expanded_sources = expand_glob_patterns(sources)?;
     let dest_path = Path::new(destination);
+    check_dir_rules(args, &expanded_sources, Some(dest_path))?;
+    let created_dirs = ensure_destination_dir(args, destination, expanded_sources.len())?;
+
+    if args.preview {
+        let dest_is_dir =
+            dest_path.is_dir() || destination_names_dir(destination, expanded_sources.len());
+        let moves: Vec<(PathBuf, PathBuf)> = expanded_sources
+            .iter()
+            .map(|source| {
+                let target =
+                    file_ops::destination_for(source, dest_path, dest_is_dir, config.layout);
+                (source.clone(), target)
+            })
+            .collect();
+
+        if output::json() {
+            for (source, target) in &moves {
+                output::planned(Op::Move, Some(source), Some(target));
+            }
+            output::stats(
+                true,
+                output::Totals {
+                    processed: moves.len() as u32,
+                    done: moves.len() as u32,
+                    ..Default::default()
+                },
+            );
+            return Ok(());
+        }
+        for (source, target) in &moves {
+            println!(
+                "{} Move: {:?} → {:?}",
+                "[PREVIEW]".blue().bold(),
+                source,
+                target
+            );
+        }
+        print_disk_usage(args, &moves);
+        println!(
+            "\n{}",
+            "This was a preview only. No files were actually moved."
+                .bold()
+                .blue()
+        );
+        return Ok(());
+    }
 
     // Execute move operation
+    let config = FileOpConfig {
+        progress: start_progress(args, &expanded_sources)?,
+        ..config
+    };
     let stats = move_files(&expanded_sources, dest_path, &config)?;
+    if let Some(progress) = &config.progress {
+        progress.finish();
+    }
+
+    // Moves into created directories are undoable, so `smv -u` can remove them again
+    if let Some(outermost) = created_dirs.first() {
+        let cwd = std::env::current_dir()?;
+        let moves: Vec<(PathBuf, PathBuf)> = expanded_sources
+            .iter()
+            .map(|source| {
+                let target = file_ops::destination_for(source, dest_path, true, config.layout);
+                (cwd.join(source), cwd.join(target))
+            })
+            .filter(|(source, target)| !source.exists() && target.symlink_metadata().is_ok())
+            .collect();
+        let root = cwd.join(outermost.parent().unwrap_or(Path::new("")));
+        let backup_dir = paths::state_dir();
+        fs::create_dir_all(&backup_dir)?;
+        let mut history_manager = HistoryManager::open(args.max_history_size, &backup_dir)?;
+        history_manager.record_mapping(root, &moves)?;
+    }
 
     // Print results
+    notify::set_summary(format!("{} moved, {} errors", stats.moved, stats.errors));
+    if output::json() {
+        output::stats(false, stats.totals());
+        return Ok(());
+    }
     println!("\n{}:", "Results".bold());
     println!("Files processed: {}", stats.processed.to_string().cyan());
     println!("Files moved: {}", stats.moved.to_string().green());
@@ -858,37 +1814,197 @@ fn run_move_command(
     Ok(())
 }
 
-fn run_remove_command(args: &Args, targets: &[String]) -> Result<(), Box<dyn Error>> {
-    let config = build_file_op_config(args);
-
-    println!("\n{}", "CNP Smart Move - Remove Operation".bold());
-    println!("Targets: {}", targets.join(", ").cyan());
-    println!(
-        "Recursive: {}",
-        if config.recursive {
-            "Yes".green()
-        } else {
-            "No".yellow()
-        }
-    );
-    println!(
-        "Force: {}",
-        if config.force {
-            "Yes".red()
+/// Move with a destination pattern: `{01..12}`/`{a,b}` groups or `{}` for the source stem
+///
+/// A destination that expands to several paths is paired with the sources in order;
+/// a single one applies to every source. Paths ending in `/` are directories.
+fn run_patterned_move(
+    args: &Args,
+    sources: &[String],
+    destination: &str,
+) -> Result<(), Box<dyn Error>> {
+    // Brace groups in quoted sources expand here; missing ones are reported by the planner
+    let mut expanded_sources = Vec::new();
+    for source in sources.iter().flat_map(|source| brace::expand(source)) {
+        if Path::new(&source).symlink_metadata().is_err()
+            && (source.contains('*') || source.contains('?') || source.contains('['))
+        {
+            expanded_sources.extend(expand_glob_patterns(&[source])?);
         } else {
-            "No".green()
+            expanded_sources.push(PathBuf::from(source));
         }
-    );
+    }
+    let expanded_sources = file_ops::dedupe_reported(expanded_sources);
+
+    let destinations = brace::expand(destination);
+    if destinations.len() > 1 && destinations.len() != expanded_sources.len() {
+        return Err(format!(
+            "{} sources but the destination pattern expands to {} paths",
+            expanded_sources.len(),
+            destinations.len()
+        )
+        .into());
+    }
+
+    if !output::json() {
+        println!("\n{}", "CNP Smart Move - Batch Move".bold());
+        println!("Sources: {}", expanded_sources.len().to_string().cyan());
+        println!("Destination pattern: {}", destination.cyan());
+        println!();
+    }
+
+    let planned: Vec<(PathBuf, PathBuf)> = expanded_sources
+        .into_iter()
+        .enumerate()
+        .map(|(index, source)| {
+            let pattern = &destinations[index.min(destinations.len() - 1)];
+            let target = brace::substitute_stem(pattern, &source);
+            let target = if target.ends_with('/') || Path::new(&target).is_dir() {
+                Path::new(&target).join(source.file_name().unwrap_or_default())
+            } else {
+                PathBuf::from(target)
+            };
+            (source, target)
+        })
+        .collect();
+
+    run_file_batch(
+        args,
+        Path::new("."),
+        args.preview,
+        args.force,
+        planned,
+        false,
+    )
+}
+
+/// Build a command from the wizard's questions, show it and run it once confirmed
+fn run_wizard() -> Result<(), Box<dyn Error>> {
+    use std::io::{self, Write};
+
+    println!("\n{}\n", "CNP Smart Move - Wizard".bold());
+    let stdin = io::stdin();
+    let Some(answers) = wizard::ask(&mut stdin.lock(), &mut io::stdout())? else {
+        println!("\nWizard cancelled.");
+        return Ok(());
+    };
+
+    let command_line = answers.command_line();
     println!(
-        "Interactive: {}",
-        if config.interactive {
-            "Yes".cyan()
-        } else {
-            "No".yellow()
-        }
+        "\nThe command for this is:\n\n  {}\n",
+        wizard::display_command(&command_line).cyan().bold()
     );
+    print!("Run it now? [y/N] ");
+    io::stdout().flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    if !matches!(input.trim().to_lowercase().as_str(), "y" | "yes") {
+        println!("Not run. Copy the command above to use it later.");
+        return Ok(());
+    }
 
-    println!();
+    let args = Args::try_parse_from(std::iter::once("smv".to_string()).chain(command_line))?;
+    run(args)
+}
+
+/// Save, list, delete or run the commands kept in presets.toml
+fn run_preset_command(command: preset::PresetCommand) -> Result<(), Box<dyn Error>> {
+    let mut presets = preset::Presets::load()?;
+    match command {
+        preset::PresetCommand::List => {
+            println!("{}", "CNP Smart Move - Presets".bold());
+            let mut any = false;
+            for (name, preset) in presets.iter() {
+                any = true;
+                match &preset.description {
+                    Some(description) => println!("\n  {}  {}", name.cyan().bold(), description),
+                    None => println!("\n  {}", name.cyan().bold()),
+                }
+                println!("    {}", wizard::display_command(&preset.args));
+            }
+            if !any {
+                println!(
+                    "\nNo presets saved yet. Save one with {}",
+                    "smv preset save NAME COMMAND...".cyan()
+                );
+            }
+        }
+        preset::PresetCommand::Save { name, preset } => {
+            // Catch typos now rather than on the first run
+            Args::try_parse_from(std::iter::once("smv".to_string()).chain(preset.args.clone()))?;
+            let line = wizard::display_command(&preset.args);
+            let replaced = presets.insert(name.clone(), preset).is_some();
+            let path = presets.save()?;
+            println!(
+                "{} preset {}: {}",
+                if replaced { "Replaced" } else { "Saved" },
+                name.cyan().bold(),
+                line
+            );
+            println!(
+                "Run it with {} ({})",
+                format!("smv preset run {name}").cyan(),
+                path.display()
+            );
+        }
+        preset::PresetCommand::Remove { name } => {
+            if presets.remove(&name).is_none() {
+                return Err(format!("Unknown preset: {name}").into());
+            }
+            presets.save()?;
+            println!("Removed preset {}", name.cyan());
+        }
+        preset::PresetCommand::Run { name, overrides } => {
+            let preset = presets.get(&name).ok_or_else(|| {
+                format!("Unknown preset: {name} (smv preset list shows the saved ones)")
+            })?;
+            let command_line = preset.command_line(&overrides);
+            println!(
+                "Running preset {}: {}",
+                name.cyan().bold(),
+                wizard::display_command(&command_line)
+            );
+            let args =
+                Args::try_parse_from(std::iter::once("smv".to_string()).chain(command_line))?;
+            run(args)?;
+        }
+    }
+    Ok(())
+}
+
+fn run_remove_command(args: &Args, targets: &[String]) -> Result<(), Box<dyn Error>> {
+    let config = build_file_op_config(args);
+
+    if !output::json() {
+        println!("\n{}", "CNP Smart Move - Remove Operation".bold());
+        println!("Targets: {}", targets.join(", ").cyan());
+        println!(
+            "Recursive: {}",
+            if config.recursive {
+                "Yes".green()
+            } else {
+                "No".yellow()
+            }
+        );
+        println!(
+            "Force: {}",
+            if config.force {
+                "Yes".red()
+            } else {
+                "No".green()
+            }
+        );
+        println!(
+            "Interactive: {}",
+            if config.interactive {
+                "Yes".cyan()
+            } else {
+                "No".yellow()
+            }
+        );
+
+        println!();
+    }
 
     // Expand globs and get source paths
     let expanded_targets = expand_glob_patterns(targets)?;
@@ -896,10 +2012,22 @@ fn run_remove_command(args: &Args, targets: &[String]) -> Result<(), Box<dyn Err
     if expanded_targets.is_empty() {
         return Err("No files match the specified targets".into());
     }
+    check_dir_rules(args, &expanded_targets, None)?;
 
     // Perform the remove operation
+    let trash = open_trash(args)?;
+    let config = FileOpConfig {
+        trash: trash.as_ref().map(|(_, trash)| Rc::clone(trash)),
+        ..config
+    };
     let stats = remove_files(&expanded_targets, &config)?;
+    record_trash(trash)?;
 
+    notify::set_summary(format!("{} removed, {} errors", stats.moved, stats.errors));
+    if output::json() {
+        output::stats(false, stats.totals());
+        return Ok(());
+    }
     println!("\n{}", "Results:".bold());
     println!("Files processed: {}", stats.processed);
     println!("Files removed: {}", stats.moved); // Using moved count for removed
@@ -909,29 +2037,59 @@ fn run_remove_command(args: &Args, targets: &[String]) -> Result<(), Box<dyn Err
     Ok(())
 }
 
+/// The history an `rm` is recorded in, with the trash shared through its [`FileOpConfig`]
+type RmTrash = (HistoryManager, Rc<RefCell<history::Trash>>);
+
+/// The history and trash `rm` moves removed items into, unless `--permanent` is given
+fn open_trash(args: &Args) -> Result<Option<RmTrash>, Box<dyn Error>> {
+    if args.permanent {
+        return Ok(None);
+    }
+    let backup_dir = paths::state_dir();
+    fs::create_dir_all(&backup_dir)?;
+    let history_manager = HistoryManager::open(args.max_history_size, &backup_dir)?;
+    let trash = Rc::new(RefCell::new(history_manager.trash()?));
+    Ok(Some((history_manager, trash)))
+}
+
+/// Record what an `rm` put into the trash, so `smv -u` restores it
+fn record_trash(trash: Option<RmTrash>) -> Result<(), Box<dyn Error>> {
+    let Some((mut history_manager, trash)) = trash else {
+        return Ok(());
+    };
+    let trash = trash.borrow();
+    history_manager.record_trash(&trash)?;
+    if !trash.is_empty() && !output::json() {
+        println!("Restore with {}", "smv -u".cyan());
+    }
+    Ok(())
+}
+
 fn run_mkdir_command(args: &Args, directories: &[String]) -> Result<(), Box<dyn Error>> {
     let config = build_file_op_config(args);
 
-    println!("\n{}", "CNP Smart Move - Create Directories".bold());
-    println!("Directories: {}", directories.join(", ").cyan());
-    println!(
-        "Parents: {}",
-        if args.recursive {
-            "Yes (create parent directories)".green()
-        } else {
-            "No".yellow()
-        }
-    );
-    println!("Mode: {}", args.mode.as_deref().unwrap_or("default").cyan());
-    println!(
-        "Verbose: {}",
-        if config.verbose {
-            "Yes".green()
-        } else {
-            "No".yellow()
-        }
-    );
-    println!();
+    if !output::json() {
+        println!("\n{}", "CNP Smart Move - Create Directories".bold());
+        println!("Directories: {}", directories.join(", ").cyan());
+        println!(
+            "Parents: {}",
+            if args.recursive {
+                "Yes (create parent directories)".green()
+            } else {
+                "No".yellow()
+            }
+        );
+        println!("Mode: {}", args.mode.as_deref().unwrap_or("default").cyan());
+        println!(
+            "Verbose: {}",
+            if config.verbose {
+                "Yes".green()
+            } else {
+                "No".yellow()
+            }
+        );
+        println!();
+    }
 
     // Parse mode if provided
     let mode = if let Some(mode_str) = &args.mode {
@@ -943,6 +2101,10 @@ fn run_mkdir_command(args: &Args, directories: &[String]) -> Result<(), Box<dyn
     // Perform the mkdir operation
     let stats = file_ops::create_directories(directories, args.recursive, mode, config.verbose)?;
 
+    if output::json() {
+        output::stats(false, stats.totals());
+        return Ok(());
+    }
     println!("\n{}", "Results:".bold());
     println!("Directories processed: {}", stats.processed);
     println!("Directories created: {}", stats.moved); // Using moved count for created
@@ -955,29 +2117,27 @@ fn run_mkdir_command(args: &Args, directories: &[String]) -> Result<(), Box<dyn
 fn run_touch_command(args: &Args, files: &[String]) -> Result<(), Box<dyn Error>> {
     let config = build_file_op_config(args);
 
-    println!("\n{}", "CNP Smart Move - Create/Touch Files".bold());
-    println!("Files: {}", files.join(", ").cyan());
-    println!(
-        "Verbose: {}",
-        if config.verbose {
-            "Yes".green()
-        } else {
-            "No".yellow()
-        }
-    );
-    println!();
+    if !output::json() {
+        println!("\n{}", "CNP Smart Move - Create/Touch Files".bold());
+        println!("Files: {}", files.join(", ").cyan());
+        println!(
+            "Verbose: {}",
+            if config.verbose {
+                "Yes".green()
+            } else {
+                "No".yellow()
+            }
+        );
+        println!();
+    }
 
     // Perform the touch operation first
     let stats = file_ops::create_files(files, config.verbose, None, None)?;
 
     // Initialize history manager for undo support
-    let backup_dir = home_dir()
-        .unwrap_or_else(|| PathBuf::from("/tmp"))
-        .join(".config")
-        .join("smv")
-        .join("backups");
+    let backup_dir = paths::state_dir();
     fs::create_dir_all(&backup_dir)?;
-    let mut history_manager = HistoryManager::new(args.max_history_size, &backup_dir);
+    let mut history_manager = HistoryManager::open(args.max_history_size, &backup_dir)?;
 
     // Record only successfully created files for undo support
     for file_path in files {
@@ -989,6 +2149,10 @@ fn run_touch_command(args: &Args, files: &[String]) -> Result<(), Box<dyn Error>
         }
     }
 
+    if output::json() {
+        output::stats(false, stats.totals());
+        return Ok(());
+    }
     println!("\n{}", "Results:".bold());
     println!("Files processed: {}", stats.processed);
     println!("Files created/touched: {}", stats.moved); // Using moved count for created/touched
@@ -1005,59 +2169,122 @@ fn run_copy_command(
 ) -> Result<(), Box<dyn Error>> {
     let config = build_file_op_config(args);
 
-    println!("\n{}", "CNP Smart Move - Copy Operation".bold());
-    println!("Sources: {}", sources.join(", ").cyan());
-    println!("Destination: {}", destination.cyan());
-    println!(
-        "Recursive: {}",
-        if config.recursive {
-            "Yes".green()
-        } else {
-            "No".yellow()
-        }
-    );
-    println!(
-        "Force: {}",
-        if config.force {
-            "Yes".red()
-        } else {
-            "No".green()
-        }
-    );
-    println!(
-        "No-clobber: {}",
-        if config.no_clobber {
-            "Yes".green()
-        } else {
-            "No".yellow()
-        }
-    );
-    println!(
-        "Interactive: {}",
-        if config.interactive {
-            "Yes".cyan()
-        } else {
-            "No".yellow()
-        }
-    );
-    println!(
-        "Preserve metadata: {}",
-        if config.preserve_metadata {
-            "Yes".green()
-        } else {
-            "No".yellow()
-        }
-    );
-    println!();
+    if !output::json() {
+        println!("\n{}", "CNP Smart Move - Copy Operation".bold());
+        println!("Sources: {}", sources.join(", ").cyan());
+        println!("Destination: {}", destination.cyan());
+        println!(
+            "Recursive: {}",
+            if config.recursive {
+                "Yes".green()
+            } else {
+                "No".yellow()
+            }
+        );
+        println!(
+            "Force: {}",
+            if config.force {
+                "Yes".red()
+            } else {
+                "No".green()
+            }
+        );
+        println!(
+            "No-clobber: {}",
+            if config.no_clobber {
+                "Yes".green()
+            } else {
+                "No".yellow()
+            }
+        );
+        println!(
+            "Interactive: {}",
+            if config.interactive {
+                "Yes".cyan()
+            } else {
+                "No".yellow()
+            }
+        );
+        println!(
+            "Preserve metadata: {}",
+            if config.preserve_metadata {
+                "Yes".green()
+            } else {
+                "No".yellow()
+            }
+        );
+        println!();
+    }
 
     // Expand glob patterns
     let expanded_sources = expand_glob_patterns(sources)?;
     let dest_path = Path::new(destination);
+    check_dir_rules(args, &expanded_sources, Some(dest_path))?;
+    let created_dirs = ensure_destination_dir(args, destination, expanded_sources.len())?;
+    if args.preview {
+        let dest_is_dir =
+            dest_path.is_dir() || destination_names_dir(destination, expanded_sources.len());
+        for source in &expanded_sources {
+            let target = file_ops::destination_for(source, dest_path, dest_is_dir, config.layout);
+            if output::json() {
+                output::planned(Op::Copy, Some(source), Some(&target));
+                continue;
+            }
+            println!(
+                "{} Copy: {:?} → {:?}",
+                "[PREVIEW]".blue().bold(),
+                source,
+                target
+            );
+        }
+        if output::json() {
+            let count = expanded_sources.len() as u32;
+            output::stats(
+                true,
+                output::Totals {
+                    processed: count,
+                    done: count,
+                    ..Default::default()
+                },
+            );
+            return Ok(());
+        }
+        println!(
+            "\n{}",
+            "This was a preview only. No files were actually copied."
+                .bold()
+                .blue()
+        );
+        return Ok(());
+    }
 
     // Execute copy operation
+    let config = FileOpConfig {
+        progress: start_progress(args, &expanded_sources)?,
+        ..config
+    };
     let stats = copy_files(&expanded_sources, dest_path, &config)?;
+    if let Some(progress) = &config.progress {
+        progress.finish();
+    }
+
+    // Copies are not undoable, but the directories made for them are once emptied
+    if !created_dirs.is_empty() {
+        let cwd = std::env::current_dir()?;
+        let backup_dir = paths::state_dir();
+        fs::create_dir_all(&backup_dir)?;
+        let mut history_manager = HistoryManager::open(args.max_history_size, &backup_dir)?;
+        for dir in &created_dirs {
+            history_manager.record(PathBuf::new(), cwd.join(dir))?;
+        }
+    }
 
     // Print results
+    notify::set_summary(format!("{} copied, {} errors", stats.copied, stats.errors));
+    if output::json() {
+        output::stats(false, stats.totals());
+        return Ok(());
+    }
     println!("\n{}:", "Results".bold());
     println!("Files processed: {}", stats.processed.to_string().cyan());
     println!("Files copied: {}", stats.copied.to_string().green());
@@ -1067,6 +2294,72 @@ fn run_copy_command(
     Ok(())
 }
 
+/// Whether a mv/cp destination names a directory rather than a new file name
+fn destination_names_dir(destination: &str, source_count: usize) -> bool {
+    destination.ends_with('/')
+        || destination.ends_with(std::path::MAIN_SEPARATOR)
+        || source_count > 1
+}
+
+/// Create a missing mv/cp destination directory
+///
+/// Creates it with `--mkpath` or after asking on a terminal, and fails otherwise.
+/// `--parents` and `--strip-components` build paths below the destination anyway,
+/// so they create it too. Returns the directories created, outermost first, for
+/// the undo history.
+fn ensure_destination_dir(
+    args: &Args,
+    destination: &str,
+    source_count: usize,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let dir = Path::new(destination);
+    if !destination_names_dir(destination, source_count) || dir.symlink_metadata().is_ok() {
+        return Ok(Vec::new());
+    }
+
+    let mkpath = args.mkpath || args.parents || args.strip_components.is_some();
+    let missing = file_ops::missing_directories(dir);
+    if args.preview {
+        for created in &missing {
+            println!(
+                "{} Create directory: {:?}{}",
+                "[PREVIEW]".blue().bold(),
+                created,
+                if mkpath { "" } else { " (needs --mkpath)" }
+            );
+        }
+        return Ok(Vec::new());
+    }
+
+    if !mkpath && !confirm_create_directory(dir)? {
+        return Err(format!(
+            "Destination directory '{}' does not exist (use --mkpath to create it)",
+            dir.display()
+        )
+        .into());
+    }
+    attr::create_dir_all(dir)?;
+    for created in &missing {
+        println!("{} {}", "Created directory:".green(), created.display());
+    }
+    Ok(missing)
+}
+
+/// Ask whether to create a missing directory; never asks when stdin is not a terminal
+fn confirm_create_directory(dir: &Path) -> Result<bool, Box<dyn Error>> {
+    use std::io::{self, IsTerminal, Write};
+
+    if !io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    print!("Create missing directory '{}'? [y/N] ", dir.display());
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(matches!(input.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
 fn build_file_op_config(args: &Args) -> FileOpConfig {
     FileOpConfig {
         recursive: args.recursive,
@@ -1077,66 +2370,518 @@ fn build_file_op_config(args: &Args) -> FileOpConfig {
         dereference_symlinks: args.dereference,
         follow_symlinks: !args.no_follow,
         verbose: args.verbose,
+        layout: if let Some(strip) = args.strip_components {
+            DestinationLayout::Parents { strip }
+        } else if args.parents || args.preserve_structure {
+            DestinationLayout::Parents { strip: 0 }
+        } else {
+            DestinationLayout::Flat
+        },
+        progress: None,
+        trash: None,
+        one_file_system: args.one_file_system,
+        on_error: args.on_error.unwrap_or_default(),
     }
 }
 
-/// Runs the Text-based User Interface (TUI) mode of the application.
-fn run_tui_mode() -> Result<(), Box<dyn Error>> {
-    // Setup backup directory
-    let backup_dir = home_dir()
-        .unwrap_or_else(|| PathBuf::from("/tmp"))
-        .join(".config")
-        .join("smv")
-        .join("backups");
-
-    // Ensure backup directory exists
-    fs::create_dir_all(&backup_dir)?;
+/// Report the directories `ops` cannot change, and stop before the first op
+/// unless this is only a preview
+fn check_writable(ops: &[plan::PlannedOp], preview: bool) -> Result<(), Box<dyn Error>> {
+    let refusals = plan::preflight(ops);
+    if refusals.is_empty() {
+        return Ok(());
+    }
+    eprintln!(
+        "{}",
+        format!("{} directories cannot be changed:", refusals.len())
+            .yellow()
+            .bold()
+    );
+    for refusal in &refusals {
+        eprintln!(
+            "  {}  {} ({} {})",
+            refusal.dir.display(),
+            refusal.reason.red(),
+            refusal.ops,
+            if refusal.ops == 1 { "op" } else { "ops" }
+        );
+    }
+    if preview {
+        return Ok(());
+    }
+    Err(format!(
+        "Nothing was changed: {} directories refuse the changes (see above)",
+        refusals.len()
+    )
+    .into())
+}
 
-    // Create and run TUI application
-    let mut app = ui::terminal::App::new()?;
-    app.run()?;
+/// Refuse to touch file types a configured directory does not allow, unless `--any-ext`
+///
+/// With a `destination`, the places `sources` would land are checked too.
+fn check_dir_rules(
+    args: &Args,
+    sources: &[PathBuf],
+    destination: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    if args.any_ext {
+        return Ok(());
+    }
+    let config = config::Config::load()?;
+    match destination {
+        Some(destination) => config.check_transfer(sources, destination),
+        None => config.check(sources),
+    }
+}
 
-    Ok(())
+/// Progress reporting for a mv/cp of `sources`, when `--heartbeat` or `--output json` asks for it
+fn start_progress(
+    args: &Args,
+    sources: &[PathBuf],
+) -> Result<Option<Rc<progress::Progress>>, Box<dyn Error>> {
+    let format = progress::OutputFormat::parse(&args.output)?;
+    let interval = match (args.heartbeat, format) {
+        (Some(secs), _) => secs,
+        (None, progress::OutputFormat::Json) => progress::DEFAULT_JSON_INTERVAL,
+        (None, progress::OutputFormat::Text) => return Ok(None),
+    };
+    let total = progress::count_files(sources, args.recursive, args.one_file_system);
+    Ok(Some(Rc::new(progress::Progress::start(
+        format, interval, total,
+    ))))
 }
 
-/// Launch the interactive REPL session
-fn run_interactive_mode(max_history_size: usize) -> Result<(), Box<dyn Error>> {
-    // Setup backup directory
-    let backup_dir = home_dir()
-        .unwrap_or_else(|| PathBuf::from("/tmp"))
-        .join(".config")
-        .join("smv")
-        .join("backups");
+/// Execute a plan piped in by another tool (`smv --stdin-ops`)
+///
+/// Applies the same rails as other commands: -p previews, conflicts are skipped
+/// unless -f is given, and the whole batch is undoable with `smv -u`.
+fn run_stdin_ops(args: &Args) -> Result<(), Box<dyn Error>> {
+    use std::io::Read;
 
-    // Ensure backup directory exists
-    fs::create_dir_all(&backup_dir)?;
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input)?;
+    run_plan(args, &input, "stdin")
+}
 
-    // Create and run interactive session
+/// Check a plan saved with `--plan` against the files as they are now, then run it
+fn run_apply_plan(args: &Args, file: &str) -> Result<(), Box<dyn Error>> {
+    let input = fs::read_to_string(file).map_err(|e| format!("Cannot read plan {file}: {e}"))?;
+    let ops = plan::parse_plan(&input)?;
+    // Files change between review and apply; -F runs what still can anyway
+    if !args.preview
+        && !args.force
+        && let Err(e) = validate_plan(&ops, file, false)
+    {
+        return Err(format!("{e}. {}", forced_outcome(&ops)).into());
+    }
+    run_ops(args, &ops, file)
+}
+
+/// Which ops of a plan that failed validation `-F` would skip, and which would
+/// overwrite their destination
+fn forced_outcome(ops: &[plan::PlannedOp]) -> String {
+    let skipped: Vec<usize> = plan::validate(ops, true)
+        .into_iter()
+        .map(|(index, _)| index)
+        .collect();
+    let overwriting: Vec<usize> = plan::validate(ops, false)
+        .into_iter()
+        .map(|(index, _)| index)
+        .filter(|index| !skipped.contains(index))
+        .collect();
+    let numbered = |indexes: &[usize]| {
+        let numbers: Vec<String> = indexes.iter().map(|i| (i + 1).to_string()).collect();
+        match numbers.len() {
+            1 => format!("op {}", numbers[0]),
+            _ => format!("ops {}", numbers.join(", ")),
+        }
+    };
+    let mut outcome = Vec::new();
+    if !overwriting.is_empty() {
+        outcome.push(format!(
+            "{} would overwrite the existing destination (backed up for smv -u)",
+            numbered(&overwriting)
+        ));
+    }
+    if !skipped.is_empty() {
+        outcome.push(format!("{} would be skipped", numbered(&skipped)));
+    }
+    format!(
+        "With -F, {}; every other op runs as planned",
+        outcome.join(" and ")
+    )
+}
+
+/// Run a plan handed over by another system (`smv batch FILE`, `-` for stdin)
+///
+/// As with `apply-plan`, nothing runs unless every op can: the whole plan is
+/// checked first, and each problem is listed. Here `-F` only lets ops
+/// overwrite. `-p` previews, and `smv -u` reverts the moves and copies together.
+fn run_batch(args: &Args, file: &str) -> Result<(), Box<dyn Error>> {
+    let input = if file == "-" {
+        use std::io::Read;
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input)?;
+        input
+    } else {
+        fs::read_to_string(file).map_err(|e| format!("Cannot read operations {file}: {e}"))?
+    };
+    let origin = if file == "-" { "stdin" } else { file };
+    let ops = plan::parse_plan(&input)?;
+
+    // A preview reports each problem with its op
+    if !args.preview {
+        validate_plan(&ops, origin, args.force)?;
+    }
+    run_ops(args, &ops, origin)
+}
+
+/// List every op of a plan that cannot run, failing if there is one
+fn validate_plan(ops: &[plan::PlannedOp], origin: &str, overwrite: bool) -> Result<(), String> {
+    let problems = plan::validate(ops, overwrite);
+    if problems.is_empty() {
+        return Ok(());
+    }
+    for (index, conflict) in &problems {
+        let op = &ops[*index];
+        eprintln!(
+            "{} op {}: {} {:?} → {:?}: {}",
+            "Error".red(),
+            index + 1,
+            op.op.as_str(),
+            op.from,
+            op.to,
+            conflict
+        );
+    }
+    Err(format!(
+        "{} of {} operations in {origin} cannot run; nothing was changed",
+        problems.len(),
+        ops.len()
+    ))
+}
+
+/// Run the ops of a plan read from `origin`
+fn run_plan(args: &Args, input: &str, origin: &str) -> Result<(), Box<dyn Error>> {
+    let ops = plan::parse_plan(input)?;
+    run_ops(args, &ops, origin)
+}
+
+fn run_ops(args: &Args, ops: &[plan::PlannedOp], origin: &str) -> Result<(), Box<dyn Error>> {
+    let json = output::json();
+
+    if !json {
+        println!(
+            "\n{}",
+            format!(
+                "CNP Smart Move - {} Plan from {}",
+                if args.preview { "Preview" } else { "Execute" },
+                origin
+            )
+            .bold()
+        );
+        println!("Operations: {}", ops.len().to_string().cyan());
+        println!(
+            "Conflicts: {}",
+            if args.force {
+                "overwrite (-F)".red()
+            } else {
+                "skip".green()
+            }
+        );
+        println!();
+    }
+
+    let config = FileOpConfig {
+        recursive: true,
+        force: args.force,
+        ..build_file_op_config(args)
+    };
+    let touched: Vec<PathBuf> = ops
+        .iter()
+        .flat_map(|op| {
+            // A directory's files are checked where they are; its new name has no type
+            let to = (!op.from.is_dir()).then(|| op.to.clone());
+            std::iter::once(op.from.clone()).chain(to)
+        })
+        .collect();
+    check_dir_rules(args, &touched, None)?;
+    check_writable(ops, args.preview)?;
+    let mut checker = plan::ConflictChecker::new(args.force);
+    let mut stats = Stats::default();
+    let mut moves = Vec::new();
+    let mut copies = Vec::new();
+    let mut history_manager = if args.preview {
+        None
+    } else {
+        Some(HistoryManager::open(
+            args.max_history_size,
+            &paths::state_dir(),
+        )?)
+    };
+    // Absolute paths keep the batch undoable from any directory
+    let cwd = std::env::current_dir()?;
+    let absolute = |path: &Path| cwd.join(path).components().collect::<PathBuf>();
+
+    for op in ops {
+        stats.processed += 1;
+
+        if json && args.preview {
+            let mut conflicts: Vec<_> = checker.check(op).into_iter().collect();
+            conflicts.extend(checker.warnings(op));
+            if conflicts.iter().any(plan::Conflict::is_blocking) {
+                stats.skip(SkipReason::Conflict);
+            } else {
+                stats.renamed += 1;
+            }
+            output::preview(op.op.into(), &op.from, &op.to, &conflicts);
+            continue;
+        }
+
+        if let Some(conflict) = checker.check(op) {
+            if json {
+                output::conflict(op.op.into(), &op.from, &op.to, conflict);
+            } else {
+                println!(
+                    "{} {:?} → {:?}: {}",
+                    "Skipping".yellow(),
+                    op.from,
+                    op.to,
+                    conflict
+                );
+            }
+            if conflict == plan::Conflict::MissingSource {
+                stats.errors += 1;
+            } else {
+                stats.skip(SkipReason::Conflict);
+            }
+            continue;
+        }
+
+        if args.preview {
+            println!(
+                "{} {}: {:?} → {:?}",
+                "[PREVIEW]".blue().bold(),
+                op.op.as_str(),
+                op.from,
+                op.to
+            );
+            stats.renamed += 1;
+            continue;
+        }
+
+        // -F replaces files; undo brings them back from a backup
+        if op.to.is_file()
+            && !plan::same_file(&op.from, &op.to)
+            && let Some(ref mut history_manager) = history_manager
+            && let Err(e) = history_manager.record_overwrite(&absolute(&op.to))
+        {
+            let error = format!("not overwritten, it could not be backed up: {e}");
+            if json {
+                output::failed(op.op.into(), Some(&op.from), Some(&op.to), &error);
+            } else {
+                eprintln!("{}: {:?}: {}", "Error".red(), op.to, error);
+            }
+            stats.errors += 1;
+            continue;
+        }
+
+        // What a copy creates is told apart from what was there before it
+        let existing: HashSet<PathBuf> = if op.op == plan::OpKind::Copy {
+            paths_below(&op.to).into_iter().collect()
+        } else {
+            HashSet::new()
+        };
+        let result: Result<(), Box<dyn Error>> = match op.op {
+            plan::OpKind::Move if op.from.is_dir() => move_directory(&op.from, &op.to),
+            plan::OpKind::Move => file_ops::move_file_verified(&op.from, &op.to).map(|_| ()),
+            plan::OpKind::Copy => copy_files(std::slice::from_ref(&op.from), &op.to, &config)
+                .and_then(|s| {
+                    if s.errors > 0 {
+                        Err("copy failed".into())
+                    } else {
+                        Ok(())
+                    }
+                }),
+        };
+
+        // Copies report their files themselves
+        let copy = op.op == plan::OpKind::Copy;
+        match result {
+            Ok(()) => {
+                if !json {
+                    println!("{}: {:?} → {:?}", op.op.as_str(), op.from, op.to);
+                } else if !copy {
+                    output::done(Op::Move, Some(&op.from), Some(&op.to));
+                }
+                stats.renamed += 1;
+                match op.op {
+                    plan::OpKind::Move => moves.push((absolute(&op.from), absolute(&op.to))),
+                    plan::OpKind::Copy => copies.extend(
+                        paths_below(&op.to)
+                            .into_iter()
+                            .filter(|path| !existing.contains(path))
+                            .map(|path| absolute(&path)),
+                    ),
+                }
+            }
+            Err(e) => {
+                if !json {
+                    eprintln!(
+                        "{}: {} {:?} → {:?}: {}",
+                        "Error".red(),
+                        op.op.as_str(),
+                        op.from,
+                        op.to,
+                        e
+                    );
+                } else if !copy {
+                    output::failed(Op::Move, Some(&op.from), Some(&op.to), &e.to_string());
+                }
+                stats.errors += 1;
+            }
+        }
+    }
+
+    if let Some(mut history_manager) = history_manager
+        && (!moves.is_empty() || !copies.is_empty())
+    {
+        // Copies undo by deleting what was created, files before their directories
+        for copy in copies {
+            history_manager.record(PathBuf::new(), copy)?;
+        }
+        if !moves.is_empty() {
+            history_manager.record_mapping(cwd.clone(), &moves)?;
+        }
+    }
+    if json {
+        output::stats_with(args.preview, stats.totals(), stats.details());
+    } else {
+        print_plan_results(args, &stats);
+    }
+
+    if stats.errors > 0 {
+        return Err(format!("{} planned operations failed", stats.errors).into());
+    }
+
+    Ok(())
+}
+
+/// `root` and everything below it, parents first; nothing if it does not exist
+fn paths_below(root: &Path) -> Vec<PathBuf> {
+    walkdir::WalkDir::new(root)
+        .into_iter()
+        .filter_map(Result::ok)
+        .map(walkdir::DirEntry::into_path)
+        .collect()
+}
+
+fn print_plan_results(args: &Args, stats: &Stats) {
+    println!("\n{}", "Results:".bold());
+    println!("Operations processed: {}", stats.processed);
+    println!(
+        "Operations {}: {}",
+        if args.preview { "to apply" } else { "applied" },
+        stats.renamed.to_string().green()
+    );
+    println!("Skipped: {}", stats.skipped.to_string().yellow());
+    println!("Errors: {}", stats.errors.to_string().red());
+    if args.verbose {
+        print_skip_reasons(stats);
+    }
+
+    if args.preview {
+        println!(
+            "\n{}",
+            "This was a preview only. No files were actually moved."
+                .bold()
+                .blue()
+        );
+    }
+}
+
+/// Rename a directory, creating the destination's parents
+fn move_directory(from: &Path, to: &Path) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = to.parent() {
+        attr::create_dir_all(parent)?;
+    }
+    fs::rename(from, to)?;
+    Ok(())
+}
+
+/// Runs the Text-based User Interface (TUI) mode of the application.
+///
+/// `force` runs the queue on `x` without reviewing it first.
+fn run_tui_mode(max_history_size: usize, force: bool) -> Result<(), Box<dyn Error>> {
+    // Setup backup directory
+    let backup_dir = paths::state_dir();
+
+    // Ensure backup directory exists
+    fs::create_dir_all(&backup_dir)?;
+
+    // Create and run TUI application
+    let history_manager = HistoryManager::open(max_history_size, &backup_dir)?;
+    let mut app = ui::terminal::App::new(history_manager, force)?;
+    app.run()?;
+
+    Ok(())
+}
+
+/// Launch the interactive REPL session, or run a script of its commands
+fn run_interactive_mode(
+    max_history_size: usize,
+    script: Option<&Path>,
+    resume: bool,
+    force: bool,
+) -> Result<(), Box<dyn Error>> {
+    // Setup backup directory
+    let backup_dir = paths::state_dir();
+
+    // Ensure backup directory exists
+    fs::create_dir_all(&backup_dir)?;
+
+    // Create and run interactive session
     let mut session = InteractiveSession::new(max_history_size, &backup_dir)?;
-    session.run()?;
+    session.set_assume_yes(force);
+    if resume {
+        session.resume()?;
+    }
+    match script {
+        Some(script) => session.run_script(script)?,
+        None => session.run()?,
+    }
 
     Ok(())
 }
 
-/// Undo the last operation
-fn run_undo_mode(max_history_size: usize) -> Result<(), Box<dyn Error>> {
+/// Undo the last command (its whole batch), or with --select only some files of the last batch
+fn run_undo_mode(args: &Args, force: bool) -> Result<(), Box<dyn Error>> {
     // Setup backup directory
-    let backup_dir = home_dir()
-        .unwrap_or_else(|| PathBuf::from("/tmp"))
-        .join(".config")
-        .join("smv")
-        .join("backups");
+    let backup_dir = paths::state_dir();
 
     // Ensure backup directory exists
     fs::create_dir_all(&backup_dir)?;
 
     // Create history manager
-    let mut history_manager = HistoryManager::new(max_history_size, &backup_dir);
+    let mut history_manager = HistoryManager::open(args.max_history_size, &backup_dir)?;
+
+    if let Some(ref selection) = args.select {
+        history_manager.set_reporter(Box::new(report::Console));
+        return run_selective_undo(&mut history_manager, selection, force, args.preview);
+    }
+
+    // With --output json every line, the final one included, is an event
+    let format = progress::OutputFormat::parse(&args.output)?;
+    // The last command is undone whole, the same as `smv history undo 1`
+    if args.preview {
+        let steps = history_manager.plan_undo_batch(1, force)?;
+        return print_undo_preview(&steps, format);
+    }
+    history_manager.set_reporter(report::for_format(format));
 
-    // Attempt to undo the last operation
-    match history_manager.undo() {
+    match history_manager.undo_batch(1, force) {
         Ok(_) => {
-            println!("Operation undone successfully.");
+            report::for_format(format).info("Operation undone successfully.");
             Ok(())
         }
         Err(e) => {
@@ -1146,13 +2891,474 @@ fn run_undo_mode(max_history_size: usize) -> Result<(), Box<dyn Error>> {
     }
 }
 
+/// Summarize the activity recorded in the history (`smv stats --since 30d`)
+fn parse_shell_init_command(args: &Args) -> Result<XfdCommand, Box<dyn Error>> {
+    match args.arg1.as_deref() {
+        Some("name") => {
+            let (Some(style), Some(word)) = (args.into_keyword.as_deref(), args.arg2.as_deref())
+            else {
+                return Err("Usage: smv shell-init name STYLE WORD".into());
+            };
+            let transform_type =
+                TransformType::from_str(style).ok_or_else(|| format!("Unknown style: {style}"))?;
+            Ok(XfdCommand::ShellName {
+                transform_type,
+                word: word.to_string(),
+            })
+        }
+        Some(shell) => Ok(XfdCommand::ShellInit {
+            shell: shell_init::Shell::parse(shell)?,
+        }),
+        None => Err("Missing shell (expected: smv shell-init zsh or bash)".into()),
+    }
+}
+
+fn run_stats(args: &Args) -> Result<(), Box<dyn Error>> {
+    let since = args
+        .since
+        .as_deref()
+        .map(parse_date_string)
+        .transpose()?
+        .map(chrono::DateTime::<chrono::Local>::from);
+    let history_manager = HistoryManager::open(usize::MAX, &paths::state_dir())?;
+    stats::Activity::collect(&history_manager, since)?.print();
+    Ok(())
+}
+
+/// Report the naming styles per directory and how to normalize the outliers
+fn run_detect(args: &Args) -> Result<(), Box<dyn Error>> {
+    let root = Path::new(args.arg1.as_deref().unwrap_or("."));
+    let directories = detect::detect(root, args.hidden)?;
+    if progress::OutputFormat::parse(&args.output)? == progress::OutputFormat::Json {
+        for directory in &directories {
+            println!("{}", serde_json::to_string(directory)?);
+        }
+    } else {
+        detect::print(root, &directories);
+    }
+    Ok(())
+}
+
+/// Follow every file named like `name` through the history to where it is now
+fn run_where_did_it_go(args: &Args, name: &str) -> Result<(), Box<dyn Error>> {
+    let history_manager = HistoryManager::open(usize::MAX, &paths::state_dir())?;
+    let trails = trace::trace(&history_manager, name)?;
+    if progress::OutputFormat::parse(&args.output)? == progress::OutputFormat::Json {
+        for trail in &trails {
+            println!("{}", serde_json::to_string(trail)?);
+        }
+    } else {
+        trace::print(name, &trails);
+    }
+    Ok(())
+}
+
+/// Copy a directory aside to reorganize it there (`smv stage photos`)
+fn run_stage_command(args: &Args, dir: &str) -> Result<(), Box<dyn Error>> {
+    let stage = stage::Stage::of(Path::new(dir))?;
+    if args.preview {
+        println!(
+            "Would copy {} to {}",
+            stage.dir.display(),
+            stage.staging.display().to_string().cyan()
+        );
+        return Ok(());
+    }
+    stage.create()?;
+    println!(
+        "Staged {} at {}",
+        stage.dir.display(),
+        stage.staging.display().to_string().green()
+    );
+    println!("Reorganize it there with any smv command, then run: smv commit-stage {dir}");
+    Ok(())
+}
+
+/// Build, refresh or drop the saved listing of a directory (`smv index build photos`)
+fn run_index_command(args: &Args, action: IndexAction, dir: &str) -> Result<(), Box<dyn Error>> {
+    let root = Path::new(dir);
+    if action == IndexAction::Clear {
+        if index::Index::clear(root)? {
+            println!("Removed the index of {dir}");
+        } else {
+            println!("{dir} has no index");
+        }
+        return Ok(());
+    }
+
+    let previous = index::Index::load(root);
+    if action == IndexAction::Update && previous.is_none() {
+        return Err(format!("{dir} has no index yet: run smv index build {dir}").into());
+    }
+    // An update keeps hashing if the index had hashes
+    let hashes = args.hash || previous.as_ref().is_some_and(|index| index.hashes);
+    let built = index::Index::build(root, hashes, previous.as_ref())?;
+    let file = built.save()?;
+    let entries = built.entries.len();
+    match (&previous, action) {
+        (Some(previous), IndexAction::Update) => {
+            let changes = previous.changes(&built);
+            println!(
+                "Updated the index of {}: {} added, {} removed, {} changed ({} entries)",
+                built.root.display().to_string().green(),
+                changes.added,
+                changes.removed,
+                changes.changed,
+                entries
+            );
+        }
+        _ => println!(
+            "Indexed {} entries below {}{}",
+            entries,
+            built.root.display().to_string().green(),
+            if hashes { ", with hashes" } else { "" }
+        ),
+    }
+    if args.verbose {
+        println!("Saved to {}", file.display());
+    }
+    Ok(())
+}
+
+/// Swap a staged reorganization in place of the original (`smv commit-stage photos`)
+fn run_commit_stage(args: &Args, dir: &str) -> Result<(), Box<dyn Error>> {
+    let stage = stage::Stage::of(Path::new(dir))?;
+    if let Some(problem) = stage.check() {
+        return Err(problem.into());
+    }
+    let swap = [
+        (stage.dir.clone(), stage.previous.clone()),
+        (stage.staging.clone(), stage.dir.clone()),
+    ];
+    let ops: Vec<plan::PlannedOp> = swap
+        .iter()
+        .map(|(from, to)| plan::PlannedOp {
+            op: plan::OpKind::Move,
+            from: from.clone(),
+            to: to.clone(),
+        })
+        .collect();
+    check_writable(&ops, args.preview)?;
+    if args.preview {
+        println!(
+            "Would swap {} in for {}, keeping the original as {}",
+            stage.staging.display(),
+            stage.dir.display(),
+            stage.previous.display()
+        );
+        return Ok(());
+    }
+
+    let atomic = stage.commit()?;
+    let backup_dir = paths::state_dir();
+    fs::create_dir_all(&backup_dir)?;
+    let mut history_manager = HistoryManager::open(args.max_history_size, &backup_dir)?;
+    let root = stage.dir.parent().unwrap_or(Path::new("/")).to_path_buf();
+    history_manager.record_mapping(root, &swap)?;
+
+    println!(
+        "Swapped the staged tree into {}{}",
+        stage.dir.display().to_string().green(),
+        if atomic {
+            ""
+        } else {
+            " (in two renames: no atomic exchange on this system)"
+        }
+    );
+    println!(
+        "The original is kept at {} (smv -u swaps it back)",
+        stage.previous.display()
+    );
+    Ok(())
+}
+
+/// Check the environment smv runs in and say what to fix
+///
+/// `--env` is the only kind of check so far, so `smv doctor` runs it too.
+fn run_doctor(args: &Args) -> Result<(), Box<dyn Error>> {
+    let target = Path::new(args.arg1.as_deref().unwrap_or("."));
+    if !target.is_dir() {
+        return Err(format!("Not a directory: {}", target.display()).into());
+    }
+    let findings = doctor::check_environment(target);
+    if progress::OutputFormat::parse(&args.output)? == progress::OutputFormat::Json {
+        for finding in &findings {
+            println!("{}", serde_json::to_string(finding)?);
+        }
+    } else {
+        doctor::print(&findings);
+    }
+    let failed = findings
+        .iter()
+        .filter(|finding| finding.status == doctor::Status::Fail)
+        .count();
+    if failed > 0 {
+        return Err(format!("{failed} environment problem(s) found").into());
+    }
+    Ok(())
+}
+
+/// Prune history batches and backups beyond the retention limits
+fn run_history_gc(args: &Args) -> Result<(), Box<dyn Error>> {
+    let backup_dir = paths::state_dir();
+
+    let retention = history::Retention {
+        max_entries: Some(args.max_history_size),
+        max_age: args
+            .max_history_age
+            .map(|days| chrono::Duration::days(i64::from(days))),
+        max_bytes: args
+            .max_history_bytes
+            .as_deref()
+            .map(parse_size_string)
+            .transpose()?,
+    };
+
+    // Load everything so the size limit can apply to what is on disk
+    let mut history_manager = HistoryManager::open(usize::MAX, &backup_dir)?;
+    let report = history_manager.gc(&retention, args.preview)?;
+
+    println!("{}", "CNP Smart Move - History GC".green().bold());
+    let verb = if args.preview {
+        "Would remove"
+    } else {
+        "Removed"
+    };
+    println!(
+        "{} {} batches ({} entries) and {} backup files",
+        verb, report.batches, report.entries, report.files
+    );
+    println!(
+        "{} {}",
+        if args.preview {
+            "Space to reclaim:"
+        } else {
+            "Space reclaimed:"
+        },
+        disk_usage::format_size(report.bytes)
+    );
+    if args.preview {
+        println!(
+            "\n{}",
+            "This was a preview only. No history was actually removed.".yellow()
+        );
+    }
+    Ok(())
+}
+
+/// List the recorded batches, newest first, numbered for `smv history undo N`
+fn run_history_list(args: &Args) -> Result<(), Box<dyn Error>> {
+    // Files shown per batch; --full shows them all
+    const SHOWN_FILES: usize = 3;
+
+    let history_manager = HistoryManager::open(usize::MAX, &paths::state_dir())?;
+    let batches = history_manager.batches();
+    if batches.is_empty() {
+        println!("No operations recorded");
+        return Ok(());
+    }
+    println!("{}", "CNP Smart Move - History".green().bold());
+    for (number, operations) in batches.iter().rev().enumerate() {
+        let first = &operations[0];
+        let files: Vec<history::MappedMove> = operations
+            .iter()
+            .filter(|operation| {
+                !operation.source.as_os_str().is_empty() && !operation.is_overwrite()
+            })
+            .flat_map(|operation| history_manager.files_of(operation).unwrap_or_default())
+            .collect();
+        let created = operations.len()
+            - operations
+                .iter()
+                .filter(|operation| !operation.source.as_os_str().is_empty())
+                .count();
+        let mut counts = Vec::new();
+        if !files.is_empty() {
+            let what = if first.trash.is_some() {
+                "removed"
+            } else if first.modes.is_some() {
+                "permissions changed"
+            } else {
+                "moved"
+            };
+            counts.push(format!("{} {what}", files.len()));
+        }
+        if created > 0 {
+            counts.push(format!("{created} created"));
+        }
+        let overwritten = operations.iter().filter(|op| op.is_overwrite()).count();
+        if overwritten > 0 {
+            counts.push(format!("{overwritten} overwritten, backed up"));
+        }
+        println!(
+            "\n{:>4}  {}  {}  ({})",
+            (number + 1).to_string().cyan(),
+            first.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            first.command.as_deref().map_or_else(
+                || "(command not recorded)".dimmed(),
+                |line| { format!("smv {line}").bold() }
+            ),
+            counts.join(", ")
+        );
+        let shown = if args.full { files.len() } else { SHOWN_FILES };
+        for file in files.iter().take(shown) {
+            if file.source == file.destination {
+                println!("        {}", file.destination.display());
+            } else {
+                println!(
+                    "        {} → {}",
+                    file.source.display(),
+                    file.destination.display()
+                );
+            }
+        }
+        if files.len() > shown {
+            println!(
+                "        ... and {} more (--full lists them)",
+                files.len() - shown
+            );
+        }
+    }
+    println!(
+        "\nRevert one batch with {}, the latest with {}",
+        "smv history undo N".cyan(),
+        "smv -u".cyan()
+    );
+    Ok(())
+}
+
+/// Revert batch `number` of `smv history`, leaving the later ones as they are
+fn run_history_undo(args: &Args, number: usize) -> Result<(), Box<dyn Error>> {
+    let backup_dir = paths::state_dir();
+    fs::create_dir_all(&backup_dir)?;
+    let mut history_manager = HistoryManager::open(args.max_history_size, &backup_dir)?;
+    history_manager.set_reporter(Box::new(report::Console));
+    match history_manager.undo_batch(number, args.force) {
+        Ok(count) => {
+            println!("Batch {number} undone ({count} operations).");
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("{}: {}", "Error".red(), e);
+            Err(e)
+        }
+    }
+}
+
+/// Show what an undo would move back and what it would have to leave alone
+fn print_undo_preview(
+    steps: &[history::UndoStep],
+    format: progress::OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    if format == progress::OutputFormat::Json {
+        for step in steps {
+            println!("{}", serde_json::to_string(step)?);
+        }
+        return Ok(());
+    }
+
+    for step in steps {
+        let path = step.path.display();
+        let verb = match step.action {
+            history::UndoAction::MoveBack => "Would move back",
+            history::UndoAction::Restore => "Would restore from the trash",
+            history::UndoAction::RestoreMode => "Would restore the previous permissions of",
+            history::UndoAction::Delete => "Would delete created file",
+            history::UndoAction::RemoveDir => "Would remove created directory",
+            history::UndoAction::FromBackup => "Would restore from backup",
+        };
+        let line = match &step.to {
+            Some(to) => format!("{}: \"{}\" → \"{}\"", verb, path, to.display()),
+            None => format!("{}: \"{}\"", verb, path),
+        };
+        match &step.problem {
+            None => println!("{} {}", "[PREVIEW]".blue().bold(), line),
+            Some(problem) => println!(
+                "{} {} {}",
+                "[PREVIEW]".blue().bold(),
+                line.dimmed(),
+                format!("- cannot: {problem}").yellow()
+            ),
+        }
+    }
+    let blocked = steps.iter().filter(|step| step.problem.is_some()).count();
+    println!(
+        "\n{} of {} files would be undone; {} would be left as they are",
+        (steps.len() - blocked).to_string().green(),
+        steps.len(),
+        blocked.to_string().yellow()
+    );
+    println!(
+        "\n{}",
+        "This was a preview only. Nothing was undone.".yellow()
+    );
+    Ok(())
+}
+
+/// List the files of the last batch and move back the selected ones
+fn run_selective_undo(
+    history_manager: &mut HistoryManager,
+    selection: &str,
+    force: bool,
+    preview: bool,
+) -> Result<(), Box<dyn Error>> {
+    let batch = history_manager.last_batch()?;
+    if batch.is_empty() {
+        return Err("The last operation has no moved files to select from".into());
+    }
+
+    println!("{}", "CNP Smart Move - Selective Undo".green().bold());
+    println!("Last batch ({} files):", batch.len());
+    for (index, entry) in batch.iter().enumerate() {
+        println!(
+            "  {:>3}. \"{}\" → \"{}\"",
+            index + 1,
+            entry.destination.display(),
+            entry.source.display()
+        );
+    }
+
+    let selection = if selection.trim().is_empty() {
+        use std::io::{self, Write};
+        print!("Revert which files? (e.g. 1,3-5 or all; empty to cancel): ");
+        io::stdout().flush()?;
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        input
+    } else {
+        selection.to_string()
+    };
+    if selection.trim().is_empty() {
+        println!("Operation cancelled.");
+        return Ok(());
+    }
+
+    let indexes = history::parse_selection(&selection, batch.len())?;
+    if preview {
+        println!();
+        let steps = history_manager.plan_undo_selected(&batch, &indexes, force);
+        return print_undo_preview(&steps, progress::OutputFormat::Text);
+    }
+    let reverted = history_manager.undo_selected(&batch, &indexes, force)?;
+    println!(
+        "\nReverted {} of {} selected files; undo with {} to move them forward again",
+        reverted,
+        indexes.len(),
+        "smv -u".cyan()
+    );
+    Ok(())
+}
+
 /// Run transform command using XFD syntax
 fn run_transform_command(args: &Args, transform_type: TransformType) -> Result<(), Box<dyn Error>> {
     // For transformation commands, check if arg1 contains a filename when target is not specified
     let target = if let Some(ref arg1) = args.arg1 {
         // Check if arg1 is a file (for natural syntax like "smv title file.txt")
         let arg1_path = Path::new(arg1);
-        if arg1_path.exists() && arg1_path.is_file() {
+        // Plain case commands also take a directory there ("smv snake notes/")
+        let is_case_command = args.into_keyword.as_deref() != Some("INTO")
+            && args.command.as_deref() != Some("split");
+        if arg1_path.is_file() || (is_case_command && arg1_path.is_dir()) {
             arg1.as_str()
         } else {
             args.target.as_deref().unwrap_or(".")
@@ -1204,6 +3410,8 @@ fn run_transform_command(args: &Args, transform_type: TransformType) -> Result<(
                     && !arg.starts_with("DEPTH")
                     && !arg.starts_with("MODIFIED")
                     && !arg.starts_with("ACCESSED")
+                    && !arg.starts_with("RES>")
+                    && !arg.starts_with("RES<")
             })
             .cloned()
             .collect();
@@ -1218,75 +3426,418 @@ fn run_transform_command(args: &Args, transform_type: TransformType) -> Result<(
     let exclude_patterns: Vec<regex::Regex> = process_exclude_patterns(args.exclude.as_deref())?;
 
     // Print operation mode
+    if !output::json() {
+        println!(
+            "\n{}",
+            format!(
+                "CNP Smart Move - {} Mode",
+                transform_mode(args.preview, args.simulate)
+            )
+            .bold()
+        );
+        println!("Transformation: {}", transform_type.as_str().green());
+
+        if let Some(ref pat) = pattern {
+            println!("Pattern: {}", pat.cyan());
+            println!("Base Directory: {}", directory.cyan());
+        } else {
+            println!("Directory: {}", directory.cyan());
+        }
+
+        println!(
+            "Extensions: {}",
+            match &extensions {
+                Some(exts) => exts.join(", ").cyan(),
+                None => "All files".yellow(),
+            }
+        );
+        println!(
+            "Recursive: {}",
+            if args.recursive {
+                "Yes".green()
+            } else {
+                "No".yellow()
+            }
+        );
+        println!();
+    }
+
+    // Build file list - use DSC for glob patterns, fallback to original for directories
+    let (files, filtered) = if let Some(pattern_str) = pattern {
+        if !output::json() {
+            println!("Using DSC for pattern matching...");
+        }
+        let files = build_file_list_with_dsc(
+            &pattern_str,
+            &extensions,
+            args.recursive,
+            &exclude_patterns,
+            args.hidden,
+            ApplyTo::of(args),
+        )?;
+        (files, Filtered::new())
+    } else {
+        build_file_list(
+            &directory,
+            &extensions,
+            args.recursive,
+            &exclude_patterns,
+            args.hidden,
+            ApplyTo::of(args),
+            args.one_file_system,
+        )?
+    };
+    let files = pick_files(args, files)?;
+
+    if files.is_empty() {
+        report_no_matches(
+            "No files or directories found matching criteria.",
+            args.preview,
+        );
+        return Ok(());
+    }
+
+    // Process files and directories for transformation
+    let mut fs = transform_fs(args, Path::new(&directory))?;
+    let preview = args.preview && !args.simulate;
+    let name_fallback = NameFallback::parse(&args.name_fallback)?;
+    let mut stats = Stats {
+        sample: preview::Sample::for_preview(files.len(), !preview || args.full),
+        ..Stats::default()
+    };
+    stats.add_filtered(filtered);
+    let renames = transform_items(
+        args,
+        fs.as_mut(),
+        files,
+        &transform_type,
+        name_fallback,
+        preview,
+        &mut stats,
+    )?;
+
+    record_new_names(args, &renames, args.preview);
+    let mut history = record_rename_batch(args, Path::new(&directory), &renames, args.preview)?;
+    // Copies leave every link target in place
+    if !args.copy && !args.simulate {
+        run_link_updates(
+            args,
+            Path::new(&directory),
+            &renames,
+            args.preview,
+            history.as_mut(),
+        )?;
+    }
+
+    // Print results
+    print_transformation_results(args, &stats, preview);
+    print_simulation_note(args.simulate);
+    save_plan(args, &renames, args.copy)?;
+
+    Ok(())
+}
+
+/// Rename notes in a vault to `YYYY-MM-DD-kebab-title.ext` and keep links pointing at them
+fn run_note_command(args: &Args) -> Result<(), Box<dyn Error>> {
+    use std::collections::{HashMap, HashSet};
+
+    let target = args
+        .arg1
+        .as_deref()
+        .or(args.target.as_deref())
+        .unwrap_or(".");
+    let target_path = Path::new(target);
+    if !target_path.exists() {
+        return Err(format!("Path does not exist: {target}").into());
+    }
+
+    // Only link-bearing notes are renamed; attachments keep their names
+    let extensions = links::parse_link_extensions(args.link_ext.as_deref());
+    let exclude_patterns = process_exclude_patterns(args.exclude.as_deref())?;
+
+    let (files, root, filtered) = if target_path.is_file() {
+        let parent = target_path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        (
+            vec![target_path.to_path_buf()],
+            parent.to_path_buf(),
+            Filtered::new(),
+        )
+    } else {
+        let (files, filtered) = build_file_list(
+            target,
+            &Some(extensions),
+            args.recursive,
+            &exclude_patterns,
+            args.hidden,
+            ApplyTo::Files,
+            args.one_file_system,
+        )?;
+        (files, target_path.to_path_buf(), filtered)
+    };
+
     println!(
         "\n{}",
         format!(
             "CNP Smart Move - {} Mode",
-            if args.preview { "Preview" } else { "Transform" }
+            if args.preview { "Preview" } else { "Note" }
         )
         .bold()
     );
-    println!("Transformation: {}", transform_type.as_str().green());
+    println!("Vault: {}", root.display().to_string().cyan());
+    println!();
 
-    if let Some(ref pat) = pattern {
-        println!("Pattern: {}", pat.cyan());
-        println!("Base Directory: {}", directory.cyan());
-    } else {
-        println!("Directory: {}", directory.cyan());
+    if files.is_empty() {
+        println!("No notes found matching criteria.");
+        return Ok(());
     }
 
-    println!(
-        "Extensions: {}",
-        match &extensions {
-            Some(exts) => exts.join(", ").cyan(),
-            None => "All files".yellow(),
+    // Names already present in each directory, updated as renames are planned
+    let mut taken: HashMap<PathBuf, HashSet<String>> = HashMap::new();
+    let mut disk = file_ops::DiskFs::new(build_file_op_config(args));
+    let mut stats = Stats::default();
+    stats.add_filtered(filtered);
+    let mut renames = Vec::new();
+
+    for file in files {
+        stats.processed += 1;
+        let parent = file.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let Some(file_name) = item_name(&file, Op::Rename, args.preview) else {
+            stats.skip(SkipReason::NotUtf8);
+            continue;
+        };
+
+        let names = taken.entry(parent.clone()).or_insert_with(|| {
+            fs::read_dir(&parent)
+                .map(|entries| {
+                    entries
+                        .filter_map(Result::ok)
+                        .map(|e| e.file_name().to_string_lossy().to_string())
+                        .collect()
+                })
+                .unwrap_or_default()
+        });
+
+        let modified = fs::metadata(&file)
+            .and_then(|m| m.modified())
+            .map(chrono::DateTime::<chrono::Local>::from)
+            .unwrap_or_else(|_| chrono::Local::now());
+        let candidate = notes::note_file_name(&file_name, modified.date_naive());
+        if file.file_name() == Some(OsStr::new(&candidate)) {
+            stats.skip(SkipReason::NoChange);
+            continue;
         }
-    );
-    println!(
-        "Recursive: {}",
-        if args.recursive {
-            "Yes".green()
-        } else {
-            "No".yellow()
+        if stats.protected(&file, args.preview) {
+            continue;
         }
-    );
-    println!();
 
-    // Build file list - use DSC for glob patterns, fallback to original for directories
-    let files = if let Some(pattern_str) = pattern {
-        println!("Using DSC for pattern matching...");
-        build_file_list_with_dsc(
-            &pattern_str,
-            &extensions,
-            args.recursive,
-            &exclude_patterns,
-            args.hidden,
-            !args.everything,
-        )?
+        names.remove(&file_name);
+        let new_name = notes::unique_name(&candidate, &file.to_string_lossy(), names);
+        names.insert(new_name.clone());
+
+        let new_path = parent.join(&new_name);
+        println!(
+            "{}Rename file: \"{}\" → \"{}\"",
+            if args.preview { "[PREVIEW] " } else { "" },
+            file_name,
+            new_name
+        );
+
+        if !args.preview
+            && let Err(e) = disk.rename(&file, &new_path)
+        {
+            eprintln!("{}: {}: {e}", "Error".red(), file.display());
+            stats.errors += 1;
+            names.remove(&new_name);
+            names.insert(file_name);
+            continue;
+        }
+
+        stats.rename(&file_name, &new_name);
+        renames.push((file, new_path));
+    }
+
+    // Vault renames always carry their links along
+    let mut link_args = args.clone();
+    link_args.update_links = true;
+    record_new_names(args, &renames, args.preview);
+    let mut history = record_rename_batch(args, &root, &renames, args.preview)?;
+    run_link_updates(&link_args, &root, &renames, args.preview, history.as_mut())?;
+
+    print_transformation_results(args, &stats, args.preview);
+    save_plan(args, &renames, false)?;
+
+    Ok(())
+}
+
+/// Rename files by rendering a template such as `"{fm:date}-{fm:title|kebab}.md"`
+fn run_template_command(args: &Args) -> Result<(), Box<dyn Error>> {
+    let source = args
+        .arg1
+        .as_deref()
+        .ok_or("Missing template. Usage: smv template \"PATTERN\" [target]")?;
+    let template = template::Template::parse(source)?;
+    let target = args.into_keyword.as_deref().unwrap_or(".");
+    let target_path = Path::new(target);
+    if !target_path.exists() {
+        return Err(format!("Path does not exist: {target}").into());
+    }
+
+    // `BY:key [ASC|DESC]` after the target sets the order {n} counts in
+    let trailing: Vec<String> = args
+        .arg2
+        .iter()
+        .chain(args.target.iter())
+        .chain(args.args.iter())
+        .cloned()
+        .collect();
+    let mut sort = None;
+    let mut i = 0;
+    while i < trailing.len() {
+        match cnp_grammar::SortOrder::parse(&trailing, &mut i)? {
+            Some(order) => sort = Some(order),
+            None => i += 1,
+        }
+    }
+
+    let exclude_patterns = process_exclude_patterns(args.exclude.as_deref())?;
+    let (files, root, filtered) = if target_path.is_file() {
+        let parent = target_path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        (
+            vec![target_path.to_path_buf()],
+            parent.to_path_buf(),
+            Filtered::new(),
+        )
     } else {
-        build_file_list(
-            &directory,
-            &extensions,
+        let (files, filtered) = build_file_list(
+            target,
+            &None,
             args.recursive,
             &exclude_patterns,
             args.hidden,
-            !args.everything,
-        )?
+            ApplyTo::of(args),
+            args.one_file_system,
+        )?;
+        (files, target_path.to_path_buf(), filtered)
     };
 
+    println!(
+        "\n{}",
+        format!(
+            "CNP Smart Move - {} Mode",
+            if args.preview { "Preview" } else { "Template" }
+        )
+        .bold()
+    );
+    println!("Template: {}", template.as_str().green());
+    println!("Directory: {}", root.display().to_string().cyan());
+    if let Some(order) = sort {
+        println!("Order: {}", order.describe().cyan());
+    }
+    println!();
+    let mut files = pick_files(args, files)?;
+
     if files.is_empty() {
         println!("No files or directories found matching criteria.");
         return Ok(());
     }
 
-    // Process files and directories for transformation
+    let providers = template::default_providers();
+    let indexes = match sort {
+        Some(order) => {
+            selection::sort_files(&mut files, order);
+            template::index_in_order(&files)
+        }
+        None => template::index_in_directories(&files),
+    };
+    let mut disk = file_ops::DiskFs::new(build_file_op_config(args));
     let mut stats = Stats::default();
+    stats.add_filtered(filtered);
+    let mut renames = Vec::new();
+
     for item_path in files {
-        process_item_transformation(&item_path, &transform_type, args.preview, &mut stats)?;
+        if !item_path.exists() {
+            continue;
+        }
+        stats.processed += 1;
+
+        let Some(item_name) = item_name(&item_path, Op::Rename, args.preview) else {
+            stats.skip(SkipReason::NotUtf8);
+            continue;
+        };
+
+        let mut context = template::TemplateContext::new(&item_path, &providers)
+            .with_index(indexes.get(&item_path).copied().unwrap_or(1));
+        let new_name = match template.render(&mut context) {
+            Ok(name) if !name.trim().is_empty() => name,
+            Ok(_) => {
+                println!(
+                    "{} \"{}\": template rendered an empty name",
+                    "Skip".yellow(),
+                    item_name
+                );
+                stats.skip(SkipReason::TemplateError);
+                continue;
+            }
+            Err(e) => {
+                println!("{} \"{}\": {}", "Skip".yellow(), item_name, e);
+                stats.skip(SkipReason::TemplateError);
+                continue;
+            }
+        };
+
+        let parent = item_path.parent().ok_or("Invalid parent directory")?;
+        let new_name = fit_name_to(parent, new_name, args.preview);
+        if item_path.file_name() == Some(OsStr::new(&new_name)) {
+            stats.skip(SkipReason::NoChange);
+            continue;
+        }
+
+        if stats.protected(&item_path, args.preview) {
+            continue;
+        }
+        let new_path = parent.join(&new_name);
+        if new_path.exists() || renames.iter().any(|(_, planned)| planned == &new_path) {
+            println!(
+                "{}Conflict: \"{}\" → \"{}\" (target exists)",
+                if args.preview { "[PREVIEW] " } else { "" },
+                item_name,
+                new_name
+            );
+            stats.errors += 1;
+            continue;
+        }
+
+        println!(
+            "{}Rename file: \"{}\" → \"{}\"",
+            if args.preview { "[PREVIEW] " } else { "" },
+            item_name,
+            new_name
+        );
+
+        if !args.preview
+            && let Err(e) = disk.rename(&item_path, &new_path)
+        {
+            eprintln!("{}: {}: {e}", "Error".red(), item_path.display());
+            stats.errors += 1;
+            continue;
+        }
+
+        stats.rename(&item_name, &new_name);
+        renames.push((item_path, new_path));
     }
 
-    // Print results
-    print_transformation_results(&stats, args.preview);
+    record_new_names(args, &renames, args.preview);
+    let mut history = record_rename_batch(args, &root, &renames, args.preview)?;
+    run_link_updates(args, &root, &renames, args.preview, history.as_mut())?;
+
+    print_transformation_results(args, &stats, args.preview);
+    save_plan(args, &renames, false)?;
 
     Ok(())
 }
@@ -1317,25 +3868,42 @@ fn is_path_or_parent_hidden(path: &std::path::Path, base_dir: &std::path::Path)
 }
 
 /// Build list of files and directories to process based on directory and extensions
+///
+/// Also returns how many entries were left out, and why.
 fn build_file_list(
     directory: &str,
     extensions: &Option<Vec<String>>,
     recursive: bool,
     exclude_patterns: &[regex::Regex],
     include_hidden: bool,
-    files_only: bool,
-) -> Result<Vec<std::path::PathBuf>, Box<dyn Error>> {
+    apply_to: ApplyTo,
+    one_file_system: bool,
+) -> Result<(Vec<std::path::PathBuf>, Filtered), Box<dyn Error>> {
     use walkdir::WalkDir;
 
     let mut items = Vec::new();
+    let mut filtered = Filtered::new();
+    let mut filter_out = |reason: SkipReason| *filtered.entry(reason).or_default() += 1;
     let base_dir = std::path::Path::new(directory);
     let walker = if recursive {
         WalkDir::new(directory)
     } else {
         WalkDir::new(directory).max_depth(1)
-    };
-
-    for entry in walker.into_iter().filter_map(Result::ok) {
+    }
+    .same_file_system(one_file_system);
+
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                if error.io_error().map(std::io::Error::kind)
+                    == Some(std::io::ErrorKind::PermissionDenied)
+                {
+                    filter_out(SkipReason::Permission);
+                }
+                continue;
+            }
+        };
         let path = entry.path();
 
         // Skip the root directory itself to avoid self-transformation
@@ -1345,6 +3913,7 @@ fn build_file_list(
 
         // Check for hidden files/directories recursively (skip if not including hidden)
         if !include_hidden && is_path_or_parent_hidden(path, base_dir) {
+            filter_out(SkipReason::Hidden);
             continue;
         }
 
@@ -1358,10 +3927,12 @@ fn build_file_list(
                         .iter()
                         .any(|ext| ext.to_lowercase() == file_ext_str)
                     {
+                        filter_out(SkipReason::ExtensionMismatch);
                         continue;
                     }
                 } else {
                     // File has no extension, skip if extensions were specified
+                    filter_out(SkipReason::ExtensionMismatch);
                     continue;
                 }
             }
@@ -1369,8 +3940,8 @@ fn build_file_list(
         // For directories, we always include them regardless of extension filters
         // since directories don't have extensions
 
-        // Apply files-only filter if enabled
-        if files_only && path.is_dir() {
+        // Leave out the kinds of entries --apply-to does not cover
+        if !apply_to.admits(path) {
             continue;
         }
 
@@ -1380,13 +3951,14 @@ fn build_file_list(
             .iter()
             .any(|pattern| pattern.is_match(&path_str))
         {
+            filter_out(SkipReason::Excluded);
             continue;
         }
 
         items.push(path.to_path_buf());
     }
 
-    Ok(items)
+    Ok((items, filtered))
 }
 
 /// Build list of files using DSC for pattern matching and discovery
@@ -1396,10 +3968,10 @@ fn build_file_list_with_dsc(
     recursive: bool,
     exclude_patterns: &[regex::Regex],
     include_hidden: bool,
-    files_only: bool,
+    apply_to: ApplyTo,
 ) -> Result<Vec<PathBuf>, Box<dyn Error>> {
     use std::io::{BufRead, BufReader};
-    use std::process::{Command, Stdio};
+    use std::process::Stdio;
 
     // Determine the base path and pattern
     let (base_path, file_pattern) = if pattern.contains('/') {
@@ -1419,7 +3991,7 @@ fn build_file_list_with_dsc(
     };
 
     // Build DSC command
-    let mut dsc_cmd = Command::new("dsc");
+    let mut dsc_cmd = config::Config::load()?.tools.command("dsc")?;
     dsc_cmd.arg(&base_path);
     dsc_cmd.arg("--paths"); // Get file paths only
     dsc_cmd.arg("--glob"); // Use glob pattern matching
@@ -1492,8 +4064,8 @@ fn build_file_list_with_dsc(
             }
         }
 
-        // Apply files-only filter if enabled
-        if files_only && path.is_dir() {
+        // Leave out the kinds of entries --apply-to does not cover
+        if !apply_to.admits(&path) {
             continue;
         }
 
@@ -1519,88 +4091,521 @@ fn build_file_list_with_dsc(
 }
 
 /// Process a single file or directory for transformation
+///
+/// Returns the new path when the item was (or, in preview, would be) renamed.
+/// Header label of a transform run
+fn transform_mode(preview: bool, simulate: bool) -> &'static str {
+    if simulate {
+        "Simulate"
+    } else if preview {
+        "Preview"
+    } else {
+        "Transform"
+    }
+}
+
+/// The filesystem a transform runs against: the disk, or a copy of `root` in memory
+fn transform_fs(args: &Args, root: &Path) -> Result<Box<dyn Vfs>, Box<dyn Error>> {
+    if args.simulate {
+        Ok(Box::new(vfs::MemoryFs::snapshot(root)?))
+    } else {
+        Ok(Box::new(file_ops::DiskFs::new(build_file_op_config(args))))
+    }
+}
+
+fn print_simulation_note(simulate: bool) {
+    if simulate && !output::json() {
+        println!(
+            "\n{}",
+            "Simulated on an in-memory copy of the target. Nothing on disk was changed."
+                .bold()
+                .blue()
+        );
+    }
+}
+
+/// Guard a transformed name before renaming to it in `dir`; `None` means the
+/// item keeps its name
+fn guard_name(
+    original: &str,
+    renamed: String,
+    fallback: NameFallback,
+    dir: &Path,
+    preview_only: bool,
+) -> Option<String> {
+    let Some(problem) = transformers::check_name(original, &renamed) else {
+        return Some(fit_name_to(dir, renamed, preview_only));
+    };
+    let replacement = fallback.apply(original, problem);
+    if !output::json() {
+        println!(
+            "{}{}: \"{}\" → \"{}\" ({}); {}",
+            if preview_only { "[PREVIEW] " } else { "" },
+            "Unusable name".yellow(),
+            original,
+            renamed,
+            problem,
+            match &replacement {
+                Some(name) => format!("using \"{name}\""),
+                None => "keeping the name".to_string(),
+            }
+        );
+    }
+    replacement.map(|name| fit_name_to(dir, name, preview_only))
+}
+
+/// Shorten `name` to the bytes the filesystem holding `dir` allows in a name
+fn fit_name_to(dir: &Path, name: String, preview_only: bool) -> String {
+    let limit = plan::name_max(dir);
+    if name.len() <= limit {
+        return name;
+    }
+    let fitted = transformers::fit_name(&name, limit);
+    if !output::json() {
+        println!(
+            "{}{}: \"{}\" is {} bytes, over the {} its filesystem allows; using \"{}\"",
+            if preview_only { "[PREVIEW] " } else { "" },
+            "Name too long".yellow(),
+            name,
+            name.len(),
+            limit,
+            fitted
+        );
+    }
+    fitted
+}
+
+/// The name of `item` as text to transform, reporting one that is not UTF-8
+fn item_name(item: &Path, op: Op, preview_only: bool) -> Option<String> {
+    let name = transformers::name_text(item.file_name()?);
+    if name.is_none() {
+        output::skipped(op, item, "name is not valid UTF-8");
+        if !output::json() {
+            println!(
+                "{}{}: {} (name is not valid UTF-8; --non-utf8 transliterate renames it)",
+                if preview_only { "[PREVIEW] " } else { "" },
+                "Skip".yellow(),
+                item.display()
+            );
+        }
+    }
+    name
+}
+
+/// Transform each of `files`, returning the renames made
+///
+/// Without `--on-error` the first failed rename ends the run with its error.
+/// With it, failures and conflicts are reported and the policy decides
+/// whether to go on, stop, or roll the renames back.
+fn transform_items(
+    args: &Args,
+    fs: &mut dyn Vfs,
+    files: Vec<PathBuf>,
+    transform_type: &TransformType,
+    name_fallback: NameFallback,
+    preview: bool,
+    stats: &mut Stats,
+) -> Result<Vec<(PathBuf, PathBuf)>, Box<dyn Error>> {
+    // Renaming a directory first would leave the paths of everything in it stale
+    let mut files = files;
+    files.sort_by(|a, b| children_first(a, b));
+    let touched: Vec<PathBuf> = files.iter().filter(|f| !fs.is_dir(f)).cloned().collect();
+    check_dir_rules(args, &touched, None)?;
+    if !args.simulate {
+        let ops: Vec<plan::PlannedOp> = files
+            .iter()
+            .filter_map(|file| {
+                let name = file.file_name()?;
+                let new_name = transform(&transformers::name_text(name)?, transform_type);
+                (OsStr::new(&new_name) != name).then(|| plan::PlannedOp {
+                    op: if args.copy {
+                        plan::OpKind::Copy
+                    } else {
+                        plan::OpKind::Move
+                    },
+                    from: file.clone(),
+                    to: file.with_file_name(new_name),
+                })
+            })
+            .collect();
+        check_writable(&ops, preview)?;
+    }
+    let mut batch = Batch::new(args.on_error.unwrap_or_default());
+    let mut renames = Vec::new();
+    for item_path in files {
+        let errors = stats.errors;
+        let result = process_item_transformation(
+            fs,
+            &item_path,
+            transform_type,
+            name_fallback,
+            preview,
+            args.copy,
+            stats,
+        );
+        match result {
+            Ok(Some(new_path)) => {
+                if !preview {
+                    if args.copy {
+                        batch.copied(&new_path, false);
+                    } else {
+                        batch.moved(&item_path, &new_path);
+                    }
+                }
+                renames.push((item_path, new_path));
+            }
+            Ok(None) => {}
+            Err(e) if args.on_error.is_some() => {
+                eprintln!(
+                    "{}: Failed to transform \"{}\": {}",
+                    "Error".red(),
+                    item_path.display(),
+                    e
+                );
+                let op = if args.copy { Op::Copy } else { Op::Rename };
+                output::failed(op, Some(&item_path), None, &e.to_string());
+                stats.errors += 1;
+            }
+            Err(e) => return Err(e),
+        }
+        if stats.errors > errors && !preview && !batch.keep_going(fs) {
+            break;
+        }
+    }
+    if batch.rolled_back() {
+        renames.clear();
+        stats.renamed = 0;
+        stats.changes.clear();
+    }
+    Ok(renames)
+}
+
+/// Order paths so everything in a directory comes right before it
+///
+/// Siblings keep their order by name; `a/b/c`, `a/b`, `a/d`, `a`.
+fn children_first(a: &Path, b: &Path) -> std::cmp::Ordering {
+    let (mut left, mut right) = (a.components(), b.components());
+    loop {
+        match (left.next(), right.next()) {
+            (Some(x), Some(y)) if x == y => {}
+            (Some(x), Some(y)) => return x.cmp(&y),
+            // The longer path is inside the shorter one
+            (Some(_), None) => return std::cmp::Ordering::Less,
+            (None, Some(_)) => return std::cmp::Ordering::Greater,
+            (None, None) => return std::cmp::Ordering::Equal,
+        }
+    }
+}
+
 fn process_item_transformation(
+    fs: &mut dyn Vfs,
     item_path: &std::path::Path,
     transform_type: &TransformType,
+    name_fallback: NameFallback,
     preview_only: bool,
+    copy: bool,
     stats: &mut Stats,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<Option<PathBuf>, Box<dyn Error>> {
     // Check if the item still exists (it might have been moved already)
-    if !item_path.exists() {
+    if !fs.exists(item_path) {
         // Skip silently - this item was likely moved as part of a parent directory rename
-        return Ok(());
+        return Ok(None);
     }
 
-    let item_name = item_path
-        .file_name()
-        .ok_or("Invalid item name")?
-        .to_string_lossy();
+    // Copies are made per file; their directories are walked, not duplicated
+    if copy && fs.is_dir(item_path) {
+        return Ok(None);
+    }
+
+    let file_name = item_path.file_name().ok_or("Invalid item name")?;
+    stats.processed += 1;
 
+    let op = if copy { Op::Copy } else { Op::Rename };
+    let Some(item_name) = item_name(item_path, op, preview_only) else {
+        stats.skip(SkipReason::NotUtf8);
+        return Ok(None);
+    };
     let new_name = transform(&item_name, transform_type);
 
-    stats.processed += 1;
+    let parent = item_path.parent().ok_or("Invalid parent directory")?;
+    let Some(new_name) = guard_name(&item_name, new_name, name_fallback, parent, preview_only)
+    else {
+        output::skipped(op, item_path, "unusable name");
+        stats.skip(SkipReason::UnusableName);
+        return Ok(None);
+    };
 
     // If name unchanged, nothing to do
-    if new_name == item_name {
-        return Ok(());
+    if OsStr::new(&new_name) == file_name {
+        stats.skip(SkipReason::NoChange);
+        return Ok(None);
+    }
+    // Copies leave the original as it is
+    if !copy && stats.protected(item_path, preview_only) {
+        return Ok(None);
     }
 
-    let new_path = item_path
-        .parent()
-        .ok_or("Invalid parent directory")?
-        .join(&new_name);
+    let new_path = parent.join(&new_name);
 
     // Check for conflicts
-    if new_path.exists() && item_path != new_path {
-        let item_type = if item_path.is_dir() {
+    if fs.exists(&new_path) && item_path != new_path {
+        let item_type = if fs.is_dir(item_path) {
             "directory"
         } else {
             "file"
         };
-        println!(
-            "{}Conflict: {} \"{}\" → \"{}\" (target exists)",
-            if preview_only { "[PREVIEW] " } else { "" },
-            item_type,
-            item_name,
-            new_name
-        );
+        output::conflict(op, item_path, &new_path, plan::Conflict::DestinationExists);
+        if !output::json() {
+            println!(
+                "{}Conflict: {} \"{}\" → \"{}\" (target exists)",
+                if preview_only { "[PREVIEW] " } else { "" },
+                item_type,
+                item_name,
+                new_name
+            );
+        }
         stats.errors += 1;
-        return Ok(());
+        return Ok(None);
     }
 
     // Log the operation
-    let item_type = if item_path.is_dir() {
+    let item_type = if fs.is_dir(item_path) {
         "directory"
     } else {
         "file"
     };
-    println!(
-        "{}Rename {}: \"{}\" → \"{}\"",
+    let head = format!(
+        "{}{} {}: ",
         if preview_only { "[PREVIEW] " } else { "" },
-        item_type,
-        item_name,
-        new_name
+        if copy { "Copy" } else { "Rename" },
+        item_type
     );
+    let line = layout::arrow(&head, &item_name, &new_name, layout::terminal_width());
+    match stats.sample.as_mut() {
+        _ if output::json() => {}
+        Some(sample) => sample.add(&item_name, &new_name, line),
+        None => println!("{line}"),
+    }
 
     if !preview_only {
         // Double-check the item still exists before renaming
-        if !item_path.exists() {
+        if !fs.exists(item_path) {
             // Item was moved/renamed by a previous operation, skip silently
-            return Ok(());
+            return Ok(None);
+        }
+        if copy {
+            fs.copy(item_path, &new_path)?;
+        } else {
+            fs.rename(item_path, &new_path)?;
+        }
+        output::done(op, Some(item_path), Some(&new_path));
+    } else {
+        output::planned(op, Some(item_path), Some(&new_path));
+    }
+
+    stats.rename(&item_name, &new_name);
+    Ok(Some(new_path))
+}
+
+/// `--plan FILE`: save what a preview would do, for `smv apply-plan` to run later
+///
+/// Paths are saved absolute, so the plan applies from any directory. A `.yaml`
+/// or `.yml` file gets a YAML plan, anything else JSON.
+fn save_plan(args: &Args, moves: &[(PathBuf, PathBuf)], copy: bool) -> Result<(), Box<dyn Error>> {
+    let Some(file) = &args.plan else {
+        return Ok(());
+    };
+    let cwd = std::env::current_dir()?;
+    let absolute = |path: &Path| cwd.join(path.strip_prefix(".").unwrap_or(path));
+    let ops: Vec<plan::PlannedOp> = moves
+        .iter()
+        .map(|(from, to)| plan::PlannedOp {
+            op: if copy {
+                plan::OpKind::Copy
+            } else {
+                plan::OpKind::Move
+            },
+            from: absolute(from),
+            to: absolute(to),
+        })
+        .collect();
+    let text = match file.extension().and_then(|ext| ext.to_str()) {
+        Some("yaml" | "yml") => plan::to_yaml(&ops)?,
+        _ => plan::to_json(&ops)?,
+    };
+    fs::write(file, text).map_err(|e| format!("Cannot write plan {}: {e}", file.display()))?;
+    if !output::json() {
+        println!(
+            "\nPlan with {} operation(s) saved to {}; run it with {}",
+            ops.len(),
+            file.display().to_string().cyan(),
+            format!("smv apply-plan {}", file.display()).cyan()
+        );
+    }
+    Ok(())
+}
+
+/// Remember where a real run's renames ended up, for --copy-names
+fn record_new_names(args: &Args, renames: &[(PathBuf, PathBuf)], preview_only: bool) {
+    if !preview_only && !args.simulate {
+        clipboard::record_renames(renames);
+    }
+}
+
+/// Record a real run's renames below `root` as one history entry, so a single
+/// `smv -u` puts the whole batch back
+///
+/// Returns the history it recorded in, for the link edits of the same batch.
+fn record_rename_batch(
+    args: &Args,
+    root: &Path,
+    renames: &[(PathBuf, PathBuf)],
+    preview_only: bool,
+) -> Result<Option<HistoryManager>, Box<dyn Error>> {
+    // Copies leave the sources as they were, so there is nothing to move back
+    if preview_only || args.simulate || args.copy || renames.is_empty() {
+        return Ok(None);
+    }
+    // Absolute paths keep the batch undoable from any directory
+    let cwd = std::env::current_dir()?;
+    let absolute = |path: &Path| cwd.join(path).components().collect::<PathBuf>();
+    let recorded: Vec<_> = renames
+        .iter()
+        .map(|(source, target)| (absolute(source), absolute(target)))
+        .collect();
+    let mut history_manager = HistoryManager::open(args.max_history_size, &paths::state_dir())?;
+    history_manager.record_mapping(absolute(root), &recorded)?;
+    Ok(Some(history_manager))
+}
+
+/// Rewrite links that point at renamed items (--update-links)
+///
+/// The edited files are backed up into `history`, the batch of the renames, so
+/// undoing the renames restores their links too.
+fn run_link_updates(
+    args: &Args,
+    root: &Path,
+    renames: &[(PathBuf, PathBuf)],
+    preview_only: bool,
+    history: Option<&mut HistoryManager>,
+) -> Result<(), Box<dyn Error>> {
+    if !args.update_links || renames.is_empty() {
+        return Ok(());
+    }
+
+    let extensions = links::parse_link_extensions(args.link_ext.as_deref());
+    let edits = links::plan_link_updates(root, renames, &extensions)?;
+    if !preview_only && let Some(history) = history {
+        let cwd = std::env::current_dir()?;
+        for edit in &edits {
+            history.record_overwrite(&cwd.join(&edit.path).components().collect::<PathBuf>())?;
+        }
+    }
+    if output::json() {
+        for edit in &edits {
+            if preview_only {
+                output::planned(Op::Edit, None, Some(&edit.path));
+            } else {
+                links::apply_link_updates(std::slice::from_ref(edit))?;
+                output::done(Op::Edit, None, Some(&edit.path));
+            }
+        }
+        return Ok(());
+    }
+
+    println!("\n{}:", "Link updates".bold());
+    if edits.is_empty() {
+        println!("No links reference the renamed items.");
+        return Ok(());
+    }
+
+    for edit in &edits {
+        let display_path = edit.path.strip_prefix(root).unwrap_or(&edit.path);
+        println!(
+            "{}Update links in {}",
+            if preview_only { "[PREVIEW] " } else { "" },
+            display_path.display().to_string().cyan()
+        );
+        for change in &edit.changes {
+            println!(
+                "  {:>4} {} {}",
+                change.line_number,
+                "-".red(),
+                change.before.red()
+            );
+            println!("  {:>4} {} {}", "", "+".green(), change.after.green());
         }
-        std::fs::rename(item_path, &new_path)?;
     }
 
-    stats.renamed += 1;
+    if !preview_only {
+        links::apply_link_updates(&edits)?;
+    }
+
+    println!(
+        "Files with {} links: {}",
+        if preview_only { "outdated" } else { "updated" },
+        edits.len().to_string().green()
+    );
+
     Ok(())
 }
 
+/// Skipped and filtered-out items by reason (`-v`)
+fn print_skip_reasons(stats: &Stats) {
+    for (filters, heading) in [(false, "Skipped"), (true, "Left out while collecting")] {
+        let reasons: Vec<_> = stats
+            .reasons
+            .iter()
+            .filter(|(reason, _)| reason.is_filter() == filters)
+            .collect();
+        if reasons.is_empty() {
+            continue;
+        }
+        println!("{heading}:");
+        for (reason, count) in reasons {
+            println!("  {:<22} {}", reason.label(), count.to_string().yellow());
+        }
+    }
+}
+
+/// Say that nothing matched; with `--json` that is a `stats` record of zeros
+fn report_no_matches(message: &str, preview: bool) {
+    if output::json() {
+        output::stats(preview, output::Totals::default());
+    } else {
+        println!("{message}");
+    }
+}
+
 /// Print transformation results
-fn print_transformation_results(stats: &Stats, preview_only: bool) {
+///
+/// With `--json` the results are the closing `stats` record, skip reasons included.
+fn print_transformation_results(args: &Args, stats: &Stats, preview_only: bool) {
+    if !preview_only {
+        notify::set_summary(format!(
+            "{} renamed, {} skipped, {} errors",
+            stats.renamed, stats.skipped, stats.errors
+        ));
+    }
+    if output::json() {
+        output::stats_with(preview_only, stats.totals(), stats.details());
+        return;
+    }
+
+    if let Some(ref sample) = stats.sample {
+        sample.print();
+    }
     println!("\n{}:", "Results".bold());
     println!("Items processed: {}", stats.processed.to_string().cyan());
     println!("Items to be renamed: {}", stats.renamed.to_string().green());
     println!("Errors encountered: {}", stats.errors.to_string().red());
+    if stats.skipped > 0 {
+        println!("Items skipped: {}", stats.skipped.to_string().yellow());
+    }
+    if args.verbose {
+        print_skip_reasons(stats);
+    }
+    print_changes(stats);
 
     if preview_only && stats.renamed > 0 {
         println!(
@@ -1616,6 +4621,32 @@ fn print_transformation_results(stats: &Stats, preview_only: bool) {
     }
 }
 
+/// Renames grouped by the kind of edit, so an unexpected one stands out
+fn print_changes(stats: &Stats) {
+    const SHOWN_CHANGES: usize = 10;
+
+    let changes = stats.changes();
+    if changes.is_empty() {
+        return;
+    }
+    println!("{}", "Changes:".bold());
+    for (class, count) in changes.iter().take(SHOWN_CHANGES) {
+        let files = if *count == 1 { "file" } else { "files" };
+        println!(
+            "  {class}: {} {files}",
+            preview::thousands(*count as usize).cyan()
+        );
+    }
+    if changes.len() > SHOWN_CHANGES {
+        let rest = &changes[SHOWN_CHANGES..];
+        println!(
+            "  ...and {} other kinds of change ({} files)",
+            rest.len(),
+            preview::thousands(rest.iter().map(|(_, count)| *count as usize).sum())
+        );
+    }
+}
+
 /// Run transformation on a specific target file
 fn run_transform_target_command(
     args: &Args,
@@ -1623,7 +4654,6 @@ fn run_transform_target_command(
     target_file: &str,
 ) -> Result<(), Box<dyn Error>> {
     use crate::transformers::transform;
-    use std::fs;
 
     // Verify the target file exists
     let target_path = Path::new(target_file);
@@ -1632,16 +4662,45 @@ fn run_transform_target_command(
     }
 
     // Get the filename to transform
-    let filename = target_path
-        .file_name()
-        .ok_or("Invalid file path")?
-        .to_string_lossy();
+    let file_name = target_path.file_name().ok_or("Invalid file path")?;
+    let op = if args.copy { Op::Copy } else { Op::Rename };
+    let preview = args.preview || args.simulate;
+    let report = |done: u32, skipped: u32, errors: u32| {
+        output::stats(
+            preview,
+            output::Totals {
+                processed: 1,
+                done,
+                skipped,
+                errors,
+            },
+        )
+    };
+    let Some(filename) = item_name(target_path, op, args.preview) else {
+        report(0, 1, 0);
+        return Ok(());
+    };
 
     // Apply transformation
     let new_filename = transform(&filename, &transform_type);
+    let Some(new_filename) = guard_name(
+        &filename,
+        new_filename,
+        NameFallback::parse(&args.name_fallback)?,
+        target_path.parent().unwrap_or(Path::new("")),
+        args.preview,
+    ) else {
+        output::skipped(op, target_path, "unusable name");
+        report(0, 1, 0);
+        return Ok(());
+    };
 
     // Check if transformation actually changed the name
-    if filename == new_filename {
+    if file_name == OsStr::new(&new_filename) {
+        if output::json() {
+            report(0, 1, 0);
+            return Ok(());
+        }
         println!(
             "No change needed: {} -> {}",
             filename.green(),
@@ -1657,29 +4716,46 @@ fn run_transform_target_command(
         PathBuf::from(&new_filename)
     };
 
-    println!(
-        "\n{}",
-        format!(
-            "CNP Smart Move - {} Mode (Target: {})",
-            if args.preview { "Preview" } else { "Transform" },
-            target_file
-        )
-        .bold()
-    );
-    println!("Transformation: {}", transform_type.as_str().green());
+    if !output::json() {
+        println!(
+            "\n{}",
+            format!(
+                "CNP Smart Move - {} Mode (Target: {})",
+                transform_mode(args.preview, args.simulate),
+                target_file
+            )
+            .bold()
+        );
+        println!("Transformation: {}", transform_type.as_str().green());
+
+        // Show the transformation
+        println!("\n{} -> {}", filename.yellow(), new_filename.green());
+    }
 
-    // Show the transformation
-    println!("\n{} -> {}", filename.yellow(), new_filename.green());
+    let link_root = target_path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let renames = vec![(target_path.to_path_buf(), new_path.clone())];
 
-    if args.preview {
-        println!("\n{}", "Preview mode - no changes made".blue());
-        return Ok(());
+    // A single rename has nothing to diverge on, so simulating it is a preview
+    if preview {
+        if !args.copy {
+            run_link_updates(args, link_root, &renames, true, None)?;
+        }
+        if output::json() {
+            output::planned(op, Some(target_path), Some(&new_path));
+            report(1, 0, 0);
+        } else {
+            println!("\n{}", "Preview mode - no changes made".blue());
+        }
+        return save_plan(args, &renames, args.copy);
     }
 
     // Check if destination exists and handle conflicts
     if new_path.exists() {
         if !args.force {
-            let should_continue = if args.interactive {
+            let should_continue = if args.interactive && !output::json() {
                 println!("File already exists: {}", new_path.display());
                 print!("Overwrite? (y/n): ");
                 use std::io::{self, Write};
@@ -1692,6 +4768,16 @@ fn run_transform_target_command(
             };
 
             if !should_continue {
+                if output::json() {
+                    output::conflict(
+                        op,
+                        target_path,
+                        &new_path,
+                        plan::Conflict::DestinationExists,
+                    );
+                    report(0, 0, 1);
+                    return Ok(());
+                }
                 println!(
                     "Operation cancelled - file already exists: {}",
                     new_path.display()
@@ -1701,27 +4787,64 @@ fn run_transform_target_command(
         }
     }
 
+    if args.copy {
+        file_ops::transfer_file(target_path, &new_path, true, &build_file_op_config(args))
+            .map_err(|e| format!("Failed to copy file: {}", e))?;
+        if output::json() {
+            output::done(op, Some(target_path), Some(&new_path));
+            report(1, 0, 0);
+            return Ok(());
+        }
+        println!(
+            "✓ Copied: {} -> {}",
+            filename.yellow(),
+            new_filename.green()
+        );
+        return Ok(());
+    }
+
     // Perform the rename
-    fs::rename(target_path, &new_path).map_err(|e| format!("Failed to rename file: {}", e))?;
+    file_ops::DiskFs::new(build_file_op_config(args))
+        .rename(target_path, &new_path)
+        .map_err(|e| format!("Failed to rename file: {}", e))?;
 
-    println!(
-        "✓ Renamed: {} -> {}",
-        filename.yellow(),
-        new_filename.green()
-    );
+    if !output::json() {
+        println!(
+            "✓ Renamed: {} -> {}",
+            filename.yellow(),
+            new_filename.green()
+        );
+    }
+
+    record_new_names(args, &renames, false);
+    let mut history = record_rename_batch(args, link_root, &renames, false)?;
+    run_link_updates(args, link_root, &renames, false, history.as_mut())?;
+    output::done(op, Some(target_path), Some(&new_path));
+    report(1, 0, 0);
 
     Ok(())
 }
 
 /// Run sort command using XFD syntax
 fn run_sort_command(args: &Args, method: SortMethod) -> Result<(), Box<dyn Error>> {
-    let directory = args.target.as_deref().unwrap_or(".");
+    let directory = match method {
+        // smv bucket RULE [target], smv sort alpha [target]
+        SortMethod::Bucket(_) | SortMethod::Alpha | SortMethod::ByDate => {
+            args.into_keyword.as_deref().unwrap_or(".")
+        }
+        // smv group [target], smv flatten [target]
+        _ => args
+            .target
+            .as_deref()
+            .or(args.arg1.as_deref())
+            .unwrap_or("."),
+    };
 
-    match method {
+    let moves = match method {
         SortMethod::Group => {
             println!("\n{}\n", "CNP Smart Move - Group Files by Basename".bold());
             println!("Processing directory: {}", directory.cyan());
-            sort::group_by_basename(directory, args.preview)?
+            sort::group_by_basename(directory, args.preview, &mut report::Console)?
         }
         SortMethod::Flatten => {
             println!(
@@ -1729,22 +4852,107 @@ fn run_sort_command(args: &Args, method: SortMethod) -> Result<(), Box<dyn Error
                 "CNP Smart Move - Flatten Directory Structure".bold()
             );
             println!("Processing directory: {}", directory.cyan());
-            unsort::flatten_directory(directory, args.preview)?;
+            let plan = unsort::FlattenPlan::new(directory, &mut report::Console)?;
+
+            // Renamed files are shown before anything moves
+            if !plan.collisions.is_empty() {
+                println!(
+                    "\n{} {} file(s) share a name with another; they are renamed:",
+                    "Name collisions:".yellow().bold(),
+                    plan.collisions.len()
+                );
+                for (from, to) in &plan.collisions {
+                    println!(
+                        "  {} → {}",
+                        from.display(),
+                        to.file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .yellow()
+                    );
+                }
+                println!();
+            }
+            if plan.moves.len() > unsort::FLATTEN_LIMIT && !args.preview && !args.force {
+                return Err(format!(
+                    "Flattening {directory} would move {} files (more than {}); look at smv flatten {directory} -p first, then add -F to go ahead",
+                    plan.moves.len(),
+                    unsort::FLATTEN_LIMIT
+                )
+                .into());
+            }
+            plan.apply(args.preview, &mut report::Console)?;
+            let moves = plan.moves;
 
             // Also remove empty directories
             println!("\nRemoving empty directories:");
-            unsort::remove_empty_dirs(directory, args.preview)?
+            unsort::remove_empty_dirs(directory, args.preview, &mut report::Console)?;
+            moves
+        }
+        SortMethod::Bucket(rule) => {
+            println!("\n{}\n", "CNP Smart Move - Bucket Files by Name".bold());
+            println!("Processing directory: {}", directory.cyan());
+            let moves = sort::bucket_files(
+                directory,
+                &rule,
+                args.recursive,
+                args.preview,
+                &mut report::Console,
+            )?;
+            if !args.preview {
+                record_sort_moves(args, directory, &moves)?;
+            }
+            moves
+        }
+        SortMethod::Alpha => {
+            println!("\n{}\n", "CNP Smart Move - Alphabetical Fan-out".bold());
+            println!("Processing directory: {}", directory.cyan());
+            let (moves, error) = match sort::fan_out_alpha(
+                directory,
+                args.levels,
+                args.preview,
+                &mut report::Console,
+            ) {
+                Ok(moves) => (moves, None),
+                Err(interrupted) => (interrupted.moves, Some(interrupted.error)),
+            };
+
+            if !args.preview {
+                record_sort_moves(args, directory, &moves)?;
+            }
+            if let Some(error) = error {
+                return Err(format!("{error:#}").into());
+            }
+            moves
         }
         SortMethod::ByType => {
             println!("Sort by type not yet implemented.");
+            Vec::new()
         }
         SortMethod::ByDate => {
-            println!("Sort by date not yet implemented.");
+            println!("\n{}\n", "CNP Smart Move - Sort by Date".bold());
+            println!("Processing directory: {}", directory.cyan());
+            let layout = args.layout.as_deref().unwrap_or(sort::DEFAULT_DATE_LAYOUT);
+            let rule = sort::BucketRule::Modified(sort::DateLayout::parse(layout)?);
+            let moves = sort::bucket_files(
+                directory,
+                &rule,
+                args.recursive,
+                args.preview,
+                &mut report::Console,
+            )?;
+            if !args.preview {
+                record_sort_moves(args, directory, &moves)?;
+            }
+            moves
         }
         SortMethod::BySize => {
             println!("Sort by size not yet implemented.");
+            Vec::new()
         }
-    }
+    };
+
+    print_disk_usage(args, &moves);
 
     if args.preview {
         println!(
@@ -1762,6 +4970,49 @@ fn run_sort_command(args: &Args, method: SortMethod) -> Result<(), Box<dyn Error
     Ok(())
 }
 
+/// Record the moves of a sort under `directory` as one undoable batch
+fn record_sort_moves(
+    args: &Args,
+    directory: &str,
+    moves: &[(PathBuf, PathBuf)],
+) -> Result<(), Box<dyn Error>> {
+    if moves.is_empty() {
+        return Ok(());
+    }
+    // Absolute paths keep the batch undoable from any directory
+    let cwd = std::env::current_dir()?;
+    let absolute = |path: &Path| cwd.join(path).components().collect::<PathBuf>();
+    let recorded: Vec<_> = moves
+        .iter()
+        .map(|(source, target)| (absolute(source), absolute(target)))
+        .collect();
+    let mut history_manager = HistoryManager::open(args.max_history_size, &paths::state_dir())?;
+    history_manager.record_mapping(absolute(Path::new(directory)), &recorded)?;
+    println!(
+        "\nMoved {} files; undo with {}",
+        moves.len(),
+        "smv -u".cyan()
+    );
+    Ok(())
+}
+
+/// Print the `--du` summary for a previewed operation
+///
+/// Sizes are measured from the sources, so this only works before anything moved.
+fn print_disk_usage(args: &Args, moves: &[(PathBuf, PathBuf)]) {
+    if !args.du {
+        return;
+    }
+    if args.preview {
+        disk_usage::UsagePlan::new(moves).print();
+    } else {
+        println!(
+            "\n{}",
+            "--du summaries are shown in preview mode (-p).".yellow()
+        );
+    }
+}
+
 /// Process exclude patterns into Regex objects
 fn process_exclude_patterns(patterns: Option<&str>) -> Result<Vec<regex::Regex>, Box<dyn Error>> {
     match patterns {
@@ -1790,7 +5041,30 @@ fn process_exclude_patterns(patterns: Option<&str>) -> Result<Vec<regex::Regex>,
 }
 
 /// Check if we should use CNP grammar parsing instead of legacy syntax
+/// The filter options (`--newer-than 7d`, `--ext jpg`, ...) spelled as CNP filters
+fn filter_option_args(args: &Args) -> Vec<String> {
+    [
+        ("MODIFIED>", &args.newer_than),
+        ("MODIFIED<", &args.older_than),
+        ("SIZE>", &args.larger_than),
+        ("SIZE<", &args.smaller_than),
+        ("NAME:", &args.name),
+        ("EXT:", &args.ext),
+    ]
+    .into_iter()
+    .filter_map(|(keyword, value)| value.as_ref().map(|value| format!("{keyword}{value}")))
+    .collect()
+}
+
 fn should_use_cnp_grammar(args: &Args) -> bool {
+    // A preset holds a whole command line, keywords included, run later
+    if args.command.as_deref() == Some("preset") {
+        return false;
+    }
+    if !filter_option_args(args).is_empty() {
+        return true;
+    }
+
     // Collect all arguments to check for CNP keywords
     let mut all_args = Vec::new();
 
@@ -1821,14 +5095,18 @@ fn should_use_cnp_grammar(args: &Args) -> bool {
             || arg.starts_with("DEPTH")
             || arg.starts_with("MODIFIED")
             || arg.starts_with("ACCESSED")
+            || arg.starts_with("RES>")
+            || arg.starts_with("RES<")
             || *arg == "WHERE"
+            || *arg == chain::KEYWORD
             || matches!(*arg, "TO" | "INTO" | "FORMAT")
     });
 
-    // Check for glob patterns that should use CNP parsing
-    let has_glob_patterns = all_args.iter().any(|arg| {
-        arg.contains('*') || arg.contains('?') || arg.contains('[') || arg.contains('{')
-    });
+    // Check for glob patterns that should use CNP parsing; mv/cp expand their own
+    let has_glob_patterns = !matches!(args.command.as_deref(), Some("mv" | "cp"))
+        && all_args.iter().any(|arg| {
+            arg.contains('*') || arg.contains('?') || arg.contains('[') || arg.contains('{')
+        });
 
     // Special case: rm command with CNP filters
     let has_rm_with_filters = args.command.as_deref() == Some("rm") && has_cnp_keywords;
@@ -1860,6 +5138,7 @@ fn run_cnp_command(args: &Args) -> Result<(), Box<dyn Error>> {
 
     // Add trailing arguments
     cnp_args.extend(args.args.iter().cloned());
+    cnp_args.extend(filter_option_args(args));
 
     // Add flags as arguments
     if args.recursive {
@@ -1868,8 +5147,24 @@ fn run_cnp_command(args: &Args) -> Result<(), Box<dyn Error>> {
     if args.preview {
         cnp_args.push("-p".to_string());
     }
-    if args.force {
-        cnp_args.push("-F".to_string());
+    // In CNP commands -f means force, as it does for rm
+    if args.force || args.file_flag {
+        cnp_args.push("-f".to_string());
+    }
+    if args.no_clobber {
+        cnp_args.push("-n".to_string());
+    }
+    if args.hidden {
+        cnp_args.push("-a".to_string());
+    }
+    if args.one_file_system {
+        cnp_args.push("-x".to_string());
+    }
+    if args.dereference {
+        cnp_args.push("-L".to_string());
+    }
+    if args.no_follow {
+        cnp_args.push("-P".to_string());
     }
     if args.case_insensitive {
         cnp_args.push("-i".to_string());
@@ -1887,32 +5182,82 @@ fn run_cnp_command(args: &Args) -> Result<(), Box<dyn Error>> {
         cnp_args.push("-u".to_string());
     }
 
+    if cnp_args.iter().any(|arg| arg == chain::KEYWORD) {
+        return run_cnp_chain(args, &cnp_args);
+    }
+
     // Parse CNP command
     let cnp_command = CnpGrammarParser::parse(&cnp_args)?;
+    // FORMAT:json on a command that changes files reports what it did; on a bare
+    // selection it lists the matches
+    let changes_files = cnp_command.transform_command.is_some()
+        || cnp_command.remove_command.is_some()
+        || cnp_command.transfer.is_some();
+    if changes_files
+        && cnp_command.routes.iter().any(|route| {
+            matches!(
+                route,
+                cnp_grammar::Route::Format(cnp_grammar::OutputFormat::Json)
+            )
+        })
+    {
+        output::enable();
+    }
 
     // Handle special flags first
     if args.interactive || cnp_command.flags.contains('I') {
-        return run_interactive_mode(args.max_history_size);
+        return run_interactive_mode(
+            args.max_history_size,
+            args.script.as_deref(),
+            args.resume,
+            cnp_command.flags.contains('f'),
+        );
     }
     if args.tui || cnp_command.flags.contains('T') {
-        return run_tui_mode();
+        return run_tui_mode(args.max_history_size, cnp_command.flags.contains('f'));
     }
     if args.undo || cnp_command.flags.contains('u') {
-        return run_undo_mode(args.max_history_size);
+        return run_undo_mode(args, cnp_command.flags.contains('f'));
+    }
+
+    // Tiering consumes its TO-DIR: route itself
+    if cnp_command.tier {
+        return run_cnp_tier_command(args, &cnp_command);
+    }
+    if cnp_command.attr {
+        return run_cnp_attr_command(args, &cnp_command);
+    }
+    if let Some(transfer) = cnp_command.transfer {
+        return run_cnp_transfer_command(args, &cnp_command, transfer);
     }
 
     // Handle routes (tool delegation)
     if let Some(route) = cnp_command.routes.first() {
         match route {
-            cnp_grammar::Route::To { tool, args } => {
-                return run_tool_delegation(&cnp_command, tool, args);
+            cnp_grammar::Route::To {
+                tool,
+                args: tool_args,
+            } => {
+                return run_tool_delegation(&cnp_command, tool, tool_args, args.full);
             }
             cnp_grammar::Route::Into(file) => {
                 return run_output_to_file(&cnp_command, file);
             }
+            cnp_grammar::Route::Format(cnp_grammar::OutputFormat::Json) if changes_files => {}
             cnp_grammar::Route::Format(format) => {
                 return run_formatted_output(&cnp_command, format);
             }
+            cnp_grammar::Route::ToDir(_) => {
+                return Err("TO-DIR: is only supported by the tier command".into());
+            }
+            // Transforms pick up their destination below
+            cnp_grammar::Route::IntoDir(_) if cnp_command.transform_command.is_some() => {}
+            cnp_grammar::Route::IntoDir(_) => {
+                return Err(
+                    "INTO-DIR: needs a transform command (e.g. smv snake inbox/ INTO-DIR:archive/)"
+                        .into(),
+                );
+            }
         }
     }
 
@@ -1939,6 +5284,8 @@ fn run_cnp_command(args: &Args) -> Result<(), Box<dyn Error>> {
                     .new_value
                     .as_ref()
                     .ok_or("Missing replacement for REGEX")?;
+                regex::Regex::new(pattern)
+                    .map_err(|e| format!("Invalid regex pattern '{pattern}': {e}"))?;
                 TransformType::replace_regex(pattern, replacement)
             }
             "snake" => TransformType::Snake,
@@ -1959,19 +5306,122 @@ fn run_cnp_command(args: &Args) -> Result<(), Box<dyn Error>> {
             }
         };
 
-        return run_cnp_transform_command(&cnp_command, transform_type);
+        return run_cnp_transform_command(args, &cnp_command, transform_type);
     }
 
     // Handle remove command
     if let Some(ref remove_cmd) = cnp_command.remove_command {
-        return run_cnp_remove_command(&cnp_command);
+        return run_cnp_remove_command(args, &cnp_command);
     }
 
+    // `smv sanke . EXT:jpg` takes the misspelled command for the path
+    if let Some(first) = cnp_args.first()
+        && !Path::new(first).exists()
+    {
+        eprintln!("Error: {}", suggest::unknown_command(first));
+        eprintln!("For help: smv --help");
+        process::exit(1);
+    }
     Err("No valid CNP command found".into())
 }
 
+/// Run stages joined by THEN as one batch (`smv clean . EXT:jpg THEN sort date`)
+///
+/// The first stage's path, filters and flags select the files; flags may also
+/// come at the end of the line. The stages only plan, and the combined moves run
+/// (or are previewed) together and are undone together.
+fn run_cnp_chain(args: &Args, words: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut stages = Vec::new();
+    let mut selection = Vec::new();
+    for (index, part) in chain::split(words).into_iter().enumerate() {
+        let (stage, taken) = chain::Stage::parse(part, args.layout.as_deref(), args.levels)?
+            .ok_or_else(|| {
+                format!(
+                    "Stage {} is not a transform, sort, bucket or mv: {}",
+                    index + 1,
+                    part.join(" ")
+                )
+            })?;
+        stages.push(stage);
+        for word in &part[taken..] {
+            if index > 0 && !word.starts_with('-') {
+                return Err(format!(
+                    "Only the first stage takes a path and filters; stage {} has: {}",
+                    index + 1,
+                    word
+                )
+                .into());
+            }
+            selection.push(word.clone());
+        }
+    }
+
+    let cnp_command = CnpGrammarParser::parse(&selection)?;
+    let preview = cnp_command.flags.contains('p');
+    let root = Path::new(&cnp_command.path);
+    let expanded_filters =
+        cnp_grammar::CnpGrammarParser::expand_semantic_groups(&cnp_command.filters);
+    let files = build_cnp_file_list(
+        &cnp_command.path,
+        &expanded_filters,
+        cnp_command.flags.contains('r'),
+        cnp_command.flags.contains('a'),
+        cnp_command.case_insensitive,
+        false,
+        cnp_command.flags.contains('x'),
+    )?;
+    // Stages move files; a directory would carry other files along
+    let files: Vec<PathBuf> = select_cnp_files(args, &cnp_command, files)?
+        .into_iter()
+        .filter(|file| file.is_file())
+        .collect();
+    if files.is_empty() {
+        println!("No files found matching CNP filter criteria.");
+        return Ok(());
+    }
+
+    let selected = files.len();
+    let (entries, changed) = chain::run(&stages, root, files);
+    if !output::json() {
+        println!(
+            "\n{}",
+            format!(
+                "CNP Smart Move - {} Chain",
+                if preview { "Preview" } else { "Execute" }
+            )
+            .bold()
+        );
+        println!("Path: {}", cnp_command.path.cyan());
+        println!("Files: {}", selected.to_string().cyan());
+        for (number, (stage, count)) in stages.iter().zip(&changed).enumerate() {
+            println!(
+                "  {}. {}: {} changed",
+                number + 1,
+                stage.describe().green(),
+                count
+            );
+        }
+        println!();
+    }
+
+    let planned = entries
+        .into_iter()
+        .filter(|entry| entry.source != entry.current)
+        .map(|entry| (entry.source, entry.current))
+        .collect();
+    run_file_batch(
+        args,
+        root,
+        preview,
+        cnp_command.flags.contains('f'),
+        planned,
+        false,
+    )
+}
+
 /// Run transform command with CNP grammar
 fn run_cnp_transform_command(
+    args: &Args,
     cnp_command: &CnpCommand,
     transform_type: TransformType,
 ) -> Result<(), Box<dyn Error>> {
@@ -1983,56 +5433,486 @@ fn run_cnp_transform_command(
     let expanded_filters =
         cnp_grammar::CnpGrammarParser::expand_semantic_groups(&cnp_command.filters);
 
-    println!(
-        "\n{}",
-        format!(
-            "CNP Smart Move - {} Mode",
-            if preview { "Preview" } else { "Transform" }
+    let json = output::json();
+    if !json {
+        println!(
+            "\n{}",
+            format!(
+                "CNP Smart Move - {} Mode",
+                transform_mode(preview, args.simulate)
+            )
+            .bold()
+        );
+        println!("Transformation: {}", transform_type.as_str().green());
+        println!("Path: {}", path.cyan());
+        println!(
+            "Filters: {} active",
+            expanded_filters.len().to_string().cyan()
+        );
+        println!(
+            "Recursive: {}",
+            if recursive {
+                "Yes".green()
+            } else {
+                "No".yellow()
+            }
+        );
+        println!();
+    }
+
+    // Build file list based on CNP filters
+    let include_hidden = cnp_command.flags.contains('a');
+    let files = build_cnp_file_list(
+        path,
+        &expanded_filters,
+        recursive,
+        include_hidden,
+        cnp_command.case_insensitive,
+        false, // files_only disabled for CNP commands - they handle this through TYPE:file filters
+        cnp_command.flags.contains('x'),
+    )?;
+    // TYPE:file and TYPE:dir choose the kinds; --apply-to narrows them further
+    let files = match args.apply_to {
+        Some(apply_to) => files.into_iter().filter(|f| apply_to.admits(f)).collect(),
+        None => files,
+    };
+    let files = select_cnp_files(args, cnp_command, files)?;
+
+    if files.is_empty() {
+        report_no_matches("No files found matching CNP filter criteria.", preview);
+        return Ok(());
+    }
+
+    let into_dir = cnp_command.routes.iter().find_map(|route| match route {
+        cnp_grammar::Route::IntoDir(dir) => Some(PathBuf::from(dir)),
+        _ => None,
+    });
+    if let Some(destination) = into_dir {
+        if args.simulate {
+            return Err("--simulate covers in-place transforms; preview INTO-DIR: with -p".into());
+        }
+        return run_transform_into_dir(args, cnp_command, &transform_type, files, &destination);
+    }
+
+    // Process files for transformation
+    let mut fs = transform_fs(args, Path::new(path))?;
+    let preview = preview && !args.simulate;
+    let name_fallback = NameFallback::parse(&args.name_fallback)?;
+    let mut stats = Stats {
+        sample: preview::Sample::for_preview(files.len(), !preview || args.full),
+        ..Stats::default()
+    };
+    let renames = transform_items(
+        args,
+        fs.as_mut(),
+        files,
+        &transform_type,
+        name_fallback,
+        preview,
+        &mut stats,
+    )?;
+
+    record_new_names(args, &renames, preview);
+    let mut history = record_rename_batch(args, Path::new(path), &renames, preview)?;
+    if !args.copy && !args.simulate {
+        run_link_updates(args, Path::new(path), &renames, preview, history.as_mut())?;
+    }
+
+    // Print results
+    print_transformation_results(args, &stats, preview);
+    print_simulation_note(args.simulate);
+    save_plan(args, &renames, args.copy)?;
+
+    Ok(())
+}
+
+/// Rename matched files and move them into `destination` as one batch
+///
+/// Paths below the search root are kept under the destination. Every planned move is
+/// checked against the destination (and the rest of the batch) before anything moves.
+fn run_transform_into_dir(
+    args: &Args,
+    cnp_command: &CnpCommand,
+    transform_type: &TransformType,
+    files: Vec<PathBuf>,
+    destination: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let root = Path::new(&cnp_command.path);
+    let name_fallback = NameFallback::parse(&args.name_fallback)?;
+    let preview = cnp_command.flags.contains('p');
+    if !output::json() {
+        println!("Destination: {}", destination.display().to_string().cyan());
+        println!();
+    }
+
+    let mut planned = Vec::new();
+    for source in files {
+        // Directories are walked for their files; moving them would move those too
+        if !source.is_file() {
+            continue;
+        }
+
+        let Some(name) = item_name(&source, Op::Move, preview) else {
+            continue;
+        };
+        let relative_dir = source
+            .parent()
+            .and_then(|parent| parent.strip_prefix(root).ok())
+            .unwrap_or(Path::new(""));
+        let target_dir = destination.join(relative_dir);
+        let new_name = guard_name(
+            &name,
+            transform(&name, transform_type),
+            name_fallback,
+            &target_dir,
+            preview,
         )
-        .bold()
-    );
-    println!("Transformation: {}", transform_type.as_str().green());
-    println!("Path: {}", path.cyan());
-    println!(
-        "Filters: {} active",
-        expanded_filters.len().to_string().cyan()
-    );
-    println!(
-        "Recursive: {}",
-        if recursive {
-            "Yes".green()
-        } else {
-            "No".yellow()
+        .unwrap_or_else(|| name.clone());
+        let target = target_dir.join(new_name);
+        planned.push((source, target));
+    }
+
+    run_file_batch(
+        args,
+        Path::new(&cnp_command.path),
+        cnp_command.flags.contains('p'),
+        cnp_command.flags.contains('f'),
+        planned,
+        args.copy,
+    )
+}
+
+/// Check every planned move (or copy), then preview or apply them
+///
+/// Conflicts with the destination or the rest of the batch are reported and skipped.
+/// Moves are recorded as one undoable batch; copies leave nothing to undo.
+fn run_file_batch(
+    args: &Args,
+    root: &Path,
+    preview: bool,
+    overwrite: bool,
+    planned: Vec<(PathBuf, PathBuf)>,
+    copy: bool,
+) -> Result<(), Box<dyn Error>> {
+    let verb = if copy { "Copy" } else { "Move" };
+    let output_op = if copy { Op::Copy } else { Op::Move };
+    let json = preview && output::json();
+    let touched: Vec<PathBuf> = planned
+        .iter()
+        .flat_map(|(source, target)| [source.clone(), target.clone()])
+        .collect();
+    check_dir_rules(args, &touched, None)?;
+
+    let mut stats = Stats::default();
+    let mut checker = plan::ConflictChecker::new(overwrite);
+    let mut moves = Vec::new();
+    for (source, target) in planned {
+        stats.processed += 1;
+        let op = plan::PlannedOp {
+            op: if copy {
+                plan::OpKind::Copy
+            } else {
+                plan::OpKind::Move
+            },
+            from: source.clone(),
+            to: target.clone(),
+        };
+        if json {
+            let mut conflicts: Vec<_> = checker.check(&op).into_iter().collect();
+            conflicts.extend(checker.warnings(&op));
+            if conflicts.iter().any(plan::Conflict::is_blocking) {
+                stats.errors += 1;
+            } else {
+                stats.renamed += 1;
+            }
+            output::preview(output_op, &source, &target, &conflicts);
+            continue;
         }
-    );
-    println!();
+        if let Some(conflict) = checker.check(&op) {
+            if output::json() {
+                output::conflict(output_op, &source, &target, conflict);
+            } else {
+                println!(
+                    "{}Conflict: \"{}\" → \"{}\" ({})",
+                    if preview { "[PREVIEW] " } else { "" },
+                    source.display(),
+                    target.display(),
+                    conflict
+                );
+            }
+            stats.errors += 1;
+            continue;
+        }
+        moves.push((source, target));
+    }
+
+    let kind = if copy {
+        plan::OpKind::Copy
+    } else {
+        plan::OpKind::Move
+    };
+    let ops: Vec<plan::PlannedOp> = moves
+        .iter()
+        .map(|(from, to)| plan::PlannedOp {
+            op: kind,
+            from: from.clone(),
+            to: to.clone(),
+        })
+        .collect();
+    check_writable(&ops, preview)?;
+    if json {
+        print_transformation_results(args, &stats, true);
+        return Ok(());
+    }
+    if preview {
+        let width = layout::terminal_width();
+        for (source, target) in &moves {
+            println!(
+                "{}",
+                layout::arrow(
+                    &format!("[PREVIEW] {verb}: "),
+                    &source.display().to_string(),
+                    &target.display().to_string(),
+                    width
+                )
+            );
+        }
+        stats.renamed = moves.len() as u32;
+        print_transformation_results(args, &stats, true);
+        return save_plan(args, &moves, copy);
+    }
+
+    let config = build_file_op_config(args);
+    let mut batch = Batch::new(config.on_error);
+    // Conflicts are the batch's first errors; stopping here leaves nothing to undo
+    if stats.errors > 0 && !batch.keep_going(&mut file_ops::DiskFs::default()) {
+        print_transformation_results(args, &stats, false);
+        return Ok(());
+    }
+    let mut done = Vec::new();
+    for (source, target) in moves {
+        if let Some(parent) = target.parent() {
+            attr::create_dir_all(parent)?;
+        }
+        let replaced = target.symlink_metadata().is_ok();
+        let result = file_ops::transfer_file(&source, &target, copy, &config);
+        match result {
+            Ok(()) => {
+                if output::json() {
+                    output::done(output_op, Some(&source), Some(&target));
+                } else {
+                    println!(
+                        "{}: \"{}\" → \"{}\"",
+                        verb,
+                        source.display(),
+                        target.display()
+                    );
+                }
+                stats.renamed += 1;
+                if copy {
+                    batch.copied(&target, replaced);
+                } else {
+                    batch.moved(&source, &target);
+                }
+                done.push((source, target));
+            }
+            Err(e) => {
+                eprintln!(
+                    "{}: Failed to {} \"{}\" - {}",
+                    "Error".red(),
+                    verb.to_lowercase(),
+                    source.display(),
+                    e
+                );
+                output::failed(output_op, Some(&source), Some(&target), &e.to_string());
+                stats.errors += 1;
+                if !batch.keep_going(&mut file_ops::DiskFs::new(config.clone())) {
+                    break;
+                }
+            }
+        }
+    }
+    if batch.rolled_back() {
+        done.clear();
+        stats.renamed = 0;
+    }
+
+    // Copies leave the source as it was, so there is nothing to undo or relink
+    if copy {
+        print_transformation_results(args, &stats, false);
+        return Ok(());
+    }
+
+    if !done.is_empty() {
+        // Absolute paths keep the batch undoable from any directory
+        let cwd = std::env::current_dir()?;
+        let recorded: Vec<_> = done
+            .iter()
+            .map(|(source, target)| (cwd.join(source), cwd.join(target)))
+            .collect();
+        let mut history_manager = HistoryManager::open(args.max_history_size, &paths::state_dir())?;
+        history_manager.record_mapping(cwd, &recorded)?;
+        record_new_names(args, &done, false);
+        run_link_updates(args, root, &done, false, Some(&mut history_manager))?;
+    }
+
+    print_transformation_results(args, &stats, false);
+    if stats.renamed > 0 && !output::json() {
+        println!("Undo the whole batch with {}", "smv -u".cyan());
+    }
+    Ok(())
+}
+
+/// Copy or move the files matching CNP filters into a destination directory
+///
+/// Files land flat in the destination unless `--preserve-structure` keeps their
+/// path relative to the search root.
+fn run_cnp_transfer_command(
+    args: &Args,
+    cnp_command: &CnpCommand,
+    transfer: cnp_grammar::Transfer,
+) -> Result<(), Box<dyn Error>> {
+    let copy = transfer == cnp_grammar::Transfer::Copy;
+    let destination = cnp_command
+        .destination
+        .clone()
+        .or_else(|| {
+            cnp_command.routes.iter().find_map(|route| match route {
+                cnp_grammar::Route::ToDir(dir) => Some(dir.clone()),
+                _ => None,
+            })
+        })
+        .map(PathBuf::from)
+        .ok_or("Missing destination. Usage: smv cp [path] FILTERS... DEST")?;
+    let path = &cnp_command.path;
+    let recursive = cnp_command.flags.contains('r');
+    let preview = cnp_command.flags.contains('p');
+
+    let expanded_filters =
+        cnp_grammar::CnpGrammarParser::expand_semantic_groups(&cnp_command.filters);
+
+    if !output::json() {
+        println!(
+            "\n{}",
+            format!(
+                "CNP Smart Move - {} {} Operation",
+                if preview { "Preview" } else { "Execute" },
+                if copy { "Copy" } else { "Move" }
+            )
+            .bold()
+        );
+        println!("Path: {}", path.cyan());
+        println!("Destination: {}", destination.display().to_string().cyan());
+        println!(
+            "Filters: {} active",
+            expanded_filters.len().to_string().cyan()
+        );
+        println!(
+            "Structure: {}",
+            if args.preserve_structure {
+                "preserved".green()
+            } else {
+                "flat".yellow()
+            }
+        );
+        println!();
+    }
 
-    // Build file list based on CNP filters
-    let include_hidden = cnp_command.flags.contains('a');
     let files = build_cnp_file_list(
         path,
         &expanded_filters,
         recursive,
-        include_hidden,
+        cnp_command.flags.contains('a'),
         cnp_command.case_insensitive,
-        false, // files_only disabled for CNP commands - they handle this through TYPE:file filters
+        true, // directories are walked for their files
+        cnp_command.flags.contains('x'),
     )?;
+    let files = exclude_destination(files, &destination);
+    let files = select_cnp_files(args, cnp_command, files)?;
 
     if files.is_empty() {
-        println!("No files found matching CNP filter criteria.");
+        report_no_matches("No files found matching CNP filter criteria.", preview);
         return Ok(());
     }
 
-    // Process files for transformation
-    let mut stats = Stats::default();
-    for item_path in files {
-        process_item_transformation(&item_path, &transform_type, preview, &mut stats)?;
+    let planned = files
+        .into_iter()
+        .map(|file| {
+            let target = if args.preserve_structure {
+                destination.join(file.strip_prefix(path).unwrap_or(&file))
+            } else {
+                destination.join(file.file_name().unwrap_or_default())
+            };
+            (file, target)
+        })
+        .collect();
+
+    run_file_batch(
+        args,
+        Path::new(&cnp_command.path),
+        cnp_command.flags.contains('p'),
+        cnp_command.flags.contains('f'),
+        planned,
+        copy,
+    )
+}
+
+/// Order matches with `BY:` and narrow them with `LIMIT:`/`FIRST:`/`SAMPLE:`,
+/// reporting both
+fn select_cnp_files(
+    args: &Args,
+    cnp_command: &CnpCommand,
+    files: Vec<PathBuf>,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let matched = files.len();
+    let files = selection::apply(files, cnp_command.limit, cnp_command.sort);
+
+    if let Some(order) = cnp_command.sort {
+        println!("Order: {}", order.describe().cyan());
+    }
+    if let Some(limit) = cnp_command.limit {
+        println!(
+            "Selection: {} ({} of {} matches)",
+            selection::describe(limit, cnp_command.sort).cyan(),
+            files.len(),
+            matched
+        );
     }
+    if cnp_command.sort.is_some() || cnp_command.limit.is_some() {
+        println!();
+    }
+    pick_files(args, files)
+}
 
-    // Print results
-    print_transformation_results(&stats, preview);
+/// Drop matches that already sit inside `destination`, when it lies in the searched tree
+///
+/// Otherwise `smv cp . EXT:jpg backup/ -r` would pick up the copies a previous
+/// run left in backup/ and copy them onto themselves.
+fn exclude_destination(files: Vec<PathBuf>, destination: &Path) -> Vec<PathBuf> {
+    let (inside, files): (Vec<PathBuf>, Vec<PathBuf>) = files.into_iter().partition(|file| {
+        file.ancestors()
+            .skip(1)
+            .any(|dir| plan::same_file(dir, destination))
+    });
+    if !inside.is_empty() {
+        println!(
+            "Skipping {} matches inside the destination {}",
+            inside.len(),
+            destination.display().to_string().cyan()
+        );
+    }
+    files
+}
 
-    Ok(())
+/// With `--pick`, narrow `files` to the ones chosen in the fuzzy finder
+fn pick_files(args: &Args, files: Vec<PathBuf>) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if !args.pick || files.is_empty() {
+        return Ok(files);
+    }
+    let matched = files.len();
+    let files = pick::pick(files)?;
+    println!("Picked: {} of {} matches\n", files.len(), matched);
+    Ok(files)
 }
 
 /// Build file list based on CNP filters
@@ -2043,231 +5923,484 @@ fn build_cnp_file_list(
     include_hidden: bool,
     case_insensitive: bool,
     files_only: bool,
+    one_file_system: bool,
 ) -> Result<Vec<PathBuf>, Box<dyn Error>> {
-    use cnp_grammar::{FileType, Filter};
     use walkdir::WalkDir;
 
-    let mut items = Vec::new();
-    let walker = if recursive {
-        WalkDir::new(path)
-    } else {
-        WalkDir::new(path).max_depth(1)
+    let base = Path::new(path);
+    // Names and depths decide most entries before anything is read from the disk
+    let (duplicates, mut filters): (Vec<&cnp_grammar::Filter>, Vec<&cnp_grammar::Filter>) = filters
+        .iter()
+        .partition(|filter| matches!(filter, cnp_grammar::Filter::Duplicates { .. }));
+    filters.sort_by_key(|filter| filter_cost(filter));
+    let matching = Matching::new(path, &filters, case_insensitive)?;
+    let keep = |candidate: &Candidate| -> Result<bool, Box<dyn Error>> {
+        // Skip the root directory itself, and hidden entries unless asked for
+        if candidate.path() == base
+            || (!include_hidden && is_path_or_parent_hidden(candidate.path(), base))
+        {
+            return Ok(false);
+        }
+        for filter in &filters {
+            if !cnp_filter_matches(candidate, &matching, filter)? {
+                return Ok(false);
+            }
+        }
+        Ok(!(files_only && candidate.is_dir()))
     };
 
-    for entry in walker.into_iter().filter_map(Result::ok) {
-        let entry_path = entry.path();
+    let mut items = Vec::new();
+    let mut indexed = false;
+    // The index knows nothing of mount points, so -x always walks
+    if !one_file_system && let Some(index) = index::Index::load(base) {
+        match index.stale() {
+            None => {
+                for entry in &index.entries {
+                    if !recursive && entry.path.components().count() > 1 {
+                        continue;
+                    }
+                    let entry_path = base.join(&entry.path);
+                    if keep(&Candidate::indexed(&entry_path, entry))? {
+                        items.push(entry_path);
+                    }
+                }
+                indexed = true;
+            }
+            Some(reason) => eprintln!(
+                "{} the index of {} is out of date ({}); walking instead. Refresh it with: smv index update {}",
+                "Note:".yellow(),
+                path,
+                reason,
+                path
+            ),
+        }
+    }
 
-        // Skip the root directory itself
-        if entry_path == std::path::Path::new(path) {
-            continue;
+    if !indexed {
+        let walker = if recursive {
+            WalkDir::new(path)
+        } else {
+            WalkDir::new(path).max_depth(1)
         }
+        .same_file_system(one_file_system);
 
-        // Check for hidden files/directories recursively (skip if not including hidden)
-        if !include_hidden {
-            let base_path_obj = std::path::Path::new(path);
-            if is_path_or_parent_hidden(entry_path, base_path_obj) {
-                continue;
+        for entry in walker.into_iter().filter_map(Result::ok) {
+            if keep(&Candidate::new(&entry))? {
+                items.push(entry.path().to_path_buf());
             }
         }
+    }
 
-        // Apply CNP filters
-        let mut matches = true;
-
-        for filter in filters {
-            match filter {
-                Filter::Name(name) => {
-                    if let Some(filename) = entry_path.file_name() {
-                        let filename_str = filename.to_string_lossy();
-                        let match_result =
-                            if name.contains('*') || name.contains('?') || name.contains('[') {
-                                // Glob pattern matching
-                                let pattern = if case_insensitive {
-                                    glob::Pattern::new(&name.to_lowercase())?
-                                } else {
-                                    glob::Pattern::new(name)?
-                                };
-                                let test_str = if case_insensitive {
-                                    filename_str.to_lowercase()
-                                } else {
-                                    filename_str.to_string()
-                                };
-                                pattern.matches(&test_str)
-                            } else {
-                                // Substring matching
-                                if case_insensitive {
-                                    filename_str.to_lowercase().contains(&name.to_lowercase())
-                                } else {
-                                    filename_str.contains(name)
-                                }
-                            };
-
-                        if !match_result {
-                            matches = false;
-                            break;
-                        }
+    if let Some(cnp_grammar::Filter::Duplicates { all }) = duplicates.first() {
+        let copies: HashSet<PathBuf> = hash::duplicates(&items, hash::algorithm())
+            .into_iter()
+            .flat_map(|group| group.into_iter().skip(usize::from(!*all)))
+            .collect();
+        items.retain(|item| copies.contains(item));
+    }
+
+    Ok(items)
+}
+
+/// An entry the CNP filters look at, with its metadata read at most once
+///
+/// The walk already knows each entry's type; `SIZE`, `MODIFIED` and
+/// `ACCESSED` share one stat, made only when one of them asks. Entries from
+/// an index know their type, size and modification time without any.
+struct Candidate<'a> {
+    path: &'a Path,
+    source: Source<'a>,
+    metadata: OnceCell<Option<fs::Metadata>>,
+}
+
+enum Source<'a> {
+    Walk(&'a walkdir::DirEntry),
+    Indexed(&'a index::Entry),
+}
+
+impl<'a> Candidate<'a> {
+    fn new(entry: &'a walkdir::DirEntry) -> Self {
+        Self {
+            path: entry.path(),
+            source: Source::Walk(entry),
+            metadata: OnceCell::new(),
+        }
+    }
+
+    fn indexed(path: &'a Path, entry: &'a index::Entry) -> Self {
+        Self {
+            path,
+            source: Source::Indexed(entry),
+            metadata: OnceCell::new(),
+        }
+    }
+
+    fn path(&self) -> &'a Path {
+        self.path
+    }
+
+    /// The metadata of what the path points to
+    fn metadata(&self) -> Option<&fs::Metadata> {
+        self.metadata
+            .get_or_init(|| match self.source {
+                Source::Walk(entry) if !self.is_symlink() => entry.metadata().ok(),
+                _ => self.path.metadata().ok(),
+            })
+            .as_ref()
+    }
+
+    /// The metadata of the path itself
+    fn symlink_metadata(&self) -> Option<fs::Metadata> {
+        match self.source {
+            _ if !self.is_symlink() => self.metadata().cloned(),
+            Source::Walk(entry) => entry.metadata().ok(),
+            Source::Indexed(_) => self.path.symlink_metadata().ok(),
+        }
+    }
+
+    fn is_symlink(&self) -> bool {
+        match self.source {
+            Source::Walk(entry) => entry.path_is_symlink(),
+            Source::Indexed(entry) => entry.link,
+        }
+    }
+
+    /// What the path points to, free unless it is a symlink found by the walk
+    fn kind(&self) -> Option<index::Kind> {
+        let file_type = match self.source {
+            Source::Indexed(entry) => return Some(entry.kind),
+            Source::Walk(_) if self.is_symlink() => self.metadata()?.file_type(),
+            Source::Walk(entry) => entry.file_type(),
+        };
+        Some(if file_type.is_file() {
+            index::Kind::File
+        } else if file_type.is_dir() {
+            index::Kind::Dir
+        } else {
+            index::Kind::Other
+        })
+    }
+
+    fn is_file(&self) -> bool {
+        self.kind() == Some(index::Kind::File)
+    }
+
+    fn is_dir(&self) -> bool {
+        self.kind() == Some(index::Kind::Dir)
+    }
+
+    fn len(&self) -> Option<u64> {
+        match self.source {
+            Source::Indexed(entry) => entry.size,
+            Source::Walk(_) => self.metadata().map(fs::Metadata::len),
+        }
+    }
+
+    /// The content hash of a file, from the index when it has one of `algorithm`
+    fn digest(&self, algorithm: hash::Algorithm) -> Option<hash::Digest> {
+        if let Source::Indexed(entry) = self.source
+            && let Some(digest) = entry.digest()
+            && digest.algorithm == algorithm
+        {
+            return Some(digest);
+        }
+        if !self.is_file() {
+            return None;
+        }
+        hash::file(self.path, algorithm).ok()
+    }
+
+    fn modified(&self) -> Option<std::time::SystemTime> {
+        match self.source {
+            Source::Indexed(entry) => entry.modified(),
+            Source::Walk(_) => self.metadata()?.modified().ok(),
+        }
+    }
+}
+
+/// How much checking a filter costs, cheapest first: names, then the entry
+/// type, then metadata, then reading the file
+fn filter_cost(filter: &cnp_grammar::Filter) -> u8 {
+    use cnp_grammar::Filter;
+
+    match filter {
+        Filter::Name(_)
+        | Filter::NameRegex(_)
+        | Filter::Extension(_)
+        | Filter::DepthGreater(_)
+        | Filter::DepthLess(_)
+        | Filter::Tag(_)
+        | Filter::For(_) => 0,
+        Filter::Type(_) => 1,
+        Filter::SizeGreater(_)
+        | Filter::SizeLess(_)
+        | Filter::ModifiedAfter(_)
+        | Filter::ModifiedBefore(_)
+        | Filter::AccessedAfter(_)
+        | Filter::AccessedBefore(_)
+        | Filter::Empty(_)
+        | Filter::Where(_) => 2,
+        Filter::ResolutionGreater(_)
+        | Filter::ResolutionLess(_)
+        | Filter::Hash(_)
+        | Filter::Duplicates { .. } => 3,
+    }
+}
+
+/// What checking filters against the paths below `root` needs, prepared once per search
+struct Matching<'a> {
+    root: &'a str,
+    case_insensitive: bool,
+    /// `NAME~:` patterns, compiled before the walk so a bad one fails up front
+    name_regexes: HashMap<&'a str, regex::Regex>,
+}
+
+impl<'a> Matching<'a> {
+    fn new(
+        root: &'a str,
+        filters: &[&'a cnp_grammar::Filter],
+        case_insensitive: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        use cnp_grammar::Filter;
+
+        let mut name_regexes = HashMap::new();
+        let nested = filters.iter().flat_map(|filter| match filter {
+            Filter::Where(expr) => expr.filters(),
+            _ => vec![*filter],
+        });
+        for filter in nested {
+            if let Filter::NameRegex(pattern) = filter {
+                let regex = regex::RegexBuilder::new(pattern)
+                    .case_insensitive(case_insensitive)
+                    .build()
+                    .map_err(|e| format!("Invalid regex in NAME~: {e}"))?;
+                name_regexes.insert(pattern.as_str(), regex);
+            }
+        }
+        Ok(Self {
+            root,
+            case_insensitive,
+            name_regexes,
+        })
+    }
+}
+
+/// Check one CNP filter against a path found below the searched root
+fn cnp_filter_matches(
+    entry: &Candidate,
+    matching: &Matching,
+    filter: &cnp_grammar::Filter,
+) -> Result<bool, Box<dyn Error>> {
+    use cnp_grammar::{FileType, Filter};
+
+    let (root, case_insensitive) = (matching.root, matching.case_insensitive);
+    let path = entry.path();
+    match filter {
+        Filter::Name(name) => {
+            if let Some(filename) = path.file_name() {
+                let filename_str = filename.to_string_lossy();
+                let match_result = if name.contains('*') || name.contains('?') || name.contains('[')
+                {
+                    // Glob pattern matching
+                    let pattern = if case_insensitive {
+                        glob::Pattern::new(&name.to_lowercase())?
                     } else {
-                        matches = false;
-                        break;
-                    }
-                }
-                Filter::Type(file_type) => {
-                    let entry_matches = match file_type {
-                        FileType::File => entry_path.is_file(),
-                        FileType::Folder => entry_path.is_dir(),
-                        FileType::Symlink => entry_path.is_symlink(),
-                        FileType::Other => {
-                            !entry_path.is_file()
-                                && !entry_path.is_dir()
-                                && !entry_path.is_symlink()
-                        }
+                        glob::Pattern::new(name)?
                     };
-                    if !entry_matches {
-                        matches = false;
-                        break;
-                    }
-                }
-                Filter::Extension(ext) => {
-                    if let Some(entry_ext) = entry_path.extension() {
-                        if entry_ext.to_string_lossy().to_lowercase() != ext.to_lowercase() {
-                            matches = false;
-                            break;
-                        }
+                    let test_str = if case_insensitive {
+                        filename_str.to_lowercase()
                     } else {
-                        matches = false;
-                        break;
-                    }
-                }
-                cnp_grammar::Filter::SizeGreater(size_str) => {
-                    if let Ok(metadata) = entry_path.metadata() {
-                        if let Ok(size_bytes) = parse_size_string(size_str) {
-                            if metadata.len() <= size_bytes {
-                                matches = false;
-                                break;
-                            }
-                        }
-                    }
-                }
-                cnp_grammar::Filter::SizeLess(size_str) => {
-                    if let Ok(metadata) = entry_path.metadata() {
-                        if let Ok(size_bytes) = parse_size_string(size_str) {
-                            if metadata.len() >= size_bytes {
-                                matches = false;
-                                break;
-                            }
-                        }
-                    }
-                }
-                cnp_grammar::Filter::DepthGreater(max_depth) => {
-                    let entry_depth = entry_path.components().count();
-                    let base_depth = std::path::Path::new(path).components().count();
-                    let relative_depth = entry_depth.saturating_sub(base_depth);
-                    if relative_depth <= *max_depth {
-                        matches = false;
-                        break;
-                    }
-                }
-                cnp_grammar::Filter::DepthLess(min_depth) => {
-                    let entry_depth = entry_path.components().count();
-                    let base_depth = std::path::Path::new(path).components().count();
-                    let relative_depth = entry_depth.saturating_sub(base_depth);
-                    if relative_depth >= *min_depth {
-                        matches = false;
-                        break;
-                    }
-                }
-                cnp_grammar::Filter::ModifiedAfter(date_str) => {
-                    if let Ok(metadata) = entry_path.metadata() {
-                        if let Ok(modified) = metadata.modified() {
-                            if let Ok(target_time) = parse_date_string(date_str) {
-                                if modified <= target_time {
-                                    matches = false;
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                }
-                cnp_grammar::Filter::ModifiedBefore(date_str) => {
-                    if let Ok(metadata) = entry_path.metadata() {
-                        if let Ok(modified) = metadata.modified() {
-                            if let Ok(target_time) = parse_date_string(date_str) {
-                                if modified >= target_time {
-                                    matches = false;
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                }
-                cnp_grammar::Filter::AccessedAfter(date_str) => {
-                    if let Ok(metadata) = entry_path.metadata() {
-                        if let Ok(accessed) = metadata.accessed() {
-                            if let Ok(target_time) = parse_date_string(date_str) {
-                                if accessed <= target_time {
-                                    matches = false;
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                }
-                cnp_grammar::Filter::AccessedBefore(date_str) => {
-                    if let Ok(metadata) = entry_path.metadata() {
-                        if let Ok(accessed) = metadata.accessed() {
-                            if let Ok(target_time) = parse_date_string(date_str) {
-                                if accessed >= target_time {
-                                    matches = false;
-                                    break;
-                                }
-                            }
-                        }
+                        filename_str.to_string()
+                    };
+                    pattern.matches(&test_str)
+                } else {
+                    // Substring matching
+                    if case_insensitive {
+                        filename_str.to_lowercase().contains(&name.to_lowercase())
+                    } else {
+                        filename_str.contains(name)
                     }
+                };
+
+                if !match_result {
+                    return Ok(false);
                 }
-                cnp_grammar::Filter::Tag(_tag) => {
-                    // Tag filtering would require integration with file tagging system
-                    // For now, skip tags
-                    continue;
-                }
-                cnp_grammar::Filter::Hash(_hash) => {
-                    // Hash filtering would require file hash computation
-                    // For now, skip hash filters
-                    continue;
-                }
-                cnp_grammar::Filter::Where(_sub_filters) => {
-                    // WHERE filters should be expanded during parsing
-                    // For now, skip WHERE groups
-                    continue;
-                }
-                cnp_grammar::Filter::For(_semantic_group) => {
-                    // FOR filters should be expanded by semantic group expansion
-                    // If we encounter one here, it means expansion didn't work properly
-                    // Skip it for now
-                    continue;
-                }
+            } else {
+                return Ok(false);
             }
         }
-
-        if matches {
-            // Apply files-only filter if enabled
-            if files_only && entry_path.is_dir() {
-                // Skip directories if files-only is enabled
+        Filter::Empty(expected) => {
+            let empty = match entry.symlink_metadata() {
+                Some(metadata) if metadata.is_dir() => fs::read_dir(path)
+                    .map(|mut entries| entries.next().is_none())
+                    .unwrap_or(false),
+                Some(metadata) => metadata.is_file() && metadata.len() == 0,
+                None => false,
+            };
+            if empty != *expected {
+                return Ok(false);
+            }
+        }
+        Filter::NameRegex(pattern) => {
+            let regex = matching
+                .name_regexes
+                .get(pattern.as_str())
+                .ok_or("NAME~: pattern was not compiled before the search")?;
+            let name_matches = path
+                .file_name()
+                .is_some_and(|name| regex.is_match(&name.to_string_lossy()));
+            if !name_matches {
+                return Ok(false);
+            }
+        }
+        Filter::Type(file_type) => {
+            let entry_matches = match file_type {
+                FileType::File => entry.is_file(),
+                FileType::Folder => entry.is_dir(),
+                FileType::Symlink => entry.is_symlink(),
+                FileType::Other => !entry.is_file() && !entry.is_dir() && !entry.is_symlink(),
+            };
+            if !entry_matches {
+                return Ok(false);
+            }
+        }
+        Filter::Extension(ext) => {
+            if let Some(entry_ext) = path.extension() {
+                if entry_ext.to_string_lossy().to_lowercase() != ext.to_lowercase() {
+                    return Ok(false);
+                }
             } else {
-                items.push(entry_path.to_path_buf());
+                return Ok(false);
+            }
+        }
+        cnp_grammar::Filter::SizeGreater(size_str) => {
+            if let Some(len) = entry.len()
+                && let Ok(size_bytes) = parse_size_string(size_str)
+                && len <= size_bytes
+            {
+                return Ok(false);
+            }
+        }
+        cnp_grammar::Filter::SizeLess(size_str) => {
+            if let Some(len) = entry.len()
+                && let Ok(size_bytes) = parse_size_string(size_str)
+                && len >= size_bytes
+            {
+                return Ok(false);
+            }
+        }
+        cnp_grammar::Filter::DepthGreater(max_depth) => {
+            let entry_depth = path.components().count();
+            let base_depth = Path::new(root).components().count();
+            let relative_depth = entry_depth.saturating_sub(base_depth);
+            if relative_depth <= *max_depth {
+                return Ok(false);
+            }
+        }
+        cnp_grammar::Filter::DepthLess(min_depth) => {
+            let entry_depth = path.components().count();
+            let base_depth = Path::new(root).components().count();
+            let relative_depth = entry_depth.saturating_sub(base_depth);
+            if relative_depth >= *min_depth {
+                return Ok(false);
+            }
+        }
+        cnp_grammar::Filter::ModifiedAfter(date_str) => {
+            if let Some(modified) = entry.modified()
+                && let Ok(target_time) = parse_date_string(date_str)
+                && modified <= target_time
+            {
+                return Ok(false);
+            }
+        }
+        cnp_grammar::Filter::ModifiedBefore(date_str) => {
+            if let Some(modified) = entry.modified()
+                && let Ok(target_time) = parse_date_string(date_str)
+                && modified >= target_time
+            {
+                return Ok(false);
+            }
+        }
+        cnp_grammar::Filter::AccessedAfter(date_str) => {
+            if let Some(metadata) = entry.metadata()
+                && let Ok(accessed) = metadata.accessed()
+                && let Ok(target_time) = parse_date_string(date_str)
+                && accessed <= target_time
+            {
+                return Ok(false);
+            }
+        }
+        cnp_grammar::Filter::AccessedBefore(date_str) => {
+            if let Some(metadata) = entry.metadata()
+                && let Ok(accessed) = metadata.accessed()
+                && let Ok(target_time) = parse_date_string(date_str)
+                && accessed >= target_time
+            {
+                return Ok(false);
+            }
+        }
+        cnp_grammar::Filter::ResolutionGreater(height)
+        | cnp_grammar::Filter::ResolutionLess(height) => {
+            if !template::video::backend_available() {
+                return Err("RES filters need video support (build with --features video)".into());
+            }
+            let greater = matches!(filter, cnp_grammar::Filter::ResolutionGreater(_));
+            let entry_matches = match template::video::probe(path) {
+                Some(info) if greater => info.height > *height,
+                Some(info) => info.height < *height,
+                None => false,
+            };
+            if !entry_matches {
+                return Ok(false);
+            }
+        }
+        cnp_grammar::Filter::Tag(_tag) => {
+            // Tag filtering would require integration with file tagging system
+            // For now, skip tags
+        }
+        cnp_grammar::Filter::Hash(value) => {
+            let (algorithm, prefix) = match value.split_once(':') {
+                Some((name, hex)) => (hash::Algorithm::parse(name)?, hex),
+                None => (hash::algorithm(), value.as_str()),
+            };
+            if !entry
+                .digest(algorithm)
+                .is_some_and(|digest| digest.hex.starts_with(prefix))
+            {
+                return Ok(false);
+            }
+        }
+        cnp_grammar::Filter::Duplicates { .. } => {
+            // Whole lists are compared in build_cnp_file_list, not single entries
+            return Err("DUPES: compares all matches, so it cannot go inside WHERE".into());
+        }
+        cnp_grammar::Filter::Where(expr) => {
+            if !expr.evaluate(&mut |inner| cnp_filter_matches(entry, matching, inner))? {
+                return Ok(false);
             }
         }
+        cnp_grammar::Filter::For(_semantic_group) => {
+            // FOR filters should be expanded by semantic group expansion
+            // If we encounter one here, it means expansion didn't work properly
+            // Skip it for now
+        }
     }
 
-    Ok(items)
+    Ok(true)
 }
 
 /// Handle tool delegation
+///
+/// With `-p` nothing is spawned: the command line, its arguments and the paths that
+/// would be piped to it are shown instead.
 fn run_tool_delegation(
     cnp_command: &CnpCommand,
     tool: &str,
     additional_args: &[String],
+    full: bool,
 ) -> Result<(), Box<dyn Error>> {
     use std::io::Write;
-    use std::process::{Command, Stdio};
+    use std::process::Stdio;
 
     println!("Delegating to tool: {}", tool.cyan());
 
@@ -2279,64 +6412,35 @@ fn run_tool_delegation(
     let files = build_cnp_file_list(
         &cnp_command.path,
         &expanded_filters,
-        recursive,
-        include_hidden,
-        cnp_command.case_insensitive,
-        false, // files_only disabled for CNP commands - they handle this through TYPE:file filters
-    )?;
-
-    if files.is_empty() {
-        println!("No files found to delegate to {tool}");
-        return Ok(());
-    }
-
-    // Create subprocess for tool delegation
-    let mut cmd = Command::new(tool);
-    cmd.stdin(Stdio::piped());
-    cmd.stdout(Stdio::piped());
-    cmd.stderr(Stdio::piped());
-
-    // Add base arguments based on the tool
-    match tool {
-        "say" => {
-            // For SAY tool, we need to pass the operation type
-            if let Some(ref transform_cmd) = cnp_command.transform_command {
-                match transform_cmd.command_type.as_str() {
-                    "snake" | "kebab" | "pascal" | "camel" | "title" | "sentence" | "start"
-                    | "studly" | "lower" | "upper" | "clean" => {
-                        cmd.arg(&transform_cmd.command_type);
-                    }
-                    _ => {
-                        cmd.arg("split_and_titlecase"); // Default SAY operation
-                    }
-                }
-            } else {
-                cmd.arg("split_and_titlecase");
-            }
-        }
-        "dff" => {
-            cmd.arg("find_duplicates");
-        }
-        "xfd" => {
-            cmd.arg("interactive_select");
-        }
-        "dsc" => {
-            cmd.arg(&cnp_command.path);
-            cmd.arg("--paths");
-        }
-        _ => {
-            // Generic tool delegation
-            cmd.arg(&cnp_command.path);
-        }
+        recursive,
+        include_hidden,
+        cnp_command.case_insensitive,
+        false, // files_only disabled for CNP commands - they handle this through TYPE:file filters
+        cnp_command.flags.contains('x'),
+    )?;
+    let files = selection::apply(files, cnp_command.limit, cnp_command.sort);
+
+    if files.is_empty() {
+        println!("No files found to delegate to {tool}");
+        return Ok(());
+    }
+
+    let tool_args = tool_arguments(cnp_command, tool, additional_args);
+    let tools = config::Config::load()?.tools;
+    if cnp_command.flags.contains('p') {
+        print_tool_preview(&tools, tool, &tool_args, &files, full);
+        return Ok(());
     }
 
-    // Add user-provided additional arguments
+    // Create subprocess for tool delegation
+    let mut cmd = tools.command(tool)?;
     if !additional_args.is_empty() {
         println!("Adding additional arguments: {additional_args:?}");
-        for arg in additional_args {
-            cmd.arg(arg);
-        }
     }
+    cmd.args(&tool_args);
+    cmd.stdin(Stdio::piped());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
 
     println!("Spawning {} with {} files...", tool, files.len());
 
@@ -2380,6 +6484,93 @@ fn run_tool_delegation(
     Ok(())
 }
 
+/// The arguments a delegated tool is started with: its own, then the user's
+fn tool_arguments(cnp_command: &CnpCommand, tool: &str, additional_args: &[String]) -> Vec<String> {
+    let mut args = match tool {
+        "say" => {
+            // For SAY tool, we need to pass the operation type
+            let operation = cnp_command
+                .transform_command
+                .as_ref()
+                .map(|transform_cmd| transform_cmd.command_type.as_str())
+                .filter(|command_type| {
+                    matches!(
+                        *command_type,
+                        "snake"
+                            | "kebab"
+                            | "pascal"
+                            | "camel"
+                            | "title"
+                            | "sentence"
+                            | "start"
+                            | "studly"
+                            | "lower"
+                            | "upper"
+                            | "clean"
+                    )
+                })
+                .unwrap_or("split_and_titlecase"); // Default SAY operation
+            vec![operation.to_string()]
+        }
+        "dff" => vec![String::from("find_duplicates")],
+        "xfd" => vec![String::from("interactive_select")],
+        "dsc" => vec![cnp_command.path.clone(), String::from("--paths")],
+        // Generic tool delegation
+        _ => vec![cnp_command.path.clone()],
+    };
+    args.extend(additional_args.iter().cloned());
+    args
+}
+
+/// Paths shown by a TO: preview before the rest are counted
+const TOOL_PREVIEW_PATHS: usize = 10;
+
+/// Show what a TO: delegation would run and send, without running it
+fn print_tool_preview(
+    tools: &config::Tools,
+    tool: &str,
+    tool_args: &[String],
+    files: &[PathBuf],
+    full: bool,
+) {
+    let program = match tools.resolve(tool) {
+        Ok(path) => path.display().to_string(),
+        Err(e) => {
+            println!("{}: {}; running this would fail", "Warning".yellow(), e);
+            tool.to_string()
+        }
+    };
+    let line: Vec<String> = std::iter::once(program.as_str())
+        .chain(tool_args.iter().map(String::as_str))
+        .map(wizard::quote_arg)
+        .collect();
+    println!(
+        "{} Would run: {}",
+        "[PREVIEW]".blue().bold(),
+        line.join(" ").cyan()
+    );
+    println!("Arguments ({}):", tool_args.len());
+    for (i, arg) in tool_args.iter().enumerate() {
+        println!("  [{i}] {arg:?}");
+    }
+    println!("Standard input: {} paths, one per line", files.len());
+    let shown = if full {
+        files.len()
+    } else {
+        TOOL_PREVIEW_PATHS
+    };
+    for file in files.iter().take(shown) {
+        println!("  {}", file.display());
+    }
+    if files.len() > shown {
+        println!(
+            "  ...and {} more (--full lists them all)",
+            files.len() - shown
+        );
+    }
+    println!("\nThis was a preview only. {tool} was not started.");
+}
+
 /// Handle output to file
 fn run_output_to_file(cnp_command: &CnpCommand, file: &str) -> Result<(), Box<dyn Error>> {
     use std::fs::File;
@@ -2399,7 +6590,9 @@ fn run_output_to_file(cnp_command: &CnpCommand, file: &str) -> Result<(), Box<dy
         include_hidden,
         cnp_command.case_insensitive,
         false, // files_only disabled for CNP commands - they handle this through TYPE:file filters
+        cnp_command.flags.contains('x'),
     )?;
+    let files = selection::apply(files, cnp_command.limit, cnp_command.sort);
 
     if files.is_empty() {
         println!("No files found to write to output file");
@@ -2456,7 +6649,9 @@ fn run_formatted_output(
         include_hidden,
         cnp_command.case_insensitive,
         false, // files_only disabled for CNP commands - they handle this through TYPE:file filters
+        cnp_command.flags.contains('x'),
     )?;
+    let files = selection::apply(files, cnp_command.limit, cnp_command.sort);
 
     if files.is_empty() {
         println!("No files found for formatted output");
@@ -2556,9 +6751,7 @@ fn parse_mode_string(mode_str: &str) -> Result<Option<u32>, Box<dyn Error>> {
 fn parse_size_string(size_str: &str) -> Result<u64, Box<dyn Error>> {
     let size_str = size_str.to_uppercase();
 
-    if let Some(num_str) = size_str.strip_suffix("B") {
-        return Ok(num_str.parse::<u64>()?);
-    }
+    // Longest suffixes first, otherwise "2KB" would be read as "2K" bytes
     if let Some(num_str) = size_str.strip_suffix("KB") {
         return Ok(num_str.parse::<u64>()? * 1024);
     }
@@ -2571,36 +6764,201 @@ fn parse_size_string(size_str: &str) -> Result<u64, Box<dyn Error>> {
     if let Some(num_str) = size_str.strip_suffix("TB") {
         return Ok(num_str.parse::<u64>()? * 1024 * 1024 * 1024 * 1024);
     }
+    if let Some(num_str) = size_str.strip_suffix("B") {
+        return Ok(num_str.parse::<u64>()?);
+    }
 
     // If no suffix, assume bytes
     Ok(size_str.parse::<u64>()?)
 }
 
-/// Run remove command with CNP grammar
-fn run_cnp_remove_command(cnp_command: &CnpCommand) -> Result<(), Box<dyn Error>> {
+/// Run tier command: move matching files to another directory (usually another mount)
+///
+/// Relative paths are kept under the destination. Cross-device moves are copied,
+/// verified and only then removed from the source.
+fn run_cnp_tier_command(args: &Args, cnp_command: &CnpCommand) -> Result<(), Box<dyn Error>> {
+    let destination = cnp_command
+        .routes
+        .iter()
+        .find_map(|route| match route {
+            cnp_grammar::Route::ToDir(dir) => Some(PathBuf::from(dir)),
+            _ => None,
+        })
+        .ok_or("Missing destination. Usage: smv tier [path] FILTERS... TO-DIR:/mnt/cold")?;
     let path = &cnp_command.path;
     let recursive = cnp_command.flags.contains('r');
     let preview = cnp_command.flags.contains('p');
-    let force = cnp_command.flags.contains('f');
-    let include_hidden = cnp_command.flags.contains('a');
+
+    let expanded_filters =
+        cnp_grammar::CnpGrammarParser::expand_semantic_groups(&cnp_command.filters);
+
+    println!(
+        "\n{}",
+        format!(
+            "CNP Smart Move - {} Tier Operation",
+            if preview { "Preview" } else { "Execute" }
+        )
+        .bold()
+    );
+    println!("Path: {}", path.cyan());
+    println!("Destination: {}", destination.display().to_string().cyan());
+    println!(
+        "Filters: {} active",
+        expanded_filters.len().to_string().cyan()
+    );
     println!(
-        "Debug: flags='{}', include_hidden={}",
-        cnp_command.flags, include_hidden
+        "Recursive: {}",
+        if recursive {
+            "Yes".green()
+        } else {
+            "No".yellow()
+        }
     );
+    println!();
+
+    let files = build_cnp_file_list(
+        path,
+        &expanded_filters,
+        recursive,
+        cnp_command.flags.contains('a'),
+        cnp_command.case_insensitive,
+        true, // only files are tiered; directories stay in place
+        cnp_command.flags.contains('x'),
+    )?;
+    let files = select_cnp_files(args, cnp_command, files)?;
+
+    if files.is_empty() {
+        println!("No files found matching CNP filter criteria.");
+        return Ok(());
+    }
+
+    let mut stats = file_ops::FileOpStats::default();
+    let mut bytes_moved = 0u64;
+    let mut bytes_reclaimed = 0u64;
+    let mut copied = 0u32;
+    let mut protection = protect::Protection::new();
+
+    for file in &files {
+        stats.processed += 1;
+        let relative = file.strip_prefix(path).unwrap_or(file);
+        let new_path = destination.join(relative);
+        let size = fs::metadata(file).map(|m| m.len()).unwrap_or(0);
+
+        if let Some(reason) = protection.reason(file) {
+            println!(
+                "{} {} (protected: {})",
+                "Skipping".yellow(),
+                file.display(),
+                reason
+            );
+            stats.skipped += 1;
+            continue;
+        }
+        if new_path.exists() {
+            println!(
+                "{} {} (target exists: {})",
+                "Skipping".yellow(),
+                file.display(),
+                new_path.display()
+            );
+            stats.skipped += 1;
+            continue;
+        }
+
+        if preview {
+            println!(
+                "{} Move: {:?} → {:?} ({})",
+                "[PREVIEW]".blue().bold(),
+                file,
+                new_path,
+                disk_usage::format_size(size)
+            );
+            stats.moved += 1;
+            bytes_moved += size;
+            continue;
+        }
+
+        match file_ops::move_file_verified(file, &new_path) {
+            Ok(kind) => {
+                stats.moved += 1;
+                bytes_moved += size;
+                if kind == file_ops::MoveKind::Copied {
+                    copied += 1;
+                    bytes_reclaimed += size;
+                }
+                println!(
+                    "Moved: {:?} → {:?} ({})",
+                    file,
+                    new_path,
+                    disk_usage::format_size(size)
+                );
+            }
+            Err(e) => {
+                eprintln!(
+                    "{}: Failed to move {}: {}",
+                    "Error".red(),
+                    file.display(),
+                    e
+                );
+                stats.errors += 1;
+            }
+        }
+    }
+
+    println!("\n{}", "Results:".bold());
+    println!("Files processed: {}", stats.processed);
+    println!(
+        "Files {}: {} ({})",
+        if preview { "to move" } else { "moved" },
+        stats.moved,
+        disk_usage::format_size(bytes_moved)
+    );
+    if !preview {
+        println!("Copied across filesystems and verified: {copied}");
+        println!(
+            "Space reclaimed on source filesystem: {}",
+            disk_usage::format_size(bytes_reclaimed).green()
+        );
+    }
+    println!("Skipped: {}", stats.skipped);
+    println!("Errors: {}", stats.errors);
+
+    if preview {
+        println!(
+            "\n{}",
+            "This was a preview only. No files were actually moved."
+                .bold()
+                .blue()
+        );
+    }
+
+    Ok(())
+}
+
+/// Change the permissions of the matching files as PERM: says (`smv attr`)
+fn run_cnp_attr_command(args: &Args, cnp_command: &CnpCommand) -> Result<(), Box<dyn Error>> {
+    let perm = cnp_command
+        .perm
+        .as_deref()
+        .ok_or("Missing PERM:. Usage: smv attr [path] FILTERS... PERM:+x")?;
+    let change = attr::PermChange::parse(perm)?;
+    let path = &cnp_command.path;
+    let recursive = cnp_command.flags.contains('r');
+    let preview = cnp_command.flags.contains('p');
 
-    // Expand semantic groups
     let expanded_filters =
         cnp_grammar::CnpGrammarParser::expand_semantic_groups(&cnp_command.filters);
 
     println!(
         "\n{}",
         format!(
-            "CNP Smart Move - {} Remove Operation",
+            "CNP Smart Move - {} Attribute Operation",
             if preview { "Preview" } else { "Execute" }
         )
         .bold()
     );
     println!("Path: {}", path.cyan());
+    println!("Change: {}", format!("PERM:{perm}").cyan());
     println!(
         "Filters: {} active",
         expanded_filters.len().to_string().cyan()
@@ -2613,14 +6971,167 @@ fn run_cnp_remove_command(cnp_command: &CnpCommand) -> Result<(), Box<dyn Error>
             "No".yellow()
         }
     );
+    println!();
+
+    let files = build_cnp_file_list(
+        path,
+        &expanded_filters,
+        recursive,
+        cnp_command.flags.contains('a'),
+        cnp_command.case_insensitive,
+        !args.everything,
+        cnp_command.flags.contains('x'),
+    )?;
+    let mut files = select_cnp_files(args, cnp_command, files)?;
+    if files.is_empty() {
+        println!("No files found matching CNP filter criteria.");
+        return Ok(());
+    }
+    // Directories go last, deepest first, so taking away their x or r
+    // cannot lock out the files still to be changed inside them
+    files.sort_by_key(|file| {
+        let depth = file.components().count();
+        (file.is_dir(), std::cmp::Reverse(depth))
+    });
+
+    let mut stats = file_ops::FileOpStats::default();
+    let mut unchanged = 0;
+    let mut changes = Vec::new();
+    let cwd = std::env::current_dir()?;
+    for file in &files {
+        stats.processed += 1;
+        if file.is_symlink() {
+            println!("{} {} (symlink)", "Skipping".yellow(), file.display());
+            stats.skipped += 1;
+            continue;
+        }
+        let before = match attr::mode_of(file) {
+            Ok(mode) => mode,
+            Err(e) => {
+                eprintln!("{}: {}: {}", "Error".red(), file.display(), e);
+                stats.errors += 1;
+                continue;
+            }
+        };
+        let after = change.apply(before, file.is_dir());
+        if after == before {
+            unchanged += 1;
+            continue;
+        }
+        let line = format!(
+            "{:?}: {} → {}",
+            file,
+            attr::format_mode(before),
+            attr::format_mode(after).green()
+        );
+        if preview {
+            println!("{} Mode: {}", "[PREVIEW]".blue().bold(), line);
+            stats.moved += 1;
+            continue;
+        }
+        match attr::set_mode(file, after) {
+            Ok(()) => {
+                println!("Mode: {line}");
+                stats.moved += 1;
+                changes.push(history::ModeChange {
+                    path: cwd.join(file),
+                    before,
+                    after,
+                });
+            }
+            Err(e) => {
+                eprintln!(
+                    "{}: Failed to change {}: {}",
+                    "Error".red(),
+                    file.display(),
+                    e
+                );
+                stats.errors += 1;
+            }
+        }
+    }
+
+    if !changes.is_empty() {
+        let mut history_manager = HistoryManager::open(args.max_history_size, &paths::state_dir())?;
+        history_manager.record_modes(cwd.join(path), &changes)?;
+    }
 
+    println!("\n{}", "Results:".bold());
+    println!("Files processed: {}", stats.processed);
+    println!(
+        "Files {}: {}",
+        if preview { "to change" } else { "changed" },
+        stats.moved
+    );
+    println!("Already as asked: {unchanged}");
+    println!("Skipped: {}", stats.skipped);
+    println!("Errors: {}", stats.errors);
     if preview {
         println!(
-            "{}",
-            "PREVIEW MODE - No files will be deleted".bold().blue()
+            "\n{}",
+            "This was a preview only. No permissions were changed."
+                .bold()
+                .blue()
+        );
+    } else if !changes.is_empty() {
+        println!(
+            "Undo with {} to restore the previous permissions",
+            "smv -u".cyan()
         );
     }
-    println!();
+    notify::set_summary(format!("{} changed, {} errors", stats.moved, stats.errors));
+    Ok(())
+}
+
+/// Run remove command with CNP grammar
+fn run_cnp_remove_command(args: &Args, cnp_command: &CnpCommand) -> Result<(), Box<dyn Error>> {
+    let path = &cnp_command.path;
+    let recursive = cnp_command.flags.contains('r');
+    let preview = cnp_command.flags.contains('p');
+    let force = cnp_command.flags.contains('f');
+    let include_hidden = cnp_command.flags.contains('a');
+    if !output::json() {
+        println!(
+            "Debug: flags='{}', include_hidden={}",
+            cnp_command.flags, include_hidden
+        );
+    }
+
+    // Expand semantic groups
+    let expanded_filters =
+        cnp_grammar::CnpGrammarParser::expand_semantic_groups(&cnp_command.filters);
+
+    if !output::json() {
+        println!(
+            "\n{}",
+            format!(
+                "CNP Smart Move - {} Remove Operation",
+                if preview { "Preview" } else { "Execute" }
+            )
+            .bold()
+        );
+        println!("Path: {}", path.cyan());
+        println!(
+            "Filters: {} active",
+            expanded_filters.len().to_string().cyan()
+        );
+        println!(
+            "Recursive: {}",
+            if recursive {
+                "Yes".green()
+            } else {
+                "No".yellow()
+            }
+        );
+
+        if preview {
+            println!(
+                "{}",
+                "PREVIEW MODE - No files will be deleted".bold().blue()
+            );
+        }
+        println!();
+    }
 
     // Build file list based on CNP filters
     let include_hidden = cnp_command.flags.contains('a');
@@ -2631,48 +7142,73 @@ fn run_cnp_remove_command(cnp_command: &CnpCommand) -> Result<(), Box<dyn Error>
         include_hidden,
         cnp_command.case_insensitive,
         false, // files_only disabled for CNP commands - they handle this through TYPE:file filters
+        cnp_command.flags.contains('x'),
     )?;
+    let files = select_cnp_files(args, cnp_command, files)?;
 
     if files.is_empty() {
-        println!("No files found matching CNP filter criteria.");
+        report_no_matches("No files found matching CNP filter criteria.", preview);
+        return Ok(());
+    }
+
+    if output::json() && preview {
+        for file_path in &files {
+            output::planned(Op::Remove, Some(file_path), None);
+        }
+        let count = files.len() as u32;
+        output::stats(
+            true,
+            output::Totals {
+                processed: count,
+                done: count,
+                ..Default::default()
+            },
+        );
         return Ok(());
     }
 
     // Show what will be deleted
-    println!("Files to be deleted:");
-    for file_path in &files {
-        let file_type = if file_path.is_file() {
-            "file"
-        } else if file_path.is_dir() {
-            "directory"
-        } else {
-            "other"
-        };
+    if !output::json() {
+        println!("Files to be deleted:");
+        for file_path in &files {
+            let file_type = if file_path.is_file() {
+                "file"
+            } else if file_path.is_dir() {
+                "directory"
+            } else {
+                "other"
+            };
+
+            if preview {
+                println!(
+                    "{} [{}] {}",
+                    "[PREVIEW]".blue().bold(),
+                    file_type,
+                    file_path.display()
+                );
+            } else {
+                println!("{} {}", file_type, file_path.display());
+            }
+        }
 
         if preview {
             println!(
-                "{} [{}] {}",
+                "\n{} {} files would be deleted.",
                 "[PREVIEW]".blue().bold(),
-                file_type,
-                file_path.display()
+                files.len()
             );
-        } else {
-            println!("{} {}", file_type, file_path.display());
+            println!("Use without -p flag to actually delete these files.");
+            return Ok(());
         }
     }
 
-    if preview {
-        println!(
-            "\n{} {} files would be deleted.",
-            "[PREVIEW]".blue().bold(),
-            files.len()
-        );
-        println!("Use without -p flag to actually delete these files.");
-        return Ok(());
-    }
+    check_dir_rules(args, &files, None)?;
 
     // Safety check - require confirmation for dangerous operations
-    if !force && !ask_for_confirmation(&files)? {
+    if !force && output::json() {
+        return Err("--json cannot ask before removing; add -f to remove without asking".into());
+    }
+    if !force && !ask_for_confirmation(&files, args.show)? {
         println!("Operation cancelled.");
         return Ok(());
     }
@@ -2687,11 +7223,27 @@ fn run_cnp_remove_command(cnp_command: &CnpCommand) -> Result<(), Box<dyn Error>
         dereference_symlinks: false,
         follow_symlinks: false,
         verbose: true,
+        layout: DestinationLayout::Flat,
+        progress: None,
+        trash: None,
+        one_file_system: cnp_command.flags.contains('x'),
+        on_error: args.on_error.unwrap_or_default(),
     };
 
     // Perform the removal
+    let trash = open_trash(args)?;
+    let config = FileOpConfig {
+        trash: trash.as_ref().map(|(_, trash)| Rc::clone(trash)),
+        ..config
+    };
     let stats = file_ops::remove_files(&files, &config)?;
+    record_trash(trash)?;
 
+    notify::set_summary(format!("{} removed, {} errors", stats.moved, stats.errors));
+    if output::json() {
+        output::stats(false, stats.totals());
+        return Ok(());
+    }
     println!("\n{}", "Results:".bold());
     println!("Files processed: {}", stats.processed);
     println!("Files removed: {}", stats.moved); // Using moved count for removed
@@ -2702,7 +7254,10 @@ fn run_cnp_remove_command(cnp_command: &CnpCommand) -> Result<(), Box<dyn Error>
 }
 
 /// Ask for user confirmation before deleting files
-fn ask_for_confirmation(files: &[PathBuf]) -> Result<bool, Box<dyn Error>> {
+/// Largest files listed before an rm is confirmed, unless `--show` says otherwise
+const DEFAULT_SHOW_LARGEST: usize = 5;
+
+fn ask_for_confirmation(files: &[PathBuf], show: usize) -> Result<bool, Box<dyn Error>> {
     use std::io::{self, Write};
 
     if files.len() > 10 {
@@ -2718,6 +7273,7 @@ fn ask_for_confirmation(files: &[PathBuf]) -> Result<bool, Box<dyn Error>> {
             files.len()
         );
     }
+    disk_usage::RemovalSummary::new(files, show).print();
 
     print!("Are you sure you want to continue? (type 'yes' to confirm): ");
     io::stdout().flush()?;
@@ -2731,7 +7287,25 @@ fn ask_for_confirmation(files: &[PathBuf]) -> Result<bool, Box<dyn Error>> {
 
 /// Parse date strings like "2024-01-01", "2023-12-25" into SystemTime
 fn parse_date_string(date_str: &str) -> Result<std::time::SystemTime, Box<dyn Error>> {
-    use std::time::{Duration, UNIX_EPOCH};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    // Relative ages (30m, 12h, 7d, 2w, 1y) count back from now
+    if let Some(unit) = date_str.chars().last().filter(char::is_ascii_alphabetic)
+        && let Ok(amount) = date_str[..date_str.len() - 1].parse::<u64>()
+    {
+        let seconds = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            'y' => 365 * 24 * 60 * 60,
+            _ => return Err(format!("Unknown age unit '{unit}' (use s, m, h, d, w or y)").into()),
+        };
+        return SystemTime::now()
+            .checked_sub(Duration::from_secs(amount.saturating_mul(seconds)))
+            .ok_or_else(|| format!("Age too large: {date_str}").into());
+    }
 
     // Simple date parsing for YYYY-MM-DD format
     let parts: Vec<&str> = date_str.split('-').collect();
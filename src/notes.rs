@@ -0,0 +1,150 @@
+//! Note vault helpers
+//! Builds Obsidian/Zettelkasten style names: `YYYY-MM-DD-kebab-title.md`
+
+use chrono::NaiveDate;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashSet;
+
+use crate::transformers::{TransformType, transform};
+
+// Dates (2024-03-01, 2024_03_01) or Zettelkasten IDs (202403011230) already at the start of a name
+static DATE_PREFIX_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\d{4}[-_]\d{2}[-_]\d{2}|\d{12,14})(?:[\s_-]+|$)").unwrap());
+
+/// Format used for date prefixes added to notes
+pub const NOTE_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Turn a free-form title into a lowercase kebab-case slug
+///
+/// Punctuation is dropped and accented characters are transliterated, so
+/// `"Café: Ideas & Plans!"` becomes `"cafe-ideas-plans"`.
+pub fn slugify(title: &str) -> String {
+    let cleaned = transform(title, &TransformType::Clean).replace('.', " ");
+    transform(&cleaned, &TransformType::Kebab)
+        .trim_matches('-')
+        .to_string()
+}
+
+/// Build the vault name for a note
+///
+/// An existing date or Zettelkasten ID prefix is kept as-is; otherwise `date`
+/// is prepended. The extension is lowercased.
+pub fn note_file_name(file_name: &str, date: NaiveDate) -> String {
+    let (stem, extension) = match file_name.rfind('.') {
+        Some(pos) if pos > 0 => (&file_name[..pos], Some(&file_name[pos + 1..])),
+        _ => (file_name, None),
+    };
+
+    let (prefix, title) = match DATE_PREFIX_RE.captures(stem) {
+        Some(caps) => {
+            let matched = caps.get(0).map(|m| m.end()).unwrap_or(0);
+            (caps[1].replace('_', "-"), &stem[matched..])
+        }
+        None => (date.format(NOTE_DATE_FORMAT).to_string(), stem),
+    };
+
+    let slug = slugify(title);
+    let base = if slug.is_empty() {
+        prefix
+    } else {
+        format!("{prefix}-{slug}")
+    };
+
+    match extension {
+        Some(ext) => format!("{base}.{}", ext.to_lowercase()),
+        None => base,
+    }
+}
+
+/// Short, stable identifier derived from `seed` (FNV-1a, hex encoded)
+pub fn short_id(seed: &str, length: usize) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in seed.as_bytes() {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    let hex = format!("{hash:016x}");
+    hex[..length.min(hex.len())].to_string()
+}
+
+/// Make `candidate` unique among `taken` by appending a short ID before the extension
+///
+/// `seed` should identify the note (its original path works well) so that
+/// re-running the command produces the same name.
+pub fn unique_name(candidate: &str, seed: &str, taken: &HashSet<String>) -> String {
+    if !taken.contains(candidate) {
+        return candidate.to_string();
+    }
+
+    let (base, extension) = match candidate.rfind('.') {
+        Some(pos) if pos > 0 => (&candidate[..pos], &candidate[pos..]),
+        _ => (candidate, ""),
+    };
+
+    for length in [4, 6, 8, 12, 16] {
+        let name = format!("{base}-{}{extension}", short_id(seed, length));
+        if !taken.contains(&name) {
+            return name;
+        }
+    }
+
+    let mut counter = 2;
+    loop {
+        let name = format!("{base}-{}-{counter}{extension}", short_id(seed, 16));
+        if !taken.contains(&name) {
+            return name;
+        }
+        counter += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Café: Ideas & Plans!"), "cafe-ideas-plans");
+        assert_eq!(slugify("  Version 1.2 notes "), "version-1-2-notes");
+    }
+
+    #[test]
+    fn test_note_file_name_adds_date_prefix() {
+        assert_eq!(
+            note_file_name("My Great Idea.MD", date()),
+            "2024-03-01-my-great-idea.md"
+        );
+    }
+
+    #[test]
+    fn test_note_file_name_keeps_existing_prefix() {
+        assert_eq!(
+            note_file_name("2023_12_24 Christmas List.md", date()),
+            "2023-12-24-christmas-list.md"
+        );
+        assert_eq!(
+            note_file_name("202401011200 Zettel.md", date()),
+            "202401011200-zettel.md"
+        );
+        assert_eq!(
+            note_file_name("2024-03-01-already-done.md", date()),
+            "2024-03-01-already-done.md"
+        );
+    }
+
+    #[test]
+    fn test_unique_name_appends_short_id() {
+        let mut taken = HashSet::new();
+        assert_eq!(unique_name("a.md", "seed", &taken), "a.md");
+
+        taken.insert("a.md".to_string());
+        let unique = unique_name("a.md", "seed", &taken);
+        assert_eq!(unique, format!("a-{}.md", short_id("seed", 4)));
+        assert_eq!(unique_name("a.md", "seed", &taken), unique);
+    }
+}
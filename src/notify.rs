@@ -0,0 +1,116 @@
+//! Desktop notifications when a long batch finishes
+//!
+//! Runs that take longer than `--notify-after` seconds end with a desktop
+//! notification carrying the summary, so a big reorganization can run while
+//! you work elsewhere. `--notify` notifies after every run, `--no-notify` never.
+//! Notifications go through `notify-send` (`osascript` on macOS); without one
+//! installed nothing happens.
+
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Seconds a run must take before it notifies by default
+pub const DEFAULT_NOTIFY_AFTER: u64 = 60;
+
+/// Summary of the finished batch, set by whichever command ran
+static SUMMARY: Mutex<Option<String>> = Mutex::new(None);
+
+/// When to notify
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// After runs longer than the threshold
+    Long(Duration),
+    Always,
+    Never,
+}
+
+impl Mode {
+    pub fn from_flags(notify: bool, no_notify: bool, after_secs: u64) -> Self {
+        if no_notify {
+            Mode::Never
+        } else if notify {
+            Mode::Always
+        } else {
+            Mode::Long(Duration::from_secs(after_secs))
+        }
+    }
+
+    pub fn should_notify(&self, elapsed: Duration) -> bool {
+        match self {
+            Mode::Long(threshold) => elapsed >= *threshold,
+            Mode::Always => true,
+            Mode::Never => false,
+        }
+    }
+}
+
+/// Remember the summary of the batch that just ran ("Renamed 120, errors 2")
+pub fn set_summary(summary: String) {
+    if let Ok(mut slot) = SUMMARY.lock() {
+        *slot = Some(summary);
+    }
+}
+
+/// Notify about a finished run if `mode` asks for it
+///
+/// `failure` is the error the run ended with, if any. Runs that recorded no
+/// summary (help, previews, listings) stay quiet unless they failed.
+pub fn finish(mode: Mode, elapsed: Duration, failure: Option<&str>) {
+    if !mode.should_notify(elapsed) {
+        return;
+    }
+    let summary = SUMMARY.lock().ok().and_then(|mut slot| slot.take());
+    let (title, body) = match (failure, summary) {
+        (Some(error), _) => ("smv failed", error.to_string()),
+        (None, Some(summary)) => ("smv finished", summary),
+        (None, None) => return,
+    };
+    let body = format!("{} ({})", body, crate::progress::format_elapsed(elapsed));
+    let _ = send(title, &body);
+}
+
+#[cfg(target_os = "macos")]
+fn send(title: &str, body: &str) -> std::io::Result<()> {
+    let script = format!(
+        "display notification {} with title {}",
+        applescript_string(body),
+        applescript_string(title)
+    );
+    Command::new("osascript")
+        .args(["-e", &script])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|_| ())
+}
+
+#[cfg(target_os = "macos")]
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(not(target_os = "macos"))]
+fn send(title: &str, body: &str) -> std::io::Result<()> {
+    Command::new("notify-send")
+        .args(["--app-name=smv", title, body])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode() {
+        let long = Mode::from_flags(false, false, 60);
+        assert!(!long.should_notify(Duration::from_secs(59)));
+        assert!(long.should_notify(Duration::from_secs(60)));
+        assert!(Mode::from_flags(true, false, 60).should_notify(Duration::ZERO));
+        // --no-notify wins over --notify
+        assert_eq!(Mode::from_flags(true, true, 0), Mode::Never);
+    }
+}
@@ -0,0 +1,215 @@
+//! Machine-readable reports of what a command plans and does (`--json`)
+//!
+//! With `--json` (or its alias `--output json`), or a `FORMAT:json` route on a
+//! CNP command, transforms, mv, cp, rm, mkdir, touch and plans print no prose.
+//! Stdout carries one JSON object per line instead, told apart by `event`:
+//!
+//! - `planned`: an operation a preview would carry out
+//! - `done`: an operation carried out
+//! - `conflict`: an operation left out because of what is at its destination,
+//!   with a stable `code` (`TARGET_EXISTS`, `SOURCE_MISSING`, ...) and a `reason`
+//! - `skipped`: an item left as it was, with the reason
+//! - `error`: an operation that failed, with the error
+//! - `stats`: the totals, always the last line
+//!
+//! Every record but `stats` has an `op` (`rename`, `copy`, `move`, `remove`,
+//! `mkdir`, `touch` or `edit`) and the paths involved as `from` and `to`. Planned ops
+//! that go ahead despite a problem, such as crossing filesystems, list its
+//! codes under `warnings`. An error that ends the whole command still goes to
+//! stderr, and progress events go there too.
+
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::plan::{Conflict, OpKind};
+
+/// Whether this run reports in JSON, set once from the command line
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// What an operation does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    Rename,
+    Copy,
+    Move,
+    Remove,
+    Mkdir,
+    Touch,
+    /// A file rewritten in place, such as the links `--update-links` fixes
+    Edit,
+}
+
+impl From<OpKind> for Op {
+    fn from(kind: OpKind) -> Self {
+        match kind {
+            OpKind::Move => Op::Move,
+            OpKind::Copy => Op::Copy,
+        }
+    }
+}
+
+/// The numbers of the final `stats` record
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Totals {
+    pub processed: u32,
+    /// Operations carried out, or planned in a preview
+    pub done: u32,
+    pub skipped: u32,
+    pub errors: u32,
+}
+
+/// Report in JSON from here on
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether reports are JSON, and prose should stay unprinted
+pub fn json() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// One per-operation record
+pub fn record(
+    event: &str,
+    op: Op,
+    from: Option<&Path>,
+    to: Option<&Path>,
+    reason: Option<&str>,
+) -> Value {
+    let mut record = json!({ "event": event, "op": op });
+    if let Some(from) = from {
+        record["from"] = json!(from.to_string_lossy());
+    }
+    if let Some(to) = to {
+        record["to"] = json!(to.to_string_lossy());
+    }
+    if let Some(reason) = reason {
+        record["reason"] = json!(reason);
+    }
+    record
+}
+
+fn emit(record: Value) {
+    if json() {
+        println!("{record}");
+    }
+}
+
+pub fn planned(op: Op, from: Option<&Path>, to: Option<&Path>) {
+    emit(record("planned", op, from, to, None));
+}
+
+pub fn done(op: Op, from: Option<&Path>, to: Option<&Path>) {
+    emit(record("done", op, from, to, None));
+}
+
+pub fn conflict(op: Op, from: &Path, to: &Path, conflict: Conflict) {
+    emit(conflict_record(op, from, to, conflict));
+}
+
+fn conflict_record(op: Op, from: &Path, to: &Path, conflict: Conflict) -> Value {
+    let mut record = record(
+        "conflict",
+        op,
+        Some(from),
+        Some(to),
+        Some(&conflict.to_string()),
+    );
+    record["code"] = json!(conflict.code());
+    record
+}
+
+/// What a preview finds for one op: the conflict that stops it, or the op as
+/// planned with the problems that do not
+pub fn preview(op: Op, from: &Path, to: &Path, conflicts: &[Conflict]) {
+    emit(preview_record(op, from, to, conflicts));
+}
+
+fn preview_record(op: Op, from: &Path, to: &Path, conflicts: &[Conflict]) -> Value {
+    if let Some(blocking) = conflicts.iter().find(|conflict| conflict.is_blocking()) {
+        return conflict_record(op, from, to, *blocking);
+    }
+    let mut record = record("planned", op, Some(from), Some(to), None);
+    if !conflicts.is_empty() {
+        record["warnings"] = conflicts.iter().map(Conflict::code).collect();
+    }
+    record
+}
+
+pub fn skipped(op: Op, path: &Path, reason: &str) {
+    emit(record("skipped", op, Some(path), None, Some(reason)));
+}
+
+pub fn failed(op: Op, from: Option<&Path>, to: Option<&Path>, error: &str) {
+    emit(record("error", op, from, to, Some(error)));
+}
+
+/// The closing record, with `preview` telling planned totals from performed ones
+pub fn stats(preview: bool, totals: Totals) {
+    stats_with(preview, totals, serde_json::Map::new());
+}
+
+/// The closing record with more the command counted, such as why items were skipped
+pub fn stats_with(preview: bool, totals: Totals, details: serde_json::Map<String, Value>) {
+    let mut record = json!({ "event": "stats", "preview": preview });
+    if let (Value::Object(record), Ok(Value::Object(totals))) =
+        (&mut record, serde_json::to_value(totals))
+    {
+        record.extend(totals);
+        record.extend(details);
+    }
+    emit(record);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records() {
+        let moved = record(
+            "done",
+            Op::Move,
+            Some(Path::new("a.txt")),
+            Some(Path::new("b/a.txt")),
+            None,
+        );
+        assert_eq!(
+            moved.to_string(),
+            r#"{"event":"done","from":"a.txt","op":"move","to":"b/a.txt"}"#
+        );
+        let created = record("planned", Op::Mkdir, None, Some(Path::new("new")), None);
+        assert!(created.get("from").is_none());
+        let conflict = conflict_record(
+            Op::Rename,
+            Path::new("A"),
+            Path::new("a"),
+            Conflict::DestinationExists,
+        );
+        assert_eq!(conflict["event"], "conflict");
+        assert_eq!(conflict["code"], "TARGET_EXISTS");
+        assert_eq!(
+            conflict["reason"],
+            "destination exists (use -F to overwrite)"
+        );
+
+        let (from, to) = (Path::new("a"), Path::new("/mnt/a"));
+        let planned = preview_record(Op::Move, from, to, &[Conflict::CrossDevice]);
+        assert_eq!(planned["event"], "planned");
+        assert_eq!(planned["warnings"], json!(["CROSS_DEVICE"]));
+        let stopped = preview_record(
+            Op::Move,
+            from,
+            to,
+            &[Conflict::CrossDevice, Conflict::DestinationExists],
+        );
+        assert_eq!(stopped["code"], "TARGET_EXISTS");
+        assert_eq!(
+            serde_json::to_value(Totals::default()).unwrap(),
+            json!({ "processed": 0, "done": 0, "skipped": 0, "errors": 0 })
+        );
+    }
+}
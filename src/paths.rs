@@ -0,0 +1,114 @@
+//! Where SMV keeps its state (undo history, bulk-move mappings and backups)
+//!
+//! The directory is the first of:
+//! 1. `--state-dir DIR`
+//! 2. `SMV_STATE_DIR`
+//! 3. `$SMV_HOME/state`
+//! 4. `~/.config/smv/backups`, when it already exists: history written there by
+//!    older versions refers to its backups by absolute path
+//! 5. `$XDG_STATE_HOME/smv`, `$XDG_DATA_HOME/smv`, then `~/.local/state/smv`
+
+use once_cell::sync::OnceCell;
+use std::path::{Path, PathBuf};
+
+static STATE_DIR_OVERRIDE: OnceCell<PathBuf> = OnceCell::new();
+
+/// Use `dir` instead of the resolved state directory for the rest of the run
+pub fn set_state_dir(dir: PathBuf) {
+    let _ = STATE_DIR_OVERRIDE.set(dir);
+}
+
+/// The state directory for this run
+pub fn state_dir() -> PathBuf {
+    if let Some(dir) = STATE_DIR_OVERRIDE.get() {
+        return dir.clone();
+    }
+    resolve_state_dir(
+        &|name| {
+            std::env::var_os(name)
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from)
+        },
+        dirs::home_dir().as_deref(),
+    )
+}
+
+/// Resolve the state directory from environment variables and the home directory
+pub fn resolve_state_dir(env: &dyn Fn(&str) -> Option<PathBuf>, home: Option<&Path>) -> PathBuf {
+    if let Some(dir) = env("SMV_STATE_DIR") {
+        return dir;
+    }
+    if let Some(smv_home) = env("SMV_HOME") {
+        return smv_home.join("state");
+    }
+
+    let Some(home) = home else {
+        // Containers without a home directory still get a working history
+        return env("XDG_STATE_HOME")
+            .or_else(|| env("XDG_DATA_HOME"))
+            .map(|base| base.join("smv"))
+            .unwrap_or_else(|| std::env::temp_dir().join("smv"));
+    };
+
+    let legacy = home.join(".config").join("smv").join("backups");
+    if legacy.is_dir() {
+        return legacy;
+    }
+
+    env("XDG_STATE_HOME")
+        .or_else(|| env("XDG_DATA_HOME"))
+        .unwrap_or_else(|| home.join(".local").join("state"))
+        .join("smv")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn resolve(vars: &[(&str, &str)], home: &Path) -> PathBuf {
+        let vars: HashMap<String, PathBuf> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), PathBuf::from(value)))
+            .collect();
+        resolve_state_dir(&|name| vars.get(name).cloned(), Some(home))
+    }
+
+    #[test]
+    fn test_resolve_state_dir() {
+        let home = tempfile::tempdir().unwrap();
+        let home = home.path();
+
+        assert_eq!(resolve(&[], home), home.join(".local/state/smv"));
+        assert_eq!(
+            resolve(&[("XDG_DATA_HOME", "/data")], home),
+            PathBuf::from("/data/smv")
+        );
+        assert_eq!(
+            resolve(
+                &[("XDG_STATE_HOME", "/state"), ("XDG_DATA_HOME", "/data")],
+                home
+            ),
+            PathBuf::from("/state/smv")
+        );
+        assert_eq!(
+            resolve(
+                &[("SMV_HOME", "/opt/smv"), ("XDG_STATE_HOME", "/state")],
+                home
+            ),
+            PathBuf::from("/opt/smv/state")
+        );
+        assert_eq!(
+            resolve(
+                &[("SMV_STATE_DIR", "/ci/smv"), ("SMV_HOME", "/opt/smv")],
+                home
+            ),
+            PathBuf::from("/ci/smv")
+        );
+
+        // An existing pre-XDG directory keeps being used
+        let legacy = home.join(".config/smv/backups");
+        std::fs::create_dir_all(&legacy).unwrap();
+        assert_eq!(resolve(&[("XDG_STATE_HOME", "/state")], home), legacy);
+    }
+}
@@ -0,0 +1,116 @@
+//! Choosing matches by hand before an operation runs (`--pick`)
+//!
+//! The matched paths go through a fuzzy finder with multi-select (Tab marks,
+//! Enter accepts): `fzf` when it is installed, the built-in skim finder
+//! otherwise. Only the chosen paths go on to build the plan, so filters can
+//! stay loose and the last narrowing is done by eye; aborting picks nothing.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::io::{self, Cursor, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use skim::prelude::*;
+
+use crate::config;
+
+const PROMPT: &str = "pick> ";
+const HEADER: &str = "Tab marks files, Enter runs on the marked ones, Esc cancels";
+
+/// Let the user choose some of `files`, in their original order
+pub fn pick(files: Vec<PathBuf>) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if files.is_empty() {
+        return Ok(files);
+    }
+    let input = lines(&files);
+    // fzf is started like any other delegated tool; without it skim does the job
+    let tools = config::Config::load()?.tools;
+    let use_fzf = tools.delegation
+        && (tools.paths.contains_key("fzf") || config::find_in_path("fzf").is_some());
+    let selected = if use_fzf {
+        with_fzf(tools.command("fzf")?, &input)?
+    } else {
+        with_skim(&input)
+    };
+    Ok(chosen(files, &selected))
+}
+
+/// One line per path, the way the finders display them
+fn lines(files: &[PathBuf]) -> String {
+    files
+        .iter()
+        .map(|file| file.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The paths whose lines were selected, keeping the order of `files`
+fn chosen(files: Vec<PathBuf>, selected: &[String]) -> Vec<PathBuf> {
+    let selected: HashSet<&str> = selected.iter().map(String::as_str).collect();
+    files
+        .into_iter()
+        .filter(|file| selected.contains(file.to_string_lossy().as_ref()))
+        .collect()
+}
+
+fn with_fzf(mut fzf: Command, input: &str) -> io::Result<Vec<String>> {
+    let mut child = fzf
+        .args(["--multi", "--prompt", PROMPT, "--header", HEADER])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // fzf may exit before reading everything; what it read is what it shows
+        let _ = stdin.write_all(input.as_bytes());
+    }
+    let mut output = String::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        stdout.read_to_string(&mut output)?;
+    }
+    // 1 means nothing matched the query, 130 that the user cancelled
+    match child.wait()?.code() {
+        Some(0) => Ok(output.lines().map(str::to_string).collect()),
+        Some(1) | Some(130) => Ok(Vec::new()),
+        code => Err(io::Error::other(format!("fzf failed (exit code {code:?})"))),
+    }
+}
+
+fn with_skim(input: &str) -> Vec<String> {
+    let options = SkimOptionsBuilder::default()
+        .multi(true)
+        .prompt(Some(PROMPT))
+        .header(Some(HEADER))
+        .build()
+        .unwrap();
+    let items = SkimItemReader::default().of_bufread(Cursor::new(input.to_string()));
+    match Skim::run_with(&options, Some(items)) {
+        Some(output) if !output.is_abort => output
+            .selected_items
+            .iter()
+            .map(|item| item.output().to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chosen_keeps_order() {
+        let files = vec![
+            PathBuf::from("a/one.txt"),
+            PathBuf::from("b/two.txt"),
+            PathBuf::from("c/three.txt"),
+        ];
+        assert_eq!(lines(&files), "a/one.txt\nb/two.txt\nc/three.txt");
+
+        let selected = vec!["c/three.txt".to_string(), "a/one.txt".to_string()];
+        assert_eq!(
+            chosen(files, &selected),
+            [PathBuf::from("a/one.txt"), PathBuf::from("c/three.txt")]
+        );
+    }
+}
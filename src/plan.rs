@@ -0,0 +1,980 @@
+//! Move/rename plans piped in by other tools (`smv --stdin-ops`) or saved with
+//! `--plan` for `smv apply-plan`
+//!
+//! Four formats are accepted:
+//! - JSON: an array of ops, or one op object per line:
+//!   `{"op": "move", "from": "a.txt", "to": "b.txt"}` (`op` defaults to `move`)
+//! - YAML: a list of the same ops (`- op: move`, `from: ...`, `to: ...`)
+//! - CSV with an `operation,source,destination` header, as inventory systems
+//!   export them; the columns may come in any order and `operation` may be left out
+//! - Plain text: one `source<TAB>destination` or `source -> destination` pair per line
+//!
+//! Blank lines and lines starting with `#` are ignored in the text and CSV formats.
+//! [`to_script`], [`to_yaml`] and [`to_json`] write plans back out, for the TUI's
+//! queue export and `--plan`.
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::disk_usage::device_of;
+use crate::protect::Protection;
+use crate::report::{Outcome, Reporter};
+
+/// Longest file name most filesystems accept, in bytes
+pub const NAME_MAX_BYTES: usize = 255;
+/// Longest path Linux accepts, in bytes
+pub const PATH_MAX_BYTES: usize = 4096;
+
+/// Name limits already asked for, by existing directory
+static NAME_LIMITS: Lazy<Mutex<HashMap<PathBuf, usize>>> = Lazy::new(Mutex::default);
+
+/// What to do with a planned pair of paths
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OpKind {
+    #[default]
+    #[serde(alias = "rename", alias = "mv")]
+    Move,
+    #[serde(alias = "cp")]
+    Copy,
+}
+
+impl OpKind {
+    /// Read an op name as the plan formats write it; empty means `move`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "" | "move" | "rename" | "mv" => Some(OpKind::Move),
+            "copy" | "cp" => Some(OpKind::Copy),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OpKind::Move => "Move",
+            OpKind::Copy => "Copy",
+        }
+    }
+}
+
+/// One operation of a plan
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedOp {
+    #[serde(default)]
+    pub op: OpKind,
+    #[serde(alias = "source", alias = "src")]
+    pub from: PathBuf,
+    #[serde(alias = "destination", alias = "dst")]
+    pub to: PathBuf,
+}
+
+/// A plan line that could not be understood
+#[derive(Debug)]
+pub struct PlanError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Plan error on line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for PlanError {}
+
+/// Parse a plan in any of the supported formats
+pub fn parse_plan(input: &str) -> Result<Vec<PlannedOp>, PlanError> {
+    let trimmed = input.trim_start();
+
+    if trimmed.starts_with('[') {
+        return serde_json::from_str(trimmed).map_err(|e| PlanError {
+            line: e.line(),
+            message: e.to_string(),
+        });
+    }
+
+    if is_yaml_list(input) {
+        return serde_yaml::from_str(input).map_err(|e| PlanError {
+            line: e.location().map_or(0, |location| location.line()),
+            message: e.to_string(),
+        });
+    }
+
+    if let Some((header_line, columns)) = csv_header(input) {
+        return parse_csv(input, header_line, columns);
+    }
+
+    let mut ops = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        let op = if line.trim_start().starts_with('{') {
+            serde_json::from_str(line).map_err(|e| PlanError {
+                line: line_number,
+                message: e.to_string(),
+            })?
+        } else {
+            parse_text_line(line).ok_or_else(|| PlanError {
+                line: line_number,
+                message: format!(
+                    "expected 'source<TAB>destination' or 'source -> destination', got '{line}'"
+                ),
+            })?
+        };
+        ops.push(op);
+    }
+
+    Ok(ops)
+}
+
+/// Whether the first line that is not blank or a comment starts a YAML list
+fn is_yaml_list(input: &str) -> bool {
+    input
+        .lines()
+        .map(str::trim_start)
+        .find(|line| !line.is_empty() && !line.starts_with('#') && *line != "---")
+        .is_some_and(|line| line.starts_with("- "))
+}
+
+/// Where the operation, source and destination are in each CSV row
+#[derive(Debug, Clone, Copy)]
+struct CsvColumns {
+    operation: Option<usize>,
+    source: usize,
+    destination: usize,
+}
+
+/// The line number and columns of a CSV header, when the first line that is
+/// not blank or a comment is one
+fn csv_header(input: &str) -> Option<(usize, CsvColumns)> {
+    let (index, line) = input
+        .lines()
+        .enumerate()
+        .find(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))?;
+    let names = split_csv_record(line.trim_end_matches('\r'))?;
+    let position = |known: &[&str]| {
+        names
+            .iter()
+            .position(|name| known.contains(&name.trim().to_lowercase().as_str()))
+    };
+    let columns = CsvColumns {
+        operation: position(&["operation", "op", "action"]),
+        source: position(&["source", "src", "from"])?,
+        destination: position(&["destination", "dest", "dst", "to"])?,
+    };
+    Some((index + 1, columns))
+}
+
+fn parse_csv(
+    input: &str,
+    header_line: usize,
+    columns: CsvColumns,
+) -> Result<Vec<PlannedOp>, PlanError> {
+    let mut ops = Vec::new();
+    for (index, line) in input.lines().enumerate().skip(header_line) {
+        let line_number = index + 1;
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let error = |message: String| PlanError {
+            line: line_number,
+            message,
+        };
+        let fields =
+            split_csv_record(line).ok_or_else(|| error(String::from("unterminated quote")))?;
+        let field = |column: usize| fields.get(column).map_or("", |field| field.as_str());
+        let name = columns.operation.map_or("", field);
+        let op = OpKind::parse(name).ok_or_else(|| {
+            error(format!(
+                "unknown operation '{name}' (expected move or copy)"
+            ))
+        })?;
+        let (from, to) = (field(columns.source), field(columns.destination));
+        if from.is_empty() || to.is_empty() {
+            return Err(error(format!(
+                "expected a source and a destination, got '{line}'"
+            )));
+        }
+        ops.push(PlannedOp {
+            op,
+            from: PathBuf::from(from),
+            to: PathBuf::from(to),
+        });
+    }
+    Ok(ops)
+}
+
+/// The fields of one CSV line: commas separate them, double quotes enclose
+/// fields holding commas, and `""` inside quotes is a quote. `None` when a
+/// quote is left open.
+fn split_csv_record(line: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    fields.push(field);
+    Some(fields)
+}
+
+fn parse_text_line(line: &str) -> Option<PlannedOp> {
+    let (from, to) = line.split_once('\t').or_else(|| line.split_once(" -> "))?;
+    let (from, to) = (from.trim(), to.trim());
+    if from.is_empty() || to.is_empty() {
+        return None;
+    }
+    Some(PlannedOp {
+        op: OpKind::Move,
+        from: PathBuf::from(from),
+        to: PathBuf::from(to),
+    })
+}
+
+/// Why a planned operation will not be applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    MissingSource,
+    SamePath,
+    DestinationExists,
+    /// An earlier op in the plan already targets this destination
+    DuplicateDestination,
+    /// The destination differs only in letter case from an existing file or an
+    /// earlier destination, which is the same file on case-insensitive filesystems
+    CaseCollision,
+    /// The destination's name or full path is longer than filesystems allow
+    NameTooLong,
+    /// Source and destination are on different filesystems; the move still happens,
+    /// as a copy followed by removing the source
+    CrossDevice,
+    /// The source is listed in a `.smvignore` or marked NORENAME
+    Protected,
+}
+
+impl Conflict {
+    /// Stable code for JSON output
+    pub fn code(&self) -> &'static str {
+        match self {
+            Conflict::MissingSource => "SOURCE_MISSING",
+            Conflict::SamePath => "SAME_PATH",
+            Conflict::DestinationExists => "TARGET_EXISTS",
+            Conflict::DuplicateDestination => "DUPLICATE_TARGET",
+            Conflict::CaseCollision => "CASE_INSENSITIVE_COLLISION",
+            Conflict::NameTooLong => "NAME_TOO_LONG",
+            Conflict::CrossDevice => "CROSS_DEVICE",
+            Conflict::Protected => "PROTECTED",
+        }
+    }
+
+    /// Whether the op is skipped; cross-device moves go ahead
+    pub fn is_blocking(&self) -> bool {
+        *self != Conflict::CrossDevice
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Conflict::MissingSource => "source does not exist",
+            Conflict::SamePath => "source and destination are the same",
+            Conflict::DestinationExists => "destination exists (use -F to overwrite)",
+            Conflict::DuplicateDestination => "destination already used earlier in the plan",
+            Conflict::CaseCollision => "destination differs only in letter case from another file",
+            Conflict::NameTooLong => "destination name has more bytes than the filesystem takes",
+            Conflict::CrossDevice => "crosses filesystems (copied, then the source is removed)",
+            Conflict::Protected => "protected by a .smvignore or a NORENAME mark",
+        };
+        f.write_str(text)
+    }
+}
+
+/// Checks ops in plan order, remembering which destinations are taken
+#[derive(Debug, Default)]
+pub struct ConflictChecker {
+    overwrite: bool,
+    claimed: HashSet<PathBuf>,
+    /// Claimed destinations in lowercase, to catch case-only differences
+    claimed_folded: HashSet<PathBuf>,
+    /// Lowercased names in each destination directory, read once
+    listings: HashMap<PathBuf, HashMap<String, PathBuf>>,
+    protection: Protection,
+}
+
+impl ConflictChecker {
+    pub fn new(overwrite: bool) -> Self {
+        Self {
+            overwrite,
+            ..Self::default()
+        }
+    }
+
+    /// Return the conflict for `op`, or claim its destination
+    pub fn check(&mut self, op: &PlannedOp) -> Option<Conflict> {
+        if op.from.symlink_metadata().is_err() {
+            return Some(Conflict::MissingSource);
+        }
+        // Copies leave the source as it is
+        if op.op == OpKind::Move && self.protection.reason(&op.from).is_some() {
+            return Some(Conflict::Protected);
+        }
+        if op.from == op.to {
+            return Some(Conflict::SamePath);
+        }
+        if name_too_long(&op.to, name_max(parent_dir(&op.to))) {
+            return Some(Conflict::NameTooLong);
+        }
+        if self.claimed.contains(&op.to) {
+            return Some(Conflict::DuplicateDestination);
+        }
+        if self.claimed_folded.contains(&fold_case(&op.to)) {
+            return Some(Conflict::CaseCollision);
+        }
+        if !self.overwrite {
+            // A case-only rename finds the source itself on case-insensitive filesystems
+            if exists(&op.to) && !same_file(&op.from, &op.to) {
+                return Some(Conflict::DestinationExists);
+            }
+            if self.case_variant_exists(op) {
+                return Some(Conflict::CaseCollision);
+            }
+        }
+        self.claimed_folded.insert(fold_case(&op.to));
+        self.claimed.insert(op.to.clone());
+        None
+    }
+
+    /// Problems that do not stop `op`, for reports
+    pub fn warnings(&self, op: &PlannedOp) -> Vec<Conflict> {
+        let destination_dir = match op.to.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        match (device_of(&op.from), device_of(destination_dir)) {
+            (Some(source), Some(destination)) if op.op == OpKind::Move && source != destination => {
+                vec![Conflict::CrossDevice]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether the destination directory holds a name equal to the destination's
+    /// except for letter case (other than the source being renamed)
+    fn case_variant_exists(&mut self, op: &PlannedOp) -> bool {
+        let (Some(dir), Some(name)) = (op.to.parent(), op.to.file_name()) else {
+            return false;
+        };
+        let listing = self.listings.entry(dir.to_path_buf()).or_insert_with(|| {
+            let read_from = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            std::fs::read_dir(read_from)
+                .into_iter()
+                .flatten()
+                .filter_map(Result::ok)
+                .map(|entry| {
+                    let name = entry.file_name().to_string_lossy().to_lowercase();
+                    (name, dir.join(entry.file_name()))
+                })
+                .collect()
+        });
+        match listing.get(&name.to_string_lossy().to_lowercase()) {
+            Some(existing) => existing != &op.to && !same_file(existing, &op.from),
+            None => false,
+        }
+    }
+}
+
+/// Check a whole plan before anything runs: the index of every op that would be
+/// skipped, with why
+pub fn validate(ops: &[PlannedOp], overwrite: bool) -> Vec<(usize, Conflict)> {
+    let mut checker = ConflictChecker::new(overwrite);
+    ops.iter()
+        .enumerate()
+        .filter_map(|(index, op)| checker.check(op).map(|conflict| (index, conflict)))
+        .collect()
+}
+
+fn exists(path: &Path) -> bool {
+    path.symlink_metadata().is_ok()
+}
+
+fn fold_case(path: &Path) -> PathBuf {
+    PathBuf::from(path.to_string_lossy().to_lowercase())
+}
+
+fn name_too_long(path: &Path, name_max: usize) -> bool {
+    let name_len = path
+        .file_name()
+        .map_or(0, |name| name.as_encoded_bytes().len());
+    name_len > name_max || path.as_os_str().len() > PATH_MAX_BYTES
+}
+
+/// Longest file name, in bytes, the filesystem holding `dir` takes
+///
+/// Limits count bytes, not characters: 100 `é`s are 200 bytes. A directory
+/// that does not exist yet is answered for its nearest existing ancestor, and
+/// [`NAME_MAX_BYTES`] stands in where the filesystem does not tell.
+pub fn name_max(dir: &Path) -> usize {
+    let dir = existing_ancestor(dir);
+    let mut limits = NAME_LIMITS.lock().unwrap_or_else(|e| e.into_inner());
+    *limits
+        .entry(dir)
+        .or_insert_with_key(|dir| probe_name_max(dir).unwrap_or(NAME_MAX_BYTES))
+}
+
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)] // field widths differ between platforms
+fn probe_name_max(dir: &Path) -> Option<usize> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_dir = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_dir.as_ptr(), &mut stat) } != 0 || stat.f_namemax == 0 {
+        return None;
+    }
+    usize::try_from(stat.f_namemax as u64).ok()
+}
+
+#[cfg(not(unix))]
+fn probe_name_max(_dir: &Path) -> Option<usize> {
+    None
+}
+
+/// `sources` with repeats dropped, keeping the first spelling of each
+///
+/// `./a.txt`, `a.txt` and `docs/../a.txt` are the same source: paths are
+/// compared with their parent directory canonicalized, so a symlink and the file
+/// it points to stay two different sources.
+pub fn dedupe_sources(sources: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    sources
+        .into_iter()
+        .filter(|source| seen.insert(source_key(source)))
+        .collect()
+}
+
+fn source_key(path: &Path) -> PathBuf {
+    let lexical: PathBuf = path
+        .components()
+        .filter(|component| *component != std::path::Component::CurDir)
+        .collect();
+    let (Some(parent), Some(name)) = (lexical.parent(), lexical.file_name()) else {
+        return lexical;
+    };
+    let parent = if parent.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        parent
+    };
+    match parent.canonicalize() {
+        Ok(parent) => parent.join(name),
+        Err(_) => lexical,
+    }
+}
+
+/// A directory a plan has to change that will not allow it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Refusal {
+    pub dir: PathBuf,
+    pub reason: String,
+    /// How many ops of the plan need the directory
+    pub ops: usize,
+}
+
+/// Find the directories `ops` would fail in, before the first op runs
+///
+/// A move changes the directory it leaves and the one it enters, a copy only
+/// the one it enters (or its nearest existing ancestor, when the op creates
+/// it). Each directory is probed once: for write access, which also answers
+/// for read-only mounts and SELinux, and for immutable or append-only flags.
+pub fn preflight(ops: &[PlannedOp]) -> Vec<Refusal> {
+    let mut verdicts: HashMap<(PathBuf, bool), Option<String>> = HashMap::new();
+    let mut refused: BTreeMap<PathBuf, (String, usize)> = BTreeMap::new();
+    for op in ops {
+        let entering = existing_ancestor(parent_dir(&op.to));
+        let leaving = (op.op == OpKind::Move).then(|| parent_dir(&op.from).to_path_buf());
+        let mut seen = HashSet::new();
+        for (dir, is_leaving) in
+            std::iter::once((entering, false)).chain(leaving.map(|d| (d, true)))
+        {
+            let verdict = verdicts
+                .entry((dir.clone(), is_leaving))
+                .or_insert_with(|| refuses_changes(&dir, is_leaving));
+            if let Some(reason) = verdict
+                && seen.insert(dir.clone())
+            {
+                refused.entry(dir).or_insert_with(|| (reason.clone(), 0)).1 += 1;
+            }
+        }
+    }
+    refused
+        .into_iter()
+        .map(|(dir, (reason, ops))| Refusal { dir, reason, ops })
+        .collect()
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+fn existing_ancestor(dir: &Path) -> PathBuf {
+    dir.ancestors()
+        .map(|ancestor| {
+            if ancestor.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                ancestor
+            }
+        })
+        .find(|ancestor| ancestor.is_dir())
+        .unwrap_or(dir)
+        .to_path_buf()
+}
+
+/// Why entries of `dir` cannot be added, or when `leaving` renamed away, if so
+#[cfg(unix)]
+fn refuses_changes(dir: &Path, leaving: bool) -> Option<String> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    if unsafe { libc::access(c_dir.as_ptr(), libc::W_OK | libc::X_OK) } != 0 {
+        let e = std::io::Error::last_os_error();
+        return Some(match e.raw_os_error() {
+            Some(libc::EROFS) => String::from("read-only filesystem"),
+            Some(libc::EACCES | libc::EPERM) => String::from("no permission to change it"),
+            _ => e.to_string(),
+        });
+    }
+    let (immutable, append_only) = inode_flags(dir);
+    if immutable {
+        Some(String::from(
+            "marked immutable (chattr -i / chflags nouchg to allow changes)",
+        ))
+    } else if append_only && leaving {
+        Some(String::from(
+            "marked append-only: files can be added but not moved out",
+        ))
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+fn refuses_changes(_dir: &Path, _leaving: bool) -> Option<String> {
+    None
+}
+
+/// Whether `dir` is marked immutable and append-only (`chattr +i`, `+a`)
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn inode_flags(dir: &Path) -> (bool, bool) {
+    use std::os::fd::AsRawFd;
+
+    const FS_IMMUTABLE_FL: libc::c_int = 0x10;
+    const FS_APPEND_FL: libc::c_int = 0x20;
+    let Ok(handle) = std::fs::File::open(dir) else {
+        return (false, false);
+    };
+    let mut flags: libc::c_int = 0;
+    // Filesystems without inode flags refuse the request, and have none to mind
+    if unsafe { libc::ioctl(handle.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) } != 0 {
+        return (false, false);
+    }
+    (flags & FS_IMMUTABLE_FL != 0, flags & FS_APPEND_FL != 0)
+}
+
+/// Whether `dir` is marked immutable and append-only (`chflags uchg`, `uappnd`)
+#[cfg(target_os = "macos")]
+fn inode_flags(dir: &Path) -> (bool, bool) {
+    use std::os::macos::fs::MetadataExt;
+
+    let Ok(metadata) = dir.metadata() else {
+        return (false, false);
+    };
+    let flags = metadata.st_flags();
+    (
+        flags & (libc::UF_IMMUTABLE | libc::SF_IMMUTABLE) != 0,
+        flags & (libc::UF_APPEND | libc::SF_APPEND) != 0,
+    )
+}
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "linux", target_os = "android", target_os = "macos"))
+))]
+fn inode_flags(_dir: &Path) -> (bool, bool) {
+    (false, false)
+}
+
+/// Run planned renames in order, telling `reporter` what became of each
+///
+/// Files that disappeared since the plan was made are skipped, and so are those
+/// whose new name is taken unless `overwrite` is set. Returns the renames that
+/// happened, for the caller to record as one undoable batch.
+pub fn execute_renames(
+    changes: &[(PathBuf, PathBuf)],
+    overwrite: bool,
+    reporter: &mut dyn Reporter,
+) -> Vec<(PathBuf, PathBuf)> {
+    let mut done = Vec::new();
+    let mut protection = Protection::new();
+    for (i, (from, to)) in changes.iter().enumerate() {
+        let outcome = if from.symlink_metadata().is_err() {
+            Outcome::Skipped(String::from("no longer exists"))
+        } else if let Some(reason) = protection.reason(from) {
+            Outcome::Skipped(format!("protected: {reason}"))
+        } else if !overwrite && to.exists() && !same_file(from, to) {
+            Outcome::Skipped(String::from("its new name is taken now"))
+        } else {
+            match std::fs::rename(from, to) {
+                Ok(()) => Outcome::Done,
+                Err(e) => Outcome::Failed(e.to_string()),
+            }
+        };
+        reporter.result(from, to, &outcome);
+        reporter.progress(i + 1, changes.len());
+        if outcome == Outcome::Done && from != to {
+            done.push((from.clone(), to.clone()));
+        }
+    }
+    done
+}
+
+/// Whether two paths name the same file or directory (same device and inode)
+#[cfg(unix)]
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (a.symlink_metadata(), b.symlink_metadata()) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    a == b
+}
+
+/// A shell script that runs `ops` as one undoable `smv --stdin-ops` batch
+///
+/// Ops whose paths would not survive the text format are written as JSON lines.
+pub fn to_script(ops: &[PlannedOp]) -> String {
+    let mut script = format!(
+        "#!/bin/sh\n# {} operation(s) exported from the smv TUI queue; smv -u undoes the run\n",
+        ops.len()
+    );
+    let copy = ops.iter().any(|op| op.op == OpKind::Copy);
+    script.push_str("smv --stdin-ops <<'SMV_PLAN'\n");
+    for op in ops {
+        let (from, to) = (op.from.to_string_lossy(), op.to.to_string_lossy());
+        let plain = |path: &str| {
+            path.trim() == path && !path.contains(['\t', '\n', '\r']) && !path.starts_with('#')
+        };
+        if !copy && plain(&from) && plain(&to) {
+            script.push_str(&format!("{from}\t{to}\n"));
+        } else {
+            script.push_str(&json!({"op": op.op, "from": op.from, "to": op.to}).to_string());
+            script.push('\n');
+        }
+    }
+    script.push_str("SMV_PLAN\n");
+    script
+}
+
+/// `ops` as a YAML plan, for `smv --stdin-ops < plan.yaml`
+pub fn to_yaml(ops: &[PlannedOp]) -> Result<String, serde_yaml::Error> {
+    serde_yaml::to_string(ops)
+}
+
+/// `ops` as a JSON array, one op per line, easy to edit and to diff
+pub fn to_json(ops: &[PlannedOp]) -> Result<String, serde_json::Error> {
+    let mut json = String::from("[\n");
+    for (index, op) in ops.iter().enumerate() {
+        json.push_str("  ");
+        json.push_str(&serde_json::to_string(op)?);
+        json.push_str(if index + 1 < ops.len() { ",\n" } else { "\n" });
+    }
+    json.push_str("]\n");
+    Ok(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedupe_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir(root.join("docs")).unwrap();
+        std::fs::write(root.join("a.txt"), "").unwrap();
+        std::fs::write(root.join("b.txt"), "").unwrap();
+
+        let sources = vec![
+            root.join("a.txt"),
+            root.join("./b.txt"),
+            root.join("docs/../a.txt"),
+            root.join("b.txt"),
+            root.join("missing.txt"),
+            root.join("./missing.txt"),
+        ];
+        assert_eq!(
+            dedupe_sources(sources),
+            [
+                root.join("a.txt"),
+                root.join("./b.txt"),
+                root.join("missing.txt")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_text_plan() {
+        let plan = "# renames\na.txt\tb.txt\n\nsrc/c.md -> notes/c.md\n";
+        let ops = parse_plan(plan).unwrap();
+        assert_eq!(ops.len(), 2);
+        assert_eq!(ops[1].from, PathBuf::from("src/c.md"));
+        assert_eq!(ops[1].to, PathBuf::from("notes/c.md"));
+        assert!(parse_plan("just-one-path\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_json_plans() {
+        let array =
+            r#"[{"op": "copy", "from": "a", "to": "b"}, {"source": "c", "destination": "d"}]"#;
+        let ops = parse_plan(array).unwrap();
+        assert_eq!(ops[0].op, OpKind::Copy);
+        assert_eq!(ops[1].op, OpKind::Move);
+        assert_eq!(ops[1].to, PathBuf::from("d"));
+
+        let lines = "{\"op\": \"rename\", \"from\": \"x\", \"to\": \"y\"}\n{\"from\": \"y\"}\n";
+        let err = parse_plan(lines).unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn test_parse_csv_plans() {
+        let csv = "# from the inventory\n\
+                   Source,Destination,Operation\r\n\
+                   a.txt,archive/a.txt,move\r\n\
+                   \"b, final.txt\",\"out/\"\"b\"\".txt\",cp\n\
+                   c.txt,c2.txt,\n";
+        let ops = parse_plan(csv).unwrap();
+        assert_eq!(ops.len(), 3);
+        assert_eq!(ops[0].to, PathBuf::from("archive/a.txt"));
+        assert_eq!(ops[1].op, OpKind::Copy);
+        assert_eq!(ops[1].from, PathBuf::from("b, final.txt"));
+        assert_eq!(ops[1].to, PathBuf::from("out/\"b\".txt"));
+        assert_eq!(ops[2].op, OpKind::Move);
+
+        let err = parse_plan("operation,source,destination\nmv,a,b\ndelete,c,d\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(err.message.contains("delete"));
+        assert_eq!(
+            parse_plan("source,destination\n\"a,b\n").unwrap_err().line,
+            2
+        );
+        // Text plans with commas in their names are not CSV
+        assert_eq!(
+            parse_plan("a,b.txt\tc.txt\n").unwrap()[0].to,
+            PathBuf::from("c.txt")
+        );
+    }
+
+    #[test]
+    fn test_validate_checks_the_whole_plan() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("a"), "").unwrap();
+        std::fs::write(root.join("b"), "").unwrap();
+        let op = |from: &str, to: &str| PlannedOp {
+            op: OpKind::Move,
+            from: root.join(from),
+            to: root.join(to),
+        };
+        let ops = [op("a", "x"), op("missing", "y"), op("b", "x"), op("b", "a")];
+        assert_eq!(
+            validate(&ops, false),
+            [
+                (1, Conflict::MissingSource),
+                (2, Conflict::DuplicateDestination),
+                (3, Conflict::DestinationExists)
+            ]
+        );
+        assert_eq!(validate(&ops[3..], true), []);
+    }
+
+    #[test]
+    fn test_exported_plans_parse_back() {
+        let op = |from: &str, to: &str| PlannedOp {
+            op: OpKind::Move,
+            from: PathBuf::from(from),
+            to: PathBuf::from(to),
+        };
+        let ops = vec![
+            op("/music/Track 01.mp3", "/music/track_01.mp3"),
+            op("/music/ odd\tname", "/music/odd_name"),
+        ];
+
+        let yaml = to_yaml(&ops).unwrap();
+        assert!(yaml.starts_with("- op: move"), "{yaml}");
+        assert_eq!(parse_plan(&yaml).unwrap(), ops);
+
+        let script = to_script(&ops);
+        let body = script
+            .split_once("<<'SMV_PLAN'\n")
+            .and_then(|(_, rest)| rest.strip_suffix("SMV_PLAN\n"))
+            .unwrap();
+        assert!(body.starts_with("/music/Track 01.mp3\t"));
+        assert_eq!(parse_plan(body).unwrap(), ops);
+
+        let json = to_json(&ops).unwrap();
+        assert_eq!(json.lines().count(), 4);
+        assert_eq!(parse_plan(&json).unwrap(), ops);
+        assert_eq!(parse_plan(&to_json(&[]).unwrap()).unwrap(), []);
+    }
+
+    #[test]
+    fn test_conflict_checker() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        std::fs::write(&a, "a").unwrap();
+        std::fs::write(&b, "b").unwrap();
+        std::fs::write(dir.path().join("Report.txt"), "r").unwrap();
+
+        let op = |from: &Path, to: &Path| PlannedOp {
+            op: OpKind::Move,
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        };
+        let mut checker = ConflictChecker::new(false);
+        assert_eq!(
+            checker.check(&op(&a, &b)),
+            Some(Conflict::DestinationExists)
+        );
+        assert_eq!(
+            checker.check(&op(&dir.path().join("nope"), &b)),
+            Some(Conflict::MissingSource)
+        );
+        let c = dir.path().join("c");
+        assert_eq!(checker.check(&op(&a, &c)), None);
+        assert_eq!(
+            checker.check(&op(&b, &c)),
+            Some(Conflict::DuplicateDestination)
+        );
+
+        // Case-only differences, from the disk or from earlier in the plan
+        assert_eq!(
+            checker.check(&op(&b, &dir.path().join("C"))),
+            Some(Conflict::CaseCollision)
+        );
+        assert_eq!(
+            checker.check(&op(&b, &dir.path().join("report.txt"))),
+            Some(Conflict::CaseCollision)
+        );
+        assert_eq!(
+            checker.check(&op(&b, &dir.path().join("x".repeat(300)))),
+            Some(Conflict::NameTooLong)
+        );
+        // 200 characters, but 400 bytes
+        assert_eq!(
+            checker.check(&op(&b, &dir.path().join("é".repeat(200)))),
+            Some(Conflict::NameTooLong)
+        );
+        // Renaming a file to a different case of its own name is fine
+        assert_eq!(
+            checker.check(&op(
+                &dir.path().join("Report.txt"),
+                &dir.path().join("REPORT.txt")
+            )),
+            None
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_preflight_finds_locked_directories() {
+        use std::os::fd::AsRawFd;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let locked = dir.path().join("locked");
+        std::fs::create_dir(&locked).unwrap();
+        std::fs::write(locked.join("a.txt"), "a").unwrap();
+        std::fs::write(dir.path().join("c.txt"), "c").unwrap();
+        let op = |from: PathBuf, to: PathBuf| PlannedOp {
+            op: OpKind::Move,
+            from,
+            to,
+        };
+        let ops = [
+            op(locked.join("a.txt"), dir.path().join("b.txt")),
+            op(dir.path().join("c.txt"), dir.path().join("new/sub/c.txt")),
+        ];
+        assert!(preflight(&ops).is_empty());
+
+        // Root may write anywhere, so lock the directory with the immutable flag then
+        let set_immutable = |on: bool| {
+            let handle = std::fs::File::open(&locked).unwrap();
+            let mut flags: libc::c_int = 0;
+            unsafe {
+                libc::ioctl(handle.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) == 0 && {
+                    flags = if on { flags | 0x10 } else { flags & !0x10 };
+                    libc::ioctl(handle.as_raw_fd(), libc::FS_IOC_SETFLAGS, &flags) == 0
+                }
+            }
+        };
+        let root = unsafe { libc::geteuid() } == 0;
+        if root {
+            if !set_immutable(true) {
+                return;
+            }
+        } else {
+            std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o555)).unwrap();
+        }
+        let refusals = preflight(&ops);
+        if root {
+            set_immutable(false);
+        } else {
+            std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        assert_eq!(refusals.len(), 1, "{refusals:?}");
+        assert_eq!(refusals[0].dir, locked);
+        assert_eq!(refusals[0].ops, 1);
+    }
+}
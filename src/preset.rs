@@ -0,0 +1,251 @@
+//! Named commands for recurring workflows (`smv preset save/run/list/rm`)
+//!
+//! A preset is one smv command line, filters, transform, flags and destination
+//! included, saved under a name with an optional description:
+//!
+//! ```text
+//! smv preset save photos-cleanup --description "Camera dumps" snake ~/Pictures -r EXT:jpg
+//! smv preset run photos-cleanup -p
+//! ```
+//!
+//! Options given after the name on `run` are added to the saved ones and win
+//! over them. Presets live in `presets.toml` next to the configuration file.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config;
+
+/// The presets file, in the directory of `config.toml`
+pub const PRESETS_FILE: &str = "presets.toml";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The command line after `smv`
+    pub args: Vec<String>,
+}
+
+/// What `smv preset` was asked to do
+#[derive(Debug, Clone, PartialEq)]
+pub enum PresetCommand {
+    List,
+    Save {
+        name: String,
+        preset: Preset,
+    },
+    Run {
+        name: String,
+        overrides: Vec<String>,
+    },
+    Remove {
+        name: String,
+    },
+}
+
+impl PresetCommand {
+    /// Read the command from the raw command line (without the program name)
+    ///
+    /// The saved command is taken verbatim from the command line, since its
+    /// options are smv's own and parsing would scatter them.
+    pub fn parse(command_line: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut description = None;
+        let mut words = Vec::new();
+        let mut iter = command_line.iter();
+        while let Some(word) = iter.next() {
+            if word == "--description" {
+                description = iter.next().cloned();
+            } else if let Some(value) = word.strip_prefix("--description=") {
+                description = Some(value.to_string());
+            } else {
+                words.push(word.clone());
+            }
+        }
+
+        let Some(start) = words.iter().position(|word| word == "preset") else {
+            return Err("Missing preset command (expected: save, run, list or rm)".into());
+        };
+        // Options before `preset` belong to the saved or run command too
+        let mut before = words[..start].to_vec();
+        let rest = &words[start + 1..];
+        let name = || -> Result<String, Box<dyn Error>> {
+            let name = rest.get(1).ok_or("Missing preset name")?;
+            validate_name(name)?;
+            Ok(name.clone())
+        };
+        match rest.first().map(String::as_str) {
+            None | Some("list") => Ok(PresetCommand::List),
+            Some("save") => {
+                let name = name()?;
+                before.extend(rest[2..].iter().cloned());
+                if before.is_empty() {
+                    return Err(format!(
+                        "Nothing to save (usage: smv preset save {name} COMMAND...)"
+                    )
+                    .into());
+                }
+                if before.iter().any(|word| word == "preset") {
+                    return Err("A preset cannot run another preset".into());
+                }
+                Ok(PresetCommand::Save {
+                    name,
+                    preset: Preset {
+                        description,
+                        args: before,
+                    },
+                })
+            }
+            Some("run") => {
+                let name = name()?;
+                before.extend(rest[2..].iter().cloned());
+                Ok(PresetCommand::Run {
+                    name,
+                    overrides: before,
+                })
+            }
+            Some("rm") => Ok(PresetCommand::Remove { name: name()? }),
+            Some(other) => Err(format!(
+                "Unknown preset command: {other} (expected: save, run, list or rm)"
+            )
+            .into()),
+        }
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), Box<dyn Error>> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(
+            format!("Invalid preset name {name:?}: use letters, digits, '-' and '_'").into(),
+        );
+    }
+    Ok(())
+}
+
+/// Where presets are kept
+pub fn presets_path() -> Option<PathBuf> {
+    config::config_path().and_then(|path| path.parent().map(|dir| dir.join(PRESETS_FILE)))
+}
+
+/// The saved presets, by name
+#[derive(Debug, Default)]
+pub struct Presets {
+    path: Option<PathBuf>,
+    presets: BTreeMap<String, Preset>,
+}
+
+impl Presets {
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let path = presets_path();
+        let presets = match &path {
+            Some(path) if path.is_file() => toml::from_str(&fs::read_to_string(path)?)
+                .map_err(|e| format!("{}: {}", path.display(), e))?,
+            _ => BTreeMap::new(),
+        };
+        Ok(Self { path, presets })
+    }
+
+    pub fn save(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let path = self
+            .path
+            .clone()
+            .ok_or("No configuration directory to keep presets in (set SMV_HOME)")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, toml::to_string(&self.presets)?)?;
+        Ok(path)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Preset> {
+        self.presets.get(name)
+    }
+
+    /// Add or replace a preset, returning the one it replaced
+    pub fn insert(&mut self, name: String, preset: Preset) -> Option<Preset> {
+        self.presets.insert(name, preset)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Preset> {
+        self.presets.remove(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Preset)> {
+        self.presets.iter()
+    }
+}
+
+impl Preset {
+    /// The command line a run executes: the saved arguments, then `overrides`
+    pub fn command_line(&self, overrides: &[String]) -> Vec<String> {
+        self.args.iter().chain(overrides).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_preset_commands() {
+        let command = PresetCommand::parse(&words(
+            "-r preset save photos --description=Camera snake pics EXT:jpg",
+        ))
+        .unwrap();
+        assert_eq!(
+            command,
+            PresetCommand::Save {
+                name: "photos".to_string(),
+                preset: Preset {
+                    description: Some("Camera".to_string()),
+                    args: words("-r snake pics EXT:jpg"),
+                },
+            }
+        );
+        assert_eq!(
+            PresetCommand::parse(&words("preset run photos -p")).unwrap(),
+            PresetCommand::Run {
+                name: "photos".to_string(),
+                overrides: words("-p"),
+            }
+        );
+        assert_eq!(
+            PresetCommand::parse(&words("preset")).unwrap(),
+            PresetCommand::List
+        );
+        assert!(PresetCommand::parse(&words("preset save photos")).is_err());
+        assert!(PresetCommand::parse(&words("preset save a/b snake .")).is_err());
+        assert!(PresetCommand::parse(&words("preset save x preset run y")).is_err());
+        assert!(PresetCommand::parse(&words("preset frobnicate")).is_err());
+    }
+
+    #[test]
+    fn test_presets_round_trip() {
+        let mut presets = BTreeMap::new();
+        presets.insert(
+            "photos".to_string(),
+            Preset {
+                description: Some("Camera dumps".to_string()),
+                args: words("snake pics -r"),
+            },
+        );
+        let text = toml::to_string(&presets).unwrap();
+        let read: BTreeMap<String, Preset> = toml::from_str(&text).unwrap();
+        assert_eq!(read, presets);
+        assert_eq!(
+            read["photos"].command_line(&words("-p")),
+            words("snake pics -r -p")
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Previews of selections too large to list
+//!
+//! A preview of 100,000 renames scrolls past faster than anyone can read it. When
+//! a preview covers more than [`LARGE_PREVIEW`] items, the renames are not
+//! printed one by one: a random sample of them is, followed by how many renames
+//! follow each pattern, with digits masked (`IMG_#### → ####`), so an unexpected
+//! rename stands out as a pattern of its own. `--full` lists everything.
+
+use colored::*;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::selection::{next_random, seed};
+
+/// Items a preview lists in full
+pub const LARGE_PREVIEW: usize = 1000;
+
+/// Renames shown from a sampled preview
+pub const SAMPLE_SIZE: usize = 25;
+
+/// Patterns listed before the rest are counted together
+const SHOWN_PATTERNS: usize = 10;
+
+/// A uniform sample of the renames of one preview and their patterns
+#[derive(Debug)]
+pub struct Sample {
+    seen: usize,
+    /// `(position, line)`, kept by reservoir sampling
+    lines: Vec<(usize, String)>,
+    patterns: HashMap<(String, String), usize>,
+    random: u64,
+}
+
+impl Sample {
+    /// A sample for a preview of `items`, or `None` when listing them is fine
+    pub fn for_preview(items: usize, full: bool) -> Option<Self> {
+        (!full && items > LARGE_PREVIEW).then(|| Sample {
+            seen: 0,
+            lines: Vec::with_capacity(SAMPLE_SIZE),
+            patterns: HashMap::new(),
+            random: seed(),
+        })
+    }
+
+    /// Count a rename of `old` to `new`, keeping `line` if it is sampled
+    pub fn add(&mut self, old: &str, new: &str, line: String) {
+        *self.patterns.entry(pattern(old, new)).or_default() += 1;
+        if self.lines.len() < SAMPLE_SIZE {
+            self.lines.push((self.seen, line));
+        } else {
+            let slot = (next_random(&mut self.random) % (self.seen as u64 + 1)) as usize;
+            if slot < SAMPLE_SIZE {
+                self.lines[slot] = (self.seen, line);
+            }
+        }
+        self.seen += 1;
+    }
+
+    /// Patterns by how many renames follow them, most first
+    pub fn patterns(&self) -> Vec<(&(String, String), usize)> {
+        let mut patterns: Vec<_> = self.patterns.iter().map(|(p, n)| (p, *n)).collect();
+        patterns.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        patterns
+    }
+
+    pub fn print(&self) {
+        if self.seen == 0 {
+            return;
+        }
+        let mut lines: Vec<&(usize, String)> = self.lines.iter().collect();
+        lines.sort_by_key(|(position, _)| *position);
+        println!(
+            "{}",
+            format!(
+                "Showing a random sample of {} of {} renames (--full lists them all):",
+                lines.len(),
+                thousands(self.seen)
+            )
+            .bold()
+        );
+        for (_, line) in lines {
+            println!("{line}");
+        }
+
+        println!("\n{}", "Rename patterns:".bold());
+        let patterns = self.patterns();
+        for ((old, new), count) in patterns.iter().take(SHOWN_PATTERNS) {
+            println!(
+                "  {:>8} files: {} → {}",
+                thousands(*count),
+                old,
+                new.green()
+            );
+        }
+        if patterns.len() > SHOWN_PATTERNS {
+            let rest = &patterns[SHOWN_PATTERNS..];
+            println!(
+                "  ...and {} other patterns ({} files)",
+                rest.len(),
+                thousands(rest.iter().map(|(_, count)| count).sum())
+            );
+        }
+    }
+}
+
+/// A rename with every digit masked as `#`, and an extension both names
+/// share left off
+pub fn pattern(old: &str, new: &str) -> (String, String) {
+    let extension = |name: &str| Path::new(name).extension().map(|e| e.len() + 1);
+    let (old, new) = match (extension(old), extension(new)) {
+        (Some(a), Some(b)) if old[old.len() - a..] == new[new.len() - b..] => {
+            (&old[..old.len() - a], &new[..new.len() - b])
+        }
+        _ => (old, new),
+    };
+    let mask = |name: &str| {
+        name.chars()
+            .map(|c| if c.is_ascii_digit() { '#' } else { c })
+            .collect()
+    };
+    (mask(old), mask(new))
+}
+
+/// `12431` as `12,431`
+pub fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patterns_and_sample() {
+        assert_eq!(
+            pattern("IMG_0412.jpg", "0412.jpg"),
+            ("IMG_####".to_string(), "####".to_string())
+        );
+        assert_eq!(
+            pattern("Report 7.PDF", "report_7.pdf"),
+            ("Report #.PDF".to_string(), "report_#.pdf".to_string())
+        );
+        assert_eq!(thousands(12431), "12,431");
+        assert_eq!(thousands(999), "999");
+        assert_eq!(thousands(1_000_000), "1,000,000");
+
+        assert!(Sample::for_preview(LARGE_PREVIEW, false).is_none());
+        assert!(Sample::for_preview(LARGE_PREVIEW + 1, true).is_none());
+        let mut sample = Sample::for_preview(5000, false).unwrap();
+        for n in 0..5000 {
+            let old = format!("IMG_{n:04}.jpg");
+            let new = old.trim_start_matches("IMG_").to_string();
+            sample.add(&old, &new, format!("{old} → {new}"));
+        }
+        sample.add("notes.txt", "Notes.txt", String::from("notes"));
+        assert_eq!(sample.seen, 5001);
+        assert_eq!(sample.lines.len(), SAMPLE_SIZE);
+        let patterns = sample.patterns();
+        assert_eq!(patterns.len(), 2);
+        assert_eq!(patterns[0].1, 5000);
+        assert_eq!(patterns[1].0.0, "notes");
+    }
+}
@@ -0,0 +1,374 @@
+//! Heartbeats and per-directory checkpoints for long moves and copies
+//!
+//! With `--heartbeat SECS`, `mv`/`cp` report on stderr every SECS seconds which
+//! directory they are working in, how many files are done and left, and how many
+//! failed. `--output json` turns the reports into NDJSON events (one object per
+//! line) and adds one for every finished directory, so wrappers can drive their
+//! own progress UI. Reports go to stderr so they never mix with the results.
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+use walkdir::WalkDir;
+
+/// Seconds between heartbeats with `--output json` and no `--heartbeat`
+pub const DEFAULT_JSON_INTERVAL: u64 = 5;
+
+/// How reports are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl OutputFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "Unknown output format '{other}' (expected text or json)"
+            )),
+        }
+    }
+}
+
+/// Something worth reporting during a long operation
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Start {
+        total: u64,
+    },
+    Heartbeat {
+        dir: Option<PathBuf>,
+        done: u64,
+        total: u64,
+        errors: u64,
+        elapsed: Duration,
+    },
+    Directory {
+        dir: PathBuf,
+        files: u64,
+        errors: u64,
+        done: u64,
+        total: u64,
+    },
+    Finish {
+        done: u64,
+        total: u64,
+        errors: u64,
+        elapsed: Duration,
+    },
+}
+
+impl Event {
+    /// The event as one line of text, or as one JSON object
+    pub fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Text => self.render_text(),
+            OutputFormat::Json => self.render_json(),
+        }
+    }
+
+    fn render_text(&self) -> String {
+        match self {
+            Event::Start { total } => format!("Progress: {total} files to process"),
+            Event::Heartbeat {
+                dir,
+                done,
+                total,
+                errors,
+                elapsed,
+            } => {
+                let place = dir
+                    .as_ref()
+                    .map(|dir| format!(", in {}", dir.display()))
+                    .unwrap_or_default();
+                format!(
+                    "Progress: {done}/{total} files ({} left), {errors} errors{place} [{}]",
+                    total.saturating_sub(*done),
+                    format_elapsed(*elapsed)
+                )
+            }
+            Event::Directory {
+                dir,
+                files,
+                errors,
+                done,
+                total,
+            } => format!(
+                "Checkpoint: {} done ({files} files, {errors} errors); {done}/{total} overall",
+                dir.display()
+            ),
+            Event::Finish {
+                done,
+                total,
+                errors,
+                elapsed,
+            } => format!(
+                "Progress: {done}/{total} files done, {errors} errors in {}",
+                format_elapsed(*elapsed)
+            ),
+        }
+    }
+
+    fn render_json(&self) -> String {
+        let value = match self {
+            Event::Start { total } => json!({ "event": "start", "total": total }),
+            Event::Heartbeat {
+                dir,
+                done,
+                total,
+                errors,
+                elapsed,
+            } => json!({
+                "event": "heartbeat",
+                "dir": dir.as_ref().map(|dir| dir.to_string_lossy()),
+                "done": done,
+                "remaining": total.saturating_sub(*done),
+                "total": total,
+                "errors": errors,
+                "elapsed_secs": elapsed.as_secs(),
+            }),
+            Event::Directory {
+                dir,
+                files,
+                errors,
+                done,
+                total,
+            } => json!({
+                "event": "directory",
+                "dir": dir.to_string_lossy(),
+                "files": files,
+                "errors": errors,
+                "done": done,
+                "remaining": total.saturating_sub(*done),
+                "total": total,
+            }),
+            Event::Finish {
+                done,
+                total,
+                errors,
+                elapsed,
+            } => json!({
+                "event": "finish",
+                "done": done,
+                "total": total,
+                "errors": errors,
+                "elapsed_secs": elapsed.as_secs(),
+            }),
+        };
+        value.to_string()
+    }
+}
+
+/// `1h 02m 03s`, `2m 03s` or `5s`
+pub fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{hours}h {minutes:02}m {seconds:02}s")
+    } else if minutes > 0 {
+        format!("{minutes}m {seconds:02}s")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+/// Files (not directories) a move or copy of `sources` will touch
+///
+/// `one_file_system` leaves out what is mounted below a source, like `-x` does.
+pub fn count_files(sources: &[PathBuf], recursive: bool, one_file_system: bool) -> u64 {
+    sources
+        .iter()
+        .map(|source| {
+            if source.is_dir() && recursive {
+                WalkDir::new(source)
+                    .same_file_system(one_file_system)
+                    .into_iter()
+                    .filter_map(Result::ok)
+                    .filter(|entry| !entry.file_type().is_dir())
+                    .count() as u64
+            } else {
+                u64::from(!source.is_dir())
+            }
+        })
+        .sum()
+}
+
+/// Counters for a running operation, reported as they change
+///
+/// Methods take `&self` so the file operations can share one tracker through
+/// their config.
+#[derive(Debug)]
+pub struct Progress {
+    format: OutputFormat,
+    interval: Duration,
+    started: Instant,
+    state: RefCell<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    total: u64,
+    done: u64,
+    errors: u64,
+    last_report: Option<Instant>,
+    /// Directories being worked on, with the counters when each was entered
+    dirs: Vec<(PathBuf, u64, u64)>,
+}
+
+impl Progress {
+    /// Start tracking `total` files, reporting at most every `interval_secs`
+    pub fn start(format: OutputFormat, interval_secs: u64, total: u64) -> Self {
+        let progress = Self {
+            format,
+            interval: Duration::from_secs(interval_secs),
+            started: Instant::now(),
+            state: RefCell::new(State {
+                total,
+                ..State::default()
+            }),
+        };
+        progress.emit(&Event::Start { total });
+        progress
+    }
+
+    pub fn enter_dir(&self, dir: &Path) {
+        let mut state = self.state.borrow_mut();
+        let (done, errors) = (state.done, state.errors);
+        state.dirs.push((dir.to_path_buf(), done, errors));
+    }
+
+    /// A directory is finished: JSON reports every one, text only when a report is due
+    pub fn leave_dir(&self, dir: &Path) {
+        let event = {
+            let mut state = self.state.borrow_mut();
+            let Some(position) = state.dirs.iter().rposition(|(path, ..)| path == dir) else {
+                return;
+            };
+            let (_, done_before, errors_before) = state.dirs[position].clone();
+            // Directories abandoned after an error are dropped along with this one
+            state.dirs.truncate(position);
+            if self.format == OutputFormat::Text && !self.due(&state) {
+                return;
+            }
+            state.last_report = Some(Instant::now());
+            Event::Directory {
+                dir: dir.to_path_buf(),
+                files: state.done - done_before,
+                errors: state.errors - errors_before,
+                done: state.done,
+                total: state.total,
+            }
+        };
+        self.emit(&event);
+    }
+
+    pub fn file_done(&self) {
+        self.state.borrow_mut().done += 1;
+        self.heartbeat();
+    }
+
+    pub fn file_failed(&self) {
+        let mut state = self.state.borrow_mut();
+        state.done += 1;
+        state.errors += 1;
+        drop(state);
+        self.heartbeat();
+    }
+
+    pub fn finish(&self) {
+        let state = self.state.borrow();
+        self.emit(&Event::Finish {
+            done: state.done,
+            total: state.total,
+            errors: state.errors,
+            elapsed: self.started.elapsed(),
+        });
+    }
+
+    fn heartbeat(&self) {
+        let event = {
+            let mut state = self.state.borrow_mut();
+            if !self.due(&state) {
+                return;
+            }
+            state.last_report = Some(Instant::now());
+            Event::Heartbeat {
+                dir: state.dirs.last().map(|(dir, ..)| dir.clone()),
+                done: state.done,
+                total: state.total,
+                errors: state.errors,
+                elapsed: self.started.elapsed(),
+            }
+        };
+        self.emit(&event);
+    }
+
+    fn due(&self, state: &State) -> bool {
+        state.last_report.unwrap_or(self.started).elapsed() >= self.interval
+    }
+
+    fn emit(&self, event: &Event) {
+        eprintln!("{}", event.render(self.format));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let heartbeat = Event::Heartbeat {
+            dir: Some(PathBuf::from("photos/2019")),
+            done: 1200,
+            total: 5000,
+            errors: 2,
+            elapsed: Duration::from_secs(3723),
+        };
+        assert_eq!(
+            heartbeat.render(OutputFormat::Text),
+            "Progress: 1200/5000 files (3800 left), 2 errors, in photos/2019 [1h 02m 03s]"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&heartbeat.render(OutputFormat::Json)).unwrap();
+        assert_eq!(json["event"], "heartbeat");
+        assert_eq!(json["dir"], "photos/2019");
+        assert_eq!(json["remaining"], 3800);
+        assert_eq!(json["elapsed_secs"], 3723);
+
+        let directory = Event::Directory {
+            dir: PathBuf::from("photos/2019"),
+            files: 40,
+            errors: 0,
+            done: 1240,
+            total: 5000,
+        };
+        assert!(!directory.render(OutputFormat::Json).contains('\n'));
+        assert_eq!(format_elapsed(Duration::from_secs(125)), "2m 05s");
+        assert_eq!(OutputFormat::parse("JSON"), Ok(OutputFormat::Json));
+        assert!(OutputFormat::parse("yaml").is_err());
+    }
+
+    #[test]
+    fn test_count_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        std::fs::write(dir.path().join("a/one.txt"), "").unwrap();
+        std::fs::write(dir.path().join("a/b/two.txt"), "").unwrap();
+        let sources = vec![dir.path().join("a")];
+
+        assert_eq!(count_files(&sources, true, false), 2);
+        assert_eq!(count_files(&sources, true, true), 2);
+        assert_eq!(count_files(&sources, false, false), 0);
+        assert_eq!(
+            count_files(&[dir.path().join("a/one.txt")], false, false),
+            1
+        );
+    }
+}
@@ -0,0 +1,315 @@
+//! Files that must keep their name and place (`.smvignore`, NORENAME marks)
+//!
+//! A `.smvignore` file in any directory lists, in gitignore syntax, paths below
+//! it that smv never renames or moves: `*.tex`, `/final/`, `!draft.tex` to take
+//! one back out. Patterns without a slash match a name at any depth, a leading
+//! or inner slash anchors to the file's directory, a trailing slash matches
+//! directories only, and a directory's protection covers everything in it.
+//! Deeper files and later lines win.
+//!
+//! Single files can be marked instead: an extended attribute
+//! `user.smv.norename`, or a `NORENAME` tag (the `user.xdg.tags` attribute
+//! desktop file managers write, or a Finder tag on macOS).
+//!
+//! The planner checks every rename and move against both, so transforms,
+//! sorting, `mv`, plans and the interactive modes all leave these files alone.
+//! Only the files themselves are guarded: renaming a directory still carries
+//! any protected files inside it along, and copies are always allowed.
+
+use glob::{MatchOptions, Pattern};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The per-directory ignore file
+pub const IGNORE_FILE: &str = ".smvignore";
+
+/// The attribute that marks one file, whatever its value
+pub const MARK_ATTRIBUTE: &str = "user.smv.norename";
+
+/// The tag that marks a file in file managers that support tags
+pub const MARK_TAG: &str = "NORENAME";
+
+/// One line of a `.smvignore`
+#[derive(Debug, Clone)]
+struct Rule {
+    line: String,
+    pattern: Pattern,
+    negated: bool,
+    anchored: bool,
+    dir_only: bool,
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Self> {
+        let trimmed = line.trim_end();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            return None;
+        }
+        let (negated, rest) = match trimmed.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, trimmed.strip_prefix('\\').unwrap_or(trimmed)),
+        };
+        let (dir_only, rest) = match rest.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let anchored = rest.contains('/');
+        let pattern = Pattern::new(rest.trim_start_matches('/')).ok()?;
+        Some(Self {
+            line: trimmed.to_string(),
+            pattern,
+            negated,
+            anchored,
+            dir_only,
+        })
+    }
+
+    /// Whether the rule matches `relative`, a path below the ignore file's directory
+    fn matches(&self, relative: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        if self.anchored {
+            self.pattern.matches_path_with(relative, options)
+        } else {
+            relative
+                .file_name()
+                .is_some_and(|name| self.pattern.matches_with(&name.to_string_lossy(), options))
+        }
+    }
+}
+
+/// Answers whether paths are protected, reading each `.smvignore` once
+#[derive(Debug, Default)]
+pub struct Protection {
+    ignore_files: HashMap<PathBuf, Vec<Rule>>,
+}
+
+impl Protection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Why `path` must not be renamed or moved, or `None` when it may be
+    pub fn reason(&mut self, path: &Path) -> Option<String> {
+        if path.file_name().is_some_and(|name| name == IGNORE_FILE) {
+            return Some(String::from("ignore files stay where they apply"));
+        }
+        if let Some(mark) = marked(path) {
+            return Some(mark);
+        }
+        let path = absolute(path);
+        // A protected directory protects everything in it
+        let mut candidates: Vec<&Path> = path.ancestors().collect();
+        candidates.reverse();
+        for candidate in candidates.into_iter().skip(1) {
+            if let Some(reason) = self.ignored(candidate) {
+                return Some(reason);
+            }
+        }
+        None
+    }
+
+    /// The `.smvignore` line that ignores `path` itself, judging by every
+    /// ignore file above it
+    fn ignored(&mut self, path: &Path) -> Option<String> {
+        let is_dir = path.is_dir();
+        let mut verdict = None;
+        let mut dirs: Vec<&Path> = path.ancestors().skip(1).collect();
+        dirs.reverse();
+        for dir in dirs {
+            let Ok(relative) = path.strip_prefix(dir) else {
+                continue;
+            };
+            for rule in self.rules(dir) {
+                if rule.matches(relative, is_dir) {
+                    verdict = (!rule.negated)
+                        .then(|| format!("{} in {}", rule.line, dir.join(IGNORE_FILE).display()));
+                }
+            }
+        }
+        verdict
+    }
+
+    fn rules(&mut self, dir: &Path) -> &[Rule] {
+        self.ignore_files
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| {
+                fs::read_to_string(dir.join(IGNORE_FILE))
+                    .map(|text| text.lines().filter_map(Rule::parse).collect())
+                    .unwrap_or_default()
+            })
+    }
+}
+
+/// `path` made absolute without resolving its last component, so a symlink
+/// is judged by its own name
+fn absolute(path: &Path) -> PathBuf {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match (parent.canonicalize(), path.file_name()) {
+        (Ok(parent), Some(name)) => parent.join(name),
+        _ => std::env::current_dir()
+            .map(|cwd| cwd.join(path))
+            .unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+/// How `path` is marked NORENAME, if it is
+pub fn marked(path: &Path) -> Option<String> {
+    if xattr(path, MARK_ATTRIBUTE).is_some() {
+        return Some(format!("marked {MARK_ATTRIBUTE}"));
+    }
+    let has_tag = |tags: Vec<u8>| {
+        String::from_utf8_lossy(&tags)
+            .split(|c: char| c == ',' || c.is_control())
+            .any(|tag| tag.trim().eq_ignore_ascii_case(MARK_TAG))
+    };
+    for attribute in ["user.xdg.tags", "com.apple.metadata:_kMDItemUserTags"] {
+        if xattr(path, attribute).is_some_and(has_tag) {
+            return Some(format!("tagged {MARK_TAG}"));
+        }
+    }
+    None
+}
+
+/// The value of extended attribute `name` on `path`, without following symlinks
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+fn xattr(path: &Path, name: &str) -> Option<Vec<u8>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let c_name = CString::new(name).ok()?;
+    let read = |buffer: &mut [u8]| -> isize {
+        let (pointer, size) = if buffer.is_empty() {
+            (std::ptr::null_mut(), 0)
+        } else {
+            (buffer.as_mut_ptr().cast(), buffer.len())
+        };
+        #[cfg(target_os = "macos")]
+        let length = unsafe {
+            libc::getxattr(
+                c_path.as_ptr(),
+                c_name.as_ptr(),
+                pointer,
+                size,
+                0,
+                libc::XATTR_NOFOLLOW,
+            )
+        };
+        #[cfg(not(target_os = "macos"))]
+        let length = unsafe { libc::lgetxattr(c_path.as_ptr(), c_name.as_ptr(), pointer, size) };
+        length
+    };
+
+    let length = read(&mut []);
+    if length < 0 {
+        return None;
+    }
+    let mut value = vec![0; length as usize];
+    let length = read(&mut value);
+    if length < 0 {
+        return None;
+    }
+    value.truncate(length as usize);
+    Some(value)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn xattr(_path: &Path, _name: &str) -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smvignore_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("thesis/final")).unwrap();
+        fs::create_dir_all(root.join("photos")).unwrap();
+        fs::write(
+            root.join(IGNORE_FILE),
+            "# keep the LaTeX sources\n*.tex\n!draft.tex\nthesis/final/\n",
+        )
+        .unwrap();
+        fs::write(root.join("photos").join(IGNORE_FILE), "/Keep Me.jpg\n").unwrap();
+        for file in [
+            "thesis/main.tex",
+            "thesis/draft.tex",
+            "thesis/final/Out Put.pdf",
+            "photos/Keep Me.jpg",
+            "photos/sub Keep Me.jpg",
+            "notes.txt",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        let mut protection = Protection::new();
+        let reason = protection.reason(&root.join("thesis/main.tex")).unwrap();
+        assert!(reason.starts_with("*.tex in "), "{reason}");
+        assert!(protection.reason(&root.join("thesis/draft.tex")).is_none());
+        assert!(protection.reason(&root.join("thesis/final")).is_some());
+        assert!(
+            protection
+                .reason(&root.join("thesis/final/Out Put.pdf"))
+                .is_some()
+        );
+        assert!(
+            protection
+                .reason(&root.join("photos/Keep Me.jpg"))
+                .is_some()
+        );
+        assert!(
+            protection
+                .reason(&root.join("photos/sub Keep Me.jpg"))
+                .is_none()
+        );
+        assert!(protection.reason(&root.join("notes.txt")).is_none());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_norename_mark() {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let set = |path: &Path, name: &str, value: &[u8]| {
+            let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+            let c_name = CString::new(name).unwrap();
+            unsafe {
+                libc::setxattr(
+                    c_path.as_ptr(),
+                    c_name.as_ptr(),
+                    value.as_ptr().cast(),
+                    value.len(),
+                    0,
+                )
+            }
+        };
+        let file = dir.path().join("Contract.pdf");
+        fs::write(&file, "").unwrap();
+        assert!(marked(&file).is_none());
+        // Filesystems without user attributes cannot hold the mark at all
+        if set(&file, MARK_ATTRIBUTE, b"") != 0 {
+            return;
+        }
+        assert!(Protection::new().reason(&file).is_some());
+
+        let tagged = dir.path().join("Tagged.pdf");
+        fs::write(&tagged, "").unwrap();
+        set(&tagged, "user.xdg.tags", b"work,norename");
+        assert_eq!(marked(&tagged).as_deref(), Some("tagged NORENAME"));
+    }
+}
@@ -1,9 +1,10 @@
 use std::env;
 use std::error::Error;
+use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
-use std::process;
 
+use chrono::{DateTime, Local};
 use colored::*;
 use glob::glob;
 use rustyline::Helper;
@@ -14,8 +15,16 @@ use rustyline::hint::Hinter;
 use rustyline::history::DefaultHistory;
 use rustyline::validate::Validator;
 use rustyline::{CompletionType, Config, Editor, Result as RustylineResult};
-
-use crate::history::HistoryManager;
+use serde::{Deserialize, Serialize};
+
+use crate::clipboard;
+use crate::history::{self, HistoryManager};
+use crate::journal::{self, JournalKey};
+use crate::layout;
+use crate::paths;
+use crate::plan;
+use crate::protect::Protection;
+use crate::report::{Console, Reporter};
 use crate::transformers::{TransformType, transform};
 
 // Custom command completer
@@ -29,8 +38,14 @@ impl CommandCompleter {
         let commands = vec![
             "preview".to_string(),
             "apply".to_string(),
+            "show".to_string(),
             "undo".to_string(),
             "cd".to_string(),
+            "pushd".to_string(),
+            "popd".to_string(),
+            "dirs".to_string(),
+            "source".to_string(),
+            "copy".to_string(),
             "ls".to_string(),
             "rename".to_string(),
             "help".to_string(),
@@ -98,11 +113,48 @@ impl Highlighter for CommandCompleter {}
 impl Validator for CommandCompleter {}
 impl Helper for CommandCompleter {}
 
+/// How deeply `source` may nest scripts that source other scripts
+const MAX_SOURCE_DEPTH: usize = 8;
+
+/// File in the state directory keeping the working set of the last session
+const SESSION_FILE: &str = "repl-session.json";
+
+/// What `smv -I --resume` brings back: saved after every command at the prompt,
+/// so a session that was interrupted can carry on where it stopped
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedSession {
+    saved: DateTime<Local>,
+    current_dir: PathBuf,
+    #[serde(default)]
+    dir_stack: Vec<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_plan: Option<RenamePlan>,
+    /// A plan that was waiting for its confirmation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pending: Option<RenamePlan>,
+}
+
 /// Interactive REPL for SMV
 pub struct InteractiveSession {
     editor: Editor<CommandCompleter, DefaultHistory>,
     history_manager: HistoryManager,
     current_dir: PathBuf,
+    /// The last previewed plan, for `apply last` and `show`
+    last_plan: Option<RenamePlan>,
+    /// Directories saved by `pushd`, most recent last
+    dir_stack: Vec<PathBuf>,
+    /// Answer yes to confirmations instead of asking (`-F`)
+    assume_yes: bool,
+    /// Scripts currently being run by `source`
+    source_depth: usize,
+    /// Set by `quit`; the session ends instead of exiting the process
+    quitting: bool,
+    /// The plan being confirmed, until it is answered
+    pending: Option<RenamePlan>,
+    /// Whether the working set of the last session was restored
+    resumed: bool,
+    /// Shows planned renames and what became of them
+    reporter: Box<dyn Reporter>,
 }
 
 impl InteractiveSession {
@@ -121,16 +173,175 @@ impl InteractiveSession {
         let current_dir = env::current_dir()?;
 
         // Create history manager
-        let history_manager = HistoryManager::new(max_history_size, backup_dir);
+        let mut history_manager = HistoryManager::open(max_history_size, backup_dir)?;
+        history_manager.set_reporter(Box::new(Console));
 
         Ok(Self {
             editor,
             history_manager,
             current_dir,
+            last_plan: None,
+            dir_stack: Vec::new(),
+            assume_yes: false,
+            source_depth: 0,
+            quitting: false,
+            pending: None,
+            resumed: false,
+            reporter: Box::new(Console),
         })
     }
 
+    /// Skip confirmation prompts, as `-F` does elsewhere
+    pub fn set_assume_yes(&mut self, assume_yes: bool) {
+        self.assume_yes = assume_yes;
+    }
+
+    /// Carry on with the working set of the last session at the prompt
+    ///
+    /// The directory, the directory stack and the last preview come back; a
+    /// plan that was waiting for confirmation becomes the last preview, for
+    /// `apply last`. Previewed files that moved since are pointed out, and
+    /// `apply last` skips them.
+    pub fn resume(&mut self) -> Result<(), Box<dyn Error>> {
+        let file = session_file();
+        let content = journal::read_state_file(JournalKey::from_env()?.as_ref(), &file)?
+            .ok_or_else(|| format!("No saved REPL session in {}", file.display()))?;
+        let saved: SavedSession = serde_json::from_slice(&content)
+            .map_err(|e| format!("{}: not a REPL session ({e})", file.display()))?;
+
+        if let Err(e) = self.enter_dir(saved.current_dir.clone()) {
+            eprintln!(
+                "{}: cannot return to {} ({e}); staying in {}",
+                "Warning".yellow(),
+                saved.current_dir.display(),
+                self.current_dir.display()
+            );
+        }
+        self.dir_stack = saved
+            .dir_stack
+            .into_iter()
+            .filter(|dir| dir.is_dir())
+            .collect();
+        self.resumed = true;
+        println!(
+            "Resumed the session saved {} in {}",
+            saved.saved.format("%Y-%m-%d %H:%M"),
+            self.current_dir.display().to_string().cyan()
+        );
+
+        let was_pending = saved.pending.is_some();
+        self.last_plan = saved.pending.or(saved.last_plan);
+        if let Some(plan) = &self.last_plan {
+            let gone = plan
+                .changes
+                .iter()
+                .filter(|(from, _)| !from.exists())
+                .count();
+            if was_pending {
+                println!(
+                    "  {} rename(s) of '{} {}' were waiting for confirmation: 'show' lists them, 'apply last' runs them",
+                    plan.changes.len(),
+                    plan.transform_type.as_str(),
+                    plan.patterns.join(" ")
+                );
+            } else {
+                println!(
+                    "  Last preview: '{} {}' ({} rename(s)); 'show' lists it again",
+                    plan.transform_type.as_str(),
+                    plan.patterns.join(" "),
+                    plan.changes.len()
+                );
+            }
+            if gone > 0 {
+                println!(
+                    "  {} {gone} of its files moved since; 'apply last' skips them",
+                    "Note:".yellow()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Save the working set for `--resume`; failing to is only worth a warning
+    fn save_session(&self) {
+        let saved = SavedSession {
+            saved: Local::now(),
+            current_dir: self.current_dir.clone(),
+            dir_stack: self.dir_stack.clone(),
+            last_plan: self.last_plan.clone(),
+            pending: self.pending.clone(),
+        };
+        let file = session_file();
+        let written = serde_json::to_vec_pretty(&saved)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|json| {
+                journal::write_state_file(JournalKey::from_env()?.as_ref(), &file, &json)
+            });
+        if let Err(e) = written {
+            eprintln!(
+                "{}: session not saved to {} ({e})",
+                "Warning".yellow(),
+                file.display()
+            );
+        }
+    }
+
+    /// Run the REPL commands in `path`, one per line
+    ///
+    /// Blank lines and lines starting with `#` are skipped, and `quit` or
+    /// `exit` ends the script. Each command is echoed before it runs, and the
+    /// first failing command stops the script with its file and line.
+    pub fn run_script(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if self.source_depth >= MAX_SOURCE_DEPTH {
+            return Err(format!(
+                "{}: scripts nested more than {MAX_SOURCE_DEPTH} deep",
+                path.display()
+            )
+            .into());
+        }
+        let script = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+
+        self.source_depth += 1;
+        let result = self.run_script_lines(path, &script);
+        self.source_depth -= 1;
+        // `quit` ends the script it is in, not the session that sourced it
+        self.quitting = false;
+        result
+    }
+
+    fn run_script_lines(&mut self, path: &Path, script: &str) -> Result<(), Box<dyn Error>> {
+        for (number, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            println!("{} {}", "smv>".green().bold(), line);
+            self.execute_command(line)
+                .map_err(|e| format!("{}:{}: {e}", path.display(), number + 1))?;
+            if self.quitting {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Ask a yes/no question; `-F` answers yes without reading input
+    fn confirm(&self, question: &str) -> Result<bool, Box<dyn Error>> {
+        print!("{question} [y/N] ");
+        if self.assume_yes {
+            println!("{}", "y (-F)".dimmed());
+            return Ok(true);
+        }
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        Ok(input.trim().eq_ignore_ascii_case("y"))
+    }
+
     /// Run the REPL session
+    ///
+    /// The working set is saved after every command; see [`Self::resume`].
     pub fn run(&mut self) -> Result<(), Box<dyn Error>> {
         self.display_welcome();
 
@@ -157,6 +368,10 @@ impl InteractiveSession {
                     if let Err(e) = self.execute_command(line) {
                         eprintln!("{}: {}", "Error".red(), e);
                     }
+                    self.save_session();
+                    if self.quitting {
+                        break;
+                    }
                 }
                 Err(ReadlineError::Interrupted) => {
                     println!("CTRL-C");
@@ -186,14 +401,21 @@ impl InteractiveSession {
         match parts[0] {
             "preview" => self.cmd_preview(&parts[1..]),
             "apply" => self.cmd_apply(&parts[1..]),
-            "undo" => self.cmd_undo(),
+            "undo" => self.cmd_undo(&parts[1..]),
             "cd" => self.cmd_cd(&parts[1..]),
+            "pushd" => self.cmd_pushd(&parts[1..]),
+            "popd" => self.cmd_popd(),
+            "dirs" => self.cmd_dirs(),
+            "source" => self.cmd_source(&parts[1..]),
+            "copy" => self.cmd_copy(),
             "ls" => self.cmd_ls(&parts[1..]),
             "rename" => self.cmd_rename(&parts[1..]),
+            "show" => self.cmd_show(),
             "help" => self.cmd_help(),
             "quit" | "exit" => {
                 println!("Goodbye!");
-                process::exit(0);
+                self.quitting = true;
+                Ok(())
             }
             _ => {
                 // Check if the command is a transformation type
@@ -230,6 +452,13 @@ impl InteractiveSession {
             "•".green(),
             "help".cyan().bold()
         );
+        if !self.resumed && journal::state_file_exists(&session_file()) {
+            println!(
+                "  {} The last session was saved; {} carries on with it",
+                "•".green(),
+                "smv -I --resume".cyan().bold()
+            );
+        }
         println!("{}", "━".repeat(60).dimmed());
     }
 
@@ -262,6 +491,26 @@ impl InteractiveSession {
             "cd <dir>".cyan(),
             "Change to specified directory"
         );
+        println!(
+            "  {:<cmd_width$} {:<desc_width$}",
+            "pushd <dir>".cyan(),
+            "Save the current directory and change to <dir>"
+        );
+        println!(
+            "  {:<cmd_width$} {:<desc_width$}",
+            "pushd".cyan(),
+            "Swap the current directory with the last saved one"
+        );
+        println!(
+            "  {:<cmd_width$} {:<desc_width$}",
+            "popd".cyan(),
+            "Return to the last saved directory"
+        );
+        println!(
+            "  {:<cmd_width$} {:<desc_width$}",
+            "dirs".cyan(),
+            "Show the current and saved directories"
+        );
 
         // Transformation commands
         println!("\n  {}", "Transformation Commands:".yellow());
@@ -285,6 +534,16 @@ impl InteractiveSession {
             "apply <transform> <files>".white().dimmed(),
             "Example: apply snake *.txt"
         );
+        println!(
+            "  {:<cmd_width$} {:<desc_width$}",
+            "apply last".cyan(),
+            "Apply exactly the last preview, without matching again"
+        );
+        println!(
+            "  {:<cmd_width$} {:<desc_width$}",
+            "show".cyan(),
+            "Print the last preview again"
+        );
         println!(
             "  {:<cmd_width$} {:<desc_width$}",
             "<transform>".cyan(),
@@ -303,11 +562,26 @@ impl InteractiveSession {
             "undo".cyan(),
             "Revert the last operation"
         );
+        println!(
+            "  {:<cmd_width$} {:<desc_width$}",
+            "undo select [list]".cyan(),
+            "Revert only some files of the last batch (e.g. 1,3-5)"
+        );
+        println!(
+            "  {:<cmd_width$} {:<desc_width$}",
+            "copy".cyan(),
+            "Copy the new paths of the last renamed files to the clipboard"
+        );
         println!(
             "  {:<cmd_width$} {:<desc_width$}",
             "help".cyan(),
             "Display this help information"
         );
+        println!(
+            "  {:<cmd_width$} {:<desc_width$}",
+            "source <file>".cyan(),
+            "Run the commands in a file, one per line (# comments)"
+        );
         println!(
             "  {:<cmd_width$} {:<desc_width$}",
             "quit/exit".cyan(),
@@ -360,26 +634,78 @@ impl InteractiveSession {
 
     /// Change current directory
     fn cmd_cd(&mut self, args: &[&str]) -> Result<(), Box<dyn Error>> {
-        if args.is_empty() {
+        let target_dir = if args.is_empty() {
             // Default to home directory if no args
-            let home = dirs::home_dir().ok_or("Could not determine home directory")?;
-            self.current_dir = home;
+            dirs::home_dir().ok_or("Could not determine home directory")?
         } else {
-            let new_dir = Path::new(args[0]);
-            let target_dir = if new_dir.is_absolute() {
-                new_dir.to_path_buf()
-            } else {
-                self.current_dir.join(new_dir)
-            };
+            self.resolve_dir(args[0])?
+        };
+        self.enter_dir(target_dir)
+    }
 
-            if target_dir.is_dir() {
-                self.current_dir = target_dir;
-            } else {
-                return Err(format!("Directory not found: {}", args[0]).into());
+    /// `dir` relative to the current directory, if it is a directory
+    fn resolve_dir(&self, dir: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let new_dir = Path::new(dir);
+        let target_dir = if new_dir.is_absolute() {
+            new_dir.to_path_buf()
+        } else {
+            self.current_dir.join(new_dir)
+        };
+
+        if target_dir.is_dir() {
+            // Keep `..` and `.` out of the prompt and the directory stack
+            Ok(target_dir.canonicalize().unwrap_or(target_dir))
+        } else {
+            Err(format!("Directory not found: {dir}").into())
+        }
+    }
+
+    fn enter_dir(&mut self, dir: PathBuf) -> Result<(), Box<dyn Error>> {
+        env::set_current_dir(&dir)?;
+        self.current_dir = dir;
+        Ok(())
+    }
+
+    /// Save the current directory and change to another, or swap with the saved one
+    fn cmd_pushd(&mut self, args: &[&str]) -> Result<(), Box<dyn Error>> {
+        let target_dir = match args.first() {
+            Some(dir) => self.resolve_dir(dir)?,
+            None => self
+                .dir_stack
+                .pop()
+                .ok_or("No other directory: the directory stack is empty")?,
+        };
+        let previous = self.current_dir.clone();
+        if let Err(e) = self.enter_dir(target_dir.clone()) {
+            if args.is_empty() {
+                self.dir_stack.push(target_dir);
             }
+            return Err(e);
         }
+        self.dir_stack.push(previous);
+        self.cmd_dirs()
+    }
+
+    /// Return to the directory saved last
+    fn cmd_popd(&mut self) -> Result<(), Box<dyn Error>> {
+        let target_dir = self.dir_stack.pop().ok_or("The directory stack is empty")?;
+        if let Err(e) = self.enter_dir(target_dir.clone()) {
+            self.dir_stack.push(target_dir);
+            return Err(e);
+        }
+        self.cmd_dirs()
+    }
 
-        env::set_current_dir(&self.current_dir)?;
+    /// Print the current directory followed by the saved ones, newest first
+    fn cmd_dirs(&self) -> Result<(), Box<dyn Error>> {
+        println!(
+            "  {} {}",
+            "0".dimmed(),
+            self.current_dir.display().to_string().cyan()
+        );
+        for (index, dir) in self.dir_stack.iter().rev().enumerate() {
+            println!("  {} {}", (index + 1).to_string().dimmed(), dir.display());
+        }
         Ok(())
     }
 
@@ -389,8 +715,11 @@ impl InteractiveSession {
         let path_pattern = self.current_dir.join(pattern);
         let pattern_str = path_pattern.to_string_lossy();
 
+        let width = layout::terminal_width().unwrap_or(80);
+        let rule = width.min(60);
+
         // Display header
-        println!("\n{}", "━".repeat(60).dimmed());
+        println!("\n{}", "━".repeat(rule).dimmed());
         println!(
             "{} {}",
             "Directory:".blue().bold(),
@@ -399,7 +728,7 @@ impl InteractiveSession {
         if pattern != "*" {
             println!("{} {}", "Pattern:".blue().bold(), pattern.yellow());
         }
-        println!("{}", "━".repeat(60).dimmed());
+        println!("{}", "━".repeat(rule).dimmed());
 
         // Use glob pattern matching
         let mut files = Vec::new();
@@ -435,44 +764,23 @@ impl InteractiveSession {
         // Format and display directories
         if !dirs.is_empty() {
             println!("\n{}", "Directories:".cyan().bold());
-
-            let mut output = String::new();
-            for (i, dir) in dirs.iter().enumerate() {
-                let formatted = format!("  {dir}/").blue().bold().to_string();
-                output.push_str(&formatted);
-
-                // Add padding and handle line breaks
-                if (i + 1) % 3 == 0 || i == dirs.len() - 1 {
-                    output.push('\n');
-                } else {
-                    output.push_str("  ");
-                }
+            let names: Vec<String> = dirs.iter().map(|dir| format!("{dir}/")).collect();
+            for line in layout::columns(&names, width, "  ") {
+                println!("{}", line.blue().bold());
             }
-            print!("{output}");
         }
 
         // Format and display files
         if !files.is_empty() {
             println!("\n{}", "Files:".green().bold());
-
-            let mut output = String::new();
-            for (i, file) in files.iter().enumerate() {
-                let formatted = format!("  {file}").white().to_string();
-                output.push_str(&formatted);
-
-                // Add padding and handle line breaks
-                if (i + 1) % 3 == 0 || i == files.len() - 1 {
-                    output.push('\n');
-                } else {
-                    output.push_str("  ");
-                }
+            for line in layout::columns(&files, width, "  ") {
+                println!("{}", line.white());
             }
-            print!("{output}");
         }
 
         // Display summary
         println!("\n{}", "Summary:".cyan().bold());
-        println!("{}", "┈".repeat(60).dimmed());
+        println!("{}", "┈".repeat(rule).dimmed());
         println!("  {} directories", dirs.len().to_string().blue().bold());
         println!("  {} files", files.len().to_string().green().bold());
 
@@ -500,8 +808,28 @@ impl InteractiveSession {
         Ok(())
     }
 
+    /// Copy where the last batch's files ended up to the clipboard
+    fn cmd_copy(&self) -> Result<(), Box<dyn Error>> {
+        let names = clipboard::new_paths(&self.history_manager, None);
+        if names.is_empty() {
+            return Err("Nothing renamed yet to copy".into());
+        }
+        let tool = clipboard::copy_paths(&names)?;
+        println!("Copied {} path(s) to the clipboard ({tool})", names.len());
+        Ok(())
+    }
+
+    /// Run a script of REPL commands, relative to the current directory
+    fn cmd_source(&mut self, args: &[&str]) -> Result<(), Box<dyn Error>> {
+        let [file] = args else {
+            return Err("Usage: source <file>".into());
+        };
+        let path = self.current_dir.join(file);
+        self.run_script(&path)
+    }
+
     /// Preview transformation without applying
-    fn cmd_preview(&self, args: &[&str]) -> Result<(), Box<dyn Error>> {
+    fn cmd_preview(&mut self, args: &[&str]) -> Result<(), Box<dyn Error>> {
         if args.len() < 2 {
             return Err("Usage: preview <transform> <file_pattern>".into());
         }
@@ -514,6 +842,9 @@ impl InteractiveSession {
 
     /// Apply transformation to files
     fn cmd_apply(&mut self, args: &[&str]) -> Result<(), Box<dyn Error>> {
+        if args == ["last"] {
+            return self.apply_last();
+        }
         if args.len() < 2 {
             return Err("Usage: apply <transform> <file_pattern>".into());
         }
@@ -524,8 +855,12 @@ impl InteractiveSession {
         self.apply_transform(transform_type, &args[1..])
     }
 
-    /// Undo the last operation
-    fn cmd_undo(&mut self) -> Result<(), Box<dyn Error>> {
+    /// Undo the last operation, or with `select` only some files of the last batch
+    fn cmd_undo(&mut self, args: &[&str]) -> Result<(), Box<dyn Error>> {
+        if let Some(&("select" | "--select")) = args.first() {
+            return self.cmd_undo_select(&args[1..].join(" "));
+        }
+
         match self.history_manager.undo() {
             Ok(_) => {
                 println!("Operation undone successfully.");
@@ -535,6 +870,49 @@ impl InteractiveSession {
         }
     }
 
+    /// List the last batch and move back the files picked from it
+    fn cmd_undo_select(&mut self, selection: &str) -> Result<(), Box<dyn Error>> {
+        let batch = self.history_manager.last_batch()?;
+        if batch.is_empty() {
+            return Err("The last operation has no moved files to select from".into());
+        }
+
+        println!("\n{}", "Last batch:".cyan().bold());
+        println!("{}", "┈".repeat(60).dimmed());
+        for (index, entry) in batch.iter().enumerate() {
+            let current = entry.destination.file_name().unwrap_or_default();
+            let original = entry.source.file_name().unwrap_or_default();
+            println!(
+                "  {:>3}. \"{}\" {} \"{}\"",
+                index + 1,
+                current.to_string_lossy().white(),
+                "→".dimmed(),
+                original.to_string_lossy().green()
+            );
+        }
+
+        let selection = if selection.trim().is_empty() {
+            print!("\nRevert which files? (e.g. 1,3-5 or all; empty to cancel) ");
+            io::stdout().flush()?;
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            input
+        } else {
+            selection.to_string()
+        };
+        if selection.trim().is_empty() {
+            println!("Operation cancelled.");
+            return Ok(());
+        }
+
+        let indexes = history::parse_selection(&selection, batch.len())?;
+        let reverted = self
+            .history_manager
+            .undo_selected(&batch, &indexes, false)?;
+        println!("Reverted {reverted} file(s). Use 'undo' to move them forward again.");
+        Ok(())
+    }
+
     /// Interactive rename wizard
     fn cmd_rename(&mut self, args: &[&str]) -> Result<(), Box<dyn Error>> {
         if args.is_empty() {
@@ -605,6 +983,7 @@ impl InteractiveSession {
 
         // Preview transformations
         let mut changes = Vec::new();
+        let mut protection = Protection::new();
         println!("\nPreview of changes:");
 
         for path in &files {
@@ -621,6 +1000,10 @@ impl InteractiveSession {
                 println!("  \"{filename}\" → (no change needed)");
                 continue;
             }
+            if let Some(reason) = protection.reason(path) {
+                println!("  \"{filename}\" → {} ({reason})", "PROTECTED".yellow());
+                continue;
+            }
 
             // Create the new path
             let parent = path.parent().unwrap_or(Path::new(""));
@@ -647,18 +1030,14 @@ impl InteractiveSession {
         }
 
         // Confirm with user
-        print!("\nApply these changes? [y/N] ");
-        io::stdout().flush()?;
-
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-
-        if !input.trim().eq_ignore_ascii_case("y") {
+        println!();
+        if !self.confirm("Apply these changes?")? {
             println!("Operation cancelled.");
             return Ok(());
         }
 
         // Apply changes
+        self.history_manager.start_batch();
         for (src, dst) in changes {
             // Record the operation for undo
             self.history_manager.record(src.clone(), dst.clone())?;
@@ -686,130 +1065,40 @@ impl InteractiveSession {
     }
 
     /// Preview transformation on files
+    ///
+    /// The plan is kept so `apply last` runs exactly these renames and `show`
+    /// prints them again.
     fn preview_transform(
-        &self,
+        &mut self,
         transform_type: TransformType,
         patterns: &[&str],
     ) -> Result<(), Box<dyn Error>> {
-        let mut changes = Vec::new();
-        let mut no_changes = Vec::new();
-        let mut conflicts = Vec::new();
-
         // Display header
-        println!("\n{}", "━".repeat(60).dimmed());
-        println!(
-            "{} {} {}",
-            "Preview:".blue().bold(),
-            transform_type.as_str().yellow().bold(),
-            format!("({})", patterns.join(", ")).dimmed()
-        );
-        println!("{}", "━".repeat(60).dimmed());
-
-        // Process each file pattern
-        for pattern in patterns {
-            let path_pattern = self.current_dir.join(pattern);
-            let pattern_str = path_pattern.to_string_lossy();
+        print_header("Preview:", &transform_type, &patterns.join(", "));
 
-            for entry in glob(&pattern_str)? {
-                match entry {
-                    Ok(path) => {
-                        // Skip directories
-                        if path.is_dir() {
-                            continue;
-                        }
-
-                        // Get the file name
-                        let filename = path
-                            .file_name()
-                            .ok_or("Invalid file name")?
-                            .to_string_lossy();
-
-                        // Apply the transformation
-                        let new_name = transform(&filename, &transform_type);
-
-                        // Create the new path
-                        let parent = path.parent().unwrap_or(Path::new(""));
-                        let new_path = parent.join(&new_name);
-
-                        // If the name hasn't changed, track but don't show
-                        if filename == new_name {
-                            no_changes.push(filename.to_string());
-                            continue;
-                        }
-
-                        // Check for conflicts
-                        if new_path.exists() && path != new_path {
-                            conflicts.push((filename.to_string(), new_name.to_string()));
-                            continue;
-                        }
-
-                        changes.push((
-                            path.clone(),
-                            new_path.clone(),
-                            filename.to_string(),
-                            new_name.to_string(),
-                        ));
-                    }
-                    Err(e) => eprintln!("  {} {}", "Error:".red().bold(), e),
-                }
-            }
-        }
-
-        // Display the results in a structured way
-        if !changes.is_empty() {
-            println!("\n{}", "Files to rename:".green().bold());
-            println!("{}", "┈".repeat(60).dimmed());
-            for (_, _, src_name, dst_name) in &changes {
-                println!("  \"{}\" {}", src_name.white(), "→".dimmed());
-                println!("     \"{}\"", dst_name.green());
-            }
-        }
-
-        if !conflicts.is_empty() {
-            println!("\n{}", "Conflicts detected:".red().bold());
-            println!("{}", "┈".repeat(60).dimmed());
-            for (src_name, dst_name) in &conflicts {
-                println!("  \"{}\" {}", src_name, "→".dimmed());
-                println!(
-                    "     \"{}\" {}",
-                    dst_name.dimmed(),
-                    "File already exists".red()
-                );
-            }
-        }
-
-        // Summary
-        println!("\n{}", "Summary:".cyan().bold());
-        println!("{}", "┈".repeat(60).dimmed());
-        println!(
-            "  {} files matched pattern",
-            (changes.len() + conflicts.len() + no_changes.len())
-                .to_string()
-                .white()
-                .bold()
-        );
-        println!(
-            "  {} files ready to rename",
-            changes.len().to_string().green().bold()
-        );
-        println!(
-            "  {} files with conflicts",
-            conflicts.len().to_string().red().bold()
-        );
-        println!(
-            "  {} files with no changes needed",
-            no_changes.len().to_string().yellow()
-        );
+        let plan = self.collect_plan(transform_type, patterns)?;
+        plan.print(self.reporter.as_mut());
 
         // Instructions
-        if !changes.is_empty() {
+        if !plan.changes.is_empty() {
             println!("\n{}", "To apply these changes:".cyan());
             println!(
                 "  {}",
-                format!("apply {} {}", transform_type.as_str(), patterns.join(" ")).white()
+                format!(
+                    "apply {} {}",
+                    plan.transform_type.as_str(),
+                    patterns.join(" ")
+                )
+                .white()
+            );
+            println!(
+                "  {} {}",
+                "apply last".white(),
+                "(exactly the files above, without matching again)".dimmed()
             );
         }
 
+        self.last_plan = Some(plan);
         Ok(())
     }
 
@@ -819,19 +1108,70 @@ impl InteractiveSession {
         transform_type: TransformType,
         patterns: &[&str],
     ) -> Result<(), Box<dyn Error>> {
-        let mut changes = Vec::new();
-        let mut no_changes = Vec::new();
-        let mut conflicts = Vec::new();
-
         // Display header
-        println!("\n{}", "━".repeat(60).dimmed());
-        println!(
-            "{} {} {}",
-            "Apply:".blue().bold(),
-            transform_type.as_str().yellow().bold(),
-            format!("({})", patterns.join(", ")).dimmed()
+        print_header("Apply:", &transform_type, &patterns.join(", "));
+
+        let plan = self.collect_plan(transform_type, patterns)?;
+        if plan.changes.is_empty() && plan.conflicts.is_empty() {
+            println!("\n{}", "No files found or no changes needed.".yellow());
+            return Ok(());
+        }
+        plan.print(self.reporter.as_mut());
+        self.confirm_and_rename(plan)?;
+        Ok(())
+    }
+
+    /// Apply the last previewed plan as it was shown
+    fn apply_last(&mut self) -> Result<(), Box<dyn Error>> {
+        let plan = self
+            .last_plan
+            .clone()
+            .ok_or("Nothing previewed yet; run 'preview <transform> <files>' first")?;
+
+        print_header(
+            "Apply:",
+            &plan.transform_type,
+            &format!("previewed plan: {}", plan.patterns.join(", ")),
         );
-        println!("{}", "━".repeat(60).dimmed());
+        plan.print(self.reporter.as_mut());
+        if self.confirm_and_rename(plan)? {
+            // The files have moved on; the plan no longer describes them
+            self.last_plan = None;
+        }
+        Ok(())
+    }
+
+    /// Print the last previewed plan again
+    fn cmd_show(&mut self) -> Result<(), Box<dyn Error>> {
+        let plan = self
+            .last_plan
+            .as_ref()
+            .ok_or("Nothing previewed yet; run 'preview <transform> <files>' first")?;
+        print_header(
+            "Previewed:",
+            &plan.transform_type,
+            &plan.patterns.join(", "),
+        );
+        plan.print(self.reporter.as_mut());
+        if !plan.changes.is_empty() {
+            println!("\n{} {}", "To apply:".cyan(), "apply last".white());
+        }
+        Ok(())
+    }
+
+    /// Match `patterns` and work out the new name of every file
+    fn collect_plan(
+        &self,
+        transform_type: TransformType,
+        patterns: &[&str],
+    ) -> Result<RenamePlan, Box<dyn Error>> {
+        let mut plan = RenamePlan {
+            transform_type,
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            changes: Vec::new(),
+            conflicts: Vec::new(),
+            unchanged: 0,
+        };
 
         // Process each file pattern
         for pattern in patterns {
@@ -853,11 +1193,11 @@ impl InteractiveSession {
                             .to_string_lossy();
 
                         // Apply the transformation
-                        let new_name = transform(&filename, &transform_type);
+                        let new_name = transform(&filename, &plan.transform_type);
 
                         // If the name hasn't changed, track but don't show
                         if filename == new_name {
-                            no_changes.push(filename.to_string());
+                            plan.unchanged += 1;
                             continue;
                         }
 
@@ -867,129 +1207,58 @@ impl InteractiveSession {
 
                         // Check for conflicts
                         if new_path.exists() && path != new_path {
-                            conflicts.push((filename.to_string(), new_name.to_string()));
+                            plan.conflicts.push((filename.to_string(), new_name));
                             continue;
                         }
 
-                        changes.push((
-                            path.clone(),
-                            new_path.clone(),
-                            filename.to_string(),
-                            new_name.to_string(),
-                        ));
+                        plan.changes.push((path.clone(), new_path));
                     }
                     Err(e) => eprintln!("  {} {}", "Error:".red().bold(), e),
                 }
             }
         }
 
-        if changes.is_empty() && conflicts.is_empty() {
-            println!("\n{}", "No files found or no changes needed.".yellow());
-            return Ok(());
-        }
-
-        // Display the results in a structured way
-        if !changes.is_empty() {
-            println!("\n{}", "Files to rename:".green().bold());
-            println!("{}", "┈".repeat(60).dimmed());
-            for (_src, _dst, src_name, dst_name) in &changes {
-                println!("  \"{}\" {}", src_name.white(), "→".dimmed());
-                println!("     \"{}\"", dst_name.green());
-            }
-        }
-
-        if !conflicts.is_empty() {
-            println!("\n{}", "Conflicts detected:".red().bold());
-            println!("{}", "┈".repeat(60).dimmed());
-            for (src_name, dst_name) in &conflicts {
-                println!("  \"{}\" {}", src_name, "→".dimmed());
-                println!(
-                    "     \"{}\" {}",
-                    dst_name.dimmed(),
-                    "File already exists".red()
-                );
-            }
-        }
-
-        // Summary
-        println!("\n{}", "Summary:".cyan().bold());
-        println!("{}", "┈".repeat(60).dimmed());
-        println!(
-            "  {} files matched pattern",
-            (changes.len() + conflicts.len() + no_changes.len())
-                .to_string()
-                .white()
-                .bold()
-        );
-        println!(
-            "  {} files ready to rename",
-            changes.len().to_string().green().bold()
-        );
-        println!(
-            "  {} files with conflicts",
-            conflicts.len().to_string().red().bold()
-        );
-        println!(
-            "  {} files with no changes needed",
-            no_changes.len().to_string().yellow()
-        );
+        Ok(plan)
+    }
 
+    /// Ask before renaming, then rename and record the batch for undo
+    ///
+    /// Stale entries are skipped (see [`plan::execute_renames`]). While the
+    /// question is open the plan is saved as pending, so `--resume` offers it
+    /// again when the session is cut off there. Returns whether it ran.
+    fn confirm_and_rename(&mut self, plan: RenamePlan) -> Result<bool, Box<dyn Error>> {
+        let changes = plan.changes.clone();
         if changes.is_empty() {
             println!("\n{}", "No changes to apply.".yellow());
-            return Ok(());
+            return Ok(false);
         }
 
         // Confirm with user
         println!("\n{}", "Confirmation:".cyan().bold());
         println!("{}", "┈".repeat(60).dimmed());
-        print!("Apply these changes? [y/N] ");
-        io::stdout().flush()?;
-
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-
-        if !input.trim().eq_ignore_ascii_case("y") {
+        if !self.assume_yes && self.source_depth == 0 {
+            self.pending = Some(plan);
+            self.save_session();
+        }
+        let answer = self.confirm("Apply these changes?");
+        self.pending = None;
+        if !answer? {
             println!("{}", "Operation cancelled.".yellow());
-            return Ok(());
+            return Ok(false);
         }
 
         // Apply changes section
         println!("\n{}", "Applying changes:".cyan().bold());
         println!("{}", "┈".repeat(60).dimmed());
 
-        let mut success_count = 0;
-        let mut error_count = 0;
-
-        for (src, dst, _, _) in changes {
-            // Record the operation for undo
-            self.history_manager.record(src.clone(), dst.clone())?;
-
-            // Perform the rename
-            match std::fs::rename(&src, &dst) {
-                Ok(_) => {
-                    let src_name = src.file_name().unwrap_or_default().to_string_lossy();
-                    let dst_name = dst.file_name().unwrap_or_default().to_string_lossy();
-                    println!(
-                        "  {} \"{}\" {} \"{}\"",
-                        "✓".green(),
-                        src_name.white(),
-                        "→".dimmed(),
-                        dst_name.green()
-                    );
-                    success_count += 1;
-                }
-                Err(e) => {
-                    let src_name = src.file_name().unwrap_or_default().to_string_lossy();
-                    eprintln!(
-                        "  {} {} \"{}\" - {}",
-                        "✗".red(),
-                        "Failed to rename".red(),
-                        src_name,
-                        e
-                    );
-                    error_count += 1;
-                }
-            }
+        let total = changes.len();
+        let done = plan::execute_renames(&changes, false, self.reporter.as_mut());
+        let success_count = done.len();
+        let error_count = total - success_count;
+        if !done.is_empty() {
+            self.history_manager.start_batch();
+            self.history_manager
+                .record_mapping(self.current_dir.clone(), &done)?;
         }
 
         // Result summary
@@ -1014,6 +1283,108 @@ impl InteractiveSession {
             );
         }
 
-        Ok(())
+        Ok(true)
     }
 }
+
+/// Renames worked out for a transform, as shown to the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RenamePlan {
+    #[serde(with = "transform_name")]
+    transform_type: TransformType,
+    patterns: Vec<String>,
+    /// `(old path, new path)` of every file that gets renamed
+    changes: Vec<(PathBuf, PathBuf)>,
+    /// `(old name, new name)` of files whose new name is taken
+    conflicts: Vec<(String, String)>,
+    /// Files whose name is already right
+    unchanged: usize,
+}
+
+impl RenamePlan {
+    /// Show the renames and the conflicts through `reporter`, then a summary
+    fn print(&self, reporter: &mut dyn Reporter) {
+        // Display the results in a structured way
+        if !self.changes.is_empty() {
+            println!("\n{}", "Files to rename:".green().bold());
+            println!("{}", "┈".repeat(60).dimmed());
+            for (src, dst) in &self.changes {
+                reporter.planned(src, dst);
+            }
+        }
+
+        if !self.conflicts.is_empty() {
+            println!("\n{}", "Conflicts detected:".red().bold());
+            println!("{}", "┈".repeat(60).dimmed());
+            for (src_name, dst_name) in &self.conflicts {
+                reporter.conflict(
+                    Path::new(src_name),
+                    Path::new(dst_name),
+                    "File already exists",
+                );
+            }
+        }
+
+        // Summary
+        println!("\n{}", "Summary:".cyan().bold());
+        println!("{}", "┈".repeat(60).dimmed());
+        println!(
+            "  {} files matched pattern",
+            (self.changes.len() + self.conflicts.len() + self.unchanged)
+                .to_string()
+                .white()
+                .bold()
+        );
+        println!(
+            "  {} files ready to rename",
+            self.changes.len().to_string().green().bold()
+        );
+        println!(
+            "  {} files with conflicts",
+            self.conflicts.len().to_string().red().bold()
+        );
+        println!(
+            "  {} files with no changes needed",
+            self.unchanged.to_string().yellow()
+        );
+    }
+}
+
+/// The transforms the REPL runs, saved by name
+mod transform_name {
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    use crate::transformers::TransformType;
+
+    pub fn serialize<S: Serializer>(
+        transform_type: &TransformType,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&transform_type.as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<TransformType, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        TransformType::from_str(&name)
+            .ok_or_else(|| D::Error::custom(format!("unknown transformation: {name}")))
+    }
+}
+
+/// Where the working set of the REPL is kept
+fn session_file() -> PathBuf {
+    paths::state_dir().join(SESSION_FILE)
+}
+
+/// `━━━` framed "Preview: snake (*.md)" header
+fn print_header(label: &str, transform_type: &TransformType, detail: &str) {
+    println!("\n{}", "━".repeat(60).dimmed());
+    println!(
+        "{} {} {}",
+        label.blue().bold(),
+        transform_type.as_str().yellow().bold(),
+        format!("({detail})").dimmed()
+    );
+    println!("{}", "━".repeat(60).dimmed());
+}
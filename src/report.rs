@@ -0,0 +1,461 @@
+//! Where library code sends messages meant for the user
+//!
+//! Operations that have something to say while they run (undo skipping a
+//! modified file, sort creating a directory) tell a [`Reporter`] instead of
+//! printing, so embedding smv does not write to the host's stdout. Renames are
+//! reported as events (planned, conflicting, done) rather than text, so the
+//! CLI, the REPL and the TUI render them the same way: the [`Console`] as
+//! colored text, [`Json`] as NDJSON for `--output json`, and the TUI in its
+//! status bar. The TUI and library users get [`Quiet`] unless they ask
+//! otherwise, or [`Collected`] to show the messages themselves.
+
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use colored::*;
+use serde_json::json;
+
+use crate::progress::OutputFormat;
+
+/// How much a message matters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// Progress and results ("Moving a → b")
+    Info,
+    /// Something was skipped or looks wrong, but the operation goes on
+    Warning,
+}
+
+/// What became of one rename
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Done,
+    /// Not attempted: the file or its new name changed since the plan was made
+    Skipped(String),
+    Failed(String),
+}
+
+/// Receives the messages and rename events an operation produces
+///
+/// Only [`Reporter::message`] is required; the events fall back to messages.
+pub trait Reporter: Send {
+    fn message(&mut self, level: Level, text: &str);
+
+    fn info(&mut self, text: &str) {
+        self.message(Level::Info, text);
+    }
+
+    fn warn(&mut self, text: &str) {
+        self.message(Level::Warning, text);
+    }
+
+    /// A rename in a plan, before anything runs
+    fn planned(&mut self, from: &Path, to: &Path) {
+        self.info(&format!("{} → {}", from.display(), to.display()));
+    }
+
+    /// A planned rename that cannot run
+    fn conflict(&mut self, from: &Path, to: &Path, reason: &str) {
+        self.warn(&format!("{} → {}: {reason}", from.display(), to.display()));
+    }
+
+    /// How many of the renames have been attempted
+    fn progress(&mut self, _done: usize, _total: usize) {}
+
+    /// What became of one rename
+    fn result(&mut self, from: &Path, to: &Path, outcome: &Outcome) {
+        match outcome {
+            Outcome::Done => self.info(&format!("{} → {}", from.display(), to.display())),
+            Outcome::Skipped(reason) => {
+                self.warn(&format!("Skipped {} - {reason}", from.display()))
+            }
+            Outcome::Failed(error) => {
+                self.warn(&format!("Failed to rename {} - {error}", from.display()))
+            }
+        }
+    }
+}
+
+/// The reporter for `--output text|json`
+pub fn for_format(format: OutputFormat) -> Box<dyn Reporter> {
+    match format {
+        OutputFormat::Text => Box::new(Console),
+        OutputFormat::Json => Box::new(Json),
+    }
+}
+
+impl fmt::Debug for dyn Reporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Reporter")
+    }
+}
+
+/// Shared, so the caller can read what a long-lived owner was told
+impl<R: Reporter> Reporter for Arc<Mutex<R>> {
+    fn message(&mut self, level: Level, text: &str) {
+        if let Ok(mut reporter) = self.lock() {
+            reporter.message(level, text);
+        }
+    }
+
+    fn planned(&mut self, from: &Path, to: &Path) {
+        if let Ok(mut reporter) = self.lock() {
+            reporter.planned(from, to);
+        }
+    }
+
+    fn conflict(&mut self, from: &Path, to: &Path, reason: &str) {
+        if let Ok(mut reporter) = self.lock() {
+            reporter.conflict(from, to, reason);
+        }
+    }
+
+    fn progress(&mut self, done: usize, total: usize) {
+        if let Ok(mut reporter) = self.lock() {
+            reporter.progress(done, total);
+        }
+    }
+
+    fn result(&mut self, from: &Path, to: &Path, outcome: &Outcome) {
+        if let Ok(mut reporter) = self.lock() {
+            reporter.result(from, to, outcome);
+        }
+    }
+}
+
+/// The last component of `path`, for showing renames within one directory
+fn name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Prints every message on stdout, as the command line always has, and
+/// renames as file names with the changed part highlighted
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Console;
+
+impl Reporter for Console {
+    fn message(&mut self, _level: Level, text: &str) {
+        println!("{text}");
+    }
+
+    fn planned(&mut self, from: &Path, to: &Path) {
+        println!("  \"{}\"", inline_diff(&name(from), &name(to)));
+    }
+
+    fn conflict(&mut self, from: &Path, to: &Path, reason: &str) {
+        println!("  \"{}\" {}", name(from), "→".dimmed());
+        println!("     \"{}\" {}", name(to).dimmed(), reason.red());
+    }
+
+    fn result(&mut self, from: &Path, to: &Path, outcome: &Outcome) {
+        match outcome {
+            Outcome::Done => println!(
+                "  {} \"{}\"",
+                "✓".green(),
+                inline_diff(&name(from), &name(to))
+            ),
+            Outcome::Skipped(reason) => eprintln!(
+                "  {} {} \"{}\" - {}",
+                "✗".red(),
+                "Skipped".red(),
+                name(from),
+                reason
+            ),
+            Outcome::Failed(error) => eprintln!(
+                "  {} {} \"{}\" - {}",
+                "✗".red(),
+                "Failed to rename".red(),
+                name(from),
+                error
+            ),
+        }
+    }
+}
+
+/// Writes every message and event as one JSON object per line on stdout
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Json;
+
+impl Json {
+    fn emit(value: serde_json::Value) {
+        println!("{value}");
+    }
+}
+
+impl Reporter for Json {
+    fn message(&mut self, level: Level, text: &str) {
+        let level = match level {
+            Level::Info => "info",
+            Level::Warning => "warning",
+        };
+        Self::emit(json!({"event": "message", "level": level, "text": text}));
+    }
+
+    fn planned(&mut self, from: &Path, to: &Path) {
+        Self::emit(json!({"event": "planned", "from": from, "to": to}));
+    }
+
+    fn conflict(&mut self, from: &Path, to: &Path, reason: &str) {
+        Self::emit(json!({"event": "conflict", "from": from, "to": to, "reason": reason}));
+    }
+
+    fn progress(&mut self, done: usize, total: usize) {
+        Self::emit(json!({"event": "progress", "done": done, "total": total}));
+    }
+
+    fn result(&mut self, from: &Path, to: &Path, outcome: &Outcome) {
+        let (status, reason) = match outcome {
+            Outcome::Done => ("done", None),
+            Outcome::Skipped(reason) => ("skipped", Some(reason)),
+            Outcome::Failed(error) => ("failed", Some(error)),
+        };
+        Self::emit(json!({
+            "event": "result",
+            "from": from,
+            "to": to,
+            "status": status,
+            "reason": reason,
+        }));
+    }
+}
+
+/// Drops every message
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Quiet;
+
+impl Reporter for Quiet {
+    fn message(&mut self, _level: Level, _text: &str) {}
+}
+
+/// Keeps the messages for the caller to show
+#[allow(dead_code)]
+#[derive(Debug, Default, Clone)]
+pub struct Collected {
+    pub messages: Vec<(Level, String)>,
+}
+
+#[allow(dead_code)]
+impl Collected {
+    pub fn warnings(&self) -> impl Iterator<Item = &str> {
+        self.messages
+            .iter()
+            .filter(|(level, _)| *level == Level::Warning)
+            .map(|(_, text)| text.as_str())
+    }
+}
+
+impl Reporter for Collected {
+    fn message(&mut self, level: Level, text: &str) {
+        self.messages.push((level, text.to_string()));
+    }
+}
+
+/// Split two names into their common prefix, the differing middles and the
+/// common suffix, working on characters so multi-byte names split cleanly
+fn split_change<'a>(old: &'a str, new: &'a str) -> (&'a str, &'a str, &'a str, &'a str) {
+    let prefix = old
+        .char_indices()
+        .zip(new.chars())
+        .find(|((_, a), b)| a != b)
+        .map_or(old.len().min(new.len()), |((i, _), _)| i);
+    // The prefix is a char boundary in both names, since they agree up to it
+    let (old_rest, new_rest) = (&old[prefix..], &new[prefix..]);
+    let suffix: usize = old_rest
+        .chars()
+        .rev()
+        .zip(new_rest.chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+    (
+        &old[..prefix],
+        &old_rest[..old_rest.len() - suffix],
+        &new_rest[..new_rest.len() - suffix],
+        &old_rest[old_rest.len() - suffix..],
+    )
+}
+
+/// What kind of edit a rename is, worded so renames of the same kind agree:
+/// `prefix IMG_ removed`, `spaces → underscores`, `lowercased`
+///
+/// Digits in inserted or removed text are masked, so date prefixes added to
+/// different files count as one kind of edit.
+pub fn edit_class(old: &str, new: &str) -> String {
+    // Case changes and separators swapped for others everywhere they differ
+    let (old_chars, new_chars): (Vec<char>, Vec<char>) = (
+        old.to_lowercase().chars().collect(),
+        new.to_lowercase().chars().collect(),
+    );
+    if old_chars.len() == new_chars.len() {
+        let mut swaps: Vec<(char, char)> = old_chars
+            .iter()
+            .zip(&new_chars)
+            .filter(|(a, b)| a != b)
+            .map(|(a, b)| (*a, *b))
+            .collect();
+        swaps.sort_unstable();
+        swaps.dedup();
+        let consistent = swaps.windows(2).all(|pair| pair[0].0 != pair[1].0);
+        if consistent
+            && swaps
+                .iter()
+                .all(|(a, b)| !a.is_alphanumeric() && !b.is_alphanumeric())
+        {
+            let swapped: String = old
+                .chars()
+                .map(|c| swaps.iter().find(|(a, _)| *a == c).map_or(c, |(_, b)| *b))
+                .collect();
+            let case = if swapped == new {
+                None
+            } else if new == swapped.to_lowercase() {
+                Some("lowercased".to_string())
+            } else if new == swapped.to_uppercase() {
+                Some("uppercased".to_string())
+            } else {
+                Some("case changed".to_string())
+            };
+            let described: Vec<String> = case
+                .into_iter()
+                .chain(
+                    swaps
+                        .iter()
+                        .map(|(a, b)| format!("{} → {}", char_name(*a), char_name(*b))),
+                )
+                .collect();
+            return described.join(", ");
+        }
+    }
+
+    let (old_path, new_path) = (Path::new(old), Path::new(new));
+    if old_path.file_stem() == new_path.file_stem() {
+        let extension = |path: &Path| {
+            path.extension().map_or(String::from("none"), |e| {
+                format!(".{}", e.to_string_lossy())
+            })
+        };
+        return format!(
+            "extension {} → {}",
+            extension(old_path),
+            extension(new_path)
+        );
+    }
+
+    let (prefix, removed, added, suffix) = split_change(old, new);
+    // Separators alone are named; other text is shown trimmed with digits masked
+    let mask = |text: &str| -> String {
+        match text.trim().is_empty() {
+            true => text.chars().next().map(char_name).unwrap_or_default(),
+            false => text
+                .trim()
+                .chars()
+                .map(|c| if c.is_ascii_digit() { '#' } else { c })
+                .collect(),
+        }
+    };
+    let at_end = suffix.is_empty() || (suffix.starts_with('.') && !suffix[1..].contains('.'));
+    let place = if prefix.is_empty() {
+        "prefix "
+    } else if at_end {
+        "suffix "
+    } else {
+        ""
+    };
+    match (removed.is_empty(), added.is_empty()) {
+        (false, true) => format!("{place}{} removed", mask(removed)),
+        (true, false) => format!("{place}{} added", mask(added)),
+        _ => format!("{place}{} → {}", mask(removed), mask(added)),
+    }
+}
+
+/// Plural name of a separator character, for [`edit_class`]
+fn char_name(c: char) -> String {
+    match c {
+        ' ' => "spaces".to_string(),
+        '_' => "underscores".to_string(),
+        '-' => "hyphens".to_string(),
+        '.' => "dots".to_string(),
+        _ => format!("'{c}'"),
+    }
+}
+
+/// One name showing what a rename changes: `photo[ 01→_01].jpg`
+///
+/// With colors the brackets are dropped: the removed text is struck through
+/// in red and the added text is green.
+pub fn inline_diff(old: &str, new: &str) -> String {
+    let (prefix, removed, added, suffix) = split_change(old, new);
+    if colored::control::SHOULD_COLORIZE.should_colorize() {
+        format!(
+            "{}{}{}{}",
+            prefix.white(),
+            removed.red().strikethrough(),
+            added.green().bold(),
+            suffix.white()
+        )
+    } else {
+        format!("{prefix}[{removed}→{added}]{suffix}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_change() {
+        assert_eq!(
+            split_change("Photo 01.jpg", "photo_01.jpg"),
+            ("", "Photo ", "photo_", "01.jpg")
+        );
+        assert_eq!(
+            split_change("report.TXT", "report.txt"),
+            ("report.", "TXT", "txt", "")
+        );
+        // Repeated letters are not claimed by both the prefix and the suffix
+        assert_eq!(split_change("aa", "aaa"), ("aa", "", "a", ""));
+        assert_eq!(
+            split_change("café menu.md", "café-menu.md"),
+            ("café", " ", "-", "menu.md")
+        );
+    }
+
+    #[test]
+    fn test_edit_class() {
+        assert_eq!(
+            edit_class("IMG_0412.jpg", "0412.jpg"),
+            "prefix IMG_ removed"
+        );
+        assert_eq!(
+            edit_class("My Holiday 2.jpg", "My_Holiday_2.jpg"),
+            "spaces → underscores"
+        );
+        assert_eq!(
+            edit_class("a b-c.txt", "a_b_c.txt"),
+            "spaces → underscores, hyphens → underscores"
+        );
+        assert_eq!(edit_class("README.MD", "readme.md"), "lowercased");
+        assert_eq!(edit_class("notes.md", "Notes.md"), "case changed");
+        assert_eq!(
+            edit_class("My File.txt", "my_file.txt"),
+            "lowercased, spaces → underscores"
+        );
+        assert_eq!(
+            edit_class("notes.md", "2024-05-01-notes.md"),
+            "prefix ####-##-##- added"
+        );
+        assert_eq!(
+            edit_class("scan.pdf", "scan-final.pdf"),
+            "suffix -final added"
+        );
+        assert_eq!(
+            edit_class("report.jpeg", "report.jpg"),
+            "extension .jpeg → .jpg"
+        );
+        assert_eq!(edit_class("a (copy) b.txt", "a b.txt"), "(copy) removed");
+        assert_eq!(edit_class("a  b.txt", "a b.txt"), "spaces removed");
+    }
+}
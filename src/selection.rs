@@ -0,0 +1,214 @@
+//! Ordering and narrowing matched files with `BY:`, `LIMIT:`, `FIRST:` and `SAMPLE:`
+//!
+//! Filters decide which files match; these modifiers decide the order matches are
+//! processed and previewed in, and how many of them an operation actually touches,
+//! which makes it cheap to try a risky transform on a handful of files before
+//! running it over the whole tree.
+
+use crate::cnp_grammar::{Limit, SampleSize, SortKey, SortOrder};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Order files by `sort`, then keep the ones selected by `limit`
+///
+/// `LIMIT:` and `SAMPLE:` keep the order; `FIRST:` without `BY:` takes the newest.
+pub fn apply(
+    mut files: Vec<PathBuf>,
+    limit: Option<Limit>,
+    sort: Option<SortOrder>,
+) -> Vec<PathBuf> {
+    if let Some(order) = sort {
+        sort_files(&mut files, order);
+    }
+    match limit {
+        None => files,
+        Some(Limit::Count(count)) => {
+            files.truncate(count);
+            files
+        }
+        Some(Limit::First(count)) => {
+            if sort.is_none() {
+                sort_files(&mut files, SortOrder::new(SortKey::Modified));
+            }
+            files.truncate(count);
+            files
+        }
+        Some(Limit::Sample(size)) => {
+            let count = sample_count(files.len(), size);
+            sample(files, count, seed())
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum SortValue {
+    Name(String),
+    Modified(Option<SystemTime>),
+    Size(Option<u64>),
+}
+
+fn sort_value(path: &Path, key: SortKey) -> SortValue {
+    match key {
+        SortKey::Name => SortValue::Name(
+            path.file_name()
+                .map(|name| name.to_string_lossy().to_lowercase())
+                .unwrap_or_default(),
+        ),
+        SortKey::Modified => SortValue::Modified(
+            fs::symlink_metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok(),
+        ),
+        SortKey::Size => SortValue::Size(fs::symlink_metadata(path).map(|m| m.len()).ok()),
+    }
+}
+
+/// Order files by `order`; ties keep their walk order
+pub fn sort_files(files: &mut Vec<PathBuf>, order: SortOrder) {
+    let mut keyed: Vec<(SortValue, PathBuf)> = files
+        .drain(..)
+        .map(|path| (sort_value(&path, order.key), path))
+        .collect();
+    keyed.sort_by(|a, b| {
+        if order.descending {
+            b.0.cmp(&a.0)
+        } else {
+            a.0.cmp(&b.0)
+        }
+    });
+    files.extend(keyed.into_iter().map(|(_, path)| path));
+}
+
+/// Human description of the selection for headers ("first 10 by mtime, newest first")
+pub fn describe(limit: Limit, sort: Option<SortOrder>) -> String {
+    match limit {
+        Limit::Count(count) => format!("at most {count}"),
+        Limit::First(count) => format!(
+            "first {} by {}",
+            count,
+            sort.unwrap_or(SortOrder::new(SortKey::Modified)).describe()
+        ),
+        Limit::Sample(SampleSize::Count(count)) => format!("random sample of {count}"),
+        Limit::Sample(SampleSize::Percent(percent)) => format!("random {percent}% sample"),
+    }
+}
+
+/// Number of files a sample keeps; a percentage of a non-empty list keeps at least one
+fn sample_count(total: usize, size: SampleSize) -> usize {
+    match size {
+        SampleSize::Count(count) => count.min(total),
+        SampleSize::Percent(percent) => {
+            let count = (total as f64 * percent / 100.0).round() as usize;
+            count.clamp(total.min(1), total)
+        }
+    }
+}
+
+/// Pick `count` files at random, keeping them in their original order
+fn sample(files: Vec<PathBuf>, count: usize, seed: u64) -> Vec<PathBuf> {
+    let mut state = seed;
+    let mut indices: Vec<usize> = (0..files.len()).collect();
+    // Partial Fisher-Yates shuffle: the first `count` slots end up a uniform sample
+    for slot in 0..count {
+        let pick = slot + (next_random(&mut state) % (files.len() - slot) as u64) as usize;
+        indices.swap(slot, pick);
+    }
+    indices.truncate(count);
+    indices.sort_unstable();
+
+    let mut files: Vec<Option<PathBuf>> = files.into_iter().map(Some).collect();
+    indices
+        .into_iter()
+        .filter_map(|index| files[index].take())
+        .collect()
+}
+
+/// A seed that differs between runs
+pub(crate) fn seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or(0);
+    nanos ^ (u64::from(std::process::id()) << 32)
+}
+
+/// SplitMix64; good enough to pick files, and no extra dependency
+pub(crate) fn next_random(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn test_limit_and_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let small = dir.path().join("small.txt");
+        let large = dir.path().join("large.txt");
+        let medium = dir.path().join("medium.txt");
+        fs::write(&small, "a").unwrap();
+        fs::write(&large, "aaaaaaaa").unwrap();
+        fs::write(&medium, "aaaa").unwrap();
+        let files = vec![small.clone(), large.clone(), medium.clone()];
+
+        assert_eq!(
+            apply(files.clone(), Some(Limit::Count(2)), None),
+            vec![small.clone(), large.clone()]
+        );
+        assert_eq!(
+            apply(
+                files.clone(),
+                Some(Limit::First(2)),
+                Some(SortOrder::new(SortKey::Size))
+            ),
+            vec![large.clone(), medium.clone()]
+        );
+        assert_eq!(
+            apply(
+                files.clone(),
+                Some(Limit::First(1)),
+                Some(SortOrder::new(SortKey::Name))
+            ),
+            vec![large.clone()]
+        );
+
+        // BY: alone reorders everything; ASC flips the default direction
+        let ascending = SortOrder {
+            key: SortKey::Size,
+            descending: false,
+        };
+        assert_eq!(
+            apply(files, None, Some(ascending)),
+            vec![small, medium, large]
+        );
+    }
+
+    #[test]
+    fn test_sample() {
+        let files = paths(&["a", "b", "c", "d", "e", "f", "g", "h", "i", "j"]);
+
+        let picked = sample(files.clone(), 3, 42);
+        assert_eq!(picked.len(), 3);
+        // Sampled files keep their walk order
+        let positions: Vec<usize> = picked
+            .iter()
+            .map(|path| files.iter().position(|file| file == path).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+
+        assert_eq!(sample_count(10, SampleSize::Percent(5.0)), 1);
+        assert_eq!(sample_count(200, SampleSize::Percent(5.0)), 10);
+        assert_eq!(sample_count(4, SampleSize::Count(10)), 4);
+        assert_eq!(sample_count(0, SampleSize::Percent(50.0)), 0);
+    }
+}
@@ -0,0 +1,160 @@
+//! Shell integration (`smv shell-init zsh|bash`)
+//!
+//! Prints a function for the shell's startup file that binds Ctrl-X s to
+//! renaming the word before the cursor: it asks for a style, shows SMV's
+//! transform preview, and once confirmed renames the file (undoable like any
+//! other smv run) and puts the new name on the command line. A word that names
+//! no file is only rewritten on the line. Load it with
+//! `eval "$(smv shell-init zsh)"`.
+
+use std::path::Path;
+
+use crate::transformers::{TransformType, transform};
+
+/// Shells with a widget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Zsh,
+    Bash,
+}
+
+impl Shell {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "zsh" => Ok(Self::Zsh),
+            "bash" => Ok(Self::Bash),
+            other => Err(format!(
+                "Unsupported shell '{other}' (expected zsh or bash)"
+            )),
+        }
+    }
+
+    /// The script to `eval` in the shell's startup file
+    pub fn script(self) -> &'static str {
+        match self {
+            Shell::Zsh => ZSH_WIDGET,
+            Shell::Bash => BASH_WIDGET,
+        }
+    }
+}
+
+/// `word` with its last path component renamed by `transform_type`
+///
+/// The directory part and a trailing slash are kept, so `docs/My Notes/`
+/// becomes `docs/my_notes/` in snake case.
+pub fn renamed_word(word: &str, transform_type: &TransformType) -> String {
+    let trimmed = word.trim_end_matches('/');
+    let slash = &word[trimmed.len()..];
+    let path = Path::new(trimmed);
+    let Some(name) = path.file_name() else {
+        return word.to_string();
+    };
+    let renamed = transform(&name.to_string_lossy(), transform_type);
+    let prefix = &trimmed[..trimmed.len() - name.len()];
+    format!("{prefix}{renamed}{slash}")
+}
+
+const ZSH_WIDGET: &str = r#"# smv shell integration for zsh: eval "$(smv shell-init zsh)" in ~/.zshrc
+# Ctrl-X s renames the file before the cursor (style from $SMV_WIDGET_STYLE, default snake)
+_smv_rename_word() {
+  local -a words
+  words=(${(z)LBUFFER})
+  local raw=${words[-1]}
+  if [[ -z $raw || $LBUFFER == *[[:space:]] ]]; then
+    zle -M "smv: no file name before the cursor"
+    return 1
+  fi
+  local word=${(Q)raw} style=${SMV_WIDGET_STYLE:-snake} answer renamed
+  zle -I
+  read "answer?smv style for '$word' [$style]: " </dev/tty
+  style=${answer:-$style}
+  renamed=$(command smv shell-init name "$style" "$word") || { zle reset-prompt; return 1 }
+  if [[ $renamed == $word ]]; then
+    print "smv: '$word' is already $style"
+    zle reset-prompt
+    return 0
+  fi
+  if [[ -e $word ]]; then
+    command smv "$style" "$word" -p
+    if ! read -q "?Rename '$word' -> '$renamed'? [y/N] " </dev/tty; then
+      print
+      zle reset-prompt
+      return 0
+    fi
+    print
+    command smv "$style" "$word" >/dev/null || { zle reset-prompt; return 1 }
+  else
+    print "smv: '$word' -> '$renamed'"
+    if ! read -q "?Use the new name? [y/N] " </dev/tty; then
+      print
+      zle reset-prompt
+      return 0
+    fi
+    print
+  fi
+  LBUFFER="${LBUFFER%$raw}${(q-)renamed}"
+  zle reset-prompt
+}
+zle -N _smv_rename_word
+bindkey '^Xs' _smv_rename_word
+"#;
+
+const BASH_WIDGET: &str = r#"# smv shell integration for bash: eval "$(smv shell-init bash)" in ~/.bashrc
+# Ctrl-X s renames the file before the cursor (style from $SMV_WIDGET_STYLE, default snake)
+_smv_rename_word() {
+  local before=${READLINE_LINE:0:READLINE_POINT} after=${READLINE_LINE:READLINE_POINT}
+  if ! [[ $before =~ (\\.|[^[:space:]\\])+$ ]]; then
+    echo "smv: no file name before the cursor" >&2
+    return 1
+  fi
+  local raw=${BASH_REMATCH[0]}
+  local word=$raw style=${SMV_WIDGET_STYLE:-snake} answer renamed quoted
+  word=${word#[\'\"]}
+  word=${word%[\'\"]}
+  word=$(sed 's/\\\(.\)/\1/g' <<<"$word")
+  read -r -p "smv style for '$word' [$style]: " answer </dev/tty
+  style=${answer:-$style}
+  renamed=$(command smv shell-init name "$style" "$word") || return 1
+  if [[ $renamed == "$word" ]]; then
+    echo "smv: '$word' is already $style"
+    return 0
+  fi
+  if [[ -e $word ]]; then
+    command smv "$style" "$word" -p
+    read -r -p "Rename '$word' -> '$renamed'? [y/N] " answer </dev/tty
+    [[ $answer == [yY]* ]] || return 0
+    command smv "$style" "$word" >/dev/null || return 1
+  else
+    echo "smv: '$word' -> '$renamed'"
+    read -r -p "Use the new name? [y/N] " answer </dev/tty
+    [[ $answer == [yY]* ]] || return 0
+  fi
+  printf -v quoted '%q' "$renamed"
+  before=${before:0:${#before}-${#raw}}$quoted
+  READLINE_LINE=$before$after
+  READLINE_POINT=${#before}
+}
+bind -x '"\C-xs": _smv_rename_word'
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renamed_word() {
+        assert_eq!(
+            renamed_word("My Report.pdf", &TransformType::Snake),
+            "my_report.pdf"
+        );
+        assert_eq!(
+            renamed_word("docs/My Notes/", &TransformType::Kebab),
+            "docs/my-notes/"
+        );
+        assert_eq!(renamed_word("/", &TransformType::Snake), "/");
+        assert_eq!(Shell::parse("ZSH"), Ok(Shell::Zsh));
+        assert!(Shell::parse("fish").is_err());
+        assert!(Shell::Zsh.script().contains("zle -N _smv_rename_word"));
+        assert!(Shell::Bash.script().contains("bind -x"));
+    }
+}
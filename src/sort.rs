@@ -1,16 +1,29 @@
 use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Weekday};
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::attr;
+use crate::protect::Protection;
+use crate::report::Reporter;
+
 /// Groups files in a directory by their base name (ignores extension) and moves them into folders.
-pub fn group_by_basename(dir: &str, dry_run: bool) -> Result<()> {
+///
+/// Returns the `(old, new)` paths that were moved (or would be, in a dry run).
+pub fn group_by_basename(
+    dir: &str,
+    dry_run: bool,
+    reporter: &mut dyn Reporter,
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut moves = Vec::new();
     let mut groups: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    let mut protection = Protection::new();
 
     for entry in fs::read_dir(dir)? {
         let entry = entry?;
         let path = entry.path();
-        if path.is_file() {
+        if path.is_file() && !skip_protected(&mut protection, &path, reporter) {
             let file_name = path.file_stem().unwrap().to_string_lossy().to_string();
             groups.entry(file_name).or_default().push(path);
         }
@@ -19,18 +32,695 @@ pub fn group_by_basename(dir: &str, dry_run: bool) -> Result<()> {
     for (base, files) in groups {
         let target_dir = Path::new(dir).join(&base);
         if !target_dir.exists() && !dry_run {
-            fs::create_dir(&target_dir)?;
-            println!("Created directory: {}", target_dir.display());
+            attr::create_dir_all(&target_dir)?;
+            reporter.info(&format!("Created directory: {}", target_dir.display()));
         }
 
         for file in files {
             let new_path = target_dir.join(file.file_name().unwrap());
-            println!("Moving {} → {}", file.display(), new_path.display());
+            reporter.info(&format!(
+                "Moving {} → {}",
+                file.display(),
+                new_path.display()
+            ));
             if !dry_run {
                 fs::rename(&file, &new_path)?;
             }
+            moves.push((file, new_path));
+        }
+    }
+
+    Ok(moves)
+}
+
+/// Whether `file` is protected from moving, telling `reporter` so when it is
+pub(crate) fn skip_protected(
+    protection: &mut Protection,
+    file: &Path,
+    reporter: &mut dyn Reporter,
+) -> bool {
+    match protection.reason(file) {
+        Some(reason) => {
+            reporter.info(&format!(
+                "Skipping {} (protected: {})",
+                file.display(),
+                reason
+            ));
+            true
+        }
+        None => false,
+    }
+}
+
+/// How `bucket` derives a directory name from a file name
+#[derive(Debug, Clone)]
+pub enum BucketRule {
+    /// First character (lowercased; digits share `0-9`, everything else `#`)
+    Letter,
+    /// First `n` characters, lowercased
+    Prefix(usize),
+    /// Leading date (`2024-03-01...`, `20240301...`), named by a layout
+    Date(DateLayout),
+    /// Modification time, named by a layout
+    Modified(DateLayout),
+    /// Capture groups of a regex; several groups become nested directories
+    Regex(regex::Regex),
+}
+
+/// Layout of `smv sort date` when `--layout` is not given
+pub const DEFAULT_DATE_LAYOUT: &str = "{Y}/{Y}-{m}";
+
+/// Placeholders a date layout may use
+const LAYOUT_PLACEHOLDERS: &[&str] = &["Y", "y", "m", "d", "W", "month_name", "mon", "relative"];
+
+/// Month names by language, January first
+const MONTH_NAMES: &[(&str, [&str; 12])] = &[
+    (
+        "en",
+        [
+            "January",
+            "February",
+            "March",
+            "April",
+            "May",
+            "June",
+            "July",
+            "August",
+            "September",
+            "October",
+            "November",
+            "December",
+        ],
+    ),
+    (
+        "de",
+        [
+            "Januar",
+            "Februar",
+            "März",
+            "April",
+            "Mai",
+            "Juni",
+            "Juli",
+            "August",
+            "September",
+            "Oktober",
+            "November",
+            "Dezember",
+        ],
+    ),
+    (
+        "es",
+        [
+            "enero",
+            "febrero",
+            "marzo",
+            "abril",
+            "mayo",
+            "junio",
+            "julio",
+            "agosto",
+            "septiembre",
+            "octubre",
+            "noviembre",
+            "diciembre",
+        ],
+    ),
+    (
+        "fr",
+        [
+            "janvier",
+            "février",
+            "mars",
+            "avril",
+            "mai",
+            "juin",
+            "juillet",
+            "août",
+            "septembre",
+            "octobre",
+            "novembre",
+            "décembre",
+        ],
+    ),
+    (
+        "it",
+        [
+            "gennaio",
+            "febbraio",
+            "marzo",
+            "aprile",
+            "maggio",
+            "giugno",
+            "luglio",
+            "agosto",
+            "settembre",
+            "ottobre",
+            "novembre",
+            "dicembre",
+        ],
+    ),
+    (
+        "nl",
+        [
+            "januari",
+            "februari",
+            "maart",
+            "april",
+            "mei",
+            "juni",
+            "juli",
+            "augustus",
+            "september",
+            "oktober",
+            "november",
+            "december",
+        ],
+    ),
+    (
+        "pt",
+        [
+            "janeiro",
+            "fevereiro",
+            "março",
+            "abril",
+            "maio",
+            "junho",
+            "julho",
+            "agosto",
+            "setembro",
+            "outubro",
+            "novembro",
+            "dezembro",
+        ],
+    ),
+];
+
+/// How date buckets are named: a template such as `{Y}/{Y}-{m}-{month_name}`
+///
+/// `{Y}` and `{y}` are the year with four and two digits, `{m}` and `{d}` the
+/// zero-padded month and day, `{W}` the ISO week, `{month_name}` and `{mon}`
+/// the full and three-letter month name in the language of `LC_TIME` (English
+/// when it has none of the built-in names), and `{relative}` one of `today`,
+/// `yesterday`, `this-week`, `last-week`, `this-month` or `last-month`, falling
+/// back to `YYYY-MM` for older dates. `/` nests directories.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DateLayout {
+    template: String,
+    month_names: &'static [&'static str; 12],
+}
+
+impl DateLayout {
+    /// Check the placeholders of `template`, with month names for the current locale
+    pub fn parse(template: &str) -> Result<Self> {
+        Self::with_language(template, &locale_language())
+    }
+
+    /// Like [`DateLayout::parse`], with month names for `language` (`de`, `fr`, ...)
+    pub fn with_language(template: &str, language: &str) -> Result<Self> {
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| anyhow::anyhow!("Unclosed {{ in layout: {template}"))?;
+            let name = &rest[start + 1..start + end];
+            if !LAYOUT_PLACEHOLDERS.contains(&name) {
+                anyhow::bail!(
+                    "Unknown layout placeholder {{{name}}} (use {})",
+                    LAYOUT_PLACEHOLDERS
+                        .iter()
+                        .map(|p| format!("{{{p}}}"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+            rest = &rest[start + end + 1..];
+        }
+        if template.trim_matches('/').is_empty() {
+            anyhow::bail!("Empty date layout");
+        }
+
+        let month_names = MONTH_NAMES
+            .iter()
+            .find(|(code, _)| *code == language)
+            .unwrap_or(&MONTH_NAMES[0]);
+        Ok(Self {
+            template: template.to_string(),
+            month_names: &month_names.1,
+        })
+    }
+
+    /// The relative directory for `date`; `today` anchors `{relative}`
+    pub fn bucket(&self, date: NaiveDate, today: NaiveDate) -> Option<PathBuf> {
+        let month_name = self.month_names[date.month0() as usize];
+        let mut bucket = PathBuf::new();
+        for part in self.template.split('/').filter(|part| !part.is_empty()) {
+            let name = part
+                .replace("{Y}", &format!("{:04}", date.year()))
+                .replace("{y}", &format!("{:02}", date.year() % 100))
+                .replace("{m}", &format!("{:02}", date.month()))
+                .replace("{d}", &format!("{:02}", date.day()))
+                .replace("{W}", &format!("{:02}", date.iso_week().week()))
+                .replace("{month_name}", month_name)
+                .replace("{mon}", &month_name.chars().take(3).collect::<String>())
+                .replace("{relative}", &relative_name(date, today));
+            bucket.push(sanitize_bucket(&name)?);
+        }
+        Some(bucket)
+    }
+}
+
+/// `today`, `last-week`, ... for recent dates, `YYYY-MM` for older ones
+fn relative_name(date: NaiveDate, today: NaiveDate) -> String {
+    let days = (today - date).num_days();
+    let month = |d: NaiveDate| d.year() * 12 + d.month0() as i32;
+    let week = |d: NaiveDate| d.week(Weekday::Mon).first_day();
+    let name = match days {
+        0 => "today",
+        1 => "yesterday",
+        _ if days > 0 && week(date) == week(today) => "this-week",
+        _ if days > 0 && week(date) + Duration::days(7) == week(today) => "last-week",
+        _ if days > 0 && month(date) == month(today) => "this-month",
+        _ if days > 0 && month(date) + 1 == month(today) => "last-month",
+        _ => return format!("{:04}-{:02}", date.year(), date.month()),
+    };
+    name.to_string()
+}
+
+/// Two-letter language of `LC_ALL`, `LC_TIME` or `LANG` (`de_DE.UTF-8` gives `de`)
+fn locale_language() -> String {
+    ["LC_ALL", "LC_TIME", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| value.chars().take(2).collect::<String>().to_lowercase())
+        .unwrap_or_default()
+}
+
+impl BucketRule {
+    /// Parse `LETTER`, `PREFIX:n`, `DATE[:year|month|day]`, `MTIME[:year|month|day]`
+    /// or `REGEX:pattern`
+    #[allow(dead_code)]
+    pub fn parse(rule: &str) -> Result<Self> {
+        Self::parse_with_layout(rule, None)
+    }
+
+    /// Like [`BucketRule::parse`], with `layout` naming the DATE and MTIME buckets
+    pub fn parse_with_layout(rule: &str, layout: Option<&str>) -> Result<Self> {
+        let (kind, value) = match rule.split_once(':') {
+            Some((kind, value)) => (kind, Some(value)),
+            None => (rule, None),
+        };
+
+        match (kind.to_uppercase().as_str(), value) {
+            ("LETTER", None) => Ok(BucketRule::Letter),
+            ("PREFIX", Some(n)) => {
+                let n: usize = n
+                    .parse()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| anyhow::anyhow!("PREFIX needs a positive length: {n}"))?;
+                Ok(BucketRule::Prefix(n))
+            }
+            (kind @ ("DATE" | "MTIME"), precision) => {
+                let template = match precision.map(str::to_lowercase).as_deref() {
+                    _ if layout.is_some() => layout.unwrap_or_default(),
+                    None | Some("year") => "{Y}",
+                    Some("month") => "{Y}-{m}",
+                    Some("day") => "{Y}-{m}-{d}",
+                    Some(other) => anyhow::bail!("Unknown {kind} precision: {other}"),
+                };
+                let layout = DateLayout::parse(template)?;
+                Ok(if kind == "DATE" {
+                    BucketRule::Date(layout)
+                } else {
+                    BucketRule::Modified(layout)
+                })
+            }
+            ("REGEX", Some(pattern)) => Ok(BucketRule::Regex(regex::Regex::new(pattern)?)),
+            _ => anyhow::bail!(
+                "Unknown bucket rule: {rule} (use LETTER, PREFIX:n, DATE[:year|month|day], MTIME[:year|month|day] or REGEX:pattern)"
+            ),
+        }
+    }
+
+    /// Relative directory for the file at `path`; MTIME reads its modification time
+    pub fn bucket_for_file(&self, path: &Path) -> Option<PathBuf> {
+        match self {
+            BucketRule::Modified(layout) => {
+                let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+                let date = DateTime::<Local>::from(modified).date_naive();
+                layout.bucket(date, Local::now().date_naive())
+            }
+            _ => self.bucket_for(&path.file_name()?.to_string_lossy()),
         }
     }
 
-    Ok(())
+    /// Relative directory a file belongs in, or `None` if the rule does not apply
+    ///
+    /// MTIME needs the file itself; see [`BucketRule::bucket_for_file`].
+    pub fn bucket_for(&self, file_name: &str) -> Option<PathBuf> {
+        match self {
+            BucketRule::Letter => {
+                let first = file_name.chars().next()?;
+                let bucket = if first.is_ascii_digit() {
+                    "0-9".to_string()
+                } else if first.is_alphanumeric() {
+                    first.to_lowercase().to_string()
+                } else {
+                    "#".to_string()
+                };
+                Some(PathBuf::from(bucket))
+            }
+            BucketRule::Prefix(n) => {
+                let prefix: String = file_name.chars().take(*n).collect();
+                let prefix = prefix.trim().to_lowercase();
+                (!prefix.is_empty() && !prefix.starts_with('.')).then(|| PathBuf::from(prefix))
+            }
+            BucketRule::Date(layout) => {
+                static DATE_RE: once_cell::sync::Lazy<regex::Regex> =
+                    once_cell::sync::Lazy::new(|| {
+                        regex::Regex::new(r"^(\d{4})[-_]?(\d{2})[-_]?(\d{2})").unwrap()
+                    });
+                let caps = DATE_RE.captures(file_name)?;
+                let date = NaiveDate::from_ymd_opt(
+                    caps[1].parse().ok()?,
+                    caps[2].parse().ok()?,
+                    caps[3].parse().ok()?,
+                )?;
+                layout.bucket(date, Local::now().date_naive())
+            }
+            BucketRule::Modified(_) => None,
+            BucketRule::Regex(re) => {
+                let caps = re.captures(file_name)?;
+                let mut bucket = PathBuf::new();
+                if caps.len() == 1 {
+                    bucket.push(sanitize_bucket(caps.get(0)?.as_str())?);
+                } else {
+                    for group in caps.iter().skip(1).flatten() {
+                        bucket.push(sanitize_bucket(group.as_str())?);
+                    }
+                }
+                (!bucket.as_os_str().is_empty()).then_some(bucket)
+            }
+        }
+    }
+}
+
+/// Captured text becomes a single directory name
+fn sanitize_bucket(text: &str) -> Option<String> {
+    let cleaned = text.trim().replace(['/', '\\'], "-");
+    (!cleaned.is_empty() && cleaned != "." && cleaned != "..").then_some(cleaned)
+}
+
+/// Moves files into directories derived from their names (see [`BucketRule`])
+///
+/// Returns the `(old, new)` paths that were moved (or would be, in a dry run).
+pub fn bucket_files(
+    dir: &str,
+    rule: &BucketRule,
+    recursive: bool,
+    dry_run: bool,
+    reporter: &mut dyn Reporter,
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let root = Path::new(dir);
+    let walker = walkdir::WalkDir::new(root).min_depth(1);
+    let walker = if recursive {
+        walker
+    } else {
+        walker.max_depth(1)
+    };
+
+    let files: Vec<PathBuf> = walker
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .collect();
+
+    let mut created = std::collections::HashSet::new();
+    let mut moves = Vec::new();
+    let mut protection = Protection::new();
+    for file in files {
+        let file_name = file.file_name().unwrap().to_owned();
+        let Some(bucket) = rule.bucket_for_file(&file) else {
+            continue;
+        };
+        if skip_protected(&mut protection, &file, reporter) {
+            continue;
+        }
+
+        let target_dir = root.join(&bucket);
+        let new_path = target_dir.join(&file_name);
+        if new_path == file {
+            continue;
+        }
+        if new_path.exists() {
+            reporter.warn(&format!(
+                "Skipping {} (target exists: {})",
+                file.display(),
+                new_path.display()
+            ));
+            continue;
+        }
+
+        if !target_dir.exists() && created.insert(target_dir.clone()) {
+            reporter.info(&format!("Created directory: {}", target_dir.display()));
+            if !dry_run {
+                attr::create_dir_all(&target_dir)?;
+            }
+        }
+
+        reporter.info(&format!(
+            "Moving {} → {}",
+            file.display(),
+            new_path.display()
+        ));
+        if !dry_run {
+            fs::rename(&file, &new_path)?;
+        }
+        moves.push((file, new_path));
+    }
+
+    Ok(moves)
+}
+
+/// Shard directory for a file name: `abcdef.txt` with 2 levels becomes `a/ab`
+///
+/// Only the stem counts; characters that are not alphanumeric become `_` and short
+/// stems are padded with `_`.
+pub fn alpha_shard(file_name: &str, levels: usize) -> PathBuf {
+    let stem = Path::new(file_name)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let key: Vec<char> = stem
+        .chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .chain(std::iter::repeat('_'))
+        .take(levels)
+        .collect();
+
+    (1..=levels)
+        .map(|len| key[..len].iter().collect::<String>())
+        .collect()
+}
+
+/// Splits a flat directory into alphabetical shards (`a/ab/abcdef.txt`)
+///
+/// Returns the `(old, new)` paths that were moved (or would be, in a dry run).
+/// When a move fails, the moves made before it come back with the error so
+/// they can still be recorded for undo.
+pub fn fan_out_alpha(
+    dir: &str,
+    levels: usize,
+    dry_run: bool,
+    reporter: &mut dyn Reporter,
+) -> std::result::Result<Vec<(PathBuf, PathBuf)>, Interrupted> {
+    if levels == 0 {
+        return Err(Interrupted::before_any_move(anyhow::anyhow!(
+            "--levels must be at least 1"
+        )));
+    }
+
+    let root = Path::new(dir);
+    let mut files: Vec<PathBuf> = fs::read_dir(root)
+        .map_err(Interrupted::before_any_move)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file() && !path.file_name().unwrap().to_string_lossy().starts_with('.')
+        })
+        .collect();
+    files.sort();
+
+    let mut created = std::collections::HashSet::new();
+    let mut moves = Vec::with_capacity(files.len());
+    let mut protection = Protection::new();
+    for file in files {
+        if skip_protected(&mut protection, &file, reporter) {
+            continue;
+        }
+        let file_name = file.file_name().unwrap().to_owned();
+        let target_dir = root.join(alpha_shard(&file_name.to_string_lossy(), levels));
+        let new_path = target_dir.join(&file_name);
+        if new_path.exists() {
+            reporter.warn(&format!(
+                "Skipping {} (target exists: {})",
+                file.display(),
+                new_path.display()
+            ));
+            continue;
+        }
+
+        if !target_dir.exists() && created.insert(target_dir.clone()) {
+            reporter.info(&format!("Created directory: {}", target_dir.display()));
+            if !dry_run && let Err(error) = attr::create_dir_all(&target_dir) {
+                return Err(Interrupted {
+                    moves,
+                    error: anyhow::Error::new(error)
+                        .context(format!("Cannot create {}", target_dir.display())),
+                });
+            }
+        }
+
+        reporter.info(&format!(
+            "Moving {} → {}",
+            file.display(),
+            new_path.display()
+        ));
+        if !dry_run && let Err(error) = fs::rename(&file, &new_path) {
+            return Err(Interrupted {
+                moves,
+                error: anyhow::Error::new(error).context(format!("Cannot move {}", file.display())),
+            });
+        }
+        moves.push((file, new_path));
+    }
+
+    Ok(moves)
+}
+
+/// A fan-out that stopped part way, with the moves it made before the error
+#[derive(Debug)]
+pub struct Interrupted {
+    pub moves: Vec<(PathBuf, PathBuf)>,
+    pub error: anyhow::Error,
+}
+
+impl Interrupted {
+    fn before_any_move(error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            moves: Vec::new(),
+            error: error.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#}", self.error)
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_rules() -> Result<()> {
+        let letter = BucketRule::parse("LETTER")?;
+        assert_eq!(letter.bucket_for("Zebra.txt"), Some(PathBuf::from("z")));
+        assert_eq!(letter.bucket_for("42.txt"), Some(PathBuf::from("0-9")));
+        assert_eq!(letter.bucket_for("_x.txt"), Some(PathBuf::from("#")));
+
+        let date = BucketRule::parse("DATE:month")?;
+        assert_eq!(
+            date.bucket_for("20240301_photo.jpg"),
+            Some(PathBuf::from("2024-03"))
+        );
+        assert_eq!(date.bucket_for("photo.jpg"), None);
+
+        let regex = BucketRule::parse(r"REGEX:^(\d{4})-(\d{2})")?;
+        assert_eq!(
+            regex.bucket_for("2023-07-15 trip.jpg"),
+            Some(PathBuf::from("2023").join("07"))
+        );
+
+        assert!(BucketRule::parse("PREFIX:0").is_err());
+        assert!(BucketRule::parse("NOPE").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_date_layouts() -> Result<()> {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let today = date(2024, 6, 20); // a Thursday
+
+        let named = DateLayout::with_language("{Y}/{Y}-{m}-{month_name}", "en")?;
+        assert_eq!(
+            named.bucket(date(2024, 6, 3), today),
+            Some(PathBuf::from("2024").join("2024-06-June"))
+        );
+        let german = DateLayout::with_language("{y}{m}{d} {mon}", "de")?;
+        assert_eq!(
+            german.bucket(date(2024, 3, 9), today),
+            Some(PathBuf::from("240309 Mär"))
+        );
+
+        let relative = DateLayout::with_language("{relative}", "en")?;
+        let name = |d| relative.bucket(d, today).unwrap();
+        assert_eq!(name(date(2024, 6, 20)), PathBuf::from("today"));
+        assert_eq!(name(date(2024, 6, 19)), PathBuf::from("yesterday"));
+        assert_eq!(name(date(2024, 6, 17)), PathBuf::from("this-week"));
+        assert_eq!(name(date(2024, 6, 12)), PathBuf::from("last-week"));
+        assert_eq!(name(date(2024, 6, 2)), PathBuf::from("this-month"));
+        assert_eq!(name(date(2024, 5, 2)), PathBuf::from("last-month"));
+        assert_eq!(name(date(2023, 12, 24)), PathBuf::from("2023-12"));
+
+        assert!(DateLayout::parse("{Y}/{month}").is_err());
+        assert!(DateLayout::parse("{Y").is_err());
+
+        // --layout renames the DATE buckets; invalid dates are left alone
+        let rule = BucketRule::parse_with_layout("DATE", Some("{Y}/{mon}"))?;
+        assert_eq!(
+            rule.bucket_for("2024-01-15 scan.pdf"),
+            Some(PathBuf::from("2024").join("Jan"))
+        );
+        assert_eq!(rule.bucket_for("20241399.txt"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fan_out_keeps_the_moves_made_before_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("a.txt"), "").unwrap();
+        // `b` sits where its own shard directory would go, so moving it fails
+        fs::write(root.join("b"), "").unwrap();
+        fs::write(root.join("b.txt"), "").unwrap();
+
+        let interrupted =
+            fan_out_alpha(&root.to_string_lossy(), 1, false, &mut crate::report::Quiet)
+                .unwrap_err();
+        assert_eq!(
+            interrupted.moves,
+            [(root.join("a.txt"), root.join("a").join("a.txt"))]
+        );
+        assert!(root.join("a").join("a.txt").exists());
+        assert!(interrupted.to_string().starts_with("Cannot move"));
+    }
+
+    #[test]
+    fn test_alpha_shard() {
+        assert_eq!(alpha_shard("Abcdef.txt", 2), PathBuf::from("a").join("ab"));
+        assert_eq!(
+            alpha_shard("x.txt", 3),
+            PathBuf::from("x").join("x_").join("x__")
+        );
+        assert_eq!(alpha_shard("my file", 1), PathBuf::from("m"));
+    }
 }
@@ -0,0 +1,191 @@
+//! Staged reorganizations swapped in at once (`smv stage` / `smv commit-stage`)
+//!
+//! `smv stage photos` copies `photos/` to `photos.smv-staging/`, where any smv
+//! command can rearrange it while `photos/` stays as it was for whatever reads
+//! it. `smv commit-stage photos` then swaps the two directories: with one
+//! atomic rename exchange where the system has one (Linux `renameat2`, macOS
+//! `renamex_np`), with two quick renames otherwise. The original is kept as
+//! `photos.smv-previous/` until it is removed, and `smv -u` swaps it back.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Suffix of the directory a reorganization is built in
+pub const STAGING_SUFFIX: &str = ".smv-staging";
+
+/// Suffix the original directory is kept under after the swap
+pub const PREVIOUS_SUFFIX: &str = ".smv-previous";
+
+/// A staged directory and the names around it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stage {
+    pub dir: PathBuf,
+    pub staging: PathBuf,
+    pub previous: PathBuf,
+}
+
+impl Stage {
+    /// The staging names of `dir`, which must exist
+    pub fn of(dir: &Path) -> io::Result<Self> {
+        let dir = dir.canonicalize()?;
+        if !dir.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                format!("Not a directory: {}", dir.display()),
+            ));
+        }
+        let (Some(parent), Some(name)) = (dir.parent(), dir.file_name()) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The root directory cannot be staged",
+            ));
+        };
+        let sibling = |suffix: &str| {
+            let mut sibling = OsString::from(name);
+            sibling.push(suffix);
+            parent.join(sibling)
+        };
+        Ok(Self {
+            staging: sibling(STAGING_SUFFIX),
+            previous: sibling(PREVIOUS_SUFFIX),
+            dir,
+        })
+    }
+
+    /// Copy the directory into its staging directory
+    pub fn create(&self) -> io::Result<()> {
+        if self.staging.symlink_metadata().is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} already exists: work there, or remove it to start over",
+                    self.staging.display()
+                ),
+            ));
+        }
+        crate::history::copy_tree(&self.dir, &self.staging).inspect_err(|_| {
+            let _ = fs::remove_dir_all(&self.staging);
+        })
+    }
+
+    /// What stops the swap, if anything
+    pub fn check(&self) -> Option<String> {
+        if !self.staging.is_dir() {
+            return Some(format!(
+                "Nothing staged: {} does not exist (run smv stage first)",
+                self.staging.display()
+            ));
+        }
+        if self.previous.symlink_metadata().is_ok() {
+            return Some(format!(
+                "{} is still there from an earlier swap: remove it first",
+                self.previous.display()
+            ));
+        }
+        None
+    }
+
+    /// Put the staged tree in place of the directory, keeping the original
+    /// as `previous`
+    ///
+    /// Returns whether the swap was atomic. Without a rename exchange the
+    /// directory is missing for the moment between two renames.
+    pub fn commit(&self) -> io::Result<bool> {
+        if let Some(problem) = self.check() {
+            return Err(io::Error::other(problem));
+        }
+        if exchange(&self.dir, &self.staging).is_ok() {
+            fs::rename(&self.staging, &self.previous)?;
+            return Ok(true);
+        }
+        fs::rename(&self.dir, &self.previous)?;
+        if let Err(e) = fs::rename(&self.staging, &self.dir) {
+            let _ = fs::rename(&self.previous, &self.dir);
+            return Err(e);
+        }
+        Ok(false)
+    }
+}
+
+/// Swap two paths in one step
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn exchange(a: &Path, b: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_a = CString::new(a.as_os_str().as_bytes())?;
+    let c_b = CString::new(b.as_os_str().as_bytes())?;
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            libc::AT_FDCWD,
+            c_a.as_ptr(),
+            libc::AT_FDCWD,
+            c_b.as_ptr(),
+            libc::RENAME_EXCHANGE,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn exchange(a: &Path, b: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_a = CString::new(a.as_os_str().as_bytes())?;
+    let c_b = CString::new(b.as_os_str().as_bytes())?;
+    if unsafe { libc::renamex_np(c_a.as_ptr(), c_b.as_ptr(), libc::RENAME_SWAP) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn exchange(_a: &Path, _b: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "no atomic rename exchange here",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage_and_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let photos = dir.path().join("photos");
+        fs::create_dir_all(photos.join("raw")).unwrap();
+        fs::write(photos.join("raw/IMG 1.jpg"), "1").unwrap();
+
+        let stage = Stage::of(&photos).unwrap();
+        assert_eq!(stage.staging.file_name().unwrap(), "photos.smv-staging");
+        assert!(stage.check().is_some());
+        stage.create().unwrap();
+        assert!(stage.create().is_err());
+        assert!(stage.check().is_none());
+
+        fs::create_dir(stage.staging.join("2024")).unwrap();
+        fs::rename(
+            stage.staging.join("raw/IMG 1.jpg"),
+            stage.staging.join("2024/img_1.jpg"),
+        )
+        .unwrap();
+        stage.commit().unwrap();
+        assert!(photos.join("2024/img_1.jpg").is_file());
+        assert!(stage.previous.join("raw/IMG 1.jpg").is_file());
+        assert!(!stage.staging.exists());
+        // The kept original blocks the next swap until it is removed
+        stage.create().unwrap();
+        assert!(stage.check().unwrap().contains("earlier swap"));
+    }
+}
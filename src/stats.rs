@@ -0,0 +1,198 @@
+//! Activity summaries over the undo history (`smv stats`)
+//!
+//! Counts what the journal remembers: batches and files per day, the directories
+//! touched most, bytes moved and how often work was undone. Only what is still in
+//! the history is counted, so `--max-history-size` and `history gc` limit how far
+//! back the numbers reach.
+
+use chrono::{DateTime, Local, NaiveDate};
+use colored::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::disk_usage::format_size;
+use crate::history::HistoryManager;
+use crate::layout::{self, Table};
+
+/// Directories listed under "most touched"
+const TOP_DIRECTORIES: usize = 10;
+
+/// Activity on one day
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DayActivity {
+    pub batches: usize,
+    pub files: usize,
+}
+
+/// What the history says happened since a point in time
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Activity {
+    pub since: Option<DateTime<Local>>,
+    pub days: BTreeMap<NaiveDate, DayActivity>,
+    pub batches: usize,
+    pub files: usize,
+    /// Sizes of the files whose size was recorded
+    pub bytes: u64,
+    pub undos: usize,
+    /// Directories by how many files were moved out of or into them, most first
+    pub directories: Vec<(PathBuf, usize)>,
+}
+
+impl Activity {
+    /// Summarize the history, counting only activity after `since`
+    pub fn collect(
+        history: &HistoryManager,
+        since: Option<DateTime<Local>>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut activity = Activity {
+            since,
+            ..Activity::default()
+        };
+        let recent = |time: &DateTime<Local>| since.is_none_or(|since| *time >= since);
+
+        let mut seen_batches = HashSet::new();
+        let mut directories: HashMap<PathBuf, usize> = HashMap::new();
+        for operation in history.list_operations() {
+            if !recent(&operation.timestamp) {
+                continue;
+            }
+            let day = activity
+                .days
+                .entry(operation.timestamp.date_naive())
+                .or_default();
+            let batch = operation
+                .batch
+                .clone()
+                .unwrap_or_else(|| operation.timestamp.to_rfc3339());
+            if seen_batches.insert(batch) {
+                day.batches += 1;
+                activity.batches += 1;
+            }
+
+            for file in history.files_of(operation)? {
+                day.files += 1;
+                activity.files += 1;
+                activity.bytes += file.fingerprint.as_ref().map_or(0, |f| f.size);
+
+                let mut touched = HashSet::new();
+                for path in [&file.source, &file.destination] {
+                    if path.as_os_str().is_empty() {
+                        continue;
+                    }
+                    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+                    if touched.insert(dir.clone()) {
+                        *directories.entry(dir).or_default() += 1;
+                    }
+                }
+            }
+        }
+
+        activity.undos = history.undo_times()?.iter().filter(|t| recent(t)).count();
+        activity.directories = directories.into_iter().collect();
+        activity
+            .directories
+            .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(activity)
+    }
+
+    /// Undos per recorded batch
+    pub fn undo_rate(&self) -> f64 {
+        if self.batches == 0 {
+            0.0
+        } else {
+            self.undos as f64 / self.batches as f64
+        }
+    }
+
+    pub fn print(&self) {
+        let period = match self.since {
+            Some(since) => format!("since {}", since.format("%Y-%m-%d %H:%M")),
+            None => "everything in the history".to_string(),
+        };
+        println!("{} ({})", "CNP Smart Move - Stats".bold(), period);
+
+        println!("\n{}", "Totals:".bold());
+        println!("  Batches: {}", self.batches.to_string().cyan());
+        println!("  Files: {}", self.files.to_string().cyan());
+        println!("  Bytes moved: {}", format_size(self.bytes).cyan());
+        println!(
+            "  Undos: {} ({:.0}% of batches)",
+            self.undos.to_string().yellow(),
+            self.undo_rate() * 100.0
+        );
+
+        if self.days.is_empty() {
+            println!("\nNo recorded activity.");
+            return;
+        }
+
+        println!("\n{}", "Per day:".bold());
+        for (day, activity) in &self.days {
+            println!(
+                "  {}  {:>4} batches  {:>7} files",
+                day, activity.batches, activity.files
+            );
+        }
+
+        println!("\n{}", "Most touched directories:".bold());
+        let mut table = Table::new().right(0);
+        for (dir, files) in self.directories.iter().take(TOP_DIRECTORIES) {
+            let dir = if dir.as_os_str().is_empty() {
+                ".".to_string()
+            } else {
+                dir.display().to_string()
+            };
+            table.row(vec![files.to_string(), String::from("files"), dir]);
+        }
+        for line in table.render("  ", layout::terminal_width()) {
+            println!("{line}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_collect() {
+        let dir = tempfile::tempdir().unwrap();
+        let inbox = dir.path().join("inbox");
+        let archive = dir.path().join("archive");
+        fs::create_dir_all(&inbox).unwrap();
+        fs::create_dir_all(&archive).unwrap();
+
+        let mut moves = Vec::new();
+        for name in ["a.txt", "b.txt"] {
+            fs::write(archive.join(name), "12345").unwrap();
+            moves.push((inbox.join(name), archive.join(name)));
+        }
+        let mut history = HistoryManager::new(10, &dir.path().join("state"));
+        history
+            .record_mapping(dir.path().to_path_buf(), &moves)
+            .unwrap();
+        // A second batch, undone again: only the undo is left to count
+        history.start_batch();
+        fs::write(archive.join("c.txt"), "new").unwrap();
+        history
+            .record(PathBuf::new(), archive.join("c.txt"))
+            .unwrap();
+        history.undo().unwrap();
+
+        let activity = Activity::collect(&history, None).unwrap();
+        assert_eq!(activity.batches, 1);
+        assert_eq!(activity.files, 2);
+        assert_eq!(activity.bytes, 10);
+        assert_eq!(activity.undos, 1);
+        assert_eq!(activity.days.values().next().unwrap().files, 2);
+        assert_eq!(activity.directories.len(), 2);
+        assert_eq!(activity.directories[0].1, 2);
+
+        let later = Local::now() + chrono::Duration::hours(1);
+        let activity = Activity::collect(&history, Some(later)).unwrap();
+        assert_eq!(activity.files, 0);
+        assert_eq!(activity.undos, 0);
+    }
+}
@@ -0,0 +1,280 @@
+//! "Did you mean" for mistyped commands, transforms and CNP keywords
+//!
+//! `smv sanke .` should not stop at "Unknown command". Misspelled words are
+//! compared with every word smv knows by edit distance, and the closest ones
+//! are offered together with their usage line from `smv --help`.
+
+/// Command words, with the usage line `--help` gives them
+const COMMANDS: &[(&str, &str)] = &[
+    ("snake", "smv snake [target]             snake_case names"),
+    ("kebab", "smv kebab [target]             kebab-case names"),
+    ("pascal", "smv pascal [target]            PascalCase names"),
+    ("camel", "smv camel [target]             camelCase names"),
+    ("title", "smv title [target]             Title Case names"),
+    (
+        "sentence",
+        "smv sentence [target]          Sentence case names",
+    ),
+    ("start", "smv start [target]             Start Case names"),
+    ("studly", "smv studly [target]            StUdLy names"),
+    ("lower", "smv lower [target]             lowercase names"),
+    ("upper", "smv upper [target]             UPPERCASE names"),
+    (
+        "clean",
+        "smv clean [target]             Remove special characters and extra spaces",
+    ),
+    (
+        "split",
+        "smv split TRANSFORMATION [target]   Split camelCase/PascalCase, then transform",
+    ),
+    (
+        "change",
+        "smv CHANGE \"old\" INTO \"new\" [target]   Replace text in names",
+    ),
+    (
+        "regex",
+        "smv REGEX \"pattern\" INTO \"new\" [target]   Replace a regular expression in names",
+    ),
+    (
+        "note",
+        "smv note [target]              Date-prefixed kebab-case vault names",
+    ),
+    (
+        "sort",
+        "smv sort alpha|date [target]   Shard by name or file by modification date",
+    ),
+    (
+        "group",
+        "smv group [target]             Group files by basename into directories",
+    ),
+    (
+        "flatten",
+        "smv flatten [target]           Move every file up into the target",
+    ),
+    (
+        "bucket",
+        "smv bucket RULE [target]       Folders by LETTER, PREFIX:n, DATE, MTIME or REGEX:",
+    ),
+    (
+        "template",
+        "smv template \"PATTERN\" [target]   Rename from {name}, {ext}, {n:02}, ...",
+    ),
+    (
+        "tier",
+        "smv tier [path] FILTERS... TO-DIR:dir   Move matches to other storage",
+    ),
+    (
+        "attr",
+        "smv attr [path] FILTERS... PERM:MODE   chmod the matches",
+    ),
+    (
+        "mv",
+        "smv mv source... destination   Move files and directories",
+    ),
+    (
+        "cp",
+        "smv cp source... destination   Copy files and directories",
+    ),
+    (
+        "rm",
+        "smv rm targets...              Remove into the trash (undoable)",
+    ),
+    (
+        "mkdir",
+        "smv mkdir directories...       Create directories (undoable)",
+    ),
+    (
+        "undo",
+        "smv undo [-p] [--select LIST]  Revert the last batch, or part of it",
+    ),
+    (
+        "history",
+        "smv history gc|undo N          Prune past batches, or revert one of them",
+    ),
+    (
+        "stats",
+        "smv stats [--since AGE]        What smv did, per day and directory",
+    ),
+    (
+        "detect",
+        "smv detect [dir]               Naming styles per directory",
+    ),
+    (
+        "doctor",
+        "smv doctor --env [dir]         Check the environment smv runs in",
+    ),
+    (
+        "where-did-it-go",
+        "smv where-did-it-go NAME       Trace a file through the history",
+    ),
+    (
+        "stage",
+        "smv stage DIR                  Copy DIR aside to reorganize it",
+    ),
+    (
+        "commit-stage",
+        "smv commit-stage DIR           Swap the staged copy in atomically",
+    ),
+    (
+        "apply-plan",
+        "smv apply-plan FILE            Run a plan saved with --plan",
+    ),
+    (
+        "batch",
+        "smv batch FILE                 Check, then run, an operations CSV or JSON plan",
+    ),
+    (
+        "index",
+        "smv index build|update|clear [dir]   Saved listings CNP filters use",
+    ),
+    ("interactive", "smv interactive                The REPL"),
+    (
+        "tui",
+        "smv tui                        The terminal file explorer",
+    ),
+    (
+        "wizard",
+        "smv wizard                     Build a command by answering questions",
+    ),
+    (
+        "preset",
+        "smv preset save|run|list|rm    Saved command lines",
+    ),
+    (
+        "shell-init",
+        "smv shell-init zsh|bash        Shell widget renaming the word at the cursor",
+    ),
+];
+
+/// Transforms `split` takes
+const SPLIT_TRANSFORMS: &[&str] = &[
+    "snake", "kebab", "title", "camel", "pascal", "sentence", "start", "studly", "lower", "upper",
+];
+
+/// CNP keywords that take a value after a colon
+pub const KEYWORDS: &[&str] = &[
+    "NAME", "NAME~", "REGEX", "TYPE", "EXT", "TAG", "EMPTY", "HASH", "DUPES", "FOR", "LIMIT",
+    "FIRST", "SAMPLE", "BY", "PERM", "TO", "INTO", "TO-DIR", "INTO-DIR", "FORMAT", "SIZE", "DEPTH",
+    "MODIFIED", "ACCESSED", "RES",
+];
+
+/// Edit distance between two words (insertions, deletions, substitutions and swaps
+/// of neighbors each count one), ignoring case
+pub fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+    let mut rows = vec![(0..=b.len()).collect::<Vec<_>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+/// The known words close enough to `word` to be what was meant, closest first
+pub fn closest<'a>(word: &str, known: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+    // One typo per three letters, at least one
+    let limit = (word.chars().count() / 3).max(1);
+    let mut close: Vec<(usize, &str)> = known
+        .into_iter()
+        .map(|candidate| (distance(word, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .collect();
+    close.sort_by_key(|(distance, _)| *distance);
+    close.into_iter().take(3).map(|(_, word)| word).collect()
+}
+
+/// The error for a word that is not a command, with what was likely meant
+pub fn unknown_command(word: &str) -> String {
+    let matches = closest(word, COMMANDS.iter().map(|(name, _)| *name));
+    let mut message = format!("Unknown command: {word}");
+    if matches.is_empty() {
+        return message;
+    }
+    message.push_str(&format!("\n\nDid you mean {}?", or_list(&matches)));
+    for name in matches {
+        if let Some((_, usage)) = COMMANDS.iter().find(|(known, _)| *known == name) {
+            message.push_str(&format!("\n  {usage}"));
+        }
+    }
+    message
+}
+
+/// The error for an unknown `split` transformation
+pub fn unknown_split(word: &str) -> String {
+    let mut message = format!("Unknown split transformation: {word}");
+    let matches = closest(word, SPLIT_TRANSFORMS.iter().copied());
+    if !matches.is_empty() {
+        message.push_str(&format!(" (did you mean split {}?)", or_list(&matches)));
+    }
+    message
+}
+
+/// The error for `KEY:value` with a key smv does not know
+pub fn unknown_keyword(arg: &str) -> String {
+    let key = arg.split([':', '<', '>']).next().unwrap_or(arg);
+    let mut message = format!("Unknown keyword {key}: in '{arg}'");
+    let matches = closest(key, KEYWORDS.iter().copied());
+    if !matches.is_empty() {
+        let keys: Vec<String> = matches.iter().map(|key| format!("{key}:")).collect();
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        message.push_str(&format!(" (did you mean {}?)", or_list(&keys)));
+    }
+    message
+}
+
+/// Whether `arg` is written like a CNP keyword (`EXTT:jpg`) rather than a path
+pub fn looks_like_keyword(arg: &str) -> bool {
+    arg.split_once(':').is_some_and(|(key, _)| {
+        key.len() > 1
+            && key
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c == '-' || c == '~')
+    })
+}
+
+/// `a`, `a or b`, `a, b or c`
+fn or_list(words: &[&str]) -> String {
+    match words {
+        [] => String::new(),
+        [one] => one.to_string(),
+        [rest @ .., last] => format!("{} or {}", rest.join(", "), last),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suggestions() {
+        assert_eq!(distance("sanke", "snake"), 1);
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(
+            closest("sanke", COMMANDS.iter().map(|(name, _)| *name)),
+            ["snake"]
+        );
+        assert!(closest("frobnicate", COMMANDS.iter().map(|(name, _)| *name)).is_empty());
+
+        let message = unknown_command("histroy");
+        assert!(message.contains("Did you mean history?"), "{message}");
+        assert!(message.contains("smv history gc"));
+        assert_eq!(
+            unknown_keyword("EXTT:jpg"),
+            "Unknown keyword EXTT: in 'EXTT:jpg' (did you mean EXT:?)"
+        );
+        assert!(unknown_split("snaek").ends_with("(did you mean split snake?)"));
+        assert!(looks_like_keyword("TYEP:file"));
+        assert!(!looks_like_keyword("C:"));
+        assert!(!looks_like_keyword("notes:2024.txt"));
+    }
+}
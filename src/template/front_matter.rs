@@ -0,0 +1,71 @@
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use super::MetadataProvider;
+
+/// Front matter blocks larger than this are ignored
+const MAX_FRONT_MATTER_LINES: usize = 500;
+
+/// YAML front matter (`---` ... `---`) at the top of markdown files, exposed as `{fm:key}`
+pub struct FrontMatterProvider;
+
+impl MetadataProvider for FrontMatterProvider {
+    fn namespace(&self) -> &'static str {
+        "fm"
+    }
+
+    fn extract(&self, path: &Path) -> Option<Value> {
+        let file = File::open(path).ok()?;
+        let yaml = read_front_matter(BufReader::new(file))?;
+        parse_front_matter(&yaml)
+    }
+}
+
+/// Read the raw YAML between the opening and closing `---` lines
+fn read_front_matter<R: BufRead>(reader: R) -> Option<String> {
+    let mut lines = reader.lines();
+    let first = lines.next()?.ok()?;
+    if first.trim_start_matches('\u{feff}').trim_end() != "---" {
+        return None;
+    }
+
+    let mut yaml = String::new();
+    for line in lines.take(MAX_FRONT_MATTER_LINES) {
+        let line = line.ok()?;
+        if matches!(line.trim_end(), "---" | "...") {
+            return Some(yaml);
+        }
+        yaml.push_str(&line);
+        yaml.push('\n');
+    }
+    None
+}
+
+/// Parse front matter YAML into a JSON object
+pub fn parse_front_matter(yaml: &str) -> Option<Value> {
+    let value: Value = serde_yaml::from_str(yaml).ok()?;
+    value.is_object().then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_front_matter() {
+        let note = "---\ntitle: Hello World\ndate: 2024-03-01\ntags: [a, b]\n---\n# Body\n";
+        let yaml = read_front_matter(note.as_bytes()).unwrap();
+        let value = parse_front_matter(&yaml).unwrap();
+        assert_eq!(value["title"], "Hello World");
+        assert_eq!(value["date"], "2024-03-01");
+        assert_eq!(value["tags"][1], "b");
+    }
+
+    #[test]
+    fn test_no_front_matter() {
+        assert!(read_front_matter("# Just a heading\n".as_bytes()).is_none());
+        assert!(read_front_matter("---\nunterminated: true\n".as_bytes()).is_none());
+    }
+}
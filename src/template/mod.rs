@@ -0,0 +1,514 @@
+//! Rename templates
+//!
+//! A template such as `"{fm:date}-{fm:title|kebab}.md"` is rendered once per
+//! file. Plain variables (`{name}`, `{ext}`) come from the file name itself;
+//! namespaced variables (`{fm:title}`) are looked up through metadata
+//! providers, which are only consulted when a template actually uses them.
+
+pub mod front_matter;
+pub mod office;
+pub mod pdf;
+pub mod video;
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::transformers::{TransformType, transform};
+
+/// Errors raised while parsing or rendering a template
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateError {
+    /// The template string itself is malformed
+    Syntax(String),
+    /// A variable had no value for the file being renamed
+    Missing(String),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TemplateError::Syntax(message) => write!(f, "Template syntax error: {message}"),
+            TemplateError::Missing(variable) => write!(f, "No value for {{{variable}}}"),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// Source of namespaced template variables (`{fm:title}`, `{pdf:author}`, `{doc:modified}`, ...)
+pub trait MetadataProvider {
+    /// Namespace used in templates, e.g. `fm` for `{fm:title}`
+    fn namespace(&self) -> &'static str;
+
+    /// Keys that may also be written without the namespace, e.g. `{width}`
+    fn bare_keys(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Extract metadata for a file as a JSON object, or `None` if the file is unsupported
+    fn extract(&self, path: &Path) -> Option<Value>;
+}
+
+/// All providers known to the template engine
+pub fn default_providers() -> Vec<Box<dyn MetadataProvider>> {
+    vec![
+        Box::new(front_matter::FrontMatterProvider),
+        Box::new(pdf::PdfProvider),
+        Box::new(office::OfficeProvider),
+        Box::new(video::VideoProvider),
+    ]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Variable {
+        namespace: Option<String>,
+        key: String,
+        filters: Vec<TransformType>,
+        width: Option<usize>,
+    },
+}
+
+/// A parsed rename template
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Parse a template string
+    ///
+    /// Literal braces are written as `{{` and `}}`.
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut body = String::new();
+                    let mut closed = false;
+                    for inner in chars.by_ref() {
+                        if inner == '}' {
+                            closed = true;
+                            break;
+                        }
+                        body.push(inner);
+                    }
+                    if !closed {
+                        return Err(TemplateError::Syntax(format!(
+                            "unclosed '{{' in \"{source}\""
+                        )));
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Self::parse_variable(&body)?);
+                }
+                '}' => {
+                    return Err(TemplateError::Syntax(format!(
+                        "unmatched '}}' in \"{source}\""
+                    )));
+                }
+                '/' | '\\' => {
+                    return Err(TemplateError::Syntax(
+                        "templates produce file names and cannot contain path separators".into(),
+                    ));
+                }
+                _ => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            segments,
+        })
+    }
+
+    fn parse_variable(body: &str) -> Result<Segment, TemplateError> {
+        let mut parts = body.split('|');
+        let expression = parts.next().unwrap_or("").trim();
+        if expression.is_empty() {
+            return Err(TemplateError::Syntax("empty variable '{}'".into()));
+        }
+
+        // `{n:02}` pads with zeros; `{fm:title}` selects a provider namespace
+        let (namespace, key, width) = match expression.split_once(':') {
+            Some((key, spec)) if !spec.is_empty() && spec.chars().all(|c| c.is_ascii_digit()) => {
+                let width = spec.parse().map_err(|_| {
+                    TemplateError::Syntax(format!("invalid width in '{{{expression}}}'"))
+                })?;
+                (None, key.trim().to_string(), Some(width))
+            }
+            Some((namespace, key)) => (
+                Some(namespace.trim().to_string()),
+                key.trim().to_string(),
+                None,
+            ),
+            None => (None, expression.to_string(), None),
+        };
+
+        let filters = parts
+            .map(|filter| {
+                let filter = filter.trim();
+                TransformType::from_str(filter)
+                    .ok_or_else(|| TemplateError::Syntax(format!("unknown filter '{filter}'")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Segment::Variable {
+            namespace,
+            key,
+            filters,
+            width,
+        })
+    }
+
+    /// The template as written by the user
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Render the template for one file
+    pub fn render(&self, context: &mut TemplateContext) -> Result<String, TemplateError> {
+        let mut output = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => output.push_str(text),
+                Segment::Variable {
+                    namespace,
+                    key,
+                    filters,
+                    width,
+                } => {
+                    let mut value = context
+                        .lookup(namespace.as_deref(), key)
+                        .ok_or_else(|| TemplateError::Missing(variable_name(namespace, key)))?;
+                    for filter in filters {
+                        value = transform(&value, filter);
+                    }
+                    if let Some(width) = width {
+                        value = format!("{value:0>width$}");
+                    }
+                    output.push_str(&sanitize_value(&value));
+                }
+            }
+        }
+        Ok(output)
+    }
+}
+
+fn variable_name(namespace: &Option<String>, key: &str) -> String {
+    match namespace {
+        Some(namespace) => format!("{namespace}:{key}"),
+        None => key.to_string(),
+    }
+}
+
+/// Values end up inside a single file name, so separators and line breaks are flattened
+fn sanitize_value(value: &str) -> String {
+    value
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' => '-',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect()
+}
+
+/// Per-file state used while rendering; provider results are cached
+pub struct TemplateContext<'a> {
+    path: &'a Path,
+    providers: &'a [Box<dyn MetadataProvider>],
+    cache: HashMap<&'static str, Option<Value>>,
+    index_in_dir: Option<usize>,
+}
+
+impl<'a> TemplateContext<'a> {
+    pub fn new(path: &'a Path, providers: &'a [Box<dyn MetadataProvider>]) -> Self {
+        Self {
+            path,
+            providers,
+            cache: HashMap::new(),
+            index_in_dir: None,
+        }
+    }
+
+    /// Set the 1-based position of the file among the planned files in its directory
+    pub fn with_index(mut self, index_in_dir: usize) -> Self {
+        self.index_in_dir = Some(index_in_dir);
+        self
+    }
+
+    fn lookup(&mut self, namespace: Option<&str>, key: &str) -> Option<String> {
+        let provider = match namespace {
+            None => {
+                if let Some(value) = self.builtin(key) {
+                    return Some(value);
+                }
+                let root_key = key.split(['.', '[']).next().unwrap_or(key);
+                self.providers
+                    .iter()
+                    .find(|provider| provider.bare_keys().contains(&root_key))?
+            }
+            Some(namespace) => self
+                .providers
+                .iter()
+                .find(|provider| provider.namespace() == namespace)?,
+        };
+
+        let path = self.path;
+        let metadata = self
+            .cache
+            .entry(provider.namespace())
+            .or_insert_with(|| provider.extract(path))
+            .as_ref()?;
+        lookup_value(metadata, key)
+    }
+
+    fn builtin(&self, key: &str) -> Option<String> {
+        let file_name = crate::transformers::name_text(self.path.file_name()?)?;
+        let (stem, extension) = match file_name.rfind('.') {
+            Some(pos) if pos > 0 => (
+                file_name[..pos].to_string(),
+                file_name[pos + 1..].to_string(),
+            ),
+            _ => (file_name.clone(), String::new()),
+        };
+        match key {
+            "name" | "stem" => Some(stem),
+            "ext" => Some(extension),
+            "filename" => Some(file_name),
+            "n" | "index_in_dir" => self.index_in_dir.map(|index| index.to_string()),
+            _ => self.ancestor(key),
+        }
+    }
+
+    /// `{parent}` is the containing directory, `{parent[-2]}` its parent, and so on
+    fn ancestor(&self, key: &str) -> Option<String> {
+        let rest = key.strip_prefix("parent")?;
+        let level: usize = if rest.is_empty() {
+            1
+        } else {
+            let offset: isize = rest
+                .strip_prefix('[')?
+                .strip_suffix(']')?
+                .trim()
+                .parse()
+                .ok()?;
+            if offset >= 0 {
+                return None;
+            }
+            offset.unsigned_abs()
+        };
+
+        let absolute = std::fs::canonicalize(self.path)
+            .or_else(|_| std::path::absolute(self.path))
+            .ok()?;
+        absolute
+            .ancestors()
+            .nth(level)?
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+    }
+}
+
+/// Number planned files within each directory (1-based, ordered by file name)
+///
+/// This is what `{n}` and `{index_in_dir}` render, so album tracks in several
+/// folders each start again at 1.
+pub fn index_in_directories(paths: &[PathBuf]) -> HashMap<PathBuf, usize> {
+    let mut sorted = paths.to_vec();
+    sorted.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    index_in_order(&sorted)
+}
+
+/// Number planned files within each directory in the order given (1-based)
+///
+/// Used instead of [`index_in_directories`] when `BY:` sets the order.
+pub fn index_in_order(paths: &[PathBuf]) -> HashMap<PathBuf, usize> {
+    let mut counters: HashMap<&Path, usize> = HashMap::new();
+    paths
+        .iter()
+        .map(|path| {
+            let counter = counters
+                .entry(path.parent().unwrap_or(Path::new("")))
+                .or_default();
+            *counter += 1;
+            (path.clone(), *counter)
+        })
+        .collect()
+}
+
+/// Resolve `title`, `tags[0]` or `author.name` inside a metadata object
+pub fn lookup_value(root: &Value, key: &str) -> Option<String> {
+    let mut current = root;
+    for part in key.split('.') {
+        let (field, indexes) = match part.find('[') {
+            Some(pos) => (&part[..pos], &part[pos..]),
+            None => (part, ""),
+        };
+        if !field.is_empty() {
+            current = current.get(field)?;
+        }
+        for index in indexes
+            .split(']')
+            .filter_map(|chunk| chunk.strip_prefix('['))
+        {
+            let index: usize = index.trim().parse().ok()?;
+            current = current.get(index)?;
+        }
+    }
+
+    match current {
+        Value::Null => None,
+        Value::String(s) if s.trim().is_empty() => None,
+        Value::String(s) => Some(s.clone()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Array(items) => {
+            let joined: Vec<String> = items
+                .iter()
+                .filter_map(|item| match item {
+                    Value::String(s) => Some(s.clone()),
+                    Value::Number(n) => Some(n.to_string()),
+                    _ => None,
+                })
+                .collect();
+            (!joined.is_empty()).then(|| joined.join("-"))
+        }
+        Value::Object(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    struct StaticProvider(Value);
+
+    impl MetadataProvider for StaticProvider {
+        fn namespace(&self) -> &'static str {
+            "fm"
+        }
+
+        fn extract(&self, _path: &Path) -> Option<Value> {
+            Some(self.0.clone())
+        }
+    }
+
+    fn render(template: &str, path: &str, metadata: Value) -> Result<String, TemplateError> {
+        let providers: Vec<Box<dyn MetadataProvider>> = vec![Box::new(StaticProvider(metadata))];
+        let mut context = TemplateContext::new(Path::new(path), &providers);
+        Template::parse(template)?.render(&mut context)
+    }
+
+    #[test]
+    fn test_builtin_variables() {
+        assert_eq!(
+            render("{name|snake}_v2.{ext}", "dir/My File.TXT", json!({})).unwrap(),
+            "my_file_v2.TXT"
+        );
+    }
+
+    #[test]
+    fn test_namespaced_variables_and_filters() {
+        let metadata = json!({
+            "title": "My Great Note",
+            "date": "2024-03-01",
+            "tags": ["rust", "cli"],
+            "author": { "name": "Ada" }
+        });
+        assert_eq!(
+            render(
+                "{fm:date}-{fm:title|kebab}-{fm:tags[0]}-{fm:author.name|lower}.md",
+                "note.md",
+                metadata
+            )
+            .unwrap(),
+            "2024-03-01-my-great-note-rust-ada.md"
+        );
+    }
+
+    #[test]
+    fn test_location_variables() {
+        let dir = tempfile::tempdir().unwrap();
+        let album = dir.path().join("Artist").join("Album");
+        std::fs::create_dir_all(&album).unwrap();
+        let track = album.join("track.mp3");
+        std::fs::write(&track, "").unwrap();
+
+        let providers: Vec<Box<dyn MetadataProvider>> = Vec::new();
+        let template = Template::parse("{parent[-2]|snake}_{parent}_{n:02}.{ext}").unwrap();
+        let mut context = TemplateContext::new(&track, &providers).with_index(7);
+        assert_eq!(
+            template.render(&mut context).unwrap(),
+            "artist_Album_07.mp3"
+        );
+
+        // Without a plan there is no index to render
+        let mut context = TemplateContext::new(&track, &providers);
+        assert_eq!(
+            Template::parse("{index_in_dir}")
+                .unwrap()
+                .render(&mut context),
+            Err(TemplateError::Missing("index_in_dir".into()))
+        );
+    }
+
+    #[test]
+    fn test_missing_variable() {
+        assert_eq!(
+            render("{fm:title}.md", "note.md", json!({"date": "x"})),
+            Err(TemplateError::Missing("fm:title".into()))
+        );
+    }
+
+    #[test]
+    fn test_values_cannot_introduce_directories() {
+        assert_eq!(
+            render("{fm:title}.md", "note.md", json!({"title": "a/b: c"})).unwrap(),
+            "a-b- c.md"
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert!(matches!(
+            Template::parse("{fm:title"),
+            Err(TemplateError::Syntax(_))
+        ));
+        assert!(matches!(
+            Template::parse("{name|nope}"),
+            Err(TemplateError::Syntax(_))
+        ));
+        assert!(matches!(
+            Template::parse("dir/{name}"),
+            Err(TemplateError::Syntax(_))
+        ));
+        assert_eq!(
+            Template::parse("{{literal}}").unwrap().segments,
+            vec![Segment::Literal("{literal}".into())]
+        );
+    }
+}
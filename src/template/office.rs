@@ -0,0 +1,159 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use super::MetadataProvider;
+use super::pdf::parse_xmp_date;
+
+/// OOXML containers we know carry `docProps/core.xml`
+const OFFICE_EXTENSIONS: &[&str] = &["docx", "docm", "xlsx", "xlsm", "pptx", "pptm"];
+
+/// Property parts larger than this are not worth reading for a file name
+const MAX_PART_SIZE: u64 = 1024 * 1024;
+
+// <dc:title>Report</dc:title>, <cp:lastModifiedBy>...</cp:lastModifiedBy>, <Company>...</Company>
+static XML_ELEMENT_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<(?:[A-Za-z]+:)?([A-Za-z]+)(?:\s[^>]*)?>([^<]*)</").unwrap());
+
+/// Core and app properties of Office documents (docx/xlsx/pptx), exposed as `{doc:key}`
+///
+/// Keys: `title`, `subject`, `author`, `keywords`, `description`, `category`,
+/// `last_modified_by`, `revision`, `created`, `modified` (dates as `YYYY-MM-DD`),
+/// `company` and `application`.
+pub struct OfficeProvider;
+
+impl MetadataProvider for OfficeProvider {
+    fn namespace(&self) -> &'static str {
+        "doc"
+    }
+
+    fn extract(&self, path: &Path) -> Option<Value> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        if !OFFICE_EXTENSIONS.contains(&extension.as_str()) {
+            return None;
+        }
+
+        let mut archive = zip::ZipArchive::new(File::open(path).ok()?).ok()?;
+        let mut fields = Map::new();
+
+        if let Some(core) = read_part(&mut archive, "docProps/core.xml") {
+            parse_core_properties(&core, &mut fields);
+        }
+        if let Some(app) = read_part(&mut archive, "docProps/app.xml") {
+            parse_app_properties(&app, &mut fields);
+        }
+
+        (!fields.is_empty()).then_some(Value::Object(fields))
+    }
+}
+
+fn read_part(archive: &mut zip::ZipArchive<File>, name: &str) -> Option<String> {
+    let part = archive.by_name(name).ok()?;
+    let mut content = String::new();
+    part.take(MAX_PART_SIZE).read_to_string(&mut content).ok()?;
+    Some(content)
+}
+
+/// Read `docProps/core.xml` (Dublin Core plus OPC core properties)
+pub fn parse_core_properties(xml: &str, fields: &mut Map<String, Value>) {
+    for caps in XML_ELEMENT_RE.captures_iter(xml) {
+        let name = match &caps[1] {
+            "title" => "title",
+            "subject" => "subject",
+            "creator" => "author",
+            "keywords" => "keywords",
+            "description" => "description",
+            "category" => "category",
+            "lastModifiedBy" => "last_modified_by",
+            "revision" => "revision",
+            "created" => "created",
+            "modified" => "modified",
+            _ => continue,
+        };
+        let text = unescape_xml(caps[2].trim());
+        if text.is_empty() {
+            continue;
+        }
+        let value = if matches!(name, "created" | "modified") {
+            // W3CDTF timestamps, e.g. 2024-03-01T09:30:00Z, the same form as XMP dates
+            match parse_xmp_date(&text) {
+                Some(date) => date,
+                None => continue,
+            }
+        } else {
+            text
+        };
+        fields.insert(name.into(), Value::from(value));
+    }
+}
+
+/// Read the interesting bits of `docProps/app.xml`
+pub fn parse_app_properties(xml: &str, fields: &mut Map<String, Value>) {
+    for caps in XML_ELEMENT_RE.captures_iter(xml) {
+        let name = match &caps[1] {
+            "Company" => "company",
+            "Application" => "application",
+            _ => continue,
+        };
+        let text = unescape_xml(caps[2].trim());
+        if !text.is_empty() {
+            fields.insert(name.into(), Value::from(text));
+        }
+    }
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_core_properties() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/">
+<dc:title>Budget &amp; Forecast</dc:title><dc:creator>Jane Doe</dc:creator><cp:lastModifiedBy>John</cp:lastModifiedBy>
+<dcterms:created xsi:type="dcterms:W3CDTF">2023-11-02T08:00:00Z</dcterms:created>
+<dcterms:modified xsi:type="dcterms:W3CDTF">2024-03-01T09:30:00Z</dcterms:modified>
+<dc:subject></dc:subject></cp:coreProperties>"#;
+        let mut fields = Map::new();
+        parse_core_properties(xml, &mut fields);
+        assert_eq!(fields["title"], "Budget & Forecast");
+        assert_eq!(fields["author"], "Jane Doe");
+        assert_eq!(fields["last_modified_by"], "John");
+        assert_eq!(fields["created"], "2023-11-02");
+        assert_eq!(fields["modified"], "2024-03-01");
+        assert!(!fields.contains_key("subject"));
+    }
+
+    #[test]
+    fn test_invalid_core_dates_are_left_out() {
+        for date in ["2023-13-45T08:00:00Z", "yesterday afternoon", "0001-01-01"] {
+            let xml = format!("<dcterms:created>{date}</dcterms:created>");
+            let mut fields = Map::new();
+            parse_core_properties(&xml, &mut fields);
+            assert!(!fields.contains_key("created"), "{date}");
+        }
+        let mut fields = Map::new();
+        parse_core_properties("<dcterms:modified>2024-03</dcterms:modified>", &mut fields);
+        assert_eq!(fields["modified"], "2024-03-01");
+    }
+
+    #[test]
+    fn test_parse_app_properties() {
+        let xml = "<Properties><Application>Microsoft Excel</Application><Company>ACME</Company></Properties>";
+        let mut fields = Map::new();
+        parse_app_properties(xml, &mut fields);
+        assert_eq!(fields["company"], "ACME");
+        assert_eq!(fields["application"], "Microsoft Excel");
+    }
+}
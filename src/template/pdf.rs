@@ -0,0 +1,324 @@
+use lopdf::{Document, Object, decode_text_string};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::path::Path;
+
+use super::MetadataProvider;
+
+// D:YYYYMMDDHHmmSS with everything after the year optional
+static PDF_DATE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(?:D:)?(\d{4})(\d{2})?(\d{2})?(\d{2})?(\d{2})?(\d{2})?").unwrap());
+
+static INVOICE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(?:invoice|rechnung|facture|bill)\s*(?:no\.?|number|nr\.?|#)?\s*[:#]?\s*([A-Z0-9][A-Z0-9/_-]{2,})")
+        .unwrap()
+});
+
+static ISO_DATE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b(\d{4})[-/.](\d{1,2})[-/.](\d{1,2})\b").unwrap());
+
+// Day first (31.12.2024, 31/12/2024); month-first dates are too ambiguous to guess
+static DAY_FIRST_DATE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b(\d{1,2})[./](\d{1,2})[./](\d{4})\b").unwrap());
+
+static MONTH_NAME_DATE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(?:(\d{1,2})\.?\s+([a-z]{3,9})\.?,?\s+(\d{4})|([a-z]{3,9})\.?\s+(\d{1,2}),?\s+(\d{4}))\b")
+        .unwrap()
+});
+
+static XMP_FIELD_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?s)<(dc:title|dc:creator|dc:description|xmp:CreateDate|xmp:ModifyDate)[^>]*>(.*?)</(?:dc:title|dc:creator|dc:description|xmp:CreateDate|xmp:ModifyDate)>")
+        .unwrap()
+});
+
+static XML_TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]+>").unwrap());
+
+/// PDF document info, XMP metadata and first-page heuristics, exposed as `{pdf:key}`
+///
+/// Keys: `title`, `author`, `subject`, `keywords`, `creator`, `producer`,
+/// `created`, `modified`, `year`, `pages`, `invoice` (number found on the first
+/// page) and `date` (first date printed on the first page, else the creation date).
+pub struct PdfProvider;
+
+impl MetadataProvider for PdfProvider {
+    fn namespace(&self) -> &'static str {
+        "pdf"
+    }
+
+    fn extract(&self, path: &Path) -> Option<Value> {
+        let is_pdf = path
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("pdf"))
+            .unwrap_or(false);
+        if !is_pdf {
+            return None;
+        }
+
+        let document = Document::load(path).ok()?;
+        let mut fields = Map::new();
+
+        read_info_dictionary(&document, &mut fields);
+        if let Some(xmp) = read_xmp(&document) {
+            merge_xmp(&xmp, &mut fields);
+        }
+
+        let pages = document.get_pages();
+        fields.insert("pages".into(), Value::from(pages.len()));
+
+        // Text extraction fails on some encodings; the heuristics are best-effort
+        let first_page_text = pages
+            .keys()
+            .next()
+            .and_then(|first| document.extract_text(&[*first]).ok())
+            .unwrap_or_default();
+
+        if let Some(invoice) = find_invoice_number(&first_page_text) {
+            fields.insert("invoice".into(), Value::from(invoice));
+        }
+
+        let date = find_date(&first_page_text).or_else(|| {
+            fields
+                .get("created")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        });
+        if let Some(date) = date {
+            if let Some(year) = date.get(..4) {
+                fields.insert("year".into(), Value::from(year));
+            }
+            fields.insert("date".into(), Value::from(date));
+        }
+
+        Some(Value::Object(fields))
+    }
+}
+
+fn read_info_dictionary(document: &Document, fields: &mut Map<String, Value>) {
+    let info = match document.trailer.get(b"Info") {
+        Ok(Object::Reference(id)) => document.get_dictionary(*id).ok(),
+        Ok(Object::Dictionary(dictionary)) => Some(dictionary),
+        _ => None,
+    };
+    let Some(info) = info else {
+        return;
+    };
+
+    let keys: [(&[u8], &str); 8] = [
+        (b"Title", "title"),
+        (b"Author", "author"),
+        (b"Subject", "subject"),
+        (b"Keywords", "keywords"),
+        (b"Creator", "creator"),
+        (b"Producer", "producer"),
+        (b"CreationDate", "created"),
+        (b"ModDate", "modified"),
+    ];
+
+    for (pdf_key, name) in keys {
+        let Ok(object) = info.get_deref(pdf_key, document) else {
+            continue;
+        };
+        let Ok(text) = decode_text_string(object) else {
+            continue;
+        };
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        let value = if matches!(name, "created" | "modified") {
+            match parse_pdf_date(text) {
+                Some(date) => date,
+                None => continue,
+            }
+        } else {
+            text.to_string()
+        };
+        fields.insert(name.into(), Value::from(value));
+    }
+}
+
+fn read_xmp(document: &Document) -> Option<String> {
+    let catalog = document.catalog().ok()?;
+    let stream = catalog
+        .get_deref(b"Metadata", document)
+        .ok()?
+        .as_stream()
+        .ok()?;
+    let content = stream
+        .decompressed_content()
+        .unwrap_or_else(|_| stream.content.clone());
+    String::from_utf8(content).ok()
+}
+
+/// Fill gaps left by the info dictionary from XMP (`dc:title`, `xmp:CreateDate`, ...)
+fn merge_xmp(xmp: &str, fields: &mut Map<String, Value>) {
+    for caps in XMP_FIELD_RE.captures_iter(xmp) {
+        let name = match &caps[1] {
+            "dc:title" => "title",
+            "dc:creator" => "author",
+            "dc:description" => "subject",
+            "xmp:CreateDate" => "created",
+            _ => "modified",
+        };
+        if fields.contains_key(name) {
+            continue;
+        }
+        let text = XML_TAG_RE.replace_all(&caps[2], " ");
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let value = if matches!(name, "created" | "modified") {
+            parse_xmp_date(&text)
+        } else {
+            Some(text)
+        };
+        if let Some(value) = value.filter(|v| !v.is_empty()) {
+            fields.insert(name.into(), Value::from(value));
+        }
+    }
+}
+
+/// Convert a PDF date (`D:20240301123000+01'00'`) to `YYYY-MM-DD`
+pub fn parse_pdf_date(raw: &str) -> Option<String> {
+    let caps = PDF_DATE_RE.captures(raw.trim())?;
+    let year = &caps[1];
+    let month = caps.get(2).map(|m| m.as_str()).unwrap_or("01");
+    let day = caps.get(3).map(|m| m.as_str()).unwrap_or("01");
+    valid_date(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?)
+}
+
+/// Convert an XMP date (`2024-03-01T12:30:00+01:00`, or just `2024-03`) to `YYYY-MM-DD`
+pub fn parse_xmp_date(raw: &str) -> Option<String> {
+    let date = raw.trim().split('T').next()?;
+    let mut parts = date.splitn(3, '-');
+    let year = parts.next().filter(|year| year.len() == 4)?;
+    let month = parts.next().unwrap_or("01");
+    let day = parts.next().unwrap_or("01");
+    valid_date(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?)
+}
+
+/// Find an invoice number such as `Invoice No: INV-2024-001`
+pub fn find_invoice_number(text: &str) -> Option<String> {
+    INVOICE_RE
+        .captures_iter(text)
+        .map(|caps| caps[1].trim_end_matches(['-', '/', '_']).to_string())
+        // Skip words that merely follow "invoice", e.g. "Invoice date"
+        .find(|candidate| candidate.chars().any(|c| c.is_ascii_digit()))
+}
+
+/// Find the first plausible date in free text, returned as `YYYY-MM-DD`
+pub fn find_date(text: &str) -> Option<String> {
+    let mut found: Vec<(usize, String)> = Vec::new();
+
+    for caps in ISO_DATE_RE.captures_iter(text) {
+        if let Some(date) = valid_date(
+            caps[1].parse().ok()?,
+            caps[2].parse().ok()?,
+            caps[3].parse().ok()?,
+        ) {
+            found.push((caps.get(0)?.start(), date));
+        }
+    }
+
+    for caps in DAY_FIRST_DATE_RE.captures_iter(text) {
+        if let Some(date) = valid_date(
+            caps[3].parse().ok()?,
+            caps[2].parse().ok()?,
+            caps[1].parse().ok()?,
+        ) {
+            found.push((caps.get(0)?.start(), date));
+        }
+    }
+
+    for caps in MONTH_NAME_DATE_RE.captures_iter(text) {
+        let (day, month, year) = match caps.get(1) {
+            Some(day) => (day.as_str(), &caps[2], &caps[3]),
+            None => (&caps[5], &caps[4], &caps[6]),
+        };
+        let Some(month) = month_number(month) else {
+            continue;
+        };
+        if let Some(date) = valid_date(year.parse().ok()?, month, day.parse().ok()?) {
+            found.push((caps.get(0)?.start(), date));
+        }
+    }
+
+    found
+        .into_iter()
+        .min_by_key(|(pos, _)| *pos)
+        .map(|(_, d)| d)
+}
+
+fn month_number(name: &str) -> Option<u32> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let name = name.to_lowercase();
+    if name.len() < 3 {
+        return None;
+    }
+    MONTHS
+        .iter()
+        .position(|month| name.starts_with(month))
+        .map(|index| index as u32 + 1)
+}
+
+fn valid_date(year: i32, month: u32, day: u32) -> Option<String> {
+    chrono::NaiveDate::from_ymd_opt(year, month, day)
+        .filter(|_| (1900..=2100).contains(&year))
+        .map(|date| date.format("%Y-%m-%d").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pdf_date() {
+        assert_eq!(
+            parse_pdf_date("D:20240301123000+01'00'"),
+            Some("2024-03-01".into())
+        );
+        assert_eq!(parse_pdf_date("D:2023"), Some("2023-01-01".into()));
+        assert_eq!(parse_pdf_date("yesterday"), None);
+    }
+
+    #[test]
+    fn test_xmp_dates_are_validated() {
+        assert_eq!(
+            parse_xmp_date("2024-03-01T12:30:00+01:00"),
+            Some("2024-03-01".into())
+        );
+        assert_eq!(parse_xmp_date("2023-07"), Some("2023-07-01".into()));
+        assert_eq!(parse_xmp_date("2024-13-45"), None);
+        assert_eq!(parse_xmp_date("n/a"), None);
+
+        let mut fields = Map::new();
+        merge_xmp(
+            "<xmp:CreateDate>sometime</xmp:CreateDate><xmp:ModifyDate>2024-02-30</xmp:ModifyDate>",
+            &mut fields,
+        );
+        assert!(fields.is_empty(), "{fields:?}");
+    }
+
+    #[test]
+    fn test_find_invoice_number() {
+        let text = "ACME Corp\nInvoice date: 2024-03-01\nInvoice No: INV-2024-0042\n";
+        assert_eq!(find_invoice_number(text), Some("INV-2024-0042".into()));
+        assert_eq!(
+            find_invoice_number("Rechnung Nr. 12345"),
+            Some("12345".into())
+        );
+        assert_eq!(find_invoice_number("No numbers here"), None);
+    }
+
+    #[test]
+    fn test_find_date_prefers_first_occurrence() {
+        assert_eq!(
+            find_date("Issued 15.02.2024, due 2024-03-15"),
+            Some("2024-02-15".into())
+        );
+        assert_eq!(find_date("Date: March 5, 2023"), Some("2023-03-05".into()));
+        assert_eq!(find_date("1 Sept 2022"), Some("2022-09-01".into()));
+        assert_eq!(find_date("Total 99.99 EUR"), None);
+    }
+}
@@ -0,0 +1,214 @@
+use serde_json::{Map, Value};
+use std::path::Path;
+
+use super::MetadataProvider;
+
+/// Containers worth handing to the probe backend
+const VIDEO_EXTENSIONS: &[&str] = &[
+    "mp4", "m4v", "mkv", "mov", "avi", "webm", "wmv", "flv", "mpg", "mpeg", "ts", "3gp",
+];
+
+/// Basic container metadata for a video file
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoInfo {
+    pub width: u32,
+    pub height: u32,
+    pub duration_secs: f64,
+    pub codec: String,
+    pub fps: Option<f64>,
+}
+
+impl VideoInfo {
+    /// Common label for the vertical resolution (`1080p`, `2160p`)
+    pub fn resolution_label(&self) -> String {
+        format!("{}p", self.height)
+    }
+
+    /// Duration as `1h02m03s` (or `2m03s` for clips under an hour)
+    pub fn duration_label(&self) -> String {
+        let total = self.duration_secs.round() as u64;
+        let (hours, minutes, seconds) = (total / 3600, (total % 3600) / 60, total % 60);
+        if hours > 0 {
+            format!("{hours}h{minutes:02}m{seconds:02}s")
+        } else {
+            format!("{minutes}m{seconds:02}s")
+        }
+    }
+}
+
+/// Whether this build can read video metadata
+pub fn backend_available() -> bool {
+    cfg!(feature = "video")
+}
+
+#[cfg_attr(not(feature = "video"), allow(dead_code))]
+fn is_video(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| {
+            let ext = ext.to_string_lossy().to_lowercase();
+            VIDEO_EXTENSIONS.contains(&ext.as_str())
+        })
+        .unwrap_or(false)
+}
+
+/// Probe a video file, returning `None` for non-video files or when probing fails
+#[cfg(feature = "video")]
+pub fn probe(path: &Path) -> Option<VideoInfo> {
+    use std::sync::OnceLock;
+
+    // Probing runs per file; the config is read once
+    static TOOLS: OnceLock<crate::config::Tools> = OnceLock::new();
+
+    if !is_video(path) {
+        return None;
+    }
+
+    let tools = TOOLS.get_or_init(|| crate::config::Config::load().unwrap_or_default().tools);
+    let output = tools
+        .command("ffprobe")
+        .ok()?
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=width,height,codec_name,avg_frame_rate:format=duration",
+            "-of",
+            "json",
+        ])
+        .arg(path)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    parse_ffprobe_json(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Without the `video` feature there is no backend to ask
+#[cfg(not(feature = "video"))]
+pub fn probe(_path: &Path) -> Option<VideoInfo> {
+    None
+}
+
+/// Parse `ffprobe -of json` output for the first video stream
+pub fn parse_ffprobe_json(json: &str) -> Option<VideoInfo> {
+    let value: Value = serde_json::from_str(json).ok()?;
+    let stream = value.get("streams")?.get(0)?;
+
+    let width = stream.get("width")?.as_u64()? as u32;
+    let height = stream.get("height")?.as_u64()? as u32;
+    let codec = stream
+        .get("codec_name")
+        .and_then(Value::as_str)
+        .unwrap_or("unknown")
+        .to_string();
+    let fps = stream
+        .get("avg_frame_rate")
+        .and_then(Value::as_str)
+        .and_then(parse_frame_rate);
+    let duration_secs = value
+        .get("format")
+        .and_then(|format| format.get("duration"))
+        .and_then(Value::as_str)
+        .and_then(|d| d.parse::<f64>().ok())
+        .unwrap_or(0.0);
+
+    Some(VideoInfo {
+        width,
+        height,
+        duration_secs,
+        codec,
+        fps,
+    })
+}
+
+fn parse_frame_rate(rate: &str) -> Option<f64> {
+    let (numerator, denominator) = rate.split_once('/')?;
+    let numerator: f64 = numerator.parse().ok()?;
+    let denominator: f64 = denominator.parse().ok()?;
+    (denominator > 0.0 && numerator > 0.0).then(|| numerator / denominator)
+}
+
+/// Parse a resolution threshold such as `1080p`, `720`, `4k` or `1920x1080` into a height
+pub fn parse_resolution(value: &str) -> Option<u32> {
+    let value = value.trim().to_lowercase();
+    match value.as_str() {
+        "sd" => return Some(480),
+        "hd" => return Some(720),
+        "fhd" => return Some(1080),
+        "qhd" => return Some(1440),
+        "uhd" | "4k" => return Some(2160),
+        "8k" => return Some(4320),
+        _ => {}
+    }
+    if let Some((_, height)) = value.split_once('x') {
+        return height.parse().ok();
+    }
+    value.trim_end_matches('p').parse().ok()
+}
+
+/// Video metadata for templates, as `{video:width}` or simply `{width}`
+///
+/// Keys: `width`, `height`, `resolution` (`1080p`), `duration` (`1h02m03s`),
+/// `duration_secs`, `codec` and `fps`.
+pub struct VideoProvider;
+
+impl MetadataProvider for VideoProvider {
+    fn namespace(&self) -> &'static str {
+        "video"
+    }
+
+    fn bare_keys(&self) -> &'static [&'static str] {
+        &["width", "height", "resolution", "duration", "codec", "fps"]
+    }
+
+    fn extract(&self, path: &Path) -> Option<Value> {
+        let info = probe(path)?;
+        let mut fields = Map::new();
+        fields.insert("width".into(), Value::from(info.width));
+        fields.insert("height".into(), Value::from(info.height));
+        fields.insert("resolution".into(), Value::from(info.resolution_label()));
+        fields.insert("duration".into(), Value::from(info.duration_label()));
+        fields.insert(
+            "duration_secs".into(),
+            Value::from(info.duration_secs.round() as u64),
+        );
+        fields.insert("codec".into(), Value::from(info.codec.clone()));
+        if let Some(fps) = info.fps {
+            fields.insert("fps".into(), Value::from(format!("{}", fps.round() as u32)));
+        }
+        Some(Value::Object(fields))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ffprobe_json() {
+        let json = r#"{
+            "programs": [],
+            "streams": [{"codec_name": "h264", "width": 1920, "height": 1080, "avg_frame_rate": "30000/1001"}],
+            "format": {"duration": "3723.4"}
+        }"#;
+        let info = parse_ffprobe_json(json).unwrap();
+        assert_eq!(info.width, 1920);
+        assert_eq!(info.codec, "h264");
+        assert_eq!(info.resolution_label(), "1080p");
+        assert_eq!(info.duration_label(), "1h02m03s");
+        assert_eq!(info.fps.map(|f| f.round() as u32), Some(30));
+    }
+
+    #[test]
+    fn test_parse_resolution() {
+        assert_eq!(parse_resolution("1080p"), Some(1080));
+        assert_eq!(parse_resolution("720"), Some(720));
+        assert_eq!(parse_resolution("4K"), Some(2160));
+        assert_eq!(parse_resolution("1280x720"), Some(720));
+        assert_eq!(parse_resolution("big"), None);
+    }
+}
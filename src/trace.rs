@@ -0,0 +1,286 @@
+//! Following a file through the undo history (`smv where-did-it-go <name>`)
+//!
+//! Weeks after a large batch rename the question is rarely how to undo it, but
+//! where one file ended up. Every recorded move whose old or new name contains
+//! the search (ignoring case) starts a trail, which is followed forward through
+//! later renames, moves of directories it sat in, and removals, to where the
+//! file should be now. Only what is still in the history can be followed.
+
+use chrono::{DateTime, Local};
+use colored::*;
+use serde::Serialize;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::history::HistoryManager;
+
+/// What one recorded step did to a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    /// New name in the same directory
+    Renamed,
+    /// Into another directory
+    Moved,
+    /// Along with a directory above it
+    Carried,
+    /// Created or copied there
+    Created,
+    /// Into the trash by `smv rm`
+    Removed,
+}
+
+impl Change {
+    fn label(self) -> &'static str {
+        match self {
+            Change::Renamed => "renamed",
+            Change::Moved => "moved",
+            Change::Carried => "carried",
+            Change::Created => "created",
+            Change::Removed => "removed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Step {
+    pub timestamp: DateTime<Local>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch: Option<String>,
+    pub change: Change,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<PathBuf>,
+    pub to: PathBuf,
+}
+
+impl Step {
+    fn new(record: &Record, change: Change, from: Option<PathBuf>, to: PathBuf) -> Self {
+        Step {
+            timestamp: record.timestamp,
+            batch: record.batch.clone(),
+            change,
+            from,
+            to,
+        }
+    }
+}
+
+/// Everything the history says happened to one file, oldest first
+#[derive(Debug, Clone, Serialize)]
+pub struct Trail {
+    pub steps: Vec<Step>,
+    /// Where the file should be now: its last destination, or its place in the trash
+    pub now: PathBuf,
+    /// Whether something is at `now`
+    pub exists: bool,
+}
+
+impl Trail {
+    pub fn removed(&self) -> bool {
+        self.steps
+            .last()
+            .is_some_and(|step| step.change == Change::Removed)
+    }
+}
+
+/// One recorded file move, flattened out of its operation
+struct Record {
+    timestamp: DateTime<Local>,
+    batch: Option<String>,
+    source: PathBuf,
+    destination: PathBuf,
+    removal: bool,
+}
+
+/// The trails of every file whose name, old or new, contains `name`
+pub fn trace(history: &HistoryManager, name: &str) -> Result<Vec<Trail>, Box<dyn Error>> {
+    let mut records = Vec::new();
+    // Permission changes leave every file where it was
+    for operation in history
+        .list_operations()
+        .iter()
+        .filter(|op| op.modes.is_none())
+    {
+        for file in history.files_of(operation)? {
+            records.push(Record {
+                timestamp: operation.timestamp,
+                batch: operation.batch.clone(),
+                source: file.source,
+                destination: file.destination,
+                removal: operation.trash.is_some(),
+            });
+        }
+    }
+
+    let needle = name.to_lowercase();
+    let matches = |path: &Path| {
+        path.file_name()
+            .is_some_and(|file| file.to_string_lossy().to_lowercase().contains(&needle))
+    };
+
+    let mut trails: Vec<Trail> = Vec::new();
+    for record in &records {
+        let mut followed = false;
+        for trail in trails.iter_mut().filter(|trail| !trail.removed()) {
+            if let Some(step) = follow(&trail.now, record) {
+                trail.now = step.to.clone();
+                trail.steps.push(step);
+                followed = true;
+            }
+        }
+        // A file carried along with its directory is not a match of its own
+        if followed || !(matches(&record.source) || matches(&record.destination)) {
+            continue;
+        }
+        let step = if record.source.as_os_str().is_empty() {
+            Step::new(record, Change::Created, None, record.destination.clone())
+        } else {
+            direct(record)
+        };
+        trails.push(Trail {
+            now: step.to.clone(),
+            steps: vec![step],
+            exists: false,
+        });
+    }
+
+    for trail in &mut trails {
+        trail.exists = trail.now.symlink_metadata().is_ok();
+    }
+    Ok(trails)
+}
+
+/// The step `record` makes with a file at `path`, if it touches it
+fn follow(path: &Path, record: &Record) -> Option<Step> {
+    if record.source.as_os_str().is_empty() {
+        return None;
+    }
+    if path == record.source {
+        return Some(direct(record));
+    }
+    let rest = path.strip_prefix(&record.source).ok()?;
+    let change = if record.removal {
+        Change::Removed
+    } else {
+        Change::Carried
+    };
+    Some(Step::new(
+        record,
+        change,
+        Some(path.to_path_buf()),
+        record.destination.join(rest),
+    ))
+}
+
+/// The step `record` makes with its own source
+fn direct(record: &Record) -> Step {
+    let change = if record.removal {
+        Change::Removed
+    } else if record.source.parent() == record.destination.parent() {
+        Change::Renamed
+    } else {
+        Change::Moved
+    };
+    Step::new(
+        record,
+        change,
+        Some(record.source.clone()),
+        record.destination.clone(),
+    )
+}
+
+pub fn print(name: &str, trails: &[Trail]) {
+    println!("{} {:?}", "CNP Smart Move - Where did it go:".bold(), name);
+    if trails.is_empty() {
+        println!("\nNothing in the history was ever named like that.");
+        return;
+    }
+    for trail in trails {
+        println!();
+        for step in &trail.steps {
+            let from = step
+                .from
+                .as_ref()
+                .map(|from| format!("{} → ", from.display()))
+                .unwrap_or_default();
+            println!(
+                "  {}  {:<8} {}{}",
+                step.timestamp.format("%Y-%m-%d %H:%M"),
+                step.change.label(),
+                from,
+                step.to.display()
+            );
+        }
+        let now = trail.now.display().to_string();
+        match (trail.removed(), trail.exists) {
+            (true, true) => println!(
+                "  {} in the trash at {} (undo the removal to bring it back)",
+                "now:".bold(),
+                now.yellow()
+            ),
+            (true, false) => println!(
+                "  {} gone: the trash copy at {} no longer exists",
+                "now:".bold(),
+                now.red()
+            ),
+            (false, true) => println!("  {} {}", "now:".bold(), now.green()),
+            (false, false) => println!(
+                "  {} {} {}",
+                "now:".bold(),
+                now.red(),
+                "(not there any more; changed outside smv)".dimmed()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_trace_follows_renames_and_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let inbox = root.join("inbox");
+        let archive = root.join("archive");
+        fs::create_dir_all(&inbox).unwrap();
+        fs::write(inbox.join("Final Report.pdf"), "report").unwrap();
+        fs::write(inbox.join("notes.txt"), "notes").unwrap();
+
+        let mut history = HistoryManager::new(10, &root.join("state"));
+        fs::rename(
+            inbox.join("Final Report.pdf"),
+            inbox.join("final_report.pdf"),
+        )
+        .unwrap();
+        history
+            .record_mapping(
+                root.to_path_buf(),
+                &[(
+                    inbox.join("Final Report.pdf"),
+                    inbox.join("final_report.pdf"),
+                )],
+            )
+            .unwrap();
+        history.start_batch();
+        fs::rename(&inbox, &archive).unwrap();
+        history
+            .record_mapping(root.to_path_buf(), &[(inbox.clone(), archive.clone())])
+            .unwrap();
+
+        let trails = trace(&history, "REPORT").unwrap();
+        assert_eq!(trails.len(), 1);
+        let changes: Vec<Change> = trails[0].steps.iter().map(|s| s.change).collect();
+        assert_eq!(changes, [Change::Renamed, Change::Carried]);
+        assert_eq!(trails[0].now, archive.join("final_report.pdf"));
+        assert!(trails[0].exists);
+
+        // The directory matches as a file of its own; its contents do not
+        let trails = trace(&history, "inbox").unwrap();
+        assert_eq!(trails.len(), 1);
+        assert_eq!(trails[0].steps[0].change, Change::Renamed);
+        assert!(trace(&history, "notes").unwrap().is_empty());
+    }
+}
@@ -1,6 +1,7 @@
 use deunicode::deunicode;
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use regex::Regex;
+use std::ffi::OsStr;
 
 /// Transformation types available for filename conversion
 ///
@@ -169,6 +170,207 @@ static WORD_SEPARATORS_WITH_DOTS_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"[\s
 /// # Returns
 /// A new string transformed according to the specified transformation type
 pub fn transform(name: &str, transform_type: &TransformType) -> String {
+    // Dotfiles keep their leading dot: `.env.local` is a hidden `env.local`, not a
+    // file named `env` with a `.local` extension. Literal edits see the whole name.
+    let rest = name.trim_start_matches('.');
+    let literal = matches!(
+        transform_type,
+        TransformType::Replace(..)
+            | TransformType::ReplaceRegex(..)
+            | TransformType::RemovePrefix(_)
+    );
+    if rest.len() < name.len() && !rest.is_empty() && !literal {
+        let renamed = apply_transform(rest, transform_type);
+        return if renamed.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}{}", &name[..name.len() - rest.len()], renamed)
+        };
+    }
+
+    let renamed = apply_transform(name, transform_type);
+    // Symbol-only names ("###") have nothing left to rename to; keep them as they are
+    if renamed.is_empty() {
+        name.to_string()
+    } else {
+        renamed
+    }
+}
+
+/// Why a transformed name cannot be used as is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameProblem {
+    Empty,
+    DotsOnly,
+    BecomesHidden,
+    StopsBeingHidden,
+}
+
+impl std::fmt::Display for NameProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            NameProblem::Empty => "empty name",
+            NameProblem::DotsOnly => "name of only dots",
+            NameProblem::BecomesHidden => "would become a hidden file",
+            NameProblem::StopsBeingHidden => "would no longer be hidden",
+        })
+    }
+}
+
+/// Check a transformed name before anything is renamed to it
+pub fn check_name(original: &str, renamed: &str) -> Option<NameProblem> {
+    if renamed.trim().is_empty() {
+        Some(NameProblem::Empty)
+    } else if renamed.chars().all(|c| c == '.') {
+        Some(NameProblem::DotsOnly)
+    } else if renamed.starts_with('.') && !original.starts_with('.') {
+        Some(NameProblem::BecomesHidden)
+    } else if original.starts_with('.') && !renamed.starts_with('.') {
+        Some(NameProblem::StopsBeingHidden)
+    } else {
+        None
+    }
+}
+
+/// What to do with a name [`check_name`] rejects (`--name-fallback`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NameFallback {
+    /// Leave the item as it is
+    #[default]
+    Keep,
+    /// Use [`PLACEHOLDER_NAME`], keeping the original extension
+    Placeholder,
+}
+
+impl NameFallback {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "keep" => Ok(NameFallback::Keep),
+            "placeholder" => Ok(NameFallback::Placeholder),
+            other => Err(format!(
+                "Unknown name fallback '{other}' (expected keep or placeholder)"
+            )),
+        }
+    }
+
+    /// The name to use instead of a rejected one; `None` keeps the original
+    ///
+    /// Names whose words were all symbols get the placeholder; a hidden file that
+    /// would lose its leading dot is always kept.
+    pub fn apply(self, original: &str, problem: NameProblem) -> Option<String> {
+        if self == NameFallback::Keep || problem == NameProblem::StopsBeingHidden {
+            return None;
+        }
+        match original.rfind('.') {
+            Some(dot) if dot > 0 && dot < original.len() - 1 => Some(format!(
+                "{PLACEHOLDER_NAME}.{}",
+                original[dot + 1..].to_lowercase()
+            )),
+            _ => Some(PLACEHOLDER_NAME.to_string()),
+        }
+    }
+}
+
+/// Name given by [`NameFallback::Placeholder`]
+pub const PLACEHOLDER_NAME: &str = "untitled";
+
+/// Shorten `name` to at most `max_bytes` bytes of UTF-8, keeping its extension
+///
+/// Filesystems limit names in bytes, so a name of 200 accented letters can be
+/// too long where 200 ASCII ones are not. The cut falls between characters,
+/// and separators left dangling at it are dropped.
+pub fn fit_name(name: &str, max_bytes: usize) -> String {
+    if name.len() <= max_bytes {
+        return name.to_string();
+    }
+    let (stem, extension) = match name.rfind('.') {
+        // An extension that takes up half the room is cut like the rest
+        Some(dot) if dot > 0 && name.len() - dot <= max_bytes / 2 => name.split_at(dot),
+        _ => (name, ""),
+    };
+    let room = max_bytes - extension.len();
+    let cut = stem
+        .char_indices()
+        .map(|(index, c)| index + c.len_utf8())
+        .take_while(|end| *end <= room)
+        .last()
+        .unwrap_or(0);
+    let stem = stem[..cut].trim_end_matches(['_', '-', ' ', '.']);
+    format!("{stem}{extension}")
+}
+
+/// What to do with file names that are not valid UTF-8 (`--non-utf8`)
+///
+/// Transforms work on text. Read lossily, such a name would be renamed to one
+/// with U+FFFD replacement characters in place of the bytes that were there.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NonUtf8 {
+    /// Leave the item as it is, and say so
+    #[default]
+    Skip,
+    /// Read the bytes that are not UTF-8 as Latin-1, and rename to that text
+    Transliterate,
+}
+
+static NON_UTF8: OnceCell<NonUtf8> = OnceCell::new();
+
+impl NonUtf8 {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "skip" => Ok(NonUtf8::Skip),
+            "transliterate" => Ok(NonUtf8::Transliterate),
+            other => Err(format!(
+                "Unknown --non-utf8 policy '{other}' (expected skip or transliterate)"
+            )),
+        }
+    }
+
+    /// Handle names that are not UTF-8 this way for the rest of the run
+    pub fn set(self) {
+        let _ = NON_UTF8.set(self);
+    }
+
+    pub fn current() -> Self {
+        NON_UTF8.get().copied().unwrap_or_default()
+    }
+}
+
+/// A file name as text to transform, or `None` when it is not UTF-8 and is skipped
+///
+/// With [`NonUtf8::Transliterate`] the text differs from the name on disk, so
+/// compare a transformed name with `name` itself to tell whether it changed.
+pub fn name_text(name: &OsStr) -> Option<String> {
+    if let Some(text) = name.to_str() {
+        return Some(text.to_string());
+    }
+    match NonUtf8::current() {
+        NonUtf8::Skip => None,
+        NonUtf8::Transliterate => Some(transliterate(name)),
+    }
+}
+
+#[cfg(unix)]
+fn transliterate(name: &OsStr) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut text = String::new();
+    for chunk in name.as_bytes().utf8_chunks() {
+        text.push_str(chunk.valid());
+        // Latin-1 maps 0x80-0x9f to control characters, which no name should get
+        text.extend(chunk.invalid().iter().map(|&byte| match byte {
+            0x80..=0x9f => '_',
+            _ => char::from(byte),
+        }));
+    }
+    text
+}
+
+#[cfg(not(unix))]
+fn transliterate(name: &OsStr) -> String {
+    name.to_string_lossy().replace('\u{fffd}', "_")
+}
+
+fn apply_transform(name: &str, transform_type: &TransformType) -> String {
     match transform_type {
         TransformType::Clean => clean(name),
         TransformType::Snake => snake_case_preserve_extension(name),
@@ -214,10 +416,11 @@ pub fn transform(name: &str, transform_type: &TransformType) -> String {
 /// A cleaned string with normalized spacing and no special characters
 fn clean(name: &str) -> String {
     let trimmed = name.trim();
-    let normalized_spaces = MULTIPLE_SPACES_RE.replace_all(trimmed, " ");
-    let no_special_chars = SPECIAL_CHARS_RE.replace_all(&normalized_spaces, "");
+    // Removing characters can leave spaces next to each other, so normalize after
+    let no_special_chars = SPECIAL_CHARS_RE.replace_all(trimmed, "");
+    let normalized_spaces = MULTIPLE_SPACES_RE.replace_all(&no_special_chars, " ");
     LEADING_TRAILING_SPECIALS_RE
-        .replace_all(&no_special_chars, "")
+        .replace_all(&normalized_spaces, "")
         .to_string()
 }
 
@@ -240,21 +443,7 @@ fn snake_case(name: &str) -> String {
 
 /// Convert a filename to snake_case while preserving the file extension
 fn snake_case_preserve_extension(name: &str) -> String {
-    if let Some(dot_pos) = name.rfind('.') {
-        if dot_pos > 0 && dot_pos < name.len() - 1 {
-            // File has an extension
-            let (basename, extension) = name.split_at(dot_pos);
-            let transformed_basename = snake_case(basename);
-            let transformed_extension = extension[1..].to_lowercase(); // Remove the dot and lowercase extension
-            format!("{transformed_basename}.{transformed_extension}")
-        } else {
-            // Dot at beginning or end, treat as regular filename
-            snake_case(name)
-        }
-    } else {
-        // No extension
-        snake_case(name)
-    }
+    preserve_extension_transform(name, snake_case)
 }
 
 /// Convert a filename to kebab-case
@@ -289,6 +478,10 @@ where
             // File has an extension
             let (basename, extension) = name.split_at(dot_pos);
             let transformed_basename = transform_fn(basename);
+            if transformed_basename.is_empty() {
+                // A symbol-only stem ("-.txt") would leave just ".txt", a hidden file
+                return name.to_string();
+            }
             let transformed_extension = extension[1..].to_lowercase(); // Remove the dot and lowercase extension
             format!("{transformed_basename}.{transformed_extension}")
         } else {
@@ -591,13 +784,11 @@ fn replace_substring(name: &str, find: &str, replace: &str) -> String {
 ///
 /// # Returns
 /// A new string with all pattern matches replaced, or the original string if regex is invalid
+/// (callers validate the pattern up front to report the error)
 fn replace_regex(name: &str, pattern: &str, replacement: &str) -> String {
     match Regex::new(pattern) {
         Ok(re) => re.replace_all(name, replacement).to_string(),
-        Err(_) => {
-            eprintln!("Warning: Invalid regex pattern '{pattern}', returning original string");
-            name.to_string()
-        }
+        Err(_) => name.to_string(),
     }
 }
 
@@ -683,6 +874,89 @@ fn split_and_transform_basename(basename: &str, transform_type: TransformType) -
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_dotfiles() {
+        assert_eq!(transform(".env.local", &TransformType::Snake), ".env.local");
+        assert_eq!(
+            transform(".My Config.JSON", &TransformType::Snake),
+            ".my_config.json"
+        );
+        assert_eq!(transform(".gitignore", &TransformType::Kebab), ".gitignore");
+        assert_eq!(transform(".gitignore", &TransformType::Clean), ".gitignore");
+        assert_eq!(transform(".tar.gz", &TransformType::Snake), ".tar.gz");
+        assert_eq!(
+            transform(".My Notes.TXT", &TransformType::Kebab),
+            ".my-notes.txt"
+        );
+        assert_eq!(
+            transform(".hidden file", &TransformType::Title),
+            ".HiddenFile"
+        );
+        // Nothing but dots is left alone
+        assert_eq!(transform("..", &TransformType::Snake), "..");
+        assert_eq!(transform(".", &TransformType::Kebab), ".");
+        assert_eq!(transform("..###", &TransformType::Clean), "..###");
+        // Literal edits may still remove the dot on purpose
+        assert_eq!(transform(".env", &TransformType::remove_prefix(".")), "env");
+    }
+
+    #[test]
+    fn test_fit_name_counts_bytes() {
+        // 200 characters fit a 255-byte limit only while each is one byte
+        assert_eq!(fit_name(&"a".repeat(200), 255).len(), 200);
+        let accented = format!("{}.txt", "é".repeat(200));
+        let fitted = fit_name(&accented, 255);
+        assert!(fitted.len() <= 255);
+        assert!(fitted.ends_with("é.txt"));
+        assert_eq!(fitted.chars().count(), 125 + 4);
+
+        assert_eq!(fit_name("long_name_here.md", 12), "long_name.md");
+        assert_eq!(fit_name("abcdef.verylongextension", 10), "abcdef.ver");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_names() {
+        use std::os::unix::ffi::OsStrExt;
+
+        // "Café 1.txt" written in Latin-1, and a stray C1 byte
+        let name = OsStr::from_bytes(b"Caf\xe9 1\x85.txt");
+        assert_eq!(transliterate(name), "Café 1_.txt");
+        assert_eq!(
+            name_text(OsStr::new("Café.txt")),
+            Some("Café.txt".to_string())
+        );
+        assert_eq!(NonUtf8::parse("SKIP"), Ok(NonUtf8::Skip));
+        assert!(NonUtf8::parse("lossy").is_err());
+    }
+
+    #[test]
+    fn test_check_name() {
+        assert_eq!(
+            check_name("***.png", ".png"),
+            Some(NameProblem::BecomesHidden)
+        );
+        assert_eq!(check_name("x", ""), Some(NameProblem::Empty));
+        assert_eq!(check_name("a..", ".."), Some(NameProblem::DotsOnly));
+        assert_eq!(
+            check_name(".env", "env"),
+            Some(NameProblem::StopsBeingHidden)
+        );
+        assert_eq!(check_name(".My Env", ".my_env"), None);
+
+        let placeholder = NameFallback::Placeholder;
+        assert_eq!(
+            placeholder.apply("***.PNG", NameProblem::BecomesHidden),
+            Some("untitled.png".to_string())
+        );
+        assert_eq!(
+            placeholder.apply(".env", NameProblem::StopsBeingHidden),
+            None
+        );
+        assert_eq!(NameFallback::Keep.apply("***", NameProblem::Empty), None);
+        assert!(NameFallback::parse("rename").is_err());
+    }
+
     #[test]
     fn test_clean() {
         assert_eq!(clean("  My File (1) !!  "), "My File 1");
@@ -36,6 +36,12 @@ pub enum UiAction {
     GroupFiles,
     /// Flatten directory structure
     FlattenDirectory,
+    /// Open the selected file in an editor or the default application
+    OpenFile(terminal::OpenWith),
+    /// Bookmark the current directory under a letter
+    SetBookmark(char),
+    /// Go to a bookmark (letter), a recent directory (digit) or the previous one (`'`)
+    JumpTo(char),
 }
 
 /// Transform action for UI operations
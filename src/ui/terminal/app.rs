@@ -4,9 +4,21 @@ use std::path::PathBuf;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::Frame;
 
+use crate::clipboard;
+use crate::config;
+use crate::history::{HistoryManager, MappedMove};
+use crate::paths;
+use crate::plan;
+use crate::report::Quiet;
 use crate::transformers::transform;
-use crate::ui::terminal::views::{FileExplorer, FileItem, PreviewView, QueueView};
-use crate::ui::terminal::{AppMode, Event, KeyResult, Tui};
+use crate::ui::terminal::bookmarks::BOOKMARKS_FILE;
+use crate::ui::terminal::views::{
+    ConfirmView, Decision, DirOperation, Field, FileExplorer, FileItem, HistoryAction, HistoryView,
+    PreviewView, QueueView, ReplaceAction, ReplaceView, TreeLine, TreeMark, TreePreviewView,
+};
+use crate::ui::terminal::{
+    AppMode, Bookmarks, Capabilities, Event, KeyResult, OpenWith, StatusReport, Tui, launch,
+};
 use crate::ui::{Theme, TransformAction, UiAction, UserInterface};
 use crate::{sort, unsort};
 
@@ -74,6 +86,30 @@ impl OperationQueue {
         self.operations.clear();
         self.selected_index = 0;
     }
+
+    /// The queue as a plan, every operation a rename
+    pub fn planned_ops(&self) -> Vec<plan::PlannedOp> {
+        self.operations
+            .iter()
+            .map(|operation| plan::PlannedOp {
+                op: plan::OpKind::Move,
+                from: operation.source.clone(),
+                to: operation.destination.clone(),
+            })
+            .collect()
+    }
+
+    /// Drop the operations that were run, keeping the rest queued
+    pub fn remove_executed(&mut self, executed: &[FileOperation]) {
+        self.operations.retain(|operation| {
+            !executed.iter().any(|done| {
+                done.source == operation.source && done.destination == operation.destination
+            })
+        });
+        self.selected_index = self
+            .selected_index
+            .min(self.operations.len().saturating_sub(1));
+    }
 }
 
 /// Represents a file operation in the queue
@@ -109,15 +145,37 @@ pub struct App {
     preview: PreviewView,
     /// Global theme
     theme: Theme,
+    /// Colors and glyphs the terminal can draw
+    capabilities: Capabilities,
     /// Whether the application should exit
     should_exit: bool,
     /// Status message
     status_message: String,
+    /// Undo history shared with the command line
+    history: HistoryManager,
+    /// Queue review shown before executing
+    confirm: Option<ConfirmView>,
+    /// Execute the queue without the review (`smv tui -F`)
+    force: bool,
+    /// Trees before and after `o` or `O`, while waiting for confirmation
+    dir_preview: Option<TreePreviewView>,
+    /// Bookmarked and recent directories, kept in the state directory
+    bookmarks: Bookmarks,
+    /// First file row shown in the explorer
+    explorer_offset: usize,
+    /// History panel, while open
+    history_view: Option<HistoryView>,
+    /// Find/replace dialog, while open (insert mode)
+    replace: Option<ReplaceView>,
+    /// What has been typed after `:`
+    command_input: String,
 }
 
 impl App {
     /// Create a new application
-    pub fn new() -> anyhow::Result<Self> {
+    ///
+    /// With `force` the queue runs as soon as `x` is pressed, without the review.
+    pub fn new(history: HistoryManager, force: bool) -> anyhow::Result<Self> {
         // Initialize terminal UI
         let tui = Tui::new()?;
 
@@ -151,6 +209,12 @@ impl App {
             });
         }
 
+        let config = config::Config::load().map_err(|e| anyhow::anyhow!("{e}"))?;
+        let capabilities = Capabilities::detect(&config.tui);
+
+        let mut bookmarks = Bookmarks::load(&paths::state_dir().join(BOOKMARKS_FILE));
+        bookmarks.visit(&current_dir);
+
         Ok(Self {
             tui,
             mode: AppMode::Normal,
@@ -160,8 +224,18 @@ impl App {
             queue_view: QueueView::new(),
             preview: PreviewView::new(),
             theme: Theme::default(),
+            capabilities,
             should_exit: false,
             status_message: String::from("Press ? for help. j/k to navigate, Ctrl+Q to quit"),
+            history,
+            confirm: None,
+            force,
+            dir_preview: None,
+            bookmarks,
+            explorer_offset: 0,
+            history_view: None,
+            replace: None,
+            command_input: String::new(),
         })
     }
 
@@ -173,14 +247,48 @@ impl App {
                 self.should_exit = true;
                 return Ok(());
             }
-            (KeyCode::Char('?'), KeyModifiers::NONE) => {
+            (KeyCode::Char('?'), KeyModifiers::NONE)
+                if !matches!(
+                    self.mode,
+                    AppMode::Confirm
+                        | AppMode::DirPreview
+                        | AppMode::History
+                        | AppMode::Insert
+                        | AppMode::Command
+                ) =>
+            {
                 // Toggle help mode
                 self.mode = AppMode::Help;
                 self.status_message = String::from("Help mode - press ESC or ? to exit");
                 return Ok(());
             }
+            (KeyCode::Esc, KeyModifiers::NONE) if self.replace.is_some() => {
+                self.replace = None;
+                self.mode = AppMode::Normal;
+                self.status_message = String::from("Replace cancelled");
+                return Ok(());
+            }
+            (KeyCode::Esc, KeyModifiers::NONE) if self.mode == AppMode::History => {
+                self.history_view = None;
+                self.mode = AppMode::Normal;
+                self.status_message = String::from("Normal mode");
+                return Ok(());
+            }
+            (KeyCode::Esc, KeyModifiers::NONE) if self.mode == AppMode::Confirm => {
+                self.confirm = None;
+                self.mode = AppMode::Normal;
+                self.status_message = String::from("Execution cancelled; the queue is unchanged");
+                return Ok(());
+            }
+            (KeyCode::Esc, KeyModifiers::NONE) if self.mode == AppMode::DirPreview => {
+                self.dir_preview = None;
+                self.mode = AppMode::Normal;
+                self.status_message = String::from("Cancelled; nothing was moved");
+                return Ok(());
+            }
             (KeyCode::Esc, KeyModifiers::NONE) => {
                 // Always go back to normal mode on ESC
+                self.explorer.cancel_pending();
                 self.mode = AppMode::Normal;
                 self.status_message = String::from("Normal mode");
                 return Ok(());
@@ -195,7 +303,11 @@ impl App {
             AppMode::Command => self.handle_command_mode_key(key)?,
             AppMode::Insert => self.handle_insert_mode_key(key)?,
             AppMode::Help => self.handle_help_mode_key(key)?,
+            AppMode::Confirm => self.handle_confirm_mode_key(key)?,
+            AppMode::History => self.handle_history_mode_key(key),
+            AppMode::DirPreview => self.handle_dir_preview_key(key)?,
         }
+        self.follow_explorer();
 
         Ok(())
     }
@@ -232,8 +344,10 @@ impl App {
             }
             (KeyCode::Char(':'), KeyModifiers::NONE) => {
                 self.mode = AppMode::Command;
+                self.command_input.clear();
                 self.status_message = String::from(":");
             }
+            (KeyCode::Char('%'), _) => self.open_replace(String::new(), false),
             (KeyCode::Char('x'), KeyModifiers::NONE) => {
                 // Execute queue
                 self.handle_ui_action(UiAction::ExecuteQueue)?;
@@ -243,6 +357,29 @@ impl App {
                 self.queue.clear();
                 self.status_message = String::from("Queue cleared");
             }
+            (KeyCode::Char('u'), KeyModifiers::NONE) => {
+                // Queue the last batch in reverse for selective undo
+                self.queue_last_batch_undo();
+            }
+            (KeyCode::Char('E'), _) => self.export_queue(""),
+            (KeyCode::Char('Y'), _) => {
+                let names = clipboard::new_paths(&self.history, None);
+                self.status_message = if names.is_empty() {
+                    String::from("Nothing renamed yet to copy")
+                } else {
+                    match clipboard::copy_paths(&names) {
+                        Ok(_) => format!("Copied {} path(s) to the clipboard", names.len()),
+                        Err(e) => format!("Copy failed: {e}"),
+                    }
+                };
+            }
+            (KeyCode::Char('H'), _) => {
+                self.history_view = Some(HistoryView::load(&self.history));
+                self.mode = AppMode::History;
+                self.status_message = String::from(
+                    "History: Enter lists files, u queues an undo, r queues a redo, Esc closes",
+                );
+            }
             _ => {}
         }
 
@@ -258,6 +395,11 @@ impl App {
             return Ok(());
         }
 
+        if key.code == KeyCode::Char('%') {
+            self.open_replace(String::new(), false);
+            return Ok(());
+        }
+
         // Handle visual mode selection
         match self.explorer.handle_key(key, &self.mode) {
             KeyResult::Handled(action) => {
@@ -275,23 +417,171 @@ impl App {
     /// Handle keys in command mode
     fn handle_command_mode_key(&mut self, key: KeyEvent) -> anyhow::Result<()> {
         // Command input handling
-        if key.code == KeyCode::Enter {
-            // Process command (to be implemented)
-            self.mode = AppMode::Normal;
-            self.status_message = String::from("Command executed");
+        match key.code {
+            KeyCode::Enter => {
+                self.mode = AppMode::Normal;
+                let input = std::mem::take(&mut self.command_input);
+                self.run_command(input.trim());
+            }
+            KeyCode::Backspace if self.command_input.is_empty() => {
+                self.mode = AppMode::Normal;
+                self.status_message = String::from("Normal mode");
+            }
+            KeyCode::Backspace => {
+                self.command_input.pop();
+                self.status_message = format!(":{}", self.command_input);
+            }
+            KeyCode::Char(c) => {
+                self.command_input.push(c);
+                self.status_message = format!(":{}", self.command_input);
+            }
+            _ => {}
         }
 
         Ok(())
     }
 
+    /// Run a `:` command
+    ///
+    /// `:change [TEXT]` and `:regex [PATTERN]` open the find/replace dialog,
+    /// like the CLI's CHANGE and REGEX commands. `:export [FILE]` saves the
+    /// queue, or copies it without a file.
+    fn run_command(&mut self, input: &str) {
+        let (command, rest) = input.split_once(' ').unwrap_or((input, ""));
+        match command {
+            "change" | "c" => self.open_replace(rest.to_string(), false),
+            "regex" | "r" => self.open_replace(rest.to_string(), true),
+            "export" | "e" => self.export_queue(rest.trim()),
+            "" => self.status_message = String::from("Normal mode"),
+            other => self.status_message = format!("Unknown command: {other}"),
+        }
+    }
+
+    /// Turn the queue into a script running it as `smv --stdin-ops`, or a YAML
+    /// plan when `file` ends in `.yaml`/`.yml`, and save it to `file` or copy
+    /// it to the clipboard when `file` is empty
+    ///
+    /// The queue stays as it is, so it can still be run here.
+    fn export_queue(&mut self, file: &str) {
+        if self.queue.is_empty() {
+            self.status_message = String::from("Queue is empty; nothing to export");
+            return;
+        }
+        let ops = self.queue.planned_ops();
+        let path = PathBuf::from(file);
+        let yaml = path
+            .extension()
+            .is_some_and(|ext| ext == "yaml" || ext == "yml");
+        let text = if yaml {
+            match plan::to_yaml(&ops) {
+                Ok(text) => text,
+                Err(e) => {
+                    self.status_message = format!("Export failed: {e}");
+                    return;
+                }
+            }
+        } else {
+            plan::to_script(&ops)
+        };
+
+        self.status_message = if file.is_empty() {
+            match clipboard::copy(&text) {
+                Ok(_) => format!(
+                    "Copied a script running the {} queued operation(s) to the clipboard",
+                    ops.len()
+                ),
+                Err(e) => format!("Copy failed: {e} (:export FILE saves it instead)"),
+            }
+        } else {
+            let path = self.current_dir.join(path);
+            match std::fs::write(&path, text) {
+                Ok(()) => {
+                    #[cfg(unix)]
+                    if !yaml {
+                        use std::os::unix::fs::PermissionsExt;
+                        let _ =
+                            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755));
+                    }
+                    let run = if yaml {
+                        format!("smv --stdin-ops < {}", path.display())
+                    } else {
+                        path.display().to_string()
+                    };
+                    format!("Exported {} operation(s); run them with: {run}", ops.len())
+                }
+                Err(e) => format!("Export failed: {}: {e}", path.display()),
+            }
+        };
+    }
+
+    /// Open the find/replace dialog on the selected files
+    fn open_replace(&mut self, find: String, regex: bool) {
+        let files: Vec<FileItem> = self
+            .explorer
+            .visual_selection()
+            .into_iter()
+            .filter(|file| !file.is_dir)
+            .cloned()
+            .collect();
+        if files.is_empty() {
+            self.status_message = String::from("No files selected (directories are ignored)");
+            return;
+        }
+        self.status_message = format!(
+            "Replace in {} file(s): Tab switches fields, Ctrl+R toggles regex, Enter queues, Esc cancels",
+            files.len()
+        );
+        self.replace = Some(ReplaceView::new(files, find, regex));
+        self.mode = AppMode::Insert;
+    }
+
     /// Handle keys in insert mode
     fn handle_insert_mode_key(&mut self, key: KeyEvent) -> anyhow::Result<()> {
         // Text editing for rename operations
-        if key.code == KeyCode::Enter {
-            // Finish text input
-            self.mode = AppMode::Normal;
+        let Some(replace) = self.replace.as_mut() else {
+            if key.code == KeyCode::Enter {
+                // Finish text input
+                self.mode = AppMode::Normal;
+            }
+            return Ok(());
+        };
+        match replace.handle_key(key) {
+            ReplaceAction::None => {}
+            ReplaceAction::Cancel => {
+                self.replace = None;
+                self.mode = AppMode::Normal;
+                self.status_message = String::from("Replace cancelled");
+            }
+            ReplaceAction::Queue => {
+                let transform_type = replace.transform_type();
+                let operations: Vec<FileOperation> = replace
+                    .previews()
+                    .into_iter()
+                    .filter(|(file, renamed)| file.name != *renamed)
+                    .map(|(file, renamed)| FileOperation {
+                        source: file.path.clone(),
+                        destination: file.path.with_file_name(renamed),
+                        operation_type: OperationType::Transform(transform_type.clone()),
+                    })
+                    .collect();
+                let unchanged = replace.files.len() - operations.len();
+                self.status_message = format!(
+                    "Added {} rename(s) to queue{}",
+                    operations.len(),
+                    if unchanged > 0 {
+                        format!(" ({unchanged} unchanged)")
+                    } else {
+                        String::new()
+                    }
+                );
+                for operation in operations {
+                    self.queue.add(operation);
+                }
+                self.replace = None;
+                self.explorer.visual_selection_start = None;
+                self.mode = AppMode::Normal;
+            }
         }
-
         Ok(())
     }
 
@@ -311,6 +601,90 @@ impl App {
         Ok(())
     }
 
+    /// Handle keys in the queue review
+    fn handle_confirm_mode_key(&mut self, key: KeyEvent) -> anyhow::Result<()> {
+        let Some(confirm) = self.confirm.as_mut() else {
+            self.mode = AppMode::Normal;
+            return Ok(());
+        };
+        match confirm.handle_key(key) {
+            Decision::Pending => {}
+            Decision::Run => {
+                let operations = confirm.included();
+                self.confirm = None;
+                self.mode = AppMode::Normal;
+                if operations.is_empty() {
+                    self.status_message = String::from("Nothing selected; the queue is unchanged");
+                } else {
+                    self.run_operations(operations);
+                }
+            }
+            Decision::Cancel => {
+                self.confirm = None;
+                self.mode = AppMode::Normal;
+                self.status_message = String::from("Execution cancelled; the queue is unchanged");
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle keys while the trees before and after `o` or `O` are shown
+    fn handle_dir_preview_key(&mut self, key: KeyEvent) -> anyhow::Result<()> {
+        let Some(view) = self.dir_preview.as_mut() else {
+            self.mode = AppMode::Normal;
+            return Ok(());
+        };
+        match view.handle_key(key) {
+            Decision::Pending => {}
+            Decision::Run => {
+                let (operation, root) = (view.operation, view.root.clone());
+                self.dir_preview = None;
+                self.mode = AppMode::Normal;
+                self.run_dir_operation(operation, &root)?;
+            }
+            Decision::Cancel => {
+                self.dir_preview = None;
+                self.mode = AppMode::Normal;
+                self.status_message = String::from("Cancelled; nothing was moved");
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle keys in the history panel
+    fn handle_history_mode_key(&mut self, key: KeyEvent) {
+        let Some(view) = self.history_view.as_mut() else {
+            self.mode = AppMode::Normal;
+            return;
+        };
+        let action = view.handle_key(key);
+        let moves = view.selected().map(|batch| batch.moves.clone());
+        let undo = match action {
+            HistoryAction::None => return,
+            HistoryAction::Close => {
+                self.history_view = None;
+                self.mode = AppMode::Normal;
+                self.status_message = String::from("Normal mode");
+                return;
+            }
+            HistoryAction::Undo => true,
+            HistoryAction::Redo => false,
+        };
+        let Some(moves) = moves.filter(|moves| !moves.is_empty()) else {
+            self.status_message = String::from("This batch has no moved files");
+            return;
+        };
+
+        let (queued, modified) = self.queue_moves(moves, undo);
+        self.history_view = None;
+        self.mode = AppMode::Normal;
+        self.status_message = format!(
+            "Queued {queued} file(s) to {}{}; D drops one, x executes",
+            if undo { "move back" } else { "move again" },
+            modified_note(modified)
+        );
+    }
+
     /// Handle UI action
     fn handle_ui_action(&mut self, action: UiAction) -> anyhow::Result<()> {
         match action {
@@ -382,17 +756,33 @@ impl App {
             UiAction::GroupFiles => {
                 if let Some(dir) = self.explorer.selected().cloned() {
                     if dir.is_dir {
-                        self.group_files_in_directory(&dir.path)?;
+                        self.preview_dir_operation(DirOperation::Group, &dir.path)?;
                     }
                 }
             }
             UiAction::FlattenDirectory => {
                 if let Some(dir) = self.explorer.selected().cloned() {
                     if dir.is_dir {
-                        self.flatten_directory(&dir.path)?;
+                        self.preview_dir_operation(DirOperation::Flatten, &dir.path)?;
                     }
                 }
             }
+            UiAction::OpenFile(with) => {
+                if let Some(file) = self.explorer.selected().cloned() {
+                    self.open_file(&file.path, with)?;
+                }
+            }
+            UiAction::SetBookmark(mark) => {
+                if Bookmarks::is_mark(mark) {
+                    self.bookmarks.set(mark, self.current_dir.clone());
+                    self.status_message =
+                        format!("Bookmarked {} as '{mark}", self.current_dir.display());
+                    self.save_bookmarks();
+                } else {
+                    self.status_message = format!("Bookmarks are named a-z, not '{mark}'");
+                }
+            }
+            UiAction::JumpTo(mark) => self.jump_to(mark),
             UiAction::Continue => {}
         }
 
@@ -447,40 +837,272 @@ impl App {
         Ok(())
     }
 
-    /// Execute all operations in the queue
+    /// Review the queue before running it, or run it at once with `force`
     fn execute_queue(&mut self) -> anyhow::Result<()> {
         if self.queue.is_empty() {
             self.status_message = String::from("Queue is empty");
             return Ok(());
         }
+        if self.force {
+            self.run_operations(self.queue.operations().to_vec());
+            return Ok(());
+        }
 
-        let operations = self.queue.operations().to_vec();
-        let mut success_count = 0;
-        let mut error_count = 0;
+        let confirm = ConfirmView::new(self.queue.operations());
+        self.status_message = format!(
+            "Review {} operation(s), {} with conflicts: Space toggles, y runs the selected, n cancels",
+            confirm.entries.len(),
+            confirm.conflicts()
+        );
+        self.confirm = Some(confirm);
+        self.mode = AppMode::Confirm;
+        Ok(())
+    }
 
-        for operation in operations {
-            match std::fs::rename(&operation.source, &operation.destination) {
-                Ok(_) => {
-                    success_count += 1;
-                }
-                Err(_e) => {
-                    error_count += 1;
-                }
+    /// Run `operations` and take them off the queue; deselected ones stay queued
+    fn run_operations(&mut self, operations: Vec<FileOperation>) {
+        self.queue.remove_executed(&operations);
+        let changes: Vec<_> = operations
+            .into_iter()
+            .map(|operation| (operation.source, operation.destination))
+            .collect();
+        // Conflicts were deselected in the review; what is left was chosen
+        let mut report = StatusReport::default();
+        let moved = plan::execute_renames(&changes, true, &mut report);
+
+        self.status_message = report.status_line();
+        if !self.queue.is_empty() {
+            self.status_message = format!(
+                "{}; {} left in the queue",
+                self.status_message,
+                self.queue.operations().len()
+            );
+        }
+
+        // The whole queue is one undoable batch
+        if !moved.is_empty() {
+            self.history.start_batch();
+            if let Err(e) = self
+                .history
+                .record_mapping(self.current_dir.clone(), &moved)
+            {
+                self.status_message =
+                    format!("{} (not recorded for undo: {e})", self.status_message);
             }
         }
 
+        // Reload the file explorer to show changes
+        let _ = self.explorer.reload_files();
+    }
+
+    /// Replace the queue with the last batch reversed
+    ///
+    /// Dropping entries with D before executing reverts only part of the batch,
+    /// and executing records the partial undo as its own batch.
+    fn queue_last_batch_undo(&mut self) {
+        let batch = match self.history.last_batch() {
+            Ok(batch) if !batch.is_empty() => batch,
+            Ok(_) => {
+                self.status_message = String::from("The last operation has no moved files");
+                return;
+            }
+            Err(e) => {
+                self.status_message = format!("Nothing to undo: {e}");
+                return;
+            }
+        };
+
+        let (queued, modified) = self.queue_moves(batch, true);
+        self.status_message = format!(
+            "Queued {queued} file(s) from the last batch to move back{}; D drops one, x executes",
+            modified_note(modified)
+        );
+    }
+
+    /// Replace the queue with `moves` undone (newest first) or done again
+    ///
+    /// Files that moved on since, or whose contents changed after the move, are
+    /// left out. Returns how many were queued and how many were modified.
+    fn queue_moves(&mut self, moves: Vec<MappedMove>, undo: bool) -> (usize, usize) {
         self.queue.clear();
-        self.status_message = format!("Executed: {success_count} success, {error_count} errors");
+        let mut modified = 0;
+        let moves: Vec<MappedMove> = if undo {
+            moves.into_iter().rev().collect()
+        } else {
+            moves
+        };
+        for entry in moves {
+            let (from, to) = if undo {
+                (entry.destination, entry.source)
+            } else {
+                (entry.source, entry.destination)
+            };
+            if !from.exists() || to.exists() {
+                continue;
+            }
+            if entry
+                .fingerprint
+                .as_ref()
+                .is_some_and(|f| f.difference(&from).is_some())
+            {
+                modified += 1;
+                continue;
+            }
+            self.queue.add(FileOperation {
+                source: from,
+                destination: to,
+                operation_type: OperationType::Move,
+            });
+        }
+        (self.queue.operations().len(), modified)
+    }
 
-        // Reload the file explorer to show changes
+    /// Open `path` with the editor or the default application
+    ///
+    /// The TUI steps aside while the program runs and comes back when it exits,
+    /// whether or not it succeeded.
+    fn open_file(&mut self, path: &std::path::Path, with: OpenWith) -> anyhow::Result<()> {
+        self.tui.suspend()?;
+        let result = launch::open(path, with);
+        self.tui.resume()?;
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        self.status_message = match result {
+            Ok(status) if status.success() => format!("Opened {name}"),
+            Ok(status) => format!("Opening {name} failed ({status})"),
+            Err(e) => format!("Could not open {name}: {e}"),
+        };
+        // The program may have renamed or removed things
         let _ = self.explorer.reload_files();
+        Ok(())
+    }
+
+    /// Keep the header and the recent list in step with the explorer's directory
+    fn follow_explorer(&mut self) {
+        let dir = self.explorer.current_dir();
+        if dir != self.current_dir {
+            self.current_dir = dir.to_path_buf();
+            self.bookmarks.visit(dir);
+        }
+    }
+
+    /// Recent directories other than the current one, as numbered by `'1`-`'9`
+    fn recent_elsewhere(&self) -> Vec<PathBuf> {
+        self.bookmarks
+            .recent()
+            .iter()
+            .filter(|dir| **dir != self.current_dir)
+            .cloned()
+            .collect()
+    }
+
+    /// Go to the bookmark `mark`, the recent directory numbered `mark`, or back with `'`
+    fn jump_to(&mut self, mark: char) {
+        let target = match mark {
+            '\'' => self.recent_elsewhere().first().cloned(),
+            '1'..='9' => self
+                .recent_elsewhere()
+                .get(mark as usize - '1' as usize)
+                .cloned(),
+            _ => self.bookmarks.get(mark).cloned(),
+        };
+        let Some(dir) = target else {
+            self.status_message = format!("No bookmark or recent directory '{mark}");
+            return;
+        };
+        match self.explorer.change_directory(dir.clone()) {
+            Ok(()) => {
+                self.follow_explorer();
+                self.status_message = format!("Jumped to {}", dir.display());
+            }
+            Err(e) => self.status_message = format!("Cannot open {}: {e}", dir.display()),
+        }
+    }
+
+    /// Lines of the list shown after `'`: bookmarks, then recent directories
+    fn jump_list(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .bookmarks
+            .marks()
+            .map(|(mark, dir)| format!("{mark}  {}", dir.display()))
+            .collect();
+        for (index, dir) in self.recent_elsewhere().iter().enumerate() {
+            lines.push(format!("{}  {}", index + 1, dir.display()));
+        }
+        if lines.is_empty() {
+            lines.push(String::from("No bookmarks yet: m{a-z} sets one"));
+        }
+        lines
+    }
+
+    fn save_bookmarks(&mut self) {
+        if let Err(e) = self
+            .bookmarks
+            .save(&paths::state_dir().join(BOOKMARKS_FILE))
+        {
+            self.status_message = format!("{} (bookmarks not saved: {e})", self.status_message);
+        }
+    }
+
+    /// Show the selected directory before and after `operation`, or run it at
+    /// once with `force`
+    fn preview_dir_operation(
+        &mut self,
+        operation: DirOperation,
+        dir_path: &PathBuf,
+    ) -> anyhow::Result<()> {
+        if self.force {
+            return self.run_dir_operation(operation, dir_path);
+        }
+        let dir = dir_path.to_string_lossy();
+        let moves = match operation {
+            DirOperation::Group => sort::group_by_basename(&dir, true, &mut Quiet),
+            DirOperation::Flatten => unsort::flatten_directory(&dir, true, &mut Quiet),
+        };
+        let moves = match moves {
+            Ok(moves) => moves,
+            Err(e) => {
+                self.status_message = format!("Error previewing {}: {e}", dir_path.display());
+                return Ok(());
+            }
+        };
+        if moves.is_empty() {
+            self.status_message = format!(
+                "{}: nothing would move in {}",
+                operation.as_str(),
+                dir_path.display()
+            );
+            return Ok(());
+        }
 
+        let view = TreePreviewView::new(operation, dir_path, &moves);
+        self.status_message = format!(
+            "{} in {}: {} file(s) move, {} folder(s) created, {} removed; y runs, n cancels",
+            operation.as_str(),
+            dir_path.display(),
+            view.moves,
+            view.created,
+            view.removed
+        );
+        self.dir_preview = Some(view);
+        self.mode = AppMode::DirPreview;
         Ok(())
     }
 
+    fn run_dir_operation(
+        &mut self,
+        operation: DirOperation,
+        dir_path: &PathBuf,
+    ) -> anyhow::Result<()> {
+        match operation {
+            DirOperation::Group => self.group_files_in_directory(dir_path),
+            DirOperation::Flatten => self.flatten_directory(dir_path),
+        }
+    }
+
     /// Group files by basename in the selected directory
     fn group_files_in_directory(&mut self, dir_path: &PathBuf) -> anyhow::Result<()> {
-        match sort::group_by_basename(&dir_path.to_string_lossy(), false) {
+        match sort::group_by_basename(&dir_path.to_string_lossy(), false, &mut Quiet) {
             Ok(_) => {
                 self.status_message = format!("Grouped files in {}", dir_path.display());
                 // Reload the file explorer to show changes
@@ -495,10 +1117,10 @@ impl App {
 
     /// Flatten the selected directory structure
     fn flatten_directory(&mut self, dir_path: &PathBuf) -> anyhow::Result<()> {
-        match unsort::flatten_directory(&dir_path.to_string_lossy(), false) {
+        match unsort::flatten_directory(&dir_path.to_string_lossy(), false, &mut Quiet) {
             Ok(_) => {
                 // Also remove empty directories
-                let _ = unsort::remove_empty_dirs(&dir_path.to_string_lossy(), false);
+                let _ = unsort::remove_empty_dirs(&dir_path.to_string_lossy(), false, &mut Quiet);
                 self.status_message = format!("Flattened directory {}", dir_path.display());
                 // Reload the file explorer to show changes
                 let _ = self.explorer.reload_files();
@@ -523,19 +1145,19 @@ impl App {
         } else {
             None
         };
-        let files_data: Vec<(String, bool, usize)> = self
-            .explorer
-            .files
-            .iter()
-            .enumerate()
-            .map(|(idx, file)| (file.name.clone(), file.is_dir, idx))
-            .collect();
+        let jump_list = (self.explorer.pending_key() == Some('\'')).then(|| self.jump_list());
+        let files_title = if self.explorer.is_loading() {
+            format!("Files (loading... {})", self.explorer.files.len())
+        } else {
+            String::from("Files")
+        };
 
+        let caps = self.capabilities;
         self.tui.draw(|frame| {
             use ratatui::{
                 layout::{Constraint, Direction, Layout},
                 style::{Color, Modifier, Style},
-                widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+                widgets::{List, ListItem, Paragraph, Wrap},
             };
 
             let size = frame.size();
@@ -552,8 +1174,8 @@ impl App {
 
             // Header
             let header = Paragraph::new(format!("SMV Terminal UI - {current_dir}"))
-                .block(Block::default().borders(Borders::ALL).title("Smart Move"))
-                .style(Style::default().fg(Color::Cyan));
+                .block(caps.block().title("Smart Move"))
+                .style(caps.paint(Style::default().fg(Color::Cyan)));
             frame.render_widget(header, chunks[0]);
 
             // Main content area: horizontal split
@@ -565,37 +1187,52 @@ impl App {
                 ])
                 .split(chunks[1]);
 
+            // Only the rows that fit are built, so huge directories draw as fast as small ones
+            let rows = main_chunks[0].height.saturating_sub(2) as usize;
+            let selected = selected_index.unwrap_or(0);
+            if selected < self.explorer_offset {
+                self.explorer_offset = selected;
+            } else if rows > 0 && selected >= self.explorer_offset + rows {
+                self.explorer_offset = selected + 1 - rows;
+            }
+            let offset = self.explorer_offset;
+            let mut window = ratatui::widgets::ListState::default();
+            window.select(selected_index.and_then(|index| index.checked_sub(offset)));
+
             // File explorer with real data and visual selection support
-            let explorer_content: Vec<ListItem> = files_data.iter()
-                .map(|(name, is_dir, idx)| {
-                    let icon = if *is_dir { "📁" } else { "📄" };
-                    let mut line = format!("{icon} {name}");
+            let explorer_content: Vec<ListItem> = self.explorer.files.iter()
+                .enumerate()
+                .skip(offset)
+                .take(rows)
+                .map(|(idx, file)| {
+                    let icon = if file.is_dir { "📁" } else { "📄" };
+                    let mut line = format!("{icon} {}", file.name);
 
                     // Add visual selection indicator
                     if let (Some(start), Some(current)) = (visual_start, selected_index) {
                         let (min, max) = if start <= current { (start, current) } else { (current, start) };
-                        if *idx >= min && *idx <= max {
+                        if idx >= min && idx <= max {
                             line = format!("► {line}");  // Visual selection marker
                         }
                     }
 
-                    ListItem::new(line)
+                    ListItem::new(caps.text(&line).into_owned())
                 })
                 .collect();
 
             let explorer = List::new(explorer_content)
-                .block(Block::default().borders(Borders::ALL).title("Files"))
-                .style(Style::default().fg(Color::White))
-                .highlight_style(Style::default()
+                .block(caps.block().title(files_title))
+                .style(caps.paint(Style::default().fg(Color::White)))
+                .highlight_style(caps.highlight(Style::default()
                     .fg(Color::Black)
                     .bg(Color::Cyan)
-                    .add_modifier(Modifier::BOLD));
+                    .add_modifier(Modifier::BOLD)));
 
-            frame.render_stateful_widget(explorer, main_chunks[0], &mut self.explorer.state);
+            frame.render_stateful_widget(explorer, main_chunks[0], &mut window);
 
             // Queue view with detailed operations
             let queue_content = if queue_len > 0 {
-                let mut items = vec![ListItem::new(format!("📝 {queue_len} operations pending:"))];
+                let mut items = vec![ListItem::new(caps.text(&format!("📝 {queue_len} operations pending:")).into_owned())];
 
                 // Show up to 8 operations in detail
                 for op in self.queue.operations().iter().take(8) {
@@ -623,7 +1260,7 @@ impl App {
                         format!("{op_icon} {source_name} → {dest_name}")
                     };
 
-                    items.push(ListItem::new(op_text));
+                    items.push(ListItem::new(caps.text(&op_text).into_owned()));
                 }
 
                 if queue_len > 8 {
@@ -640,31 +1277,36 @@ impl App {
                     ListItem::new("No operations queued"),
                     ListItem::new(""),
                     ListItem::new("Select files and press:"),
-                    ListItem::new("• s = snake_case"),
-                    ListItem::new("• c = clean spaces"),
-                    ListItem::new("• t = Title Case"),
-                    ListItem::new("• K = kebab-case"),
-                    ListItem::new("• o = group files"),
-                    ListItem::new("• O = flatten dirs"),
+                    ListItem::new(caps.text("• s = snake_case")),
+                    ListItem::new(caps.text("• c = clean spaces")),
+                    ListItem::new(caps.text("• t = Title Case")),
+                    ListItem::new(caps.text("• K = kebab-case")),
+                    ListItem::new(caps.text("• o = group files")),
+                    ListItem::new(caps.text("• O = flatten dirs")),
                 ]
             };
 
             let queue = List::new(queue_content)
-                .block(Block::default().borders(Borders::ALL).title("Operations Queue"))
-                .style(Style::default().fg(Color::White));
+                .block(caps.block().title("Operations Queue"))
+                .style(caps.paint(Style::default().fg(Color::White)));
             frame.render_widget(queue, main_chunks[1]);
 
             // Status bar with navigation and action help
             let nav_help = match self.mode {
-                AppMode::Normal => "j/k: Navigate | Enter: Dir/Add to Queue | h: Back | l: Enter Dir | Actions: s=Snake c=Clean t=Title K=Kebab | v: Visual | x: Execute | q: Clear Queue | ?: Help | Ctrl+Q: Quit",
+                AppMode::Normal => "j/k: Navigate | Enter: Dir/Add to Queue | h: Back | l: Enter Dir | e/gx: Open | Actions: s=Snake c=Clean t=Title K=Kebab | v: Visual | x: Execute | q: Clear Queue | ?: Help | Ctrl+Q: Quit",
                 AppMode::Visual => "j/k: Extend selection | Enter: Apply to Selection | Esc: Normal mode | Available actions: s c t K o O | ?: Help",
                 AppMode::Help => "Press ESC, ?, or q to exit help mode",
+                AppMode::Insert if self.replace.is_some() => "Tab: Switch field | Ctrl+R: Regex | Enter: Queue | Esc: Cancel",
+                AppMode::Command => ":change TEXT | :regex PATTERN | Enter: Run | Esc: Cancel",
+                AppMode::History => "j/k: Move | Enter: Files | u: Queue undo | r: Queue redo | Esc: Close",
+                AppMode::Confirm => "j/k: Move | Space: Toggle | a: Toggle all | y/Enter: Run selected | n/Esc: Cancel",
+                AppMode::DirPreview => "j/k: Scroll | PgUp/PgDn: Page | y/Enter: Run | n/Esc: Cancel",
                 _ => "j/k: Navigate | Enter: select | h: back | l: forward | ?: Help",
             };
             let status_text = format!("Mode: {mode} | {status_message} | {nav_help}");
             let status = Paragraph::new(status_text)
-                .block(Block::default().borders(Borders::ALL))
-                .style(Style::default().fg(Color::Yellow))
+                .block(caps.block())
+                .style(caps.paint(Style::default().fg(Color::Yellow)))
                 .wrap(Wrap { trim: true });
             frame.render_widget(status, chunks[2]);
 
@@ -697,6 +1339,14 @@ impl App {
   Enter   - Enter directory OR add file to queue
   gg      - Go to first item
   G       - Go to last item
+  e       - Open file in $VISUAL/$EDITOR
+  gx      - Open file with the default application
+
+🔖 BOOKMARKS:
+  m{a-z}  - Bookmark the current directory
+  '{a-z}  - Jump to a bookmark
+  '{1-9}  - Jump to a recent directory
+  ''      - Jump back to the previous directory
 
 🎯 FILE TRANSFORMATION ACTIONS:
   s       - Convert to snake_case (my_file.txt)
@@ -707,15 +1357,21 @@ impl App {
 📂 DIRECTORY OPERATIONS:
   o       - Group files by basename into directories
   O       - Flatten directory (move all files to root)
+            Both show the tree before and after; y runs, n cancels
 
 👁️ MODES:
   v       - Enter Visual mode (select multiple files)
-  :       - Enter Command mode
+  :       - Enter Command mode (:change TEXT, :regex PATTERN, :export FILE)
+  %       - Find/replace in the selected files with a live preview
   Esc     - Return to Normal mode
 
 ⚡ QUEUE OPERATIONS:
-  x       - Execute all queued operations
+  x       - Review the queue (Space toggles, y runs, n cancels)
   q       - Clear the operation queue
+  u       - Queue the last batch for undo (D drops files to keep)
+  H       - History: pick any batch to queue its undo (u) or redo (r)
+  Y       - Copy the new paths of the last executed batch to the clipboard
+  E       - Copy the queue as a script (:export FILE saves it, FILE.yaml as a plan)
 
 🔍 OTHER:
   f       - Fuzzy search (if available)
@@ -728,17 +1384,236 @@ impl App {
 Press ESC, ?, or q to close this help.
 ";
 
-                let help_popup = Paragraph::new(help_text)
-                    .block(Block::default()
-                        .borders(Borders::ALL)
+                let help_popup = Paragraph::new(caps.text(help_text))
+                    .block(caps.block()
                         .title(" Help - SMV Actions & Navigation ")
                         .title_alignment(Alignment::Center))
-                    .style(Style::default().fg(Color::White).bg(Color::DarkGray))
+                    .style(caps.paint(Style::default().fg(Color::White).bg(Color::DarkGray)))
                     .alignment(Alignment::Left)
                     .wrap(Wrap { trim: true });
 
                 frame.render_widget(help_popup, help_area);
             }
+
+            // Render the jump list while a bookmark key is awaited
+            if let Some(lines) = jump_list {
+                use ratatui::widgets::Clear;
+
+                let height = (lines.len() as u16 + 2).min(size.height);
+                let area = ratatui::layout::Rect {
+                    x: size.width / 4,
+                    y: size.height.saturating_sub(height + 3),
+                    width: size.width / 2,
+                    height,
+                };
+                frame.render_widget(Clear, area);
+                let list = Paragraph::new(lines.join("\n"))
+                    .block(caps.block().title(" Jump to "))
+                    .style(caps.paint(Style::default().fg(Color::White).bg(Color::DarkGray)));
+                frame.render_widget(list, area);
+            }
+
+            // Render the find/replace dialog with its live preview
+            if let Some(replace) = self.replace.as_ref() {
+                use ratatui::{layout::Alignment, text::Line, widgets::Clear};
+
+                let area = ratatui::layout::Rect {
+                    x: size.width / 8,
+                    y: size.height / 8,
+                    width: size.width * 3 / 4,
+                    height: size.height * 3 / 4,
+                };
+                frame.render_widget(Clear, area);
+                let field_style = |field: Field| caps.paint(if replace.field == field {
+                    Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(Color::White)
+                });
+                let mut lines = vec![
+                    Line::styled(format!("Find:    {}", replace.find), field_style(Field::Find)),
+                    Line::styled(format!("Replace: {}", replace.replace), field_style(Field::Replace)),
+                    match replace.error() {
+                        Some(error) => Line::styled(error, caps.paint(Style::default().fg(Color::Red))),
+                        None => Line::from(""),
+                    },
+                ];
+                for (file, renamed) in replace.previews().into_iter().take(area.height.saturating_sub(5) as usize) {
+                    lines.push(if file.name == renamed {
+                        Line::styled(caps.text(&format!("  {}", file.name)).into_owned(), caps.paint(Style::default().fg(Color::DarkGray)))
+                    } else {
+                        Line::styled(caps.text(&format!("  {} → {renamed}", file.name)).into_owned(), caps.paint(Style::default().fg(Color::Green)))
+                    });
+                }
+                let title = format!(
+                    " {} in {} file(s) ",
+                    if replace.regex { "Regex replace" } else { "Replace" },
+                    replace.files.len()
+                );
+                let dialog = Paragraph::new(lines)
+                    .block(caps.block()
+                        .title(title)
+                        .title_alignment(Alignment::Center))
+                    .style(caps.paint(Style::default().bg(Color::DarkGray)));
+                frame.render_widget(dialog, area);
+            }
+
+            // Render the history panel
+            if let Some(view) = self.history_view.as_mut() {
+                use ratatui::{layout::Alignment, widgets::Clear};
+
+                let area = ratatui::layout::Rect {
+                    x: size.width / 10,
+                    y: size.height / 10,
+                    width: size.width * 4 / 5,
+                    height: size.height * 4 / 5,
+                };
+                frame.render_widget(Clear, area);
+                let halves = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints(if view.expanded {
+                        [Constraint::Percentage(40), Constraint::Percentage(60)]
+                    } else {
+                        [Constraint::Percentage(100), Constraint::Percentage(0)]
+                    })
+                    .split(area);
+
+                let items: Vec<ListItem> = if view.batches.is_empty() {
+                    vec![ListItem::new("Nothing recorded yet")]
+                } else {
+                    view.batches.iter().map(|batch| ListItem::new(batch.summary())).collect()
+                };
+                let batches = List::new(items)
+                    .block(caps.block()
+                        .title(" History (newest first) ")
+                        .title_alignment(Alignment::Center))
+                    .style(caps.paint(Style::default().fg(Color::White).bg(Color::DarkGray)))
+                    .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                frame.render_stateful_widget(batches, halves[0], &mut view.state);
+
+                if view.expanded {
+                    let files: Vec<ListItem> = view
+                        .selected()
+                        .map(|batch| {
+                            batch.moves.iter().map(|entry| {
+                                ListItem::new(caps.text(&format!(
+                                    "{} → {}",
+                                    entry.source.display(),
+                                    entry.destination.display()
+                                )).into_owned())
+                            }).collect()
+                        })
+                        .unwrap_or_default();
+                    let files = List::new(files)
+                        .block(caps.block().title(" Files "))
+                        .style(caps.paint(Style::default().fg(Color::White).bg(Color::DarkGray)));
+                    frame.render_widget(files, halves[1]);
+                }
+            }
+
+            // Render the queue review before executing
+            if let Some(confirm) = self.confirm.as_mut() {
+                use ratatui::{layout::Alignment, text::Line, widgets::Clear};
+
+                let area = ratatui::layout::Rect {
+                    x: size.width / 8,
+                    y: size.height / 8,
+                    width: size.width * 3 / 4,
+                    height: size.height * 3 / 4,
+                };
+                frame.render_widget(Clear, area);
+
+                let items: Vec<ListItem> = confirm
+                    .entries
+                    .iter()
+                    .map(|entry| {
+                        let mark = if entry.included { "[x]" } else { "[ ]" };
+                        let mut lines = vec![
+                            Line::from(format!("{mark} - {}", entry.operation.source.display())),
+                            Line::from(format!("    + {}", entry.operation.destination.display())),
+                        ];
+                        let style = caps.paint(match entry.conflict {
+                            Some(conflict) => {
+                                lines.push(Line::from(format!("    ! {conflict}")));
+                                Style::default().fg(Color::Red)
+                            }
+                            None if entry.included => Style::default().fg(Color::Green),
+                            None => Style::default().fg(Color::DarkGray),
+                        });
+                        ListItem::new(lines).style(style)
+                    })
+                    .collect();
+                let selected = confirm.entries.iter().filter(|entry| entry.included).count();
+                let title = format!(
+                    " Execute {selected} of {} operation(s)? {} conflict(s) ",
+                    confirm.entries.len(),
+                    confirm.conflicts()
+                );
+
+                let review = List::new(items)
+                    .block(caps.block()
+                        .title(title)
+                        .title_alignment(Alignment::Center))
+                    .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                frame.render_stateful_widget(review, area, &mut confirm.state);
+            }
+
+            // Render the trees before and after a directory operation
+            if let Some(view) = self.dir_preview.as_ref() {
+                use ratatui::{layout::Alignment, widgets::Clear};
+
+                let area = ratatui::layout::Rect {
+                    x: size.width / 10,
+                    y: size.height / 10,
+                    width: size.width * 4 / 5,
+                    height: size.height * 4 / 5,
+                };
+                frame.render_widget(Clear, area);
+                let outer = caps.block()
+                    .title(format!(
+                        " {} in {}: {} move, {} new folder(s), {} removed - y runs, n cancels ",
+                        view.operation.as_str(),
+                        view.root.display(),
+                        view.moves,
+                        view.created,
+                        view.removed
+                    ))
+                    .title_alignment(Alignment::Center);
+                let inner = outer.inner(area);
+                frame.render_widget(outer, area);
+                let halves = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .split(inner);
+
+                // `-` leaves or goes away, `+` arrives or is made, so the change
+                // reads without colors too
+                let tree = |lines: &[TreeLine]| -> Vec<ListItem> {
+                    lines
+                        .iter()
+                        .skip(view.offset)
+                        .map(|line| {
+                            let (sign, color) = match line.mark {
+                                TreeMark::Unchanged => (' ', Color::White),
+                                TreeMark::Leaves => ('-', Color::Yellow),
+                                TreeMark::Removed => ('-', Color::Red),
+                                TreeMark::Arrives => ('+', Color::Yellow),
+                                TreeMark::Created => ('+', Color::Green),
+                            };
+                            let text = format!("{sign} {}{}", "  ".repeat(line.depth), line.name);
+                            ListItem::new(caps.text(&text).into_owned())
+                                .style(caps.paint(Style::default().fg(color)))
+                        })
+                        .collect()
+                };
+                let before = List::new(tree(&view.before))
+                    .block(caps.block().title(" Before "))
+                    .style(caps.paint(Style::default().bg(Color::DarkGray)));
+                let after = List::new(tree(&view.after))
+                    .block(caps.block().title(" After "))
+                    .style(caps.paint(Style::default().bg(Color::DarkGray)));
+                frame.render_widget(before, halves[0]);
+                frame.render_widget(after, halves[1]);
+            }
         })?;
         Ok(())
     }
@@ -763,6 +1638,15 @@ Press ESC, ?, or q to close this help.
     }
 }
 
+/// " (N modified since, left out)" when files were skipped for having changed
+fn modified_note(modified: usize) -> String {
+    if modified > 0 {
+        format!(" ({modified} modified since, left out)")
+    } else {
+        String::new()
+    }
+}
+
 impl UserInterface for App {
     fn run(&mut self) -> Result<(), Box<dyn Error>> {
         // Force initial render
@@ -789,11 +1673,15 @@ impl UserInterface for App {
                 }
             }
 
+            // Take what the background scan has read since the last frame
+            self.explorer.poll_loading();
+
             // Draw UI after handling events
             self.render().map_err(|e| format!("Render failed: {e}"))?;
         }
 
         // Clean up
+        self.save_bookmarks();
         self.tui.exit()?;
 
         Ok(())
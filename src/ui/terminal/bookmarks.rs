@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::journal::{self, JournalKey};
+
+/// Recent directories kept across sessions
+pub const MAX_RECENT: usize = 9;
+
+/// File in the state directory holding bookmarks and recent directories
+pub const BOOKMARKS_FILE: &str = "tui_bookmarks.json";
+
+/// Directory bookmarks (`m{a-z}` sets, `'{a-z}` jumps) and recently visited directories
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bookmarks {
+    #[serde(default)]
+    marks: BTreeMap<char, PathBuf>,
+    /// Most recent first, without duplicates
+    #[serde(default)]
+    recent: Vec<PathBuf>,
+}
+
+impl Bookmarks {
+    /// Read bookmarks from `path`; a missing or unreadable file gives none
+    pub fn load(path: &Path) -> Self {
+        JournalKey::from_env()
+            .and_then(|key| journal::read_state_file(key.as_ref(), path))
+            .ok()
+            .flatten()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default()
+    }
+
+    /// Write bookmarks to `path`, encrypted when `SMV_HISTORY_KEY` is set
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_vec_pretty(self)?;
+        journal::write_state_file(JournalKey::from_env()?.as_ref(), path, &json)?;
+        Ok(())
+    }
+
+    /// Whether `mark` can name a bookmark
+    pub fn is_mark(mark: char) -> bool {
+        mark.is_ascii_lowercase()
+    }
+
+    pub fn set(&mut self, mark: char, dir: PathBuf) {
+        self.marks.insert(mark, dir);
+    }
+
+    pub fn get(&self, mark: char) -> Option<&PathBuf> {
+        self.marks.get(&mark)
+    }
+
+    pub fn marks(&self) -> impl Iterator<Item = (char, &PathBuf)> {
+        self.marks.iter().map(|(mark, dir)| (*mark, dir))
+    }
+
+    /// Put `dir` at the front of the recent list
+    pub fn visit(&mut self, dir: &Path) {
+        self.recent.retain(|recent| recent != dir);
+        self.recent.insert(0, dir.to_path_buf());
+        self.recent.truncate(MAX_RECENT);
+    }
+
+    pub fn recent(&self) -> &[PathBuf] {
+        &self.recent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bookmarks_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("state").join(BOOKMARKS_FILE);
+        assert_eq!(Bookmarks::load(&file), Bookmarks::default());
+
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.set('d', PathBuf::from("/home/me/Downloads"));
+        for n in 0..12 {
+            bookmarks.visit(Path::new(&format!("/dir{n}")));
+        }
+        bookmarks.visit(Path::new("/dir5"));
+        assert_eq!(bookmarks.recent().len(), MAX_RECENT);
+        assert_eq!(bookmarks.recent()[0], PathBuf::from("/dir5"));
+        assert_eq!(bookmarks.recent()[1], PathBuf::from("/dir11"));
+
+        bookmarks.save(&file).unwrap();
+        let loaded = Bookmarks::load(&file);
+        assert_eq!(loaded, bookmarks);
+        assert_eq!(loaded.get('d'), Some(&PathBuf::from("/home/me/Downloads")));
+        assert!(Bookmarks::is_mark('a') && !Bookmarks::is_mark('A'));
+    }
+}
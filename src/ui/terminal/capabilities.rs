@@ -0,0 +1,265 @@
+//! What the terminal can draw, so the TUI falls back instead of drawing garbage
+//!
+//! The Linux console, `TERM=vt100` and tmux or screen started with a plain
+//! `TERM` show box-drawing characters, arrows and emoji as stray bytes, and
+//! some have no colors at all. Capabilities are read from the environment at
+//! startup: `NO_COLOR`, `COLORTERM` and `TERM` for colors, the locale and
+//! `TERM` for Unicode. The `[tui]` table of the config overrides either:
+//!
+//! ```toml
+//! [tui]
+//! colors = "16"     # none, 16, 256 or truecolor
+//! unicode = false   # ASCII borders and markers
+//! ```
+
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::symbols::border;
+use ratatui::widgets::{Block, Borders};
+use serde::Deserialize;
+use std::borrow::Cow;
+
+/// Terminals known to lack Unicode glyphs even under a UTF-8 locale
+const ASCII_TERMS: &[&str] = &["linux", "vt100", "vt102", "vt220", "ansi", "cons25", "dumb"];
+
+/// Borders drawn with `+`, `-` and `|`
+const ASCII_BORDER: border::Set = border::Set {
+    top_left: "+",
+    top_right: "+",
+    bottom_left: "+",
+    bottom_right: "+",
+    vertical_left: "|",
+    vertical_right: "|",
+    horizontal_top: "-",
+    horizontal_bottom: "-",
+};
+
+/// Symbols the TUI draws, with what stands in for them on ASCII terminals
+const ASCII_SYMBOLS: &[(char, &str)] = &[
+    ('→', "->"),
+    ('←', "<-"),
+    ('↑', "^"),
+    ('↓', "v"),
+    ('►', ">"),
+    ('•', "*"),
+    ('📁', "[D]"),
+    ('📄', "[F]"),
+];
+
+/// How many colors the terminal shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+pub enum ColorDepth {
+    #[serde(rename = "none")]
+    None,
+    /// The 16 named colors
+    #[serde(rename = "16")]
+    Basic,
+    #[serde(rename = "256")]
+    Indexed,
+    #[serde(rename = "truecolor")]
+    TrueColor,
+}
+
+/// `[tui]` in the config: overrides for what is detected
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TuiConfig {
+    #[serde(default)]
+    pub colors: Option<ColorDepth>,
+    #[serde(default)]
+    pub unicode: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub colors: ColorDepth,
+    pub unicode: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            colors: ColorDepth::Basic,
+            unicode: true,
+        }
+    }
+}
+
+impl Capabilities {
+    /// What this process's terminal can draw, with the config's overrides
+    pub fn detect(config: &TuiConfig) -> Self {
+        Self::from_env(|name| std::env::var(name).ok()).with_config(config)
+    }
+
+    /// Capabilities told by the environment variables `var` returns
+    pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        let term = var("TERM").unwrap_or_default().to_lowercase();
+        let colors = if var("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            ColorDepth::None
+        } else if var("COLORTERM").is_some_and(|value| value == "truecolor" || value == "24bit") {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Indexed
+        } else if term.is_empty() || ["dumb", "vt100", "vt102", "vt220"].contains(&term.as_str()) {
+            ColorDepth::None
+        } else {
+            ColorDepth::Basic
+        };
+        // The first locale variable that is set decides, as for every other program
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .into_iter()
+            .find_map(|name| var(name).filter(|value| !value.is_empty()))
+            .unwrap_or_default()
+            .to_lowercase();
+        let unicode = (locale.contains("utf-8") || locale.contains("utf8"))
+            && !ASCII_TERMS.contains(&term.as_str());
+        Self { colors, unicode }
+    }
+
+    pub fn with_config(mut self, config: &TuiConfig) -> Self {
+        if let Some(colors) = config.colors {
+            self.colors = colors;
+        }
+        if let Some(unicode) = config.unicode {
+            self.unicode = unicode;
+        }
+        self
+    }
+
+    /// A block with borders all around, in lines the terminal can draw
+    pub fn block(&self) -> Block<'static> {
+        let block = Block::default().borders(Borders::ALL);
+        if self.unicode {
+            block
+        } else {
+            block.border_set(ASCII_BORDER)
+        }
+    }
+
+    /// `style` with the colors the terminal lacks taken out
+    pub fn paint(&self, style: Style) -> Style {
+        Style {
+            fg: style.fg.map(|color| self.color(color)),
+            bg: style.bg.map(|color| self.color(color)),
+            ..style
+        }
+    }
+
+    /// `style` for the selected row; without colors it is shown in reverse video
+    pub fn highlight(&self, style: Style) -> Style {
+        if self.colors == ColorDepth::None {
+            self.paint(style).add_modifier(Modifier::REVERSED)
+        } else {
+            self.paint(style)
+        }
+    }
+
+    fn color(&self, color: Color) -> Color {
+        let depth = match color {
+            Color::Reset => return color,
+            Color::Rgb(..) => ColorDepth::TrueColor,
+            Color::Indexed(_) => ColorDepth::Indexed,
+            _ => ColorDepth::Basic,
+        };
+        if depth <= self.colors {
+            color
+        } else {
+            Color::Reset
+        }
+    }
+
+    /// `text` as the terminal can show it: on ASCII terminals arrows and markers
+    /// become ASCII, and other symbols and emoji are left out
+    pub fn text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.unicode || text.is_ascii() {
+            return Cow::Borrowed(text);
+        }
+        let mut plain = String::with_capacity(text.len());
+        for c in text.chars() {
+            match ASCII_SYMBOLS.iter().find(|(symbol, _)| *symbol == c) {
+                Some((_, ascii)) => plain.push_str(ascii),
+                // Letters of names stay; the terminal shows what it can of them
+                None if c.is_ascii() || c.is_alphanumeric() => plain.push(c),
+                None => {}
+            }
+        }
+        Cow::Owned(plain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn test_detects_what_the_terminal_draws() {
+        let console = Capabilities::from_env(env(&[("TERM", "linux"), ("LANG", "en_US.UTF-8")]));
+        assert_eq!(console.colors, ColorDepth::Basic);
+        assert!(!console.unicode);
+
+        let xterm = Capabilities::from_env(env(&[
+            ("TERM", "xterm-256color"),
+            ("LC_ALL", "C.UTF-8"),
+            ("LANG", "C"),
+        ]));
+        assert_eq!(xterm.colors, ColorDepth::Indexed);
+        assert!(xterm.unicode);
+
+        let plain =
+            Capabilities::from_env(env(&[("TERM", "screen"), ("LANG", "C"), ("NO_COLOR", "1")]));
+        assert_eq!(
+            plain,
+            Capabilities {
+                colors: ColorDepth::None,
+                unicode: false
+            }
+        );
+
+        let config: TuiConfig = toml::from_str("colors = \"256\"\nunicode = true").unwrap();
+        assert_eq!(
+            plain.with_config(&config),
+            Capabilities {
+                colors: ColorDepth::Indexed,
+                unicode: true
+            }
+        );
+        assert!(toml::from_str::<TuiConfig>("colors = \"lots\"").is_err());
+    }
+
+    #[test]
+    fn test_falls_back_to_ascii_and_fewer_colors() {
+        let ascii = Capabilities {
+            colors: ColorDepth::None,
+            unicode: false,
+        };
+        assert_eq!(ascii.text("📁 Café → café"), "[D] Café -> café");
+        assert_eq!(ascii.text("🔧 Help"), " Help");
+
+        let style = Style::default().fg(Color::Black).bg(Color::Cyan);
+        assert_eq!(
+            ascii.paint(style),
+            Style::default().fg(Color::Reset).bg(Color::Reset)
+        );
+        assert!(
+            ascii
+                .highlight(style)
+                .add_modifier
+                .contains(Modifier::REVERSED)
+        );
+
+        let basic = Capabilities::default();
+        assert_eq!(basic.text("a → b"), "a → b");
+        assert_eq!(basic.paint(style), style);
+        assert_eq!(
+            basic.paint(Style::default().fg(Color::Rgb(1, 2, 3))).fg,
+            Some(Color::Reset)
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use std::io;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+
+/// Programs a file can be opened with from the explorer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpenWith {
+    /// `$VISUAL`, then `$EDITOR`, then `vi`
+    Editor,
+    /// The desktop's default application (`xdg-open`, `open` on macOS)
+    System,
+}
+
+/// The editor command line, split on whitespace so `code -w` works
+pub fn editor_command(visual: Option<String>, editor: Option<String>) -> Vec<String> {
+    let command = [visual, editor]
+        .into_iter()
+        .flatten()
+        .find(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    command.split_whitespace().map(str::to_string).collect()
+}
+
+#[cfg(target_os = "macos")]
+const SYSTEM_OPENER: &str = "open";
+#[cfg(not(target_os = "macos"))]
+const SYSTEM_OPENER: &str = "xdg-open";
+
+/// Open `path` and wait for the program to finish
+///
+/// The caller suspends the TUI first, since editors take over the terminal.
+pub fn open(path: &Path, with: OpenWith) -> io::Result<ExitStatus> {
+    let words = match with {
+        OpenWith::Editor => {
+            editor_command(std::env::var("VISUAL").ok(), std::env::var("EDITOR").ok())
+        }
+        OpenWith::System => vec![SYSTEM_OPENER.to_string()],
+    };
+    Command::new(&words[0]).args(&words[1..]).arg(path).status()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editor_command() {
+        assert_eq!(editor_command(None, None), ["vi"]);
+        assert_eq!(
+            editor_command(Some(" ".into()), Some("code -w".into())),
+            ["code", "-w"]
+        );
+        assert_eq!(
+            editor_command(Some("nvim".into()), Some("nano".into())),
+            ["nvim"]
+        );
+    }
+}
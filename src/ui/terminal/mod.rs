@@ -1,9 +1,17 @@
 mod app;
+mod bookmarks;
+mod capabilities;
+mod launch;
+mod status;
 mod tui;
 pub mod views;
 pub mod widgets;
 
 pub use app::App;
+pub use bookmarks::Bookmarks;
+pub use capabilities::{Capabilities, TuiConfig};
+pub use launch::OpenWith;
+pub use status::StatusReport;
 pub use tui::Tui;
 
 use crate::ui::UiAction;
@@ -31,6 +39,12 @@ pub enum AppMode {
     Insert,
     /// Help mode - showing available actions and shortcuts
     Help,
+    /// Confirm mode - reviewing the queue before it runs
+    Confirm,
+    /// History mode - browsing past batches to undo or redo
+    History,
+    /// Directory preview - the trees before and after `o` or `O`, until confirmed
+    DirPreview,
 }
 
 impl Default for AppMode {
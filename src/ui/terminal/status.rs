@@ -0,0 +1,71 @@
+use std::path::Path;
+
+use crate::report::{Level, Outcome, Reporter};
+
+/// Reporter for the TUI: tallies renames for the status bar
+///
+/// The screen has no room for a line per file, so only the counts and the
+/// last problem are kept.
+#[derive(Debug, Default)]
+pub struct StatusReport {
+    pub done: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// The most recent warning, shown after the counts
+    pub last_problem: Option<String>,
+}
+
+impl StatusReport {
+    pub fn status_line(&self) -> String {
+        let mut line = format!(
+            "Executed: {} success, {} errors",
+            self.done,
+            self.skipped + self.failed
+        );
+        if let Some(ref problem) = self.last_problem {
+            line.push_str(&format!(" (last: {problem})"));
+        }
+        line
+    }
+}
+
+impl Reporter for StatusReport {
+    fn message(&mut self, level: Level, text: &str) {
+        if level == Level::Warning {
+            self.last_problem = Some(text.to_string());
+        }
+    }
+
+    fn result(&mut self, from: &Path, _to: &Path, outcome: &Outcome) {
+        let name = from.file_name().unwrap_or_default().to_string_lossy();
+        match outcome {
+            Outcome::Done => self.done += 1,
+            Outcome::Skipped(reason) => {
+                self.skipped += 1;
+                self.last_problem = Some(format!("{name} skipped: {reason}"));
+            }
+            Outcome::Failed(error) => {
+                self.failed += 1;
+                self.last_problem = Some(format!("{name}: {error}"));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_line_counts_outcomes() {
+        let mut report = StatusReport::default();
+        let (a, b) = (Path::new("a.txt"), Path::new("b.txt"));
+        report.result(a, b, &Outcome::Done);
+        assert_eq!(report.status_line(), "Executed: 1 success, 0 errors");
+        report.result(a, b, &Outcome::Skipped("no longer exists".into()));
+        assert_eq!(
+            report.status_line(),
+            "Executed: 1 success, 1 errors (last: a.txt skipped: no longer exists)"
+        );
+    }
+}
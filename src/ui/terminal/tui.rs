@@ -72,6 +72,24 @@ impl Tui {
         Ok(())
     }
 
+    /// Hand the terminal to another program, such as an editor
+    pub fn suspend(&mut self) -> anyhow::Result<()> {
+        self.restore_terminal()
+    }
+
+    /// Take the terminal back after [`Tui::suspend`] and redraw from scratch
+    pub fn resume(&mut self) -> anyhow::Result<()> {
+        terminal::enable_raw_mode()?;
+        execute!(
+            self.terminal.backend_mut(),
+            EnterAlternateScreen,
+            EnableMouseCapture
+        )?;
+        self.terminal.hide_cursor()?;
+        self.terminal.clear()?;
+        Ok(())
+    }
+
     /// Set up terminal panic hook to restore terminal state
     pub fn init_panic_hook() {
         let original_hook = panic::take_hook();
@@ -0,0 +1,148 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::widgets::ListState;
+
+use crate::plan::{Conflict, ConflictChecker, OpKind, PlannedOp};
+use crate::ui::terminal::app::FileOperation;
+
+/// One queued operation as shown in the confirmation dialog
+#[derive(Clone, Debug)]
+pub struct ConfirmEntry {
+    pub operation: FileOperation,
+    /// Why the operation would fail or overwrite something
+    pub conflict: Option<Conflict>,
+    /// Whether it runs when the dialog is confirmed
+    pub included: bool,
+}
+
+/// What the user decided in the dialog
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// Still looking
+    Pending,
+    /// Run the included operations
+    Run,
+    /// Go back to the queue without running anything
+    Cancel,
+}
+
+/// Summary of the queue shown before `x` runs it
+///
+/// Operations with a conflict start deselected, since renaming onto an existing
+/// file would replace it; Space toggles any entry.
+pub struct ConfirmView {
+    pub entries: Vec<ConfirmEntry>,
+    pub state: ListState,
+}
+
+impl ConfirmView {
+    /// Check `operations` in queue order, the way a plan is checked
+    pub fn new(operations: &[FileOperation]) -> Self {
+        let mut checker = ConflictChecker::new(false);
+        let entries = operations
+            .iter()
+            .map(|operation| {
+                let conflict = checker.check(&PlannedOp {
+                    op: OpKind::Move,
+                    from: operation.source.clone(),
+                    to: operation.destination.clone(),
+                });
+                ConfirmEntry {
+                    operation: operation.clone(),
+                    conflict,
+                    included: conflict.is_none(),
+                }
+            })
+            .collect();
+        let mut state = ListState::default();
+        state.select(Some(0));
+        Self { entries, state }
+    }
+
+    pub fn conflicts(&self) -> usize {
+        self.entries.iter().filter(|e| e.conflict.is_some()).count()
+    }
+
+    /// The operations that run on confirmation, in queue order
+    pub fn included(&self) -> Vec<FileOperation> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.included)
+            .map(|entry| entry.operation.clone())
+            .collect()
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> Decision {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Enter => return Decision::Run,
+            KeyCode::Char('n') | KeyCode::Char('q') | KeyCode::Esc => return Decision::Cancel,
+            KeyCode::Char('j') | KeyCode::Down => self.select_by(1),
+            KeyCode::Char('k') | KeyCode::Up => self.select_by(-1),
+            KeyCode::Char(' ') => {
+                let selected = self.state.selected().unwrap_or(0);
+                if let Some(entry) = self.entries.get_mut(selected) {
+                    entry.included = !entry.included;
+                }
+                self.select_by(1);
+            }
+            KeyCode::Char('a') => {
+                // Select everything, or nothing when everything is selected
+                let all = self.entries.iter().all(|entry| entry.included);
+                for entry in &mut self.entries {
+                    entry.included = !all;
+                }
+            }
+            _ => {}
+        }
+        Decision::Pending
+    }
+
+    fn select_by(&mut self, step: isize) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let len = self.entries.len() as isize;
+        let current = self.state.selected().unwrap_or(0) as isize;
+        self.state
+            .select(Some((current + step).rem_euclid(len) as usize));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::terminal::app::OperationType;
+    use crossterm::event::KeyModifiers;
+    use std::fs;
+
+    fn press(view: &mut ConfirmView, code: KeyCode) -> Decision {
+        view.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn test_conflicts_start_deselected() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("A File"), dir.path().join("b"));
+        fs::write(&a, "").unwrap();
+        fs::write(&b, "").unwrap();
+        let operation = |source: &std::path::Path, destination: &std::path::Path| FileOperation {
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+            operation_type: OperationType::Move,
+        };
+        let mut view =
+            ConfirmView::new(&[operation(&a, &dir.path().join("a_file")), operation(&b, &a)]);
+
+        assert_eq!(view.conflicts(), 1);
+        assert_eq!(view.entries[1].conflict, Some(Conflict::DestinationExists));
+        assert_eq!(view.included().len(), 1);
+
+        // Space on the first entry drops it and moves on to the second
+        assert_eq!(press(&mut view, KeyCode::Char(' ')), Decision::Pending);
+        assert!(view.included().is_empty());
+        assert_eq!(view.state.selected(), Some(1));
+        press(&mut view, KeyCode::Char('a'));
+        assert_eq!(view.included().len(), 2);
+        assert_eq!(press(&mut view, KeyCode::Esc), Decision::Cancel);
+        assert_eq!(press(&mut view, KeyCode::Char('y')), Decision::Run);
+    }
+}
@@ -1,12 +1,14 @@
 use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
-pub use std::path::PathBuf;
+pub use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
 
 use crossterm::event::{KeyCode, KeyEvent};
 use ratatui::widgets::ListState;
 
-use crate::ui::terminal::{AppMode, KeyResult};
+use crate::ui::terminal::{AppMode, KeyResult, OpenWith};
 use crate::ui::{TransformAction, UiAction};
 use skim::prelude::*;
 
@@ -25,6 +27,18 @@ pub struct FileItem {
     pub size: u64,
 }
 
+/// Entries the background scan sends at a time
+const LOAD_BATCH: usize = 500;
+/// Batches taken per [`FileExplorer::poll_loading`], so one frame stays cheap
+const BATCHES_PER_POLL: usize = 20;
+
+/// A directory being read on a background thread
+struct Loading {
+    batches: Receiver<Vec<FileItem>>,
+    /// Path to select once loaded, and the position to fall back to
+    reselect: (Option<PathBuf>, usize),
+}
+
 /// The file explorer view
 pub struct FileExplorer {
     /// Current directory
@@ -41,6 +55,10 @@ pub struct FileExplorer {
     filtered_files: Vec<usize>,
     /// Cursor position memory for each directory
     cursor_positions: HashMap<PathBuf, usize>,
+    /// First key of a two-key command (`gg`, `gx`, `ma`, `'a`)
+    pending_key: Option<char>,
+    /// The scan in progress, if the directory is still loading
+    loading: Option<Loading>,
 }
 
 impl FileExplorer {
@@ -54,11 +72,13 @@ impl FileExplorer {
             search_pattern: None,
             filtered_files: Vec::new(),
             cursor_positions: HashMap::new(),
+            pending_key: None,
+            loading: None,
         };
 
         // Load initial directory
-        let _ = explorer.reload_files();
         explorer.state.select(Some(0));
+        let _ = explorer.reload_files();
 
         explorer
     }
@@ -72,31 +92,39 @@ impl FileExplorer {
         }
 
         // Change to new directory
-        self.current_dir = dir;
-        self.reload_files()?;
+        let previous = std::mem::replace(&mut self.current_dir, dir);
+        self.visual_selection_start = None;
+        self.search_pattern = None;
+        self.filtered_files.clear();
+        if let Err(e) = self.reload_files() {
+            self.current_dir = previous;
+            return Err(e);
+        }
 
-        // Restore cursor position for the new directory, or default to 0
+        // Restore cursor position for the new directory once loaded, or default to 0
         let cursor_position = self
             .cursor_positions
             .get(&self.current_dir)
             .copied()
             .unwrap_or(0);
-        let max_index = if self.files.is_empty() {
-            0
-        } else {
-            self.files.len() - 1
-        };
-        let safe_position = cursor_position.min(max_index);
-        self.state.select(Some(safe_position));
-
-        self.visual_selection_start = None;
-        self.search_pattern = None;
-        self.filtered_files.clear();
+        if let Some(loading) = self.loading.as_mut() {
+            loading.reselect = (None, cursor_position);
+        }
+        self.state.select(Some(0));
         Ok(())
     }
 
     /// Reload files in the current directory
+    ///
+    /// Only opening the directory happens here; the entries are read on a
+    /// background thread and arrive through [`FileExplorer::poll_loading`], so
+    /// directories with tens of thousands of entries do not freeze the UI.
     pub fn reload_files(&mut self) -> Result<(), Box<dyn Error>> {
+        let entries = fs::read_dir(&self.current_dir)?;
+        let reselect = (
+            self.selected().map(|item| item.path.clone()),
+            self.state.selected().unwrap_or(0),
+        );
         self.files.clear();
 
         // Always add a parent directory entry
@@ -113,36 +141,106 @@ impl FileExplorer {
             size: 0,
         });
 
-        // Read directory entries
-        let entries = fs::read_dir(&self.current_dir)?;
-        for entry in entries {
-            let entry = entry?;
-            let path = entry.path();
-            let metadata = entry.metadata()?;
-
-            self.files.push(FileItem {
-                name: entry.file_name().to_string_lossy().to_string(),
-                path,
-                is_dir: metadata.is_dir(),
-                is_symlink: metadata.file_type().is_symlink(),
-                size: metadata.len(),
-            });
+        // Read directory entries in the background; a newer scan drops the
+        // receiver, which stops this one at its next batch
+        let (sender, batches) = mpsc::channel();
+        thread::spawn(move || {
+            let mut batch = Vec::with_capacity(LOAD_BATCH);
+            for entry in entries.flatten() {
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                batch.push(FileItem {
+                    name: entry.file_name().to_string_lossy().to_string(),
+                    path: entry.path(),
+                    is_dir: metadata.is_dir(),
+                    is_symlink: metadata.file_type().is_symlink(),
+                    size: metadata.len(),
+                });
+                if batch.len() == LOAD_BATCH
+                    && sender
+                        .send(std::mem::replace(
+                            &mut batch,
+                            Vec::with_capacity(LOAD_BATCH),
+                        ))
+                        .is_err()
+                {
+                    return;
+                }
+            }
+            let _ = sender.send(batch);
+        });
+        self.loading = Some(Loading { batches, reselect });
+
+        Ok(())
+    }
+
+    /// Whether the directory is still being read
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    /// Take the entries the background scan has read so far
+    ///
+    /// Returns whether anything changed. Entries are shown as they arrive and
+    /// sorted (directories first, then alphabetically) once the scan is done.
+    pub fn poll_loading(&mut self) -> bool {
+        let Some(loading) = self.loading.as_ref() else {
+            return false;
+        };
+        let mut changed = false;
+        for _ in 0..BATCHES_PER_POLL {
+            match loading.batches.try_recv() {
+                Ok(batch) => {
+                    self.files.extend(batch);
+                    changed = true;
+                }
+                Err(TryRecvError::Empty) => return changed,
+                Err(TryRecvError::Disconnected) => {
+                    self.finish_loading();
+                    return true;
+                }
+            }
         }
+        changed
+    }
+
+    fn finish_loading(&mut self) {
+        let Some(Loading { reselect, .. }) = self.loading.take() else {
+            return;
+        };
 
         // Sort: directories first, then files alphabetically
-        self.files.sort_by(|a, b| match (a.is_dir, b.is_dir) {
-            (true, false) => std::cmp::Ordering::Less,
-            (false, true) => std::cmp::Ordering::Greater,
-            _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
-        });
+        self.files
+            .sort_by_cached_key(|file| (!file.is_dir, file.name.to_lowercase()));
 
         // Update filtered files if the search is active
         if let Some(pattern) = self.search_pattern.as_ref() {
             let pattern = pattern.clone();
             self.filter_files(&pattern);
+            return;
         }
 
-        Ok(())
+        let (path, position) = reselect;
+        let position = path
+            .and_then(|path| self.files.iter().position(|file| file.path == path))
+            .unwrap_or(position)
+            .min(self.files.len().saturating_sub(1));
+        self.state.select(Some(position));
+    }
+
+    /// The directory being shown
+    pub fn current_dir(&self) -> &Path {
+        &self.current_dir
+    }
+
+    /// The first key of an unfinished two-key command
+    pub fn pending_key(&self) -> Option<char> {
+        self.pending_key
+    }
+
+    pub fn cancel_pending(&mut self) {
+        self.pending_key = None;
     }
 
     /// Get the selected file
@@ -228,6 +326,23 @@ impl FileExplorer {
 
     /// Handle keys in normal mode
     fn handle_normal_key(&mut self, key: KeyEvent) -> KeyResult {
+        match (self.pending_key.take(), key.code) {
+            (Some('g'), KeyCode::Char('g')) => {
+                self.select_first();
+                return KeyResult::Handled(None);
+            }
+            (Some('g'), KeyCode::Char('x')) => return self.open_selected(OpenWith::System),
+            (Some('m'), KeyCode::Char(mark)) => {
+                return KeyResult::Handled(Some(UiAction::SetBookmark(mark)));
+            }
+            (Some('\''), KeyCode::Char(mark)) => {
+                return KeyResult::Handled(Some(UiAction::JumpTo(mark)));
+            }
+            // Any other key cancels a bookmark command
+            (Some('m' | '\''), _) => return KeyResult::Handled(None),
+            _ => {}
+        }
+
         match key.code {
             // Navigation
             KeyCode::Down | KeyCode::Char('j') => {
@@ -246,10 +361,14 @@ impl FileExplorer {
                 self.select_prev(10);
                 KeyResult::Handled(None)
             }
-            KeyCode::Home | KeyCode::Char('g') => {
+            KeyCode::Home => {
                 self.select_first();
                 KeyResult::Handled(None)
             }
+            KeyCode::Char(first @ ('g' | 'm' | '\'')) => {
+                self.pending_key = Some(first);
+                KeyResult::Handled(None)
+            }
             KeyCode::End | KeyCode::Char('G') => {
                 self.select_last();
                 KeyResult::Handled(None)
@@ -283,6 +402,9 @@ impl FileExplorer {
                 KeyResult::Handled(None)
             }
 
+            // Opening files
+            KeyCode::Char('e') => self.open_selected(OpenWith::Editor),
+
             // Transformation shortcuts
             KeyCode::Char('s') => {
                 // Snake case transformation
@@ -367,6 +489,14 @@ impl FileExplorer {
         }
     }
 
+    /// Open the selected file; directories are left alone
+    fn open_selected(&mut self, with: OpenWith) -> KeyResult {
+        match self.selected() {
+            Some(item) if !item.is_dir => KeyResult::Handled(Some(UiAction::OpenFile(with))),
+            _ => KeyResult::Handled(None),
+        }
+    }
+
     /// Handle keys in visual mode
     fn handle_visual_key(&mut self, key: KeyEvent) -> KeyResult {
         match key.code {
@@ -0,0 +1,159 @@
+use chrono::{DateTime, Local};
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::widgets::ListState;
+
+use crate::history::{HistoryManager, MappedMove};
+
+/// Batches listed in the panel, newest first
+pub const MAX_BATCHES: usize = 50;
+
+/// One command's worth of recorded operations
+#[derive(Clone, Debug)]
+pub struct BatchEntry {
+    pub when: DateTime<Local>,
+    /// What moved (or was put in the trash), in the order it happened
+    pub moves: Vec<MappedMove>,
+    /// Files the batch created, which have nothing to move back
+    pub created: usize,
+    /// Whether the batch removed files into the trash
+    pub removal: bool,
+}
+
+impl BatchEntry {
+    pub fn summary(&self) -> String {
+        let what = if self.removal { "removed" } else { "moved" };
+        let mut summary = format!(
+            "{}  {} {what}",
+            self.when.format("%Y-%m-%d %H:%M:%S"),
+            self.moves.len()
+        );
+        if self.created > 0 {
+            summary.push_str(&format!(", {} created", self.created));
+        }
+        summary
+    }
+}
+
+/// What the panel asks the app to do
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistoryAction {
+    None,
+    Close,
+    /// Queue the selected batch's moves in reverse
+    Undo,
+    /// Queue the selected batch's moves again, after it was undone
+    Redo,
+}
+
+/// The undo history as a list of batches, with the selected one's files shown
+pub struct HistoryView {
+    pub batches: Vec<BatchEntry>,
+    pub state: ListState,
+    /// Whether the selected batch lists its files
+    pub expanded: bool,
+}
+
+impl HistoryView {
+    /// Read the most recent batches; unreadable mapping files show as empty
+    pub fn load(history: &HistoryManager) -> Self {
+        let batches: Vec<BatchEntry> = history
+            .batches()
+            .into_iter()
+            .rev()
+            .take(MAX_BATCHES)
+            .map(|operations| {
+                let mut entry = BatchEntry {
+                    when: operations[0].timestamp,
+                    moves: Vec::new(),
+                    created: 0,
+                    removal: false,
+                };
+                for operation in operations {
+                    if operation.trash.is_some() {
+                        entry.removal = true;
+                    } else if operation.source.as_os_str().is_empty() {
+                        entry.created += 1;
+                        continue;
+                    }
+                    entry
+                        .moves
+                        .extend(history.files_of(operation).unwrap_or_default());
+                }
+                entry
+            })
+            .collect();
+        let mut state = ListState::default();
+        state.select((!batches.is_empty()).then_some(0));
+        Self {
+            batches,
+            state,
+            expanded: false,
+        }
+    }
+
+    pub fn selected(&self) -> Option<&BatchEntry> {
+        self.batches.get(self.state.selected()?)
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> HistoryAction {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('H') => return HistoryAction::Close,
+            KeyCode::Char('u') => return HistoryAction::Undo,
+            KeyCode::Char('r') => return HistoryAction::Redo,
+            KeyCode::Enter | KeyCode::Char('l') => self.expanded = !self.expanded,
+            KeyCode::Char('j') | KeyCode::Down => self.select_by(1),
+            KeyCode::Char('k') | KeyCode::Up => self.select_by(-1),
+            _ => {}
+        }
+        HistoryAction::None
+    }
+
+    fn select_by(&mut self, step: isize) {
+        if self.batches.is_empty() {
+            return;
+        }
+        let len = self.batches.len() as isize;
+        let current = self.state.selected().unwrap_or(0) as isize;
+        self.state
+            .select(Some((current + step).rem_euclid(len) as usize));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_history_view_lists_newest_batch_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = HistoryManager::new(10, &dir.path().join("backups"));
+        let moves = [(PathBuf::from("a.txt"), PathBuf::from("b.txt"))];
+        history
+            .record_mapping(dir.path().to_path_buf(), &moves)
+            .unwrap();
+        history.start_batch();
+        history
+            .record(PathBuf::new(), dir.path().join("new.txt"))
+            .unwrap();
+
+        let mut view = HistoryView::load(&history);
+        assert_eq!(view.batches.len(), 2);
+        assert_eq!(view.batches[0].created, 1);
+        assert!(view.batches[0].moves.is_empty());
+
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        assert_eq!(
+            view.handle_key(key(KeyCode::Char('j'))),
+            HistoryAction::None
+        );
+        let older = view.selected().unwrap();
+        assert_eq!(older.moves.len(), 1);
+        assert!(older.summary().ends_with("1 moved"));
+        assert_eq!(
+            view.handle_key(key(KeyCode::Char('u'))),
+            HistoryAction::Undo
+        );
+    }
+}
@@ -1,7 +1,15 @@
+mod confirm_view;
 mod file_explorer;
+mod history_view;
 mod preview_view;
 mod queue_view;
+mod replace_view;
+mod tree_preview_view;
 
+pub use confirm_view::{ConfirmView, Decision};
 pub use file_explorer::{FileExplorer, FileItem};
+pub use history_view::{HistoryAction, HistoryView};
 pub use preview_view::PreviewView;
 pub use queue_view::QueueView;
+pub use replace_view::{Field, ReplaceAction, ReplaceView};
+pub use tree_preview_view::{DirOperation, TreeLine, TreeMark, TreePreviewView};
@@ -0,0 +1,164 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use regex::Regex;
+
+use crate::transformers::{TransformType, transform};
+use crate::ui::terminal::views::FileItem;
+
+/// The text box being typed into
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Find,
+    Replace,
+}
+
+/// What the dialog asks the app to do
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplaceAction {
+    None,
+    Cancel,
+    /// Queue the renames shown in the preview
+    Queue,
+}
+
+/// Find/replace over the selected files, like the CLI's CHANGE and REGEX
+///
+/// The preview is recomputed from the typed text on every draw, so it always
+/// shows what queueing would do.
+pub struct ReplaceView {
+    pub find: String,
+    pub replace: String,
+    /// Treat `find` as a regular expression (`$1` refers to groups)
+    pub regex: bool,
+    pub field: Field,
+    /// The files the dialog was opened on
+    pub files: Vec<FileItem>,
+}
+
+impl ReplaceView {
+    pub fn new(files: Vec<FileItem>, find: String, regex: bool) -> Self {
+        Self {
+            find,
+            replace: String::new(),
+            regex,
+            field: Field::Find,
+            files,
+        }
+    }
+
+    pub fn transform_type(&self) -> TransformType {
+        if self.regex {
+            TransformType::ReplaceRegex(self.find.clone(), self.replace.clone())
+        } else {
+            TransformType::Replace(self.find.clone(), self.replace.clone())
+        }
+    }
+
+    /// Why the find text cannot be used yet
+    pub fn error(&self) -> Option<String> {
+        if self.find.is_empty() {
+            return Some(String::from("Type what to find"));
+        }
+        if self.regex {
+            return Regex::new(&self.find).err().map(|e| e.to_string());
+        }
+        None
+    }
+
+    /// Each file's name and the name it would get
+    pub fn previews(&self) -> Vec<(&FileItem, String)> {
+        let transform_type = self.transform_type();
+        let usable = self.error().is_none();
+        self.files
+            .iter()
+            .map(|file| {
+                let renamed = if usable {
+                    transform(&file.name, &transform_type)
+                } else {
+                    file.name.clone()
+                };
+                (file, renamed)
+            })
+            .collect()
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> ReplaceAction {
+        match (key.code, key.modifiers) {
+            (KeyCode::Esc, _) => return ReplaceAction::Cancel,
+            (KeyCode::Enter, _) if self.error().is_none() => return ReplaceAction::Queue,
+            (KeyCode::Tab | KeyCode::BackTab | KeyCode::Up | KeyCode::Down, _) => {
+                self.field = match self.field {
+                    Field::Find => Field::Replace,
+                    Field::Replace => Field::Find,
+                };
+            }
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => self.regex = !self.regex,
+            (KeyCode::Backspace, _) => {
+                self.text_mut().pop();
+            }
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                self.text_mut().push(c);
+            }
+            _ => {}
+        }
+        ReplaceAction::None
+    }
+
+    fn text_mut(&mut self) -> &mut String {
+        match self.field {
+            Field::Find => &mut self.find,
+            Field::Replace => &mut self.replace,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn file(name: &str) -> FileItem {
+        FileItem {
+            name: name.to_string(),
+            path: PathBuf::from(name),
+            is_dir: false,
+            is_symlink: false,
+            size: 0,
+        }
+    }
+
+    fn type_text(view: &mut ReplaceView, text: &str) {
+        for c in text.chars() {
+            view.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+    }
+
+    #[test]
+    fn test_preview_follows_typing() {
+        let mut view = ReplaceView::new(
+            vec![file("IMG_001.jpg"), file("notes.txt")],
+            String::new(),
+            false,
+        );
+        assert!(view.error().is_some());
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+        assert_eq!(view.handle_key(enter), ReplaceAction::None);
+
+        type_text(&mut view, "IMG_");
+        view.handle_key(KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE));
+        type_text(&mut view, "photo-");
+        let previews = view.previews();
+        assert_eq!(previews[0].1, "photo-001.jpg");
+        assert_eq!(previews[1].1, "notes.txt");
+
+        // Switching to a regex reinterprets the same text
+        view.handle_key(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL));
+        view.field = Field::Find;
+        type_text(&mut view, "(");
+        assert!(view.error().is_some());
+        view.handle_key(KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE));
+        type_text(&mut view, r"(\d+)");
+        view.replace = String::from("n$1");
+        assert_eq!(view.previews()[0].1, "n001.jpg");
+        assert_eq!(view.handle_key(enter), ReplaceAction::Queue);
+    }
+}
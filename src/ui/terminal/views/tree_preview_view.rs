@@ -0,0 +1,225 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::ui::terminal::views::Decision;
+
+/// The directory operations bound to `o` and `O`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DirOperation {
+    /// Files into a folder named after their basename
+    Group,
+    /// Every file up into the directory, emptied folders removed
+    Flatten,
+}
+
+impl DirOperation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DirOperation::Group => "Group files",
+            DirOperation::Flatten => "Flatten",
+        }
+    }
+}
+
+/// What happens to a path in the tree
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreeMark {
+    Unchanged,
+    /// A file that moves away (before)
+    Leaves,
+    /// A file that moved here (after)
+    Arrives,
+    /// A folder the operation makes
+    Created,
+    /// A folder left empty and removed
+    Removed,
+}
+
+/// One row of a tree
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreeLine {
+    /// Folders above the path, below the root
+    pub depth: usize,
+    /// File name, with a trailing `/` for folders
+    pub name: String,
+    pub mark: TreeMark,
+}
+
+/// The directory as it is and as it will be, shown before `o` or `O` runs
+///
+/// Both trees scroll together; `y` runs the operation and `n` drops it.
+pub struct TreePreviewView {
+    pub operation: DirOperation,
+    pub root: PathBuf,
+    pub before: Vec<TreeLine>,
+    pub after: Vec<TreeLine>,
+    /// Files that move
+    pub moves: usize,
+    pub created: usize,
+    pub removed: usize,
+    /// First row shown
+    pub offset: usize,
+}
+
+impl TreePreviewView {
+    /// Lay out `root` now and after `moves`, the dry run of `operation`
+    pub fn new(operation: DirOperation, root: &Path, moves: &[(PathBuf, PathBuf)]) -> Self {
+        // Paths below the root, with whether each is a folder; ordered by
+        // components, so every folder comes right before its contents
+        let now: BTreeMap<PathBuf, bool> = walkdir::WalkDir::new(root)
+            .min_depth(1)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let relative = entry.path().strip_prefix(root).ok()?.to_path_buf();
+                Some((relative, entry.file_type().is_dir()))
+            })
+            .collect();
+        let mut later = now.clone();
+
+        let relative = |path: &Path| path.strip_prefix(root).unwrap_or(path).to_path_buf();
+        let leaving: HashSet<PathBuf> = moves.iter().map(|(from, _)| relative(from)).collect();
+        let arriving: HashSet<PathBuf> = moves.iter().map(|(_, to)| relative(to)).collect();
+        for from in &leaving {
+            later.remove(from);
+        }
+        for to in &arriving {
+            for folder in to.ancestors().skip(1) {
+                if !folder.as_os_str().is_empty() {
+                    later.insert(folder.to_path_buf(), true);
+                }
+            }
+            later.insert(to.clone(), false);
+        }
+        if operation == DirOperation::Flatten {
+            // Deepest first, so folders holding only empty folders go too
+            let mut folders: Vec<PathBuf> = later
+                .iter()
+                .filter(|(_, is_dir)| **is_dir)
+                .map(|(path, _)| path.clone())
+                .collect();
+            folders.sort_by_key(|folder| std::cmp::Reverse(folder.components().count()));
+            for folder in folders {
+                let has_contents = later
+                    .range(folder.clone()..)
+                    .nth(1)
+                    .is_some_and(|(path, _)| path.starts_with(&folder));
+                if !has_contents {
+                    later.remove(&folder);
+                }
+            }
+        }
+
+        let line = |path: &Path, is_dir: bool, mark: TreeMark| TreeLine {
+            depth: path.components().count().saturating_sub(1),
+            name: format!(
+                "{}{}",
+                path.file_name().unwrap_or_default().to_string_lossy(),
+                if is_dir { "/" } else { "" }
+            ),
+            mark,
+        };
+        let before: Vec<TreeLine> = now
+            .iter()
+            .map(|(path, &is_dir)| {
+                let mark = if leaving.contains(path) {
+                    TreeMark::Leaves
+                } else if is_dir && !later.contains_key(path) {
+                    TreeMark::Removed
+                } else {
+                    TreeMark::Unchanged
+                };
+                line(path, is_dir, mark)
+            })
+            .collect();
+        let after: Vec<TreeLine> = later
+            .iter()
+            .map(|(path, &is_dir)| {
+                let mark = if arriving.contains(path) {
+                    TreeMark::Arrives
+                } else if is_dir && !now.contains_key(path) {
+                    TreeMark::Created
+                } else {
+                    TreeMark::Unchanged
+                };
+                line(path, is_dir, mark)
+            })
+            .collect();
+
+        Self {
+            operation,
+            root: root.to_path_buf(),
+            moves: moves.len(),
+            created: after.iter().filter(|l| l.mark == TreeMark::Created).count(),
+            removed: before
+                .iter()
+                .filter(|l| l.mark == TreeMark::Removed)
+                .count(),
+            before,
+            after,
+            offset: 0,
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> Decision {
+        let last = self.before.len().max(self.after.len()).saturating_sub(1);
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Enter => return Decision::Run,
+            KeyCode::Char('n') | KeyCode::Char('q') | KeyCode::Esc => return Decision::Cancel,
+            KeyCode::Char('j') | KeyCode::Down => self.offset = (self.offset + 1).min(last),
+            KeyCode::Char('k') | KeyCode::Up => self.offset = self.offset.saturating_sub(1),
+            KeyCode::PageDown => self.offset = (self.offset + 10).min(last),
+            KeyCode::PageUp => self.offset = self.offset.saturating_sub(10),
+            _ => {}
+        }
+        Decision::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn names(lines: &[TreeLine], mark: TreeMark) -> Vec<String> {
+        lines
+            .iter()
+            .filter(|line| line.mark == mark)
+            .map(|line| line.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_trees_before_and_after() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::create_dir(root.join("keep")).unwrap();
+        fs::write(root.join("a/b/deep.txt"), "").unwrap();
+        fs::write(root.join("top.txt"), "").unwrap();
+        fs::write(root.join("keep/k.txt"), "").unwrap();
+
+        let moves = [
+            (root.join("a/b/deep.txt"), root.join("deep.txt")),
+            (root.join("keep/k.txt"), root.join("k.txt")),
+        ];
+        let view = TreePreviewView::new(DirOperation::Flatten, root, &moves);
+        assert_eq!(view.before[0].name, "a/");
+        assert_eq!(view.before[2].depth, 2);
+        assert_eq!(names(&view.before, TreeMark::Leaves), ["deep.txt", "k.txt"]);
+        assert_eq!(
+            names(&view.before, TreeMark::Removed),
+            ["a/", "b/", "keep/"]
+        );
+        assert_eq!(names(&view.after, TreeMark::Arrives), ["deep.txt", "k.txt"]);
+        assert_eq!(view.after.len(), 3);
+        assert_eq!(view.removed, 3);
+
+        let moves = [(root.join("top.txt"), root.join("top/top.txt"))];
+        let view = TreePreviewView::new(DirOperation::Group, root, &moves);
+        assert_eq!(names(&view.after, TreeMark::Created), ["top/"]);
+        assert_eq!(view.after.last().unwrap().depth, 1);
+        assert!(names(&view.before, TreeMark::Removed).is_empty());
+    }
+}
@@ -20,6 +20,31 @@ mod tests {
         assert_eq!(mode, AppMode::Normal, "Default app mode should be Normal");
     }
 
+    #[test]
+    fn test_explorer_loads_in_background() {
+        use crate::ui::terminal::views::FileExplorer;
+
+        let dir = tempfile::tempdir().unwrap();
+        for n in 0..1200 {
+            std::fs::write(dir.path().join(format!("file{n:04}.txt")), "").unwrap();
+        }
+        std::fs::create_dir(dir.path().join("zz_dir")).unwrap();
+
+        let mut explorer = FileExplorer::new(dir.path().to_path_buf());
+        let started = std::time::Instant::now();
+        while explorer.is_loading() {
+            explorer.poll_loading();
+            assert!(started.elapsed().as_secs() < 10, "loading never finished");
+        }
+
+        // The parent entry, then directories, then files in order
+        assert_eq!(explorer.files.len(), 1202);
+        assert_eq!(explorer.files[0].name, "..");
+        assert_eq!(explorer.files[1].name, "zz_dir");
+        assert_eq!(explorer.files[2].name, "file0000.txt");
+        assert_eq!(explorer.files[1201].name, "file1199.txt");
+    }
+
     #[test]
     fn test_transform_type() {
         use crate::transformers::transform;
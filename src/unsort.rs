@@ -1,47 +1,146 @@
 use anyhow::Result;
 use chrono::Local;
+use std::collections::HashSet;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use crate::protect::Protection;
+use crate::report::Reporter;
+use crate::sort::skip_protected;
 use walkdir::WalkDir;
 
-/// Moves all files from subdirectories into the root directory
-pub fn flatten_directory(root: &str, dry_run: bool) -> Result<()> {
-    for entry in WalkDir::new(root)
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|e| e.path().is_file())
-    {
-        let path = entry.path();
-        let target = Path::new(root).join(path.file_name().unwrap());
-
-        if path != target {
-            let mut final_target = target.clone();
-            if final_target.exists() {
-                let timestamp = Local::now().format("%Y%m%d%H%M%S");
-                let base = path.file_stem().unwrap().to_string_lossy();
-                let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-                final_target = Path::new(root).join(format!("{base}_{timestamp}.{ext}"));
+/// More files than this are not flattened without `-F`
+pub const FLATTEN_LIMIT: usize = 1000;
+
+/// Where every file below a directory goes when it is flattened
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FlattenPlan {
+    /// `(old, new)` of every file that moves up
+    pub moves: Vec<(PathBuf, PathBuf)>,
+    /// `(old, new)` of the moves whose name was taken and had to change
+    pub collisions: Vec<(PathBuf, PathBuf)>,
+    /// Mount points below the root, left as they are
+    pub other_filesystems: Vec<PathBuf>,
+}
+
+impl FlattenPlan {
+    /// Work out the moves without touching anything
+    ///
+    /// The walk stays on the root's filesystem. A file whose name is already
+    /// in the root, or taken by a file moved up before it, gets a timestamp
+    /// (and a counter when that is taken too), so nothing is overwritten.
+    pub fn new(root: &str, reporter: &mut dyn Reporter) -> Result<Self> {
+        let root_path = Path::new(root);
+        let device = device_of(root_path);
+        let mut plan = FlattenPlan::default();
+        let mut protection = Protection::new();
+        let mut taken: HashSet<std::ffi::OsString> = fs::read_dir(root_path)?
+            .filter_map(|entry| Some(entry.ok()?.file_name()))
+            .collect();
+        let timestamp = Local::now().format("%Y%m%d%H%M%S").to_string();
+
+        let mut walk = WalkDir::new(root_path)
+            .min_depth(1)
+            .sort_by_file_name()
+            .into_iter();
+        while let Some(entry) = walk.next() {
+            let Ok(entry) = entry else { continue };
+            let path = entry.path();
+            if entry.file_type().is_dir() {
+                if device.is_some() && device_of(path) != device {
+                    reporter.info(&format!("Skipping {} (another filesystem)", path.display()));
+                    plan.other_filesystems.push(path.to_path_buf());
+                    walk.skip_current_dir();
+                }
+                continue;
             }
+            if entry.depth() == 1
+                || !path.is_file()
+                || skip_protected(&mut protection, path, reporter)
+            {
+                continue;
+            }
+
+            let name = entry.file_name();
+            if taken.insert(name.to_os_string()) {
+                plan.moves.push((path.to_path_buf(), root_path.join(name)));
+                continue;
+            }
+            let base = path.file_stem().unwrap_or(name).to_string_lossy();
+            let ext = path
+                .extension()
+                .map(|ext| format!(".{}", ext.to_string_lossy()))
+                .unwrap_or_default();
+            let mut count = 1;
+            let new_name = loop {
+                let candidate = match count {
+                    1 => format!("{base}_{timestamp}{ext}"),
+                    _ => format!("{base}_{timestamp}_{count}{ext}"),
+                };
+                if taken.insert(candidate.clone().into()) {
+                    break candidate;
+                }
+                count += 1;
+            };
+            let target = root_path.join(new_name);
+            plan.collisions.push((path.to_path_buf(), target.clone()));
+            plan.moves.push((path.to_path_buf(), target));
+        }
+        Ok(plan)
+    }
 
-            println!("Moving {} → {}", path.display(), final_target.display());
+    /// Carry out the moves, or only report them in a dry run
+    pub fn apply(&self, dry_run: bool, reporter: &mut dyn Reporter) -> Result<()> {
+        for (from, to) in &self.moves {
+            reporter.info(&format!("Moving {} → {}", from.display(), to.display()));
             if !dry_run {
-                fs::rename(path, final_target)?;
+                fs::rename(from, to)?;
             }
         }
+        Ok(())
     }
-    Ok(())
+}
+
+/// Moves all files from subdirectories into the root directory
+///
+/// Returns the `(old, new)` paths that were moved (or would be, in a dry run).
+/// See [`FlattenPlan`] for how names that are taken and mount points are handled.
+pub fn flatten_directory(
+    root: &str,
+    dry_run: bool,
+    reporter: &mut dyn Reporter,
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let plan = FlattenPlan::new(root, reporter)?;
+    plan.apply(dry_run, reporter)?;
+    Ok(plan.moves)
+}
+
+/// The device `path` is on, where there are devices
+#[cfg(unix)]
+fn device_of(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|metadata| metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn device_of(_path: &Path) -> Option<u64> {
+    None
 }
 
 /// Deletes empty directories recursively
-pub fn remove_empty_dirs(root: &str, dry_run: bool) -> Result<()> {
+///
+/// Mount points, and everything below them, are left alone.
+pub fn remove_empty_dirs(root: &str, dry_run: bool, reporter: &mut dyn Reporter) -> Result<()> {
     // Collect all directories first, then sort by depth to process deepest first
     let mut dirs = Vec::new();
+    let device = device_of(Path::new(root));
 
     for entry in WalkDir::new(root)
         .min_depth(1)
+        .same_file_system(true)
         .into_iter()
         .filter_map(Result::ok)
-        .filter(|e| e.path().is_dir())
+        .filter(|e| e.path().is_dir() && device_of(e.path()) == device)
     {
         dirs.push(entry.path().to_path_buf());
     }
@@ -52,7 +151,7 @@ pub fn remove_empty_dirs(root: &str, dry_run: bool) -> Result<()> {
     // Process directories from deepest to shallowest
     for path in dirs {
         if fs::read_dir(&path)?.next().is_none() && path != Path::new(root) {
-            println!("Deleting empty directory: {}", path.display());
+            reporter.info(&format!("Deleting empty directory: {}", path.display()));
             if !dry_run {
                 fs::remove_dir(&path)?;
             }
@@ -61,3 +160,39 @@ pub fn remove_empty_dirs(root: &str, dry_run: bool) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::Quiet;
+
+    #[test]
+    fn test_flatten_plan_renames_collisions() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("a/deep")).unwrap();
+        fs::create_dir(root.join("b")).unwrap();
+        fs::write(root.join("notes.txt"), "root").unwrap();
+        fs::write(root.join("a/notes.txt"), "a").unwrap();
+        fs::write(root.join("a/deep/photo.jpg"), "").unwrap();
+        fs::write(root.join("b/notes.txt"), "b").unwrap();
+
+        let plan = FlattenPlan::new(&root.to_string_lossy(), &mut Quiet).unwrap();
+        assert_eq!(plan.moves.len(), 3);
+        assert!(
+            plan.moves
+                .contains(&(root.join("a/deep/photo.jpg"), root.join("photo.jpg")))
+        );
+        // Both notes.txt below the root get a name of their own
+        assert_eq!(plan.collisions.len(), 2);
+        let (first, second) = (&plan.collisions[0].1, &plan.collisions[1].1);
+        assert_ne!(first, second);
+        assert!(second.to_string_lossy().ends_with("_2.txt"));
+
+        plan.apply(false, &mut Quiet).unwrap();
+        remove_empty_dirs(&root.to_string_lossy(), false, &mut Quiet).unwrap();
+        assert_eq!(fs::read_to_string(root.join("notes.txt")).unwrap(), "root");
+        assert_eq!(fs::read_to_string(second).unwrap(), "b");
+        assert!(!root.join("a").exists());
+    }
+}
@@ -0,0 +1,254 @@
+//! Filesystem access behind a trait, so operations can run against memory
+//!
+//! Transform execution goes through [`Vfs`]: the binary's `DiskFs` touches the disk
+//! with the same options as `mv`/`cp`, and [`MemoryFs`] keeps a tree of paths in memory. `--simulate` loads the target
+//! into a `MemoryFs` and runs the real code path against it, so a dry run sees
+//! the same renames (including earlier directory renames moving later items)
+//! that a real run would, and tests can exercise that logic without a temp dir.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// The filesystem operations transforms need
+pub trait Vfs {
+    fn exists(&self, path: &Path) -> bool;
+    fn is_dir(&self, path: &Path) -> bool;
+    /// Rename a file or directory; the destination's parent must exist
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()>;
+    /// Copy a file, creating the destination's parents
+    fn copy(&mut self, from: &Path, to: &Path) -> io::Result<()>;
+    fn create_dir_all(&mut self, path: &Path) -> io::Result<()>;
+    /// Remove a file, or a directory with everything in it
+    fn remove(&mut self, path: &Path) -> io::Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Entry {
+    File { size: u64 },
+    Dir,
+}
+
+/// An in-memory tree of files (with sizes) and directories
+///
+/// `./a` and `a` are the same path; anything above the loaded paths counts as an
+/// existing directory, like the real parents of a snapshot do.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryFs {
+    entries: BTreeMap<PathBuf, Entry>,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load `root` and everything below it from disk
+    pub fn snapshot(root: &Path) -> io::Result<Self> {
+        let mut memory = Self::new();
+        for entry in walkdir::WalkDir::new(root) {
+            let entry = entry.map_err(io::Error::other)?;
+            if entry.file_type().is_dir() {
+                memory.add_dir(entry.path());
+            } else {
+                let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                memory.add_file(entry.path(), size);
+            }
+        }
+        Ok(memory)
+    }
+
+    pub fn add_file(&mut self, path: &Path, size: u64) {
+        self.entries.insert(key(path), Entry::File { size });
+    }
+
+    pub fn add_dir(&mut self, path: &Path) {
+        self.entries.insert(key(path), Entry::Dir);
+    }
+
+    /// Size of the file at `path`
+    pub fn size(&self, path: &Path) -> Option<u64> {
+        match self.entries.get(&key(path)) {
+            Some(Entry::File { size }) => Some(*size),
+            _ => None,
+        }
+    }
+
+    /// Parents inside the loaded tree must exist; ones outside it are assumed to
+    fn parent_exists(&self, path: &Path) -> bool {
+        match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => {
+                self.is_dir(parent)
+                    || !self.exists(parent)
+                        && !self.entries.keys().any(|loaded| parent.starts_with(loaded))
+            }
+            _ => true,
+        }
+    }
+}
+
+impl Vfs for MemoryFs {
+    fn exists(&self, path: &Path) -> bool {
+        self.entries.contains_key(&key(path))
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.entries.get(&key(path)) == Some(&Entry::Dir)
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from, to) = (key(from), key(to));
+        let Some(entry) = self.entries.get(&from).copied() else {
+            return Err(not_found(&from));
+        };
+        if !self.parent_exists(&to) {
+            return Err(not_found(to.parent().unwrap_or(&to)));
+        }
+        if from == to {
+            return Ok(());
+        }
+        if entry == Entry::Dir && to.starts_with(&from) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot move '{}' into itself", from.display()),
+            ));
+        }
+        if let Some(existing) = self.entries.get(&to) {
+            let target_is_empty_dir = *existing == Entry::Dir
+                && !self.entries.keys().any(|p| p != &to && p.starts_with(&to));
+            let file_onto_dir = *existing == Entry::Dir && entry != Entry::Dir;
+            if file_onto_dir || (entry == Entry::Dir && !target_is_empty_dir) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("'{}' exists", to.display()),
+                ));
+            }
+        }
+
+        let moved: Vec<(PathBuf, Entry)> = self
+            .entries
+            .iter()
+            .filter(|(path, _)| path.starts_with(&from))
+            .map(|(path, entry)| (path.clone(), *entry))
+            .collect();
+        for (path, entry) in moved {
+            self.entries.remove(&path);
+            let relative = path.strip_prefix(&from).unwrap_or(Path::new(""));
+            let target = if relative.as_os_str().is_empty() {
+                to.clone()
+            } else {
+                to.join(relative)
+            };
+            self.entries.insert(target, entry);
+        }
+        Ok(())
+    }
+
+    fn copy(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        let size = self.size(from).ok_or_else(|| not_found(from))?;
+        if let Some(parent) = to.parent() {
+            self.create_dir_all(parent)?;
+        }
+        self.add_file(to, size);
+        Ok(())
+    }
+
+    fn create_dir_all(&mut self, path: &Path) -> io::Result<()> {
+        let path = key(path);
+        for dir in path.ancestors().filter(|dir| !dir.as_os_str().is_empty()) {
+            match self.entries.get(dir) {
+                Some(Entry::Dir) => break,
+                Some(Entry::File { .. }) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("'{}' is a file", dir.display()),
+                    ));
+                }
+                None => {
+                    self.entries.insert(dir.to_path_buf(), Entry::Dir);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &Path) -> io::Result<()> {
+        let path = key(path);
+        if !self.entries.contains_key(&path) {
+            return Err(not_found(&path));
+        }
+        self.entries.retain(|entry, _| !entry.starts_with(&path));
+        Ok(())
+    }
+}
+
+/// `path` without `.` components, so `./a` and `a` name the same entry
+fn key(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("'{}' does not exist", path.display()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_memory_rename_moves_children() {
+        let mut memory = MemoryFs::new();
+        memory.add_dir(Path::new("."));
+        memory.add_dir(Path::new("My Photos"));
+        memory.add_file(Path::new("My Photos/Beach Day.jpg"), 10);
+        memory.add_file(Path::new("./notes.txt"), 3);
+
+        memory
+            .rename(Path::new("My Photos"), Path::new("my_photos"))
+            .unwrap();
+        assert!(memory.is_dir(Path::new("./my_photos")));
+        assert_eq!(memory.size(Path::new("my_photos/Beach Day.jpg")), Some(10));
+        assert!(!memory.exists(Path::new("My Photos/Beach Day.jpg")));
+
+        // Renaming into a directory that does not exist fails like the real thing
+        assert_eq!(
+            memory
+                .rename(Path::new("notes.txt"), Path::new("missing/notes.txt"))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+        assert_eq!(
+            memory
+                .rename(Path::new("notes.txt"), Path::new("my_photos"))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::AlreadyExists
+        );
+
+        memory
+            .copy(Path::new("notes.txt"), Path::new("backup/notes.txt"))
+            .unwrap();
+        assert!(memory.is_dir(Path::new("backup")));
+        assert_eq!(memory.size(Path::new("backup/notes.txt")), Some(3));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub/a.txt"), "abc").unwrap();
+
+        let memory = MemoryFs::snapshot(dir.path()).unwrap();
+        assert!(memory.is_dir(&dir.path().join("sub")));
+        assert_eq!(memory.size(&dir.path().join("sub/a.txt")), Some(3));
+        // Renames at the top of the snapshot can still land next to it
+        assert!(memory.parent_exists(&dir.path().join("renamed")));
+    }
+}
@@ -0,0 +1,267 @@
+//! Guided setup of a command for first-time users (`smv wizard`)
+//!
+//! Asks what to do, where and how carefully, then hands back the equivalent
+//! command line. The caller shows it so the syntax can be learned from the
+//! answers, and runs it once confirmed.
+
+use std::io::{self, BufRead, Write};
+
+/// Naming styles offered by the wizard, with an example of each
+pub const STYLES: &[(&str, &str)] = &[
+    ("snake", "my_file_name.txt"),
+    ("kebab", "my-file-name.txt"),
+    ("title", "My File Name.txt"),
+    ("camel", "myFileName.txt"),
+    ("pascal", "MyFileName.txt"),
+    ("lower", "my file name.txt"),
+    ("upper", "MY FILE NAME.txt"),
+    ("clean", "tidy spaces and special characters"),
+];
+
+/// What the user wants to do
+#[derive(Debug, Clone, PartialEq)]
+pub enum Task {
+    Rename { style: String },
+    Replace { old: String, new: String },
+    Group,
+    Flatten,
+    Undo,
+}
+
+/// Everything the wizard asked
+#[derive(Debug, Clone, PartialEq)]
+pub struct Answers {
+    pub task: Task,
+    pub path: String,
+    pub recursive: bool,
+    pub directories: bool,
+    pub preview: bool,
+}
+
+impl Answers {
+    /// The smv arguments (without the program name) doing what was asked
+    pub fn command_line(&self) -> Vec<String> {
+        let mut args: Vec<String> = match &self.task {
+            Task::Rename { style } => vec![style.clone(), self.path.clone()],
+            Task::Replace { old, new } => vec![
+                "CHANGE".to_string(),
+                old.clone(),
+                "INTO".to_string(),
+                new.clone(),
+                self.path.clone(),
+            ],
+            Task::Group => vec!["group".to_string(), self.path.clone()],
+            Task::Flatten => vec!["flatten".to_string(), self.path.clone()],
+            Task::Undo => return vec!["undo".to_string()],
+        };
+
+        let mut flags = String::new();
+        if self.recursive {
+            flags.push('r');
+        }
+        if self.directories {
+            flags.push('e');
+        }
+        if self.preview {
+            flags.push('p');
+        }
+        if !flags.is_empty() {
+            args.push(format!("-{flags}"));
+        }
+        args
+    }
+}
+
+/// Show `args` the way it would be typed into a shell
+pub fn display_command(args: &[String]) -> String {
+    let mut line = String::from("smv");
+    for arg in args {
+        line.push(' ');
+        line.push_str(&quote_arg(arg));
+    }
+    line
+}
+
+/// `arg` as typed into a shell, quoted when it has anything the shell would touch
+pub fn quote_arg(arg: &str) -> String {
+    if arg.is_empty()
+        || arg.contains(|c: char| c.is_whitespace() || "'\"$*?[]{}();&|<>".contains(c))
+    {
+        format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        arg.to_string()
+    }
+}
+
+/// Ask the wizard's questions on `output`, reading answers from `input`
+///
+/// Returns `None` when input ends before every question is answered.
+pub fn ask(input: &mut impl BufRead, output: &mut impl Write) -> io::Result<Option<Answers>> {
+    let mut prompt = Prompt { input, output };
+
+    writeln!(prompt.output, "What do you want to do?")?;
+    writeln!(prompt.output, "  1) Rename files to a naming style")?;
+    writeln!(prompt.output, "  2) Replace text in file names")?;
+    writeln!(prompt.output, "  3) Sort files into folders by type")?;
+    writeln!(prompt.output, "  4) Flatten subfolders into one folder")?;
+    writeln!(prompt.output, "  5) Undo the last operation")?;
+    let Some(choice) = prompt.choose("Choice", 5)? else {
+        return Ok(None);
+    };
+
+    let task = match choice {
+        1 => {
+            writeln!(prompt.output, "\nWhich naming style?")?;
+            for (index, (style, example)) in STYLES.iter().enumerate() {
+                writeln!(prompt.output, "  {}) {:<7} {}", index + 1, style, example)?;
+            }
+            let Some(style) = prompt.choose("Style", STYLES.len())? else {
+                return Ok(None);
+            };
+            Task::Rename {
+                style: STYLES[style - 1].0.to_string(),
+            }
+        }
+        2 => {
+            let Some(old) = prompt.text("Text to replace", None)? else {
+                return Ok(None);
+            };
+            let Some(new) = prompt.text("Replace it with (empty removes it)", Some(""))? else {
+                return Ok(None);
+            };
+            Task::Replace { old, new }
+        }
+        3 => Task::Group,
+        4 => Task::Flatten,
+        _ => {
+            return Ok(Some(Answers {
+                task: Task::Undo,
+                path: String::new(),
+                recursive: false,
+                directories: false,
+                preview: false,
+            }));
+        }
+    };
+
+    let Some(path) = prompt.text("\nWhich folder (or file)?", Some("."))? else {
+        return Ok(None);
+    };
+    let renames = matches!(task, Task::Rename { .. } | Task::Replace { .. });
+    let Some(recursive) = prompt.yes_no("Include subfolders?", false)? else {
+        return Ok(None);
+    };
+    let directories = if renames {
+        let Some(directories) = prompt.yes_no("Rename folders too, not just files?", false)? else {
+            return Ok(None);
+        };
+        directories
+    } else {
+        false
+    };
+    let Some(preview) = prompt.yes_no("Preview first without changing anything?", true)? else {
+        return Ok(None);
+    };
+
+    Ok(Some(Answers {
+        task,
+        path,
+        recursive,
+        directories,
+        preview,
+    }))
+}
+
+struct Prompt<'a, R, W> {
+    input: &'a mut R,
+    output: &'a mut W,
+}
+
+impl<R: BufRead, W: Write> Prompt<'_, R, W> {
+    fn line(&mut self, question: &str) -> io::Result<Option<String>> {
+        write!(self.output, "{question} ")?;
+        self.output.flush()?;
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+    }
+
+    /// A number from 1 to `count`, asked again until one is given
+    fn choose(&mut self, question: &str, count: usize) -> io::Result<Option<usize>> {
+        loop {
+            let Some(answer) = self.line(&format!("{question} [1-{count}]:"))? else {
+                return Ok(None);
+            };
+            match answer.trim().parse::<usize>() {
+                Ok(choice) if (1..=count).contains(&choice) => return Ok(Some(choice)),
+                _ => writeln!(self.output, "Please enter a number from 1 to {count}.")?,
+            }
+        }
+    }
+
+    /// Free text; an empty answer takes `default`, or is asked again without one
+    fn text(&mut self, question: &str, default: Option<&str>) -> io::Result<Option<String>> {
+        loop {
+            let label = match default {
+                Some(default) if !default.is_empty() => format!("{question} [{default}]:"),
+                _ => format!("{question}:"),
+            };
+            let Some(answer) = self.line(&label)? else {
+                return Ok(None);
+            };
+            if !answer.is_empty() {
+                return Ok(Some(answer));
+            }
+            if let Some(default) = default {
+                return Ok(Some(default.to_string()));
+            }
+        }
+    }
+
+    fn yes_no(&mut self, question: &str, default: bool) -> io::Result<Option<bool>> {
+        let hint = if default { "[Y/n]" } else { "[y/N]" };
+        loop {
+            let Some(answer) = self.line(&format!("{question} {hint}"))? else {
+                return Ok(None);
+            };
+            match answer.trim().to_lowercase().as_str() {
+                "" => return Ok(Some(default)),
+                "y" | "yes" => return Ok(Some(true)),
+                "n" | "no" => return Ok(Some(false)),
+                _ => writeln!(self.output, "Please answer y or n.")?,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(answers: &str) -> Option<Answers> {
+        ask(&mut answers.as_bytes(), &mut Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn test_rename_wizard() {
+        // Style 1 (snake), in photos/, subfolders yes, folders default no, preview default yes
+        let answers = run("1\n1\nphotos\ny\n\n\n").unwrap();
+        assert_eq!(answers.command_line(), ["snake", "photos", "-rp"]);
+
+        // Invalid choices are asked again; the folder defaults to "."
+        let answers = run("9\n2\nIMG_\n\n\nn\ny\nn\n").unwrap();
+        assert_eq!(
+            answers.command_line(),
+            ["CHANGE", "IMG_", "INTO", "", ".", "-e"]
+        );
+        assert_eq!(
+            display_command(&answers.command_line()),
+            "smv CHANGE IMG_ INTO \"\" . -e"
+        );
+
+        assert_eq!(run("5\n").unwrap().command_line(), ["undo"]);
+        assert_eq!(run("1\n"), None);
+    }
+}
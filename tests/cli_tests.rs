@@ -5,7 +5,17 @@ use std::path::Path;
 use tempfile::TempDir;
 
 fn smv_cmd() -> Command {
-    Command::cargo_bin("smv").unwrap()
+    let mut cmd = Command::cargo_bin("smv").unwrap();
+    // Tests isolate history through HOME, so ignore state locations from the caller
+    for var in [
+        "SMV_STATE_DIR",
+        "SMV_HOME",
+        "XDG_STATE_HOME",
+        "XDG_DATA_HOME",
+    ] {
+        cmd.env_remove(var);
+    }
+    cmd
 }
 
 #[test]
@@ -661,3 +671,2512 @@ fn test_single_file_transformation_nonexistent() {
         .success()
         .stdout(predicate::str::contains("No files or directories found"));
 }
+
+#[test]
+fn test_update_links_after_rename() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    fs::write(temp_path.join("Old Note.md"), "# Old note\n").unwrap();
+    fs::write(
+        temp_path.join("index.md"),
+        "See [[Old Note]] and [link](Old%20Note.md).\n",
+    )
+    .unwrap();
+
+    // Preview shows the content edit without touching the file
+    smv_cmd()
+        .arg("kebab")
+        .arg(temp_path.to_str().unwrap())
+        .arg("-p")
+        .arg("--update-links")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "[PREVIEW] Update links in index.md",
+        ));
+    assert!(
+        fs::read_to_string(temp_path.join("index.md"))
+            .unwrap()
+            .contains("[[Old Note]]")
+    );
+
+    smv_cmd()
+        .args([
+            "kebab",
+            temp_path.to_str().unwrap(),
+            "-p",
+            "--update-links",
+            "--json",
+        ])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""op":"edit""#));
+
+    smv_cmd()
+        .arg("kebab")
+        .arg(temp_path.to_str().unwrap())
+        .arg("--update-links")
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+
+    assert!(temp_path.join("old-note.md").exists());
+    assert_eq!(
+        fs::read_to_string(temp_path.join("index.md")).unwrap(),
+        "See [[old-note]] and [link](old-note.md).\n"
+    );
+
+    // Undoing the renames puts the old links back with them
+    smv_cmd()
+        .arg("-u")
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("Old Note.md").exists());
+    assert_eq!(
+        fs::read_to_string(temp_path.join("index.md")).unwrap(),
+        "See [[Old Note]] and [link](Old%20Note.md).\n"
+    );
+}
+
+#[test]
+fn test_template_from_front_matter() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    fs::write(
+        temp_path.join("draft.md"),
+        "---\ntitle: My Great Note\ndate: 2024-03-01\n---\nBody\n",
+    )
+    .unwrap();
+    fs::write(temp_path.join("plain.md"), "No front matter\n").unwrap();
+
+    smv_cmd()
+        .arg("template")
+        .arg("{fm:date}-{fm:title|kebab}.md")
+        .arg(temp_path.to_str().unwrap())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No value for {fm:date}"));
+
+    assert!(temp_path.join("2024-03-01-my-great-note.md").exists());
+    assert!(temp_path.join("plain.md").exists());
+}
+
+#[test]
+fn test_tier_moves_large_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let source = temp_dir.path().join("hot");
+    let cold = temp_dir.path().join("cold");
+    fs::create_dir_all(source.join("sub")).unwrap();
+
+    fs::write(source.join("small.txt"), "tiny").unwrap();
+    fs::write(source.join("sub").join("big.bin"), vec![7u8; 4096]).unwrap();
+
+    smv_cmd()
+        .arg("tier")
+        .arg(source.to_str().unwrap())
+        .arg("SIZE>2KB")
+        .arg(format!("TO-DIR:{}", cold.display()))
+        .arg("-r")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Files moved: 1"));
+
+    assert!(source.join("small.txt").exists());
+    assert!(!source.join("sub").join("big.bin").exists());
+    assert_eq!(
+        fs::read(cold.join("sub").join("big.bin")).unwrap().len(),
+        4096
+    );
+}
+
+#[test]
+fn test_where_clause_grouping() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    for name in ["a.log", "b.tmp", "keep.log", "c.md"] {
+        fs::write(temp_path.join(name), "x").unwrap();
+    }
+
+    smv_cmd()
+        .arg("rm")
+        .arg(temp_path.to_str().unwrap())
+        .arg("WHERE")
+        .arg("(EXT:log OR EXT:tmp) AND NOT NAME:keep")
+        .arg("-f")
+        .assert()
+        .success();
+
+    assert!(!temp_path.join("a.log").exists());
+    assert!(!temp_path.join("b.tmp").exists());
+    assert!(temp_path.join("keep.log").exists());
+    assert!(temp_path.join("c.md").exists());
+}
+
+#[test]
+fn test_name_regex_filters_in_and_out_of_where() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    for name in [
+        "IMG_0001.jpg",
+        "IMG_0002_draft.jpg",
+        "IMG_notes.txt",
+        "cover.jpg",
+    ] {
+        fs::write(temp_path.join(name), "x").unwrap();
+    }
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args([
+            "rm",
+            ".",
+            r"NAME~:^IMG_\d+",
+            "WHERE",
+            "NOT NAME~:draft",
+            "-f",
+        ])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+
+    assert!(!temp_path.join("IMG_0001.jpg").exists());
+    assert!(temp_path.join("IMG_0002_draft.jpg").exists());
+    assert!(temp_path.join("IMG_notes.txt").exists());
+    assert!(temp_path.join("cover.jpg").exists());
+}
+
+#[test]
+fn test_stdin_ops_executes_plan() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("a.txt"), "a").unwrap();
+    fs::write(temp_path.join("b.txt"), "b").unwrap();
+    fs::write(temp_path.join("taken.txt"), "taken").unwrap();
+
+    let plan = format!(
+        "{}\t{}\n{{\"op\": \"move\", \"from\": \"{}\", \"to\": \"{}\"}}\n",
+        temp_path.join("a.txt").display(),
+        temp_path.join("sub").join("a.txt").display(),
+        temp_path.join("b.txt").display(),
+        temp_path.join("taken.txt").display(),
+    );
+
+    smv_cmd()
+        .arg("--stdin-ops")
+        .env("HOME", temp_path)
+        .write_stdin(plan)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("destination exists"));
+
+    assert!(temp_path.join("sub").join("a.txt").exists());
+    assert!(temp_path.join("b.txt").exists());
+    assert_eq!(
+        fs::read_to_string(temp_path.join("taken.txt")).unwrap(),
+        "taken"
+    );
+}
+
+#[test]
+fn test_forced_plan_backs_up_what_it_overwrites() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::create_dir(temp_path.join("sub")).unwrap();
+    fs::write(temp_path.join("a.txt"), "new").unwrap();
+    fs::write(temp_path.join("sub/x.txt"), "old").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["--stdin-ops", "-F"])
+        .env("HOME", temp_path)
+        .write_stdin("a.txt\tsub/x.txt\n")
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(temp_path.join("sub/x.txt")).unwrap(),
+        "new"
+    );
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["-u", "-F"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(temp_path.join("a.txt")).unwrap(), "new");
+    assert_eq!(
+        fs::read_to_string(temp_path.join("sub/x.txt")).unwrap(),
+        "old"
+    );
+}
+
+#[test]
+fn test_undo_select_reverts_part_of_batch() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    let mut plan = String::new();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::write(temp_path.join(name), name).unwrap();
+        plan.push_str(&format!(
+            "{}\t{}\n",
+            temp_path.join(name).display(),
+            temp_path.join("out").join(name).display()
+        ));
+    }
+
+    smv_cmd()
+        .arg("--stdin-ops")
+        .env("HOME", temp_path)
+        .write_stdin(plan)
+        .assert()
+        .success();
+
+    smv_cmd()
+        .args(["undo", "--select", "1,3"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Reverted 2 of 2"));
+
+    assert!(temp_path.join("a.txt").exists());
+    assert!(temp_path.join("out").join("b.txt").exists());
+    assert!(temp_path.join("c.txt").exists());
+
+    // The partial undo is its own entry: undoing it moves the files forward again
+    smv_cmd()
+        .arg("-u")
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("out").join("a.txt").exists());
+    assert!(temp_path.join("out").join("c.txt").exists());
+}
+
+#[test]
+fn test_transform_into_dir() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    let inbox = temp_path.join("inbox");
+    let archive = temp_path.join("archive");
+    fs::create_dir_all(inbox.join("sub")).unwrap();
+    fs::create_dir_all(&archive).unwrap();
+    fs::write(inbox.join("My File.txt"), "a").unwrap();
+    fs::write(inbox.join("sub").join("Other Doc.md"), "b").unwrap();
+    fs::write(inbox.join("Taken One.txt"), "new").unwrap();
+    fs::write(archive.join("taken_one.txt"), "old").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", "inbox/", "INTO-DIR:archive/", "-r"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("destination exists"));
+
+    assert!(archive.join("my_file.txt").exists());
+    assert!(archive.join("sub").join("other_doc.md").exists());
+    assert!(inbox.join("Taken One.txt").exists());
+    assert_eq!(
+        fs::read_to_string(archive.join("taken_one.txt")).unwrap(),
+        "old"
+    );
+
+    smv_cmd()
+        .arg("-u")
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(inbox.join("My File.txt").exists());
+    assert!(inbox.join("sub").join("Other Doc.md").exists());
+}
+
+#[test]
+fn test_transform_copy_keeps_originals() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    let data = temp_path.join("data");
+    fs::create_dir_all(data.join("raw")).unwrap();
+    fs::write(data.join("Report One.csv"), "a").unwrap();
+    fs::write(data.join("raw").join("Sample A.csv"), "b").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", "data/", "INTO-DIR:export/", "-r", "--copy"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+
+    assert!(data.join("Report One.csv").exists());
+    assert!(data.join("raw").join("Sample A.csv").exists());
+    let export = temp_path.join("export");
+    assert!(export.join("report_one.csv").exists());
+    assert_eq!(
+        fs::read_to_string(export.join("raw").join("sample_a.csv")).unwrap(),
+        "b"
+    );
+}
+
+#[test]
+fn test_limit_caps_matches() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    for name in ["Alpha One.txt", "Beta Two.txt", "Gamma Three.txt"] {
+        fs::write(temp_path.join(name), "x").unwrap();
+    }
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "LIMIT:2"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(2 of 3 matches)"));
+
+    let renamed = fs::read_dir(temp_path)
+        .unwrap()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_string_lossy().contains('_'))
+        .count();
+    assert_eq!(renamed, 2);
+}
+
+#[test]
+fn test_filter_options() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("New Report.pdf"), "x").unwrap();
+    fs::write(temp_path.join("New Notes.txt"), "x").unwrap();
+    let old = fs::File::create(temp_path.join("Old Report.pdf")).unwrap();
+    old.set_modified(std::time::SystemTime::now() - std::time::Duration::from_secs(90 * 86400))
+        .unwrap();
+    drop(old);
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["kebab", ".", "--newer-than", "30d", "--ext", "pdf"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+
+    assert!(temp_path.join("new-report.pdf").exists());
+    assert!(temp_path.join("Old Report.pdf").exists());
+    assert!(temp_path.join("New Notes.txt").exists());
+}
+
+#[test]
+fn test_extension_list_and_negation() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    for name in ["Photo One.jpg", "Photo Two.png", "Scratch File.tmp"] {
+        fs::write(temp_path.join(name), "x").unwrap();
+    }
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "EXT:jpg", "EXT:png"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("photo_one.jpg").exists());
+    assert!(temp_path.join("photo_two.png").exists());
+    assert!(temp_path.join("Scratch File.tmp").exists());
+
+    fs::write(temp_path.join("Other Note.md"), "x").unwrap();
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["kebab", ".", "EXT:!tmp"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("other-note.md").exists());
+    assert!(temp_path.join("Scratch File.tmp").exists());
+}
+
+#[test]
+fn test_empty_filter_selects_empty_entries() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::create_dir(temp_path.join("Empty Dir")).unwrap();
+    fs::write(temp_path.join("Blank File.txt"), "").unwrap();
+    fs::write(temp_path.join("Full File.txt"), "content").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "EMPTY:true", "-p"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Blank File.txt"))
+        .stdout(predicate::str::contains("Empty Dir"))
+        .stdout(predicate::str::contains("Full File.txt").not());
+}
+
+#[test]
+fn test_copy_parents_and_strip_components() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::create_dir_all(temp_path.join("shoots/day1/raw")).unwrap();
+    fs::write(temp_path.join("shoots/day1/raw/a.cr2"), "a").unwrap();
+    fs::create_dir_all(temp_path.join("backup")).unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["cp", "--parents", "shoots/day1/raw/a.cr2", "backup/"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("backup/shoots/day1/raw/a.cr2").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args([
+            "mv",
+            "--strip-components",
+            "2",
+            "shoots/day1/raw/a.cr2",
+            "flat/",
+        ])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("flat/raw/a.cr2").exists());
+    assert!(!temp_path.join("shoots/day1/raw/a.cr2").exists());
+}
+
+#[test]
+fn test_filtered_copy_preserve_structure() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::create_dir_all(temp_path.join("shoot/day1")).unwrap();
+    fs::create_dir_all(temp_path.join("shoot/day2")).unwrap();
+    fs::write(temp_path.join("shoot/day1/img.raw"), "1").unwrap();
+    fs::write(temp_path.join("shoot/day2/img.raw"), "2").unwrap();
+    fs::write(temp_path.join("shoot/day2/img.jpg"), "j").unwrap();
+
+    // Flat copies of same-named files collide; the second is reported, not overwritten
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["cp", "shoot", "EXT:raw", "-r", "flat"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Conflict"));
+    assert!(temp_path.join("flat/img.raw").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args([
+            "cp",
+            "shoot",
+            "EXT:raw",
+            "-r",
+            "backup",
+            "--preserve-structure",
+        ])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(temp_path.join("backup/day2/img.raw")).unwrap(),
+        "2"
+    );
+    assert!(temp_path.join("backup/day1/img.raw").exists());
+    assert!(!temp_path.join("backup/day2/img.jpg").exists());
+    assert!(temp_path.join("shoot/day1/img.raw").exists());
+}
+
+#[test]
+fn test_mkpath_creates_and_undo_removes_destination() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("notes.txt"), "notes").unwrap();
+
+    // Without --mkpath (and no terminal to ask on) a missing directory is an error
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["mv", "notes.txt", "archive/2024/"])
+        .env("HOME", temp_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--mkpath"));
+    assert!(temp_path.join("notes.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["mv", "notes.txt", "archive/2024/", "--mkpath"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("archive/2024/notes.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .arg("-u")
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("notes.txt").exists());
+    assert!(!temp_path.join("archive").exists());
+}
+
+#[test]
+fn test_mv_destination_patterns() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    for n in 1..=3 {
+        fs::write(temp_path.join(format!("report_{n}.pdf")), n.to_string()).unwrap();
+    }
+    fs::write(temp_path.join("app.log"), "app").unwrap();
+    fs::write(temp_path.join("db.log"), "db").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["mv", "report_{1..3}.pdf", "reports/{01..03}/"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(temp_path.join("reports/02/report_2.pdf")).unwrap(),
+        "2"
+    );
+    assert!(temp_path.join("reports/03/report_3.pdf").exists());
+
+    // Mismatched counts are refused before anything moves
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["mv", "app.log", "db.log", "logs/{a,b,c}/"])
+        .env("HOME", temp_path)
+        .assert()
+        .failure();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["mv", "app.log", "db.log", "logs/{}.archived.log"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("logs/app.archived.log").exists());
+    assert!(temp_path.join("logs/db.archived.log").exists());
+}
+
+#[test]
+fn test_wizard_builds_and_runs_command() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("My Document.txt"), "").unwrap();
+
+    // Snake case in ".", no subfolders, files only, no preview, then run it
+    smv_cmd()
+        .current_dir(temp_path)
+        .arg("wizard")
+        .write_stdin("1\n1\n\nn\nn\nn\ny\n")
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("smv snake ."));
+    assert!(temp_path.join("my_document.txt").exists());
+}
+
+#[test]
+fn test_simulate_leaves_disk_untouched() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::create_dir(temp_path.join("My Photos")).unwrap();
+    fs::write(temp_path.join("My Photos/Beach Day.jpg"), "").unwrap();
+
+    // Like a real run, the file is renamed before the directory it is in
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "-re", "--simulate"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Simulate Mode"))
+        .stdout(predicate::str::contains("\"My Photos\" → \"my_photos\""))
+        .stdout(predicate::str::contains(
+            "\"Beach Day.jpg\" → \"beach_day.jpg\"",
+        ));
+    assert!(temp_path.join("My Photos/Beach Day.jpg").exists());
+    assert!(!temp_path.join("my_photos").exists());
+}
+
+#[test]
+fn test_unusable_names_are_guarded() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("***.png"), "").unwrap();
+
+    // Removing the stars would leave a hidden ".png"; the name is kept by default
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["REGEX", "^\\*+", "INTO", "", "."])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("would become a hidden file"));
+    assert!(temp_path.join("***.png").exists());
+    assert!(!temp_path.join(".png").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args([
+            "REGEX",
+            "^\\*+",
+            "INTO",
+            "",
+            ".",
+            "--name-fallback",
+            "placeholder",
+        ])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("untitled.png").exists());
+}
+
+#[test]
+fn test_transform_verbose_reports_renames() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("My Notes.txt"), "").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "-v"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("renamed '"))
+        .stderr(predicate::str::contains("my_notes.txt'"));
+    assert!(temp_path.join("my_notes.txt").exists());
+}
+
+#[test]
+fn test_copy_progress_events() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::create_dir_all(temp_path.join("photos/2019")).unwrap();
+    fs::write(temp_path.join("photos/a.jpg"), "a").unwrap();
+    fs::write(temp_path.join("photos/2019/b.jpg"), "b").unwrap();
+
+    let output = smv_cmd()
+        .current_dir(temp_path)
+        .args(["cp", "photos", "backup", "-r", "--output", "json"])
+        .env("HOME", temp_path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let events: Vec<serde_json::Value> = String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    assert_eq!(events.first().unwrap()["event"], "start");
+    assert_eq!(events.first().unwrap()["total"], 2);
+    assert!(
+        events
+            .iter()
+            .any(|event| event["event"] == "directory" && event["dir"] == "photos/2019")
+    );
+    let finish = events.last().unwrap();
+    assert_eq!(finish["event"], "finish");
+    assert_eq!(finish["done"], 2);
+    assert_eq!(finish["errors"], 0);
+    assert!(temp_path.join("backup/2019/b.jpg").exists());
+}
+
+#[test]
+fn test_stdin_ops_json_preview_conflict_codes() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("a.txt"), "a").unwrap();
+    fs::write(temp_path.join("b.txt"), "b").unwrap();
+    fs::write(temp_path.join("Report.txt"), "r").unwrap();
+
+    let plan = "a.txt\tb.txt\nmissing.txt\tc.txt\na.txt\treport.txt\na.txt\tnew.txt\n";
+    let output = smv_cmd()
+        .current_dir(temp_path)
+        .args(["--stdin-ops", "-p", "--output", "json"])
+        .env("HOME", temp_path)
+        .write_stdin(plan)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // Every stdout line is a record; no prose to parse around
+    let records: Vec<serde_json::Value> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let codes: Vec<_> = records
+        .iter()
+        .map(|record| record["code"].clone())
+        .collect();
+    assert_eq!(
+        codes,
+        [
+            "TARGET_EXISTS".into(),
+            "SOURCE_MISSING".into(),
+            "CASE_INSENSITIVE_COLLISION".into(),
+            serde_json::Value::Null,
+            serde_json::Value::Null,
+        ]
+    );
+    assert_eq!(records[0]["event"], "conflict");
+    assert_eq!(records[3]["event"], "planned");
+    assert_eq!(records[4]["event"], "stats");
+    assert!(temp_path.join("a.txt").exists());
+}
+
+#[test]
+fn test_rm_then_undo_restores_tree() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::create_dir_all(temp_path.join("old/logs")).unwrap();
+    fs::write(temp_path.join("old/logs/app.log"), "log").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["rm", "old", "-r"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(!temp_path.join("old").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .arg("-u")
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Restored 1 of 1"));
+    assert_eq!(
+        fs::read_to_string(temp_path.join("old/logs/app.log")).unwrap(),
+        "log"
+    );
+
+    // --permanent leaves nothing to restore
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["rm", "old", "-r", "--permanent"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    smv_cmd()
+        .current_dir(temp_path)
+        .arg("-u")
+        .env("HOME", temp_path)
+        .assert()
+        .failure();
+    assert!(!temp_path.join("old").exists());
+}
+
+#[test]
+fn test_shell_init_widget() {
+    smv_cmd()
+        .args(["shell-init", "zsh"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("bindkey '^Xs' _smv_rename_word"));
+
+    smv_cmd()
+        .args(["shell-init", "name", "kebab", "notes/My Draft.md"])
+        .assert()
+        .success()
+        .stdout("notes/my-draft.md\n");
+
+    smv_cmd().args(["shell-init", "fish"]).assert().failure();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pick_narrows_matches_through_fzf() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    fs::write(temp_path.join("Keep Me.txt"), "").unwrap();
+    fs::write(temp_path.join("Leave Me.txt"), "").unwrap();
+
+    // A stand-in fzf that picks every line mentioning "Keep"
+    let bin = temp_path.join("bin");
+    fs::create_dir(&bin).unwrap();
+    let fzf = bin.join("fzf");
+    fs::write(&fzf, "#!/bin/sh\ngrep Keep\n").unwrap();
+    fs::set_permissions(&fzf, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "EXT:txt", "--pick"])
+        .env("HOME", temp_path)
+        .env("PATH", path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Picked: 1 of 2 matches"));
+    assert!(temp_path.join("keep_me.txt").exists());
+    assert!(temp_path.join("Leave Me.txt").exists());
+}
+
+#[test]
+fn test_rm_confirmation_summarizes_sizes() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    fs::write(temp_path.join("big.log"), vec![0u8; 2048]).unwrap();
+    fs::write(temp_path.join("small.log"), "x").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["rm", ".", "EXT:log", "--show", "1"])
+        .env("HOME", temp_path)
+        .write_stdin("no\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Total: 2.0 KB in 2 files"))
+        .stdout(predicate::str::contains(".log"))
+        .stdout(predicate::str::contains("big.log"))
+        .stdout(predicate::str::contains("Operation cancelled"));
+    assert!(temp_path.join("small.log").exists());
+}
+
+#[test]
+fn test_copy_one_file_system_stays_on_device() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    fs::create_dir_all(temp_path.join("src/nested")).unwrap();
+    fs::write(temp_path.join("src/nested/a.txt"), "a").unwrap();
+
+    // Everything is on one filesystem here, so -x copies the whole tree
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["cp", "src", "dest", "-r", "-x"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(temp_path.join("dest/nested/a.txt")).unwrap(),
+        "a"
+    );
+}
+
+#[test]
+fn test_copy_into_own_subdirectory_skips_destination() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    let work = temp_path.join("work");
+    fs::create_dir_all(work.join("sub")).unwrap();
+    fs::create_dir(work.join("backup")).unwrap();
+    fs::write(work.join("a.txt"), "a").unwrap();
+    fs::write(work.join("sub/b.txt"), "b").unwrap();
+
+    smv_cmd()
+        .current_dir(&work)
+        .args(["cp", ".", "backup", "-r"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("it is the destination"));
+    assert!(work.join("backup/a.txt").exists());
+    assert!(work.join("backup/sub/b.txt").exists());
+    assert!(!work.join("backup/backup").exists());
+
+    // The destination may also be created by the copy itself
+    smv_cmd()
+        .current_dir(&work)
+        .args(["cp", "sub", "sub/nested/copy", "-r"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(work.join("sub/nested/copy/b.txt").exists());
+    assert!(!work.join("sub/nested/copy/nested/copy").exists());
+}
+
+#[test]
+fn test_duplicate_sources_processed_once() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    fs::write(temp_path.join("a.txt"), "a").unwrap();
+    fs::create_dir(temp_path.join("dest")).unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["mv", "a.txt", "./a.txt", "*.txt", "dest"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("ignoring 2 duplicate source(s)"))
+        .stdout(predicate::str::contains("Files moved: 1"))
+        .stdout(predicate::str::contains("Errors: 0"));
+    assert!(temp_path.join("dest/a.txt").exists());
+}
+
+#[test]
+fn test_transform_reports_skip_reasons() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    fs::write(temp_path.join("Needs Rename.txt"), "").unwrap();
+    fs::write(temp_path.join("already_snake.txt"), "").unwrap();
+    fs::write(temp_path.join(".Hidden File"), "").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "-p", "-v"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Items skipped: 1"))
+        .stdout(predicate::str::contains("name already right"))
+        .stdout(predicate::str::contains("hidden (use -a)"));
+
+    let output = smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "-p", "--output", "json"])
+        .env("HOME", temp_path)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // Every line is a record; the totals close the report
+    assert!(stdout.starts_with('{'), "{stdout}");
+    let summary: serde_json::Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    assert_eq!(summary["event"], "stats");
+    assert_eq!(summary["skip_reasons"]["no_change"], 1);
+    assert_eq!(summary["filtered"]["hidden"], 1);
+}
+
+#[test]
+fn test_repl_apply_last_runs_previewed_plan() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    fs::write(temp_path.join("My Notes.md"), "").unwrap();
+    fs::write(temp_path.join("Other File.txt"), "").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .arg("-I")
+        .env("HOME", temp_path)
+        .write_stdin("show\npreview snake *.md\nshow\napply last\ny\n")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Nothing previewed yet"))
+        .stdout(predicate::str::contains("Previewed:"))
+        .stdout(predicate::str::contains("1 successfully renamed"));
+
+    assert!(temp_path.join("my_notes.md").exists());
+    assert!(temp_path.join("Other File.txt").exists());
+}
+
+#[test]
+fn test_repl_directory_stack() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    fs::create_dir_all(temp_path.join("inbox")).unwrap();
+    fs::create_dir_all(temp_path.join("archive")).unwrap();
+    fs::write(temp_path.join("inbox/New Photo.jpg"), "").unwrap();
+
+    // Hop into the inbox, rename there, come back and swap with the archive
+    smv_cmd()
+        .current_dir(temp_path)
+        .arg("-I")
+        .env("HOME", temp_path)
+        .write_stdin(
+            "pushd inbox\napply snake *.jpg\ny\npopd\npushd archive\npushd\ndirs\npopd\npopd\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 successfully renamed"))
+        .stderr(predicate::str::contains("The directory stack is empty"));
+
+    assert!(temp_path.join("inbox/new_photo.jpg").exists());
+}
+
+#[test]
+fn test_repl_runs_scripts() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    fs::create_dir_all(temp_path.join("inbox")).unwrap();
+    fs::write(temp_path.join("inbox/New Photo.jpg"), "").unwrap();
+    fs::write(temp_path.join("My Notes.md"), "").unwrap();
+    fs::write(
+        temp_path.join("tidy.smv"),
+        "# rename the inbox\ncd inbox\n\napply snake *.jpg\ncd ..\nsource notes.smv\n",
+    )
+    .unwrap();
+    fs::write(temp_path.join("notes.smv"), "apply kebab *.md\n").unwrap();
+
+    // -F answers the confirmations of every command, sourced ones included
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["-I", "--script", "tidy.smv", "-F"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("smv> apply snake *.jpg"));
+    assert!(temp_path.join("inbox/new_photo.jpg").exists());
+    assert!(temp_path.join("my-notes.md").exists());
+
+    // From the prompt, a failing command stops the script at its line
+    fs::write(temp_path.join("broken.smv"), "ls\nfrobnicate\nls\n").unwrap();
+    smv_cmd()
+        .current_dir(temp_path)
+        .arg("-I")
+        .env("HOME", temp_path)
+        .write_stdin("source broken.smv\n")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "broken.smv:2: Unknown command: frobnicate",
+        ));
+}
+
+#[test]
+fn test_copy_names_reports_what_it_could_not_copy() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    fs::write(temp_path.join("My File.txt"), "").unwrap();
+
+    // A preview renames nothing, so there is nothing to copy
+    smv_cmd()
+        .args(["snake", temp_path.to_str().unwrap(), "-p", "--copy-names"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("nothing copied"));
+
+    // Without a clipboard tool the rename still succeeds, with a warning
+    smv_cmd()
+        .args(["snake", temp_path.to_str().unwrap(), "-F", "--copy-names"])
+        .env("HOME", temp_path)
+        .env("PATH", "")
+        .env_remove("WAYLAND_DISPLAY")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("no clipboard tool found"));
+    assert!(temp_path.join("my_file.txt").exists());
+}
+
+#[test]
+fn test_undo_reports_json_events() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    fs::write(temp_path.join("a.log"), "").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["rm", "a.log"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+
+    let output = smv_cmd()
+        .current_dir(temp_path)
+        .args(["-u", "--output", "json"])
+        .env("HOME", temp_path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert!(events.len() >= 2);
+    assert!(events.iter().all(|event| event["event"] == "message"));
+    assert_eq!(
+        events.last().unwrap()["text"],
+        "Operation undone successfully."
+    );
+    assert!(
+        events[0]["text"]
+            .as_str()
+            .unwrap()
+            .contains("Restored 1 of 1")
+    );
+    assert!(temp_path.join("a.log").exists());
+}
+
+#[test]
+fn test_detect_suggests_commands_for_outliers() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    let docs = temp_path.join("docs");
+    fs::create_dir(&docs).unwrap();
+    for name in ["setup-guide.md", "release-notes.md", "My Notes.md"] {
+        fs::write(docs.join(name), "").unwrap();
+    }
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["detect", "docs"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Dominant: kebab, except 1: \"My Notes.md\"",
+        ))
+        .stdout(predicate::str::contains("smv kebab docs"));
+
+    // The suggested command renames only the outlier
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["kebab", "docs"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(docs.join("my-notes.md").exists());
+    assert!(docs.join("setup-guide.md").exists());
+}
+
+#[test]
+fn test_large_preview_is_sampled() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    for n in 0..1001 {
+        fs::write(temp_path.join(format!("IMG_{n:04}.jpg")), "").unwrap();
+    }
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["CHANGE", "IMG_", "INTO", "", ".", "-p"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Showing a random sample of 25 of 1,001 renames",
+        ))
+        .stdout(predicate::str::contains("1,001 files: IMG_#### → ####"));
+
+    let output = smv_cmd()
+        .current_dir(temp_path)
+        .args(["CHANGE", "IMG_", "INTO", "", ".", "-p", "--full"])
+        .env("HOME", temp_path)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.matches("[PREVIEW] Rename").count(), 1001);
+    assert!(temp_path.join("IMG_0000.jpg").exists());
+}
+
+#[test]
+fn test_results_group_renames_by_kind_of_change() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    for name in ["IMG_0001.jpg", "IMG_0002.jpg", "notes IMG_x.txt"] {
+        fs::write(temp_path.join(name), "").unwrap();
+    }
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["CHANGE", "IMG_", "INTO", "", "."])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("prefix IMG_ removed: 2 files"))
+        .stdout(predicate::str::contains("IMG_ removed: 1 file\n"));
+    assert!(temp_path.join("0001.jpg").exists());
+}
+
+#[test]
+fn test_on_error_abort_and_rollback() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::write(temp_path.join(name), name).unwrap();
+    }
+    // A directory in the way makes the move of b.txt fail
+    fs::create_dir_all(temp_path.join("dest/b.txt/x")).unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args([
+            "mv",
+            "a.txt",
+            "b.txt",
+            "c.txt",
+            "dest/",
+            "--on-error",
+            "rollback",
+        ])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("rolled back 1 completed item"));
+    assert!(temp_path.join("a.txt").exists());
+    assert!(!temp_path.join("dest/a.txt").exists());
+    assert!(temp_path.join("c.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args([
+            "mv",
+            "a.txt",
+            "b.txt",
+            "c.txt",
+            "dest/",
+            "--on-error",
+            "abort",
+        ])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("dest/a.txt").exists());
+    assert!(temp_path.join("c.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["mv", "c.txt", "dest/", "--on-error", "later"])
+        .env("HOME", temp_path)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_directory_allow_ext_refuses_other_types() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    let pictures = temp_path.join("Pictures");
+    fs::create_dir(&pictures).unwrap();
+    fs::write(pictures.join("Beach Day.jpg"), "jpg").unwrap();
+    fs::write(pictures.join("Setup Notes.txt"), "txt").unwrap();
+    fs::write(temp_path.join("installer.exe"), "exe").unwrap();
+    let config = temp_path.join("config.toml");
+    fs::write(
+        &config,
+        "[dir.\"~/Pictures\"]\nallow_ext = [\"jpg\", \"png\", \"dng\"]\n",
+    )
+    .unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", "Pictures"])
+        .env("HOME", temp_path)
+        .env("SMV_CONFIG", &config)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Setup Notes.txt"))
+        .stderr(predicate::str::contains("--any-ext"));
+    assert!(pictures.join("Beach Day.jpg").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["mv", "installer.exe", "Pictures/"])
+        .env("HOME", temp_path)
+        .env("SMV_CONFIG", &config)
+        .assert()
+        .failure();
+    assert!(temp_path.join("installer.exe").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", "Pictures", "--any-ext"])
+        .env("HOME", temp_path)
+        .env("SMV_CONFIG", &config)
+        .assert()
+        .success();
+    assert!(pictures.join("beach_day.jpg").exists());
+    assert!(pictures.join("setup_notes.txt").exists());
+}
+
+#[test]
+fn test_doctor_reports_environment() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["doctor", "--env"])
+        .env("HOME", temp_path)
+        .env("SMV_STATE_DIR", temp_path.join("state"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("state directory"))
+        .stdout(predicate::str::contains("case sensitivity"));
+
+    // A state directory that cannot be created is a problem worth failing on
+    fs::write(temp_path.join("blocked"), "").unwrap();
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["doctor", "--env", "--output", "json"])
+        .env("HOME", temp_path)
+        .env("SMV_STATE_DIR", temp_path.join("blocked/state"))
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("\"status\":\"fail\""));
+    // Probes leave nothing behind
+    assert_eq!(fs::read_dir(temp_path).unwrap().count(), 2);
+}
+
+#[test]
+fn test_tool_delegation_preview_spawns_nothing() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    for n in 1..=12 {
+        fs::write(temp_path.join(format!("photo {n}.jpg")), "").unwrap();
+    }
+
+    // The tool is not installed; a preview must not try to start it
+    smv_cmd()
+        .current_dir(temp_path)
+        .args([".", "EXT:jpg", "TO:not-a-real-tool:--min,1 MB", "-p"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Would run: not-a-real-tool . --min \"1 MB\"",
+        ))
+        .stdout(predicate::str::contains("Standard input: 12 paths"))
+        .stdout(predicate::str::contains("...and 2 more"));
+}
+
+#[test]
+fn test_tool_delegation_can_be_disabled() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("a.txt"), "").unwrap();
+    let config = temp_path.join("config.toml");
+    fs::write(&config, "[tools]\ndelegation = false\n").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args([".", "EXT:txt", "TO:cat"])
+        .env("HOME", temp_path)
+        .env("SMV_CONFIG", &config)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("delegation is disabled"));
+}
+
+#[test]
+fn test_smvignore_protects_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(
+        temp_path.join(".smvignore"),
+        "# hand-named\nKeep This.txt\n",
+    )
+    .unwrap();
+    fs::write(temp_path.join("Keep This.txt"), "keep").unwrap();
+    fs::write(temp_path.join("Other File.txt"), "other").unwrap();
+    fs::create_dir(temp_path.join("dest")).unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", "."])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Protected: \"./Keep This.txt\""));
+    assert!(temp_path.join("Keep This.txt").exists());
+    assert!(temp_path.join("other_file.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["mv", "Keep This.txt", "other_file.txt", "dest/"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("Keep This.txt").exists());
+    assert!(temp_path.join("dest/other_file.txt").exists());
+}
+
+#[test]
+fn test_where_did_it_go() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("Quarterly Report.txt"), "q3").unwrap();
+    let step = |from: &str, to: &str| {
+        format!(
+            "{}\t{}\n",
+            temp_path.join(from).display(),
+            temp_path.join(to).display()
+        )
+    };
+
+    for plan in [
+        step("Quarterly Report.txt", "quarterly_report.txt"),
+        step("quarterly_report.txt", "archive/quarterly_report.txt"),
+    ] {
+        smv_cmd()
+            .arg("--stdin-ops")
+            .env("HOME", temp_path)
+            .write_stdin(plan)
+            .assert()
+            .success();
+    }
+
+    smv_cmd()
+        .args(["where-did-it-go", "quarterly report"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("renamed"))
+        .stdout(predicate::str::contains("moved"))
+        .stdout(predicate::str::contains("archive/quarterly_report.txt"));
+
+    smv_cmd()
+        .args(["where-did-it-go", "nothing-like-it"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Nothing in the history"));
+}
+
+#[test]
+fn test_undo_preview() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    let mut plan = String::new();
+    for name in ["a.txt", "b.txt"] {
+        fs::write(temp_path.join(name), name).unwrap();
+        plan.push_str(&format!(
+            "{}\t{}\n",
+            temp_path.join(name).display(),
+            temp_path.join("out").join(name).display()
+        ));
+    }
+    smv_cmd()
+        .arg("--stdin-ops")
+        .env("HOME", temp_path)
+        .write_stdin(plan)
+        .assert()
+        .success();
+    fs::write(temp_path.join("out/b.txt"), "edited").unwrap();
+
+    smv_cmd()
+        .args(["undo", "-p"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Would move back"))
+        .stdout(predicate::str::contains("modified since the move"))
+        .stdout(predicate::str::contains("1 of 2 files would be undone"));
+    assert!(temp_path.join("out/a.txt").exists());
+
+    smv_cmd()
+        .args(["-u", "-p", "--output", "json"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"action\":\"move-back\""));
+    assert!(!temp_path.join("a.txt").exists());
+}
+
+#[test]
+fn test_preset_save_and_run() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::create_dir(temp_path.join("pics")).unwrap();
+    fs::write(temp_path.join("pics/Holiday Photo.jpg"), "jpg").unwrap();
+    fs::write(temp_path.join("pics/Read Me.txt"), "txt").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args([
+            "preset",
+            "save",
+            "photos-cleanup",
+            "--description",
+            "Camera dumps",
+            "snake",
+            "pics",
+            "EXT:jpg",
+        ])
+        .env("SMV_HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Saved preset"));
+    assert!(temp_path.join("presets.toml").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["preset", "list"])
+        .env("SMV_HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("photos-cleanup"))
+        .stdout(predicate::str::contains("Camera dumps"))
+        .stdout(predicate::str::contains("smv snake pics EXT:jpg"));
+
+    // Options after the name are added to the saved command
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["preset", "run", "photos-cleanup", "-p"])
+        .env("SMV_HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("holiday_photo.jpg"));
+    assert!(temp_path.join("pics/Holiday Photo.jpg").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["preset", "run", "photos-cleanup"])
+        .env("SMV_HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("pics/holiday_photo.jpg").exists());
+    assert!(temp_path.join("pics/Read Me.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["preset", "run", "missing"])
+        .env("SMV_HOME", temp_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown preset"));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_attr_changes_permissions_and_undo_restores_them() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::create_dir(temp_path.join("bin")).unwrap();
+    for name in ["bin/build.sh", "bin/notes.txt"] {
+        fs::write(temp_path.join(name), "").unwrap();
+        fs::set_permissions(temp_path.join(name), fs::Permissions::from_mode(0o644)).unwrap();
+    }
+    let mode = |name: &str| {
+        fs::metadata(temp_path.join(name))
+            .unwrap()
+            .permissions()
+            .mode()
+            & 0o777
+    };
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["attr", ".", "EXT:sh", "PERM:+x", "-r", "-p"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("rwxr-xr-x (755)"));
+    assert_eq!(mode("bin/build.sh"), 0o644);
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["attr", ".", "EXT:sh", "PERM:+x", "-r"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert_eq!(mode("bin/build.sh"), 0o755);
+    assert_eq!(mode("bin/notes.txt"), 0o644);
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["undo", "-p"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Would restore the previous permissions",
+        ));
+    smv_cmd()
+        .current_dir(temp_path)
+        .arg("-u")
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert_eq!(mode("bin/build.sh"), 0o644);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_preflight_stops_before_any_op() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    let locked = temp_path.join("locked");
+    fs::create_dir(&locked).unwrap();
+    fs::write(temp_path.join("a.txt"), "a").unwrap();
+    fs::write(locked.join("b.txt"), "b").unwrap();
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o555)).unwrap();
+    // Root writes anyway; there is nothing to refuse then
+    let writable = fs::write(locked.join("probe"), "").is_ok();
+    let plan = format!(
+        "{}\t{}\n{}\t{}\n",
+        temp_path.join("a.txt").display(),
+        temp_path.join("a2.txt").display(),
+        locked.join("b.txt").display(),
+        locked.join("b2.txt").display()
+    );
+    let assert = smv_cmd()
+        .arg("--stdin-ops")
+        .env("HOME", temp_path)
+        .write_stdin(plan)
+        .assert();
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+    if writable {
+        return;
+    }
+    assert
+        .failure()
+        .stderr(predicate::str::contains("no permission to change it"));
+    assert!(temp_path.join("a.txt").exists());
+}
+
+#[test]
+fn test_stage_commit_and_undo() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::create_dir_all(temp_path.join("photos/raw")).unwrap();
+    fs::write(temp_path.join("photos/raw/IMG One.jpg"), "1").unwrap();
+    let smv = |args: &[&str]| {
+        smv_cmd()
+            .current_dir(temp_path)
+            .args(args)
+            .env("HOME", temp_path)
+            .assert()
+    };
+
+    smv(&["commit-stage", "photos"])
+        .failure()
+        .stderr(predicate::str::contains("Nothing staged"));
+    smv(&["stage", "photos"]).success();
+    fs::rename(
+        temp_path.join("photos.smv-staging/raw/IMG One.jpg"),
+        temp_path.join("photos.smv-staging/raw/img_one.jpg"),
+    )
+    .unwrap();
+    smv(&["commit-stage", "photos"]).success();
+    assert!(temp_path.join("photos/raw/img_one.jpg").exists());
+    assert!(
+        temp_path
+            .join("photos.smv-previous/raw/IMG One.jpg")
+            .exists()
+    );
+
+    smv(&["-u"]).success();
+    assert!(temp_path.join("photos/raw/IMG One.jpg").exists());
+    assert!(
+        temp_path
+            .join("photos.smv-staging/raw/img_one.jpg")
+            .exists()
+    );
+}
+
+#[test]
+fn test_stdin_ops_yaml_plan() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("Track 01.mp3"), "1").unwrap();
+    let plan = "# exported from the TUI\n- op: move\n  from: Track 01.mp3\n  to: track_01.mp3\n";
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .arg("--stdin-ops")
+        .env("HOME", temp_path)
+        .write_stdin(plan)
+        .assert()
+        .success();
+    assert!(temp_path.join("track_01.mp3").exists());
+}
+
+#[test]
+fn test_then_chain_runs_as_one_batch() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("My Trip.jpg"), "1").unwrap();
+    fs::write(temp_path.join("notes.txt"), "2").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args([
+            "snake",
+            ".",
+            "EXT:jpg",
+            "THEN",
+            "bucket",
+            "LETTER",
+            "THEN",
+            "mv",
+            "TO-DIR:out",
+        ])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2. bucket: 1 changed"));
+    assert!(temp_path.join("out/m/my_trip.jpg").exists());
+    assert!(temp_path.join("notes.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .arg("-u")
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("My Trip.jpg").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "THEN", "mv", "out", "EXT:jpg"])
+        .env("HOME", temp_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Only the first stage"));
+}
+
+#[test]
+fn test_did_you_mean_suggestions() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    for line in [&["sanke", "."][..], &["sanke", ".", "EXT:jpg"][..]] {
+        smv_cmd()
+            .current_dir(temp_path)
+            .args(line)
+            .env("HOME", temp_path)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Did you mean snake?"))
+            .stderr(predicate::str::contains("smv snake [target]"));
+    }
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "EXTT:jpg"])
+        .env("HOME", temp_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("did you mean EXT:?"));
+}
+
+#[test]
+fn test_json_reports_operations() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("My File.txt"), "a").unwrap();
+    fs::write(temp_path.join("my_file.txt.bak"), "b").unwrap();
+
+    let records = |output: &[u8]| -> Vec<serde_json::Value> {
+        String::from_utf8_lossy(output)
+            .lines()
+            .map(|line| serde_json::from_str(line).expect(line))
+            .collect()
+    };
+
+    let output = smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "EXT:txt", "-p", "--json"])
+        .env("HOME", temp_path)
+        .output()
+        .unwrap();
+    let preview = records(&output.stdout);
+    assert_eq!(preview.len(), 2, "{preview:?}");
+    assert_eq!(preview[0]["event"], "planned");
+    assert_eq!(preview[0]["op"], "rename");
+    assert_eq!(preview[1]["event"], "stats");
+    assert_eq!(preview[1]["preview"], true);
+    assert!(temp_path.join("My File.txt").exists());
+
+    fs::create_dir(temp_path.join("out")).unwrap();
+    let output = smv_cmd()
+        .current_dir(temp_path)
+        .args(["mv", "My File.txt", "out/", "--json"])
+        .env("HOME", temp_path)
+        .output()
+        .unwrap();
+    let moved = records(&output.stdout);
+    assert_eq!(moved[0]["event"], "done");
+    assert_eq!(moved[0]["to"], "out/My File.txt");
+    assert_eq!(moved.last().unwrap()["done"], 1);
+
+    // FORMAT:json does the same for CNP commands
+    fs::write(temp_path.join("out/my_file.txt"), "c").unwrap();
+    let output = smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", "out", "FORMAT:json"])
+        .env("HOME", temp_path)
+        .output()
+        .unwrap();
+    let renamed = records(&output.stdout);
+    assert_eq!(renamed[0]["event"], "conflict");
+    assert_eq!(renamed.last().unwrap()["errors"], 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_metadata_filters_follow_symlinks() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("big.bin"), vec![0u8; 4096]).unwrap();
+    fs::write(temp_path.join("small.bin"), "hi").unwrap();
+    std::os::unix::fs::symlink("big.bin", temp_path.join("link.bin")).unwrap();
+    fs::create_dir(temp_path.join("dir.bin")).unwrap();
+
+    // The walk's file types and one stat per entry answer TYPE: and SIZE
+    smv_cmd()
+        .current_dir(temp_path)
+        .args([".", "EXT:bin", "TYPE:file", "SIZE>1KB", "FORMAT:json"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"files_found\": 2"))
+        .stdout(predicate::str::contains("./link.bin"))
+        .stdout(predicate::str::contains("./small.bin").not());
+    smv_cmd()
+        .current_dir(temp_path)
+        .args([".", "SIZE>1KB", "TYPE:symlink", "FORMAT:json"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"files_found\": 1"));
+}
+
+#[test]
+fn test_plan_file_applied_later() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("My Notes.txt"), "notes").unwrap();
+    fs::write(temp_path.join("Old Draft.txt"), "draft").unwrap();
+
+    // --plan previews: nothing is renamed yet
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "--plan", "renames.json"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("smv apply-plan renames.json"));
+    assert!(temp_path.join("My Notes.txt").exists());
+    let plan = fs::read_to_string(temp_path.join("renames.json")).unwrap();
+    assert!(plan.contains("my_notes.txt"), "{plan}");
+
+    // Edited in review: one rename dropped
+    let edited: String = plan
+        .lines()
+        .filter(|line| !line.contains("Old Draft"))
+        .map(|line| format!("{}\n", line.trim_end_matches(',')))
+        .collect();
+    fs::write(temp_path.join("renames.json"), edited).unwrap();
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["apply-plan", "renames.json"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("my_notes.txt").exists());
+    assert!(temp_path.join("Old Draft.txt").exists());
+
+    // Applied again, its sources are gone
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["apply-plan", "renames.json"])
+        .env("HOME", temp_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("source does not exist"));
+
+    // One op still blocked stops them all, unless forced
+    fs::write(temp_path.join("new.txt"), "new").unwrap();
+    fs::write(temp_path.join("old.txt"), "old").unwrap();
+    let plan = format!(
+        "{}\t{}\n{}\t{}\n{}\t{}\n",
+        temp_path.join("Old Draft.txt").display(),
+        temp_path.join("old_draft.txt").display(),
+        temp_path.join("My Notes.txt").display(),
+        temp_path.join("my_notes.txt").display(),
+        temp_path.join("new.txt").display(),
+        temp_path.join("old.txt").display(),
+    );
+    fs::write(temp_path.join("more.tsv"), plan).unwrap();
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["apply-plan", "more.tsv"])
+        .env("HOME", temp_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("nothing was changed"))
+        .stderr(predicate::str::contains(
+            "With -F, op 3 would overwrite the existing destination (backed up for smv -u) \
+             and op 2 would be skipped",
+        ));
+    assert!(temp_path.join("Old Draft.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["apply-plan", "more.tsv", "-F"])
+        .env("HOME", temp_path)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("source does not exist"));
+    assert!(temp_path.join("old_draft.txt").exists());
+    assert_eq!(
+        fs::read_to_string(temp_path.join("old.txt")).unwrap(),
+        "new"
+    );
+}
+
+#[test]
+fn test_index_answers_queries_until_stale() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    let archive = temp_path.join("archive");
+    fs::create_dir(&archive).unwrap();
+    fs::write(archive.join("a.bin"), "a").unwrap();
+    fs::write(archive.join("b.bin"), vec![0u8; 4096]).unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["index", "build", "archive"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Indexed 2 entries"));
+
+    // Growing a file leaves its directory as it was: the index still answers
+    fs::write(archive.join("a.bin"), vec![0u8; 4096]).unwrap();
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["archive", "SIZE>1KB", "FORMAT:json"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"files_found\": 1"));
+
+    // A new file makes it stale, and the tree is walked again
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    fs::write(archive.join("c.bin"), vec![0u8; 4096]).unwrap();
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["archive", "SIZE>1KB", "FORMAT:json"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"files_found\": 3"))
+        .stderr(predicate::str::contains("smv index update archive"));
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["index", "update", "archive"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 added, 0 removed, 1 changed"));
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["index", "clear", "archive"])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed the index of archive"));
+}
+
+#[test]
+fn test_dupes_and_hash_filters() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::create_dir(temp_path.join("copies")).unwrap();
+    fs::write(temp_path.join("a.txt"), "abc").unwrap();
+    fs::write(temp_path.join("copies/a.txt"), "abc").unwrap();
+    fs::write(temp_path.join("copies/b.txt"), "abd").unwrap();
+
+    // Every copy but the first by path
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["rm", ".", "DUPES:", "-r", "-F"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("a.txt").exists());
+    assert!(!temp_path.join("copies/a.txt").exists());
+    assert!(temp_path.join("copies/b.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args([
+            ".",
+            "HASH:ba7816bf",
+            "-r",
+            "FORMAT:json",
+            "--hash-algo",
+            "sha256",
+        ])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"files_found\": 1"))
+        .stdout(predicate::str::contains("./a.txt"));
+    smv_cmd()
+        .current_dir(temp_path)
+        .args([".", "HASH:ba7816bf", "--hash-algo", "md5"])
+        .env("HOME", temp_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown hash algorithm: md5"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_names_skipped_or_transliterated() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    let latin1 = temp_path.join(OsStr::from_bytes(b"Caf\xe9 Menu.txt"));
+    fs::write(&latin1, "menu").unwrap();
+    fs::write(temp_path.join("Other File.txt"), "other").unwrap();
+
+    // Never renamed to a name with replacement characters
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", "."])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("name is not valid UTF-8"));
+    assert!(latin1.exists());
+    assert!(temp_path.join("other_file.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "--non-utf8", "transliterate"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(!latin1.exists());
+    assert!(temp_path.join("cafe_menu.txt").exists());
+}
+
+#[test]
+fn test_transform_batch_undone_at_once() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::create_dir(temp_path.join("sub")).unwrap();
+    for name in ["My File.txt", "Other One.txt", "sub/Deep Name.md"] {
+        fs::write(temp_path.join(name), name).unwrap();
+    }
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "-r"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("sub/deep_name.md").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .arg("-u")
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Moved 3 of 3 files back"));
+    for name in ["My File.txt", "Other One.txt", "sub/Deep Name.md"] {
+        assert!(temp_path.join(name).exists(), "{name}");
+    }
+
+    // Previews leave nothing to undo
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["kebab", ".", "-p"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    smv_cmd()
+        .current_dir(temp_path)
+        .arg("-u")
+        .env("HOME", temp_path)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_renames_fit_the_byte_limit_of_names() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    let name = format!("{}.txt", "a".repeat(200));
+    fs::write(temp_path.join(&name), "long").unwrap();
+
+    // Each 'a' becomes two 2-byte characters: 200 characters would be 800 bytes
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["CHANGE", "a", "INTO", "éé", "."])
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Name too long"));
+    let renamed: Vec<String> = fs::read_dir(temp_path)
+        .unwrap()
+        .filter_map(|entry| entry.unwrap().file_name().into_string().ok())
+        .filter(|name| name.ends_with(".txt"))
+        .collect();
+    assert_eq!(renamed.len(), 1);
+    assert!(renamed[0].starts_with('é') && renamed[0].len() <= 255);
+}
+
+#[test]
+fn test_history_lists_and_undoes_past_batches() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("First File.txt"), "1").unwrap();
+    fs::create_dir(temp_path.join("sub")).unwrap();
+    fs::write(temp_path.join("sub/Later File.txt"), "2").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", "."])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["kebab", "sub"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .arg("history")
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("smv kebab sub"))
+        .stdout(predicate::str::contains("smv snake ."))
+        .stdout(predicate::str::contains("1 moved"))
+        .stdout(predicate::str::contains("first_file.txt"));
+
+    // Batch 2 is the first rename; the one made after it stays
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["history", "undo", "2"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("First File.txt").exists());
+    assert!(temp_path.join("sub/later-file.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["history", "undo", "5"])
+        .env("HOME", temp_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No batch 5"));
+}
+
+#[test]
+fn test_repl_resumes_the_last_session() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    fs::create_dir_all(temp_path.join("inbox")).unwrap();
+    fs::write(temp_path.join("inbox/New Photo.jpg"), "").unwrap();
+
+    // The session ends after the preview, as if the terminal had been closed
+    smv_cmd()
+        .current_dir(temp_path)
+        .arg("-I")
+        .env("HOME", temp_path)
+        .write_stdin("cd inbox\npreview snake *.jpg\n")
+        .assert()
+        .success();
+    assert!(temp_path.join("inbox/New Photo.jpg").exists());
+
+    // Back in the inbox with the preview, which `apply last` runs
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["-I", "--resume"])
+        .env("HOME", temp_path)
+        .write_stdin("apply last\ny\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Resumed the session saved"))
+        .stdout(predicate::str::contains("Last preview: 'snake *.jpg'"))
+        .stdout(predicate::str::contains("1 successfully renamed"));
+    assert!(temp_path.join("inbox/new_photo.jpg").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_dir_mode_applies_to_created_directories() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    fs::write(temp_path.join("apple.txt"), "").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["bucket", "LETTER", ".", "--dir-mode", "2770", "-F"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    let mode = fs::metadata(temp_path.join("a"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o7777, 0o2770);
+    assert!(temp_path.join("a/apple.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["bucket", "LETTER", ".", "--dir-mode", "9"])
+        .env("HOME", temp_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --dir-mode 9"));
+}
+
+#[test]
+fn test_recursive_transform_renames_whole_directory_trees() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    fs::create_dir_all(temp_path.join("My Dir/Sub Dir")).unwrap();
+    fs::write(temp_path.join("My Dir/Sub Dir/My File.txt"), "").unwrap();
+    fs::write(temp_path.join("My Dir/A B.txt"), "").unwrap();
+
+    // Contents first, so the directories above them are renamed last
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "-r", "--everything", "-F"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("my_dir/sub_dir/my_file.txt").exists());
+    assert!(temp_path.join("my_dir/a_b.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["undo", "-F"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("My Dir/Sub Dir/My File.txt").exists());
+}
+
+#[test]
+fn test_flatten_asks_for_force_past_the_limit() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    fs::create_dir_all(temp_path.join("box/sub")).unwrap();
+    for n in 0..1001 {
+        fs::write(temp_path.join(format!("box/sub/{n}.txt")), "").unwrap();
+    }
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["flatten", "box"])
+        .env("HOME", temp_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("would move 1001 files"));
+    assert!(temp_path.join("box/sub/0.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["flatten", "box", "-F"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("box/1000.txt").exists());
+    assert!(!temp_path.join("box/sub").exists());
+}
+
+#[test]
+fn test_apply_to_chooses_what_a_transform_renames() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    fs::create_dir_all(temp_path.join("My Docs/Old Notes")).unwrap();
+    fs::write(temp_path.join("My Docs/Old Notes/Read Me.txt"), "").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "-r", "--apply-to", "dirs", "-F"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("my_docs/old_notes/Read Me.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["kebab", ".", "-r", "--apply-to=both", "-F"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("my-docs/old-notes/read-me.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "--apply-to", "folders"])
+        .env("HOME", temp_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected files, dirs or both"));
+}
+
+#[test]
+fn test_batch_checks_every_operation_before_running() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    fs::write(temp_path.join("a.txt"), "a").unwrap();
+    fs::write(temp_path.join("b.txt"), "b").unwrap();
+    fs::write(
+        temp_path.join("ops.csv"),
+        "operation,source,destination\nmove,a.txt,shelf/a.txt\ncopy,missing.txt,shelf/m.txt\n",
+    )
+    .unwrap();
+
+    // One op cannot run, so none does
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["batch", "ops.csv"])
+        .env("HOME", temp_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("op 2: Copy"))
+        .stderr(predicate::str::contains("nothing was changed"));
+    assert!(temp_path.join("a.txt").exists());
+
+    fs::write(
+        temp_path.join("ops.csv"),
+        "operation,source,destination\nmove,a.txt,shelf/a.txt\ncp,b.txt,\"shelf/b, copy.txt\"\n",
+    )
+    .unwrap();
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["batch", "ops.csv", "-p"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(!temp_path.join("shelf").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["batch", "ops.csv"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("shelf/a.txt").exists());
+    assert!(temp_path.join("shelf/b, copy.txt").exists());
+    assert!(temp_path.join("b.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["-u", "-F"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("a.txt").exists());
+    assert!(!temp_path.join("shelf/b, copy.txt").exists());
+}
+
+#[test]
+fn test_no_notify_overrides_notify() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+    fs::write(temp_path.join("My File.txt"), "x").unwrap();
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .args(["snake", ".", "--notify", "--no-notify"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(temp_path.join("my_file.txt").exists());
+}
+
+#[test]
+fn test_batch_with_relative_paths_undoes_from_anywhere() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    let work = temp_path.join("work");
+    fs::create_dir_all(work.join("docs/inner")).unwrap();
+    fs::write(work.join("q.txt"), "q").unwrap();
+    fs::write(work.join("r.txt"), "r").unwrap();
+    fs::write(work.join("docs/inner/d.txt"), "d").unwrap();
+    fs::write(
+        work.join("ops.csv"),
+        "operation,source,destination\nmove,q.txt,sub/q.txt\ncopy,r.txt,r2.txt\ncopy,docs,docs2\n",
+    )
+    .unwrap();
+    smv_cmd()
+        .current_dir(&work)
+        .args(["batch", "ops.csv"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(work.join("docs2/inner/d.txt").exists());
+
+    // A file of the copy's name where the undo runs is not the copy
+    fs::write(temp_path.join("r2.txt"), "unrelated").unwrap();
+    smv_cmd()
+        .current_dir(temp_path)
+        .arg("-u")
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(work.join("q.txt").exists());
+    assert!(!work.join("r2.txt").exists());
+    assert!(!work.join("docs2").exists());
+    assert!(work.join("docs/inner/d.txt").exists());
+    assert!(temp_path.join("r2.txt").exists());
+}
+
+#[test]
+fn test_alpha_fan_out_undoes_from_another_directory() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    let flat = temp_path.join("flat");
+    fs::create_dir(&flat).unwrap();
+    for name in ["apple.txt", "banana.txt", "cherry.txt"] {
+        fs::write(flat.join(name), name).unwrap();
+    }
+
+    smv_cmd()
+        .current_dir(&flat)
+        .args(["sort", "alpha", ".", "--levels", "1"])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(flat.join("a/apple.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .arg("-u")
+        .env("HOME", temp_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Moved 3 of 3 files back"));
+    for name in ["apple.txt", "banana.txt", "cherry.txt"] {
+        assert!(flat.join(name).exists(), "{name}");
+    }
+    assert!(!flat.join("a").exists());
+}
+
+#[test]
+fn test_bucket_is_undoable() {
+    let temp = TempDir::new().unwrap();
+    let temp_path = temp.path();
+    let inbox = temp_path.join("inbox");
+    fs::create_dir(&inbox).unwrap();
+    for name in ["apple.txt", "avocado.txt", "berry.txt"] {
+        fs::write(inbox.join(name), name).unwrap();
+    }
+
+    smv_cmd()
+        .current_dir(&inbox)
+        .args(["bucket", "LETTER", "."])
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    assert!(inbox.join("a/avocado.txt").exists());
+
+    smv_cmd()
+        .current_dir(temp_path)
+        .arg("-u")
+        .env("HOME", temp_path)
+        .assert()
+        .success();
+    for name in ["apple.txt", "avocado.txt", "berry.txt"] {
+        assert!(inbox.join(name).exists(), "{name}");
+    }
+    assert!(!inbox.join("a").exists());
+}
@@ -0,0 +1,42 @@
+//! The stable library API works without printing
+
+use smv::{Collected, HistoryManager, Level, TransformType, group_by_basename, transform};
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn test_operations_report_instead_of_printing() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("song.mp3"), "").unwrap();
+    fs::write(dir.path().join("song.txt"), "").unwrap();
+
+    let mut messages = Collected::default();
+    let moves = group_by_basename(&dir.path().to_string_lossy(), false, &mut messages).unwrap();
+    assert_eq!(moves.len(), 2);
+    assert!(dir.path().join("song/song.mp3").exists());
+    assert!(
+        messages
+            .messages
+            .iter()
+            .any(|(level, text)| *level == Level::Info && text.starts_with("Created directory"))
+    );
+
+    // Undo tells its reporter what it skipped, and keeps it for another try
+    let mut history = HistoryManager::new(10, &dir.path().join("state"));
+    history
+        .record_mapping(dir.path().to_path_buf(), &moves)
+        .unwrap();
+    fs::write(dir.path().join("song/song.txt"), "edited").unwrap();
+    let collected = Arc::new(Mutex::new(Collected::default()));
+    history.set_reporter(Box::new(collected.clone()));
+    assert!(history.undo().is_err());
+
+    let collected = collected.lock().unwrap();
+    assert_eq!(collected.warnings().count(), 1);
+    assert!(dir.path().join("song.mp3").exists());
+    assert_eq!(history.list_operations().len(), 1);
+    assert_eq!(
+        transform("My Song.mp3", &TransformType::Snake),
+        "my_song.mp3"
+    );
+}
@@ -0,0 +1,91 @@
+//! Property tests for invariants every rename relies on
+
+use proptest::prelude::*;
+use smv::history::HistoryManager;
+use smv::transformers::{TransformType, transform};
+use std::collections::BTreeSet;
+use std::fs;
+
+/// Names as they show up in downloads folders: words, separators, symbols, an extension
+fn file_name() -> impl Strategy<Value = String> {
+    "[A-Za-z0-9 _.()&!#'-]{1,24}"
+}
+
+fn extension() -> impl Strategy<Value = String> {
+    "[a-z0-9]{1,5}"
+}
+
+const IDEMPOTENT: &[TransformType] = &[
+    TransformType::Snake,
+    TransformType::Kebab,
+    TransformType::Clean,
+];
+
+proptest! {
+    #[test]
+    fn transforms_are_idempotent(name in file_name()) {
+        for transform_type in IDEMPOTENT {
+            let once = transform(&name, transform_type);
+            prop_assert_eq!(&transform(&once, transform_type), &once, "{:?} of {:?}", transform_type, name);
+        }
+    }
+
+    #[test]
+    fn extensions_are_preserved(stem in "[A-Za-z][A-Za-z0-9 _-]{0,16}", ext in extension()) {
+        let name = format!("{stem}.{ext}");
+        for transform_type in [TransformType::Snake, TransformType::Kebab, TransformType::Title, TransformType::Camel, TransformType::Pascal] {
+            let renamed = transform(&name, &transform_type);
+            let expected = format!(".{ext}");
+            prop_assert!(renamed.ends_with(&expected), "{:?} of {:?} gave {:?}", transform_type, name, renamed);
+        }
+    }
+
+    #[test]
+    fn outputs_are_never_empty(name in file_name()) {
+        for transform_type in [
+            TransformType::Snake,
+            TransformType::Kebab,
+            TransformType::Clean,
+            TransformType::Title,
+            TransformType::Camel,
+            TransformType::Pascal,
+            TransformType::Sentence,
+            TransformType::Start,
+            TransformType::Studly,
+        ] {
+            let renamed = transform(&name, &transform_type);
+            prop_assert!(!renamed.is_empty(), "{:?} of {:?} is empty", transform_type, name);
+        }
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    /// Undoing a recorded batch puts every file back where it was, with its contents
+    #[test]
+    fn recorded_plans_are_reversible(names in prop::collection::btree_set("[a-z0-9]{1,8}", 1..8)) {
+        let root = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        let names: BTreeSet<String> = names;
+
+        let mut moves = Vec::new();
+        for (index, name) in names.iter().enumerate() {
+            let source = root.path().join(name);
+            fs::write(&source, name).unwrap();
+            let destination = root.path().join(format!("sorted/{}", index % 3)).join(transform(name, &TransformType::Upper));
+            fs::create_dir_all(destination.parent().unwrap()).unwrap();
+            fs::rename(&source, &destination).unwrap();
+            moves.push((source, destination));
+        }
+
+        let mut history = HistoryManager::new(10, state.path());
+        history.record_mapping(root.path().to_path_buf(), &moves).unwrap();
+        history.undo().unwrap();
+
+        for name in &names {
+            prop_assert_eq!(fs::read_to_string(root.path().join(name)).unwrap(), name.clone());
+        }
+        prop_assert!(!root.path().join("sorted").exists());
+    }
+}